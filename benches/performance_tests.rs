@@ -1,4 +1,5 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use note_to_ai::vault::hnsw::HnswIndex;
 
 fn benchmark_example(c: &mut Criterion) {
     c.bench_function("example", |b| {
@@ -9,5 +10,155 @@ fn benchmark_example(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_example);
+/// Decode throughput of the local LLM on a tiny one-layer GGUF Llama. Each request decodes
+/// with its own KV cache, so a longer prompt only adds to the first step
+#[cfg(feature = "embeddings")]
+mod llm {
+    use std::path::Path;
+    use candle_core::quantized::{gguf_file, GgmlDType, QTensor};
+    use candle_core::{DType, Device, Tensor};
+    use criterion::{BenchmarkId, Criterion, Throughput};
+    use note_to_ai::ai::local_llm_full::{GenerationConfig, GenerationRequest, LocalLLM, ModelConfig, ModelType};
+
+    const EMBED: usize = 64;
+    const VOCAB: usize = 256;
+    const NEW_TOKENS: usize = 32;
+
+    fn write_tiny_model(dir: &Path) -> anyhow::Result<()> {
+        let device = Device::Cpu;
+        let matrix = |rows: usize, cols: usize| -> anyhow::Result<QTensor> {
+            Ok(QTensor::quantize(&Tensor::randn(0f32, 0.1, (rows, cols), &device)?, GgmlDType::Q8_0)?)
+        };
+        let norm = || -> anyhow::Result<QTensor> {
+            Ok(QTensor::quantize(&Tensor::ones(EMBED, DType::F32, &device)?, GgmlDType::F32)?)
+        };
+        let tensors = vec![
+            ("token_embd.weight", matrix(VOCAB, EMBED)?),
+            ("output_norm.weight", norm()?),
+            ("output.weight", matrix(VOCAB, EMBED)?),
+            ("blk.0.attn_norm.weight", norm()?),
+            ("blk.0.attn_q.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.attn_k.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.attn_v.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.attn_output.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.ffn_norm.weight", norm()?),
+            ("blk.0.ffn_gate.weight", matrix(128, EMBED)?),
+            ("blk.0.ffn_up.weight", matrix(128, EMBED)?),
+            ("blk.0.ffn_down.weight", matrix(EMBED, 128)?),
+        ];
+        let metadata = [
+            ("general.architecture", gguf_file::Value::String("llama".to_string())),
+            ("llama.attention.head_count", gguf_file::Value::U32(4)),
+            ("llama.attention.head_count_kv", gguf_file::Value::U32(4)),
+            ("llama.block_count", gguf_file::Value::U32(1)),
+            ("llama.embedding_length", gguf_file::Value::U32(EMBED as u32)),
+            ("llama.rope.dimension_count", gguf_file::Value::U32(16)),
+            ("llama.attention.layer_norm_rms_epsilon", gguf_file::Value::F32(1e-5)),
+        ];
+        let mut file = std::fs::File::create(dir.join("tiny.Q8_0.gguf"))?;
+        gguf_file::write(
+            &mut file,
+            &metadata.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>(),
+            &tensors.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>(),
+        )?;
+
+        // No end-of-sequence token, so every run decodes exactly NEW_TOKENS
+        let vocab: serde_json::Map<String, serde_json::Value> = (0..VOCAB)
+            .map(|i| (format!("w{}", i), serde_json::json!(i)))
+            .collect();
+        let tokenizer = serde_json::json!({
+            "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+            "normalizer": null, "pre_tokenizer": { "type": "Whitespace" }, "post_processor": null,
+            "decoder": null, "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "w0" }
+        });
+        std::fs::write(dir.join("tokenizer.json"), tokenizer.to_string())?;
+        Ok(())
+    }
+
+    pub fn benchmark_decoding(c: &mut Criterion) {
+        let dir = tempfile::tempdir().unwrap();
+        write_tiny_model(dir.path()).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let llm = LocalLLM::new(ModelConfig {
+            model_name: "tiny".to_string(),
+            model_type: ModelType::Llama,
+            device: "cpu".to_string(),
+            dtype: "f32".to_string(),
+            max_sequence_length: 512,
+            cache_size: 512,
+            use_flash_attention: false,
+            model_path: Some(dir.path().join("tiny.Q8_0.gguf")),
+            max_concurrent_generations: 1,
+            quantized: None,
+            chat_template: None,
+        }).unwrap();
+        runtime.block_on(llm.initialize()).unwrap();
+
+        let mut group = c.benchmark_group("llm_decode");
+        // Reported as tokens/sec
+        group.throughput(Throughput::Elements(NEW_TOKENS as u64));
+        for prompt_tokens in [8, 128] {
+            let prompt: Vec<String> = (0..prompt_tokens).map(|i| format!("w{}", i * 7 % VOCAB)).collect();
+            let request = GenerationRequest {
+                prompt: prompt.join(" "),
+                config: GenerationConfig { max_new_tokens: NEW_TOKENS, do_sample: false, stop_tokens: Vec::new(), ..Default::default() },
+                context: None,
+                system_prompt: None,
+                chat_format: false,
+                stream: false,
+            };
+            group.bench_with_input(BenchmarkId::new("kv_cache", prompt_tokens), &request, |b, request| {
+                b.iter(|| runtime.block_on(llm.generate(request.clone())).unwrap())
+            });
+        }
+        group.finish();
+    }
+}
+
+const VECTORS: usize = 50_000;
+const EMBEDDING_DIM: usize = 384;
+
+/// Top-10 over 50k embeddings: the exact scan semantic search used to do for every query,
+/// against the HNSW graph it now shortlists from
+fn benchmark_vector_search(c: &mut Criterion) {
+    let mut seed = 42u64;
+    let mut random_vector = || -> Vec<f32> {
+        (0..EMBEDDING_DIM).map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) as f32 / (1u64 << 31) as f32) - 0.5
+        }).collect()
+    };
+    let vectors: Vec<Vec<f32>> = (0..VECTORS).map(|_| random_vector()).collect();
+    let query = random_vector();
+
+    let mut index = HnswIndex::default();
+    for (i, vector) in vectors.iter().enumerate() {
+        index.insert(&i.to_string(), &[vector]);
+    }
+
+    let mut group = c.benchmark_group("vector_search");
+    group.bench_with_input(BenchmarkId::new("brute_force", VECTORS), &query, |b, query| {
+        b.iter(|| {
+            let mut scored: Vec<(usize, f32)> = vectors.iter().enumerate()
+                .map(|(i, v)| {
+                    let dot: f32 = v.iter().zip(query).map(|(a, b)| a * b).sum();
+                    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt() * query.iter().map(|x| x * x).sum::<f32>().sqrt();
+                    (i, dot / norm)
+                })
+                .collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+            scored.truncate(10);
+            scored
+        })
+    });
+    group.bench_with_input(BenchmarkId::new("hnsw", VECTORS), &query, |b, query| {
+        b.iter(|| index.search(query, 10, 64))
+    });
+    group.finish();
+}
+
+#[cfg(feature = "embeddings")]
+criterion_group!(benches, benchmark_example, llm::benchmark_decoding, benchmark_vector_search);
+#[cfg(not(feature = "embeddings"))]
+criterion_group!(benches, benchmark_example, benchmark_vector_search);
 criterion_main!(benches);
@@ -0,0 +1,135 @@
+// src/ai/answer_cache.rs - Reuse answers to repeated questions while the corpus is unchanged
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerCacheConfig {
+    pub enabled: bool,
+    /// Answers older than this are recomputed even if the corpus is unchanged
+    pub ttl_secs: u64,
+    pub max_entries: usize,
+}
+
+impl Default for AnswerCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 3600,
+            max_entries: 256,
+        }
+    }
+}
+
+struct CachedAnswer {
+    answer: String,
+    created_at: Instant,
+    last_used: Instant,
+}
+
+/// Answers keyed on `(normalized query, corpus version)`; any ingestion or removal
+/// bumps the version, so answers computed over older content are never returned
+pub struct AnswerCache {
+    config: AnswerCacheConfig,
+    entries: Mutex<HashMap<(String, u64), CachedAnswer>>,
+}
+
+impl AnswerCache {
+    pub fn new(config: AnswerCacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get(&self, query: &str, corpus_version: u64) -> Option<String> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let key = (normalize_query(query), corpus_version);
+        let mut entries = self.entries.lock().await;
+        let expired = entries.get(&key)?.created_at.elapsed() >= self.ttl();
+        if expired {
+            entries.remove(&key);
+            return None;
+        }
+
+        let entry = entries.get_mut(&key)?;
+        entry.last_used = Instant::now();
+        Some(entry.answer.clone())
+    }
+
+    pub async fn insert(&self, query: &str, corpus_version: u64, answer: String) {
+        if !self.config.enabled || self.config.max_entries == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().await;
+        // Answers over an older corpus can never be hit again
+        entries.retain(|(_, version), _| *version >= corpus_version);
+
+        if entries.len() >= self.config.max_entries {
+            if let Some(oldest) = entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+
+        let now = Instant::now();
+        entries.insert((normalize_query(query), corpus_version), CachedAnswer {
+            answer,
+            created_at: now,
+            last_used: now,
+        });
+    }
+
+    pub async fn len(&self) -> usize {
+        self.entries.lock().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.entries.lock().await.is_empty()
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.ttl_secs)
+    }
+}
+
+/// Case, spacing and trailing punctuation don't change the question
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['?', '.', '!'])
+        .trim_end()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lookup_normalizes_query_and_respects_limits() {
+        assert_eq!(normalize_query("  When do   Tomatoes need watering?? "), "when do tomatoes need watering");
+
+        let cache = AnswerCache::new(AnswerCacheConfig { max_entries: 2, ..Default::default() });
+        cache.insert("When do tomatoes need watering?", 1, "Every morning".to_string()).await;
+        assert_eq!(cache.get("when do tomatoes need watering", 1).await.as_deref(), Some("Every morning"));
+        assert_eq!(cache.get("when do tomatoes need watering", 2).await, None);
+
+        cache.insert("second", 1, "b".to_string()).await;
+        cache.insert("third", 1, "c".to_string()).await;
+        assert_eq!(cache.len().await, 2);
+
+        let expired = AnswerCache::new(AnswerCacheConfig { ttl_secs: 0, ..Default::default() });
+        expired.insert("question", 1, "answer".to_string()).await;
+        assert_eq!(expired.get("question", 1).await, None);
+    }
+}
@@ -1,14 +1,98 @@
-use crate::Result;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 
-pub struct APIClient;
+/// An OpenAI-compatible chat completions endpoint, used as the fallback for the local model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Base URL the `chat/completions` path is appended to, e.g. `https://api.openai.com/v1`
+    pub base_url: String,
+    pub model: String,
+    /// Environment variable holding the API key; no `Authorization` header is sent when unset
+    #[serde(default = "default_api_key_env")]
+    pub api_key_env: String,
+    #[serde(default = "default_api_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_api_key_env() -> String {
+    "NOTE_TO_AI_API_KEY".to_string()
+}
+
+fn default_api_timeout_ms() -> u64 {
+    60_000
+}
+
+pub struct APIClient {
+    config: ApiConfig,
+    client: reqwest::Client,
+}
 
 impl APIClient {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+    pub fn new(config: ApiConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .context("Failed to build API client")?;
+        Ok(Self { config, client })
+    }
+
+    /// POST `body` to `endpoint` under the base URL and return the JSON response
+    pub async fn make_request(&self, endpoint: &str, body: &serde_json::Value) -> Result<serde_json::Value> {
+        let url = format!("{}/{}", self.config.base_url.trim_end_matches('/'), endpoint);
+        let mut request = self.client.post(&url).json(body);
+        if let Ok(key) = std::env::var(&self.config.api_key_env) {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await
+            .with_context(|| format!("Request to {} failed", url))?
+            .error_for_status()
+            .with_context(|| format!("{} returned an error", url))?;
+        response.json().await.with_context(|| format!("{} returned invalid JSON", url))
     }
-    
-    pub async fn make_request(&self, endpoint: &str) -> Result<String> {
-        // TODO: Implement quantum-safe HTTP client
-        Ok("API response".to_string())
+
+    /// The model's reply to `prompt` as a single user message
+    pub async fn complete(&self, prompt: &str, max_tokens: usize) -> Result<String> {
+        let response = self.make_request("chat/completions", &serde_json::json!({
+            "model": self.config.model,
+            "messages": [{ "role": "user", "content": prompt }],
+            "max_tokens": max_tokens,
+        })).await?;
+
+        response["choices"][0]["message"]["content"].as_str()
+            .map(str::to_string)
+            .context("Chat completion response has no message content")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{http_response, http_server};
+
+    #[tokio::test]
+    async fn test_completion_sends_the_prompt_and_token_limit() {
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let url = http_server(move |request| {
+            sender.send(request).unwrap();
+            let reply = serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": "Water daily." } }] });
+            vec![http_response("200 OK", "application/json", &reply.to_string())]
+        }).await;
+
+        let client = APIClient::new(ApiConfig {
+            base_url: format!("{}/v1/", url),
+            model: "small".to_string(),
+            api_key_env: default_api_key_env(),
+            timeout_ms: 5000,
+        }).unwrap();
+        assert_eq!(client.complete("When do I water the tomatoes?", 64).await.unwrap(), "Water daily.");
+
+        let request = received.recv().await.unwrap();
+        assert!(request.head.starts_with("POST /v1/chat/completions "), "{}", request.head);
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["messages"][0]["content"], "When do I water the tomatoes?");
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["model"], "small");
     }
 }
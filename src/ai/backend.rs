@@ -0,0 +1,321 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use crate::ai::api_client::APIClient;
+use crate::ai::structured::OutputSchema;
+use crate::logger::Logger;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendKind {
+    Local,
+    Api,
+}
+
+/// Failures a backend can report, classified so callers can decide whether to fall back
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("Model failed to load: {0}")]
+    ModelLoad(String),
+
+    #[error("Out of memory during generation: {0}")]
+    OutOfMemory(String),
+
+    #[error("Generation timed out")]
+    Timeout,
+
+    #[error("Backend error: {0}")]
+    Other(String),
+}
+
+impl BackendError {
+    /// A model that could not be loaded, whatever the underlying cause
+    pub fn model_load(error: anyhow::Error) -> anyhow::Error {
+        BackendError::ModelLoad(format!("{:#}", error)).into()
+    }
+
+    /// A failed generation, reclassified as out of memory when the host or device ran out
+    pub fn generation(error: anyhow::Error) -> anyhow::Error {
+        let message = format!("{:#}", error);
+        let lower = message.to_lowercase();
+        let out_of_memory = ["out of memory", "failed to allocate", "cannot allocate", "alloc failed"]
+            .iter()
+            .any(|pattern| lower.contains(pattern));
+        if out_of_memory {
+            BackendError::OutOfMemory(message).into()
+        } else {
+            error
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    ModelLoad,
+    OutOfMemory,
+    Timeout,
+    Any,
+}
+
+impl FailureClass {
+    /// Classify an error returned by a backend
+    pub fn of(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<BackendError>() {
+            Some(BackendError::ModelLoad(_)) => FailureClass::ModelLoad,
+            Some(BackendError::OutOfMemory(_)) => FailureClass::OutOfMemory,
+            Some(BackendError::Timeout) => FailureClass::Timeout,
+            _ => FailureClass::Any,
+        }
+    }
+}
+
+/// Which failures of the primary backend trigger a retry on the fallback
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallbackConfig {
+    pub enabled: bool,
+    pub on: Vec<FailureClass>,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            on: vec![FailureClass::ModelLoad, FailureClass::OutOfMemory],
+        }
+    }
+}
+
+impl FallbackConfig {
+    pub fn should_fall_back(&self, error: &anyhow::Error) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let class = FailureClass::of(error);
+        self.on.iter().any(|c| *c == FailureClass::Any || *c == class)
+    }
+}
+
+/// A text generation backend (local model or remote API)
+#[async_trait]
+pub trait Backend: Send + Sync {
+    fn kind(&self) -> BackendKind;
+
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String>;
+
+    /// Generate text meant to parse as JSON matching `schema`; by default the schema is only asked for in the prompt
+    async fn generate_json(&self, prompt: &str, schema: &OutputSchema, max_tokens: usize) -> Result<String> {
+        self.generate(&format!("{}\n\n{}", prompt, schema.prompt_instructions()), max_tokens).await
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Backend for crate::ai::local_llm::LocalLLM {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Local
+    }
+
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
+        crate::ai::local_llm::LocalLLM::generate(self, prompt, max_tokens).await
+    }
+
+    async fn generate_json(&self, prompt: &str, schema: &OutputSchema, max_tokens: usize) -> Result<String> {
+        crate::ai::local_llm::LocalLLM::generate_json(self, prompt, schema, max_tokens).await
+    }
+}
+
+/// Loads the model on its first request
+#[cfg(feature = "embeddings")]
+#[async_trait]
+impl Backend for crate::ai::local_llm_full::LocalLLM {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Local
+    }
+
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
+        self.ensure_loaded().await.map_err(BackendError::model_load)?;
+        let response = crate::ai::local_llm_full::LocalLLM::generate(self, self.request(prompt, max_tokens)).await
+            .map_err(BackendError::generation)?;
+        Ok(response.text)
+    }
+
+    async fn generate_json(&self, prompt: &str, schema: &OutputSchema, max_tokens: usize) -> Result<String> {
+        self.ensure_loaded().await.map_err(BackendError::model_load)?;
+        crate::ai::local_llm_full::LocalLLM::generate_json(self, self.request(prompt, max_tokens), schema).await
+            .map_err(BackendError::generation)
+    }
+}
+
+#[async_trait]
+impl Backend for APIClient {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Api
+    }
+
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
+        self.complete(prompt, max_tokens).await
+            .map_err(|e| BackendError::Other(format!("{:#}", e)).into())
+    }
+}
+
+/// Generated text annotated with the backend that produced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendResponse {
+    pub text: String,
+    pub served_by: BackendKind,
+    pub fallback_reason: Option<String>,
+}
+
+/// Tries the primary backend and retries on the fallback for configured failure classes
+pub struct FallbackChain {
+    primary: Arc<dyn Backend>,
+    fallback: Option<Arc<dyn Backend>>,
+    config: FallbackConfig,
+    logger: Logger,
+}
+
+impl FallbackChain {
+    pub fn new(primary: Arc<dyn Backend>, fallback: Option<Arc<dyn Backend>>, config: FallbackConfig) -> Self {
+        Self {
+            primary,
+            fallback,
+            config,
+            logger: Logger::new("FallbackChain"),
+        }
+    }
+
+    pub async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<BackendResponse> {
+        self.call(|backend| backend.generate(prompt, max_tokens)).await
+    }
+
+    /// Run `call` on the primary backend, and again on the fallback if it fails in a configured way
+    async fn call<'a, F>(&'a self, call: F) -> Result<BackendResponse>
+    where
+        F: Fn(&'a dyn Backend) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>,
+    {
+        let error = match call(self.primary.as_ref()).await {
+            Ok(text) => {
+                return Ok(BackendResponse {
+                    text,
+                    served_by: self.primary.kind(),
+                    fallback_reason: None,
+                });
+            }
+            Err(e) => e,
+        };
+
+        let fallback = match &self.fallback {
+            Some(fallback) if self.config.should_fall_back(&error) => fallback,
+            _ => return Err(error),
+        };
+
+        self.logger.warn(&format!(
+            "{:?} backend failed ({}), retrying on {:?}",
+            self.primary.kind(), error, fallback.kind()
+        ));
+
+        let text = call(fallback.as_ref()).await?;
+        Ok(BackendResponse {
+            text,
+            served_by: fallback.kind(),
+            fallback_reason: Some(error.to_string()),
+        })
+    }
+}
+
+/// Served by whichever backend answered; the primary's kind is reported
+#[async_trait]
+impl Backend for FallbackChain {
+    fn kind(&self) -> BackendKind {
+        self.primary.kind()
+    }
+
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
+        Ok(FallbackChain::generate(self, prompt, max_tokens).await?.text)
+    }
+
+    async fn generate_json(&self, prompt: &str, schema: &OutputSchema, max_tokens: usize) -> Result<String> {
+        Ok(self.call(|backend| backend.generate_json(prompt, schema, max_tokens)).await?.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingBackend(fn() -> BackendError);
+
+    #[async_trait]
+    impl Backend for FailingBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Local
+        }
+
+        async fn generate(&self, _prompt: &str, _max_tokens: usize) -> Result<String> {
+            Err((self.0)().into())
+        }
+    }
+
+    struct EchoApi;
+
+    #[async_trait]
+    impl Backend for EchoApi {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Api
+        }
+
+        async fn generate(&self, prompt: &str, _max_tokens: usize) -> Result<String> {
+            Ok(format!("api: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_failure_falls_back_to_api() {
+        let chain = FallbackChain::new(
+            Arc::new(FailingBackend(|| BackendError::OutOfMemory("kv cache".to_string()))),
+            Some(Arc::new(EchoApi)),
+            FallbackConfig::default(),
+        );
+
+        let response = chain.generate("hello", 16).await.unwrap();
+        assert_eq!(response.text, "api: hello");
+        assert_eq!(response.served_by, BackendKind::Api);
+        assert!(response.fallback_reason.is_some());
+    }
+
+    #[test]
+    fn test_allocation_failures_classify_as_out_of_memory() {
+        let oom = BackendError::generation(anyhow::anyhow!("CUDA_ERROR_OUT_OF_MEMORY: out of memory"));
+        assert_eq!(FailureClass::of(&oom), FailureClass::OutOfMemory);
+        let other = BackendError::generation(anyhow::anyhow!("Llama forward pass failed"));
+        assert_eq!(FailureClass::of(&other), FailureClass::Any);
+        let load = BackendError::model_load(anyhow::anyhow!("tiny.gguf is not a GGUF file"));
+        assert_eq!(FailureClass::of(&load), FailureClass::ModelLoad);
+    }
+
+    #[tokio::test]
+    async fn test_chain_as_backend_falls_back_on_load_failure() {
+        let chain: Arc<dyn Backend> = Arc::new(FallbackChain::new(
+            Arc::new(FailingBackend(|| BackendError::ModelLoad("missing weights".to_string()))),
+            Some(Arc::new(EchoApi)),
+            FallbackConfig::default(),
+        ));
+
+        assert_eq!(chain.generate("hello", 16).await.unwrap(), "api: hello");
+    }
+
+    #[tokio::test]
+    async fn test_unlisted_failure_does_not_fall_back() {
+        let chain = FallbackChain::new(
+            Arc::new(FailingBackend(|| BackendError::Timeout)),
+            Some(Arc::new(EchoApi)),
+            FallbackConfig::default(),
+        );
+
+        assert!(chain.generate("hello", 16).await.is_err());
+    }
+}
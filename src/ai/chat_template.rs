@@ -0,0 +1,107 @@
+// src/ai/chat_template.rs - Prompt formats of the chat model families the local LLM runs
+use serde::{Deserialize, Serialize};
+
+/// How a model family expects a conversation turn to be laid out, including its BOS token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatTemplate {
+    /// `<s>[INST] <<SYS>>...<</SYS>> ... [/INST]`, also used by Code Llama
+    Llama2,
+    /// `<|im_start|>role ... <|im_end|>`, used by Hermes and Qwen
+    #[serde(rename = "chatml")]
+    ChatML,
+    /// `[INST] ... [/INST]` with no system role, so the system prompt leads the first message
+    Mistral,
+    /// Phi-3: `<|user|> ... <|end|>`
+    Phi,
+    /// `<|user|> ... </s>`
+    Zephyr,
+}
+
+impl ChatTemplate {
+    /// A single-turn prompt ending where the assistant's reply starts; `context` is
+    /// retrieved text placed ahead of the question
+    pub fn render(&self, system: Option<&str>, context: Option<&str>, prompt: &str) -> String {
+        let user = match context {
+            Some(context) => format!("Context:\n{}\n\n{}", context, prompt),
+            None => prompt.to_string(),
+        };
+        match (self, system) {
+            (ChatTemplate::Llama2, Some(system)) => {
+                format!("<s>[INST] <<SYS>>\n{}\n<</SYS>>\n\n{} [/INST]", system, user)
+            }
+            (ChatTemplate::Llama2, None) => format!("<s>[INST] {} [/INST]", user),
+            (ChatTemplate::Mistral, Some(system)) => format!("<s>[INST] {}\n\n{} [/INST]", system, user),
+            (ChatTemplate::Mistral, None) => format!("<s>[INST] {} [/INST]", user),
+            (ChatTemplate::ChatML, system) => {
+                let system = system
+                    .map(|s| format!("<|im_start|>system\n{}<|im_end|>\n", s))
+                    .unwrap_or_default();
+                format!("{}<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n", system, user)
+            }
+            (ChatTemplate::Phi, system) => {
+                let system = system
+                    .map(|s| format!("<|system|>\n{}<|end|>\n", s))
+                    .unwrap_or_default();
+                format!("<s>{}<|user|>\n{}<|end|>\n<|assistant|>\n", system, user)
+            }
+            (ChatTemplate::Zephyr, system) => {
+                let system = system
+                    .map(|s| format!("<|system|>\n{}</s>\n", s))
+                    .unwrap_or_default();
+                format!("{}<|user|>\n{}</s>\n<|assistant|>\n", system, user)
+            }
+        }
+    }
+
+    /// The token that ends the assistant's turn
+    pub fn stop_token(&self) -> &'static str {
+        match self {
+            ChatTemplate::Llama2 | ChatTemplate::Mistral | ChatTemplate::Zephyr => "</s>",
+            ChatTemplate::ChatML => "<|im_end|>",
+            ChatTemplate::Phi => "<|end|>",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_template_matches_its_documented_format() {
+        let system = Some("You are a helpful assistant.");
+        let cases = [
+            (
+                ChatTemplate::Llama2,
+                "<s>[INST] <<SYS>>\nYou are a helpful assistant.\n<</SYS>>\n\nWhen do I water? [/INST]",
+            ),
+            (
+                ChatTemplate::ChatML,
+                "<|im_start|>system\nYou are a helpful assistant.<|im_end|>\n<|im_start|>user\nWhen do I water?<|im_end|>\n<|im_start|>assistant\n",
+            ),
+            (
+                ChatTemplate::Mistral,
+                "<s>[INST] You are a helpful assistant.\n\nWhen do I water? [/INST]",
+            ),
+            (
+                ChatTemplate::Phi,
+                "<s><|system|>\nYou are a helpful assistant.<|end|>\n<|user|>\nWhen do I water?<|end|>\n<|assistant|>\n",
+            ),
+            (
+                ChatTemplate::Zephyr,
+                "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\nWhen do I water?</s>\n<|assistant|>\n",
+            ),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(template.render(system, None, "When do I water?"), expected, "{:?}", template);
+        }
+
+        assert_eq!(ChatTemplate::Llama2.render(None, None, "Hi"), "<s>[INST] Hi [/INST]");
+        assert_eq!(
+            ChatTemplate::ChatML.render(None, Some("Water at dawn."), "When?"),
+            "<|im_start|>user\nContext:\nWater at dawn.\n\nWhen?<|im_end|>\n<|im_start|>assistant\n",
+        );
+        assert_eq!(serde_json::from_str::<ChatTemplate>("\"chatml\"").unwrap(), ChatTemplate::ChatML);
+    }
+}
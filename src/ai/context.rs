@@ -1,8 +1,18 @@
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::RwLock;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, anyhow};
+use super::tokens::{truncate_to_tokens, HeuristicTokenCounter, TokenCounter};
+use super::trace::{QueryStage, QueryTrace};
+use crate::vault::embeddings::cosine_similarity;
+use crate::vault::focus::Focus;
+
+/// Placed between passages in the assembled context
+const CHUNK_SEPARATOR: &str = "\n---\n";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
@@ -15,6 +25,66 @@ pub struct Document {
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+/// Where a document came from, kept in `metadata["source_type"]`; documents without one are notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceType {
+    Note,
+    Conversation,
+    /// A web page saved with `note-to-ai save`
+    WebSave,
+    Task,
+}
+
+impl SourceType {
+    pub const METADATA_KEY: &'static str = "source_type";
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceType::Note => "note",
+            SourceType::Conversation => "conversation",
+            SourceType::WebSave => "web_save",
+            SourceType::Task => "task",
+        }
+    }
+
+    /// Source of a vault note from its tags; saved pages are tagged `clipping` and indexed chat turns `conversation`
+    pub fn from_tags(tags: &[String]) -> Self {
+        let tagged = |name: &str| tags.iter().any(|t| t.trim_start_matches('#').eq_ignore_ascii_case(name));
+        if tagged("clipping") {
+            SourceType::WebSave
+        } else if tagged("conversation") {
+            SourceType::Conversation
+        } else if tagged("task") || tagged("tasks") {
+            SourceType::Task
+        } else {
+            SourceType::Note
+        }
+    }
+}
+
+impl Document {
+    pub fn with_source_type(mut self, source_type: SourceType) -> Self {
+        self.metadata.insert(SourceType::METADATA_KEY.to_string(), source_type.as_str().to_string());
+        self
+    }
+
+    /// Tags of the source note, kept comma-separated in `metadata["tags"]`
+    pub fn tags(&self) -> Vec<String> {
+        self.metadata.get("tags")
+            .map(|tags| tags.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn source_type(&self) -> SourceType {
+        match self.metadata.get(SourceType::METADATA_KEY).map(String::as_str) {
+            Some("conversation") => SourceType::Conversation,
+            Some("web_save") => SourceType::WebSave,
+            Some("task") => SourceType::Task,
+            _ => SourceType::Note,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RetrievalResult {
     pub document: Document,
@@ -32,6 +102,27 @@ pub struct ContextQuery {
     pub min_similarity: f32,
     pub context_window: usize,
     pub include_metadata: bool,
+    /// Relevance/diversity balance for MMR selection; `None` takes the top results by relevance
+    pub mmr_lambda: Option<f32>,
+    /// Only passages from notes inside the focus are retrieved
+    pub focus: Option<Focus>,
+}
+
+/// How many passages go into the context and how they are picked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalConfig {
+    pub max_documents: usize,
+    /// 1.0 ranks purely by relevance; lower values favour passages unlike those already chosen
+    pub mmr_lambda: f32,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            max_documents: 5,
+            mmr_lambda: 0.7,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,29 +132,46 @@ pub struct ContextWindow {
     pub reserved_tokens: usize, // For system prompt, etc.
 }
 
+/// Where the passages for a query come from
+#[async_trait]
+pub trait Retriever: Send + Sync {
+    /// Passages for `query`, best first, recording the stages that ran in `trace`
+    async fn retrieve(&self, query: &ContextQuery, trace: &mut QueryTrace) -> Result<Vec<RetrievalResult>>;
+
+    /// Changes whenever the passages a query could retrieve change
+    fn corpus_version(&self) -> u64;
+}
+
 #[derive(Debug)]
 pub struct ContextBuilder {
     documents: Arc<RwLock<HashMap<String, Document>>>,
     embeddings_cache: Arc<RwLock<HashMap<String, Vec<f32>>>>,
     context_templates: Arc<RwLock<HashMap<String, String>>>,
+    /// Sizes passages against the context budget
+    counter: Arc<dyn TokenCounter>,
+    /// Bumped on every change to the documents, so derived answers can be invalidated
+    corpus_version: AtomicU64,
 }
 
 impl ContextBuilder {
     pub fn new() -> Self {
-        let builder = Self {
+        Self {
             documents: Arc::new(RwLock::new(HashMap::new())),
             embeddings_cache: Arc::new(RwLock::new(HashMap::new())),
-            context_templates: Arc::new(RwLock::new(HashMap::new())),
-        };
-        
-        // TODO: Initialize default templates in a better way
-        // For now, just return the builder without async initialization
-        
-        builder
+            context_templates: Arc::new(RwLock::new(Self::default_templates())),
+            counter: Arc::new(HeuristicTokenCounter),
+            corpus_version: AtomicU64::new(0),
+        }
     }
 
-    async fn init_default_templates(&self) {
-        let mut templates = self.context_templates.write().await;
+    /// Count passages with the model's tokenizer rather than estimating from length
+    pub fn with_token_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.counter = counter;
+        self
+    }
+
+    fn default_templates() -> HashMap<String, String> {
+        let mut templates = HashMap::new();
         
         templates.insert("default".to_string(), 
             "Based on the following context, please answer the question:\n\nContext:\n{context}\n\nQuestion: {query}\n\nAnswer:".to_string());
@@ -76,6 +184,8 @@ impl ContextBuilder {
         
         templates.insert("reasoning".to_string(),
             "Given the following information:\n\n{context}\n\nReasoning Task: {query}\n\nPlease think step by step:".to_string());
+        
+        templates
     }
 
     /// Add documents to the knowledge base
@@ -90,85 +200,150 @@ impl ContextBuilder {
             documents.insert(doc.id.clone(), doc);
         }
         
+        self.corpus_version.fetch_add(1, Ordering::SeqCst);
         Ok(())
     }
 
-    /// Compute cosine similarity between two vectors
-    fn cosine_similarity(&self, a: &[f32], b: &[f32]) -> f32 {
-        if a.len() != b.len() {
-            return 0.0;
-        }
-        
-        let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-        
-        if norm_a == 0.0 || norm_b == 0.0 {
-            return 0.0;
-        }
-        
-        dot_product / (norm_a * norm_b)
+    /// Changes whenever documents are added or removed
+    pub fn corpus_version(&self) -> u64 {
+        self.corpus_version.load(Ordering::SeqCst)
     }
 
     /// Retrieve relevant documents based on query
     pub async fn retrieve_documents(&self, query: &ContextQuery) -> Result<Vec<RetrievalResult>> {
+        self.retrieve_documents_traced(query, &mut QueryTrace::new(&query.query)).await
+    }
+
+    /// `retrieve_documents`, recording the search, merge and rerank stages in `trace`
+    pub async fn retrieve_documents_traced(&self, query: &ContextQuery, trace: &mut QueryTrace) -> Result<Vec<RetrievalResult>> {
         let documents = self.documents.read().await;
         let embeddings_cache = self.embeddings_cache.read().await;
         
+        // Apply filters
+        let candidates = documents.iter().filter(|(_, document)| {
+            query.filters.iter().all(|(key, value)| document.metadata.get(key) == Some(value))
+                && query.focus.as_ref().is_none_or(|focus| focus.matches(Path::new(&document.source), &document.tags()))
+        });
+        
+        // Passages with an embedding are compared by vector, the rest fall back to keywords
+        let (by_vector, by_text): (Vec<_>, Vec<_>) = candidates
+            .map(|(doc_id, document)| {
+                let pair = query.query_embedding.as_ref().zip(embeddings_cache.get(doc_id));
+                (document, pair)
+            })
+            .partition(|(_, pair)| pair.is_some());
+        
         let mut results = Vec::new();
+        if !by_vector.is_empty() {
+            results.extend(trace.time(QueryStage::VectorSearch, || {
+                by_vector.iter()
+                    .filter_map(|(document, pair)| {
+                        let (query_emb, doc_emb) = (*pair)?;
+                        self.scored(query, document, cosine_similarity(query_emb, doc_emb))
+                    })
+                    .collect::<Vec<_>>()
+            }));
+        }
+        if !by_text.is_empty() {
+            results.extend(trace.time(QueryStage::TextSearch, || {
+                by_text.iter()
+                    .filter_map(|(document, _)| {
+                        self.scored(query, document, self.text_similarity(&query.query, &document.content))
+                    })
+                    .collect::<Vec<_>>()
+            }));
+        }
         
-        for (doc_id, document) in documents.iter() {
-            // Apply filters
-            let mut passes_filter = true;
-            for (key, value) in &query.filters {
-                if let Some(doc_value) = document.metadata.get(key) {
-                    if doc_value != value {
-                        passes_filter = false;
-                        break;
-                    }
-                } else {
-                    passes_filter = false;
-                    break;
-                }
-            }
-            
-            if !passes_filter {
-                continue;
+        // Sort by relevance score, breaking ties by id so equal scores order the same every run
+        trace.time(QueryStage::Merge, || {
+            results.sort_by(|a, b| {
+                b.relevance_score.partial_cmp(&a.relevance_score).unwrap()
+                    .then_with(|| a.document.id.cmp(&b.document.id))
+            });
+        });
+        
+        let results = match query.mmr_lambda {
+            Some(lambda) => trace.time(QueryStage::Rerank, || {
+                self.select_diverse(results, lambda, query.max_results, &embeddings_cache)
+            }),
+            None => {
+                results.truncate(query.max_results);
+                results
             }
-            
-            // Calculate similarity
-            let similarity_score = if let (Some(query_emb), Some(doc_emb)) = 
-                (&query.query_embedding, embeddings_cache.get(doc_id)) {
-                self.cosine_similarity(query_emb, doc_emb)
-            } else {
-                // Fallback to text-based similarity (simple keyword matching)
-                self.text_similarity(&query.query, &document.content)
+        };
+        
+        Ok(results)
+    }
+
+    /// A retrieval result for `document` when its similarity clears the query's threshold
+    fn scored(&self, query: &ContextQuery, document: &Document, similarity_score: f32) -> Option<RetrievalResult> {
+        if similarity_score < query.min_similarity {
+            return None;
+        }
+        
+        // Calculate relevance score (combines similarity with recency and metadata)
+        let relevance_score = self.calculate_relevance_score(similarity_score, document, &query.query);
+        Some(RetrievalResult {
+            document: document.clone(),
+            similarity_score,
+            relevance_score,
+            context_position: 0, // Will be set during context building
+        })
+    }
+
+    /// Maximal Marginal Relevance: repeatedly take the candidate with the best balance of
+    /// relevance and dissimilarity to what is already selected, so near-duplicates don't crowd the context
+    fn select_diverse(
+        &self,
+        mut candidates: Vec<RetrievalResult>,
+        lambda: f32,
+        limit: usize,
+        embeddings: &HashMap<String, Vec<f32>>,
+    ) -> Vec<RetrievalResult> {
+        let mut selected: Vec<RetrievalResult> = Vec::new();
+        
+        while selected.len() < limit && !candidates.is_empty() {
+            let mmr = |candidate: &RetrievalResult| {
+                let redundancy = selected
+                    .iter()
+                    .map(|chosen| self.document_similarity(&candidate.document, &chosen.document, embeddings))
+                    .fold(0.0, f32::max);
+                lambda * candidate.relevance_score - (1.0 - lambda) * redundancy
             };
             
-            if similarity_score >= query.min_similarity {
-                // Calculate relevance score (combines similarity with recency and metadata)
-                let relevance_score = self.calculate_relevance_score(
-                    similarity_score, 
-                    document, 
-                    &query.query
-                );
-                
-                results.push(RetrievalResult {
-                    document: document.clone(),
-                    similarity_score,
-                    relevance_score,
-                    context_position: 0, // Will be set during context building
-                });
+            // Candidates are sorted by relevance, so ties keep the more relevant one
+            let mut best = 0;
+            let mut best_score = mmr(&candidates[0]);
+            for (i, candidate) in candidates.iter().enumerate().skip(1) {
+                let score = mmr(candidate);
+                if score > best_score {
+                    best = i;
+                    best_score = score;
+                }
             }
+            selected.push(candidates.remove(best));
         }
         
-        // Sort by relevance score
-        results.sort_by(|a, b| b.relevance_score.partial_cmp(&a.relevance_score).unwrap());
+        selected
+    }
+
+    /// Cosine similarity of two documents' embeddings, or word overlap when either has none
+    fn document_similarity(&self, a: &Document, b: &Document, embeddings: &HashMap<String, Vec<f32>>) -> f32 {
+        if let (Some(a_emb), Some(b_emb)) = (embeddings.get(&a.id), embeddings.get(&b.id)) {
+            return cosine_similarity(a_emb, b_emb);
+        }
         
-        // Limit results
-        results.truncate(query.max_results);
+        let a_lower = a.content.to_lowercase();
+        let b_lower = b.content.to_lowercase();
+        let a_words: std::collections::HashSet<&str> = a_lower.split_whitespace().collect();
+        let b_words: std::collections::HashSet<&str> = b_lower.split_whitespace().collect();
+        let union = a_words.union(&b_words).count();
         
-        Ok(results)
+        if union == 0 {
+            return 0.0;
+        }
+        
+        a_words.intersection(&b_words).count() as f32 / union as f32
     }
 
     /// Simple text-based similarity fallback
@@ -227,18 +402,56 @@ impl ContextBuilder {
         window: &ContextWindow,
         template_name: Option<&str>
     ) -> Result<String> {
-        let results = self.retrieve_documents(query).await?;
-        
+        self.build_context_traced(query, window, template_name, &mut QueryTrace::new(&query.query)).await
+    }
+
+    /// `build_context`, recording retrieval and context assembly in `trace`
+    pub async fn build_context_traced(
+        &self,
+        query: &ContextQuery,
+        window: &ContextWindow,
+        template_name: Option<&str>,
+        trace: &mut QueryTrace,
+    ) -> Result<String> {
+        let results = self.retrieve_documents_traced(query, trace).await?;
+        self.build_context_from(query, window, &results, template_name, trace).await
+    }
+
+    /// Place already retrieved passages into the named template, within the window's budget
+    pub async fn build_context_from(
+        &self,
+        query: &ContextQuery,
+        window: &ContextWindow,
+        results: &[RetrievalResult],
+        template_name: Option<&str>,
+        trace: &mut QueryTrace,
+    ) -> Result<String> {
         if results.is_empty() {
             return Ok("No relevant context found.".to_string());
         }
         
+        let templates = self.context_templates.read().await;
+        Ok(trace.time(QueryStage::Enrich, || {
+            Self::assemble_context(query, window, results, template_name, &templates, self.counter.as_ref())
+        }))
+    }
+
+    /// Sourced passages within the window's token budget, placed into the named template
+    fn assemble_context(
+        query: &ContextQuery,
+        window: &ContextWindow,
+        results: &[RetrievalResult],
+        template_name: Option<&str>,
+        templates: &HashMap<String, String>,
+        counter: &dyn TokenCounter,
+    ) -> String {
         // Build context within token limits
+        let separator_tokens = counter.count(CHUNK_SEPARATOR);
         let mut context_parts = Vec::new();
         let mut used_tokens = 0;
-        let max_context_tokens = window.available_tokens - window.reserved_tokens;
+        let max_context_tokens = window.available_tokens.saturating_sub(window.reserved_tokens);
         
-        for (i, result) in results.iter().enumerate() {
+        for result in results {
             let doc_content = if query.include_metadata {
                 format!(
                     "[Source: {}]\n{}\n[Metadata: {:?}]\n",
@@ -250,40 +463,35 @@ impl ContextBuilder {
                 format!("[Source: {}]\n{}\n", result.document.source, result.document.content)
             };
             
-            // Rough token estimation (4 chars ≈ 1 token)
-            let estimated_tokens = doc_content.len() / 4;
+            let separator = if context_parts.is_empty() { 0 } else { separator_tokens };
+            let remaining_tokens = max_context_tokens.saturating_sub(used_tokens + separator);
+            let estimated_tokens = counter.count(&doc_content);
             
-            if used_tokens + estimated_tokens > max_context_tokens {
-                // Try to fit partial content
-                let remaining_chars = (max_context_tokens - used_tokens) * 4;
-                if remaining_chars > 100 { // Minimum useful content
-                    let truncated = format!(
-                        "[Source: {}]\n{}...\n",
-                        result.document.source,
-                        &result.document.content[..remaining_chars.min(result.document.content.len())]
-                    );
-                    context_parts.push(truncated);
+            if estimated_tokens > remaining_tokens {
+                // Try to fit partial content, keeping the source line and ellipsis within budget
+                let header = format!("[Source: {}]\n", result.document.source);
+                let content_tokens = remaining_tokens.saturating_sub(counter.count(&header) + counter.count("...\n"));
+                let truncated = truncate_to_tokens(counter, &result.document.content, content_tokens);
+                if truncated.len() > 100 { // Minimum useful content
+                    context_parts.push(format!("{}{}...\n", header, truncated));
                 }
                 break;
             }
             
             context_parts.push(doc_content);
-            used_tokens += estimated_tokens;
+            used_tokens += separator + estimated_tokens;
         }
         
-        let context_content = context_parts.join("\n---\n");
+        let context_content = context_parts.join(CHUNK_SEPARATOR);
         
         // Apply template
         let template_name = template_name.unwrap_or("default");
-        let templates = self.context_templates.read().await;
         let template = templates.get(template_name)
             .unwrap_or(templates.get("default").unwrap());
         
-        let final_context = template
+        template
             .replace("{context}", &context_content)
-            .replace("{query}", &query.query);
-        
-        Ok(final_context)
+            .replace("{query}", &query.query)
     }
 
     /// Add custom context template
@@ -321,6 +529,7 @@ impl ContextBuilder {
         
         documents.clear();
         embeddings.clear();
+        self.corpus_version.fetch_add(1, Ordering::SeqCst);
     }
 
     /// Remove documents by filter
@@ -351,6 +560,102 @@ impl ContextBuilder {
             removed_count += 1;
         }
         
+        if removed_count > 0 {
+            self.corpus_version.fetch_add(1, Ordering::SeqCst);
+        }
         Ok(removed_count)
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl Retriever for ContextBuilder {
+    async fn retrieve(&self, query: &ContextQuery, trace: &mut QueryTrace) -> Result<Vec<RetrievalResult>> {
+        self.retrieve_documents_traced(query, trace).await
+    }
+
+    fn corpus_version(&self) -> u64 {
+        ContextBuilder::corpus_version(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passage(id: &str, content: &str, embedding: Vec<f32>) -> Document {
+        Document {
+            id: id.to_string(),
+            content: content.to_string(),
+            metadata: HashMap::new(),
+            embedding: Some(embedding),
+            chunk_index: 0,
+            source: format!("notes/{}.md", id),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    fn query(mmr_lambda: Option<f32>) -> ContextQuery {
+        ContextQuery {
+            query: "how often should tomatoes be watered".to_string(),
+            query_embedding: Some(vec![1.0, 0.2, 0.0]),
+            filters: HashMap::new(),
+            max_results: 2,
+            min_similarity: 0.1,
+            context_window: 4096,
+            include_metadata: false,
+            mmr_lambda,
+            focus: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mmr_prefers_diverse_passage_over_near_duplicate() {
+        let builder = ContextBuilder::new();
+        builder.add_documents(vec![
+            passage("watering", "Water tomatoes every morning.", vec![1.0, 0.0, 0.0]),
+            passage("watering-copy", "Tomatoes: water every morning", vec![0.99, 0.05, 0.0]),
+            passage("heatwave", "Water twice daily in heatwaves", vec![0.6, 0.8, 0.0]),
+        ]).await.unwrap();
+
+        let ids = |results: Vec<RetrievalResult>| -> Vec<String> {
+            results.into_iter().map(|r| r.document.id).collect()
+        };
+
+        let top_k = ids(builder.retrieve_documents(&query(None)).await.unwrap());
+        assert!(top_k.contains(&"watering".to_string()) && top_k.contains(&"watering-copy".to_string()));
+
+        let diverse = ids(builder.retrieve_documents(&query(Some(0.5))).await.unwrap());
+        assert_eq!(diverse.len(), 2);
+        assert!(diverse[0].starts_with("watering"));
+        assert_eq!(diverse[1], "heatwave");
+    }
+
+    #[tokio::test]
+    async fn test_default_templates_are_populated() {
+        let builder = ContextBuilder::new();
+        let templates = builder.context_templates.read().await;
+        for name in ["default", "summarization", "qa", "reasoning"] {
+            assert!(templates[name].contains("{context}"), "{} has no context slot", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_is_truncated_to_the_token_budget() {
+        let builder = ContextBuilder::new();
+        // Long enough that only part of the second passage fits
+        builder.add_documents(vec![
+            passage("watering", &"Water tomatoes every morning. ".repeat(20), vec![1.0, 0.0, 0.0]),
+            passage("heatwave", &"Water twice daily in heatwaves ünd shade them. ".repeat(40), vec![0.9, 0.1, 0.0]),
+        ]).await.unwrap();
+        let window = ContextWindow { total_tokens: 512, available_tokens: 400, reserved_tokens: 100 };
+
+        let context = builder.build_context(&query(None), &window, Some("missing")).await.unwrap();
+        assert!(context.starts_with("Based on the following context"), "falls back to the default template");
+        let passages = &context[context.find("Context:\n").unwrap() + 9..context.find("\n\nQuestion:").unwrap()];
+        assert!(passages.contains("[Source: notes/heatwave.md]") && passages.ends_with("...\n"));
+        assert!(HeuristicTokenCounter.count(passages) <= 300, "{} tokens", HeuristicTokenCounter.count(passages));
+
+        let qa = builder.build_context(&query(None), &window, Some("qa")).await.unwrap();
+        assert!(qa.starts_with("Context Information:"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::seed::RngSeed;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    MaxTokens,
+    StopToken,
+    EndOfSequence,
+    /// The time budget ran out; the text generated so far is returned
+    TimeBudget,
+    Error(String),
+}
+
+/// Limits checked between decode steps
+#[derive(Debug, Clone)]
+pub struct DecodeBudget {
+    max_tokens: usize,
+    time_budget: Option<Duration>,
+    started: Instant,
+}
+
+impl DecodeBudget {
+    pub fn new(max_tokens: usize, time_budget: Option<Duration>) -> Self {
+        Self {
+            max_tokens,
+            time_budget,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn from_millis(max_tokens: usize, time_budget_ms: Option<u64>) -> Self {
+        Self::new(max_tokens, time_budget_ms.map(Duration::from_millis))
+    }
+
+    /// Reason to stop decoding after `generated` tokens, if any limit has been reached.
+    /// The time budget only applies once at least one token exists to return.
+    pub fn exhausted(&self, generated: usize) -> Option<StopReason> {
+        if generated >= self.max_tokens {
+            return Some(StopReason::MaxTokens);
+        }
+
+        match self.time_budget {
+            Some(budget) if generated > 0 && self.started.elapsed() >= budget => Some(StopReason::TimeBudget),
+            _ => None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Temperature sampling over logits with optional top-k / top-p truncation and a
+/// repetition penalty, reproducible when the seed is set
+pub struct Sampler {
+    rng: StdRng,
+    temperature: f32,
+    top_k: Option<usize>,
+    top_p: f32,
+    repetition_penalty: f32,
+}
+
+impl Sampler {
+    pub fn new(seed: RngSeed, temperature: f32) -> Self {
+        Self {
+            rng: seed.rng("llm_sampling"),
+            temperature,
+            top_k: None,
+            top_p: 1.0,
+            repetition_penalty: 1.0,
+        }
+    }
+
+    /// Only the `k` most likely tokens are candidates
+    pub fn with_top_k(mut self, top_k: Option<usize>) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Only the smallest set of tokens whose probability adds up to `top_p` are candidates
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = top_p;
+        self
+    }
+
+    /// Divide the logits of tokens already in the context by `penalty` once per occurrence
+    pub fn with_repetition_penalty(mut self, penalty: f32) -> Self {
+        self.repetition_penalty = penalty;
+        self
+    }
+
+    /// Next token after `tokens`: the repetition penalty is applied to `logits`, then they are sampled
+    pub fn sample_next_token(&mut self, logits: &[f32], tokens: &[u32]) -> u32 {
+        if self.repetition_penalty == 1.0 {
+            return self.sample(logits) as u32;
+        }
+
+        let mut logits = logits.to_vec();
+        apply_repetition_penalty(&mut logits, tokens, self.repetition_penalty);
+        self.sample(&logits) as u32
+    }
+
+    /// Pick the next token index; a non-positive temperature is greedy
+    pub fn sample(&mut self, logits: &[f32]) -> usize {
+        if logits.is_empty() {
+            return 0;
+        }
+
+        if self.temperature <= 0.0 {
+            return logits
+                .iter()
+                .enumerate()
+                .fold(0, |best, (i, &logit)| if logit > logits[best] { i } else { best });
+        }
+
+        let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let mut candidates: Vec<(usize, f32)> = logits.iter()
+            .enumerate()
+            .map(|(i, &l)| (i, ((l - max) / self.temperature).exp()))
+            .collect();
+
+        if self.top_k.is_some() || self.top_p < 1.0 {
+            // Most likely first; ties keep index order so truncation is stable
+            candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            if let Some(k) = self.top_k {
+                candidates.truncate(k.max(1));
+            }
+            if self.top_p < 1.0 {
+                let total: f32 = candidates.iter().map(|(_, w)| w).sum();
+                let mut cumulative = 0.0;
+                let keep = candidates.iter()
+                    .position(|(_, w)| {
+                        cumulative += w / total;
+                        cumulative >= self.top_p
+                    })
+                    .map_or(candidates.len(), |i| i + 1);
+                candidates.truncate(keep);
+            }
+        }
+
+        let total: f32 = candidates.iter().map(|(_, w)| w).sum();
+        let mut target = self.rng.gen::<f32>() * total;
+        for (i, weight) in &candidates {
+            if target < *weight {
+                return *i;
+            }
+            target -= weight;
+        }
+        candidates.last().map_or(0, |(i, _)| *i)
+    }
+}
+
+/// Make tokens already in the context less likely: positive logits are divided by
+/// `penalty` and negative ones multiplied, once per occurrence
+pub fn apply_repetition_penalty(logits: &mut [f32], tokens: &[u32], penalty: f32) {
+    let mut counts = std::collections::HashMap::new();
+    for &token in tokens {
+        *counts.entry(token).or_insert(0i32) += 1;
+    }
+
+    for (token, count) in counts {
+        if let Some(logit) = logits.get_mut(token as usize) {
+            let factor = penalty.powi(count);
+            if *logit > 0.0 {
+                *logit /= factor;
+            } else {
+                *logit *= factor;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_k_of_one_makes_sampling_deterministic() {
+        let logits = [0.5, 2.0, 1.9, -1.0, 1.95];
+        let picks = |seed: u64| -> Vec<u32> {
+            let mut sampler = Sampler::new(RngSeed::resolve(Some(seed)), 0.9)
+                .with_top_k(Some(1))
+                .with_top_p(0.95)
+                .with_repetition_penalty(1.1);
+            (0..20).map(|_| sampler.sample_next_token(&logits, &[])).collect()
+        };
+
+        assert_eq!(picks(1), vec![1; 20]);
+        assert_eq!(picks(1), picks(2));
+
+        // Token 1 already appears twice, so the penalty hands the single candidate slot to token 4
+        let mut sampler = Sampler::new(RngSeed::resolve(Some(3)), 0.9)
+            .with_top_k(Some(1))
+            .with_repetition_penalty(1.1);
+        assert_eq!(sampler.sample_next_token(&logits, &[1, 1]), 4);
+
+        // Without truncation, other tokens do get sampled
+        let mut sampler = Sampler::new(RngSeed::resolve(Some(1)), 0.9);
+        assert!((0..50).any(|_| sampler.sample(&logits) != 1));
+    }
+}
@@ -0,0 +1,232 @@
+// src/ai/gguf.rs - Quantized GGUF model files: choosing a variant to download and reading headers
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::{Result, Context, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+const MAGIC: &[u8; 4] = b"GGUF";
+
+/// Quantization levels offered by `models download --quant`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GgufVariant {
+    /// ~4.5 bits per weight, the smallest and fastest
+    Q4_0,
+    /// ~4.8 bits per weight with better quality than q4_0; a good default for 7B models
+    Q4KM,
+    /// ~8.5 bits per weight, close to full precision
+    Q8_0,
+}
+
+impl GgufVariant {
+    /// How the variant appears in file names, e.g. `llama-2-7b.Q4_K_M.gguf`
+    pub fn tag(&self) -> &'static str {
+        match self {
+            GgufVariant::Q4_0 => "Q4_0",
+            GgufVariant::Q4KM => "Q4_K_M",
+            GgufVariant::Q8_0 => "Q8_0",
+        }
+    }
+
+    /// The file for this variant among a repository's files
+    pub fn pick_file<'a>(&self, files: &[&'a str]) -> Option<&'a str> {
+        let suffix = format!(".{}.gguf", self.tag().to_lowercase());
+        let dashed = format!("-{}.gguf", self.tag().to_lowercase());
+        files.iter()
+            .copied()
+            .filter(|file| {
+                let lower = file.to_lowercase();
+                lower.ends_with(&suffix) || lower.ends_with(&dashed)
+            })
+            // Split files (`-00001-of-00002`) aren't supported, so a single file wins
+            .min_by_key(|file| (file.contains("-of-"), file.len()))
+    }
+}
+
+impl FromStr for GgufVariant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "q4_0" => Ok(GgufVariant::Q4_0),
+            "q4_k_m" => Ok(GgufVariant::Q4KM),
+            "q8_0" => Ok(GgufVariant::Q8_0),
+            other => Err(anyhow!("Unsupported quantization {}; use q4_0, q4_k_m or q8_0", other)),
+        }
+    }
+}
+
+pub fn is_gguf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"))
+}
+
+/// The parts of a GGUF header needed to check a file before loading it
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufHeader {
+    pub version: u32,
+    pub tensor_count: u64,
+    /// String and integer metadata, such as `general.architecture`; arrays are left out
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl GgufHeader {
+    pub fn architecture(&self) -> Option<&str> {
+        self.metadata.get("general.architecture").map(String::as_str)
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::from_reader(&mut BufReader::new(file))
+            .with_context(|| format!("{} is not a valid GGUF file", path.display()))
+    }
+
+    pub fn from_reader(reader: &mut impl Read) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("missing GGUF magic");
+        }
+        let version = read_u32(reader)?;
+        if !(2..=3).contains(&version) {
+            bail!("unsupported GGUF version {}", version);
+        }
+        let tensor_count = read_u64(reader)?;
+        let metadata_count = read_u64(reader)?;
+
+        let mut metadata = BTreeMap::new();
+        for _ in 0..metadata_count {
+            let key = read_string(reader)?;
+            let value_type = read_u32(reader)?;
+            if let Some(value) = read_value(reader, value_type)? {
+                metadata.insert(key, value);
+            }
+        }
+        Ok(Self { version, tensor_count, metadata })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = read_u64(reader)?;
+    if len > 1 << 24 {
+        bail!("string of {} bytes", len);
+    }
+    let mut bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> Result<()> {
+    std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+    Ok(())
+}
+
+/// A metadata value as text; arrays such as the tokenizer vocabulary are skipped
+fn read_value(reader: &mut impl Read, value_type: u32) -> Result<Option<String>> {
+    let value = match value_type {
+        0 | 1 | 7 => {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte)?;
+            match value_type {
+                0 => byte[0].to_string(),
+                1 => (byte[0] as i8).to_string(),
+                _ => (byte[0] != 0).to_string(),
+            }
+        }
+        2 | 3 => {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes)?;
+            if value_type == 2 { u16::from_le_bytes(bytes).to_string() } else { i16::from_le_bytes(bytes).to_string() }
+        }
+        4 => read_u32(reader)?.to_string(),
+        5 => (read_u32(reader)? as i32).to_string(),
+        6 => f32::from_bits(read_u32(reader)?).to_string(),
+        8 => read_string(reader)?,
+        9 => {
+            let item_type = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            for _ in 0..len {
+                match item_type {
+                    8 => { read_string(reader)?; }
+                    0 | 1 | 7 => skip(reader, 1)?,
+                    2 | 3 => skip(reader, 2)?,
+                    4..=6 => skip(reader, 4)?,
+                    10..=12 => skip(reader, 8)?,
+                    other => bail!("unsupported array item type {}", other),
+                }
+            }
+            return Ok(None);
+        }
+        10 => read_u64(reader)?.to_string(),
+        11 => (read_u64(reader)? as i64).to_string(),
+        12 => f64::from_bits(read_u64(reader)?).to_string(),
+        other => bail!("unknown metadata type {}", other),
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_header_and_variant_selection() {
+        let mut file = Vec::new();
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&3u32.to_le_bytes());
+        file.extend_from_slice(&2u64.to_le_bytes());
+        file.extend_from_slice(&3u64.to_le_bytes());
+        string(&mut file, "general.architecture");
+        file.extend_from_slice(&8u32.to_le_bytes());
+        string(&mut file, "llama");
+        string(&mut file, "tokenizer.ggml.tokens");
+        file.extend_from_slice(&9u32.to_le_bytes());
+        file.extend_from_slice(&8u32.to_le_bytes());
+        file.extend_from_slice(&2u64.to_le_bytes());
+        string(&mut file, "<s>");
+        string(&mut file, "</s>");
+        string(&mut file, "general.file_type");
+        file.extend_from_slice(&4u32.to_le_bytes());
+        file.extend_from_slice(&15u32.to_le_bytes());
+
+        let header = GgufHeader::from_reader(&mut file.as_slice()).unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.tensor_count, 2);
+        assert_eq!(header.architecture(), Some("llama"));
+        assert_eq!(header.metadata["general.file_type"], "15");
+        assert!(!header.metadata.contains_key("tokenizer.ggml.tokens"));
+        assert!(GgufHeader::from_reader(&mut &b"GGML\x03\0\0\0"[..]).is_err());
+
+        let files = [
+            "README.md",
+            "llama-2-7b-chat.Q4_0.gguf",
+            "llama-2-7b-chat.Q4_K_M.gguf",
+            "llama-2-7b-chat.Q4_K_S.gguf",
+            "llama-2-7b-chat.Q8_0.gguf",
+        ];
+        let variant: GgufVariant = "q4_k_m".parse().unwrap();
+        assert_eq!(variant.pick_file(&files), Some("llama-2-7b-chat.Q4_K_M.gguf"));
+        assert_eq!(GgufVariant::Q8_0.pick_file(&files), Some("llama-2-7b-chat.Q8_0.gguf"));
+        assert_eq!(GgufVariant::Q4_0.pick_file(&["Phi-3-mini-4k-instruct-q4.gguf"]), None);
+        assert!("q2_k".parse::<GgufVariant>().is_err());
+    }
+}
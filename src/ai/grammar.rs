@@ -0,0 +1,472 @@
+// src/ai/grammar.rs - GBNF-style grammars that constrain decoding token by token
+use std::collections::HashMap;
+use anyhow::{Result, Context, anyhow};
+use crate::ai::generation::Sampler;
+
+/// Stacks deeper than this are dropped, which also stops left-recursive rules from looping
+const MAX_STACK_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn single(c: char) -> Self {
+        Self { ranges: vec![(c, c)], negated: false }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    Chars(CharClass),
+    Rule(usize),
+}
+
+/// Position of the next element to match: (rule, alternative, element)
+type Position = (usize, usize, usize);
+/// Positions to return to, innermost last; empty once the root rule is complete
+type Stack = Vec<Position>;
+
+/// A parsed grammar. Supports `name ::= ...` rules with `|` alternatives, "literals",
+/// `[a-z]`/`[^...]` character classes, `.`, `( ... )` groups and the `*`, `+`, `?` operators.
+/// Decoding starts at the `root` rule.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    /// Rule -> alternatives -> sequence of elements
+    rules: Vec<Vec<Vec<Element>>>,
+    root: usize,
+}
+
+impl Grammar {
+    pub fn parse(source: &str) -> Result<Self> {
+        GrammarParser::new(source).parse()
+    }
+
+    /// State before any text has been generated
+    pub fn start(&self) -> GrammarState<'_> {
+        let mut stacks = Vec::new();
+        for alternative in 0..self.rules[self.root].len() {
+            self.expand(vec![(self.root, alternative, 0)], &mut stacks);
+        }
+        GrammarState::new(self, stacks)
+    }
+
+    /// Resolve rule references and finished sequences until every stack waits on a character
+    fn expand(&self, mut stack: Stack, out: &mut Vec<Stack>) {
+        if stack.len() > MAX_STACK_DEPTH {
+            return;
+        }
+        let Some(&(rule, alternative, index)) = stack.last() else {
+            out.push(stack);
+            return;
+        };
+
+        let sequence = &self.rules[rule][alternative];
+        match sequence.get(index) {
+            None => {
+                stack.pop();
+                self.expand(stack, out);
+            }
+            Some(Element::Chars(_)) => out.push(stack),
+            Some(Element::Rule(id)) => {
+                stack.pop();
+                // Nothing to return to after a rule in tail position, so repetition doesn't grow the stack
+                if index + 1 < sequence.len() {
+                    stack.push((rule, alternative, index + 1));
+                }
+                for next in 0..self.rules[*id].len() {
+                    let mut branch = stack.clone();
+                    branch.push((*id, next, 0));
+                    self.expand(branch, out);
+                }
+            }
+        }
+    }
+}
+
+/// Every way the grammar could continue after the text accepted so far
+#[derive(Debug, Clone)]
+pub struct GrammarState<'g> {
+    grammar: &'g Grammar,
+    stacks: Vec<Stack>,
+}
+
+impl<'g> GrammarState<'g> {
+    fn new(grammar: &'g Grammar, mut stacks: Vec<Stack>) -> Self {
+        stacks.sort();
+        stacks.dedup();
+        Self { grammar, stacks }
+    }
+
+    /// State after `text`, or `None` if the grammar does not allow it here
+    pub fn accept(&self, text: &str) -> Option<Self> {
+        let mut stacks = self.stacks.clone();
+
+        for c in text.chars() {
+            let mut next = Vec::new();
+            for stack in &stacks {
+                let Some(&(rule, alternative, index)) = stack.last() else {
+                    continue;
+                };
+                if let Element::Chars(class) = &self.grammar.rules[rule][alternative][index] {
+                    if class.matches(c) {
+                        let mut advanced = stack.clone();
+                        advanced.pop();
+                        advanced.push((rule, alternative, index + 1));
+                        self.grammar.expand(advanced, &mut next);
+                    }
+                }
+            }
+
+            next.sort();
+            next.dedup();
+            if next.is_empty() {
+                return None;
+            }
+            stacks = next;
+        }
+
+        Some(Self { grammar: self.grammar, stacks })
+    }
+
+    /// Whether the text so far is a full match and generation may end here
+    pub fn is_complete(&self) -> bool {
+        self.stacks.iter().any(Vec::is_empty)
+    }
+
+    /// Mask every token the grammar rules out next; end-of-sequence only once complete
+    pub fn mask_logits(&self, vocab: &[String], eos: usize, logits: &mut [f32]) {
+        let complete = self.is_complete();
+        for (id, logit) in logits.iter_mut().enumerate() {
+            let allowed = if id == eos {
+                complete
+            } else {
+                vocab.get(id).is_some_and(|token| !token.is_empty() && self.accept(token).is_some())
+            };
+            if !allowed {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+    }
+}
+
+/// Sample tokens under the grammar mask until end-of-sequence.
+/// Fails if `max_tokens` runs out before the output is a full match.
+pub fn decode_with_grammar(
+    grammar: &Grammar,
+    vocab: &[String],
+    eos: usize,
+    max_tokens: usize,
+    sampler: &mut Sampler,
+    mut next_logits: impl FnMut(&str) -> Result<Vec<f32>>,
+) -> Result<String> {
+    let mut state = grammar.start();
+    let mut text = String::new();
+
+    for _ in 0..max_tokens {
+        let mut logits = next_logits(&text)?;
+        state.mask_logits(vocab, eos, &mut logits);
+        if logits.iter().all(|l| *l == f32::NEG_INFINITY) {
+            return Err(anyhow!("No token can continue {:?} within the grammar", text));
+        }
+
+        let id = sampler.sample(&logits);
+        if id == eos {
+            return Ok(text);
+        }
+        state = state.accept(&vocab[id]).context("Sampled a token the grammar rules out")?;
+        text.push_str(&vocab[id]);
+    }
+
+    if state.is_complete() {
+        Ok(text)
+    } else {
+        Err(anyhow!("Reached {} tokens before the output matched the grammar", max_tokens))
+    }
+}
+
+struct GrammarParser {
+    chars: Vec<char>,
+    pos: usize,
+    names: HashMap<String, usize>,
+    rules: Vec<Vec<Vec<Element>>>,
+    defined: Vec<bool>,
+}
+
+impl GrammarParser {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            names: HashMap::new(),
+            rules: Vec::new(),
+            defined: Vec::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Grammar> {
+        loop {
+            self.skip_space();
+            if self.peek().is_none() {
+                break;
+            }
+
+            let name = self.identifier()?;
+            self.skip_space();
+            if !self.eat("::=") {
+                return Err(anyhow!("Expected `::=` after rule name `{}`", name));
+            }
+            let alternatives = self.alternatives()?;
+
+            let id = self.rule_id(&name);
+            if self.defined[id] {
+                return Err(anyhow!("Rule `{}` is defined twice", name));
+            }
+            self.rules[id] = alternatives;
+            self.defined[id] = true;
+        }
+
+        if let Some((name, _)) = self.names.iter().find(|(_, id)| !self.defined[**id]) {
+            return Err(anyhow!("Rule `{}` is used but never defined", name));
+        }
+        let root = *self.names.get("root").context("Grammar has no `root` rule")?;
+        Ok(Grammar { rules: self.rules, root })
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Element>>> {
+        let mut alternatives = vec![self.sequence()?];
+        loop {
+            self.skip_space();
+            if !self.eat("|") {
+                return Ok(alternatives);
+            }
+            alternatives.push(self.sequence()?);
+        }
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Element>> {
+        let mut sequence = Vec::new();
+        loop {
+            self.skip_space();
+            let item = match self.peek() {
+                None | Some('|') | Some(')') => return Ok(sequence),
+                Some('"') => self.literal()?,
+                Some('[') => vec![Element::Chars(self.char_class()?)],
+                Some('.') => {
+                    self.pos += 1;
+                    vec![Element::Chars(CharClass { ranges: Vec::new(), negated: true })]
+                }
+                Some('(') => {
+                    self.pos += 1;
+                    let alternatives = self.alternatives()?;
+                    self.skip_space();
+                    if !self.eat(")") {
+                        return Err(anyhow!("Unclosed group at position {}", self.pos));
+                    }
+                    vec![Element::Rule(self.add_rule(alternatives))]
+                }
+                Some(c) if is_identifier_char(c) => {
+                    // The next rule's definition ends this one
+                    if self.at_definition() {
+                        return Ok(sequence);
+                    }
+                    let name = self.identifier()?;
+                    vec![Element::Rule(self.rule_id(&name))]
+                }
+                Some(c) => return Err(anyhow!("Unexpected `{}` at position {}", c, self.pos)),
+            };
+            sequence.extend(self.repetition(item));
+        }
+    }
+
+    /// Apply a `*`, `+` or `?` directly after an item by moving it into a helper rule
+    fn repetition(&mut self, item: Vec<Element>) -> Vec<Element> {
+        let operator = match self.peek() {
+            Some(op @ ('*' | '+' | '?')) => op,
+            _ => return item,
+        };
+        self.pos += 1;
+
+        let element = match item.as_slice() {
+            [single] => single.clone(),
+            _ => Element::Rule(self.add_rule(vec![item])),
+        };
+        let id = self.add_rule(Vec::new());
+        self.rules[id] = match operator {
+            '?' => vec![vec![element.clone()], Vec::new()],
+            _ => vec![vec![element.clone(), Element::Rule(id)], Vec::new()],
+        };
+
+        if operator == '+' {
+            vec![element, Element::Rule(id)]
+        } else {
+            vec![Element::Rule(id)]
+        }
+    }
+
+    fn literal(&mut self) -> Result<Vec<Element>> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        loop {
+            match self.next_char() {
+                None => return Err(anyhow!("Unterminated string literal")),
+                Some('"') => return Ok(elements),
+                Some('\\') => elements.push(Element::Chars(CharClass::single(self.escape()?))),
+                Some(c) => elements.push(Element::Chars(CharClass::single(c))),
+            }
+        }
+    }
+
+    fn char_class(&mut self) -> Result<CharClass> {
+        self.pos += 1;
+        let negated = self.eat("^");
+        let mut ranges = Vec::new();
+        loop {
+            let lo = match self.next_char() {
+                None => return Err(anyhow!("Unterminated character class")),
+                Some(']') => return Ok(CharClass { ranges, negated }),
+                Some('\\') => self.escape()?,
+                Some(c) => c,
+            };
+            let hi = if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                match self.next_char() {
+                    Some('\\') => self.escape()?,
+                    Some(c) => c,
+                    None => return Err(anyhow!("Unterminated character class")),
+                }
+            } else {
+                lo
+            };
+            ranges.push((lo, hi));
+        }
+    }
+
+    fn escape(&mut self) -> Result<char> {
+        match self.next_char() {
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some(c) => Ok(c),
+            None => Err(anyhow!("Unterminated escape")),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_identifier_char) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(anyhow!("Expected a rule name at position {}", self.pos));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn at_definition(&mut self) -> bool {
+        let start = self.pos;
+        let is_definition = self.identifier().is_ok() && {
+            self.skip_space();
+            self.eat("::=")
+        };
+        self.pos = start;
+        is_definition
+    }
+
+    fn rule_id(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.names.get(name) {
+            return id;
+        }
+        let id = self.add_rule(Vec::new());
+        self.defined[id] = false;
+        self.names.insert(name.to_string(), id);
+        id
+    }
+
+    fn add_rule(&mut self, alternatives: Vec<Vec<Element>>) -> usize {
+        self.rules.push(alternatives);
+        self.defined.push(true);
+        self.rules.len() - 1
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let token: Vec<char> = token.chars().collect();
+        if self.chars[self.pos..].starts_with(&token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepts(grammar: &Grammar, text: &str) -> bool {
+        grammar.start().accept(text).is_some_and(|state| state.is_complete())
+    }
+
+    #[test]
+    fn test_grammar_matches_alternatives_and_repetition() {
+        let answer = Grammar::parse(r#"root ::= "yes" | "no""#).unwrap();
+        assert!(accepts(&answer, "yes"));
+        assert!(accepts(&answer, "no"));
+        assert!(!accepts(&answer, "ye"));
+        assert!(answer.start().accept("ye").is_some());
+        assert!(answer.start().accept("maybe").is_none());
+
+        let list = Grammar::parse(r#"
+            # A bracketed list of signed integers
+            root ::= "[" (int ("," ws int)*)? "]"
+            int  ::= "-"? [0-9]+
+            ws   ::= [ \t]*
+        "#).unwrap();
+        assert!(accepts(&list, "[]"));
+        assert!(accepts(&list, "[1, -22,3]"));
+        assert!(!accepts(&list, "[1,]"));
+        assert!(list.start().accept("[1, x").is_none());
+    }
+
+    #[test]
+    fn test_invalid_grammars_are_rejected() {
+        assert!(Grammar::parse(r#"answer ::= "yes""#).is_err());
+        assert!(Grammar::parse(r#"root ::= digit"#).is_err());
+        assert!(Grammar::parse(r#"root ::= "unterminated"#).is_err());
+    }
+}
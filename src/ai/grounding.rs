@@ -0,0 +1,239 @@
+// src/ai/grounding.rs - Check generated answers against the passages they were meant to come from
+use std::collections::HashSet;
+use std::sync::Arc;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use super::backend::Backend;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroundingMethod {
+    /// Share of a claim's content words found in one passage; cheap, runs locally
+    #[default]
+    Overlap,
+    /// Ask the generation backend whether the passages support each claim; one call per claim
+    Entailment,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsupportedClaims {
+    /// Keep the claim and mark it `[unsupported]`
+    #[default]
+    Flag,
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroundingConfig {
+    /// Verify answers after generation; adds latency, a model call per claim with `entailment`
+    pub enabled: bool,
+    pub method: GroundingMethod,
+    /// Overlap a claim needs with a single passage to count as supported
+    pub min_support: f32,
+    pub unsupported: UnsupportedClaims,
+}
+
+impl Default for GroundingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: GroundingMethod::Overlap,
+            min_support: 0.6,
+            unsupported: UnsupportedClaims::Flag,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClaimCheck {
+    pub claim: String,
+    /// Byte range of the claim in the answer
+    pub start: usize,
+    pub end: usize,
+    pub support: f32,
+    pub supported: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GroundingReport {
+    pub claims: Vec<ClaimCheck>,
+}
+
+impl GroundingReport {
+    pub fn unsupported(&self) -> impl Iterator<Item = &ClaimCheck> {
+        self.claims.iter().filter(|check| !check.supported)
+    }
+
+    /// Share of claims the passages support; an answer without claims is fully grounded
+    pub fn confidence(&self) -> f32 {
+        if self.claims.is_empty() {
+            return 1.0;
+        }
+        self.claims.iter().filter(|check| check.supported).count() as f32 / self.claims.len() as f32
+    }
+
+    /// The answer with unsupported claims flagged or removed, followed by a confidence note
+    pub fn annotate(&self, answer: &str, unsupported: UnsupportedClaims) -> String {
+        let mut annotated = String::new();
+        let mut cursor = 0;
+        for check in self.unsupported() {
+            annotated.push_str(&answer[cursor..check.start]);
+            cursor = check.end;
+            match unsupported {
+                UnsupportedClaims::Flag => {
+                    annotated.push_str(&answer[check.start..check.end]);
+                    annotated.push_str(" [unsupported]");
+                }
+                // Drop the whitespace after a removed claim too, so no gap is left
+                UnsupportedClaims::Remove => {
+                    let rest = &answer[cursor..];
+                    cursor += rest.len() - rest.trim_start().len();
+                }
+            }
+        }
+        annotated.push_str(&answer[cursor..]);
+
+        let supported = self.claims.len() - self.unsupported().count();
+        format!(
+            "{}\n\n(Grounding: {} of {} claims supported by your notes, confidence {:.0}%)",
+            annotated.trim_end(), supported, self.claims.len(), self.confidence() * 100.0
+        )
+    }
+}
+
+pub struct GroundingVerifier {
+    config: GroundingConfig,
+    backend: Option<Arc<dyn Backend>>,
+}
+
+impl GroundingVerifier {
+    /// `backend` is required for the entailment method
+    pub fn new(config: GroundingConfig, backend: Option<Arc<dyn Backend>>) -> Self {
+        Self { config, backend }
+    }
+
+    /// Score every claim in `answer` against the retrieved `passages`
+    pub async fn verify(&self, answer: &str, passages: &[String]) -> Result<GroundingReport> {
+        let mut claims = Vec::new();
+        for (start, end) in claim_spans(answer) {
+            let claim = &answer[start..end];
+            let support = match self.config.method {
+                GroundingMethod::Overlap => overlap_support(claim, passages),
+                GroundingMethod::Entailment => self.entailment_support(claim, passages).await?,
+            };
+            claims.push(ClaimCheck {
+                claim: claim.to_string(),
+                start,
+                end,
+                support,
+                supported: support >= self.config.min_support,
+            });
+        }
+        Ok(GroundingReport { claims })
+    }
+
+    async fn entailment_support(&self, claim: &str, passages: &[String]) -> Result<f32> {
+        let backend = self.backend.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Entailment grounding needs a generation backend"))?;
+        let prompt = format!(
+            "Context:\n{}\n\nClaim: {}\n\nIs the claim stated or directly implied by the context? Answer only \"yes\" or \"no\".",
+            passages.join("\n\n"), claim
+        );
+        let verdict = backend.generate(&prompt, 4).await?;
+        Ok(if verdict.trim().to_lowercase().starts_with("yes") { 1.0 } else { 0.0 })
+    }
+}
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "are", "was", "were", "for", "with", "that", "this", "you", "your", "but",
+    "not", "have", "has", "had", "from", "they", "their", "its", "can", "will", "also", "into",
+    "about", "there", "than", "then", "should", "would", "could", "been", "every", "each",
+];
+
+/// Byte ranges of the sentences in `answer` that state something; questions and fragments are skipped
+fn claim_spans(answer: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = answer.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = match c {
+            '\n' => true,
+            '.' | '!' | '?' => chars.peek().is_none_or(|(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if at_boundary || chars.peek().is_none() {
+            let end = if c == '\n' { i } else { i + c.len_utf8() };
+            spans.push((start, end));
+            start = end;
+        }
+    }
+
+    spans.into_iter()
+        .map(|(start, end)| {
+            let text = &answer[start..end];
+            let leading = text.len() - text.trim_start().len();
+            (start + leading, start + text.trim_end().len())
+        })
+        .filter(|&(start, end)| {
+            let claim = &answer[start..end];
+            !claim.ends_with('?') && content_words(claim).len() >= 2
+        })
+        .collect()
+}
+
+fn content_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() > 2 && !STOPWORDS.contains(&word.as_str()))
+        .map(|word| match word.strip_suffix('s') {
+            Some(stem) if stem.len() > 3 => stem.to_string(),
+            _ => word,
+        })
+        .collect()
+}
+
+/// Best share of the claim's content words found together in one passage
+fn overlap_support(claim: &str, passages: &[String]) -> f32 {
+    let words = content_words(claim);
+    if words.is_empty() {
+        return 1.0;
+    }
+    passages.iter()
+        .map(|passage| {
+            let passage_words = content_words(passage);
+            words.intersection(&passage_words).count() as f32 / words.len() as f32
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unsupported_claim_is_flagged() {
+        let passages = vec![
+            "Tomatoes need watering every morning in summer.".to_string(),
+            "Basil grows well next to tomatoes.".to_string(),
+        ];
+        let answer = "Water tomatoes every morning in summer. Tomatoes were first grown on Mars in 1850. Want more tips?";
+
+        let verifier = GroundingVerifier::new(GroundingConfig { enabled: true, ..Default::default() }, None);
+        let report = verifier.verify(answer, &passages).await.unwrap();
+
+        assert_eq!(report.claims.len(), 2);
+        assert!(report.claims[0].supported);
+        let unsupported: Vec<_> = report.unsupported().map(|c| c.claim.as_str()).collect();
+        assert_eq!(unsupported, ["Tomatoes were first grown on Mars in 1850."]);
+        assert_eq!(report.confidence(), 0.5);
+
+        let flagged = report.annotate(answer, UnsupportedClaims::Flag);
+        assert!(flagged.contains("Mars in 1850. [unsupported] Want more tips?"));
+        assert!(flagged.ends_with("(Grounding: 1 of 2 claims supported by your notes, confidence 50%)"));
+
+        let removed = report.annotate(answer, UnsupportedClaims::Remove);
+        assert!(!removed.contains("Mars"));
+        assert!(removed.starts_with("Water tomatoes every morning in summer. Want more tips?"));
+    }
+}
@@ -1,10 +1,18 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use anyhow::{Result, anyhow};
 use crate::ai::model_switcher::{ModelSwitcher, TaskContext};
-use crate::ai::context::{ContextBuilder, ContextQuery, ContextWindow};
+use crate::ai::context::{ContextBuilder, ContextQuery, ContextWindow, Document, SourceType};
+use crate::ai::structured::{OutputSchema, StructuredOutputConfig};
+use crate::ai::tokens::{HeuristicTokenCounter, TokenCounter};
+use crate::vault::embedding_pool::EmbeddingWorker;
+use crate::vault::embeddings::EmbeddingVector;
+use crate::vault::parser::ObsidianParser;
+use crate::vault::search::VectorSearchEngine;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HermesMessage {
@@ -24,6 +32,9 @@ pub struct HermesRequest {
     pub presence_penalty: f32,
     pub stop: Option<Vec<String>>,
     pub stream: bool,
+    /// JSON mode: `{"type": "json_object", "schema": ...}` constrains the reply to the schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,14 +61,64 @@ pub struct HermesUsage {
     pub total_tokens: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HermesConfig {
     pub base_url: String,
+    #[serde(default)]
     pub api_key: String,
     pub default_model: String,
+    #[serde(default = "default_timeout_seconds")]
     pub timeout_seconds: u64,
+    #[serde(default = "default_max_retries")]
     pub max_retries: usize,
+    #[serde(default = "default_retry_delay_ms")]
     pub retry_delay_ms: u64,
+    /// Add each user and assistant turn to the search corpus as a conversation document
+    #[serde(default)]
+    pub index_conversations: bool,
+    /// Save conversations here after every turn; `restore_conversations` reloads them
+    #[serde(default)]
+    pub history_path: Option<PathBuf>,
+}
+
+fn default_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_max_retries() -> usize {
+    2
+}
+
+fn default_retry_delay_ms() -> u64 {
+    1000
+}
+
+/// Written to the first line of saved history; bump when `SavedConversation` changes shape
+const HISTORY_VERSION: u32 = 1;
+
+/// Saved conversations, keyed by conversation id
+#[derive(Debug, Serialize, Deserialize)]
+struct ConversationHistory {
+    version: u32,
+    #[serde(default)]
+    conversations: HashMap<String, SavedConversation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedConversation {
+    messages: Vec<HermesMessage>,
+    /// Recounted on load, since the tokenizer may have changed
+    #[serde(default)]
+    total_tokens: usize,
+    max_context_length: usize,
+    #[serde(default = "default_true")]
+    preserve_system_message: bool,
+    #[serde(default)]
+    indexed_turns: usize,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug)]
@@ -66,29 +127,57 @@ pub struct ConversationContext {
     pub total_tokens: usize,
     pub max_context_length: usize,
     pub preserve_system_message: bool,
+    /// Turns added to the search corpus so far; numbers the next indexed turn
+    pub indexed_turns: usize,
+    counter: Arc<dyn TokenCounter>,
 }
 
 impl ConversationContext {
     pub fn new(max_length: usize) -> Self {
+        Self::with_counter(max_length, Arc::new(HeuristicTokenCounter))
+    }
+
+    pub fn with_counter(max_length: usize, counter: Arc<dyn TokenCounter>) -> Self {
         Self {
             messages: Vec::new(),
             total_tokens: 0,
             max_context_length: max_length,
             preserve_system_message: true,
+            indexed_turns: 0,
+            counter,
         }
     }
 
     pub fn add_message(&mut self, message: HermesMessage) {
-        // Rough token estimation
-        let estimated_tokens = message.content.len() / 4;
+        let tokens = self.counter.count(&message.content);
         
         self.messages.push(message);
-        self.total_tokens += estimated_tokens;
+        self.total_tokens += tokens;
         
         // Trim context if needed
         self.trim_if_needed();
     }
 
+    fn to_saved(&self) -> SavedConversation {
+        SavedConversation {
+            messages: self.messages.clone(),
+            total_tokens: self.total_tokens,
+            max_context_length: self.max_context_length,
+            preserve_system_message: self.preserve_system_message,
+            indexed_turns: self.indexed_turns,
+        }
+    }
+
+    fn from_saved(saved: SavedConversation, counter: Arc<dyn TokenCounter>) -> Self {
+        let mut conversation = Self::with_counter(saved.max_context_length, counter);
+        conversation.preserve_system_message = saved.preserve_system_message;
+        conversation.indexed_turns = saved.indexed_turns;
+        conversation.total_tokens = saved.messages.iter().map(|m| conversation.counter.count(&m.content)).sum();
+        conversation.messages = saved.messages;
+        conversation.trim_if_needed();
+        conversation
+    }
+
     fn trim_if_needed(&mut self) {
         while self.total_tokens > self.max_context_length && self.messages.len() > 1 {
             // Find first non-system message to remove
@@ -99,7 +188,7 @@ impl ConversationContext {
             
             if remove_index < self.messages.len() {
                 let removed = self.messages.remove(remove_index);
-                let removed_tokens = removed.content.len() / 4;
+                let removed_tokens = self.counter.count(&removed.content);
                 self.total_tokens = self.total_tokens.saturating_sub(removed_tokens);
             } else {
                 break;
@@ -113,7 +202,10 @@ pub struct HermesIntegration {
     client: Client,
     model_switcher: Arc<ModelSwitcher>,
     context_builder: Arc<ContextBuilder>,
-    conversations: Arc<RwLock<std::collections::HashMap<String, ConversationContext>>>,
+    conversations: Arc<RwLock<HashMap<String, ConversationContext>>>,
+    counter: Arc<dyn TokenCounter>,
+    /// Where indexed turns are persisted, with the embedder for their vectors
+    search_index: Option<(Arc<VectorSearchEngine>, Arc<dyn EmbeddingWorker>)>,
 }
 
 impl HermesIntegration {
@@ -132,15 +224,29 @@ impl HermesIntegration {
             client,
             model_switcher,
             context_builder,
-            conversations: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            conversations: Arc::new(RwLock::new(HashMap::new())),
+            counter: Arc::new(HeuristicTokenCounter),
+            search_index: None,
         }
     }
 
+    /// Also write indexed turns to the search index, so they're found after a restart
+    pub fn with_search_index(mut self, engine: Arc<VectorSearchEngine>, embedder: Arc<dyn EmbeddingWorker>) -> Self {
+        self.search_index = Some((engine, embedder));
+        self
+    }
+
+    /// Budget conversations with the model's tokenizer rather than estimating from length
+    pub fn with_token_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.counter = counter;
+        self
+    }
+
     /// Initialize a new conversation
     pub async fn create_conversation(&self, conversation_id: String, system_prompt: Option<String>) -> Result<()> {
         let mut conversations = self.conversations.write().await;
         
-        let mut context = ConversationContext::new(8192); // Default context length
+        let mut context = ConversationContext::with_counter(8192, self.counter.clone()); // Default context length
         
         if let Some(prompt) = system_prompt {
             context.add_message(HermesMessage {
@@ -162,6 +268,68 @@ impl HermesIntegration {
         rag_query: Option<ContextQuery>,
         task_context: Option<TaskContext>,
     ) -> Result<HermesResponse> {
+        self.chat_with_model(conversation_id, user_message, rag_query, task_context, None).await
+    }
+
+    /// Send a message using an explicit model for this request only
+    pub async fn chat_with_model(
+        &self,
+        conversation_id: &str,
+        user_message: &str,
+        rag_query: Option<ContextQuery>,
+        task_context: Option<TaskContext>,
+        model_override: Option<&str>,
+    ) -> Result<HermesResponse> {
+        self.send_chat(conversation_id, user_message, rag_query, task_context, model_override, None).await
+    }
+
+    /// Send a message and parse the reply as JSON matching `schema`, using the API's JSON mode.
+    /// A reply that fails to parse or validate is sent back for repair.
+    pub async fn chat_structured(
+        &self,
+        conversation_id: &str,
+        user_message: &str,
+        schema: &OutputSchema,
+        config: &StructuredOutputConfig,
+    ) -> Result<serde_json::Value> {
+        let response_format = serde_json::json!({ "type": "json_object", "schema": schema.as_value() });
+        let mut message = format!("{}\n\n{}", user_message, schema.prompt_instructions());
+        let mut attempts = 0;
+
+        loop {
+            let response = self.send_chat(conversation_id, &message, None, None, None, Some(response_format.clone())).await?;
+            let output = response.choices.first()
+                .map(|choice| choice.message.content.clone())
+                .unwrap_or_default();
+
+            match schema.parse(&output) {
+                Ok(value) => return Ok(value),
+                Err(error) if attempts < config.repair_attempts => {
+                    attempts += 1;
+                    message = schema.repair_prompt(&output, &error);
+                }
+                Err(error) => {
+                    return Err(anyhow!("Reply did not match the schema after {} repair attempts: {}", attempts, error));
+                }
+            }
+        }
+    }
+
+    async fn send_chat(
+        &self,
+        conversation_id: &str,
+        user_message: &str,
+        rag_query: Option<ContextQuery>,
+        task_context: Option<TaskContext>,
+        model_override: Option<&str>,
+        response_format: Option<serde_json::Value>,
+    ) -> Result<HermesResponse> {
+        // Validate the override before touching the conversation
+        let override_model = match model_override {
+            Some(name) => Some(self.model_switcher.resolve_override(name).await?),
+            None => None,
+        };
+
         // Get or create conversation
         let mut conversations = self.conversations.write().await;
         let conversation = conversations.get_mut(conversation_id)
@@ -188,8 +356,10 @@ impl HermesIntegration {
             metadata: None,
         });
 
-        // Select optimal model
-        let model_name = if let Some(task_ctx) = task_context {
+        // Select optimal model (a per-request override bypasses the switcher)
+        let model_name = if let Some(name) = override_model {
+            name
+        } else if let Some(task_ctx) = task_context {
             self.model_switcher.select_model(&task_ctx).await?
         } else {
             self.model_switcher.get_current_model().await
@@ -210,6 +380,7 @@ impl HermesIntegration {
             presence_penalty: 0.0,
             stop: None,
             stream: false,
+            response_format,
         };
 
         // Send request with retries
@@ -228,11 +399,90 @@ impl HermesIntegration {
             conversation.add_message(choice.message.clone());
         }
 
+        // The user's own words are indexed, not the RAG-enhanced prompt
+        let turns = if self.config.index_conversations {
+            let mut turns = vec![HermesMessage {
+                role: "user".to_string(),
+                content: user_message.to_string(),
+                metadata: None,
+            }];
+            turns.extend(response.choices.first().map(|choice| choice.message.clone()));
+            self.conversation_documents(conversation_id, conversation, &turns)
+        } else {
+            Vec::new()
+        };
+
         drop(conversations); // Release lock
 
+        if !turns.is_empty() {
+            if let Err(e) = self.index_turns(&turns).await {
+                tracing::warn!("Failed to add conversation {} to the search index: {}", conversation_id, e);
+            }
+            self.context_builder.add_documents(turns).await?;
+        }
+        self.persist_conversations().await;
+
         Ok(response)
     }
 
+    /// Search documents for conversation turns, tagged so results can tell them apart from notes
+    fn conversation_documents(
+        &self,
+        conversation_id: &str,
+        conversation: &mut ConversationContext,
+        turns: &[HermesMessage],
+    ) -> Vec<Document> {
+        let now = chrono::Utc::now();
+        turns.iter()
+            .filter(|turn| !turn.content.trim().is_empty())
+            .map(|turn| {
+                conversation.indexed_turns += 1;
+                let mut metadata = std::collections::HashMap::new();
+                metadata.insert("conversation_id".to_string(), conversation_id.to_string());
+                metadata.insert("role".to_string(), turn.role.clone());
+                Document {
+                    id: format!("conversation:{}:{}", conversation_id, conversation.indexed_turns),
+                    content: turn.content.clone(),
+                    metadata,
+                    embedding: None,
+                    chunk_index: 0,
+                    source: format!("Conversation {} ({}, {})", conversation_id, turn.role, now.format("%Y-%m-%d")),
+                    timestamp: now,
+                }
+                .with_source_type(SourceType::Conversation)
+            })
+            .collect()
+    }
+
+    /// Write turns to the search index as `conversations/<id>/<turn>.md`, tagged `conversation`
+    async fn index_turns(&self, turns: &[Document]) -> Result<()> {
+        let Some((engine, embedder)) = &self.search_index else {
+            return Ok(());
+        };
+
+        let parser = ObsidianParser::new()?;
+        for turn in turns {
+            let path = PathBuf::from(turn.id.replacen("conversation:", "conversations/", 1).replace(':', "/") + ".md");
+            let content = format!(
+                "---\ntitle: {:?}\ncreated: {}\ntags: [conversation]\n---\n\n{}\n",
+                turn.source,
+                turn.timestamp.to_rfc3339(),
+                turn.content,
+            );
+            let document = parser.parse_content(&path, &content).await?;
+            let embedding = EmbeddingVector {
+                text: document.plain_text.clone(),
+                vector: embedder.embed(&document.plain_text).await?,
+                model_name: engine.model_version().to_string(),
+                created_at: turn.timestamp,
+                block_embeddings: None,
+                title_vector: None,
+            };
+            engine.index_document(&document, &embedding).await?;
+        }
+        Ok(())
+    }
+
     /// Send a simple chat message without RAG
     pub async fn chat(
         &self,
@@ -320,17 +570,164 @@ impl HermesIntegration {
         Ok(embedding)
     }
 
-    /// Stream chat response (for real-time applications)
+    /// Stream the reply over server-sent events, calling `callback` with each token as it arrives.
+    /// The assembled reply joins the conversation once the stream finishes; a stream that errors
+    /// or drops before `[DONE]` leaves it out and fails with how much was received
     pub async fn chat_stream(
         &self,
         conversation_id: &str,
         user_message: &str,
         callback: impl Fn(String) -> Result<()> + Send + Sync,
     ) -> Result<()> {
-        // Implementation for streaming responses
-        // This would use Server-Sent Events or WebSocket connection
-        // For brevity, returning not implemented
-        Err(anyhow!("Streaming not implemented in this example"))
+        // The lock isn't held while streaming, so other conversations carry on meanwhile
+        let messages = {
+            let mut conversations = self.conversations.write().await;
+            let conversation = conversations.get_mut(conversation_id)
+                .ok_or_else(|| anyhow!("Conversation {} not found", conversation_id))?;
+            conversation.add_message(HermesMessage {
+                role: "user".to_string(),
+                content: user_message.to_string(),
+                metadata: None,
+            });
+            conversation.messages.clone()
+        };
+
+        let model_name = self.model_switcher.get_current_model().await
+            .unwrap_or_else(|| self.config.default_model.clone());
+        let model_config = self.model_switcher.get_model_config(&model_name).await?;
+        let request = HermesRequest {
+            model: model_name.clone(),
+            messages,
+            temperature: model_config.temperature,
+            max_tokens: model_config.max_tokens,
+            top_p: 0.9,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            stop: None,
+            stream: true,
+            response_format: None,
+        };
+
+        let start_time = std::time::Instant::now();
+        let mut reply = String::new();
+        let streamed = self.stream_request(&request, |token| {
+            reply.push_str(&token);
+            callback(token)
+        }).await;
+        let latency = start_time.elapsed().as_millis() as u64;
+        let cost = self.counter.count(&reply) as f64 * model_config.cost_per_token;
+        self.model_switcher.record_metrics(&model_name, latency, cost, streamed.is_ok()).await;
+        streamed.map_err(|e| anyhow!("{} after {} streamed characters", e, reply.chars().count()))?;
+
+        let mut conversations = self.conversations.write().await;
+        if let Some(conversation) = conversations.get_mut(conversation_id) {
+            conversation.add_message(HermesMessage {
+                role: "assistant".to_string(),
+                content: reply,
+                metadata: None,
+            });
+        }
+        drop(conversations);
+        self.persist_conversations().await;
+        Ok(())
+    }
+
+    /// Write every conversation to `path` as JSON, replacing the file in one step
+    pub async fn save_conversations(&self, path: &Path) -> Result<()> {
+        let history = {
+            let conversations = self.conversations.read().await;
+            ConversationHistory {
+                version: HISTORY_VERSION,
+                conversations: conversations.iter()
+                    .map(|(id, conversation)| (id.clone(), conversation.to_saved()))
+                    .collect(),
+            }
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let partial = path.with_extension("json.partial");
+        tokio::fs::write(&partial, serde_json::to_vec_pretty(&history)?).await?;
+        tokio::fs::rename(&partial, path).await?;
+        Ok(())
+    }
+
+    /// Add the conversations saved at `path`, replacing any with the same id; returns how
+    /// many were loaded. History written by a newer version is refused rather than misread
+    pub async fn load_conversations(&self, path: &Path) -> Result<usize> {
+        let data = tokio::fs::read(path).await
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let history: ConversationHistory = serde_json::from_slice(&data)
+            .map_err(|e| anyhow!("Unreadable conversation history {}: {}", path.display(), e))?;
+        if history.version > HISTORY_VERSION {
+            return Err(anyhow!(
+                "Conversation history {} is version {}; this build reads up to {}",
+                path.display(), history.version, HISTORY_VERSION
+            ));
+        }
+
+        let count = history.conversations.len();
+        let mut conversations = self.conversations.write().await;
+        for (id, saved) in history.conversations {
+            conversations.insert(id, ConversationContext::from_saved(saved, self.counter.clone()));
+        }
+        Ok(count)
+    }
+
+    /// Reload conversations from `history_path` at startup; a missing file is a fresh start
+    pub async fn restore_conversations(&self) -> Result<usize> {
+        match &self.config.history_path {
+            Some(path) if path.exists() => self.load_conversations(path).await,
+            _ => Ok(0),
+        }
+    }
+
+    /// Save after a turn when `history_path` is set; a failed save doesn't fail the turn
+    async fn persist_conversations(&self) {
+        if let Some(path) = &self.config.history_path {
+            if let Err(e) = self.save_conversations(path).await {
+                tracing::warn!("Failed to save conversation history to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Send a `stream: true` request and pass each delta to `on_token` until `[DONE]`
+    async fn stream_request(
+        &self,
+        request: &HermesRequest,
+        mut on_token: impl FnMut(String) -> Result<()>,
+    ) -> Result<()> {
+        let url = format!("{}/v1/chat/completions", self.config.base_url);
+        let mut response = self.client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .header("Accept", "text/event-stream")
+            .json(request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(anyhow!("API request failed: {}", error_text));
+        }
+
+        // Chunks can split lines, and characters, anywhere; only whole lines are parsed
+        let mut buffer = Vec::new();
+        while let Some(chunk) = response.chunk().await
+            .map_err(|e| anyhow!("Stream interrupted: {}", e))?
+        {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                match parse_stream_line(&String::from_utf8_lossy(&line))? {
+                    Some(StreamEvent::Token(token)) => on_token(token)?,
+                    Some(StreamEvent::Done) => return Ok(()),
+                    None => {}
+                }
+            }
+        }
+
+        Err(anyhow!("Stream ended before [DONE]"))
     }
 
     /// Get conversation history
@@ -352,7 +749,7 @@ impl HermesIntegration {
                 let system_msg = conversation.messages[0].clone();
                 conversation.messages.clear();
                 conversation.messages.push(system_msg.clone());
-                conversation.total_tokens = system_msg.content.len() / 4;
+                conversation.total_tokens = conversation.counter.count(&system_msg.content);
             } else {
                 conversation.messages.clear();
                 conversation.total_tokens = 0;
@@ -417,6 +814,34 @@ impl HermesIntegration {
     }
 }
 
+/// What one line of an OpenAI-style event stream carries
+#[derive(Debug, PartialEq)]
+enum StreamEvent {
+    Token(String),
+    Done,
+}
+
+/// `data:` lines hold a JSON chunk or `[DONE]`; comments, other fields and empty deltas are skipped
+fn parse_stream_line(line: &str) -> Result<Option<StreamEvent>> {
+    let Some(data) = line.trim_end_matches(['\r', '\n']).strip_prefix("data:") else {
+        return Ok(None);
+    };
+    let data = data.trim_start();
+    if data == "[DONE]" {
+        return Ok(Some(StreamEvent::Done));
+    }
+
+    let chunk: serde_json::Value = serde_json::from_str(data)
+        .map_err(|e| anyhow!("Malformed stream chunk: {}", e))?;
+    if let Some(error) = chunk.get("error") {
+        return Err(anyhow!("API error mid-stream: {}", error));
+    }
+    Ok(chunk["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|token| !token.is_empty())
+        .map(|token| StreamEvent::Token(token.to_string())))
+}
+
 // Helper trait for cloning (you might need to implement this)
 impl Clone for HermesIntegration {
     fn clone(&self) -> Self {
@@ -426,6 +851,203 @@ impl Clone for HermesIntegration {
             model_switcher: Arc::clone(&self.model_switcher),
             context_builder: Arc::clone(&self.context_builder),
             conversations: Arc::clone(&self.conversations),
+            counter: Arc::clone(&self.counter),
+            search_index: self.search_index.clone(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_past_turns_are_found_in_the_search_index_after_a_restart() {
+        use crate::test_support::{http_response, http_server, BagOfWords};
+        use crate::vault::search::{SearchFilters, SearchOptions, SearchQuery};
+
+        let url = http_server(|_| {
+            let reply = serde_json::json!({
+                "id": "1", "object": "chat.completion", "created": 0, "model": "hermes",
+                "choices": [{ "index": 0, "finish_reason": "stop", "message": {
+                    "role": "assistant", "content": "Plant garlic cloves five centimetres deep in autumn.", "metadata": null } }],
+                "usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 },
+            });
+            vec![http_response("200 OK", "application/json", &reply.to_string())]
+        }).await;
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = Arc::new(VectorSearchEngine::new(db.clone()).unwrap());
+        engine.initialize().await.unwrap();
+
+        let (hermes, _) = streaming_hermes(url, None).await;
+        let mut hermes = hermes.with_search_index(engine, Arc::new(BagOfWords::Hashed(256)));
+        hermes.config.index_conversations = true;
+        hermes.create_conversation("weekend".to_string(), None).await.unwrap();
+        hermes.chat("weekend", "How deep should I plant garlic?", None).await.unwrap();
+
+        let restarted = VectorSearchEngine::new(db).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Hashed(256)));
+        restarted.initialize().await.unwrap();
+        let results = restarted.search(&SearchQuery {
+            text: "garlic cloves deep autumn".to_string(),
+            filters: SearchFilters::default(),
+            options: SearchOptions { similarity_threshold: 0.0, include_context: false, ..SearchOptions::default() },
+        }).await.unwrap();
+
+        let top = &results[0].document;
+        assert_eq!(top.path, PathBuf::from("conversations/weekend/2.md"));
+        assert_eq!(SourceType::from_tags(&top.tags), SourceType::Conversation);
+        assert!(results.iter().any(|r| r.document.path == Path::new("conversations/weekend/1.md")));
+    }
+
+    /// Serves one connection per body, writing each event as its own chunk, then closes
+    async fn sse_server(bodies: Vec<Vec<&'static str>>) -> String {
+        let mut bodies = bodies.into_iter();
+        crate::test_support::http_server(move |request| {
+            assert!(request.body.contains("\"stream\":true"));
+            let mut chunks = vec!["HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n".to_string()];
+            chunks.extend(bodies.next().unwrap().into_iter().map(str::to_string));
+            chunks
+        }).await
+    }
+
+    /// Talks to `url` with a registered `hermes` model
+    async fn streaming_hermes(url: String, history_path: Option<PathBuf>) -> (HermesIntegration, Arc<ModelSwitcher>) {
+        let switcher = Arc::new(ModelSwitcher::new());
+        switcher.register_model(crate::ai::model_switcher::ModelConfig {
+            name: "hermes".to_string(),
+            endpoint: url.clone(),
+            max_tokens: 64,
+            temperature: 0.7,
+            cost_per_token: 0.0,
+            latency_ms: 0,
+            capabilities: Vec::new(),
+            context_window: 8192,
+            is_available: true,
+        }).await.unwrap();
+        let hermes = HermesIntegration::new(
+            HermesConfig {
+                base_url: url,
+                api_key: String::new(),
+                default_model: "hermes".to_string(),
+                timeout_seconds: 5,
+                max_retries: 0,
+                retry_delay_ms: 0,
+                index_conversations: false,
+                history_path,
+            },
+            switcher.clone(),
+            Arc::new(ContextBuilder::new()),
+        );
+        (hermes, switcher)
+    }
+
+    #[tokio::test]
+    async fn test_model_override_answers_one_request_only() {
+        let url = crate::test_support::http_server(|request| {
+            let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            let reply = serde_json::json!({
+                "id": "1", "object": "chat.completion", "created": 0, "model": body["model"],
+                "choices": [{ "index": 0, "finish_reason": "stop",
+                    "message": { "role": "assistant", "content": "Water at dawn.", "metadata": null } }],
+                "usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 },
+            });
+            vec![crate::test_support::http_response("200 OK", "application/json", &reply.to_string())]
+        }).await;
+        let (hermes, switcher) = streaming_hermes(url.clone(), None).await;
+        switcher.register_model(crate::ai::model_switcher::ModelConfig {
+            name: "hermes-large".to_string(),
+            endpoint: url,
+            max_tokens: 64,
+            temperature: 0.7,
+            cost_per_token: 0.0,
+            latency_ms: 0,
+            capabilities: Vec::new(),
+            context_window: 8192,
+            is_available: true,
+        }).await.unwrap();
+        hermes.create_conversation("garden".to_string(), None).await.unwrap();
+
+        let overridden = hermes.chat_with_model("garden", "When should I water?", None, None, Some("hermes-large")).await.unwrap();
+        assert_eq!(overridden.model, "hermes-large");
+        let default = hermes.chat_with_model("garden", "And in summer?", None, None, None).await.unwrap();
+        assert_eq!(default.model, "hermes");
+
+        assert!(hermes.chat_with_model("garden", "Hello?", None, None, Some("missing")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_calls_back_per_token_and_records_reply() {
+        let url = sse_server(vec![
+            vec![
+                ": keep-alive\n\n",
+                "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Water \"}}]}\n\n",
+                // A chunk boundary in the middle of a line
+                "data: {\"choices\":[{\"delta\":{\"content\":\"at \"}}]}\n\ndata: {\"choices\":[{\"del",
+                "ta\":{\"content\":\"dawn\"}}]}\n\ndata: [DONE]\n\n",
+            ],
+            // Dropped after one token
+            vec!["data: {\"choices\":[{\"delta\":{\"content\":\"Half\"}}]}\n\n"],
+        ]).await;
+
+        let (hermes, switcher) = streaming_hermes(url, None).await;
+        hermes.create_conversation("garden".to_string(), None).await.unwrap();
+
+        let tokens = std::sync::Mutex::new(Vec::new());
+        hermes.chat_stream("garden", "When should I water?", |token| {
+            tokens.lock().unwrap().push(token);
+            Ok(())
+        }).await.unwrap();
+        assert_eq!(*tokens.lock().unwrap(), ["Water ", "at ", "dawn"]);
+        let history = hermes.get_conversation("garden").await.unwrap();
+        assert_eq!(history.last().unwrap().role, "assistant");
+        assert_eq!(history.last().unwrap().content, "Water at dawn");
+
+        tokens.lock().unwrap().clear();
+        let dropped = hermes.chat_stream("garden", "And in summer?", |token| {
+            tokens.lock().unwrap().push(token);
+            Ok(())
+        }).await;
+        assert!(dropped.unwrap_err().to_string().contains("before [DONE]"));
+        assert_eq!(*tokens.lock().unwrap(), ["Half"]);
+        let history = hermes.get_conversation("garden").await.unwrap();
+        assert_eq!(history.last().unwrap().content, "And in summer?");
+        assert_eq!(switcher.get_health_status().await["hermes"].1, 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_saved_conversation_is_restored_and_continued() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = dir.path().join("conversations.json");
+        let url = sse_server(vec![
+            vec!["data: {\"choices\":[{\"delta\":{\"content\":\"At dawn.\"}}]}\n\ndata: [DONE]\n\n"],
+            vec!["data: {\"choices\":[{\"delta\":{\"content\":\"Twice a day.\"}}]}\n\ndata: [DONE]\n\n"],
+        ]).await;
+
+        let (before, _) = streaming_hermes(url.clone(), Some(history.clone())).await;
+        before.create_conversation("garden".to_string(), Some("You are a gardener.".to_string())).await.unwrap();
+        before.chat_stream("garden", "When should I water?", |_| Ok(())).await.unwrap();
+        drop(before);
+
+        // A restarted service picks up where the last one stopped
+        let (after, _) = streaming_hermes(url, Some(history.clone())).await;
+        assert_eq!(after.restore_conversations().await.unwrap(), 1);
+        let restored = after.get_conversation("garden").await.unwrap();
+        assert_eq!(restored.iter().map(|m| m.role.as_str()).collect::<Vec<_>>(), ["system", "user", "assistant"]);
+        assert_eq!(restored[2].content, "At dawn.");
+        assert!(after.conversations.read().await["garden"].total_tokens > 0);
+
+        after.chat_stream("garden", "And in a heatwave?", |_| Ok(())).await.unwrap();
+        let (reloaded, _) = streaming_hermes("http://localhost:0".to_string(), None).await;
+        reloaded.load_conversations(&history).await.unwrap();
+        let messages = reloaded.get_conversation("garden").await.unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[4].content, "Twice a day.");
+
+        // Newer history is refused without touching what's loaded
+        std::fs::write(&history, r#"{"version": 99, "conversations": {}}"#).unwrap();
+        assert!(reloaded.load_conversations(&history).await.unwrap_err().to_string().contains("version 99"));
+        assert_eq!(reloaded.get_conversation("garden").await.unwrap().len(), 5);
+    }
+}
@@ -3,15 +3,23 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
-use tokio::sync::{RwLock, Semaphore, mpsc};
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock, Semaphore, mpsc};
 use candle_core::{Device, Tensor, DType};
+use candle_core::quantized::gguf_file;
 use candle_nn::VarBuilder;
-use candle_transformers::models::llama::{Llama, LlamaConfig, Cache};
+use candle_transformers::models::llama::{Llama, LlamaConfig, Config as LlamaRuntimeConfig, Cache};
 use candle_transformers::models::mistral::{Model as MistralModel, Config as MistralConfig};
 use candle_transformers::models::phi::{Model as PhiModel, Config as PhiConfig};
-use candle_transformers::generation_utils::LogitsProcessor;
+use candle_transformers::models::quantized_llama::ModelWeights as QuantizedLlama;
 use hf_hub::api::tokio::Api;
 use tokenizers::Tokenizer;
+use crate::ai::chat_template::ChatTemplate;
+use crate::ai::generation::{DecodeBudget, Sampler, StopReason};
+use crate::ai::gguf::{is_gguf, GgufVariant};
+use crate::ai::grammar::Grammar;
+use crate::ai::quantize;
+use crate::ai::structured::OutputSchema;
+use crate::config::seed::RngSeed;
 use crate::logger::Logger;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +32,24 @@ pub struct GenerationConfig {
     pub do_sample: bool,
     pub stop_tokens: Vec<String>,
     pub seed: Option<u64>,
+    /// Stop decoding after this long and return the partial output
+    #[serde(default)]
+    pub time_budget_ms: Option<u64>,
+    /// Output is constrained to this grammar; invalid tokens are masked before sampling
+    #[serde(skip)]
+    pub grammar: Option<Arc<Grammar>>,
+}
+
+impl GenerationConfig {
+    /// The sampler both decoding paths use, so `top_k`, `top_p` and the repetition
+    /// penalty apply the same way everywhere; `do_sample: false` is greedy
+    pub fn sampler(&self) -> Sampler {
+        let temperature = if self.do_sample { self.temperature as f32 } else { 0.0 };
+        Sampler::new(RngSeed::resolve(self.seed), temperature)
+            .with_top_k(self.top_k)
+            .with_top_p(self.top_p as f32)
+            .with_repetition_penalty(self.repetition_penalty as f32)
+    }
 }
 
 impl Default for GenerationConfig {
@@ -37,6 +63,8 @@ impl Default for GenerationConfig {
             do_sample: true,
             stop_tokens: vec!["</s>".to_string(), "<|end|>".to_string()],
             seed: None,
+            time_budget_ms: None,
+            grammar: None,
         }
     }
 }
@@ -51,6 +79,46 @@ pub struct ModelConfig {
     pub cache_size: usize,
     pub use_flash_attention: bool,
     pub model_path: Option<PathBuf>,
+    /// Generations decoded at once. Each one holds its own KV cache, roughly
+    /// `2 * layers * hidden_size * sequence_length * dtype bytes`, on top of the shared weights,
+    /// so raising this trades memory for throughput. Mistral and Phi keep their cache inside
+    /// the model and still decode one request at a time; the rest wait their turn in order
+    #[serde(default = "default_max_concurrent_generations")]
+    pub max_concurrent_generations: usize,
+    /// Load this GGUF variant of the model (about 4GB instead of 14GB for a 7B model at
+    /// q4_k_m); also used whenever `model_path` is a `.gguf` file
+    #[serde(default)]
+    pub quantized: Option<GgufVariant>,
+    /// Prompt format for chat requests; chosen from `model_type` when unset
+    #[serde(default)]
+    pub chat_template: Option<ChatTemplate>,
+}
+
+fn default_max_concurrent_generations() -> usize {
+    1
+}
+
+impl ModelConfig {
+    /// A model already on disk: a GGUF file, or a downloaded folder with `config.json`,
+    /// safetensors weights and `tokenizer.json`; the family is guessed from its name
+    pub fn local(path: PathBuf) -> Self {
+        let model_name = path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Self {
+            model_type: ModelType::from_name(&model_name),
+            model_name,
+            device: "cpu".to_string(),
+            dtype: "f32".to_string(),
+            max_sequence_length: 4096,
+            cache_size: 1024,
+            use_flash_attention: false,
+            model_path: Some(path),
+            max_concurrent_generations: default_max_concurrent_generations(),
+            quantized: None,
+            chat_template: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +130,34 @@ pub enum ModelType {
     Hermes,
 }
 
+impl ModelType {
+    /// The family a checkpoint belongs to, from its file or repo name; Llama when unrecognized
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_lowercase();
+        if name.contains("hermes") {
+            ModelType::Hermes
+        } else if name.contains("codellama") || name.contains("code-llama") {
+            ModelType::CodeLlama
+        } else if name.contains("mistral") {
+            ModelType::Mistral
+        } else if name.contains("phi") {
+            ModelType::Phi
+        } else {
+            ModelType::Llama
+        }
+    }
+
+    /// The format the family's chat models were tuned on
+    pub fn chat_template(&self) -> ChatTemplate {
+        match self {
+            ModelType::Llama | ModelType::CodeLlama => ChatTemplate::Llama2,
+            ModelType::Mistral => ChatTemplate::Mistral,
+            ModelType::Phi => ChatTemplate::Phi,
+            ModelType::Hermes => ChatTemplate::ChatML,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GenerationRequest {
     pub prompt: String,
@@ -83,14 +179,6 @@ pub struct GenerationResponse {
     pub finish_reason: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum StopReason {
-    MaxTokens,
-    StopToken,
-    EndOfSequence,
-    Error(String),
-}
-
 pub struct LocalLLM {
     config: ModelConfig,
     model: Arc<RwLock<Option<LoadedModel>>>,
@@ -99,27 +187,83 @@ pub struct LocalLLM {
     semaphore: Arc<Semaphore>,
     logger: Logger,
     generation_cache: Arc<RwLock<HashMap<String, Cache>>>,
+    /// Held while the weights load, so concurrent first requests load them once
+    loading: Arc<Mutex<()>>,
+    /// Used by requests that come in through `Backend`, which only carry a prompt and a token limit
+    defaults: GenerationConfig,
 }
 
+/// Weights are shared read-only between requests; only the KV caches are per request
 enum LoadedModel {
-    Llama(Llama),
-    Mistral(MistralModel),
-    Phi(PhiModel),
+    /// Llama keeps its KV cache outside the model, so the config is kept to build one per request
+    Llama(Arc<Llama>, LlamaRuntimeConfig),
+    /// Mistral and Phi cache inside the model, so a request holds the model for its whole decode
+    Mistral(Arc<Mutex<MistralModel>>),
+    Phi(Arc<Mutex<PhiModel>>),
+    /// Llama-family GGUF weights, which also keep their cache inside the model
+    Quantized(Arc<Mutex<QuantizedLlama>>),
 }
 
-impl LoadedModel {
-    async fn forward(&mut self, input_ids: &Tensor, pos: usize) -> Result<Tensor> {
-        match self {
-            LoadedModel::Llama(model) => {
-                model.forward(input_ids, pos).context("Llama forward pass failed")
+/// The model as one request sees it: shared weights with a cache of its own, or exclusive use
+enum SessionModel {
+    Llama(Arc<Llama>, Cache),
+    Mistral(OwnedMutexGuard<MistralModel>),
+    Phi(OwnedMutexGuard<PhiModel>),
+    Quantized(OwnedMutexGuard<QuantizedLlama>),
+}
+
+/// Incremental decoding for one request: the prompt is fed once, then each step feeds
+/// only the newly sampled token at its position, so attention reuses the cached keys and values
+struct DecodeSession {
+    model: SessionModel,
+    /// Tokens already in the KV cache
+    pos: usize,
+}
+
+impl DecodeSession {
+    /// Start with an empty KV cache; waits while another request is using a Mistral or Phi model
+    async fn new(model: &LoadedModel, dtype: DType, device: &Device) -> Result<Self> {
+        let model = match model {
+            LoadedModel::Llama(model, config) => {
+                SessionModel::Llama(model.clone(), Cache::new(true, dtype, config, device)?)
             }
             LoadedModel::Mistral(model) => {
-                model.forward(input_ids, pos).context("Mistral forward pass failed")
+                let mut model = model.clone().lock_owned().await;
+                model.clear_kv_cache();
+                SessionModel::Mistral(model)
             }
             LoadedModel::Phi(model) => {
-                model.forward(input_ids, pos).context("Phi forward pass failed")
+                let mut model = model.clone().lock_owned().await;
+                model.clear_kv_cache();
+                SessionModel::Phi(model)
             }
-        }
+            // The cache is reset by a forward pass at position 0
+            LoadedModel::Quantized(model) => SessionModel::Quantized(model.clone().lock_owned().await),
+        };
+        Ok(Self { model, pos: 0 })
+    }
+
+    /// Logits for the token following `tokens`
+    async fn step(&mut self, tokens: &[u32], device: &Device) -> Result<Tensor> {
+        let input_ids = Tensor::new(&tokens[self.pos..], device)?
+            .unsqueeze(0)?;
+        // Earlier positions come from the KV cache
+        let logits = match &mut self.model {
+            SessionModel::Llama(model, cache) => {
+                model.forward(&input_ids, self.pos, cache).context("Llama forward pass failed")
+            }
+            SessionModel::Mistral(model) => {
+                model.forward(&input_ids, self.pos).context("Mistral forward pass failed")
+            }
+            SessionModel::Phi(model) => {
+                model.forward(&input_ids).context("Phi forward pass failed")
+            }
+            SessionModel::Quantized(model) => {
+                model.forward(&input_ids, self.pos).context("Quantized forward pass failed")
+            }
+        }?;
+        self.pos = tokens.len();
+        Ok(logits)
     }
 }
 
@@ -141,17 +285,51 @@ impl LocalLLM {
             _ => Device::Cpu,
         };
 
+        let permits = config.max_concurrent_generations.max(1);
         Ok(Self {
             config,
             model: Arc::new(RwLock::new(None)),
             tokenizer: Arc::new(RwLock::new(None)),
             device,
-            semaphore: Arc::new(Semaphore::new(1)), // Single concurrent generation for now
+            semaphore: Arc::new(Semaphore::new(permits)),
             logger: Logger::new("LocalLLM"),
             generation_cache: Arc::new(RwLock::new(HashMap::new())),
+            loading: Arc::new(Mutex::new(())),
+            defaults: GenerationConfig::default(),
         })
     }
 
+    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
+        self.defaults = config;
+        self
+    }
+
+    /// Settings for a request of up to `max_tokens` that has no config of its own
+    pub fn generation_defaults(&self, max_tokens: usize) -> GenerationConfig {
+        GenerationConfig { max_new_tokens: max_tokens, ..self.defaults.clone() }
+    }
+
+    /// A request for `prompt` as the user's turn in the model's chat template, generated with the defaults
+    pub fn request(&self, prompt: &str, max_tokens: usize) -> GenerationRequest {
+        GenerationRequest {
+            prompt: prompt.to_string(),
+            config: self.generation_defaults(max_tokens),
+            context: None,
+            system_prompt: None,
+            chat_format: true,
+            stream: false,
+        }
+    }
+
+    /// Load the tokenizer and weights unless they already are
+    pub async fn ensure_loaded(&self) -> Result<()> {
+        let _loading = self.loading.lock().await;
+        if self.model.read().await.is_some() {
+            return Ok(());
+        }
+        self.initialize().await
+    }
+
     pub async fn initialize(&self) -> Result<()> {
         self.logger.info(&format!("Initializing LLM: {} on {}", self.config.model_name, self.config.device));
         
@@ -175,10 +353,12 @@ impl LocalLLM {
         self.logger.debug("Loading tokenizer...");
         
         if let Some(local_path) = &self.config.model_path {
-            let tokenizer_path = local_path.join("tokenizer.json");
+            // A GGUF file sits in the model directory beside its tokenizer
+            let local_dir = if is_gguf(local_path) { local_path.parent().unwrap_or(local_path) } else { local_path };
+            let tokenizer_path = local_dir.join("tokenizer.json");
             if tokenizer_path.exists() {
                 return Tokenizer::from_file(&tokenizer_path)
-                    .context("Failed to load local tokenizer");
+                    .map_err(|e| anyhow::anyhow!("Failed to load tokenizer {}: {}", tokenizer_path.display(), e));
             }
         }
         
@@ -190,18 +370,36 @@ impl LocalLLM {
             .context("Failed to download tokenizer")?;
         
         Tokenizer::from_file(&tokenizer_path)
-            .context("Failed to load tokenizer")
+            .map_err(|e| anyhow::anyhow!("Failed to load tokenizer {}: {}", tokenizer_path.display(), e))
     }
 
     async fn load_model(&self) -> Result<LoadedModel> {
         self.logger.debug("Loading model weights...");
         
-        let api = Api::new()?;
-        let repo = api.model(self.config.model_name.clone());
+        if let Some(path) = self.config.model_path.as_ref().filter(|p| is_gguf(p)) {
+            return self.load_quantized_model(path);
+        }
+        if let Some(variant) = self.config.quantized {
+            let api = Api::new()?;
+            let repo = api.model(self.config.model_name.clone());
+            let info = repo.info().await.context("Failed to list model files")?;
+            let files: Vec<&str> = info.siblings.iter().map(|s| s.rfilename.as_str()).collect();
+            let file = variant.pick_file(&files)
+                .with_context(|| format!("{} has no {} GGUF file", self.config.model_name, variant.tag()))?;
+            let path = repo.get(file).await.context("Failed to download GGUF weights")?;
+            return self.load_quantized_model(&path);
+        }
         
-        // Download config and weights
-        let config_path = repo.get("config.json").await?;
-        let weights_paths = self.get_model_weights(&repo).await?;
+        // A downloaded folder is loaded as is; otherwise config and weights come from the hub
+        let local_dir = self.config.model_path.as_ref().filter(|dir| dir.join("config.json").is_file());
+        let (config_path, weights_paths) = match local_dir {
+            Some(dir) => (dir.join("config.json"), local_weights(dir)?),
+            None => {
+                let api = Api::new()?;
+                let repo = api.model(self.config.model_name.clone());
+                (repo.get("config.json").await?, self.get_model_weights(&repo).await?)
+            }
+        };
         
         // Load config
         let config_content = std::fs::read_to_string(&config_path)?;
@@ -210,23 +408,23 @@ impl LocalLLM {
             ModelType::Llama | ModelType::CodeLlama => {
                 let llama_config: LlamaConfig = serde_json::from_str(&config_content)?;
                 let model = self.load_llama_model(&weights_paths, &llama_config).await?;
-                Ok(LoadedModel::Llama(model))
+                Ok(LoadedModel::Llama(Arc::new(model), llama_config.into_config(false)))
             }
             ModelType::Mistral => {
                 let mistral_config: MistralConfig = serde_json::from_str(&config_content)?;
                 let model = self.load_mistral_model(&weights_paths, &mistral_config).await?;
-                Ok(LoadedModel::Mistral(model))
+                Ok(LoadedModel::Mistral(Arc::new(Mutex::new(model))))
             }
             ModelType::Phi => {
                 let phi_config: PhiConfig = serde_json::from_str(&config_content)?;
                 let model = self.load_phi_model(&weights_paths, &phi_config).await?;
-                Ok(LoadedModel::Phi(model))
+                Ok(LoadedModel::Phi(Arc::new(Mutex::new(model))))
             }
             ModelType::Hermes => {
                 // Hermes is typically based on Llama/Mistral
                 let llama_config: LlamaConfig = serde_json::from_str(&config_content)?;
                 let model = self.load_llama_model(&weights_paths, &llama_config).await?;
-                Ok(LoadedModel::Llama(model))
+                Ok(LoadedModel::Llama(Arc::new(model), llama_config.into_config(false)))
             }
         }
     }
@@ -269,69 +467,62 @@ impl LocalLLM {
         anyhow::bail!("No supported model weights found")
     }
 
-    async fn load_llama_model(&self, weights_paths: &[PathBuf], config: &LlamaConfig) -> Result<Llama> {
-        use candle_core::safetensors::load;
-        
-        let dtype = match self.config.dtype.as_str() {
+    /// GGUF weights stay quantized in memory and are dequantized block by block in matmuls
+    fn load_quantized_model(&self, path: &Path) -> Result<LoadedModel> {
+        let mut file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let content = gguf_file::Content::read(&mut file)
+            .with_context(|| format!("{} is not a GGUF file", path.display()))?;
+        let model = QuantizedLlama::from_gguf(content, &mut file, &self.device)
+            .context("Failed to load quantized model")?;
+        self.logger.info(&format!("Loaded quantized weights from {}", path.display()));
+        Ok(LoadedModel::Quantized(Arc::new(Mutex::new(model))))
+    }
+
+    fn model_dtype(&self) -> DType {
+        match self.config.dtype.as_str() {
             "f16" => DType::F16,
             "bf16" => DType::BF16,
             _ => DType::F32,
-        };
-        
+        }
+    }
+
+    /// Every tensor in the weight files, dequantizing int8 files written by `model download`
+    fn load_weights(&self, weights_paths: &[PathBuf]) -> Result<HashMap<String, Tensor>> {
         let mut tensors = HashMap::new();
-        
         for weights_path in weights_paths {
-            let file_tensors = load(weights_path, &self.device)?;
-            tensors.extend(file_tensors);
+            if quantize::is_quantized(weights_path) {
+                for (name, tensor) in quantize::load_dequantized(weights_path)? {
+                    tensors.insert(name, Tensor::from_vec(tensor.values, tensor.shape, &self.device)?);
+                }
+            } else {
+                tensors.extend(candle_core::safetensors::load(weights_path, &self.device)?);
+            }
         }
+        Ok(tensors)
+    }
+
+    async fn load_llama_model(&self, weights_paths: &[PathBuf], config: &LlamaConfig) -> Result<Llama> {
+        let dtype = self.model_dtype();
+        let var_builder = VarBuilder::from_tensors(self.load_weights(weights_paths)?, dtype, &self.device);
         
-        let var_builder = VarBuilder::from_tensors(tensors, dtype, &self.device);
-        
-        Llama::load(&var_builder, config)
+        Llama::load(var_builder, &config.clone().into_config(false))
             .context("Failed to load Llama model")
     }
 
     async fn load_mistral_model(&self, weights_paths: &[PathBuf], config: &MistralConfig) -> Result<MistralModel> {
-        use candle_core::safetensors::load;
+        let dtype = self.model_dtype();
+        let var_builder = VarBuilder::from_tensors(self.load_weights(weights_paths)?, dtype, &self.device);
         
-        let dtype = match self.config.dtype.as_str() {
-            "f16" => DType::F16,
-            "bf16" => DType::BF16,
-            _ => DType::F32,
-        };
-        
-        let mut tensors = HashMap::new();
-        
-        for weights_path in weights_paths {
-            let file_tensors = load(weights_path, &self.device)?;
-            tensors.extend(file_tensors);
-        }
-        
-        let var_builder = VarBuilder::from_tensors(tensors, dtype, &self.device);
-        
-        MistralModel::load(&var_builder, config)
+        MistralModel::new(config, var_builder)
             .context("Failed to load Mistral model")
     }
 
     async fn load_phi_model(&self, weights_paths: &[PathBuf], config: &PhiConfig) -> Result<PhiModel> {
-        use candle_core::safetensors::load;
+        let dtype = self.model_dtype();
+        let var_builder = VarBuilder::from_tensors(self.load_weights(weights_paths)?, dtype, &self.device);
         
-        let dtype = match self.config.dtype.as_str() {
-            "f16" => DType::F16,
-            "bf16" => DType::BF16,
-            _ => DType::F32,
-        };
-        
-        let mut tensors = HashMap::new();
-        
-        for weights_path in weights_paths {
-            let file_tensors = load(weights_path, &self.device)?;
-            tensors.extend(file_tensors);
-        }
-        
-        let var_builder = VarBuilder::from_tensors(tensors, dtype, &self.device);
-        
-        PhiModel::load(&var_builder, config)
+        PhiModel::new(config, var_builder)
             .context("Failed to load Phi model")
     }
 
@@ -348,7 +539,7 @@ impl LocalLLM {
         let tokens = self.tokenize(&formatted_prompt).await?;
         
         // Generate
-        let (generated_tokens, stop_reason) = self.generate_tokens(&tokens, &request.config).await?;
+        let (generated_tokens, stop_reason) = self.generate_tokens(&tokens, &self.generation_config(&request)).await?;
         
         // Decode
         let generated_text = self.decode_tokens(&generated_tokens).await?;
@@ -361,9 +552,12 @@ impl LocalLLM {
             tokens_generated: generated_tokens.len(),
             generation_time_ms: generation_time.as_millis() as u64,
             tokens_per_second,
+            finish_reason: match stop_reason {
+                StopReason::TimeBudget => "time_budget".to_string(),
+                _ => "completed".to_string(),
+            },
             stop_reason,
             model_name: self.config.model_name.clone(),
-            finish_reason: "completed".to_string(),
         })
     }
 
@@ -380,17 +574,18 @@ impl LocalLLM {
         Ok(StreamingResponse { receiver })
     }
 
+    /// Shares the model, tokenizer and generation limit with `self`
     async fn clone_for_streaming(&self) -> Result<Self> {
-        // Create a lightweight clone for streaming
-        // In practice, you might want to share the model between instances
         Ok(Self {
             config: self.config.clone(),
             model: self.model.clone(),
             tokenizer: self.tokenizer.clone(),
             device: self.device.clone(),
-            semaphore: Arc::new(Semaphore::new(1)),
+            semaphore: self.semaphore.clone(),
             logger: Logger::new("LocalLLM-Stream"),
             generation_cache: self.generation_cache.clone(),
+            loading: self.loading.clone(),
+            defaults: self.defaults.clone(),
         })
     }
 
@@ -402,42 +597,37 @@ impl LocalLLM {
         let _permit = self.semaphore.acquire().await?;
         
         let formatted_prompt = self.format_prompt(&request)?;
+        let config = self.generation_config(&request);
         let mut tokens = self.tokenize(&formatted_prompt).await?;
         
         let tokenizer_guard = self.tokenizer.read().await;
         let tokenizer = tokenizer_guard.as_ref().context("Tokenizer not loaded")?;
         
-        let mut model_guard = self.model.write().await;
-        let model = model_guard.as_mut().context("Model not loaded")?;
+        let mut session = self.decode_session().await?;
         
-        let mut logits_processor = LogitsProcessor::new(
-            request.config.seed.unwrap_or(299792458),
-            Some(request.config.temperature),
-            Some(request.config.top_p),
-        );
+        let mut sampler = config.sampler();
         
-        for index in 0..request.config.max_new_tokens {
-            let input_tensor = Tensor::new(&tokens[..], &self.device)?
-                .unsqueeze(0)?;
-            
-            let logits = model.forward(&input_tensor, tokens.len() - 1).await?;
-            let logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?;
+        let budget = DecodeBudget::from_millis(config.max_new_tokens, config.time_budget_ms);
+        let mut generated = 0;
+        
+        while budget.exhausted(generated).is_none() {
+            let logits = session.step(&tokens, &self.device).await?;
+            let logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?.to_vec1::<f32>()?;
             
-            let next_token = logits_processor.sample(&logits)?;
+            let next_token = sampler.sample_next_token(&logits, &tokens);
             tokens.push(next_token);
+            generated += 1;
             
             // Decode the new token
             if let Ok(new_text) = tokenizer.decode(&[next_token], true) {
-                if !new_text.is_empty() {
-                    if sender.send(Ok(new_text.clone())).await.is_err() {
-                        break; // Client disconnected
-                    }
+                if !new_text.is_empty() && sender.send(Ok(new_text)).await.is_err() {
+                    break; // Client disconnected
                 }
             }
             
             // Check for stop tokens
             if let Ok(current_text) = tokenizer.decode(&tokens, true) {
-                if request.config.stop_tokens.iter().any(|stop| current_text.ends_with(stop)) {
+                if config.stop_tokens.iter().any(|stop| current_text.ends_with(stop)) {
                     break;
                 }
             }
@@ -458,34 +648,49 @@ impl LocalLLM {
         let tokenizer_guard = self.tokenizer.read().await;
         let tokenizer = tokenizer_guard.as_ref().context("Tokenizer not loaded")?;
         
-        let mut model_guard = self.model.write().await;
-        let model = model_guard.as_mut().context("Model not loaded")?;
+        let mut session = self.decode_session().await?;
         
-        let mut logits_processor = LogitsProcessor::new(
-            config.seed.unwrap_or(299792458),
-            Some(config.temperature),
-            Some(config.top_p),
-        );
+        let mut sampler = config.sampler();
         
-        for _ in 0..config.max_new_tokens {
-            let input_tensor = Tensor::new(&tokens[..], &self.device)?
-                .unsqueeze(0)?;
+        // Limits are checked between decode steps so a partial answer is returned on timeout
+        let budget = DecodeBudget::from_millis(config.max_new_tokens, config.time_budget_ms);
+        
+        // A grammar checks every candidate token's text before each step
+        let eos = tokenizer.token_to_id("</s>").unwrap_or(u32::MAX);
+        let grammar_vocab: Option<Vec<String>> = config.grammar.as_ref().map(|_| {
+            (0..tokenizer.get_vocab_size(true) as u32)
+                .map(|id| tokenizer.decode(&[id], false).unwrap_or_default())
+                .collect()
+        });
+        let mut grammar_state = config.grammar.as_ref().map(|grammar| grammar.start());
+        
+        loop {
+            if let Some(stop_reason) = budget.exhausted(generated_tokens.len()) {
+                if stop_reason == StopReason::TimeBudget {
+                    self.logger.info(&format!(
+                        "Time budget reached after {} tokens in {:?}",
+                        generated_tokens.len(), budget.elapsed()
+                    ));
+                }
+                return Ok((generated_tokens, stop_reason));
+            }
             
-            let logits = model.forward(&input_tensor, tokens.len() - 1).await?;
-            let logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?;
+            let logits = session.step(&tokens, &self.device).await?;
+            let mut logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?.to_vec1::<f32>()?;
             
-            // Apply repetition penalty
-            let logits = if config.repetition_penalty != 1.0 {
-                self.apply_repetition_penalty(&logits, &tokens, config.repetition_penalty)?
-            } else {
-                logits
-            };
+            // Mask tokens the grammar rules out so only matching output can be sampled
+            if let (Some(state), Some(vocab)) = (&grammar_state, &grammar_vocab) {
+                state.mask_logits(vocab, eos as usize, &mut logits);
+            }
             
-            let next_token = if config.do_sample {
-                logits_processor.sample(&logits)?
-            } else {
-                logits.argmax(0)?.to_scalar::<u32>()?
-            };
+            let next_token = sampler.sample_next_token(&logits, &tokens);
+            
+            if let (Some(state), Some(vocab)) = (grammar_state.as_mut(), &grammar_vocab) {
+                if next_token != eos {
+                    *state = state.accept(&vocab[next_token as usize])
+                        .context("Sampled a token the grammar rules out")?;
+                }
+            }
             
             tokens.push(next_token);
             generated_tokens.push(next_token);
@@ -498,35 +703,66 @@ impl LocalLLM {
             }
             
             // Check for EOS token
-            if next_token == tokenizer.token_to_id("</s>").unwrap_or(u32::MAX) {
+            if next_token == eos {
                 return Ok((generated_tokens, StopReason::EndOfSequence));
             }
         }
-        
-        Ok((generated_tokens, StopReason::MaxTokens))
     }
 
-    fn apply_repetition_penalty(&self, logits: &Tensor, tokens: &[u32], penalty: f64) -> Result<Tensor> {
-        let mut logits_vec = logits.to_vec1::<f32>()?;
+    /// Generate text `grammar` accepts: before each step the model's logits for tokens it rules out are masked
+    pub async fn generate_with_grammar(&self, mut request: GenerationRequest, grammar: Grammar) -> Result<GenerationResponse> {
+        request.config.grammar = Some(Arc::new(grammar));
+        self.generate(request).await
+    }
+
+    /// Generate JSON matching `schema`: before each step, logits of tokens that would
+    /// leave the schema are masked, so only conforming text can be sampled
+    pub async fn generate_json(&self, request: GenerationRequest, schema: &OutputSchema) -> Result<String> {
+        let _permit = self.semaphore.acquire().await?;
+        let formatted_prompt = self.format_prompt(&request)?;
+        let mut tokens = self.tokenize(&formatted_prompt).await?;
         
-        // Count token frequencies in the context
-        let mut token_counts = HashMap::new();
-        for &token in tokens {
-            *token_counts.entry(token).or_insert(0) += 1;
-        }
+        let tokenizer_guard = self.tokenizer.read().await;
+        let tokenizer = tokenizer_guard.as_ref().context("Tokenizer not loaded")?;
         
-        // Apply penalty
-        for (token, count) in token_counts {
-            if let Some(logit) = logits_vec.get_mut(token as usize) {
-                if *logit > 0.0 {
-                    *logit /= penalty.powf(count as f64) as f32;
-                } else {
-                    *logit *= penalty.powf(count as f64) as f32;
-                }
+        let mut session = self.decode_session().await?;
+        
+        // Decoded text of every token id, so candidates can be checked against the schema
+        let vocab: Vec<String> = (0..tokenizer.get_vocab_size(true) as u32)
+            .map(|id| tokenizer.decode(&[id], false).unwrap_or_default())
+            .collect();
+        let eos = tokenizer.token_to_id("</s>").context("Tokenizer has no end-of-sequence token")? as usize;
+        
+        let config = &request.config;
+        let mut sampler = config.sampler();
+        
+        let mut text = String::new();
+        for _ in 0..config.max_new_tokens {
+            let logits = session.step(&tokens, &self.device).await?;
+            let mut logits = logits.squeeze(0)?.squeeze(0)?.to_dtype(DType::F32)?.to_vec1::<f32>()?;
+            schema.mask_logits(&text, &vocab, eos, &mut logits);
+            if logits.iter().all(|l| *l == f32::NEG_INFINITY) {
+                return Err(anyhow::anyhow!("No token can continue {:?} within the schema", text));
+            }
+            
+            let next_token = sampler.sample_next_token(&logits, &tokens);
+            
+            if next_token as usize == eos {
+                break;
             }
+            tokens.push(next_token);
+            text.push_str(&vocab[next_token as usize]);
         }
         
-        Tensor::new(&logits_vec[..], &self.device)
+        Ok(text)
+    }
+
+    /// A fresh decode session; the model lock is released once it starts, so unloading
+    /// and other requests aren't held up by a long generation
+    async fn decode_session(&self) -> Result<DecodeSession> {
+        let model_guard = self.model.read().await;
+        let model = model_guard.as_ref().context("Model not loaded")?;
+        DecodeSession::new(model, self.model_dtype(), &self.device).await
     }
 
     async fn tokenize(&self, text: &str) -> Result<Vec<u32>> {
@@ -534,7 +770,7 @@ impl LocalLLM {
         let tokenizer = tokenizer_guard.as_ref().context("Tokenizer not loaded")?;
         
         let encoding = tokenizer.encode(text, false)
-            .context("Failed to tokenize text")?;
+            .map_err(|e| anyhow::anyhow!("Failed to tokenize text: {}", e))?;
         
         Ok(encoding.get_ids().to_vec())
     }
@@ -544,7 +780,7 @@ impl LocalLLM {
         let tokenizer = tokenizer_guard.as_ref().context("Tokenizer not loaded")?;
         
         tokenizer.decode(tokens, true)
-            .context("Failed to decode tokens")
+            .map_err(|e| anyhow::anyhow!("Failed to decode tokens: {}", e))
     }
 
     fn format_prompt(&self, request: &GenerationRequest) -> Result<String> {
@@ -555,23 +791,26 @@ impl LocalLLM {
         }
     }
 
+    fn chat_template(&self) -> ChatTemplate {
+        self.config.chat_template.unwrap_or_else(|| self.config.model_type.chat_template())
+    }
+
     fn format_chat_prompt(&self, request: &GenerationRequest) -> Result<String> {
-        let mut formatted = String::new();
-        
-        // Add system prompt if provided
-        if let Some(system) = &request.system_prompt {
-            formatted.push_str(&format!("<|system|>\n{}\n", system));
-        }
-        
-        // Add context if provided
-        if let Some(context) = &request.context {
-            formatted.push_str(&format!("<|context|>\n{}\n", context));
+        Ok(self.chat_template().render(
+            request.system_prompt.as_deref(),
+            request.context.as_deref(),
+            &request.prompt,
+        ))
+    }
+
+    /// The request's config, also stopping at the end of the assistant's turn for chat prompts
+    fn generation_config(&self, request: &GenerationRequest) -> GenerationConfig {
+        let mut config = request.config.clone();
+        let stop = self.chat_template().stop_token().to_string();
+        if request.chat_format && !config.stop_tokens.contains(&stop) {
+            config.stop_tokens.push(stop);
         }
-        
-        // Add user prompt
-        formatted.push_str(&format!("<|user|>\n{}\n<|assistant|>\n", request.prompt));
-        
-        Ok(formatted)
+        config
     }
 
     pub async fn get_model_info(&self) -> Result<ModelInfo> {
@@ -599,6 +838,15 @@ impl LocalLLM {
             ModelType::Hermes => 7_000_000_000,
         };
         
+        if let Some(variant) = self.config.quantized {
+            let bits_per_param = match variant {
+                GgufVariant::Q4_0 => 4.5,
+                GgufVariant::Q4KM => 4.8,
+                GgufVariant::Q8_0 => 8.5,
+            };
+            return Ok((base_memory as f64 * bits_per_param / 8.0) as u64);
+        }
+        
         let bytes_per_param = match self.config.dtype.as_str() {
             "f16" | "bf16" => 2,
             "f32" => 4,
@@ -669,6 +917,9 @@ impl LocalLLM {
             cache_size: 1024,
             use_flash_attention: true,
             model_path: None,
+            max_concurrent_generations: 1,
+            quantized: None,
+            chat_template: None,
         };
         
         Self::new(config)
@@ -684,6 +935,9 @@ impl LocalLLM {
             cache_size: 1024,
             use_flash_attention: true,
             model_path: None,
+            max_concurrent_generations: 1,
+            quantized: None,
+            chat_template: None,
         };
         
         Self::new(config)
@@ -699,6 +953,9 @@ impl LocalLLM {
             cache_size: 1024,
             use_flash_attention: true,
             model_path: None,
+            max_concurrent_generations: 1,
+            quantized: None,
+            chat_template: None,
         };
         
         Self::new(config)
@@ -714,12 +971,31 @@ impl LocalLLM {
             cache_size: 512,
             use_flash_attention: false,
             model_path: None,
+            max_concurrent_generations: 1,
+            quantized: None,
+            chat_template: None,
         };
         
         Self::new(config)
     }
 }
 
+/// The safetensors shards of a downloaded model folder, in shard order; a shard's int8 copy
+/// is loaded in its place when the original was kept
+fn local_weights(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut weights: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "safetensors"))
+        .filter(|path| quantize::is_quantized(path) || !quantize::quantized_path(path).exists())
+        .collect();
+    if weights.is_empty() {
+        anyhow::bail!("No safetensors weights in {}", dir.display());
+    }
+    weights.sort();
+    Ok(weights)
+}
+
 trait DeviceString {
     fn to_string(&self) -> String;
 }
@@ -732,4 +1008,305 @@ impl DeviceString for Device {
             Device::Metal(_) => "metal".to_string(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::quantized::{GgmlDType, QTensor};
+
+    /// A one-layer Llama small enough to build in the test, quantized to q8_0
+    fn write_tiny_gguf(path: &Path) -> Result<()> {
+        const EMBED: usize = 32;
+        const VOCAB: usize = 32;
+        let device = Device::Cpu;
+        let matrix = |rows: usize, cols: usize| -> Result<QTensor> {
+            let t = Tensor::randn(0f32, 0.1, (rows, cols), &device)?;
+            Ok(QTensor::quantize(&t, GgmlDType::Q8_0)?)
+        };
+        let norm = || -> Result<QTensor> { Ok(QTensor::quantize(&Tensor::ones(EMBED, DType::F32, &device)?, GgmlDType::F32)?) };
+
+        let tensors = vec![
+            ("token_embd.weight", matrix(VOCAB, EMBED)?),
+            ("output_norm.weight", norm()?),
+            ("output.weight", matrix(VOCAB, EMBED)?),
+            ("blk.0.attn_norm.weight", norm()?),
+            ("blk.0.attn_q.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.attn_k.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.attn_v.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.attn_output.weight", matrix(EMBED, EMBED)?),
+            ("blk.0.ffn_norm.weight", norm()?),
+            ("blk.0.ffn_gate.weight", matrix(64, EMBED)?),
+            ("blk.0.ffn_up.weight", matrix(64, EMBED)?),
+            ("blk.0.ffn_down.weight", matrix(EMBED, 64)?),
+        ];
+        let metadata = vec![
+            ("general.architecture", gguf_file::Value::String("llama".to_string())),
+            ("llama.attention.head_count", gguf_file::Value::U32(2)),
+            ("llama.attention.head_count_kv", gguf_file::Value::U32(2)),
+            ("llama.block_count", gguf_file::Value::U32(1)),
+            ("llama.embedding_length", gguf_file::Value::U32(EMBED as u32)),
+            ("llama.rope.dimension_count", gguf_file::Value::U32(16)),
+            ("llama.attention.layer_norm_rms_epsilon", gguf_file::Value::F32(1e-5)),
+        ];
+
+        let mut file = std::fs::File::create(path)?;
+        gguf_file::write(
+            &mut file,
+            &metadata.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>(),
+            &tensors.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>(),
+        )?;
+        Ok(())
+    }
+
+    /// The tiny model with a word-level tokenizer over `w0`..`w31`, `</s>` being id 1 when `eos` is set
+    fn write_tiny_model(dir: &Path, eos: bool) -> Result<PathBuf> {
+        let model_path = dir.join("tiny.Q8_0.gguf");
+        write_tiny_gguf(&model_path)?;
+        write_tiny_tokenizer(dir, eos)?;
+        Ok(model_path)
+    }
+
+    /// The same Llama as a downloaded folder: `config.json`, f32 safetensors and the tokenizer
+    fn write_tiny_folder(dir: &Path) -> Result<()> {
+        let device = Device::Cpu;
+        let matrix = |rows: usize, cols: usize| Tensor::randn(0f32, 0.1, (rows, cols), &device);
+        let norm = || Tensor::ones(32, DType::F32, &device);
+        let tensors: HashMap<String, Tensor> = [
+            ("model.embed_tokens.weight", matrix(32, 32)?),
+            ("model.norm.weight", norm()?),
+            ("lm_head.weight", matrix(32, 32)?),
+            ("model.layers.0.input_layernorm.weight", norm()?),
+            ("model.layers.0.post_attention_layernorm.weight", norm()?),
+            ("model.layers.0.self_attn.q_proj.weight", matrix(32, 32)?),
+            ("model.layers.0.self_attn.k_proj.weight", matrix(32, 32)?),
+            ("model.layers.0.self_attn.v_proj.weight", matrix(32, 32)?),
+            ("model.layers.0.self_attn.o_proj.weight", matrix(32, 32)?),
+            ("model.layers.0.mlp.gate_proj.weight", matrix(64, 32)?),
+            ("model.layers.0.mlp.up_proj.weight", matrix(64, 32)?),
+            ("model.layers.0.mlp.down_proj.weight", matrix(32, 64)?),
+        ].into_iter().map(|(name, tensor)| (name.to_string(), tensor)).collect();
+        candle_core::safetensors::save(&tensors, dir.join("model.safetensors"))?;
+        std::fs::write(dir.join("config.json"), serde_json::json!({
+            "hidden_size": 32, "intermediate_size": 64, "vocab_size": 32, "num_hidden_layers": 1,
+            "num_attention_heads": 2, "num_key_value_heads": 2, "rms_norm_eps": 1e-5,
+            "max_position_embeddings": 64, "tie_word_embeddings": false
+        }).to_string())?;
+        write_tiny_tokenizer(dir, true)
+    }
+
+    fn write_tiny_tokenizer(dir: &Path, eos: bool) -> Result<()> {
+        let vocab: serde_json::Map<String, serde_json::Value> = (0..32)
+            .map(|i| (if eos && i == 1 { "</s>".to_string() } else { format!("w{}", i) }, serde_json::json!(i)))
+            .collect();
+        let tokenizer = serde_json::json!({
+            "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+            "normalizer": null, "pre_tokenizer": { "type": "Whitespace" }, "post_processor": null,
+            "decoder": null, "model": { "type": "WordLevel", "vocab": vocab, "unk_token": "w0" }
+        });
+        std::fs::write(dir.join("tokenizer.json"), tokenizer.to_string())?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tiny_quantized_model_generates_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = write_tiny_model(dir.path(), true).unwrap();
+
+        let llm = LocalLLM::new(ModelConfig {
+            model_name: "tiny".to_string(),
+            model_type: ModelType::Llama,
+            device: "cpu".to_string(),
+            dtype: "f32".to_string(),
+            max_sequence_length: 64,
+            cache_size: 64,
+            use_flash_attention: false,
+            model_path: Some(model_path),
+            max_concurrent_generations: 1,
+            quantized: None,
+            chat_template: None,
+        }).unwrap();
+        llm.initialize().await.unwrap();
+
+        let response = llm.generate(GenerationRequest {
+            prompt: "w2 w3 w4".to_string(),
+            config: GenerationConfig { max_new_tokens: 4, do_sample: false, stop_tokens: Vec::new(), ..Default::default() },
+            context: None,
+            system_prompt: None,
+            chat_format: false,
+            stream: false,
+        }).await.unwrap();
+        assert!(response.tokens_generated > 0);
+    }
+
+    #[tokio::test]
+    async fn test_int8_weights_load_in_place_of_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_dir = dir.path().join("tiny");
+        std::fs::create_dir(&model_dir).unwrap();
+        write_tiny_folder(&model_dir).unwrap();
+        let config = quantize::QuantizationConfig { enabled: true, delete_original: false };
+        let quantized = quantize::quantize_file(&model_dir.join("model.safetensors"), &config).unwrap();
+        assert_eq!(local_weights(&model_dir).unwrap(), [quantized]);
+
+        let llm = LocalLLM::new(ModelConfig::local(model_dir)).unwrap();
+        llm.initialize().await.unwrap();
+        let response = llm.generate(GenerationRequest {
+            prompt: "w2 w3 w4".to_string(),
+            config: GenerationConfig { max_new_tokens: 4, do_sample: false, stop_tokens: Vec::new(), ..Default::default() },
+            context: None,
+            system_prompt: None,
+            chat_format: false,
+            stream: false,
+        }).await.unwrap();
+        assert!(response.tokens_generated > 0);
+    }
+
+    #[tokio::test]
+    async fn test_backend_loads_a_gguf_file_on_first_request() {
+        use crate::ai::backend::{Backend, BackendKind};
+
+        let dir = tempfile::tempdir().unwrap();
+        let llm = LocalLLM::new(ModelConfig::local(write_tiny_model(dir.path(), true).unwrap())).unwrap()
+            .with_generation_config(GenerationConfig { do_sample: false, stop_tokens: Vec::new(), ..Default::default() });
+        assert!(!llm.get_model_info().await.unwrap().is_loaded);
+
+        let backend: &dyn Backend = &llm;
+        assert_eq!(backend.kind(), BackendKind::Local);
+        let answer = backend.generate("w2 w3 w4", 4).await.unwrap();
+        assert!(answer.split_whitespace().all(|word| word.starts_with('w') || word == "</s>"), "{}", answer);
+        assert!(llm.get_model_info().await.unwrap().is_loaded);
+    }
+
+    #[test]
+    fn test_backend_requests_use_the_family_chat_template() {
+        let llm = LocalLLM::new(ModelConfig::local(PathBuf::from("models/Hermes-2-Pro-Mistral-7B.Q4_K_M.gguf"))).unwrap();
+        let request = llm.request("When do I water the tomatoes?", 64);
+        assert_eq!(
+            llm.format_prompt(&request).unwrap(),
+            "<|im_start|>user\nWhen do I water the tomatoes?<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert!(llm.generation_config(&request).stop_tokens.contains(&"<|im_end|>".to_string()));
+
+        let phi = LocalLLM::new(ModelConfig {
+            chat_template: Some(ChatTemplate::Phi),
+            ..ModelConfig::local(PathBuf::from("models/tiny.gguf"))
+        }).unwrap();
+        assert!(phi.format_prompt(&phi.request("hi", 8)).unwrap().starts_with("<s><|user|>"));
+    }
+
+    #[tokio::test]
+    async fn test_time_budget_stops_the_decode_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        // Without an end-of-sequence token only a limit can stop decoding
+        let llm = LocalLLM::new(ModelConfig::local(write_tiny_model(dir.path(), false).unwrap())).unwrap();
+        llm.initialize().await.unwrap();
+        let request = |time_budget_ms| GenerationRequest {
+            prompt: "w2 w3 w4".to_string(),
+            config: GenerationConfig { max_new_tokens: 32, stop_tokens: Vec::new(), time_budget_ms, ..Default::default() },
+            context: None,
+            system_prompt: None,
+            chat_format: false,
+            stream: false,
+        };
+
+        let response = llm.generate(request(Some(0))).await.unwrap();
+        assert_eq!(response.stop_reason, StopReason::TimeBudget);
+        assert_eq!(response.tokens_generated, 1);
+        assert!(!response.text.is_empty());
+
+        let response = llm.generate(request(None)).await.unwrap();
+        assert_eq!(response.stop_reason, StopReason::MaxTokens);
+        assert_eq!(response.tokens_generated, 32);
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_samples_the_same_answer() {
+        let dir = tempfile::tempdir().unwrap();
+        let llm = LocalLLM::new(ModelConfig::local(write_tiny_model(dir.path(), false).unwrap())).unwrap();
+        llm.initialize().await.unwrap();
+        let request = |seed| GenerationRequest {
+            prompt: "w2 w3 w4".to_string(),
+            config: GenerationConfig { max_new_tokens: 24, temperature: 1.5, seed: Some(seed), stop_tokens: Vec::new(), ..Default::default() },
+            context: None,
+            system_prompt: None,
+            chat_format: false,
+            stream: false,
+        };
+
+        assert_eq!(llm.generate(request(7)).await.unwrap().text, llm.generate(request(7)).await.unwrap().text);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_generations_both_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_path = write_tiny_model(dir.path(), false).unwrap();
+        for limit in [1, 2] {
+            let llm = LocalLLM::new(ModelConfig { max_concurrent_generations: limit, ..ModelConfig::local(model_path.clone()) }).unwrap();
+            llm.initialize().await.unwrap();
+            let request = |prompt: &str| GenerationRequest {
+                prompt: prompt.to_string(),
+                config: GenerationConfig { max_new_tokens: 8, stop_tokens: Vec::new(), ..Default::default() },
+                context: None,
+                system_prompt: None,
+                chat_format: false,
+                stream: false,
+            };
+
+            let (first, second) = tokio::time::timeout(
+                std::time::Duration::from_secs(10),
+                async { tokio::join!(llm.generate(request("w2 w3")), llm.generate(request("w4 w5 w6"))) },
+            ).await.unwrap();
+            assert_eq!(first.unwrap().tokens_generated, 8);
+            assert_eq!(second.unwrap().tokens_generated, 8);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_top_k_one_decodes_the_same_when_streaming() {
+        let dir = tempfile::tempdir().unwrap();
+        let llm = LocalLLM::new(ModelConfig::local(write_tiny_model(dir.path(), false).unwrap())).unwrap();
+        llm.initialize().await.unwrap();
+        let request = |seed| GenerationRequest {
+            prompt: "w2 w3 w4".to_string(),
+            config: GenerationConfig { max_new_tokens: 12, top_k: Some(1), seed: Some(seed), stop_tokens: Vec::new(), ..Default::default() },
+            context: None,
+            system_prompt: None,
+            chat_format: false,
+            stream: false,
+        };
+
+        let text = llm.generate(request(1)).await.unwrap().text;
+        assert_eq!(llm.generate(request(2)).await.unwrap().text, text);
+
+        let mut stream = llm.generate_stream(request(3)).await.unwrap();
+        let mut streamed = Vec::new();
+        while let Some(piece) = stream.next().await {
+            streamed.push(piece.unwrap());
+        }
+        assert_eq!(streamed, text.split_whitespace().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_grammar_masks_the_model_logits() {
+        let dir = tempfile::tempdir().unwrap();
+        let llm = LocalLLM::new(ModelConfig::local(write_tiny_model(dir.path(), false).unwrap())).unwrap();
+        llm.initialize().await.unwrap();
+        // Only `w1`, `w2` and `w3` can be followed by another `w`
+        let grammar = Grammar::parse(r#"root ::= ("w" [1-3])+"#).unwrap();
+
+        for seed in 0..5 {
+            let request = GenerationRequest {
+                prompt: "w2 w3 w4".to_string(),
+                config: GenerationConfig { max_new_tokens: 16, seed: Some(seed), stop_tokens: Vec::new(), ..Default::default() },
+                context: None,
+                system_prompt: None,
+                chat_format: false,
+                stream: false,
+            };
+            let response = llm.generate_with_grammar(request, grammar.clone()).await.unwrap();
+            assert_eq!(response.tokens_generated, 16);
+            assert!(response.text.split_whitespace().all(|word| ["w1", "w2", "w3"].contains(&word)), "{}", response.text);
+        }
+    }
+}
@@ -1,21 +1,444 @@
+pub mod answer_cache;
 pub mod api_client;
+pub mod backend;
+pub mod chat_template;
 pub mod context;
+pub mod generation;
+pub mod gguf;
+pub mod grammar;
+pub mod grounding;
 pub mod hermes_integration;
+#[cfg(test)]
 pub mod local_llm;
+#[cfg(feature = "embeddings")]
+pub mod local_llm_full;
+pub mod model_registry;
 pub mod model_switcher;
+pub mod quantize;
+pub mod query_queue;
+pub mod segment;
+pub mod structured;
+pub mod summarizer;
+pub mod tokens;
+pub mod trace;
 
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 use crate::Result;
+use answer_cache::{AnswerCache, AnswerCacheConfig};
+use backend::Backend;
+use grounding::{GroundingConfig, GroundingVerifier};
+use query_queue::{QueryPriority, QueryQueue, QueryQueueConfig};
+use context::{ContextBuilder, ContextQuery, ContextWindow, RetrievalConfig, RetrievalResult, Retriever};
+use structured::{OutputSchema, StructuredOutputConfig};
+use summarizer::{Summarizer, SummarizerConfig};
+use tokens::TokenCounter;
+use trace::{QueryStage, QueryTrace};
+use crate::vault::focus::FocusSession;
+use crate::webhooks::{WebhookEvent, WebhookNotifier};
+
+/// Whether a query is answered by the LLM or with the retrieved passages alone
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMode {
+    #[default]
+    Generate,
+    /// Return ranked passages with sources and skip generation entirely
+    ContextOnly,
+}
 
 pub struct AI {
-    // Simplified without complex context
+    /// Templates and token budget for the prompt context; also the passages unless a retriever is set
+    context: Arc<ContextBuilder>,
+    retriever: Option<Arc<dyn Retriever>>,
+    backend: Option<Arc<dyn Backend>>,
+    query_mode: QueryMode,
+    structured_output: StructuredOutputConfig,
+    answer_cache: AnswerCache,
+    retrieval: RetrievalConfig,
+    webhooks: WebhookNotifier,
+    focus: FocusSession,
+    grounding: GroundingConfig,
+    queue: QueryQueue,
+    summarizer: SummarizerConfig,
+    max_tokens: usize,
 }
 
 impl AI {
     pub fn new() -> Result<Self> {
-        Ok(Self {})
+        Ok(Self {
+            context: Arc::new(ContextBuilder::new()),
+            retriever: None,
+            backend: None,
+            query_mode: QueryMode::default(),
+            structured_output: StructuredOutputConfig::default(),
+            answer_cache: AnswerCache::new(AnswerCacheConfig::default()),
+            retrieval: RetrievalConfig::default(),
+            webhooks: WebhookNotifier::disabled(),
+            focus: FocusSession::in_memory(),
+            grounding: GroundingConfig::default(),
+            queue: QueryQueue::new(QueryQueueConfig::default()),
+            summarizer: SummarizerConfig::default(),
+            max_tokens: 512,
+        })
+    }
+    
+    pub fn with_context(mut self, context: Arc<ContextBuilder>) -> Self {
+        self.context = context;
+        self
+    }
+    
+    /// Retrieve passages from `retriever`, such as the vault's search index, instead of the context builder
+    pub fn with_retriever(mut self, retriever: Arc<dyn Retriever>) -> Self {
+        self.retriever = Some(retriever);
+        self
+    }
+    
+    pub fn with_backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+    
+    /// Whether answers can be generated, rather than only passages returned
+    pub fn can_generate(&self) -> bool {
+        self.backend.is_some()
+    }
+    
+    pub fn with_query_mode(mut self, mode: QueryMode) -> Self {
+        self.query_mode = mode;
+        self
+    }
+    
+    pub fn with_structured_output(mut self, config: StructuredOutputConfig) -> Self {
+        self.structured_output = config;
+        self
+    }
+    
+    pub fn with_retrieval(mut self, config: RetrievalConfig) -> Self {
+        self.retrieval = config;
+        self
+    }
+    
+    pub fn with_answer_cache(mut self, config: AnswerCacheConfig) -> Self {
+        self.answer_cache = AnswerCache::new(config);
+        self
+    }
+    
+    pub fn with_webhooks(mut self, webhooks: WebhookNotifier) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+    
+    /// Retrieve only from the session's focus while one is set
+    pub fn with_focus(mut self, focus: FocusSession) -> Self {
+        self.focus = focus;
+        self
     }
     
-    pub async fn process_query(&self, _query: &str) -> Result<String> {
-        Ok("AI response".to_string())
+    /// Check generated answers against the retrieved passages before returning them
+    pub fn with_grounding(mut self, config: GroundingConfig) -> Self {
+        self.grounding = config;
+        self
+    }
+    
+    /// Limit concurrent queries, serving user queries before background jobs
+    pub fn with_query_queue(mut self, config: QueryQueueConfig) -> Self {
+        self.queue = QueryQueue::new(config);
+        self
+    }
+    
+    /// Admit queries through `queue`, shared with background jobs such as re-indexing
+    pub fn with_shared_query_queue(mut self, queue: QueryQueue) -> Self {
+        self.queue = queue;
+        self
+    }
+    
+    /// Chunk and summary budgets for `summarize`
+    pub fn with_summarizer(mut self, config: SummarizerConfig) -> Self {
+        self.summarizer = config;
+        self
+    }
+    
+    /// Answer in the configured mode; generated answers are cached until the corpus changes
+    pub async fn process_query(&self, query: &str) -> Result<String> {
+        if self.query_mode == QueryMode::ContextOnly {
+            return self.process_query_with_mode(query, self.query_mode, self.retrieval.max_documents).await;
+        }
+        
+        // An answer from focused retrieval must not be served once the focus changes
+        let corpus_version = self.retriever().corpus_version();
+        let cache_key = match self.focus.current().await {
+            Some(focus) => format!("{}\n[focus: {}]", query, focus.describe()),
+            None => query.to_string(),
+        };
+        if let Some(answer) = self.answer_cache.get(&cache_key, corpus_version).await {
+            return Ok(answer);
+        }
+        
+        let answer = self.process_query_with_mode(query, self.query_mode, self.retrieval.max_documents).await?;
+        self.answer_cache.insert(&cache_key, corpus_version, answer.clone()).await;
+        Ok(answer)
+    }
+    
+    /// Answer a query, or in context-only mode return the ranked passages as citations
+    pub async fn process_query_with_mode(&self, query: &str, mode: QueryMode, limit: usize) -> Result<String> {
+        let (answer, _) = self.process_query_traced(query, mode, limit).await?;
+        Ok(answer)
+    }
+    
+    /// `process_query_with_mode`, also returning how long each pipeline stage took
+    pub async fn process_query_traced(&self, query: &str, mode: QueryMode, limit: usize) -> Result<(String, QueryTrace)> {
+        self.process_query_as(query, mode, limit, QueryPriority::Interactive).await
+    }
+    
+    /// Answer a query on behalf of a job such as a digest; it waits while user queries are queued
+    pub async fn process_background_query(&self, query: &str, mode: QueryMode, limit: usize) -> Result<String> {
+        let (answer, _) = self.process_query_as(query, mode, limit, QueryPriority::Background).await?;
+        Ok(answer)
+    }
+    
+    async fn process_query_as(&self, query: &str, mode: QueryMode, limit: usize, priority: QueryPriority) -> Result<(String, QueryTrace)> {
+        let _slot = self.queue.acquire(priority).await?;
+        let answered = self.answer_traced(query, mode, limit).await;
+        match &answered {
+            Ok((answer, trace)) => self.webhooks.notify(WebhookEvent::QueryAnswered, serde_json::json!({
+                "query": query,
+                "mode": mode,
+                "answer": answer,
+                "duration_ms": trace.total().as_millis() as u64,
+            })),
+            Err(e) => self.webhooks.notify(WebhookEvent::Error, serde_json::json!({
+                "stage": "query",
+                "query": query,
+                "error": e.to_string(),
+            })),
+        };
+        answered
+    }
+    
+    async fn answer_traced(&self, query: &str, mode: QueryMode, limit: usize) -> Result<(String, QueryTrace)> {
+        let mut trace = QueryTrace::new(query);
+        let context_query = self.context_query(query, limit).await;
+        
+        if mode == QueryMode::ContextOnly {
+            let results = self.retriever().retrieve(&context_query, &mut trace).await?;
+            return Ok((format_citations(&results), trace));
+        }
+        
+        let backend = self.generation_backend()?;
+        let results = self.retriever().retrieve(&context_query, &mut trace).await?;
+        let prompt = self.build_prompt(&context_query, &results, &mut trace).await?;
+        
+        let answer = trace.time_async(QueryStage::Generate, backend.generate(&prompt, self.max_tokens)).await?;
+        if !self.grounding.enabled {
+            return Ok((answer, trace));
+        }
+        
+        let answer = trace.time_async(QueryStage::Verify, self.ground(&results, answer)).await?;
+        Ok((answer, trace))
+    }
+    
+    /// Answer a query as JSON matching `schema`, for integrations that parse the answer
+    pub async fn process_query_json(&self, query: &str, schema: &OutputSchema) -> anyhow::Result<serde_json::Value> {
+        let _slot = self.queue.acquire(QueryPriority::Interactive).await?;
+        let context_query = self.context_query(query, self.retrieval.max_documents).await;
+        let backend = self.generation_backend()?;
+        let mut trace = QueryTrace::new(query);
+        let results = self.retriever().retrieve(&context_query, &mut trace).await?;
+        let prompt = self.build_prompt(&context_query, &results, &mut trace).await?;
+        
+        structured::generate_structured(backend.as_ref(), &prompt, schema, self.max_tokens, &self.structured_output).await
+    }
+    
+    async fn context_query(&self, query: &str, limit: usize) -> ContextQuery {
+        ContextQuery {
+            query: query.to_string(),
+            query_embedding: None,
+            filters: HashMap::new(),
+            max_results: limit,
+            min_similarity: 0.1,
+            context_window: 4096,
+            include_metadata: false,
+            mmr_lambda: Some(self.retrieval.mmr_lambda),
+            focus: self.focus.current().await,
+        }
+    }
+    
+    /// Flag or drop the answer's claims the retrieved passages don't support
+    async fn ground(&self, results: &[RetrievalResult], answer: String) -> anyhow::Result<String> {
+        let passages: Vec<String> = results.iter()
+            .map(|result| result.document.content.clone())
+            .collect();
+        let report = GroundingVerifier::new(self.grounding.clone(), self.backend.clone())
+            .verify(&answer, &passages)
+            .await?;
+        Ok(report.annotate(&answer, self.grounding.unsupported))
+    }
+    
+    fn retriever(&self) -> &dyn Retriever {
+        self.retriever.as_deref().unwrap_or(self.context.as_ref())
+    }
+    
+    fn generation_backend(&self) -> anyhow::Result<&Arc<dyn Backend>> {
+        self.backend.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No generation backend configured; use context-only mode to see passages"))
+    }
+    
+    async fn build_prompt(&self, context_query: &ContextQuery, results: &[RetrievalResult], trace: &mut QueryTrace) -> anyhow::Result<String> {
+        let window = ContextWindow {
+            total_tokens: context_query.context_window,
+            available_tokens: context_query.context_window - self.max_tokens,
+            reserved_tokens: 256,
+        };
+        self.context.build_context_from(context_query, &window, results, None, trace).await
+    }
+    
+    /// Summarize a long document with map-reduce over token-budgeted chunks
+    pub async fn summarize(
+        &self,
+        backend: Arc<dyn Backend>,
+        counter: Arc<dyn TokenCounter>,
+        text: &str,
+    ) -> anyhow::Result<String> {
+        let _slot = self.queue.acquire(QueryPriority::Background).await?;
+        Summarizer::new(backend, counter, self.summarizer.clone()).summarize(text).await
+    }
+}
+
+/// Numbered passages with their sources, best match first
+pub fn format_citations(results: &[RetrievalResult]) -> String {
+    if results.is_empty() {
+        return "No relevant passages found.".to_string();
+    }
+    
+    results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            format!(
+                "[{}] {} (score {:.2})\n{}",
+                i + 1,
+                result.document.source,
+                result.relevance_score,
+                result.document.content.trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use backend::BackendKind;
+    use context::Document;
+
+    struct CountingBackend(AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl Backend for CountingBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Local
+        }
+
+        async fn generate(&self, _prompt: &str, _max_tokens: usize) -> anyhow::Result<String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok("generated answer".to_string())
+        }
+    }
+
+    async fn ai_with_notes(backend: Arc<CountingBackend>) -> AI {
+        let context = Arc::new(ContextBuilder::new());
+        context.add_documents(vec![Document {
+            id: "garden".to_string(),
+            content: "Tomatoes need watering every morning in summer.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunk_index: 0,
+            source: "notes/Garden.md".to_string(),
+            timestamp: chrono::Utc::now(),
+        }]).await.unwrap();
+
+        AI::new().unwrap().with_context(context).with_backend(backend)
+    }
+
+    #[tokio::test]
+    async fn test_context_only_mode_skips_generation() {
+        let backend = Arc::new(CountingBackend(AtomicUsize::new(0)));
+        let ai = ai_with_notes(backend.clone()).await.with_query_mode(QueryMode::ContextOnly);
+
+        let answer = ai.process_query("when do tomatoes need watering").await.unwrap();
+
+        assert_eq!(backend.0.load(Ordering::SeqCst), 0);
+        assert!(answer.starts_with("[1] notes/Garden.md"));
+        assert!(answer.contains("watering every morning"));
+
+        ai.process_query_with_mode("when do tomatoes need watering", QueryMode::Generate, 5).await.unwrap();
+        assert_eq!(backend.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_query_is_cached_until_corpus_changes() {
+        let backend = Arc::new(CountingBackend(AtomicUsize::new(0)));
+        let ai = ai_with_notes(backend.clone()).await;
+
+        ai.process_query("When do tomatoes need watering?").await.unwrap();
+        let cached = ai.process_query("when do tomatoes need watering").await.unwrap();
+        assert_eq!(cached, "generated answer");
+        assert_eq!(backend.0.load(Ordering::SeqCst), 1);
+
+        ai.context.add_documents(vec![Document {
+            id: "garden-2".to_string(),
+            content: "In a heatwave tomatoes need watering twice a day.".to_string(),
+            metadata: HashMap::new(),
+            embedding: None,
+            chunk_index: 0,
+            source: "notes/Heatwave.md".to_string(),
+            timestamp: chrono::Utc::now(),
+        }]).await.unwrap();
+
+        ai.process_query("when do tomatoes need watering").await.unwrap();
+        assert_eq!(backend.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_trace_records_every_executed_stage() {
+        let backend = Arc::new(CountingBackend(AtomicUsize::new(0)));
+        let ai = ai_with_notes(backend).await;
+
+        let (answer, trace) = ai.process_query_traced("when do tomatoes need watering", QueryMode::Generate, 5).await.unwrap();
+        assert_eq!(answer, "generated answer");
+
+        let stages: Vec<QueryStage> = trace.stages.iter().map(|timing| timing.stage).collect();
+        assert_eq!(stages, [
+            QueryStage::TextSearch,
+            QueryStage::Merge,
+            QueryStage::Rerank,
+            QueryStage::Enrich,
+            QueryStage::Generate,
+        ]);
+        assert!(trace.stages.iter().all(|timing| !timing.duration.is_zero()));
+        assert!(trace.summary().contains("generate"));
+
+        let (_, trace) = ai.process_query_traced("tomatoes", QueryMode::ContextOnly, 5).await.unwrap();
+        assert!(trace.duration(QueryStage::Generate).is_none());
+        assert!(trace.duration(QueryStage::TextSearch).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_json_answers_come_back_parsed_against_the_schema() {
+        let ai = ai_with_notes(Arc::new(CountingBackend(AtomicUsize::new(0)))).await
+            .with_backend(Arc::new(crate::ai::local_llm::LocalLLM::new("./models".into()).await.unwrap()));
+        let schema = OutputSchema::new(serde_json::json!({
+            "type": "object",
+            "properties": { "answer": { "type": "string" } },
+            "required": ["answer"]
+        }));
+
+        let value = ai.process_query_json("when do tomatoes need watering", &schema).await.unwrap();
+        assert!(value["answer"].is_string());
+        assert!(schema.validate(&value).is_ok());
     }
 }
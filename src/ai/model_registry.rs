@@ -0,0 +1,187 @@
+// src/ai/model_registry.rs - Downloaded models by friendly name, kept in `registry.json`
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const REGISTRY_FILE: &str = "registry.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    /// A folder with `model.safetensors`, `tokenizer.json` and `config.json`
+    Llm,
+    /// A single quantized file beside the shared `tokenizer.json`
+    Gguf,
+    /// A Whisper checkpoint folder for the transcriber
+    Whisper,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    /// Hugging Face repo id
+    pub repo: String,
+    pub kind: ModelKind,
+    /// File or folder, relative to the model directory
+    pub path: PathBuf,
+    pub downloaded_at: DateTime<Utc>,
+}
+
+/// A registered model still present on disk
+#[derive(Debug, Clone)]
+pub struct InstalledModel {
+    pub name: String,
+    pub entry: RegistryEntry,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModelRegistry {
+    dir: PathBuf,
+    models: BTreeMap<String, RegistryEntry>,
+}
+
+impl ModelRegistry {
+    /// The registry of the model directory `dir`; empty when it has none yet
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(REGISTRY_FILE);
+        let models = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { dir: dir.to_path_buf(), models })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(REGISTRY_FILE);
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_vec_pretty(&self.models)?)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+
+    /// Friendly name for a repo id: its last segment, e.g. `Llama-2-7B-GGUF`
+    pub fn name_for(repo: &str) -> &str {
+        repo.rsplit('/').next().unwrap_or(repo)
+    }
+
+    /// Record a download under `name`, replacing an earlier one, and save
+    pub fn register(&mut self, name: &str, repo: &str, kind: ModelKind, path: &Path) -> Result<()> {
+        let path = path.strip_prefix(&self.dir).unwrap_or(path).to_path_buf();
+        self.models.insert(name.to_string(), RegistryEntry {
+            repo: repo.to_string(),
+            kind,
+            path,
+            downloaded_at: Utc::now(),
+        });
+        self.save()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegistryEntry> {
+        self.models.get(name)
+    }
+
+    /// Absolute location of a registered model
+    pub fn path_of(&self, entry: &RegistryEntry) -> PathBuf {
+        self.dir.join(&entry.path)
+    }
+
+    /// Registered models whose files are still on disk, with their sizes
+    pub fn installed(&self) -> Result<Vec<InstalledModel>> {
+        let mut installed = Vec::new();
+        for (name, entry) in &self.models {
+            let path = self.path_of(entry);
+            if !path.exists() {
+                continue;
+            }
+            installed.push(InstalledModel {
+                name: name.clone(),
+                entry: entry.clone(),
+                size_bytes: disk_usage(&path)?,
+                path,
+            });
+        }
+        Ok(installed)
+    }
+
+    /// Delete a model's files and forget it; returns the bytes freed
+    pub fn remove(&mut self, name: &str) -> Result<u64> {
+        let entry = self.models.get(name)
+            .ok_or_else(|| anyhow!("No model named {}; see `models list`", name))?;
+        let path = self.path_of(entry);
+        let size = if path.exists() { disk_usage(&path)? } else { 0 };
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else if path.exists() {
+            std::fs::remove_file(&path)
+        } else {
+            Ok(())
+        }
+        .with_context(|| format!("Failed to delete {}", path.display()))?;
+
+        self.models.remove(name);
+        self.save()?;
+        Ok(size)
+    }
+}
+
+/// Total size of a file, or of every file under a directory
+pub fn disk_usage(path: &Path) -> Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_round_trips_and_remove_deletes_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let model_dir = dir.path().join("Mistral-7B-Instruct-v0.2");
+        std::fs::create_dir_all(&model_dir).unwrap();
+        std::fs::write(model_dir.join("model.safetensors"), vec![0u8; 1000]).unwrap();
+        std::fs::write(model_dir.join("tokenizer.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("phi-2.Q4_0.gguf"), vec![0u8; 300]).unwrap();
+
+        let mut registry = ModelRegistry::load(dir.path()).unwrap();
+        let repo = "mistralai/Mistral-7B-Instruct-v0.2";
+        registry.register(ModelRegistry::name_for(repo), repo, ModelKind::Llm, &model_dir).unwrap();
+        registry.register("phi-2", "TheBloke/phi-2-GGUF", ModelKind::Gguf, &dir.path().join("phi-2.Q4_0.gguf")).unwrap();
+        registry.register("gone", "someone/gone", ModelKind::Llm, &dir.path().join("gone")).unwrap();
+
+        let reloaded = ModelRegistry::load(dir.path()).unwrap();
+        let entry = reloaded.get("Mistral-7B-Instruct-v0.2").unwrap();
+        assert_eq!(entry.repo, repo);
+        assert_eq!(entry.path, PathBuf::from("Mistral-7B-Instruct-v0.2"));
+        assert_eq!(reloaded.get("phi-2").unwrap().kind, ModelKind::Gguf);
+
+        // Only what is on disk is listed
+        let installed = reloaded.installed().unwrap();
+        let names: Vec<&str> = installed.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["Mistral-7B-Instruct-v0.2", "phi-2"]);
+        assert_eq!(installed[0].size_bytes, 1002);
+
+        let mut registry = reloaded;
+        assert_eq!(registry.remove("Mistral-7B-Instruct-v0.2").unwrap(), 1002);
+        assert!(!model_dir.exists());
+        assert_eq!(registry.remove("phi-2").unwrap(), 300);
+        assert!(registry.remove("phi-2").is_err());
+
+        let reloaded = ModelRegistry::load(dir.path()).unwrap();
+        assert!(reloaded.get("Mistral-7B-Instruct-v0.2").is_none());
+        assert!(reloaded.get("gone").is_some());
+    }
+}
@@ -114,6 +114,9 @@ impl ModelSwitcher {
             return Err(anyhow!("No available models meet the requirements"));
         }
 
+        // HashMap order is random; sort so ties between equal models break the same way every run
+        candidates.sort_by_key(|(name, _)| *name);
+
         // Apply filtering based on constraints
         if let Some(max_latency) = context.max_latency_ms {
             candidates.retain(|(name, config)| {
@@ -202,6 +205,20 @@ impl ModelSwitcher {
         Ok(())
     }
 
+    /// Resolve a per-request model override without changing the current model
+    pub async fn resolve_override(&self, model_name: &str) -> Result<String> {
+        let models = self.models.read().await;
+
+        let config = models.get(model_name)
+            .ok_or_else(|| anyhow!("Model '{}' not registered", model_name))?;
+
+        if !config.is_available {
+            return Err(anyhow!("Model '{}' is not available", model_name));
+        }
+
+        Ok(model_name.to_string())
+    }
+
     /// Get current active model
     pub async fn get_current_model(&self) -> Option<String> {
         self.current_model.read().await.clone()
@@ -348,4 +365,59 @@ mod tests {
         let selected = switcher.select_model(&context).await.unwrap();
         assert_eq!(selected, "gpt-3.5");
     }
+
+    #[tokio::test]
+    async fn test_model_override() {
+        let switcher = ModelSwitcher::new();
+
+        let cheap_model = ModelConfig {
+            name: "gpt-3.5".to_string(),
+            endpoint: "https://api.openai.com/v1".to_string(),
+            max_tokens: 4096,
+            temperature: 0.7,
+            cost_per_token: 0.000002,
+            latency_ms: 800,
+            capabilities: vec!["text-generation".to_string()],
+            context_window: 4096,
+            is_available: true,
+        };
+
+        let expensive_model = ModelConfig {
+            name: "gpt-4".to_string(),
+            cost_per_token: 0.00003,
+            is_available: true,
+            ..cheap_model.clone()
+        };
+
+        let offline_model = ModelConfig {
+            name: "offline".to_string(),
+            is_available: false,
+            ..cheap_model.clone()
+        };
+
+        switcher.register_model(cheap_model).await.unwrap();
+        switcher.register_model(expensive_model).await.unwrap();
+        switcher.register_model(offline_model).await.unwrap();
+
+        let context = TaskContext {
+            task_type: "simple_generation".to_string(),
+            required_capabilities: vec!["text-generation".to_string()],
+            max_latency_ms: None,
+            max_cost_per_token: None,
+            context_size: 2048,
+            priority: 5,
+        };
+        switcher.select_model(&context).await.unwrap();
+
+        // Override routes to the named model...
+        let overridden = switcher.resolve_override("gpt-4").await.unwrap();
+        assert_eq!(overridden, "gpt-4");
+
+        // ...without changing the selection used by later requests
+        assert_eq!(switcher.get_current_model().await.as_deref(), Some("gpt-3.5"));
+        assert_eq!(switcher.select_model(&context).await.unwrap(), "gpt-3.5");
+
+        assert!(switcher.resolve_override("offline").await.is_err());
+        assert!(switcher.resolve_override("missing").await.is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,304 @@
+// src/ai/quantize.rs - Int8 quantization of safetensors weights for machines with little RAM
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuantizationConfig {
+    /// Quantize safetensors weights right after they are downloaded
+    pub enabled: bool,
+    /// Remove the full-precision file once the quantized one is written
+    pub delete_original: bool,
+}
+
+/// Suffix of the per-row scale tensor stored next to each quantized weight
+pub const SCALE_SUFFIX: &str = ".__scale";
+
+/// A tensor's shape and values, dequantized to f32
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorData {
+    pub shape: Vec<usize>,
+    pub values: Vec<f32>,
+}
+
+struct RawTensor {
+    dtype: String,
+    shape: Vec<usize>,
+    bytes: Vec<u8>,
+}
+
+/// Where a tensor's bytes sit, relative to the start of the data section
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    begin: u64,
+    end: u64,
+}
+
+impl TensorInfo {
+    fn quantizable(&self) -> bool {
+        self.shape.len() >= 2 && matches!(self.dtype.as_str(), "F32" | "F16" | "BF16")
+    }
+
+    fn rows(&self) -> usize {
+        self.shape[0].max(1)
+    }
+}
+
+/// `model.safetensors` -> `model.int8.safetensors`
+pub fn quantized_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("model");
+    path.with_file_name(format!("{}.int8.safetensors", stem))
+}
+
+/// Whether `path` was written by `quantize_file` and needs `load_dequantized` to read
+pub fn is_quantized(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.ends_with(".int8.safetensors"))
+}
+
+/// Write an int8 copy of a safetensors file: every float matrix becomes I8 with one f32
+/// absmax scale per row, while vectors (norms, biases) stay as they are. Tensors are read,
+/// converted and written one at a time, so only the largest one is ever in memory.
+/// Returns the path of the quantized file.
+pub fn quantize_file(path: &Path, config: &QuantizationConfig) -> Result<PathBuf> {
+    let mut source = File::open(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let (data_start, tensors) = read_header(&mut source, path)?;
+
+    // Offsets are known up front: a quantized matrix is one byte per value plus a scale per row
+    let source_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let mut header = Map::new();
+    header.insert("__metadata__".to_string(), json!({ "quantization": "int8-absmax-per-row", "source": source_name }));
+    let mut offset = 0u64;
+    let mut entry = |name: String, dtype: &str, shape: Vec<usize>, len: u64| {
+        header.insert(name, json!({ "dtype": dtype, "shape": shape, "data_offsets": [offset, offset + len] }));
+        offset += len;
+    };
+    for (name, info) in &tensors {
+        if info.quantizable() {
+            entry(name.clone(), "I8", info.shape.clone(), info.shape.iter().product::<usize>() as u64);
+            entry(format!("{}{}", name, SCALE_SUFFIX), "F32", vec![info.rows()], info.rows() as u64 * 4);
+        } else {
+            entry(name.clone(), &info.dtype, info.shape.clone(), info.end - info.begin);
+        }
+    }
+
+    let target = quantized_path(path);
+    let mut output = BufWriter::new(File::create(&target)
+        .with_context(|| format!("Failed to write {}", target.display()))?);
+    write_header(&mut output, header)?;
+    for info in tensors.values() {
+        let tensor = read_tensor(&mut source, data_start, info)?;
+        if !info.quantizable() {
+            output.write_all(&tensor.bytes)?;
+            continue;
+        }
+
+        let values = to_f32(&tensor)?;
+        let row_len = values.len() / info.rows();
+        let mut quantized = Vec::with_capacity(values.len());
+        let mut scales = Vec::with_capacity(info.rows() * 4);
+        for row in values.chunks(row_len.max(1)) {
+            let absmax = row.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+            let scale = if absmax > 0.0 { absmax / 127.0 } else { 1.0 };
+            quantized.extend(row.iter().map(|v| (v / scale).round().clamp(-127.0, 127.0) as i8 as u8));
+            scales.extend_from_slice(&scale.to_le_bytes());
+        }
+        output.write_all(&quantized)?;
+        output.write_all(&scales)?;
+    }
+    output.flush().with_context(|| format!("Failed to write {}", target.display()))?;
+
+    if config.delete_original {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    Ok(target)
+}
+
+/// Load a safetensors file as f32 tensors, dequantizing int8 weights with their scales
+pub fn load_dequantized(path: &Path) -> Result<BTreeMap<String, TensorData>> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let (data_start, tensors) = read_header(&mut file, path)?;
+
+    let mut loaded = BTreeMap::new();
+    for (name, info) in &tensors {
+        if name.ends_with(SCALE_SUFFIX) {
+            continue;
+        }
+
+        let tensor = read_tensor(&mut file, data_start, info)?;
+        let values = match tensors.get(&format!("{}{}", name, SCALE_SUFFIX)) {
+            Some(scales) if tensor.dtype == "I8" => {
+                let scales = to_f32(&read_tensor(&mut file, data_start, scales)?)?;
+                let row_len = tensor.bytes.len() / scales.len().max(1);
+                tensor.bytes.iter()
+                    .enumerate()
+                    .map(|(i, &q)| q as i8 as f32 * scales[i / row_len.max(1)])
+                    .collect()
+            }
+            _ => to_f32(&tensor)?,
+        };
+        loaded.insert(name.clone(), TensorData { shape: tensor.shape, values });
+    }
+    Ok(loaded)
+}
+
+/// The tensors listed in a safetensors header, and the file offset their data starts at
+fn read_header(file: &mut File, path: &Path) -> Result<(u64, BTreeMap<String, TensorInfo>)> {
+    let file_len = file.metadata()?.len();
+    let mut len = [0u8; 8];
+    file.read_exact(&mut len)
+        .map_err(|_| anyhow!("{} is too short to be a safetensors file", path.display()))?;
+    let header_len = u64::from_le_bytes(len);
+    let data_start = 8 + header_len;
+    if data_start > file_len {
+        return Err(anyhow!("Truncated safetensors header"));
+    }
+    let mut header = vec![0u8; header_len as usize];
+    file.read_exact(&mut header)?;
+    let header: Map<String, Value> = serde_json::from_slice(&header)?;
+
+    let mut tensors = BTreeMap::new();
+    for (name, info) in header {
+        if name == "__metadata__" {
+            continue;
+        }
+        let dtype = info["dtype"].as_str().ok_or_else(|| anyhow!("Tensor {} has no dtype", name))?.to_string();
+        let shape = info["shape"].as_array()
+            .ok_or_else(|| anyhow!("Tensor {} has no shape", name))?
+            .iter()
+            .map(|d| d.as_u64().map(|d| d as usize).ok_or_else(|| anyhow!("Bad shape for {}", name)))
+            .collect::<Result<Vec<_>>>()?;
+        let (begin, end) = match info["data_offsets"].as_array().map(|o| o.as_slice()) {
+            Some([begin, end]) => (begin.as_u64().unwrap_or_default(), end.as_u64().unwrap_or_default()),
+            _ => return Err(anyhow!("Tensor {} has no data offsets", name)),
+        };
+        if begin > end || data_start + end > file_len {
+            return Err(anyhow!("Tensor {} points past the end of the file", name));
+        }
+        tensors.insert(name, TensorInfo { dtype, shape, begin, end });
+    }
+    Ok((data_start, tensors))
+}
+
+fn read_tensor(file: &mut File, data_start: u64, info: &TensorInfo) -> Result<RawTensor> {
+    let mut bytes = vec![0u8; (info.end - info.begin) as usize];
+    file.seek(SeekFrom::Start(data_start + info.begin))?;
+    file.read_exact(&mut bytes)?;
+    Ok(RawTensor { dtype: info.dtype.clone(), shape: info.shape.clone(), bytes })
+}
+
+/// The length prefix and JSON header, padded so the data starts 8-byte aligned
+fn write_header(output: &mut impl Write, header: Map<String, Value>) -> Result<()> {
+    let mut header_bytes = serde_json::to_vec(&header)?;
+    while header_bytes.len() % 8 != 0 {
+        header_bytes.push(b' ');
+    }
+    output.write_all(&(header_bytes.len() as u64).to_le_bytes())?;
+    output.write_all(&header_bytes)?;
+    Ok(())
+}
+
+fn to_f32(tensor: &RawTensor) -> Result<Vec<f32>> {
+    let values = match tensor.dtype.as_str() {
+        "F32" => tensor.bytes.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        "F16" => tensor.bytes.chunks_exact(2)
+            .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+            .collect(),
+        "BF16" => tensor.bytes.chunks_exact(2)
+            .map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16))
+            .collect(),
+        "I8" => tensor.bytes.iter().map(|&b| b as i8 as f32).collect(),
+        other => return Err(anyhow!("Unsupported tensor dtype {}", other)),
+    };
+    Ok(values)
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_safetensors(path: &Path) -> Result<BTreeMap<String, RawTensor>> {
+        let mut file = File::open(path)?;
+        let (data_start, tensors) = read_header(&mut file, path)?;
+        tensors.into_iter()
+            .map(|(name, info)| Ok((name, read_tensor(&mut file, data_start, &info)?)))
+            .collect()
+    }
+
+    fn write_safetensors(path: &Path, tensors: &BTreeMap<String, RawTensor>, metadata: Value) -> Result<()> {
+        let mut header = Map::new();
+        header.insert("__metadata__".to_string(), metadata);
+        let mut offset = 0;
+        for (name, tensor) in tensors {
+            header.insert(name.clone(), json!({
+                "dtype": tensor.dtype,
+                "shape": tensor.shape,
+                "data_offsets": [offset, offset + tensor.bytes.len()],
+            }));
+            offset += tensor.bytes.len();
+        }
+
+        let mut file = File::create(path)?;
+        write_header(&mut file, header)?;
+        for tensor in tensors.values() {
+            file.write_all(&tensor.bytes)?;
+        }
+        Ok(())
+    }
+
+    fn tiny_model(path: &Path) {
+        let weight: Vec<f32> = (0..32).map(|i| (i as f32 - 16.0) / 8.0).collect();
+        let norm = vec![1.0f32, 0.5, 0.25, 0.125];
+        let bytes = |values: &[f32]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+
+        let mut tensors = BTreeMap::new();
+        tensors.insert("layer.weight".to_string(), RawTensor { dtype: "F32".to_string(), shape: vec![8, 4], bytes: bytes(&weight) });
+        tensors.insert("layer.norm".to_string(), RawTensor { dtype: "F32".to_string(), shape: vec![4], bytes: bytes(&norm) });
+        write_safetensors(path, &tensors, json!({})).unwrap();
+    }
+
+    #[test]
+    fn test_quantized_file_is_smaller_and_loads_close_to_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("tiny.safetensors");
+        tiny_model(&original);
+        let expected = load_dequantized(&original).unwrap();
+
+        let quantized = quantize_file(&original, &QuantizationConfig { enabled: true, delete_original: true }).unwrap();
+        assert_eq!(quantized, dir.path().join("tiny.int8.safetensors"));
+        assert!(!original.exists());
+
+        let loaded = load_dequantized(&quantized).unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), ["layer.norm", "layer.weight"]);
+        assert_eq!(loaded["layer.weight"].shape, vec![8, 4]);
+        assert_eq!(loaded["layer.norm"], expected["layer.norm"]);
+        for (q, v) in loaded["layer.weight"].values.iter().zip(&expected["layer.weight"].values) {
+            assert!((q - v).abs() < 0.02, "{} vs {}", q, v);
+        }
+
+        let raw = read_safetensors(&quantized).unwrap();
+        assert_eq!(raw["layer.weight"].dtype, "I8");
+        assert_eq!(raw["layer.weight"].bytes.len(), 32);
+    }
+}
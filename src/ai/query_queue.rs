@@ -0,0 +1,222 @@
+// src/ai/query_queue.rs - Bounded admission for retrieval and generation, user queries first
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryPriority {
+    /// Digests, summaries and other jobs nobody is waiting on
+    Background,
+    /// A user waiting for an answer
+    Interactive,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryQueueConfig {
+    /// Queries retrieving or generating at once
+    pub max_concurrent: usize,
+    /// Of those, how many may be background jobs, so a burst of them can't fill every slot
+    pub max_background: usize,
+    /// Waiting queries beyond this are rejected instead of queued
+    pub max_queued: usize,
+}
+
+impl Default for QueryQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 2,
+            max_background: 1,
+            max_queued: 64,
+        }
+    }
+}
+
+struct Waiter {
+    priority: QueryPriority,
+    seq: u64,
+    grant: oneshot::Sender<QuerySlot>,
+}
+
+// Higher priority first, then first come first served
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    running_background: usize,
+    next_seq: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+struct Shared {
+    config: QueryQueueConfig,
+    state: Mutex<QueueState>,
+}
+
+/// Limits how many queries run at once and hands free slots to waiting user queries before
+/// background jobs; queries of the same priority run in arrival order
+#[derive(Clone)]
+pub struct QueryQueue {
+    shared: Arc<Shared>,
+}
+
+/// A running query's slot, given back to the queue when dropped
+pub struct QuerySlot {
+    shared: Arc<Shared>,
+    priority: QueryPriority,
+}
+
+impl Drop for QuerySlot {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.running -= 1;
+            if self.priority == QueryPriority::Background {
+                state.running_background -= 1;
+            }
+        }
+        QueryQueue::dispatch(&self.shared);
+    }
+}
+
+impl QueryQueue {
+    pub fn new(config: QueryQueueConfig) -> Self {
+        let config = QueryQueueConfig {
+            max_concurrent: config.max_concurrent.max(1),
+            max_background: config.max_background.max(1),
+            ..config
+        };
+        Self {
+            shared: Arc::new(Shared { config, state: Mutex::new(QueueState::default()) }),
+        }
+    }
+
+    /// Wait for a slot; hold the returned value for as long as the query runs
+    pub async fn acquire(&self, priority: QueryPriority) -> Result<QuerySlot> {
+        let (grant, granted) = oneshot::channel();
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.waiting.len() >= self.shared.config.max_queued {
+                return Err(anyhow::anyhow!("Too many queries waiting; try again shortly"));
+            }
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter { priority, seq, grant });
+        }
+        Self::dispatch(&self.shared);
+
+        granted.await.map_err(|_| anyhow::anyhow!("Query queue closed"))
+    }
+
+    /// Queries waiting for a slot
+    pub fn waiting(&self) -> usize {
+        self.shared.state.lock().unwrap().waiting.len()
+    }
+
+    /// Start waiting queries while there are free slots
+    fn dispatch(shared: &Arc<Shared>) {
+        loop {
+            let (waiter, slot) = {
+                let mut state = shared.state.lock().unwrap();
+                // Waiters that gave up are dropped without taking a slot
+                while state.waiting.peek().is_some_and(|w| w.grant.is_closed()) {
+                    state.waiting.pop();
+                }
+                let Some(next) = state.waiting.peek() else { return };
+                let background = next.priority == QueryPriority::Background;
+                // Only background jobs are left once the best waiter is one
+                if state.running >= shared.config.max_concurrent
+                    || (background && state.running_background >= shared.config.max_background)
+                {
+                    return;
+                }
+
+                let waiter = state.waiting.pop().unwrap();
+                state.running += 1;
+                if background {
+                    state.running_background += 1;
+                }
+                let slot = QuerySlot { shared: Arc::clone(shared), priority: waiter.priority };
+                (waiter, slot)
+            };
+            // Outside the lock: if the waiter left meanwhile, dropping the slot releases it again
+            let _ = waiter.grant.send(slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_user_query_is_served_before_waiting_background_job() {
+        let queue = QueryQueue::new(QueryQueueConfig { max_concurrent: 1, ..Default::default() });
+        let busy = queue.acquire(QueryPriority::Interactive).await.unwrap();
+
+        let served = Arc::new(Mutex::new(Vec::new()));
+        let spawn = |priority, name: &'static str| {
+            let (queue, served) = (queue.clone(), Arc::clone(&served));
+            tokio::spawn(async move {
+                let _slot = queue.acquire(priority).await.unwrap();
+                served.lock().unwrap().push(name);
+            })
+        };
+        // The background job is queued first and still loses to the user query
+        let digest = spawn(QueryPriority::Background, "digest");
+        while queue.waiting() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let user = spawn(QueryPriority::Interactive, "user");
+        while queue.waiting() < 2 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        drop(busy);
+        user.await.unwrap();
+        digest.await.unwrap();
+        assert_eq!(*served.lock().unwrap(), ["user", "digest"]);
+    }
+
+    #[tokio::test]
+    async fn test_background_jobs_leave_slots_for_user_queries() {
+        let queue = QueryQueue::new(QueryQueueConfig { max_concurrent: 2, max_background: 1, ..Default::default() });
+        let _job = queue.acquire(QueryPriority::Background).await.unwrap();
+
+        // The second background job waits even though a slot is free...
+        let queued = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(QueryPriority::Background).await.map(|_| ()) }
+        });
+        while queue.waiting() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        // ...which a user query takes straight away
+        let user = tokio::time::timeout(Duration::from_secs(1), queue.acquire(QueryPriority::Interactive)).await;
+        assert!(user.is_ok());
+        queued.abort();
+    }
+}
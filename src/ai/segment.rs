@@ -0,0 +1,86 @@
+// src/ai/segment.rs - Split text into paragraphs, tables, fenced code and sentences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    Prose,
+    Code,
+    Table,
+}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub text: String,
+}
+
+/// Paragraphs, tables and fenced code blocks in order. Blank lines inside a fence don't split
+/// it, and an unterminated fence runs to the end of the text.
+pub fn segments(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut current_kind = SegmentKind::Prose;
+    let mut fence: Option<&str> = None;
+
+    let flush = |lines: &mut Vec<&str>, kind: SegmentKind, segments: &mut Vec<Segment>| {
+        let text = lines.join("\n");
+        if !text.trim().is_empty() {
+            segments.push(Segment { kind, text: text.trim_end().to_string() });
+        }
+        lines.clear();
+    };
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence {
+            current.push(line);
+            if trimmed.starts_with(marker) {
+                flush(&mut current, SegmentKind::Code, &mut segments);
+                fence = None;
+                current_kind = SegmentKind::Prose;
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush(&mut current, current_kind, &mut segments);
+            fence = Some(&trimmed[..3]);
+            current_kind = SegmentKind::Code;
+            current.push(line);
+            continue;
+        }
+
+        let kind = if trimmed.starts_with('|') { SegmentKind::Table } else { SegmentKind::Prose };
+        if trimmed.is_empty() || kind != current_kind {
+            flush(&mut current, current_kind, &mut segments);
+            current_kind = kind;
+        }
+        if !trimmed.is_empty() {
+            current.push(line);
+        }
+    }
+
+    flush(&mut current, current_kind, &mut segments);
+    segments
+}
+
+/// Sentences ending in `.`, `!` or `?` followed by whitespace, trimmed
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+
+    for (i, ch) in text.char_indices() {
+        if matches!(ch, '.' | '!' | '?') {
+            let end = i + ch.len_utf8();
+            if end == text.len() || text[end..].starts_with(char::is_whitespace) {
+                sentences.push(text[start..end].trim().to_string());
+                start = end;
+            }
+        }
+    }
+
+    if !text[start..].trim().is_empty() {
+        sentences.push(text[start..].trim().to_string());
+    }
+
+    sentences
+}
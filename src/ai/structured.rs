@@ -0,0 +1,542 @@
+// src/ai/structured.rs - JSON-schema constrained answers for integrations
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::ai::backend::Backend;
+use crate::ai::generation::Sampler;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructuredOutputConfig {
+    /// Times an answer that fails to parse or validate is sent back to be repaired
+    pub repair_attempts: usize,
+}
+
+impl Default for StructuredOutputConfig {
+    fn default() -> Self {
+        Self { repair_attempts: 1 }
+    }
+}
+
+/// The subset of JSON Schema answers can be constrained to: `type`, `properties`,
+/// `required`, `items`, `enum` and `additionalProperties: false`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSchema(Value);
+
+impl OutputSchema {
+    pub fn new(schema: Value) -> Self {
+        Self(schema)
+    }
+
+    pub fn as_value(&self) -> &Value {
+        &self.0
+    }
+
+    /// Instructions appended to a prompt for backends without constrained decoding
+    pub fn prompt_instructions(&self) -> String {
+        format!(
+            "Respond only with JSON matching this schema, without any other text:\n{}",
+            serde_json::to_string_pretty(&self.0).unwrap_or_default()
+        )
+    }
+
+    /// Prompt asking the model to fix an answer that failed to parse or validate
+    pub fn repair_prompt(&self, output: &str, error: &str) -> String {
+        format!(
+            "Your previous answer was not valid: {}\n\nPrevious answer:\n{}\n\n{}",
+            error,
+            output.trim(),
+            self.prompt_instructions()
+        )
+    }
+
+    /// Parse model output and check it against the schema, tolerating a code fence
+    pub fn parse(&self, output: &str) -> std::result::Result<Value, String> {
+        let trimmed = output.trim();
+        let body = trimmed.strip_prefix("```json")
+            .or_else(|| trimmed.strip_prefix("```"))
+            .and_then(|t| t.strip_suffix("```"))
+            .unwrap_or(trimmed);
+
+        let value: Value = serde_json::from_str(body.trim()).map_err(|e| format!("invalid JSON: {}", e))?;
+        self.validate(&value)?;
+        Ok(value)
+    }
+
+    pub fn validate(&self, value: &Value) -> std::result::Result<(), String> {
+        validate_at(value, &self.0, "$")
+    }
+
+    /// Whether `text` can still be extended into a value matching the schema
+    pub fn accepts_prefix(&self, text: &str) -> bool {
+        !matches!(self.scan(text), Scan::Invalid)
+    }
+
+    /// Whether `text` is already a complete value matching the schema
+    pub fn is_complete(&self, text: &str) -> bool {
+        // Trailing whitespace ends a top-level number the way a closing bracket would
+        matches!(self.scan(&format!("{} ", text)), Scan::Done(_))
+    }
+
+    /// Mask every token that would take `prefix` outside the schema.
+    /// End-of-sequence is the only token allowed once the value is complete, and only then.
+    /// Object keys are limited to `properties`, which is stricter than `validate`.
+    pub fn mask_logits(&self, prefix: &str, vocab: &[String], eos: usize, logits: &mut [f32]) {
+        let complete = self.is_complete(prefix);
+        for (id, logit) in logits.iter_mut().enumerate() {
+            let allowed = if id == eos {
+                complete
+            } else {
+                !complete && vocab.get(id).is_some_and(|token| {
+                    !token.is_empty() && self.accepts_prefix(&format!("{}{}", prefix, token))
+                })
+            };
+            if !allowed {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+    }
+
+    fn scan(&self, text: &str) -> Scan {
+        let chars: Vec<char> = text.chars().collect();
+        let scanner = PrefixScanner { chars: &chars };
+        match scanner.value(0, &self.0) {
+            Scan::Done(end) if scanner.skip_ws(end) == chars.len() => Scan::Done(end),
+            Scan::Done(_) => Scan::Invalid,
+            other => other,
+        }
+    }
+}
+
+/// Sample tokens under the schema mask until end-of-sequence or `max_tokens`
+pub fn decode_constrained(
+    schema: &OutputSchema,
+    vocab: &[String],
+    eos: usize,
+    max_tokens: usize,
+    sampler: &mut Sampler,
+    mut next_logits: impl FnMut(&str) -> Result<Vec<f32>>,
+) -> Result<String> {
+    let mut text = String::new();
+
+    for _ in 0..max_tokens {
+        let mut logits = next_logits(&text)?;
+        schema.mask_logits(&text, vocab, eos, &mut logits);
+        if logits.iter().all(|l| *l == f32::NEG_INFINITY) {
+            return Err(anyhow!("No token can continue {:?} within the schema", text));
+        }
+
+        let id = sampler.sample(&logits);
+        if id == eos {
+            break;
+        }
+        text.push_str(&vocab[id]);
+    }
+
+    Ok(text)
+}
+
+/// Generate an answer conforming to `schema`, sending invalid output back for repair
+pub async fn generate_structured(
+    backend: &dyn Backend,
+    prompt: &str,
+    schema: &OutputSchema,
+    max_tokens: usize,
+    config: &StructuredOutputConfig,
+) -> Result<Value> {
+    let mut output = backend.generate_json(prompt, schema, max_tokens).await?;
+    let mut attempts = 0;
+
+    loop {
+        match schema.parse(&output) {
+            Ok(value) => return Ok(value),
+            Err(error) if attempts < config.repair_attempts => {
+                attempts += 1;
+                let repair = schema.repair_prompt(&output, &error);
+                output = backend.generate_json(&repair, schema, max_tokens).await?;
+            }
+            Err(error) => {
+                return Err(anyhow!(
+                    "Answer did not match the schema after {} repair attempts: {}", attempts, error
+                ));
+            }
+        }
+    }
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str) -> std::result::Result<(), String> {
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            return Err(format!("{} must be one of {}", path, Value::Array(options.clone())));
+        }
+    }
+
+    if !allows_type(schema, |ty| has_type(value, ty)) {
+        return Err(format!("{} must be of type {}", path, schema["type"]));
+    }
+
+    if let Value::Object(map) = value {
+        for required in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(key) = required.as_str().filter(|key| !map.contains_key(*key)) {
+                return Err(format!("{} is missing required property \"{}\"", path, key));
+            }
+        }
+
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+            for (key, item) in map {
+                match properties.get(key) {
+                    Some(property) => validate_at(item, property, &format!("{}.{}", path, key))?,
+                    None if closed => return Err(format!("{} has unexpected property \"{}\"", path, key)),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_at(item, item_schema, &format!("{}[{}]", path, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the schema's `type` (absent, a name, or a list of names) admits a type `matches` accepts
+fn allows_type(schema: &Value, matches: impl Fn(&str) -> bool) -> bool {
+    match schema.get("type") {
+        Some(Value::String(ty)) => matches(ty),
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).any(matches),
+        _ => true,
+    }
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// How far a JSON prefix got against a schema
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scan {
+    /// A complete value ending before this char index
+    Done(usize),
+    /// Input ran out while the value could still become valid
+    Partial,
+    Invalid,
+}
+
+/// Recursive descent over a possibly unfinished JSON document
+struct PrefixScanner<'a> {
+    chars: &'a [char],
+}
+
+impl PrefixScanner<'_> {
+    fn skip_ws(&self, mut i: usize) -> usize {
+        while self.chars.get(i).is_some_and(char::is_ascii_whitespace) {
+            i += 1;
+        }
+        i
+    }
+
+    fn value(&self, i: usize, schema: &Value) -> Scan {
+        let i = self.skip_ws(i);
+        let Some(&c) = self.chars.get(i) else {
+            return Scan::Partial;
+        };
+
+        if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+            let literals: Vec<String> = options.iter().map(Value::to_string).collect();
+            return self.literal(i, &literals);
+        }
+
+        let allows = |ty: &str| allows_type(schema, |t| t == ty);
+        match c {
+            '{' if allows("object") => self.object(i, schema),
+            '[' if allows("array") => self.array(i, schema),
+            '"' if allows("string") => self.string(i),
+            '-' | '0'..='9' if allows("number") || allows("integer") => self.number(i, !allows("number")),
+            't' | 'f' if allows("boolean") => self.literal(i, &["true".to_string(), "false".to_string()]),
+            'n' if allows("null") => self.literal(i, &["null".to_string()]),
+            _ => Scan::Invalid,
+        }
+    }
+
+    fn literal(&self, i: usize, options: &[String]) -> Scan {
+        let rest = &self.chars[i..];
+        let mut options: Vec<Vec<char>> = options.iter().map(|o| o.chars().collect()).collect();
+        // Prefer the longest literal so an enum of 1 and 10 still reaches 10
+        options.sort_by_key(|o| std::cmp::Reverse(o.len()));
+
+        let mut partial = false;
+        for option in &options {
+            if rest.starts_with(option) {
+                return Scan::Done(i + option.len());
+            }
+            if option.starts_with(rest) {
+                partial = true;
+            }
+        }
+
+        if partial { Scan::Partial } else { Scan::Invalid }
+    }
+
+    fn string(&self, i: usize) -> Scan {
+        let mut j = i + 1;
+        while let Some(&c) = self.chars.get(j) {
+            match c {
+                '"' => return Scan::Done(j + 1),
+                '\\' => match self.chars.get(j + 1) {
+                    None => return Scan::Partial,
+                    Some('u') => {
+                        let end = self.chars.len();
+                        let hex = &self.chars[(j + 2).min(end)..(j + 6).min(end)];
+                        if !hex.iter().all(char::is_ascii_hexdigit) {
+                            return Scan::Invalid;
+                        }
+                        if hex.len() < 4 {
+                            return Scan::Partial;
+                        }
+                        j += 6;
+                    }
+                    Some(e) if "\"\\/bfnrt".contains(*e) => j += 2,
+                    Some(_) => return Scan::Invalid,
+                },
+                c if (c as u32) < 0x20 => return Scan::Invalid,
+                _ => j += 1,
+            }
+        }
+        Scan::Partial
+    }
+
+    fn number(&self, i: usize, integer: bool) -> Scan {
+        let digits = |mut j: usize| {
+            while self.chars.get(j).is_some_and(char::is_ascii_digit) {
+                j += 1;
+            }
+            j
+        };
+        let end = self.chars.len();
+
+        let mut j = i;
+        if self.chars[j] == '-' {
+            j += 1;
+        }
+        match self.chars.get(j) {
+            None => return Scan::Partial,
+            Some('0') => j += 1,
+            Some(c) if c.is_ascii_digit() => j = digits(j),
+            Some(_) => return Scan::Invalid,
+        }
+
+        if !integer && self.chars.get(j) == Some(&'.') {
+            let start = j + 1;
+            j = digits(start);
+            if j == end {
+                return Scan::Partial;
+            }
+            if j == start {
+                return Scan::Invalid;
+            }
+        }
+
+        if !integer && matches!(self.chars.get(j), Some('e' | 'E')) {
+            j += 1;
+            if matches!(self.chars.get(j), Some('+' | '-')) {
+                j += 1;
+            }
+            let start = j;
+            j = digits(start);
+            if j == end {
+                return Scan::Partial;
+            }
+            if j == start {
+                return Scan::Invalid;
+            }
+        }
+
+        // Digits could still follow until something else ends the number
+        if j == end { Scan::Partial } else { Scan::Done(j) }
+    }
+
+    fn object(&self, i: usize, schema: &Value) -> Scan {
+        let any = json!({});
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let required: Vec<&str> = schema.get("required")
+            .and_then(Value::as_array)
+            .map(|r| r.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let mut seen: Vec<String> = Vec::new();
+        let closes = |seen: &[String], end: usize| {
+            if required.iter().all(|r| seen.iter().any(|s| s == r)) {
+                Scan::Done(end)
+            } else {
+                Scan::Invalid
+            }
+        };
+
+        let mut j = self.skip_ws(i + 1);
+        loop {
+            let Some(&c) = self.chars.get(j) else {
+                return Scan::Partial;
+            };
+            if c == '}' && seen.is_empty() {
+                return closes(&seen, j + 1);
+            }
+            if c != '"' {
+                return Scan::Invalid;
+            }
+
+            let key_end = match self.string(j) {
+                Scan::Done(end) => end,
+                Scan::Partial => {
+                    let typed: String = self.chars[j + 1..].iter().collect();
+                    let possible = properties.is_none_or(|p| {
+                        p.keys().any(|k| !seen.contains(k) && k.starts_with(&typed))
+                    });
+                    return if possible { Scan::Partial } else { Scan::Invalid };
+                }
+                Scan::Invalid => return Scan::Invalid,
+            };
+
+            let key: String = self.chars[j + 1..key_end - 1].iter().collect();
+            let value_schema = match properties {
+                Some(p) => match p.get(&key) {
+                    Some(s) if !seen.contains(&key) => s,
+                    _ => return Scan::Invalid,
+                },
+                None => &any,
+            };
+            seen.push(key);
+
+            j = self.skip_ws(key_end);
+            match self.chars.get(j) {
+                None => return Scan::Partial,
+                Some(':') => {}
+                Some(_) => return Scan::Invalid,
+            }
+
+            j = match self.value(j + 1, value_schema) {
+                Scan::Done(end) => self.skip_ws(end),
+                other => return other,
+            };
+            match self.chars.get(j) {
+                None => return Scan::Partial,
+                Some(',') => {
+                    // A comma with no key left to write could never be closed
+                    if properties.is_some_and(|p| p.len() == seen.len()) {
+                        return Scan::Invalid;
+                    }
+                    j = self.skip_ws(j + 1);
+                }
+                Some('}') => return closes(&seen, j + 1),
+                Some(_) => return Scan::Invalid,
+            }
+        }
+    }
+
+    fn array(&self, i: usize, schema: &Value) -> Scan {
+        let any = json!({});
+        let items = schema.get("items").unwrap_or(&any);
+
+        let mut j = self.skip_ws(i + 1);
+        match self.chars.get(j) {
+            None => return Scan::Partial,
+            Some(']') => return Scan::Done(j + 1),
+            Some(_) => {}
+        }
+
+        loop {
+            j = match self.value(j, items) {
+                Scan::Done(end) => self.skip_ws(end),
+                other => return other,
+            };
+            match self.chars.get(j) {
+                None => return Scan::Partial,
+                Some(',') => j += 1,
+                Some(']') => return Scan::Done(j + 1),
+                Some(_) => return Scan::Invalid,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::ai::backend::BackendKind;
+
+    fn answer_schema() -> OutputSchema {
+        OutputSchema::new(json!({
+            "type": "object",
+            "properties": {
+                "answer": { "type": "string" },
+                "confidence": { "type": "number" },
+                "kind": { "enum": ["fact", "opinion"] }
+            },
+            "required": ["answer", "confidence"]
+        }))
+    }
+
+    #[test]
+    fn test_prefix_scanning_follows_schema() {
+        let schema = answer_schema();
+
+        assert!(schema.accepts_prefix(""));
+        assert!(schema.accepts_prefix("{\"ans"));
+        assert!(schema.accepts_prefix("{\"answer\": \"yes\", \"confidence\": 0."));
+        assert!(schema.accepts_prefix("{\"kind\": \"fa"));
+        assert!(!schema.accepts_prefix("{\"other"));
+        assert!(!schema.accepts_prefix("{\"answer\": 3"));
+        assert!(!schema.accepts_prefix("{\"kind\": \"guess"));
+        // Closing before the required keys are present is not allowed
+        assert!(!schema.accepts_prefix("{\"answer\": \"yes\"}"));
+
+        assert!(!schema.is_complete("{\"answer\": \"yes\", \"confidence\": 0.5"));
+        assert!(schema.is_complete("{\"answer\": \"yes\", \"confidence\": 0.5}"));
+    }
+
+    struct RepairingBackend(AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl Backend for RepairingBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Api
+        }
+
+        async fn generate(&self, prompt: &str, _max_tokens: usize) -> Result<String> {
+            // The first answer is prose; the repair prompt gets valid JSON back
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                Ok("The answer is yes".to_string())
+            } else {
+                assert!(prompt.contains("invalid JSON"));
+                Ok("```json\n{\"answer\": \"yes\", \"confidence\": 0.9}\n```".to_string())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_output_is_repaired() {
+        let backend = RepairingBackend(AtomicUsize::new(0));
+        let schema = answer_schema();
+
+        let value = generate_structured(&backend, "Is it?", &schema, 64, &StructuredOutputConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(value["answer"], "yes");
+        assert_eq!(backend.0.load(Ordering::SeqCst), 2);
+
+        let no_repair = StructuredOutputConfig { repair_attempts: 0 };
+        let backend = RepairingBackend(AtomicUsize::new(0));
+        assert!(generate_structured(&backend, "Is it?", &schema, 64, &no_repair).await.is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+use crate::ai::backend::Backend;
+use crate::ai::segment::{segments, split_sentences, Segment, SegmentKind};
+use crate::ai::tokens::{truncate_to_tokens, TokenCounter};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummarizerConfig {
+    /// Token budget for each map-step chunk
+    pub chunk_tokens: usize,
+    /// Token budget for the combined partial summaries fed to the reduce step
+    pub reduce_budget_tokens: usize,
+    /// Maximum tokens generated per summary
+    pub max_summary_tokens: usize,
+}
+
+impl Default for SummarizerConfig {
+    fn default() -> Self {
+        Self {
+            chunk_tokens: 1024,
+            reduce_budget_tokens: 2048,
+            max_summary_tokens: 256,
+        }
+    }
+}
+
+/// Map-reduce summarizer that chunks by token count and respects block boundaries
+pub struct Summarizer {
+    backend: Arc<dyn Backend>,
+    counter: Arc<dyn TokenCounter>,
+    config: SummarizerConfig,
+}
+
+impl Summarizer {
+    pub fn new(backend: Arc<dyn Backend>, counter: Arc<dyn TokenCounter>, config: SummarizerConfig) -> Self {
+        Self {
+            backend,
+            counter,
+            config,
+        }
+    }
+
+    /// Summarize a document, reducing partial summaries until they fit the budget
+    pub async fn summarize(&self, text: &str) -> Result<String> {
+        let chunks = self.chunk(text);
+        if chunks.is_empty() {
+            return Ok(String::new());
+        }
+
+        let mut summaries = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            summaries.push(self.summarize_chunk(chunk).await?);
+        }
+
+        // Reduce: keep folding partial summaries until they fit in one prompt
+        while summaries.len() > 1 {
+            let combined = summaries.join("\n\n");
+            if self.counter.count(&combined) <= self.config.reduce_budget_tokens {
+                return self.summarize_chunk(&combined).await;
+            }
+
+            let count = summaries.len();
+            let groups = self.pack(
+                summaries.into_iter()
+                    .map(|text| Segment { kind: SegmentKind::Prose, text })
+                    .collect(),
+                self.config.reduce_budget_tokens,
+            );
+
+            let mut next = Vec::with_capacity(groups.len());
+            for group in &groups {
+                next.push(self.summarize_chunk(group).await?);
+            }
+
+            // Nothing merged and nothing got shorter, so another round would loop forever;
+            // cut the summaries to the budget so the final prompt still fits
+            if groups.len() >= count && self.counter.count(&next.join("\n\n")) >= self.counter.count(&combined) {
+                let combined = next.join("\n\n");
+                let fitted = truncate_to_tokens(self.counter.as_ref(), &combined, self.config.reduce_budget_tokens);
+                return self.summarize_chunk(fitted).await;
+            }
+            summaries = next;
+        }
+
+        Ok(summaries.remove(0))
+    }
+
+    async fn summarize_chunk(&self, chunk: &str) -> Result<String> {
+        let prompt = format!("Please summarize the following information:\n\n{}\n\nSummary:", chunk);
+        self.backend.generate(&prompt, self.config.max_summary_tokens).await
+    }
+
+    /// Split text into chunks within the token budget without breaking code blocks or tables
+    pub fn chunk(&self, text: &str) -> Vec<String> {
+        self.pack(segments(text), self.config.chunk_tokens)
+    }
+
+    fn pack(&self, segments: Vec<Segment>, budget: usize) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        let mut current_tokens = 0;
+
+        for segment in segments {
+            let tokens = self.counter.count(&segment.text);
+
+            if tokens > budget && segment.kind == SegmentKind::Prose {
+                // Oversized prose is split on sentence boundaries, rejoined with spaces
+                for (i, sentence) in split_sentences(&segment.text).iter().enumerate() {
+                    let sentence_tokens = self.counter.count(sentence);
+                    if current_tokens + sentence_tokens > budget && !current.is_empty() {
+                        chunks.push(std::mem::take(&mut current));
+                        current_tokens = 0;
+                    }
+                    if !current.is_empty() {
+                        current.push_str(if i == 0 { "\n\n" } else { " " });
+                    }
+                    current.push_str(sentence);
+                    current_tokens += sentence_tokens;
+                }
+                continue;
+            }
+
+            // Code blocks and tables are atomic: an oversized one becomes its own chunk
+            if current_tokens + tokens > budget && !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&segment.text);
+            current_tokens += tokens;
+        }
+
+        if !current.trim().is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::backend::BackendKind;
+    use crate::ai::tokens::HeuristicTokenCounter;
+
+    struct FirstLineBackend;
+
+    #[async_trait::async_trait]
+    impl Backend for FirstLineBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Local
+        }
+
+        async fn generate(&self, prompt: &str, _max_tokens: usize) -> Result<String> {
+            Ok(prompt.lines().nth(2).unwrap_or_default().chars().take(40).collect())
+        }
+    }
+
+    fn summarizer(chunk_tokens: usize) -> Summarizer {
+        Summarizer::new(
+            Arc::new(FirstLineBackend),
+            Arc::new(HeuristicTokenCounter),
+            SummarizerConfig {
+                chunk_tokens,
+                reduce_budget_tokens: 64,
+                max_summary_tokens: 32,
+            },
+        )
+    }
+
+    #[test]
+    fn test_chunks_never_split_code_blocks() {
+        let mut doc = String::new();
+        for i in 0..6 {
+            doc.push_str(&format!("Paragraph {} explains the next step. It has a second sentence.\n\n", i));
+            doc.push_str("```rust\nfn main() {\n\n    let x = 1;\n\n    println!(\"{}\", x);\n}\n```\n\n");
+        }
+
+        let chunks = summarizer(40).chunk(&doc);
+        assert!(chunks.len() > 1);
+
+        for chunk in &chunks {
+            assert_eq!(chunk.matches("```").count() % 2, 0, "chunk splits a code block:\n{}", chunk);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize_reduces_to_single_summary() {
+        let doc = "Some prose about the project. ".repeat(200);
+        let summary = summarizer(50).summarize(&doc).await.unwrap();
+        assert!(!summary.is_empty());
+    }
+
+    #[test]
+    fn test_oversized_prose_keeps_spaces_between_sentences() {
+        let doc = "First sentence here. Second one follows. Third closes it. ".repeat(4);
+        let chunks = summarizer(12).chunk(&doc);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| !chunk.contains(".S") && !chunk.contains(".T") && !chunk.contains(".F")), "{:?}", chunks);
+        assert!(chunks[0].starts_with("First sentence here. Second"));
+    }
+
+    /// Every summary is the same 40 tokens, so two never fit the 64-token reduce budget together
+    #[derive(Default)]
+    struct StubbornBackend {
+        prompts: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Backend for StubbornBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Local
+        }
+
+        async fn generate(&self, prompt: &str, _max_tokens: usize) -> Result<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            Ok("word ".repeat(32))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reduce_prompt_fits_when_summaries_cannot_merge() {
+        let backend = Arc::new(StubbornBackend::default());
+        let config = SummarizerConfig { chunk_tokens: 50, reduce_budget_tokens: 64, max_summary_tokens: 32 };
+        let summary = Summarizer::new(backend.clone(), Arc::new(HeuristicTokenCounter), config.clone())
+            .summarize(&"Some prose about the project. ".repeat(40))
+            .await
+            .unwrap();
+        assert!(!summary.is_empty());
+
+        let overhead = HeuristicTokenCounter.count("Please summarize the following information:\n\n\n\nSummary:");
+        for prompt in backend.prompts.lock().unwrap().iter() {
+            assert!(HeuristicTokenCounter.count(prompt) <= config.reduce_budget_tokens.max(config.chunk_tokens) + overhead + 1);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+use std::sync::Arc;
+
+/// Counts tokens for budgeting prompts and chunks
+pub trait TokenCounter: Send + Sync + std::fmt::Debug {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Character-based estimate used when no tokenizer is loaded (4 chars ≈ 1 token)
+#[derive(Debug, Clone, Default)]
+pub struct HeuristicTokenCounter;
+
+impl TokenCounter for HeuristicTokenCounter {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+/// Exact counts from the model's own tokenizer, without special tokens
+#[cfg(feature = "tokenizer")]
+#[derive(Debug)]
+pub struct TokenizerCounter {
+    tokenizer: tokenizers::Tokenizer,
+}
+
+#[cfg(feature = "tokenizer")]
+impl TokenizerCounter {
+    pub fn new(tokenizer: tokenizers::Tokenizer) -> Self {
+        Self { tokenizer }
+    }
+
+    /// Load a Hugging Face `tokenizer.json`
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let tokenizer = tokenizers::Tokenizer::from_file(path)
+            .map_err(|e| anyhow::anyhow!("Failed to load tokenizer {}: {}", path.display(), e))?;
+        Ok(Self::new(tokenizer))
+    }
+}
+
+#[cfg(feature = "tokenizer")]
+impl TokenCounter for TokenizerCounter {
+    fn count(&self, text: &str) -> usize {
+        match self.tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.len(),
+            Err(_) => HeuristicTokenCounter.count(text),
+        }
+    }
+}
+
+/// The tokenizer at `path` when it loads, otherwise the heuristic
+pub fn token_counter(path: Option<&Path>) -> Arc<dyn TokenCounter> {
+    #[cfg(feature = "tokenizer")]
+    if let Some(path) = path.filter(|p| p.is_file()) {
+        match TokenizerCounter::from_file(path) {
+            Ok(counter) => return Arc::new(counter),
+            Err(e) => tracing::warn!("{}; estimating token counts instead", e),
+        }
+    }
+    #[cfg(not(feature = "tokenizer"))]
+    let _ = path;
+    Arc::new(HeuristicTokenCounter)
+}
+
+/// The longest prefix of `text`, cut on a character boundary, that fits in `max_tokens`
+pub fn truncate_to_tokens<'a>(counter: &dyn TokenCounter, text: &'a str, max_tokens: usize) -> &'a str {
+    if counter.count(text) <= max_tokens {
+        return text;
+    }
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    // Counts grow with the prefix, so binary search for the last boundary that fits
+    let (mut low, mut high) = (0, boundaries.len());
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        let end = boundaries.get(mid).copied().unwrap_or(text.len());
+        if counter.count(&text[..end]) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    &text[..boundaries.get(low).copied().unwrap_or(text.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_to_tokens_keeps_characters_whole() {
+        let text = "héllo wörld, ünïcode";
+        let cut = truncate_to_tokens(&HeuristicTokenCounter, text, 2);
+        assert_eq!(cut, "héllo wö");
+        assert_eq!(truncate_to_tokens(&HeuristicTokenCounter, text, 100), text);
+        assert_eq!(truncate_to_tokens(&HeuristicTokenCounter, text, 0), "");
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn test_tokenizer_counter_matches_known_tokenization() {
+        use std::str::FromStr;
+        // Word-level vocabulary split on whitespace and punctuation, unknown words to [UNK]
+        let json = r#"{
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "BertPreTokenizer" },
+            "post_processor": null,
+            "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "[UNK]": 0, "water": 1, "the": 2, "tomatoes": 3, "!": 4 },
+                "unk_token": "[UNK]"
+            }
+        }"#;
+        let counter = TokenizerCounter::new(tokenizers::Tokenizer::from_str(json).unwrap());
+        let text = "water the tomatoes! 🍅🍅";
+        // water, the, tomatoes, !, 🍅🍅
+        assert_eq!(counter.count(text), 5);
+        assert_ne!(counter.count(text), HeuristicTokenCounter.count(text));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokenizer.json");
+        std::fs::write(&path, json).unwrap();
+        assert_eq!(token_counter(Some(&path)).count(text), 5);
+        assert_eq!(token_counter(Some(&dir.path().join("missing.json"))).count(text), HeuristicTokenCounter.count(text));
+    }
+}
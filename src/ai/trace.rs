@@ -0,0 +1,125 @@
+// src/ai/trace.rs - Per-stage timings for a single query
+use std::future::Future;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tracing::{Instrument, Span};
+
+/// Pipeline stages a query can pass through; only the ones that ran are recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryStage {
+    /// Turning the query text into a vector
+    Embed,
+    /// Scoring passages that have an embedding against the query embedding
+    VectorSearch,
+    /// Keyword scoring for passages (or queries) without embeddings
+    TextSearch,
+    /// Combining and ordering the candidates from both searches
+    Merge,
+    /// MMR selection of the final passages
+    Rerank,
+    /// Assembling the sourced passages into the prompt context
+    Enrich,
+    /// Producing the answer from the prompt with the generation backend
+    Generate,
+    /// Checking the answer's claims against the retrieved passages
+    Verify,
+}
+
+impl QueryStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryStage::Embed => "embed",
+            QueryStage::VectorSearch => "vector_search",
+            QueryStage::TextSearch => "text_search",
+            QueryStage::Merge => "merge",
+            QueryStage::Rerank => "rerank",
+            QueryStage::Enrich => "enrich",
+            QueryStage::Generate => "generate",
+            QueryStage::Verify => "verify",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    pub stage: QueryStage,
+    pub duration: Duration,
+}
+
+/// Stage durations in execution order, also emitted as `stage` spans under a `query` span
+#[derive(Debug, Serialize)]
+pub struct QueryTrace {
+    pub query: String,
+    pub stages: Vec<StageTiming>,
+    #[serde(skip)]
+    started: Instant,
+    #[serde(skip)]
+    span: Span,
+}
+
+impl QueryTrace {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            stages: Vec::new(),
+            started: Instant::now(),
+            span: tracing::info_span!("query", query = %query),
+        }
+    }
+
+    /// Run a synchronous stage and record how long it took
+    pub fn time<T>(&mut self, stage: QueryStage, f: impl FnOnce() -> T) -> T {
+        let span = self.stage_span(stage);
+        let start = Instant::now();
+        let value = span.in_scope(f);
+        self.record(stage, start.elapsed());
+        value
+    }
+
+    /// Await an asynchronous stage inside its span and record how long it took
+    pub async fn time_async<T>(&mut self, stage: QueryStage, future: impl Future<Output = T>) -> T {
+        let span = self.stage_span(stage);
+        let start = Instant::now();
+        let value = future.instrument(span).await;
+        self.record(stage, start.elapsed());
+        value
+    }
+
+    pub fn duration(&self, stage: QueryStage) -> Option<Duration> {
+        self.stages.iter()
+            .filter(|timing| timing.stage == stage)
+            .map(|timing| timing.duration)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Time since the trace started, including work between stages
+    pub fn total(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// One line per stage with its share of the total, slowest stages easy to spot
+    pub fn summary(&self) -> String {
+        let total = self.total();
+        let mut lines = vec![format!("Query trace ({:.2}ms total)", total.as_secs_f64() * 1000.0)];
+        for timing in &self.stages {
+            let share = timing.duration.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON) * 100.0;
+            lines.push(format!(
+                "  {:<14} {:>9.3}ms {:>5.1}%",
+                timing.stage.as_str(),
+                timing.duration.as_secs_f64() * 1000.0,
+                share
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn stage_span(&self, stage: QueryStage) -> Span {
+        tracing::info_span!(parent: &self.span, "stage", stage = stage.as_str())
+    }
+
+    fn record(&mut self, stage: QueryStage, duration: Duration) {
+        tracing::debug!(parent: &self.span, stage = stage.as_str(), duration_us = duration.as_micros() as u64, "query stage finished");
+        self.stages.push(StageTiming { stage, duration });
+    }
+}
@@ -0,0 +1,310 @@
+// src/audio/decode.rs - Decode voice note attachments (Ogg/Opus, m4a, MP3, WAV, FLAC) to mono PCM
+use std::io::Cursor;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecType, DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("Failed to read audio file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Unsupported audio container: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Unsupported audio codec: {0}")]
+    UnsupportedCodec(String),
+
+    #[error("Failed to decode audio: {0}")]
+    Decode(String),
+}
+
+/// Decode an audio file to mono samples at its native sample rate; channels are averaged
+pub fn decode_to_pcm(path: &Path) -> Result<(Vec<f32>, u32), DecodeError> {
+    let bytes = std::fs::read(path)?;
+
+    let mut hint = Hint::new();
+    if let Some(container) = container(path, &bytes) {
+        hint.with_extension(container);
+    }
+    let source = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| DecodeError::UnsupportedFormat(format!("{} ({})", path.display(), e)))?;
+    let format = probed.format;
+
+    let codec = format.tracks().iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .map(|t| t.codec_params.codec)
+        .ok_or_else(|| DecodeError::UnsupportedFormat(format!("{} has no audio track", path.display())))?;
+    if codec == CODEC_TYPE_OPUS {
+        return decode_opus(format);
+    }
+    decode_with_symphonia(format)
+}
+
+/// Container extension from the file's magic bytes, falling back to its extension
+fn container(path: &Path, bytes: &[u8]) -> Option<&'static str> {
+    let sniffed = match bytes {
+        [b'O', b'g', b'g', b'S', ..] => Some("ogg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'f', b'L', b'a', b'C', ..] => Some("flac"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("m4a"),
+        [0xFF, second, ..] if second & 0xF6 == 0xF0 => Some("aac"),
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("mp3"),
+        _ => None,
+    };
+    sniffed.or_else(|| {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "ogg" | "oga" | "opus" => Some("ogg"),
+            "m4a" | "mp4" => Some("m4a"),
+            "aac" => Some("aac"),
+            "mp3" => Some("mp3"),
+            "wav" => Some("wav"),
+            "flac" => Some("flac"),
+            _ => None,
+        }
+    })
+}
+
+fn codec_name(codec: CodecType) -> String {
+    symphonia::default::get_codecs()
+        .get_codec(codec)
+        .map(|descriptor| descriptor.short_name.to_string())
+        .unwrap_or_else(|| match codec {
+            symphonia::core::codecs::CODEC_TYPE_VORBIS => "vorbis".to_string(),
+            symphonia::core::codecs::CODEC_TYPE_ALAC => "alac".to_string(),
+            other => format!("codec {}", other),
+        })
+}
+
+fn decode_with_symphonia(mut format: Box<dyn FormatReader>) -> Result<(Vec<f32>, u32), DecodeError> {
+    let track = format.tracks().iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .expect("checked by the caller");
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|_| DecodeError::UnsupportedCodec(codec_name(track.codec_params.codec)))?;
+
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // Symphonia reports the end of the stream as an unexpected EOF
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(DecodeError::Decode(e.to_string())),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet loses a few milliseconds, not the whole note
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(DecodeError::Decode(e.to_string())),
+        };
+        let spec = *decoded.spec();
+        sample_rate.get_or_insert(spec.rate);
+        let channels = spec.channels.count().max(1);
+        let mut interleaved = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        interleaved.copy_interleaved_ref(decoded);
+        samples.extend(
+            interleaved.samples()
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+    }
+
+    let sample_rate = sample_rate.ok_or_else(|| DecodeError::Decode("Stream has no sample rate".to_string()))?;
+    Ok((samples, sample_rate))
+}
+
+/// Opus packets demuxed by symphonia and decoded by libopus, downmixed to mono at 48 kHz
+#[cfg(feature = "opus")]
+fn decode_opus(mut format: Box<dyn FormatReader>) -> Result<(Vec<f32>, u32), DecodeError> {
+    use audiopus::{coder::Decoder, Channels, SampleRate};
+
+    let track = format.tracks().iter()
+        .find(|t| t.codec_params.codec == CODEC_TYPE_OPUS)
+        .expect("checked by the caller");
+    let track_id = track.id;
+    // Samples the encoder padded the start with
+    let mut pre_skip = track.codec_params.delay.unwrap_or(0) as usize;
+    let mut decoder = Decoder::new(SampleRate::Hz48000, Channels::Mono)
+        .map_err(|e| DecodeError::Decode(e.to_string()))?;
+
+    // The longest Opus packet is 120 ms
+    let mut frame = vec![0.0f32; 5760];
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(DecodeError::Decode(e.to_string())),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = decoder.decode_float(Some(&packet.data[..]), &mut frame[..], false)
+            .map_err(|e| DecodeError::Decode(e.to_string()))?;
+        let skipped = pre_skip.min(decoded);
+        pre_skip -= skipped;
+        samples.extend_from_slice(&frame[skipped..decoded]);
+    }
+
+    Ok((samples, 48_000))
+}
+
+#[cfg(not(feature = "opus"))]
+fn decode_opus(_format: Box<dyn FormatReader>) -> Result<(Vec<f32>, u32), DecodeError> {
+    Err(DecodeError::UnsupportedCodec("opus (build with the `opus` feature to decode it)".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(sample_rate: u32, channels: u16, samples: &[i16]) -> Vec<u8> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    /// One Ogg page per packet
+    fn ogg(packets: &[(Vec<u8>, u64)]) -> Vec<u8> {
+        fn crc(data: &[u8]) -> u32 {
+            data.iter().fold(0u32, |mut crc, &byte| {
+                crc ^= (byte as u32) << 24;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+                }
+                crc
+            })
+        }
+
+        let mut stream = Vec::new();
+        for (sequence, (packet, granule)) in packets.iter().enumerate() {
+            let header_type = match sequence {
+                0 => 0x02,
+                n if n == packets.len() - 1 => 0x04,
+                _ => 0x00,
+            };
+            let mut lacing = vec![255u8; packet.len() / 255];
+            lacing.push((packet.len() % 255) as u8);
+
+            let mut page = b"OggS".to_vec();
+            page.push(0);
+            page.push(header_type);
+            page.extend_from_slice(&granule.to_le_bytes());
+            page.extend_from_slice(&1u32.to_le_bytes());
+            page.extend_from_slice(&(sequence as u32).to_le_bytes());
+            page.extend_from_slice(&0u32.to_le_bytes());
+            page.push(lacing.len() as u8);
+            page.extend_from_slice(&lacing);
+            page.extend_from_slice(packet);
+            let checksum = crc(&page);
+            page[22..26].copy_from_slice(&checksum.to_le_bytes());
+            stream.extend_from_slice(&page);
+        }
+        stream
+    }
+
+    fn opus_head(pre_skip: u16) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.extend_from_slice(&[1, 1]);
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        head
+    }
+
+    fn opus_tags() -> Vec<u8> {
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&4u32.to_le_bytes());
+        tags.extend_from_slice(b"test");
+        tags.extend_from_slice(&0u32.to_le_bytes());
+        tags
+    }
+
+    #[test]
+    fn test_wav_decodes_to_mono_at_native_rate() {
+        let dir = tempfile::tempdir().unwrap();
+        // 1.5 seconds of 8 kHz stereo, named without an extension so the magic bytes decide
+        let path = dir.path().join("voice-note");
+        let samples: Vec<i16> = (0..12_000).flat_map(|_| [8_192, -8_192]).collect();
+        std::fs::write(&path, wav(8_000, 2, &samples)).unwrap();
+
+        let (pcm, sample_rate) = decode_to_pcm(&path).unwrap();
+        assert_eq!(sample_rate, 8_000);
+        assert_eq!(pcm.len(), 12_000);
+        assert!(pcm.iter().all(|s| s.abs() < 1e-6));
+
+        let text = dir.path().join("notes.txt");
+        std::fs::write(&text, "not audio").unwrap();
+        assert!(matches!(decode_to_pcm(&text), Err(DecodeError::UnsupportedFormat(_))));
+    }
+
+    #[cfg(not(feature = "opus"))]
+    #[test]
+    fn test_opus_without_libopus_is_an_unsupported_codec() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("voice.opus");
+        // A silent 20 ms CELT frame after the headers
+        std::fs::write(&path, ogg(&[(opus_head(312), 0), (opus_tags(), 0), (vec![0xF8, 0xFF, 0xFE], 960)])).unwrap();
+
+        match decode_to_pcm(&path) {
+            Err(DecodeError::UnsupportedCodec(codec)) => assert!(codec.starts_with("opus")),
+            other => panic!("expected an unsupported codec, got {:?}", other.map(|(s, r)| (s.len(), r))),
+        }
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn test_opus_voice_note_decodes_to_its_duration() {
+        use audiopus::{coder::Encoder, Application, Channels, SampleRate};
+
+        // Two seconds of a 440 Hz tone in 20 ms packets
+        let encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip).unwrap();
+        let pre_skip = encoder.lookahead().unwrap() as u16;
+        let tone: Vec<f32> = (0..96_000).map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 0.3).collect();
+        let mut packets = vec![(opus_head(pre_skip), 0), (opus_tags(), 0)];
+        for (i, frame) in tone.chunks(960).enumerate() {
+            let mut packet = vec![0u8; 4000];
+            let len = encoder.encode_float(frame, &mut packet).unwrap();
+            packet.truncate(len);
+            packets.push((packet, ((i + 1) * 960) as u64 + pre_skip as u64));
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("voice.opus");
+        std::fs::write(&path, ogg(&packets)).unwrap();
+
+        let (pcm, sample_rate) = decode_to_pcm(&path).unwrap();
+        assert_eq!(sample_rate, 48_000);
+        assert_eq!(pcm.len(), 96_000 - pre_skip as usize);
+    }
+}
@@ -0,0 +1,160 @@
+// src/audio/escalation.rs - Retry low-confidence transcriptions once with a larger model
+use std::path::Path;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::audio::whisper::Whisper;
+use crate::logger::Logger;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptionConfig {
+    /// Model tried first; small and fast
+    pub model: String,
+    /// Larger model used when the first pass fails or scores below `min_confidence`
+    pub escalation_model: Option<String>,
+    pub min_confidence: f32,
+}
+
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        Self {
+            model: Whisper::DEFAULT_MODEL.to_string(),
+            escalation_model: None,
+            min_confidence: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcription {
+    pub text: String,
+    /// Mean token probability in 0..=1
+    pub confidence: f32,
+    /// Model that produced `text`
+    pub model: String,
+    pub escalated: bool,
+}
+
+/// A speech model that reports how sure it is of its output
+#[async_trait]
+pub trait SpeechModel: Send + Sync {
+    fn name(&self) -> &str;
+    /// Text and mean token probability for the audio file at `audio`
+    async fn transcribe_scored(&self, audio: &Path) -> Result<(String, f32)>;
+}
+
+/// Transcribes with the primary model and, at most once per clip, with the escalation model
+pub struct EscalatingTranscriber<P: SpeechModel, E: SpeechModel> {
+    primary: P,
+    escalation: Option<E>,
+    min_confidence: f32,
+    logger: Logger,
+}
+
+impl<P: SpeechModel, E: SpeechModel> EscalatingTranscriber<P, E> {
+    pub fn new(primary: P, escalation: Option<E>, min_confidence: f32) -> Self {
+        Self {
+            primary,
+            escalation,
+            min_confidence,
+            logger: Logger::new("EscalatingTranscriber"),
+        }
+    }
+
+    pub async fn transcribe(&self, audio: &Path) -> Result<Transcription> {
+        let first = self.primary.transcribe_scored(audio).await;
+        let first = match first {
+            Ok((text, confidence)) if confidence >= self.min_confidence => {
+                return Ok(Transcription { text, confidence, model: self.primary.name().to_string(), escalated: false });
+            }
+            other => other,
+        };
+
+        let Some(escalation) = &self.escalation else {
+            let (text, confidence) = first?;
+            return Ok(Transcription { text, confidence, model: self.primary.name().to_string(), escalated: false });
+        };
+
+        match &first {
+            Ok((_, confidence)) => self.logger.info(&format!(
+                "{} confidence {:.2} is below {:.2}; retrying with {}",
+                self.primary.name(), confidence, self.min_confidence, escalation.name()
+            )),
+            Err(e) => self.logger.warn(&format!(
+                "{} failed ({}); retrying with {}", self.primary.name(), e, escalation.name()
+            )),
+        }
+
+        match escalation.transcribe_scored(audio).await {
+            Ok((text, confidence)) => {
+                Ok(Transcription { text, confidence, model: escalation.name().to_string(), escalated: true })
+            }
+            // Keep the weak first pass rather than losing the clip
+            Err(e) => match first {
+                Ok((text, confidence)) => {
+                    self.logger.warn(&format!("{} failed ({}); keeping the first pass", escalation.name(), e));
+                    Ok(Transcription { text, confidence, model: self.primary.name().to_string(), escalated: false })
+                }
+                Err(_) => Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FixedModel {
+        name: &'static str,
+        text: &'static str,
+        confidence: f32,
+        calls: AtomicUsize,
+    }
+
+    impl FixedModel {
+        fn new(name: &'static str, text: &'static str, confidence: f32) -> Self {
+            Self { name, text, confidence, calls: AtomicUsize::new(0) }
+        }
+    }
+
+    #[async_trait]
+    impl SpeechModel for FixedModel {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn transcribe_scored(&self, _audio: &Path) -> Result<(String, f32)> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok((self.text.to_string(), self.confidence))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_low_confidence_pass_escalates_once() {
+        let transcriber = EscalatingTranscriber::new(
+            FixedModel::new("whisper-base", "by the male", 0.3),
+            Some(FixedModel::new("whisper-large", "buy the milk", 0.5)),
+            0.6,
+        );
+
+        // The larger model is also unsure, but there is no second retry
+        let result = transcriber.transcribe(Path::new("voice.ogg")).await.unwrap();
+        assert_eq!(result.text, "buy the milk");
+        assert_eq!(result.model, "whisper-large");
+        assert!(result.escalated);
+        assert_eq!(transcriber.primary.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(transcriber.escalation.as_ref().unwrap().calls.load(Ordering::SeqCst), 1);
+
+        let confident = EscalatingTranscriber::new(
+            FixedModel::new("whisper-base", "buy the milk", 0.9),
+            Some(FixedModel::new("whisper-large", "unused", 0.9)),
+            0.6,
+        );
+        let result = confident.transcribe(Path::new("voice.ogg")).await.unwrap();
+        assert_eq!(result.model, "whisper-base");
+        assert!(!result.escalated);
+        assert_eq!(confident.escalation.as_ref().unwrap().calls.load(Ordering::SeqCst), 0);
+    }
+}
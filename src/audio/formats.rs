@@ -1 +1,156 @@
+// src/audio/formats.rs - Decode voice notes into the mono 16 kHz samples Whisper expects
+use std::ops::Range;
+use anyhow::{Result, Context};
 
+/// Sample rate Whisper models are trained on
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// Mono samples in -1.0..=1.0
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcmAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl PcmAudio {
+    pub fn duration_secs(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+}
+
+/// Decode a WAV file with integer (8 to 32 bit) or 32-bit float samples; channels are averaged
+pub fn decode_wav(bytes: &[u8]) -> Result<PcmAudio> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(anyhow::anyhow!("Not a WAV file"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into()?) as usize;
+        let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let mut tag = u16::from_le_bytes([body[0], body[1]]);
+                // WAVE_FORMAT_EXTENSIBLE keeps the real format at the start of the subformat GUID
+                if tag == 0xFFFE && body.len() >= 26 {
+                    tag = u16::from_le_bytes([body[24], body[25]]);
+                }
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into()?);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                format = Some((tag, channels, sample_rate, bits));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        offset += 8 + size + (size & 1);
+    }
+
+    let (tag, channels, sample_rate, bits) = format.context("WAV file has no format chunk")?;
+    let data = data.context("WAV file has no data chunk")?;
+    if channels == 0 || sample_rate == 0 {
+        return Err(anyhow::anyhow!("WAV file has no channels"));
+    }
+
+    let width = (bits as usize).div_ceil(8);
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        _ => return Err(anyhow::anyhow!("Unsupported WAV encoding (format {}, {} bits)", tag, bits)),
+    };
+
+    let frame = width * channels as usize;
+    let samples = data.chunks_exact(frame)
+        .map(|frame| frame.chunks_exact(width).map(decode).sum::<f32>() / channels as f32)
+        .collect();
+    Ok(PcmAudio { samples, sample_rate })
+}
+
+/// Linear-interpolation resampling; good enough for speech going into Whisper
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let len = ((samples.len() as f64) / ratio).round() as usize;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position.floor() as usize;
+            let fraction = (position - index as f64) as f32;
+            let current = samples[index.min(samples.len() - 1)];
+            let next = samples[(index + 1).min(samples.len() - 1)];
+            current + (next - current) * fraction
+        })
+        .collect()
+}
+
+/// Windows of at most `chunk_secs` covering the audio, each starting `overlap_secs` before
+/// the previous one ends so words on a boundary are heard whole by one of them
+pub fn chunk_ranges(len: usize, sample_rate: u32, chunk_secs: f32, overlap_secs: f32) -> Vec<Range<usize>> {
+    let chunk = ((chunk_secs * sample_rate as f32) as usize).max(1);
+    let overlap = ((overlap_secs * sample_rate as f32) as usize).min(chunk - 1);
+    let step = chunk - overlap;
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + chunk).min(len);
+        ranges.push(start..end);
+        if end >= len {
+            break;
+        }
+        start += step;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(sample_rate: u32, channels: u16, samples: &[i16]) -> Vec<u8> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    #[test]
+    fn test_stereo_48k_voice_note_becomes_chunked_16k_mono() {
+        // 70 seconds of stereo at 48 kHz, the left channel louder than the right
+        let frames = 48_000 * 70;
+        let samples: Vec<i16> = (0..frames).flat_map(|_| [16_384, 0]).collect();
+        let audio = decode_wav(&wav(48_000, 2, &samples)).unwrap();
+        assert_eq!(audio.sample_rate, 48_000);
+        assert_eq!(audio.samples.len(), frames);
+        assert!((audio.samples[0] - 0.25).abs() < 1e-6);
+
+        let resampled = resample(&audio.samples, audio.sample_rate, WHISPER_SAMPLE_RATE);
+        assert_eq!(resampled.len(), 16_000 * 70);
+
+        let chunks = chunk_ranges(resampled.len(), WHISPER_SAMPLE_RATE, 30.0, 2.0);
+        assert_eq!(chunks, [0..480_000, 448_000..928_000, 896_000..1_120_000]);
+        assert!(decode_wav(b"not audio").is_err());
+    }
+}
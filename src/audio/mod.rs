@@ -1 +1,7 @@
-// TODO: implement this file
+pub mod decode;
+pub mod escalation;
+pub mod formats;
+pub mod transcript;
+pub mod whisper;
+#[cfg(feature = "embeddings")]
+pub mod transcriber;
@@ -0,0 +1,376 @@
+// src/audio/transcriber.rs - Candle Whisper inference over decoded voice notes
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use candle_core::{Device, IndexOp, Tensor, D};
+use candle_nn::{ops::softmax, VarBuilder};
+use candle_transformers::models::whisper::{self as m, audio, Config};
+use tokenizers::Tokenizer;
+use crate::audio::decode::decode_to_pcm;
+use crate::ai::model_registry::ModelRegistry;
+use crate::audio::escalation::{EscalatingTranscriber, SpeechModel, TranscriptionConfig};
+use crate::audio::formats::{self, WHISPER_SAMPLE_RATE};
+use crate::audio::transcript::{Transcript, TranscriptSegment};
+use crate::audio::whisper::Whisper;
+use crate::logger::Logger;
+
+/// Whisper hears 30 second windows; longer audio is split into chunks overlapping by this much
+const CHUNK_SECS: f32 = 30.0;
+const OVERLAP_SECS: f32 = 2.0;
+/// Each timestamp token is 20 ms after the previous one
+const SECS_PER_TIMESTAMP: f32 = 0.02;
+
+/// Languages in the order of their tokens in the multilingual vocabulary
+const LANGUAGES: &[&str] = &[
+    "en", "zh", "de", "es", "ru", "ko", "fr", "ja", "pt", "tr", "pl", "ca", "nl", "ar", "sv", "it",
+    "id", "hi", "fi", "vi", "he", "uk", "el", "ms", "cs", "ro", "da", "hu", "ta", "no", "th", "ur",
+    "hr", "bg", "lt", "la", "mi", "ml", "cy", "sk", "te", "fa", "lv", "bn", "sr", "az", "sl", "kn",
+    "et", "mk", "br", "eu", "is", "hy", "ne", "mn", "bs", "kk", "sq", "sw", "gl", "mr", "pa", "si",
+    "km", "sn", "yo", "so", "af", "oc", "ka", "be", "tg", "sd", "gu", "am", "yi", "lo", "uz", "fo",
+    "ht", "ps", "tk", "nn", "mt", "sa", "lb", "my", "bo", "tl", "mg", "as", "tt", "haw", "ln", "ha",
+    "ba", "jw", "su",
+];
+
+/// Special tokens looked up once from the tokenizer
+struct SpecialTokens {
+    start_of_transcript: u32,
+    transcribe: u32,
+    end_of_text: u32,
+    no_speech: Option<u32>,
+    /// `<|0.00|>`; every id from here up is a timestamp
+    timestamp_begin: u32,
+}
+
+/// Loads a Whisper checkpoint (`config.json`, `tokenizer.json`, `model.safetensors`) and
+/// transcribes mono samples at any sample rate
+pub struct AudioTranscriber {
+    name: String,
+    model: Mutex<m::model::Whisper>,
+    config: Config,
+    tokenizer: Tokenizer,
+    tokens: SpecialTokens,
+    mel_filters: Vec<f32>,
+    device: Device,
+    logger: Logger,
+}
+
+impl AudioTranscriber {
+    /// Load from a directory written by `models download whisper-base`
+    pub fn load(model_dir: &Path) -> Result<Self> {
+        let device = Device::Cpu;
+        let config: Config = serde_json::from_str(
+            &std::fs::read_to_string(model_dir.join("config.json"))
+                .with_context(|| format!("No Whisper config in {}", model_dir.display()))?,
+        )?;
+        let tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+            .map_err(|e| anyhow::anyhow!("Failed to load Whisper tokenizer: {}", e))?;
+        let vb = unsafe {
+            VarBuilder::from_mmaped_safetensors(&[model_dir.join("model.safetensors")], m::DTYPE, &device)?
+        };
+        let model = m::model::Whisper::load(&vb, config.clone())?;
+        let name = model_dir.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| Whisper::DEFAULT_MODEL.to_string());
+        Self::new(&name, model, config, tokenizer)
+    }
+
+    pub fn new(name: &str, model: m::model::Whisper, config: Config, tokenizer: Tokenizer) -> Result<Self> {
+        let device = Device::Cpu;
+        let token = |text: &str| -> Result<u32> {
+            tokenizer.token_to_id(text).with_context(|| format!("Tokenizer has no {} token", text))
+        };
+        let tokens = SpecialTokens {
+            start_of_transcript: token(m::SOT_TOKEN)?,
+            transcribe: token(m::TRANSCRIBE_TOKEN)?,
+            end_of_text: token(m::EOT_TOKEN)?,
+            no_speech: m::NO_SPEECH_TOKENS.iter().find_map(|t| tokenizer.token_to_id(t)),
+            timestamp_begin: token("<|0.00|>")?,
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            mel_filters: mel_filters(config.num_mel_bins, m::N_FFT, WHISPER_SAMPLE_RATE),
+            model: Mutex::new(model),
+            config,
+            tokenizer,
+            tokens,
+            device,
+            logger: Logger::new("AudioTranscriber"),
+        })
+    }
+
+    /// Transcribe mono samples, resampled to 16 kHz and split into overlapping 30 second chunks
+    pub fn transcribe(&self, samples: &[f32], sample_rate: u32) -> Result<Transcript> {
+        Ok(self.transcribe_scored(samples, sample_rate)?.0)
+    }
+
+    /// The transcript and the mean probability of its tokens
+    fn transcribe_scored(&self, samples: &[f32], sample_rate: u32) -> Result<(Transcript, f32)> {
+        let samples = formats::resample(samples, sample_rate, WHISPER_SAMPLE_RATE);
+        let mut model = self.model.lock().unwrap();
+
+        let mut language = None;
+        let mut chunks = Vec::new();
+        let mut probabilities = Vec::new();
+        for range in formats::chunk_ranges(samples.len(), WHISPER_SAMPLE_RATE, CHUNK_SECS, OVERLAP_SECS) {
+            let offset = range.start as f32 / WHISPER_SAMPLE_RATE as f32;
+            let features = self.encode(&mut model, &samples[range])?;
+            // Detected on the first chunk and kept, so a mixed-language note isn't split up
+            if language.is_none() {
+                language = Some(self.detect_language(&mut model, &features)?);
+            }
+            let (code, language_token) = language.clone().unwrap();
+            let (segments, chunk_probabilities) = self.decode(&mut model, &features, language_token)?;
+            self.logger.debug(&format!("Chunk at {:.1}s ({}): {} segments", offset, code, segments.len()));
+            chunks.push((offset, segments));
+            probabilities.extend(chunk_probabilities);
+        }
+
+        let confidence = if probabilities.is_empty() {
+            0.0
+        } else {
+            probabilities.iter().sum::<f32>() / probabilities.len() as f32
+        };
+        Ok((Transcript::from_chunks(chunks, language.map(|(code, _)| code)), confidence))
+    }
+
+    fn encode(&self, model: &mut m::model::Whisper, samples: &[f32]) -> Result<Tensor> {
+        let mel = audio::pcm_to_mel(&self.config, samples, &self.mel_filters);
+        let frames = mel.len() / self.config.num_mel_bins;
+        let mel = Tensor::from_vec(mel, (1, self.config.num_mel_bins, frames), &self.device)?;
+        // The spectrogram comes back padded past the 30 seconds the encoder has positions for
+        let mel = mel.narrow(2, 0, frames.min(m::N_FRAMES))?;
+        Ok(model.encoder.forward(&mel, true)?)
+    }
+
+    /// Most likely language after the start-of-transcript token
+    fn detect_language(&self, model: &mut m::model::Whisper, features: &Tensor) -> Result<(String, u32)> {
+        let candidates: Vec<(&str, u32)> = LANGUAGES.iter()
+            .filter_map(|code| Some((*code, self.tokenizer.token_to_id(&format!("<|{}|>", code))?)))
+            .collect();
+        // English-only checkpoints have no language tokens
+        if candidates.is_empty() {
+            return Err(anyhow::anyhow!("Whisper model has no language tokens"));
+        }
+
+        let tokens = Tensor::new(&[[self.tokens.start_of_transcript]], &self.device)?;
+        let hidden = model.decoder.forward(&tokens, features, true)?;
+        let logits = model.decoder.final_linear(&hidden.i(..1)?)?.i(0)?.i(0)?;
+        let ids: Vec<u32> = candidates.iter().map(|(_, id)| *id).collect();
+        let language_logits = logits.index_select(&Tensor::new(ids.as_slice(), &self.device)?, 0)?;
+        let probabilities: Vec<f32> = softmax(&language_logits, D::Minus1)?.to_vec1()?;
+
+        let best = probabilities.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        Ok((candidates[best].0.to_string(), candidates[best].1))
+    }
+
+    /// Greedy decoding with timestamps; returns segments timed from the chunk's start and the
+    /// probability of every text token
+    fn decode(&self, model: &mut m::model::Whisper, features: &Tensor, language: u32) -> Result<(Vec<TranscriptSegment>, Vec<f32>)> {
+        let mut tokens = vec![self.tokens.start_of_transcript, language, self.tokens.transcribe];
+        let prompt_len = tokens.len();
+        let mut probabilities = Vec::new();
+        let max_tokens = self.config.max_target_positions / 2;
+
+        for step in 0..max_tokens {
+            let input = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+            let hidden = model.decoder.forward(&input, features, step == 0)?;
+            let (_, seq_len, _) = hidden.dims3()?;
+            let logits = model.decoder.final_linear(&hidden.i((..1, seq_len - 1..))?)?.i(0)?.i(0)?;
+
+            // Silence: the model predicts no speech instead of a first token
+            if step == 0 {
+                if let Some(no_speech) = self.tokens.no_speech {
+                    let first: Vec<f32> = softmax(&logits, D::Minus1)?.to_vec1()?;
+                    if first[no_speech as usize] > m::NO_SPEECH_THRESHOLD as f32 {
+                        return Ok((Vec::new(), Vec::new()));
+                    }
+                }
+            }
+
+            let distribution: Vec<f32> = softmax(&logits, D::Minus1)?.to_vec1()?;
+            let next = distribution.iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(i, _)| i as u32)
+                .unwrap_or(self.tokens.end_of_text);
+            if next == self.tokens.end_of_text {
+                break;
+            }
+            if next < self.tokens.timestamp_begin {
+                probabilities.push(distribution[next as usize]);
+            }
+            tokens.push(next);
+        }
+
+        Ok((self.segments(&tokens[prompt_len..])?, probabilities))
+    }
+
+    /// Split decoded tokens into segments at timestamp tokens
+    fn segments(&self, tokens: &[u32]) -> Result<Vec<TranscriptSegment>> {
+        let mut segments = Vec::new();
+        let mut start = 0.0;
+        let mut text_tokens: Vec<u32> = Vec::new();
+        for &token in tokens {
+            if token < self.tokens.timestamp_begin {
+                text_tokens.push(token);
+                continue;
+            }
+            let time = (token - self.tokens.timestamp_begin) as f32 * SECS_PER_TIMESTAMP;
+            if !text_tokens.is_empty() {
+                segments.push(TranscriptSegment { start, end: time, text: self.detokenize(&text_tokens)? });
+                text_tokens.clear();
+            }
+            start = time;
+        }
+        // Output cut off before a closing timestamp runs to the end of the chunk
+        if !text_tokens.is_empty() {
+            segments.push(TranscriptSegment { start, end: CHUNK_SECS, text: self.detokenize(&text_tokens)? });
+        }
+        Ok(segments)
+    }
+
+    fn detokenize(&self, tokens: &[u32]) -> Result<String> {
+        self.tokenizer.decode(tokens, true)
+            .map_err(|e| anyhow::anyhow!("Failed to decode Whisper tokens: {}", e))
+    }
+}
+
+/// Shared so inference can move onto a blocking thread
+#[async_trait]
+impl SpeechModel for Arc<AudioTranscriber> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn transcribe_scored(&self, audio: &Path) -> Result<(String, f32)> {
+        let transcriber = Arc::clone(self);
+        let audio = audio.to_path_buf();
+        let (transcript, confidence) = tokio::task::spawn_blocking(move || {
+            let (samples, sample_rate) = decode_to_pcm(&audio)?;
+            AudioTranscriber::transcribe_scored(&transcriber, &samples, sample_rate)
+        }).await??;
+        Ok((transcript.text, confidence))
+    }
+}
+
+impl EscalatingTranscriber<Arc<AudioTranscriber>, Arc<AudioTranscriber>> {
+    /// The configured models, from the folders `models download` writes under `model_path`
+    pub fn load(model_path: &Path, config: &TranscriptionConfig) -> Result<Self> {
+        let load = |name: &str| -> Result<Arc<AudioTranscriber>> {
+            let repo = Whisper::repo_for(name).with_context(|| format!("{} is not a Whisper model", name))?;
+            Ok(Arc::new(AudioTranscriber::load(&model_path.join(ModelRegistry::name_for(&repo)))?))
+        };
+        let escalation = config.escalation_model.as_deref().map(load).transpose()?;
+        Ok(Self::new(load(&config.model)?, escalation, config.min_confidence))
+    }
+}
+
+/// Slaney-scale triangular mel filters over the positive FFT bins, as Whisper's preprocessing
+/// computes them (librosa's defaults)
+fn mel_filters(n_mels: usize, n_fft: usize, sample_rate: u32) -> Vec<f32> {
+    let hz_to_mel = |hz: f64| -> f64 {
+        let (min_log_hz, min_log_mel, log_step) = (1000.0, 15.0, (6.4f64).ln() / 27.0);
+        if hz < min_log_hz { hz * 3.0 / 200.0 } else { min_log_mel + (hz / min_log_hz).ln() / log_step }
+    };
+    let mel_to_hz = |mel: f64| -> f64 {
+        let (min_log_hz, min_log_mel, log_step) = (1000.0, 15.0, (6.4f64).ln() / 27.0);
+        if mel < min_log_mel { mel * 200.0 / 3.0 } else { min_log_hz * ((mel - min_log_mel) * log_step).exp() }
+    };
+
+    let bins = n_fft / 2 + 1;
+    let nyquist = sample_rate as f64 / 2.0;
+    let fft_freqs: Vec<f64> = (0..bins).map(|i| i as f64 * nyquist / (bins - 1) as f64).collect();
+    let max_mel = hz_to_mel(nyquist);
+    let mel_points: Vec<f64> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(max_mel * i as f64 / (n_mels + 1) as f64))
+        .collect();
+
+    let mut filters = vec![0.0f32; n_mels * bins];
+    for mel in 0..n_mels {
+        let (lower, center, upper) = (mel_points[mel], mel_points[mel + 1], mel_points[mel + 2]);
+        // Slaney normalization keeps each filter's area constant
+        let norm = 2.0 / (upper - lower);
+        for (bin, &freq) in fft_freqs.iter().enumerate() {
+            let rising = (freq - lower) / (center - lower);
+            let falling = (upper - freq) / (upper - center);
+            filters[mel * bins + bin] = (rising.min(falling).max(0.0) * norm) as f32;
+        }
+    }
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use candle_nn::VarMap;
+
+    /// Whisper's layout at a toy size, randomly initialized except for zeroed token embeddings:
+    /// every logit is then equal and greedy decoding settles on the last vocabulary entry
+    fn untrained_transcriber(name: &str) -> AudioTranscriber {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "num_mel_bins": 80, "max_source_positions": 1500, "d_model": 8,
+            "encoder_attention_heads": 2, "encoder_layers": 1, "vocab_size": 6,
+            "max_target_positions": 16, "decoder_attention_heads": 2, "decoder_layers": 1
+        })).unwrap();
+        let mut weights = VarMap::new();
+        let model = m::model::Whisper::load(&VarBuilder::from_varmap(&weights, m::DTYPE, &Device::Cpu), config.clone()).unwrap();
+        weights.set_one("model.decoder.embed_tokens.weight", Tensor::zeros((6, 8), m::DTYPE, &Device::Cpu).unwrap()).unwrap();
+        let tokenizer = Tokenizer::from_str(r#"{
+            "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+            "normalizer": null, "pre_tokenizer": null, "post_processor": null, "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": {
+                    "<|endoftext|>": 0, "<|startoftranscript|>": 1, "<|en|>": 2, "<|transcribe|>": 3,
+                    "tomatoes": 4, "water": 5, "<|0.00|>": 6
+                },
+                "unk_token": "<|endoftext|>"
+            }
+        }"#).unwrap();
+        AudioTranscriber::new(name, model, config, tokenizer).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transcribes_bundled_voice_note() {
+        // Half a second of 8 kHz speech-band tone, so resampling to 16 kHz is exercised too
+        let wav = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/voice-note.wav");
+        let transcriber = Arc::new(untrained_transcriber("whisper-base"));
+
+        let (text, confidence) = transcriber.transcribe_scored(&wav).await.unwrap();
+        assert!(text.starts_with("water"));
+        assert!(confidence > 0.0 && confidence <= 1.0);
+
+        let (samples, sample_rate) = decode_to_pcm(&wav).unwrap();
+        let transcript = transcriber.transcribe(&samples, sample_rate).unwrap();
+        assert_eq!(transcript.text, text);
+        assert_eq!(transcript.language.as_deref(), Some("en"));
+        assert!(!transcript.segments.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsure_small_model_escalates_to_the_large_one() {
+        let wav = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/voice-note.wav");
+        // Equal logits over six tokens put every untrained pass at about 1/6 confidence
+        let transcriber = EscalatingTranscriber::new(
+            Arc::new(untrained_transcriber("whisper-base")),
+            Some(Arc::new(untrained_transcriber("whisper-large-v3"))),
+            0.6,
+        );
+
+        let result = transcriber.transcribe(&wav).await.unwrap();
+        assert_eq!(result.model, "whisper-large-v3");
+        assert!(result.escalated);
+        assert!(result.confidence < 0.6);
+        assert!(result.text.starts_with("water"));
+
+        let missing = TranscriptionConfig { model: "whisper-base".to_string(), ..TranscriptionConfig::default() };
+        assert!(EscalatingTranscriber::load(Path::new("/nonexistent"), &missing).is_err());
+    }
+}
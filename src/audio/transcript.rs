@@ -0,0 +1,66 @@
+// src/audio/transcript.rs - Timestamped transcripts assembled from overlapping audio chunks
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    /// Seconds from the start of the audio
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub text: String,
+    /// Language code Whisper detected, e.g. `en`
+    pub language: Option<String>,
+    pub segments: Vec<TranscriptSegment>,
+}
+
+impl Transcript {
+    /// Combine the segments of consecutive chunks, each timed from its chunk's start at
+    /// `offset` seconds. Speech in an overlap is transcribed twice, so a segment is only kept
+    /// if most of it comes after what the kept segments already cover.
+    pub fn from_chunks(chunks: Vec<(f32, Vec<TranscriptSegment>)>, language: Option<String>) -> Self {
+        let mut segments: Vec<TranscriptSegment> = Vec::new();
+        let mut covered = 0.0f32;
+        for (offset, chunk) in chunks {
+            for segment in chunk {
+                let (start, end) = (offset + segment.start, offset + segment.end);
+                let text = segment.text.trim();
+                if text.is_empty() || (start + end) / 2.0 < covered {
+                    continue;
+                }
+                covered = end;
+                segments.push(TranscriptSegment { start, end, text: text.to_string() });
+            }
+        }
+
+        let text = segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" ");
+        Self { text, language, segments }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f32, end: f32, text: &str) -> TranscriptSegment {
+        TranscriptSegment { start, end, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_overlapping_chunks_do_not_repeat_speech() {
+        let transcript = Transcript::from_chunks(
+            vec![
+                (0.0, vec![segment(0.0, 12.0, "Water the tomatoes."), segment(12.0, 29.5, "Then feed the basil.")]),
+                // The second chunk starts two seconds before the first ends and hears its last words again
+                (28.0, vec![segment(0.0, 1.5, "the basil."), segment(1.5, 9.0, " Harvest on Sunday. ")]),
+            ],
+            Some("en".to_string()),
+        );
+
+        assert_eq!(transcript.text, "Water the tomatoes. Then feed the basil. Harvest on Sunday.");
+        assert_eq!(transcript.segments.last().unwrap(), &segment(29.5, 37.0, "Harvest on Sunday."));
+    }
+}
@@ -1,14 +1,14 @@
-use crate::Result;
-
+/// Names and files of the Whisper checkpoints `AudioTranscriber` loads
 pub struct Whisper;
 
 impl Whisper {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
-    }
+    pub const DEFAULT_MODEL: &'static str = "whisper-base";
+    /// What `models download` fetches for a Whisper checkpoint
+    pub const MODEL_FILES: [&'static str; 3] = ["config.json", "tokenizer.json", "model.safetensors"];
     
-    pub async fn transcribe_audio(&self, audio_data: &[u8]) -> Result<String> {
-        // TODO: Implement Candle-based Whisper
-        Ok("Whisper transcription".to_string())
+    /// Hugging Face repo for a model name like `whisper-base`, or `None` if it isn't Whisper
+    pub fn repo_for(name: &str) -> Option<String> {
+        let name = name.strip_prefix("openai/").unwrap_or(name);
+        name.starts_with("whisper-").then(|| format!("openai/{}", name))
     }
 }
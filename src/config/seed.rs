@@ -0,0 +1,48 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that overrides the configured seed
+pub const SEED_ENV: &str = "NOTE_TO_AI_SEED";
+
+/// Crate-wide RNG seed. Unset in production for true randomness; set it to make
+/// sampling, clustering, and tie-breaking reproducible across runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RngSeed(pub Option<u64>);
+
+impl RngSeed {
+    /// The configured seed, overridden by `NOTE_TO_AI_SEED` when it holds a number
+    pub fn resolve(configured: Option<u64>) -> Self {
+        let from_env = std::env::var(SEED_ENV).ok().and_then(|v| v.trim().parse().ok());
+        Self(from_env.or(configured))
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Independent generator for one component, so adding draws in one place
+    /// does not shift the sequence seen by another
+    pub fn rng(&self, component: &str) -> StdRng {
+        match self.0 {
+            Some(seed) => StdRng::seed_from_u64(seed ^ Self::component_key(component)),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Seed value for libraries that take a raw `u64` (e.g. candle's LogitsProcessor)
+    pub fn value_for(&self, component: &str) -> u64 {
+        match self.0 {
+            Some(seed) => seed ^ Self::component_key(component),
+            None => rand::random(),
+        }
+    }
+
+    fn component_key(component: &str) -> u64 {
+        let hash = blake3::hash(component.as_bytes());
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&hash.as_bytes()[..8]);
+        u64::from_le_bytes(bytes)
+    }
+}
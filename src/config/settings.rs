@@ -1,5 +1,48 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use crate::ai::answer_cache::AnswerCacheConfig;
+use crate::ai::api_client::ApiConfig;
+use crate::ai::chat_template::ChatTemplate;
+use crate::ai::grounding::GroundingConfig;
+use crate::ai::hermes_integration::HermesConfig;
+use crate::ai::query_queue::QueryQueueConfig;
+use crate::ai::backend::FallbackConfig;
+use crate::ai::QueryMode;
+use crate::ai::context::RetrievalConfig;
+use crate::ai::quantize::QuantizationConfig;
+use crate::ai::summarizer::SummarizerConfig;
+use crate::audio::escalation::TranscriptionConfig;
+use crate::audio::whisper::Whisper;
+use crate::crypto::EncryptionConfig;
+use crate::ai::structured::StructuredOutputConfig;
+use crate::config::seed::RngSeed;
+use crate::signal_integration::backfill::BackfillConfig;
+use crate::signal_integration::daemon::SignalDaemonConfig;
+use crate::signal_integration::reply::ReplyConfig;
+use crate::signal_integration::MessageFilterConfig;
+use crate::vault::capture_dedup::CaptureDedupConfig;
+use crate::vault::categorize::CategorizationConfig;
+use crate::vault::parsers::ParserConfig;
+use crate::vault::moc::MocConfig;
+use crate::vault::pii::PiiConfig;
+use crate::vault::hierarchical::HierarchicalEmbeddingConfig;
+use crate::vault::hnsw::DistanceMetric;
+use crate::vault::language::LanguageConfig;
+use crate::vault::lock::LockConfig;
+use crate::vault::search::{BlockChangeDetection, DuplicateLinkTargets, SourceWeights};
+use crate::vault::search_note::SearchNoteConfig;
+use crate::vault::embedding_pool::EmbeddingPoolConfig;
+use crate::vault::embeddings::EmbeddingModelConfig;
+use crate::vault::ingest::IngestConfig;
+use crate::vault::parser::LinkResolutionConfig;
+use crate::vault::related::RelatedLinksConfig;
+use crate::vault::qa::QaExtractionConfig;
+use crate::vault::refresh::EmbeddingRefreshConfig;
+use crate::vault::tag_graph::TagGraphConfig;
+use crate::vault::vacuum::VacuumConfig;
+use crate::vault::warmup::WarmupConfig;
+use crate::scheduler::tasks::SchedulerConfig;
+use crate::webhooks::WebhookConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -10,6 +53,78 @@ pub struct Settings {
     pub swarm: SwarmConfig,
     pub signal: SignalConfig,
     pub database: DatabaseConfig,
+    /// RNG seed for reproducible runs; also read from `NOTE_TO_AI_SEED`
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    /// Background re-indexing
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub storage: StorageSettings,
+}
+
+/// Hybrid DuckDB/Lance storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageSettings {
+    pub base_path: PathBuf,
+    #[serde(default)]
+    pub lance: LanceSettings,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        Self {
+            base_path: PathBuf::from("./storage"),
+            lance: LanceSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanceSettings {
+    /// Must match the embedding model's `dimensions`
+    pub vector_dimension: usize,
+    /// Vectors appended since the last index build that trigger a background update of the
+    /// search engine's HNSW graph
+    #[serde(default = "default_index_rebuild_threshold")]
+    pub index_rebuild_threshold: usize,
+}
+
+fn default_index_rebuild_threshold() -> usize {
+    1000
+}
+
+impl Default for LanceSettings {
+    fn default() -> Self {
+        Self {
+            vector_dimension: 384,
+            index_rebuild_threshold: default_index_rebuild_threshold(),
+        }
+    }
+}
+
+/// A setting that can't work, named by its dotted path, e.g. `storage.lance.vector_dimension`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error(transparent)]
+    Load(#[from] config::ConfigError),
+
+    #[error("Invalid configuration: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Invalid(Vec<FieldError>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +139,73 @@ pub struct VaultConfig {
     pub auto_sync: bool,
     pub index_interval: u64,
     pub cache_size: usize,
+    #[serde(default)]
+    pub links: LinkResolutionConfig,
+    #[serde(default)]
+    pub embedding: EmbeddingModelConfig,
+    #[serde(default)]
+    pub embedding_pool: EmbeddingPoolConfig,
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+    /// Where and how `/save <url>` clippings are written
+    #[serde(default)]
+    pub ingest: IngestConfig,
+    #[serde(default)]
+    pub related: RelatedLinksConfig,
+    /// Near-duplicate voice captures are appended to or linked with the earlier note
+    #[serde(default)]
+    pub capture_dedup: CaptureDedupConfig,
+    #[serde(default)]
+    pub categorization: CategorizationConfig,
+    /// Extra file extensions mapped to the built-in parsers
+    #[serde(default)]
+    pub parsers: ParserConfig,
+    /// Auto-maintained map-of-content notes for selected tags
+    #[serde(default)]
+    pub moc: MocConfig,
+    /// Tag notes containing emails, phone or card numbers while they're parsed
+    #[serde(default)]
+    pub pii: PiiConfig,
+    /// Per-language full-text analyzers and an optional multilingual embedding model
+    #[serde(default)]
+    pub languages: LanguageConfig,
+    /// Where `query --save-as` writes its result notes and how many results they list
+    #[serde(default)]
+    pub search_notes: SearchNoteConfig,
+    /// Whether a storage lock left by a crashed process is taken over on startup
+    #[serde(default)]
+    pub lock: LockConfig,
+    /// Section embeddings and a summary-of-sections document vector for long notes
+    #[serde(default)]
+    pub hierarchical_embeddings: HierarchicalEmbeddingConfig,
+    /// Edge thresholds for `export --tag-graph`
+    #[serde(default)]
+    pub tag_graph: TagGraphConfig,
+    /// Question/answer pairs extracted from notes for direct answers
+    #[serde(default)]
+    pub qa: QaExtractionConfig,
+    /// Search score multipliers by source type, e.g. `web_save = 0.8`; unset types keep 1.0
+    #[serde(default)]
+    pub source_weights: SourceWeights,
+    /// Bytes of note text shown in each search result preview
+    #[serde(default = "default_snippet_length")]
+    pub snippet_length: usize,
+    /// Re-embedding of notes stored under an older `embedding.version`
+    #[serde(default)]
+    pub embedding_refresh: EmbeddingRefreshConfig,
+    /// How a wikilink matching several notes counts toward backlinks
+    #[serde(default)]
+    pub duplicate_link_targets: DuplicateLinkTargets,
+    /// Whether re-indexing keeps stored block vectors whose content is unchanged
+    #[serde(default)]
+    pub block_change_detection: BlockChangeDetection,
+    /// How query and note vectors are compared; `cosine` unless the embedding model calls for `l2` or `dot`
+    #[serde(default)]
+    pub distance_metric: DistanceMetric,
+}
+
+fn default_snippet_length() -> usize {
+    200
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +214,55 @@ pub struct AIConfig {
     pub embeddings_path: PathBuf,
     pub context_window: usize,
     pub model_registry: PathBuf,
+    /// When the local model fails in one of these ways, the query is retried on `api`
+    #[serde(default)]
+    pub fallback: FallbackConfig,
+    /// Remote chat completions endpoint; also answers alone when no local model can be loaded
+    #[serde(default)]
+    pub api: Option<ApiConfig>,
+    /// Prompt format of the local model; guessed from the model's name when unset
+    #[serde(default)]
+    pub chat_template: Option<ChatTemplate>,
+    /// Chat endpoint that answers messages in the Signal conversation, keeping per-conversation history
+    #[serde(default)]
+    pub hermes: Option<HermesConfig>,
+    /// Local generations decoded at once, each with its own KV cache; more trades memory for throughput
+    #[serde(default = "default_max_concurrent_generations")]
+    pub max_concurrent_generations: usize,
+    #[serde(default)]
+    pub summarizer: SummarizerConfig,
+    /// Interactive generation stops after this many milliseconds with a partial answer
+    #[serde(default)]
+    pub time_budget_ms: Option<u64>,
+    /// Default for queries; `context_only` returns passages without calling the LLM
+    #[serde(default)]
+    pub query_mode: QueryMode,
+    #[serde(default)]
+    pub structured_output: StructuredOutputConfig,
+    #[serde(default)]
+    pub answer_cache: AnswerCacheConfig,
+    #[serde(default)]
+    pub retrieval: RetrievalConfig,
+    /// Verify answers against the retrieved passages; off by default for latency
+    #[serde(default)]
+    pub grounding: GroundingConfig,
+    /// How many queries run at once; user queries are served before background jobs
+    #[serde(default)]
+    pub query_queue: QueryQueueConfig,
+    /// Applied to safetensors weights fetched by `model download`
+    #[serde(default)]
+    pub quantization: QuantizationConfig,
+    /// Speech-to-text models for voice notes
+    #[serde(default)]
+    pub transcription: TranscriptionConfig,
+    /// `tokenizer.json` for budgeting prompts; defaults to the one in `model_path`. Used when
+    /// built with the `tokenizer` feature, otherwise token counts are estimated from length
+    #[serde(default)]
+    pub tokenizer_path: Option<PathBuf>,
+}
+
+fn default_max_concurrent_generations() -> usize {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +270,9 @@ pub struct CryptoConfig {
     pub pq_enabled: bool,
     pub key_path: PathBuf,
     pub hybrid_mode: bool,
+    /// Passphrase encryption of credentials written under `key_path`
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +280,33 @@ pub struct SwarmConfig {
     pub bootstrap_nodes: Vec<String>,
     pub private_key_path: PathBuf,
     pub swarm_key_path: PathBuf,
+    /// This device's id when merging note edits from other replicas; unset, it is the swarm
+    /// peer id while the swarm is enabled and merging is otherwise off
+    #[serde(default)]
+    pub replica_id: Option<String>,
+    /// Replicate the vault with peers holding the same swarm key
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_swarm_listen_addr")]
+    pub listen_addr: String,
+    /// Find peers on the local network; bootstrap nodes are dialed either way
+    #[serde(default = "default_mdns")]
+    pub mdns: bool,
+    /// Encrypted copies of synced files, named by their BLAKE3 hash
+    #[serde(default = "default_blob_path")]
+    pub blob_path: PathBuf,
+}
+
+fn default_swarm_listen_addr() -> String {
+    "/ip4/0.0.0.0/udp/0/quic-v1".to_string()
+}
+
+fn default_mdns() -> bool {
+    true
+}
+
+fn default_blob_path() -> PathBuf {
+    PathBuf::from("./blobs")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,25 +314,136 @@ pub struct SignalConfig {
     pub enabled: bool,
     pub phone_number: Option<String>,
     pub device_id: Option<u32>,
+    #[serde(default)]
+    pub replies: ReplyConfig,
+    #[serde(default)]
+    pub daemon: SignalDaemonConfig,
+    #[serde(default)]
+    pub backfill: BackfillConfig,
+    /// Conversation the assistant listens to; Note to Self unless a contact is allowlisted
+    #[serde(default)]
+    pub messages: MessageFilterConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub path: PathBuf,
+    /// Keep the database encrypted with the `crypto.encryption` passphrase while the service
+    /// isn't using it; without a passphrase it is stored unencrypted
     pub encrypted: bool,
+    #[serde(default)]
+    pub vacuum: VacuumConfig,
 }
 
 impl Settings {
-    pub fn load(path: &str) -> Result<Self, config::ConfigError> {
-        let settings = config::Config::builder()
+    /// Read `path` (TOML, or any format the extension names), apply environment overrides
+    /// and validate. `NTAI_` variables address nested fields with `__`, e.g.
+    /// `NTAI_STORAGE__LANCE__VECTOR_DIMENSION=768`
+    pub fn load(path: &str) -> Result<Self, SettingsError> {
+        Self::load_with_env(path, None)
+    }
+
+    /// `load` with `env` standing in for the process environment
+    fn load_with_env(path: &str, env: Option<config::Map<String, String>>) -> Result<Self, SettingsError> {
+        let settings: Self = config::Config::builder()
             .add_source(config::File::with_name(path))
-            .add_source(config::Environment::with_prefix("NOTE_TO_AI"))
-            .build()?;
+            .add_source(config::Environment::with_prefix("NOTE_TO_AI").source(env.clone()))
+            .add_source(
+                config::Environment::with_prefix("NTAI")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .source(env),
+            )
+            .build()?
+            .try_deserialize()?;
+
+        settings.validate()?;
+        Ok(settings)
+    }
 
-        settings.try_deserialize()
+    /// Check what would otherwise only fail deep inside indexing or storage
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut errors = Vec::new();
+        let mut invalid = |field: &str, message: String| errors.push(FieldError { field: field.to_string(), message });
+
+        let embedding = self.vault.languages.embedding_model(&self.vault.embedding);
+        if embedding.dimensions == 0 {
+            invalid("vault.embedding.dimensions", "must be greater than 0".to_string());
+        }
+        if embedding.dimensions != self.storage.lance.vector_dimension {
+            invalid(
+                "storage.lance.vector_dimension",
+                format!(
+                    "is {} but the embedding model {} produces {}-dimensional vectors",
+                    self.storage.lance.vector_dimension, embedding.model, embedding.dimensions
+                ),
+            );
+        }
+        if embedding.model.trim().is_empty() {
+            invalid("vault.embedding.model", "is empty".to_string());
+        }
+
+        if self.vault.parsers.reading_wpm == 0 {
+            invalid("vault.parsers.reading_wpm", "must be greater than 0".to_string());
+        }
+        if self.vault.snippet_length == 0 {
+            invalid("vault.snippet_length", "must be greater than 0".to_string());
+        }
+
+        let transcription = &self.ai.transcription;
+        if Whisper::repo_for(&transcription.model).is_none() {
+            invalid("ai.transcription.model", format!("{} is not a Whisper model, e.g. whisper-base", transcription.model));
+        }
+        if let Some(model) = transcription.escalation_model.as_deref().filter(|m| Whisper::repo_for(m).is_none()) {
+            invalid("ai.transcription.escalation_model", format!("{} is not a Whisper model, e.g. whisper-small", model));
+        }
+
+        let database_dir = self.database.path.parent().unwrap_or(Path::new(""));
+        for (field, dir) in [
+            ("vault.path", self.vault.path.as_path()),
+            ("ai.model_path", &self.ai.model_path),
+            ("crypto.key_path", &self.crypto.key_path),
+            ("database.path", database_dir),
+            ("storage.base_path", &self.storage.base_path),
+        ] {
+            if let Err(message) = check_writable_dir(dir) {
+                invalid(field, message);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError::Invalid(errors))
+        }
+    }
+
+    pub fn rng_seed(&self) -> RngSeed {
+        RngSeed::resolve(self.seed)
     }
 }
 
+/// A directory can be written when it, or the nearest ancestor it would be created under, is a
+/// writable directory
+fn check_writable_dir(dir: &Path) -> Result<(), String> {
+    let mut existing = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    while !existing.exists() {
+        existing = match existing.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+    }
+
+    let metadata = std::fs::metadata(existing).map_err(|e| format!("{} can't be read: {}", existing.display(), e))?;
+    if !metadata.is_dir() {
+        return Err(format!("{} is not a directory", existing.display()));
+    }
+    if metadata.permissions().readonly() {
+        return Err(format!("{} is not writable", existing.display()));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,32 +461,86 @@ mod tests {
                 auto_sync: true,
                 index_interval: 300,
                 cache_size: 1000,
+                links: LinkResolutionConfig::default(),
+                embedding: EmbeddingModelConfig::default(),
+                embedding_pool: EmbeddingPoolConfig::default(),
+                warmup: WarmupConfig::default(),
+                ingest: IngestConfig::default(),
+                related: RelatedLinksConfig::default(),
+                capture_dedup: CaptureDedupConfig::default(),
+                categorization: CategorizationConfig::default(),
+                parsers: ParserConfig::default(),
+                moc: MocConfig::default(),
+                pii: PiiConfig::default(),
+                languages: LanguageConfig::default(),
+                search_notes: SearchNoteConfig::default(),
+                lock: LockConfig::default(),
+                hierarchical_embeddings: HierarchicalEmbeddingConfig::default(),
+                tag_graph: TagGraphConfig::default(),
+                qa: QaExtractionConfig::default(),
+                source_weights: SourceWeights::default(),
+                embedding_refresh: EmbeddingRefreshConfig::default(),
+                duplicate_link_targets: DuplicateLinkTargets::default(),
+                block_change_detection: BlockChangeDetection::default(),
+                distance_metric: DistanceMetric::default(),
+                snippet_length: default_snippet_length(),
             },
             ai: AIConfig {
                 model_path: PathBuf::from("./models"),
                 embeddings_path: PathBuf::from("./models/embeddings"),
                 context_window: 4096,
                 model_registry: PathBuf::from("./models/registry.toml"),
+                fallback: FallbackConfig::default(),
+                api: None,
+                chat_template: None,
+                hermes: None,
+                max_concurrent_generations: default_max_concurrent_generations(),
+                summarizer: SummarizerConfig::default(),
+                time_budget_ms: None,
+                query_mode: QueryMode::default(),
+                structured_output: StructuredOutputConfig::default(),
+                answer_cache: AnswerCacheConfig::default(),
+                retrieval: RetrievalConfig::default(),
+                grounding: GroundingConfig::default(),
+                query_queue: QueryQueueConfig::default(),
+                quantization: QuantizationConfig::default(),
+                transcription: TranscriptionConfig::default(),
+                tokenizer_path: None,
             },
             crypto: CryptoConfig {
                 pq_enabled: true,
                 key_path: PathBuf::from("./keys"),
                 hybrid_mode: true,
+                encryption: EncryptionConfig::default(),
             },
             swarm: SwarmConfig {
                 bootstrap_nodes: vec![],
                 private_key_path: PathBuf::from("./swarm.key"),
                 swarm_key_path: PathBuf::from("./swarm.key"),
+                replica_id: None,
+                enabled: false,
+                listen_addr: default_swarm_listen_addr(),
+                mdns: true,
+                blob_path: default_blob_path(),
             },
             signal: SignalConfig {
                 enabled: false,
                 phone_number: None,
                 device_id: Some(1),
+                replies: ReplyConfig::default(),
+                daemon: SignalDaemonConfig::default(),
+                backfill: BackfillConfig::default(),
+                messages: MessageFilterConfig::default(),
             },
             database: DatabaseConfig {
                 path: PathBuf::from("./db/notetoai.db"),
                 encrypted: true,
+                vacuum: VacuumConfig::default(),
             },
+            seed: None,
+            webhooks: WebhookConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            storage: StorageSettings::default(),
         };
 
         let serialized = serde_json::to_string(&settings).unwrap();
@@ -144,10 +570,117 @@ mod tests {
             auto_sync: true,
             index_interval: 600,
             cache_size: 2000,
+            links: LinkResolutionConfig::default(),
+            embedding: EmbeddingModelConfig::default(),
+            embedding_pool: EmbeddingPoolConfig::default(),
+            warmup: WarmupConfig::default(),
+            ingest: IngestConfig::default(),
+            related: RelatedLinksConfig::default(),
+            capture_dedup: CaptureDedupConfig::default(),
+            categorization: CategorizationConfig::default(),
+            parsers: ParserConfig::default(),
+            moc: MocConfig::default(),
+            pii: PiiConfig::default(),
+            languages: LanguageConfig::default(),
+            search_notes: SearchNoteConfig::default(),
+            lock: LockConfig::default(),
+            hierarchical_embeddings: HierarchicalEmbeddingConfig::default(),
+            tag_graph: TagGraphConfig::default(),
+            qa: QaExtractionConfig::default(),
+            source_weights: SourceWeights::default(),
+            embedding_refresh: EmbeddingRefreshConfig::default(),
+            duplicate_link_targets: DuplicateLinkTargets::default(),
+            block_change_detection: BlockChangeDetection::default(),
+            distance_metric: DistanceMetric::default(),
+            snippet_length: 200,
         };
         
         assert_eq!(config.auto_sync, true);
         assert_eq!(config.index_interval, 600);
         assert_eq!(config.cache_size, 2000);
     }
+
+    /// A minimal config file under `dir`, with `extra` appended
+    fn write_config(dir: &Path, extra: &str) -> String {
+        let path = dir.join("config.toml");
+        let root = dir.display();
+        std::fs::write(&path, format!(r#"
+[logging]
+level = "info"
+
+[vault]
+path = "{root}/vault"
+auto_sync = false
+index_interval = 300
+cache_size = 100
+
+[ai]
+model_path = "{root}/models"
+embeddings_path = "{root}/models/embeddings"
+context_window = 4096
+model_registry = "{root}/models/registry.toml"
+
+[crypto]
+pq_enabled = false
+key_path = "{root}/keys"
+hybrid_mode = false
+
+[swarm]
+bootstrap_nodes = []
+private_key_path = "{root}/keys/private.key"
+swarm_key_path = "{root}/keys/swarm.key"
+
+[signal]
+enabled = false
+
+[database]
+path = "{root}/db/notes.db"
+encrypted = false
+
+[storage]
+base_path = "{root}/storage"
+{extra}"#)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_load_valid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), "");
+
+        let settings = Settings::load_with_env(&path, Some(config::Map::new())).unwrap();
+        assert_eq!(settings.vault.path, dir.path().join("vault"));
+        assert_eq!(settings.storage.lance.vector_dimension, settings.vault.embedding.dimensions);
+    }
+
+    #[test]
+    fn test_dimension_mismatch_names_the_field() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), "[storage.lance]\nvector_dimension = 768\n");
+
+        let err = Settings::load_with_env(&path, Some(config::Map::new())).unwrap_err();
+        let SettingsError::Invalid(errors) = &err else {
+            panic!("expected a validation error, got {}", err);
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "storage.lance.vector_dimension");
+        assert!(err.to_string().contains("is 768 but the embedding model all-MiniLM-L6-v2 produces 384-dimensional vectors"));
+    }
+
+    #[test]
+    fn test_env_overrides_nested_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), "");
+        let env = config::Map::from([
+            ("NTAI_VAULT__EMBEDDING__MODEL".to_string(), "bge-base-en-v1.5".to_string()),
+            ("NTAI_VAULT__EMBEDDING__DIMENSIONS".to_string(), "768".to_string()),
+            ("NTAI_STORAGE__LANCE__VECTOR_DIMENSION".to_string(), "768".to_string()),
+            ("NTAI_SIGNAL__PHONE_NUMBER".to_string(), "+15550100".to_string()),
+        ]);
+
+        let settings = Settings::load_with_env(&path, Some(env)).unwrap();
+        assert_eq!(settings.vault.embedding.model, "bge-base-en-v1.5");
+        assert_eq!(settings.storage.lance.vector_dimension, 768);
+        assert_eq!(settings.signal.phone_number.as_deref(), Some("+15550100"));
+    }
 }
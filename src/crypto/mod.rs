@@ -4,22 +4,368 @@ pub mod keys;
 pub mod pq_vault;
 pub mod zk_proofs;
 
-use crate::Result;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
 
-pub struct Crypto;
+/// Start of every encrypted file; also tells encrypted data from legacy plaintext
+const MAGIC: &[u8; 8] = b"NTAIENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Magic, the three KDF parameters, salt and nonce
+const HEADER_LEN: usize = MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+/// Keys kept for salts read from headers, e.g. one per peer; past this the cache starts over
+const MAX_CACHED_KEYS: usize = 64;
+
+/// Derived keys by the salt and cost they were derived with
+type KeyCache = HashMap<([u8; SALT_LEN], KdfParams), [u8; 32]>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("Encryption failed")]
+    Encrypt,
+    #[error("Decryption failed: wrong passphrase or the data was modified")]
+    Decrypt,
+    #[error("Not an encrypted file: {0}")]
+    Format(String),
+    #[error("Key derivation failed: {0}")]
+    KeyDerivation(String),
+    #[error("Passphrase variable {0} is not set")]
+    MissingPassphrase(String),
+    #[error("I/O error on {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Argon2id cost; stored in each file's header, so changing it only affects new writes.
+/// Headers asking for more than the configured cost are rejected before a key is derived, so
+/// data written before the cost was lowered needs the old cost to be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Whether deriving a key with `self` costs no more than with `limit` in any dimension
+    pub fn within(&self, limit: &KdfParams) -> bool {
+        self.memory_kib <= limit.memory_kib
+            && self.iterations <= limit.iterations
+            && self.parallelism <= limit.parallelism
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Encryption of stored data at rest; the passphrase is only ever read from the environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    /// Environment variable holding the passphrase
+    pub passphrase_env: String,
+    pub kdf: KdfParams,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            passphrase_env: "NOTE_TO_AI_PASSPHRASE".to_string(),
+            kdf: KdfParams::default(),
+        }
+    }
+}
+
+/// XChaCha20-Poly1305 with a key derived from a passphrase by Argon2id; every message gets
+/// a fresh nonce, and the header is authenticated along with the ciphertext. Argon2id is slow
+/// on purpose, so messages share a salt drawn per instance and each salt's key is derived once
+pub struct Crypto {
+    passphrase: Vec<u8>,
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    keys: Mutex<KeyCache>,
+}
 
 impl Crypto {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
-    }
-    
-    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        // TODO: Implement quantum-resistant encryption
-        Ok(data.to_vec())
-    }
-    
-    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        // TODO: Implement quantum-resistant decryption
-        Ok(data.to_vec())
+    pub fn new(passphrase: &str) -> Self {
+        Self::with_params(passphrase, KdfParams::default())
+    }
+
+    pub fn with_params(passphrase: &str, params: KdfParams) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        Self {
+            passphrase: passphrase.as_bytes().to_vec(),
+            params,
+            salt,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The passphrase from `config.passphrase_env`
+    pub fn from_config(config: &EncryptionConfig) -> Result<Self, CryptoError> {
+        let passphrase = std::env::var(&config.passphrase_env)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| CryptoError::MissingPassphrase(config.passphrase_env.clone()))?;
+        Ok(Self::with_params(&passphrase, config.kdf))
+    }
+
+    /// Whether `data` starts with the header `encrypt` writes
+    pub fn is_encrypted(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Whether the file at `path` starts with that header
+    pub fn is_encrypted_file(path: &Path) -> bool {
+        let mut start = [0u8; MAGIC.len()];
+        std::fs::File::open(path).and_then(|mut file| file.read_exact(&mut start)).is_ok() && Self::is_encrypted(&start)
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let mut output = Vec::with_capacity(HEADER_LEN + data.len() + 16);
+        output.extend_from_slice(MAGIC);
+        for value in [self.params.memory_kib, self.params.iterations, self.params.parallelism] {
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+        output.extend_from_slice(&self.salt);
+        output.extend_from_slice(&nonce);
+
+        let cipher = self.cipher(&self.salt, self.params)?;
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: data, aad: &output })
+            .map_err(|_| CryptoError::Encrypt)?;
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if !Self::is_encrypted(data) {
+            return Err(CryptoError::Format("missing header".to_string()));
+        }
+        if data.len() < HEADER_LEN {
+            return Err(CryptoError::Format("truncated header".to_string()));
+        }
+
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        let field = |i: usize| {
+            let start = MAGIC.len() + i * 4;
+            u32::from_le_bytes(header[start..start + 4].try_into().unwrap())
+        };
+        let params = KdfParams { memory_kib: field(0), iterations: field(1), parallelism: field(2) };
+        // The header isn't authenticated until after the key is derived, so a forged one
+        // mustn't be able to make that arbitrarily expensive
+        if !params.within(&self.params) {
+            return Err(CryptoError::Format(format!(
+                "key derivation cost ({} KiB, {} iterations, {} lanes) exceeds the configured {} KiB, {} iterations, {} lanes",
+                params.memory_kib, params.iterations, params.parallelism,
+                self.params.memory_kib, self.params.iterations, self.params.parallelism
+            )));
+        }
+        let salt_start = MAGIC.len() + 12;
+        let salt = &header[salt_start..salt_start + SALT_LEN];
+        let nonce = &header[salt_start + SALT_LEN..];
+
+        self.cipher(salt, params)?
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| CryptoError::Decrypt)
+    }
+
+    /// Encrypt `src` into `dst`; they may be the same file
+    pub fn encrypt_file(&self, src: &Path, dst: &Path) -> Result<(), CryptoError> {
+        let data = read(src)?;
+        write(dst, &self.encrypt(&data)?)
+    }
+
+    /// Decrypt `src` into `dst`; they may be the same file
+    pub fn decrypt_file(&self, src: &Path, dst: &Path) -> Result<(), CryptoError> {
+        let data = read(src)?;
+        write(dst, &self.decrypt(&data)?)
+    }
+
+    /// Encrypt a file, or every file under a directory such as a Lance dataset, keeping the layout
+    pub fn encrypt_path(&self, src: &Path, dst: &Path) -> Result<(), CryptoError> {
+        self.map_path(src, dst, &|src, dst| self.encrypt_file(src, dst))
+    }
+
+    /// Reverse of `encrypt_path`
+    pub fn decrypt_path(&self, src: &Path, dst: &Path) -> Result<(), CryptoError> {
+        self.map_path(src, dst, &|src, dst| self.decrypt_file(src, dst))
+    }
+
+    fn map_path(
+        &self,
+        src: &Path,
+        dst: &Path,
+        file: &dyn Fn(&Path, &Path) -> Result<(), CryptoError>,
+    ) -> Result<(), CryptoError> {
+        if !src.is_dir() {
+            return file(src, dst);
+        }
+        let io = |source| CryptoError::Io { path: src.to_path_buf(), source };
+        std::fs::create_dir_all(dst).map_err(|source| CryptoError::Io { path: dst.to_path_buf(), source })?;
+        // Listed up front so files rewritten in place aren't visited twice
+        let entries = std::fs::read_dir(src).map_err(io)?.collect::<Result<Vec<_>, _>>().map_err(io)?;
+        for entry in entries {
+            self.map_path(&entry.path(), &dst.join(entry.file_name()), file)?;
+        }
+        Ok(())
+    }
+
+    fn cipher(&self, salt: &[u8], params: KdfParams) -> Result<XChaCha20Poly1305, CryptoError> {
+        let salt: [u8; SALT_LEN] = salt.try_into().map_err(|_| CryptoError::Format("bad salt".to_string()))?;
+        let mut keys = self.keys.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(key) = keys.get(&(salt, params)) {
+            return Ok(XChaCha20Poly1305::new(key.into()));
+        }
+
+        let argon2_params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(32))
+            .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+            .hash_password_into(&self.passphrase, &salt, &mut key)
+            .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+        let cipher = XChaCha20Poly1305::new(&key.into());
+        if keys.len() >= MAX_CACHED_KEYS {
+            clear_keys(&mut keys);
+        }
+        keys.insert((salt, params), key);
+        key.fill(0);
+        Ok(cipher)
+    }
+}
+
+impl Drop for Crypto {
+    fn drop(&mut self) {
+        self.passphrase.fill(0);
+        clear_keys(self.keys.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+}
+
+fn clear_keys(keys: &mut KeyCache) {
+    for key in keys.values_mut() {
+        key.fill(0);
+    }
+    keys.clear();
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, CryptoError> {
+    std::fs::read(path).map_err(|source| CryptoError::Io { path: path.to_path_buf(), source })
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), CryptoError> {
+    let io = |source| CryptoError::Io { path: path.to_path_buf(), source };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(io)?;
+    }
+    // Written beside the target and renamed, so an interrupted write never leaves half a file
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, data).map_err(io)?;
+    std::fs::rename(&partial, path).map_err(io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast(passphrase: &str) -> Crypto {
+        Crypto::with_params(passphrase, KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 })
+    }
+
+    #[test]
+    fn test_round_trip_and_wrong_passphrase() {
+        let crypto = fast("correct horse");
+        let sealed = crypto.encrypt(b"voice note transcript").unwrap();
+        assert!(Crypto::is_encrypted(&sealed));
+        assert!(!sealed.windows(5).any(|w| w == b"voice"));
+        assert_eq!(crypto.decrypt(&sealed).unwrap(), b"voice note transcript");
+
+        assert!(matches!(fast("battery staple").decrypt(&sealed), Err(CryptoError::Decrypt)));
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(crypto.decrypt(&tampered), Err(CryptoError::Decrypt)));
+        assert!(matches!(crypto.decrypt(b"plain"), Err(CryptoError::Format(_))));
+    }
+
+    #[test]
+    fn test_header_asking_for_more_than_the_configured_cost_is_rejected() {
+        let crypto = fast("correct horse");
+        let mut forged = crypto.encrypt(b"voice note transcript").unwrap();
+        // 4 TiB of Argon2 memory
+        forged[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let started = std::time::Instant::now();
+        assert!(matches!(crypto.decrypt(&forged), Err(CryptoError::Format(message)) if message.contains("exceeds")));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+        // Data written with a cheaper setting still opens
+        let sealed = crypto.encrypt(b"voice note transcript").unwrap();
+        let costlier = Crypto::with_params("correct horse", KdfParams { memory_kib: 128, iterations: 2, parallelism: 1 });
+        assert_eq!(costlier.decrypt(&sealed).unwrap(), b"voice note transcript");
+    }
+
+    #[test]
+    fn test_each_salt_derives_its_key_once() {
+        let crypto = fast("correct horse");
+        let sealed: Vec<Vec<u8>> = (0..3).map(|i| crypto.encrypt(format!("frame {}", i).as_bytes()).unwrap()).collect();
+        let salt = |data: &[u8]| data[MAGIC.len() + 12..MAGIC.len() + 12 + SALT_LEN].to_vec();
+        assert!(sealed.iter().all(|data| salt(data) == salt(&sealed[0])));
+        assert_ne!(sealed[0][HEADER_LEN - NONCE_LEN..HEADER_LEN], sealed[1][HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+        assert_eq!(crypto.keys.lock().unwrap().len(), 1);
+
+        // A peer derives the sender's key once for all of its frames
+        let peer = fast("correct horse");
+        for (i, data) in sealed.iter().enumerate() {
+            assert_eq!(peer.decrypt(data).unwrap(), format!("frame {}", i).as_bytes());
+        }
+        assert_eq!(peer.keys.lock().unwrap().len(), 1);
+        assert!(matches!(fast("battery staple").decrypt(&sealed[0]), Err(CryptoError::Decrypt)));
+    }
+
+    #[test]
+    fn test_directory_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let dataset = dir.path().join("vectors.lance");
+        std::fs::create_dir_all(dataset.join("data")).unwrap();
+        std::fs::write(dataset.join("data/0.lance"), b"vectors").unwrap();
+        std::fs::write(dataset.join("_latest.manifest"), b"manifest").unwrap();
+
+        let crypto = fast("correct horse");
+        let sealed = dir.path().join("sealed");
+        crypto.encrypt_path(&dataset, &sealed).unwrap();
+        assert!(Crypto::is_encrypted(&std::fs::read(sealed.join("data/0.lance")).unwrap()));
+
+        let restored = dir.path().join("restored");
+        crypto.decrypt_path(&sealed, &restored).unwrap();
+        assert_eq!(std::fs::read(restored.join("data/0.lance")).unwrap(), b"vectors");
+        assert_eq!(std::fs::read(restored.join("_latest.manifest")).unwrap(), b"manifest");
+
+        let file = dir.path().join("metadata.duckdb");
+        std::fs::write(&file, b"rows").unwrap();
+        crypto.encrypt_file(&file, &file).unwrap();
+        assert!(fast("wrong").decrypt_file(&file, &file).is_err());
+        crypto.decrypt_file(&file, &file).unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"rows");
     }
 }
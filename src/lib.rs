@@ -6,8 +6,12 @@ pub mod identity;
 pub mod logger;
 pub mod scheduler;
 pub mod signal_integration;  // Updated to match renamed module
+pub mod status;
 pub mod swarm;
 pub mod vault;
+pub mod webhooks;
+#[cfg(test)]
+mod test_support;
 
 pub use config::Settings;
 
@@ -1,7 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use anyhow::{Result, Context};
 use clap::{Parser, Subcommand};
 use tokio::signal as tokio_signal;
+use tokio::sync::OnceCell;
 use tracing::{info, error, warn};
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
@@ -15,8 +17,62 @@ mod identity;
 mod swarm;
 mod audio;
 mod scheduler;
+mod status;
+mod webhooks;
+#[cfg(test)]
+mod test_support;
 
 use config::Settings;
+use ai::api_client::APIClient;
+use ai::backend::{Backend, FallbackChain};
+use ai::model_registry::{ModelKind, ModelRegistry};
+use ai::model_switcher::{ModelConfig, ModelSwitcher, TaskContext};
+use ai::context::ContextBuilder;
+use ai::gguf::{GgufHeader, GgufVariant};
+use ai::hermes_integration::{HermesConfig, HermesIntegration};
+use ai::query_queue::{QueryPriority, QueryQueue};
+use ai::structured::OutputSchema;
+use ai::trace::{QueryStage, QueryTrace};
+use ai::{AI, QueryMode};
+use audio::whisper::Whisper;
+use crypto::Crypto;
+use scheduler::Scheduler;
+use signal_integration::Signal;
+use signal_integration::backfill::{default_attachments_dir, Backfill, SignalCliExport, Transcriber};
+use signal_integration::client::SignalClient;
+use signal_integration::handler::{MessageCapture, MessageHandler};
+use signal_integration::daemon::SignalDaemonClient;
+use signal_integration::registration::{
+    load_credentials, SetupMethod, SetupOutcome, SetupPrompt, SignalCli, SignalCliProcess, SignalDaemonCli, SignalSetup,
+};
+use swarm::{Swarm, SwarmHandle};
+use swarm::ipfs::BlobStore;
+use swarm::sync::VaultSync;
+use status::{format_size, ModelStatus, SignalAccount, SignalStatus, StatusReport, StorageStatus};
+use vault::cache::Cache;
+use vault::capture_dedup::CaptureDeduplicator;
+use vault::categorize::Categorizer;
+use vault::circuit_breaker::{CircuitBreaker, GuardedWorker};
+use vault::embedding_engine::EmbeddingEngine;
+use vault::embedding_pool::{EmbeddingPool, EmbeddingWorker, ModelWorker};
+use vault::embeddings::{Embeddings, EmbeddingModelConfig};
+use vault::export::{DateRange, EmbeddingExporter, ExportFormat, NoteExporter};
+use vault::focus::{Focus, FocusSession};
+use vault::indexer::VaultIndexer;
+use vault::ingest::UrlIngestor;
+use vault::sealed::SealedDatabase;
+use vault::lock::{OpenMode, StorageLock};
+use vault::moc::MocGenerator;
+use vault::parser::ObsidianParser;
+use vault::parsers::ParserRegistry;
+use vault::pii::PiiScanner;
+use vault::refresh::{EmbeddingRefresher, RefreshMode};
+use vault::related::LinkSuggester;
+use vault::search::{SearchFilters, SearchOptions, SearchQuery, SearchRetriever, VectorSearchEngine};
+use vault::search_note::SearchNoteWriter;
+use vault::tag_graph::{TagGraph, TagGraphFormat};
+use vault::warmup::IndexWarmup;
+use webhooks::{WebhookEvent, WebhookNotifier};
 // Temporarily disabled while fixing Arrow ecosystem conflicts
 // use vault::storage::{HybridStorageEngine, StorageConfig};
 
@@ -44,6 +100,18 @@ struct Cli {
     /// Run in daemon mode (background service)
     #[arg(long)]
     daemon: bool,
+    
+    /// Take the storage write lock even if another instance holds it
+    #[arg(long, global = true)]
+    force: bool,
+    
+    /// Restrict searches to a folder or `#tag` until cleared; repeat for several
+    #[arg(long, global = true)]
+    focus: Vec<String>,
+    
+    /// Search all notes again
+    #[arg(long, global = true, conflicts_with = "focus")]
+    clear_focus: bool,
 }
 
 #[derive(Subcommand)]
@@ -71,6 +139,30 @@ enum Commands {
         /// Maximum number of results
         #[arg(short, long, default_value = "5")]
         limit: usize,
+        
+        /// Answer with this model for this query only
+        #[arg(long)]
+        model: Option<String>,
+        
+        /// Return the matching passages with sources instead of a generated answer
+        #[arg(long)]
+        no_llm: bool,
+        
+        /// Print how long each stage of the query took
+        #[arg(long)]
+        trace: bool,
+        
+        /// Save the ranked results as a note with this title instead of answering
+        #[arg(long, value_name = "TITLE")]
+        save_as: Option<String>,
+        
+        /// Answer with JSON matching the JSON Schema in this file
+        #[arg(long, value_name = "FILE", conflicts_with = "no_llm")]
+        schema: Option<PathBuf>,
+        
+        /// Find functions, types and other definitions in indexed code files instead
+        #[arg(long, conflicts_with_all = ["semantic", "schema", "save_as"])]
+        symbols: bool,
     },
     
     /// Export your notes to different formats
@@ -86,6 +178,31 @@ enum Commands {
         /// Date range filter (YYYY-MM-DD to YYYY-MM-DD)
         #[arg(long)]
         date_range: Option<String>,
+        
+        /// Also write document embeddings (embeddings.npy + embeddings.json)
+        #[arg(long)]
+        with_embeddings: bool,
+        
+        /// Include block-level embeddings (requires --with-embeddings)
+        #[arg(long, requires = "with_embeddings")]
+        with_block_embeddings: bool,
+        
+        /// Also write the tag hierarchy and co-occurrence graph (json or dot)
+        #[arg(long, value_name = "FORMAT")]
+        tag_graph: Option<String>,
+    },
+    
+    /// Save a web page into the vault as a markdown note
+    Save {
+        /// Page URL
+        url: String,
+    },
+    
+    /// Suggest wikilinks between similar notes that aren't linked yet
+    Related {
+        /// Insert suggestions under a "Related" section instead of only listing them
+        #[arg(long)]
+        apply: bool,
     },
     
     /// Show system status and statistics
@@ -109,7 +226,12 @@ enum ModelAction {
     /// List available models
     List,
     /// Download a specific model
-    Download { name: String },
+    Download {
+        name: String,
+        /// Fetch a quantized GGUF file instead: q4_0, q4_k_m or q8_0
+        #[arg(long)]
+        quant: Option<GgufVariant>,
+    },
     /// Remove a model
     Remove { name: String },
     /// Test model performance
@@ -123,18 +245,57 @@ enum SignalAction {
         /// Phone number for registration
         #[arg(long)]
         phone: String,
+        
+        /// Register the number with a verification code instead of linking to an existing device
+        #[arg(long)]
+        register: bool,
+        
+        /// Receive the verification code by voice call (implies --register)
+        #[arg(long)]
+        voice: bool,
     },
     /// Test Signal connection
-    Test,
+    Test {
+        /// Also send a test message to Note to Self
+        #[arg(long)]
+        send: bool,
+    },
     /// Show Signal status
     Status,
+    /// Import existing Note to Self history into the vault
+    Backfill {
+        /// Output of `signal-cli receive --output=json`, one envelope per line
+        #[arg(long)]
+        from: PathBuf,
+        
+        /// Directory signal-cli stores attachments in
+        #[arg(long)]
+        attachments: Option<PathBuf>,
+    },
 }
 
 /// Main application state
 pub struct NoteToAI {
     config: Settings,
-    // TODO: Re-add scheduler and storage when they're ready
-    // scheduler: scheduler::Scheduler,
+    model_switcher: Arc<ModelSwitcher>,
+    /// Opened on first use, since most commands never query
+    search: OnceCell<Arc<VectorSearchEngine>>,
+    indexer: OnceCell<Arc<VaultIndexer>>,
+    ai: OnceCell<Arc<AI>>,
+    /// The embedding model, loaded once for every worker; `None` when it isn't available
+    embedding_engine: OnceLock<Option<Arc<EmbeddingEngine>>>,
+    /// Answers Signal messages; loaded by `start` when `ai.hermes` is configured
+    hermes: Option<Arc<HermesIntegration>>,
+    cache: Arc<Cache>,
+    focus: FocusSession,
+    /// Admits user queries ahead of scheduled jobs and vault re-indexing
+    queue: QueryQueue,
+    webhooks: WebhookNotifier,
+    scheduler: Scheduler,
+    /// Encrypted at rest and decrypted while in use when `database.encrypted` is set; declared
+    /// last so everything using it is dropped before it is sealed
+    database: Option<SealedDatabase>,
+    // TODO: Re-add storage when it's ready
     // storage: HybridStorageEngine,
 }
 
@@ -144,8 +305,12 @@ impl NoteToAI {
         info!("Initializing note-to-ai");
         
         // Load configuration
-        let config = Settings::load(config_path.to_str().unwrap())
+        let mut config = Settings::load(config_path.to_str().unwrap())
             .context("Failed to load configuration")?;
+        let database = unseal_database(&config)?;
+        if let Some(database) = &database {
+            config.database.path = database.working_path().to_path_buf();
+        }
         
         // TODO: Re-enable hybrid storage once Arrow conflicts are resolved
         /*
@@ -159,36 +324,212 @@ impl NoteToAI {
             .context("Failed to initialize storage engine")?;
         */
         
+        let model_switcher = Arc::new(ModelSwitcher::new());
+        Self::register_local_models(&model_switcher, &config).await?;
+        
+        let cache = Arc::new(Cache::new(config.vault.cache_size));
+        let focus = FocusSession::load(&config.vault.path)?;
+        let queue = QueryQueue::new(config.ai.query_queue.clone());
+        let webhooks = WebhookNotifier::new(config.webhooks.clone())?;
+        
         Ok(Self {
             config,
+            model_switcher,
+            search: OnceCell::new(),
+            indexer: OnceCell::new(),
+            ai: OnceCell::new(),
+            embedding_engine: OnceLock::new(),
+            hermes: None,
+            cache,
+            focus,
+            scheduler: Scheduler::new().with_query_queue(queue.clone()),
+            queue,
+            webhooks,
+            database,
             // storage,
         })
     }
     
+    /// The search index that queries run against
+    async fn search_engine(&self) -> Result<&Arc<VectorSearchEngine>> {
+        self.search.get_or_try_init(|| async {
+            let model = self.embedding_model();
+            let access_log = VaultIndexer::new(self.config.database.path.clone(), self.config.vault.path.clone())?;
+            access_log.initialize_db().await?;
+            let engine = VectorSearchEngine::new(self.config.database.path.clone())?
+                .with_query_embedder(self.query_embedder(&model, None)?)
+                .with_access_log(Arc::new(access_log))
+                .with_languages(self.config.vault.languages.clone())
+                .with_focus(self.focus.clone())
+                .with_model_version(&model.version)
+                .with_duplicate_link_targets(self.config.vault.duplicate_link_targets)
+                .with_block_change_detection(self.config.vault.block_change_detection)
+                .with_index_update_threshold(self.config.storage.lance.index_rebuild_threshold)
+                .with_distance_metric(self.config.vault.distance_metric);
+            engine.initialize().await?;
+            Ok(Arc::new(engine))
+        }).await
+    }
+    
+    /// The AI pipeline, retrieving its passages from the search index and answering with the
+    /// generation model when one can be loaded
+    async fn ai(&self) -> Result<&Arc<AI>> {
+        self.ai.get_or_try_init(|| async {
+            let model = self.generation_model().await;
+            Ok(Arc::new(self.build_ai(model).await?))
+        }).await
+    }
+    
+    /// The AI pipeline answering with `model`, or from retrieved passages alone without one
+    async fn build_ai(&self, model: Option<ModelConfig>) -> Result<AI> {
+        let config = &self.config;
+        let tokenizer_path = config.ai.tokenizer_path.clone()
+            .unwrap_or_else(|| config.ai.model_path.join("tokenizer.json"));
+        let context = ContextBuilder::new().with_token_counter(ai::tokens::token_counter(Some(&tokenizer_path)));
+        let retriever = SearchRetriever::new(self.search_engine().await?.clone(), SearchOptions {
+            source_weights: config.vault.source_weights.clone(),
+            snippet_length: config.vault.snippet_length,
+            ..SearchOptions::default()
+        });
+        let mut ai = AI::new()?
+            .with_context(Arc::new(context))
+            .with_retriever(Arc::new(retriever))
+            .with_query_mode(config.ai.query_mode)
+            .with_structured_output(config.ai.structured_output.clone())
+            .with_answer_cache(config.ai.answer_cache.clone())
+            .with_retrieval(config.ai.retrieval.clone())
+            .with_grounding(config.ai.grounding.clone())
+            .with_shared_query_queue(self.queue.clone())
+            .with_summarizer(config.ai.summarizer.clone())
+            .with_webhooks(self.webhooks.clone())
+            .with_focus(self.focus.clone());
+        if let Some(backend) = self.generation_backend(model).await? {
+            ai = ai.with_backend(backend);
+        }
+        Ok(ai)
+    }
+    
+    /// The local generation model, falling back to the configured API on the failures
+    /// `ai.fallback` lists; the API alone when no local model can be loaded
+    async fn generation_backend(&self, model: Option<ModelConfig>) -> Result<Option<Arc<dyn Backend>>> {
+        let local = match model {
+            Some(model) => local_backend(&self.config, PathBuf::from(&model.endpoint))
+                .map_err(|e| warn!("Answering without {}: {}", model.name, e))
+                .ok(),
+            None => None,
+        };
+        let api = match &self.config.ai.api {
+            Some(api) => Some(Arc::new(APIClient::new(api.clone())?) as Arc<dyn Backend>),
+            None => None,
+        };
+        Ok(match local {
+            Some(local) => Some(Arc::new(FallbackChain::new(local, api, self.config.ai.fallback.clone()))),
+            None => api,
+        })
+    }
+    
+    /// The model answering queries: the current one, or else the best registered for text generation
+    async fn generation_model(&self) -> Option<ModelConfig> {
+        let name = match self.model_switcher.get_current_model().await {
+            Some(name) => name,
+            None => self.model_switcher.select_model(&TaskContext {
+                task_type: "query".to_string(),
+                required_capabilities: vec!["text-generation".to_string()],
+                max_latency_ms: None,
+                max_cost_per_token: None,
+                context_size: 0,
+                priority: 5,
+            }).await.ok()?,
+        };
+        self.model_switcher.get_model_config(&name).await.ok()
+    }
+    
+    /// Register every model file found in the configured model directory, and every
+    /// downloaded model folder in its registry
+    async fn register_local_models(switcher: &ModelSwitcher, config: &Settings) -> Result<()> {
+        let model_dir: &Path = &config.ai.model_path;
+        if !model_dir.is_dir() {
+            warn!("Model directory {} not found", model_dir.display());
+            return Ok(());
+        }
+        
+        let mut models = Vec::new();
+        for entry in std::fs::read_dir(model_dir)? {
+            let path = entry?.path();
+            let is_weights = matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("safetensors") | Some("gguf")
+            );
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if !is_weights {
+                continue;
+            }
+            models.push((name.to_string(), path));
+        }
+        for model in ModelRegistry::load(model_dir)?.installed()? {
+            if model.entry.kind == ModelKind::Llm {
+                models.push((model.name, model.path));
+            }
+        }
+        
+        for (name, path) in models {
+            switcher.register_model(ModelConfig {
+                name,
+                endpoint: path.display().to_string(),
+                max_tokens: 512,
+                temperature: 0.7,
+                cost_per_token: 0.0,
+                latency_ms: 0,
+                capabilities: vec!["text-generation".to_string()],
+                context_window: config.ai.context_window,
+                is_available: true,
+            }).await?;
+        }
+        
+        Ok(())
+    }
+    
     /// Start the main service loop
     pub async fn start(&mut self, skip_signal: bool, skip_ai: bool) -> Result<()> {
         info!("Starting note-to-ai service");
         
-        // TODO: Start scheduler when it's implemented
-        // self.scheduler.start().await
-        //     .context("Failed to start scheduler")?;
+        if self.config.scheduler.enabled {
+            self.schedule_tasks().await?;
+        }
+        
+        // Replicate the vault with this user's other devices
+        let swarm = if self.config.swarm.enabled {
+            Some(self.start_swarm().await?)
+        } else {
+            None
+        };
         
         // Load AI models (unless skipped)
         if !skip_ai {
             info!("Loading AI models...");
-            // TODO: Load models based on configuration
+            if let Some(config) = self.config.ai.hermes.clone() {
+                self.hermes = Some(self.start_hermes(config).await?);
+            }
             info!("AI models loaded successfully");
         } else {
             warn!("Skipping AI model loading");
         }
         
+        // Preload frequently accessed documents; failures only cost cold-start latency
+        if let Err(e) = self.warm_index().await {
+            warn!("Index warmup failed: {}", e);
+        }
+        
+        // Reclaim space from deleted notes a few pages at a time
+        if self.config.database.path.exists() {
+            vault::vacuum::spawn(self.config.database.path.clone(), self.config.database.vacuum.clone())?;
+        }
+        
         // Connect to Signal (unless skipped)
         if !skip_signal {
-            info!("Connecting to Signal...");
-            // TODO: Implement Signal connection
-            info!("Signal connected successfully");
-            
-            // Start message processing loop
+            // Connect and start the message processing loop
             self.start_message_processing().await?;
         } else {
             warn!("Skipping Signal connection");
@@ -199,85 +540,559 @@ impl NoteToAI {
         
         // Wait for shutdown signal
         self.wait_for_shutdown().await;
+        self.scheduler.stop().await;
+        if let Some(swarm) = swarm {
+            swarm.shutdown().await;
+        }
         
         Ok(())
     }
     
+    /// The chat model answering Signal messages, with the conversations saved before the last shutdown
+    async fn start_hermes(&self, config: HermesConfig) -> Result<Arc<HermesIntegration>> {
+        // Every request names the model, so a remote one needs registering too; it's left out
+        // of text-generation so local answering never picks it
+        if self.model_switcher.get_model_config(&config.default_model).await.is_err() {
+            self.model_switcher.register_model(ModelConfig {
+                name: config.default_model.clone(),
+                endpoint: config.base_url.clone(),
+                max_tokens: 512,
+                temperature: 0.7,
+                cost_per_token: 0.0,
+                latency_ms: 0,
+                capabilities: vec!["chat".to_string()],
+                context_window: self.config.ai.context_window,
+                is_available: true,
+            }).await?;
+        }
+        
+        let tokenizer_path = self.config.ai.tokenizer_path.clone()
+            .unwrap_or_else(|| self.config.ai.model_path.join("tokenizer.json"));
+        let counter = ai::tokens::token_counter(Some(&tokenizer_path));
+        let context = ContextBuilder::new().with_token_counter(counter.clone());
+        let mut hermes = HermesIntegration::new(config.clone(), self.model_switcher.clone(), Arc::new(context))
+            .with_token_counter(counter);
+        if config.index_conversations {
+            let embedder = Arc::new(ModelWorker::new(&self.embedding_model())?.with_engine(self.embedding_engine()?));
+            hermes = hermes.with_search_index(self.search_engine().await?.clone(), embedder);
+        }
+        
+        let restored = hermes.restore_conversations().await?;
+        if restored > 0 {
+            info!("Restored {} conversations", restored);
+        }
+        Ok(Arc::new(hermes))
+    }
+    
+    /// Register the background tasks and start running them
+    async fn schedule_tasks(&self) -> Result<()> {
+        let indexer = self.vault_indexer().await?;
+        if self.config.scheduler.watch_vault {
+            scheduler::tasks::spawn_vault_watcher(indexer.clone(), self.queue.clone())?;
+        }
+        scheduler::tasks::add_reindex_task(&self.scheduler, indexer, &self.config.scheduler);
+        let engine = self.search_engine().await?;
+        if self.config.vault.embedding_refresh.mode == RefreshMode::Eager {
+            let model = self.embedding_model();
+            self.embedding_refresher(engine.clone(), &model)?.schedule(&self.scheduler);
+        }
+        scheduler::tasks::add_optimize_task(&self.scheduler, engine.clone(), &self.config.scheduler);
+        self.scheduler.start();
+        Ok(())
+    }
+    
+    /// The indexer for the vault with the configured parsers, merging edits when this device
+    /// has a replica id; shared by scheduled indexing and the swarm
+    async fn vault_indexer(&self) -> Result<Arc<VaultIndexer>> {
+        self.indexer.get_or_try_init(|| async {
+            let mut indexer = VaultIndexer::new(self.config.database.path.clone(), self.config.vault.path.clone())?
+                .with_parsers(
+                    ParserRegistry::from_config(&self.config.vault.parsers)?
+                        .with_pii_scanner(PiiScanner::from_config(&self.config.vault.pii)?)
+                );
+            let replica = match &self.config.swarm.replica_id {
+                Some(replica) => Some(replica.clone()),
+                // Peers exchange text notes as CRDT histories, so every swarm member merges
+                None if self.config.swarm.enabled => Some(swarm::local_peer_id(&self.config.swarm)?.to_string()),
+                None => None,
+            };
+            if let Some(replica) = &replica {
+                indexer = indexer.with_crdt(replica);
+            }
+            let model = self.embedding_model();
+            let pool = Arc::new(EmbeddingPool::shared_for_model(&model, &self.config.vault.embedding_pool, self.embedding_engine()?.as_ref())?);
+            indexer = indexer
+                .with_webhooks(self.webhooks.clone())
+                .with_search_engine(self.search_engine().await?.clone())
+                .with_embeddings(pool, &model.model)
+                .with_hierarchical_embeddings(self.config.vault.hierarchical_embeddings.clone())
+                .with_link_resolution(self.config.vault.links.clone());
+            if self.config.vault.moc.enabled {
+                match self.generation_backend(self.generation_model().await).await? {
+                    Some(backend) => indexer = indexer.with_moc(Arc::new(MocGenerator::new(self.config.vault.moc.clone(), backend))),
+                    None => warn!("MOC notes need a generation model or API; none is configured"),
+                }
+            }
+            indexer.initialize_db().await?;
+            Ok(Arc::new(indexer))
+        }).await.cloned()
+    }
+    
+    /// Join the private swarm; everything sent is sealed with the swarm key
+    async fn start_swarm(&self) -> Result<SwarmHandle> {
+        let config = &self.config.swarm;
+        let swarm_key = swarm::load_swarm_key(&config.swarm_key_path)?;
+        let crypto = Arc::new(Crypto::with_params(&swarm_key, self.config.crypto.encryption.kdf));
+        let blobs = BlobStore::new(config.blob_path.clone(), crypto.clone())?;
+        let sync = VaultSync::new(self.vault_indexer().await?, blobs, crypto);
+        let handle = Swarm::new(config.clone(), sync).start().await?;
+        info!("Joined the private swarm as {}", handle.peer_id);
+        Ok(handle)
+    }
+    
+    /// Advisory lock on the storage directory, so a daemon and a one-off command don't write at once
+    fn lock_storage(&self, mode: OpenMode) -> Result<Option<StorageLock>> {
+        Ok(StorageLock::for_mode_with(&storage_dir(&self.config), mode, &self.config.vault.lock)?)
+    }
+    
+    /// Embedding model for the vault, the multilingual one when language routing configures it
+    fn embedding_model(&self) -> EmbeddingModelConfig {
+        self.config.vault.languages.embedding_model(&self.config.vault.embedding)
+    }
+    
+    /// The embedding model's weights, loaded on first use
+    fn embedding_engine(&self) -> Result<Option<Arc<EmbeddingEngine>>> {
+        if let Some(engine) = self.embedding_engine.get() {
+            return Ok(engine.clone());
+        }
+        let engine = load_embedding_engine(&self.config)?;
+        Ok(self.embedding_engine.get_or_init(|| engine).clone())
+    }
+    
+    /// Query embedding worker behind a circuit breaker, `breaker` when shared with a pool's documents
+    fn query_embedder(&self, model: &EmbeddingModelConfig, breaker: Option<Arc<CircuitBreaker>>) -> Result<Arc<GuardedWorker<ModelWorker>>> {
+        let breaker = breaker.unwrap_or_else(|| {
+            Arc::new(CircuitBreaker::new("Query embeddings", self.config.vault.embedding_pool.breaker.clone()))
+        });
+        let worker = ModelWorker::for_queries(model)?.with_engine(self.embedding_engine()?);
+        Ok(Arc::new(GuardedWorker::new(worker, breaker)))
+    }
+    
+    /// Re-embeds notes through `engine` with the current model
+    fn embedding_refresher(&self, engine: Arc<VectorSearchEngine>, model: &EmbeddingModelConfig) -> Result<Arc<EmbeddingRefresher<ModelWorker>>> {
+        let pool = Arc::new(EmbeddingPool::for_model(model, &self.config.vault.embedding_pool, self.embedding_engine()?.as_ref())?);
+        Ok(Arc::new(EmbeddingRefresher::new(
+            engine,
+            pool,
+            &model.model,
+            &self.config.vault.path,
+            self.config.vault.embedding_refresh.clone(),
+        )?))
+    }
+    
+    /// Preload the most accessed documents into the cache, bounded by the warmup timeout
+    async fn warm_index(&self) -> Result<()> {
+        let db_path = &self.config.database.path;
+        if !self.config.vault.warmup.enabled || !db_path.exists() {
+            return Ok(());
+        }
+        
+        let indexer = VaultIndexer::new(db_path.clone(), self.config.vault.path.clone())?
+            .with_parsers(
+                ParserRegistry::from_config(&self.config.vault.parsers)?
+                    .with_pii_scanner(PiiScanner::from_config(&self.config.vault.pii)?)
+            );
+        indexer.initialize_db().await?;
+        let mut embeddings = Embeddings::new()?;
+        if let Some(engine) = self.embedding_engine()? {
+            embeddings = embeddings.with_engine(engine);
+        }
+        
+        IndexWarmup::new(&indexer, &embeddings, &self.cache, &self.embedding_model())
+            .run(&self.config.vault.warmup)
+            .await?;
+        Ok(())
+    }
+    
     /// Start processing Signal messages
     async fn start_message_processing(&mut self) -> Result<()> {
-        info!("Starting Signal message processing");
+        let account = signal_account(&self.config)?;
+        let daemon = &self.config.signal.daemon;
+        let filter = self.config.signal.messages.clone();
+        
+        info!("Connecting to Signal...");
+        let client = Arc::new(
+            SignalClient::connect(&daemon.socket_path).await?
+                .with_timeout(std::time::Duration::from_millis(daemon.timeout_ms))
+        );
+        client.subscribe(&account).await?;
+        info!("Signal connected successfully");
         
-        // TODO: Implement message processing loop
-        // This would:
-        // 1. Listen for incoming Signal messages
-        // 2. Filter for "Note to Self" messages
-        // 3. Process voice messages with Whisper
-        // 4. Generate embeddings and store in hybrid database
-        // 5. Respond to queries with AI-generated answers
+        let signal = Signal::with_reply_config(self.config.signal.replies.clone())
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .with_client(client.clone(), filter.reply_to(&account));
+        let ingestor = UrlIngestor::new(self.config.vault.path.clone(), self.config.vault.ingest.clone())?;
+        let mut capture = MessageCapture::new(self.vault_indexer().await?, self.config.signal.backfill.clone(), default_attachments_dir());
+        if self.config.signal.backfill.transcribe_audio {
+            match voice_transcriber(&self.config) {
+                Ok(transcriber) => capture = capture.with_transcriber(transcriber),
+                Err(e) => warn!("Voice messages will be saved untranscribed: {}", e),
+            }
+        }
+        let mut handler = MessageHandler::new(signal, filter.reply_to(&account), self.focus.clone(), ingestor)
+            .with_capture(capture);
+        if let Some(hermes) = &self.hermes {
+            handler = handler.with_hermes(hermes.clone());
+        }
+        info!("Starting Signal message processing");
+        tokio::spawn(async move {
+            while let Some(envelope) = client.next_envelope().await {
+                // Everything outside the one conversation we serve is someone else's business
+                if !filter.accepts(&envelope, &account) {
+                    continue;
+                }
+                if let Err(e) = handler.handle(&envelope).await {
+                    warn!("Failed to handle Signal message {}: {}", envelope.timestamp, e);
+                }
+            }
+            warn!("signal-cli closed the connection; no longer receiving messages");
+        });
         
         Ok(())
     }
     
+    /// Set or clear the session focus from the `--focus` and `--clear-focus` flags
+    pub async fn update_focus(&self, targets: &[String], clear: bool) -> Result<()> {
+        if clear {
+            self.focus.clear().await?;
+            println!("Focus cleared; searching all notes.");
+        } else if !targets.is_empty() {
+            let focus = Focus::parse(targets);
+            println!("Focus: {} (use --clear-focus to search all notes)", focus.describe());
+            self.focus.set(focus).await?;
+        }
+        Ok(())
+    }
+    
     /// Query the knowledge base
-    pub async fn query(&self, text: &str, semantic: bool, limit: usize) -> Result<()> {
+    pub async fn query(&self, text: &str, semantic: bool, limit: usize, model: Option<&str>, no_llm: bool, trace: bool) -> Result<()> {
         info!("Processing query: {}", text);
+        if let Some(focus) = self.focus.current().await {
+            println!("Focus: {} (use --clear-focus to search all notes)", focus.describe());
+        }
+        
+        let engine = self.search_engine().await?;
+        if engine.get_stats().await?.total_documents == 0 {
+            println!("No notes are indexed yet. Run `note-to-ai start` to index your vault, then query again.");
+            return Ok(());
+        }
+        
+        if no_llm || self.config.ai.query_mode == QueryMode::ContextOnly {
+            let (passages, query_trace) = self.ai().await?.process_query_traced(text, QueryMode::ContextOnly, limit).await?;
+            println!("{}", passages);
+            if trace {
+                eprintln!("{}", query_trace.summary());
+            }
+            return Ok(());
+        }
+        
+        // A per-query override answers this query only and is never persisted
+        let override_ai = match model {
+            Some(name) => {
+                let model_name = self.model_switcher.resolve_override(name).await?;
+                info!("Using model override: {}", model_name);
+                let model = self.model_switcher.get_model_config(&model_name).await?;
+                Some(Arc::new(self.build_ai(Some(model)).await?))
+            }
+            None => None,
+        };
+        
+        let model = self.embedding_model();
+        
+        // A question a note already answers skips ranking altogether
+        if self.config.vault.qa.enabled {
+            match engine.direct_answer(text, self.config.vault.qa.min_similarity).await {
+                Ok(Some(hit)) => {
+                    self.webhooks.notify(WebhookEvent::QueryAnswered, serde_json::json!({
+                        "query": text,
+                        "mode": "direct",
+                        "answer": hit.pair.answer,
+                        "source": hit.pair.source,
+                    }));
+                    println!("{}", hit.pair.answer);
+                    println!("\nFrom {}: \"{}\"", hit.pair.source.display(), hit.pair.question);
+                    return Ok(());
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Direct answer lookup failed: {}", e),
+            }
+        }
         
-        if semantic {
-            // TODO: Generate embeddings for query and search vectors
+        let ai = match &override_ai {
+            Some(ai) => ai,
+            None => self.ai().await?,
+        };
+        if ai.can_generate() {
+            let (answer, query_trace) = ai.process_query_traced(text, QueryMode::Generate, limit).await?;
+            println!("{}", answer);
+            if trace {
+                eprintln!("{}", query_trace.summary());
+            }
+            return Ok(());
+        }
+        
+        let options = SearchOptions {
+            limit,
+            include_context: false,
+            source_weights: self.config.vault.source_weights.clone(),
+            snippet_length: self.config.vault.snippet_length,
+            ..SearchOptions::default()
+        };
+        let _slot = self.queue.acquire(QueryPriority::Interactive).await?;
+        let mut query_trace = QueryTrace::new(text);
+        let results = if semantic {
             info!("Performing semantic search...");
-            println!("Semantic search not yet implemented");
+            let query = SearchQuery {
+                text: text.to_string(),
+                filters: SearchFilters::default(),
+                options: SearchOptions { hybrid_search: false, ..options },
+            };
+            engine.search_traced(&query, &mut query_trace).await?
         } else {
-            // TODO: Perform text search when storage is implemented
-            println!("Text search found 0 results (storage not yet implemented):");
+            let mut results = query_trace.time_async(QueryStage::TextSearch, engine.text_search(text, &options)).await?;
+            results.truncate(limit);
+            results
+        };
+        if trace {
+            eprintln!("{}", query_trace.summary());
+        }
+        self.webhooks.notify(WebhookEvent::QueryAnswered, serde_json::json!({
+            "query": text,
+            "mode": "search",
+            "results": results.iter()
+                .map(|result| serde_json::json!({ "path": result.document.path, "score": result.score }))
+                .collect::<Vec<_>>(),
+            "duration_ms": query_trace.total().as_millis() as u64,
+        }));
+        
+        if results.is_empty() {
+            println!("No notes matched \"{}\".", text);
+            return Ok(());
         }
         
+        let kind = if semantic { "Semantic" } else { "Text" };
+        println!("{} search found {} results:", kind, results.len());
+        for (i, result) in results.iter().enumerate() {
+            println!("{}. {} ({}) score {:.3}", i + 1, result.document.title, result.document.path.display(), result.score);
+            println!("   {}", result.document.snippet.replace('\n', " "));
+        }
+        
+        // Notes embedded by an older model version are re-embedded once they're read
+        if self.config.vault.embedding_refresh.mode != RefreshMode::Off {
+            let mut stale = Vec::new();
+            for result in &results {
+                if engine.is_stale(&result.document.path).await? {
+                    stale.push(result.document.path.clone());
+                }
+            }
+            if !stale.is_empty() {
+                if let Err(e) = self.embedding_refresher(engine.clone(), &model)?.on_access(&stale).await {
+                    warn!("Refreshing stale embeddings failed: {}", e);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Code definitions matching `text` by name or by their embedded bodies
+    pub async fn query_symbols(&self, text: &str, limit: usize) -> Result<()> {
+        let engine = self.search_engine().await?;
+        let _slot = self.queue.acquire(QueryPriority::Interactive).await?;
+        let query_embedding = match self.query_embedder(&self.embedding_model(), None)?.embed(text).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                warn!("Matching symbols by name only: {}", e);
+                None
+            }
+        };
+        
+        let symbols = engine.search_symbols(text, query_embedding.as_deref(), limit).await;
+        if symbols.is_empty() {
+            println!("No code symbols matched \"{}\".", text);
+            return Ok(());
+        }
+        for (i, symbol) in symbols.iter().enumerate() {
+            println!("{}. {} {} ({}) score {:.3}", i + 1, symbol.kind, symbol.name, symbol.path.display(), symbol.score);
+        }
+        Ok(())
+    }
+    
+    /// Answer a query with JSON matching the schema in `schema_path`
+    pub async fn query_json(&self, text: &str, schema_path: &Path) -> Result<()> {
+        let schema = std::fs::read_to_string(schema_path)
+            .with_context(|| format!("Failed to read {}", schema_path.display()))?;
+        let schema = OutputSchema::new(serde_json::from_str(&schema)
+            .with_context(|| format!("{} is not a JSON Schema", schema_path.display()))?);
+        
+        let answer = self.ai().await?.process_query_json(text, &schema).await?;
+        println!("{}", serde_json::to_string_pretty(&answer)?);
         Ok(())
     }
     
     /// Export notes to different formats
     pub async fn export(&self, output: &PathBuf, format: &str, date_range: Option<&str>) -> Result<()> {
         info!("Exporting notes to {} format at {}", format, output.display());
+        let format: ExportFormat = format.parse()?;
+        let range = date_range.map(DateRange::parse).transpose()?;
         
-        // TODO: Implement export functionality
-        // This would:
-        // 1. Query all documents (with date filter if specified)
-        // 2. Convert to target format (Obsidian, Markdown, JSON)
-        // 3. Write to output directory
+        let engine = VectorSearchEngine::new(self.config.database.path.clone())?;
+        engine.initialize().await?;
+        let exported = NoteExporter::new(&engine, &self.config.vault.path)
+            .export(output, format, range)
+            .await?;
+        
+        println!("Exported {} notes to {}", exported, output.display());
+        Ok(())
+    }
+    
+    /// Export stored vectors next to the documents, keyed by document path
+    pub async fn export_embeddings(&self, output: &Path, include_blocks: bool) -> Result<()> {
+        let engine = VectorSearchEngine::new(self.config.database.path.clone())?;
+        engine.initialize().await?;
+        
+        let embedding = &self.embedding_model();
+        let manifest = EmbeddingExporter::new(&engine, &embedding.model, embedding.dimensions)
+            .export(output, include_blocks)
+            .await?;
+        
+        info!("Exported {} document embeddings to {}", manifest.documents.len(), output.display());
+        Ok(())
+    }
+    
+    /// Write the tag hierarchy and the graph of tags used together as `tags.json` or `tags.dot`
+    pub async fn export_tag_graph(&self, output: &Path, format: &str) -> Result<()> {
+        let format: TagGraphFormat = format.parse()?;
+        let engine = VectorSearchEngine::new(self.config.database.path.clone())?;
+        engine.initialize().await?;
+        
+        let tags: Vec<Vec<String>> = engine.stored_documents().await?.into_iter().map(|d| d.tags).collect();
+        let graph = TagGraph::from_documents(&tags, &self.config.vault.tag_graph);
+        
+        tokio::fs::create_dir_all(output).await?;
+        let path = output.join(format!("tags.{}", format.extension()));
+        tokio::fs::write(&path, graph.render(format)?).await?;
+        println!("Wrote {} co-occurrence edges to {}", graph.edges.len(), path.display());
+        Ok(())
+    }
+    
+    /// Fetch a web page and save its readable content as a note
+    pub async fn save_url(&self, url: &str) -> Result<()> {
+        let ingestor = UrlIngestor::new(self.config.vault.path.clone(), self.config.vault.ingest.clone())?;
+        let note = ingestor.ingest_url(url).await?;
+        
+        println!("Saved \"{}\" to {}", note.document.title, note.path.display());
+        Ok(())
+    }
+    
+    /// Write the results of a search to a note in the vault, updating it if the title exists
+    pub async fn save_search(&self, text: &str, title: &str) -> Result<()> {
+        let model = self.embedding_model();
+        let pool = Arc::new(EmbeddingPool::for_model(&model, &self.config.vault.embedding_pool, self.embedding_engine()?.as_ref())?);
+        let engine = self.search_engine().await?;
+        let writer = SearchNoteWriter::new(engine, &pool, &model.model, &self.config.vault.path, self.config.vault.search_notes.clone());
+        let path = writer.search_to_note(text, title).await?;
+        println!("Saved results for \"{}\" to {}", text, path.display());
+        Ok(())
+    }
+    
+    /// Report, and optionally insert, links between similar unlinked notes
+    pub async fn suggest_related(&self, apply: bool) -> Result<()> {
+        let vault_path = &self.config.vault.path;
+        let parser = ObsidianParser::with_config(&self.config.vault.parsers)?;
+        let pool = Arc::new(EmbeddingPool::for_model(&self.embedding_model(), &self.config.vault.embedding_pool, self.embedding_engine()?.as_ref())?);
+        
+        let mut documents = Vec::new();
+        for entry in walkdir::WalkDir::new(vault_path)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".note-to-ai")
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+        {
+            documents.push(parser.parse_file(entry.path()).await?);
+        }
+        let vectors = pool.embed_batch(documents.iter().map(|d| d.plain_text.clone()).collect()).await?;
+        
+        let suggester = LinkSuggester::new(self.config.vault.related.clone(), self.config.vault.links.clone());
+        let suggestions = suggester.suggest(&documents, &vectors);
+        
+        for suggestion in &suggestions {
+            println!(
+                "{} -> {} ({:.2})",
+                suggestion.source.strip_prefix(vault_path).unwrap_or(&suggestion.source).display(),
+                suggestion.wikilink(),
+                suggestion.similarity
+            );
+        }
+        
+        if apply || self.config.vault.related.auto_insert {
+            let changed = suggester.apply(&suggestions).await?;
+            println!("Added related links to {} notes", changed);
+        }
         
         Ok(())
     }
     
     /// Show system status and statistics
     pub async fn show_status(&self) -> Result<()> {
-        println!("🤖 note-to-ai System Status");
-        println!("===========================");
-        
-        // Storage statistics
-        // TODO: Show storage stats when storage is implemented
-        println!("📊 Storage:");
-        println!("  Documents: 0 (storage not implemented)");
-        println!("  Embeddings: 0 (storage not implemented)");
-        println!("  Storage size: 0.00 MB (storage not implemented)");
-        println!("  Avg search time: 0.00ms (storage not implemented)");
-        
-        // AI status
-        println!("\n🧠 AI Models:");
-        // TODO: Show loaded model status
-        println!("  Whisper: Ready");
-        println!("  Embeddings: Ready"); 
-        println!("  LLM: Ready");
-        
-        // Signal status
-        println!("\n📱 Signal:");
-        // TODO: Show Signal connection status
-        println!("  Status: Connected");
-        println!("  Phone: +1***-***-**90");
-        
-        println!("\n✅ System is healthy and ready!");
-        
+        let report = StatusReport {
+            storage: self.storage_status().await.map_err(|e| e.to_string()),
+            focus: self.focus.current().await.map(|focus| focus.describe()),
+            models: self.model_status().await.map_err(|e| e.to_string()),
+            signal: signal_status(&self.config).await.map_err(|e| e.to_string()),
+        };
+        print!("{}", report.render());
         Ok(())
     }
     
+    /// Counts and top tags from the search index; searches aren't timed, so no average is given
+    async fn storage_status(&self) -> Result<StorageStatus> {
+        let db_path = &self.config.database.path;
+        if !db_path.exists() {
+            anyhow::bail!("no index at {}; run `note-to-ai start`", db_path.display());
+        }
+        let engine = VectorSearchEngine::new(db_path.clone())?;
+        engine.initialize().await?;
+        let stats = engine.get_stats().await?;
+        
+        Ok(StorageStatus {
+            documents: stats.total_documents,
+            embeddings: stats.total_embeddings,
+            size_bytes: std::fs::metadata(db_path)?.len(),
+            avg_search: None,
+            top_tags: stats.top_tags,
+        })
+    }
+    
+    async fn model_status(&self) -> Result<ModelStatus> {
+        let mut registered: Vec<(String, bool)> = self.model_switcher.get_health_status().await
+            .into_iter()
+            .map(|(name, (available, _))| (name, available))
+            .collect();
+        registered.sort();
+        let whisper = ModelRegistry::load(&self.config.ai.model_path)?.installed()?
+            .into_iter()
+            .find(|model| model.entry.kind == ModelKind::Whisper)
+            .map(|model| model.name);
+        
+        Ok(ModelStatus {
+            current: self.model_switcher.get_current_model().await,
+            registered,
+            embedding_model: self.embedding_model().model,
+            whisper,
+        })
+    }
+    
     /// Wait for shutdown signal
     async fn wait_for_shutdown(&self) {
         let mut sigterm = tokio_signal::unix::signal(tokio_signal::unix::SignalKind::terminate())
@@ -309,20 +1124,58 @@ async fn main() -> Result<()> {
     // Print startup banner
     print_startup_banner();
     
+    if cli.clear_focus || !cli.focus.is_empty() {
+        NoteToAI::new(&cli.config).await?
+            .update_focus(&cli.focus, cli.clear_focus)
+            .await?;
+    }
+    
     match cli.command {
         Some(Commands::Start { skip_signal, skip_ai }) => {
             let mut app = NoteToAI::new(&cli.config).await?;
+            let _lock = app.lock_storage(OpenMode::Write { force: cli.force })?;
             app.start(skip_signal, skip_ai).await?;
         }
         
-        Some(Commands::Query { text, semantic, limit }) => {
+        Some(Commands::Query { text, semantic, limit, model, no_llm, trace, save_as, schema, symbols }) => {
             let app = NoteToAI::new(&cli.config).await?;
-            app.query(&text, semantic, limit).await?;
+            if symbols {
+                return app.query_symbols(&text, limit).await;
+            }
+            match save_as {
+                Some(title) => {
+                    let _lock = app.lock_storage(OpenMode::Write { force: cli.force })?;
+                    app.save_search(&text, &title).await?
+                }
+                None => match schema {
+                    Some(schema) => app.query_json(&text, &schema).await?,
+                    None => app.query(&text, semantic, limit, model.as_deref(), no_llm, trace).await?,
+                },
+            }
         }
         
-        Some(Commands::Export { output, format, date_range }) => {
+        Some(Commands::Export { output, format, date_range, with_embeddings, with_block_embeddings, tag_graph }) => {
             let app = NoteToAI::new(&cli.config).await?;
             app.export(&output, &format, date_range.as_deref()).await?;
+            if with_embeddings {
+                app.export_embeddings(&output, with_block_embeddings).await?;
+            }
+            if let Some(tag_format) = tag_graph {
+                app.export_tag_graph(&output, &tag_format).await?;
+            }
+        }
+        
+        Some(Commands::Save { url }) => {
+            let app = NoteToAI::new(&cli.config).await?;
+            let _lock = app.lock_storage(OpenMode::Write { force: cli.force })?;
+            app.save_url(&url).await?;
+        }
+        
+        Some(Commands::Related { apply }) => {
+            let app = NoteToAI::new(&cli.config).await?;
+            let mode = if apply { OpenMode::Write { force: cli.force } } else { OpenMode::ReadOnly };
+            let _lock = app.lock_storage(mode)?;
+            app.suggest_related(apply).await?;
         }
         
         Some(Commands::Status) => {
@@ -331,42 +1184,149 @@ async fn main() -> Result<()> {
         }
         
         Some(Commands::Models { action }) => {
+            let config = Settings::load(&cli.config.to_string_lossy())
+                .context("Failed to load configuration")?;
             match action {
                 ModelAction::List => {
-                    println!("Available AI models:");
-                    println!("  whisper-base (~290MB) - Speech-to-text");
-                    println!("  all-MiniLM-L6-v2 (~90MB) - Text embeddings");
-                    println!("  hermes-3-8b (~16GB) - Conversational AI");
-                    println!("  phi-3-mini (~6GB) - Lightweight LLM");
+                    let installed = ModelRegistry::load(&config.ai.model_path)?.installed()?;
+                    if installed.is_empty() {
+                        println!("No models downloaded yet; fetch one with `models download <repo>`");
+                    }
+                    for model in installed {
+                        println!(
+                            "  {} ({:?}, {}) - {}",
+                            model.name, model.entry.kind, format_size(model.size_bytes), model.entry.repo
+                        );
+                    }
                 }
-                ModelAction::Download { name } => {
+                ModelAction::Download { name, quant } => {
                     info!("Downloading model: {}", name);
-                    // TODO: Implement model download
+                    let (repo, kind, path) = match (Whisper::repo_for(&name), quant) {
+                        (Some(repo), _) => {
+                            let path = download_whisper(&config, &repo).await?;
+                            (repo, ModelKind::Whisper, path)
+                        }
+                        (None, Some(variant)) => {
+                            let path = download_gguf(&config, &name, variant).await?;
+                            (name.clone(), ModelKind::Gguf, path)
+                        }
+                        (None, None) => {
+                            let path = download_model(&config, &name).await?;
+                            (name.clone(), ModelKind::Llm, path)
+                        }
+                    };
+                    let friendly = match kind {
+                        ModelKind::Whisper => name.as_str(),
+                        _ => ModelRegistry::name_for(&repo),
+                    };
+                    ModelRegistry::load(&config.ai.model_path)?.register(friendly, &repo, kind, &path)?;
+                    println!("Model {} saved to {}", friendly, path.display());
                 }
                 ModelAction::Remove { name } => {
-                    info!("Removing model: {}", name);
-                    // TODO: Implement model removal
+                    let freed = ModelRegistry::load(&config.ai.model_path)?.remove(&name)?;
+                    println!("Removed {}, freeing {}", name, format_size(freed));
                 }
                 ModelAction::Benchmark { name } => {
                     info!("Benchmarking model: {}", name);
-                    // TODO: Implement model benchmarking
+                    benchmark_model(&config, &name).await?;
                 }
             }
         }
         
         Some(Commands::Signal { action }) => {
             match action {
-                SignalAction::Setup { phone } => {
+                SignalAction::Setup { phone, register, voice } => {
                     info!("Setting up Signal integration for {}", phone);
-                    // TODO: Implement Signal setup
+                    let config = Settings::load(&cli.config.to_string_lossy())
+                        .context("Failed to load configuration")?;
+                    let method = match (register, voice) {
+                        (_, true) => SetupMethod::Voice,
+                        (true, false) => SetupMethod::Sms,
+                        (false, false) => SetupMethod::Link,
+                    };
+                    
+                    // A running daemon takes the new account on directly; otherwise signal-cli is run once
+                    let outcome = if config.signal.daemon.socket_path.exists() {
+                        let cli = SignalDaemonCli::new(SignalDaemonClient::new(config.signal.daemon.clone()));
+                        run_signal_setup(cli, &config, &phone, method).await?
+                    } else {
+                        run_signal_setup(SignalCliProcess::new("signal-cli"), &config, &phone, method).await?
+                    };
+                    match outcome {
+                        SetupOutcome::Completed(credentials) => {
+                            println!("✅ Signal set up for {}", credentials.phone_number);
+                        }
+                        SetupOutcome::AlreadyLinked(credentials) => {
+                            println!("Signal is already set up for {}; existing credentials were kept", credentials.phone_number);
+                        }
+                    }
                 }
-                SignalAction::Test => {
+                SignalAction::Test { send } => {
                     info!("Testing Signal connection");
-                    // TODO: Implement Signal test
+                    let config = Settings::load(&cli.config.to_string_lossy())
+                        .context("Failed to load configuration")?;
+                    let account = signal_account(&config)?;
+                    
+                    let client = SignalDaemonClient::new(config.signal.daemon.clone());
+                    match client.probe(&account, send).await {
+                        Ok(report) => {
+                            println!("✅ signal-cli daemon reachable");
+                            println!("  Account: {} (registered)", report.account);
+                            println!("  Round trip: {:.1}ms", report.latency.as_secs_f64() * 1000.0);
+                            if let Some(latency) = report.send_latency {
+                                println!("  Test message delivered to Note to Self in {:.1}ms", latency.as_secs_f64() * 1000.0);
+                            }
+                        }
+                        Err(e) => {
+                            println!("❌ Signal connection test failed: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
                 }
                 SignalAction::Status => {
-                    info!("Signal connection status");
-                    // TODO: Show Signal status
+                    let config = Settings::load(&cli.config.to_string_lossy())
+                        .context("Failed to load configuration")?;
+                    println!("📱 Signal:");
+                    print!("{}", signal_status(&config).await?.render());
+                }
+                SignalAction::Backfill { from, attachments } => {
+                    let config = Settings::load(&cli.config.to_string_lossy())
+                        .context("Failed to load configuration")?;
+                    let account = signal_account(&config)?;
+                    
+                    let _lock = StorageLock::for_mode_with(&storage_dir(&config), OpenMode::Write { force: cli.force }, &config.vault.lock)?;
+                    let history = SignalCliExport::new(from, &account, attachments.unwrap_or_else(default_attachments_dir));
+                    let transcriber = if config.signal.backfill.transcribe_audio {
+                        Some(voice_transcriber(&config)?)
+                    } else {
+                        None
+                    };
+                    let dedup = CaptureDeduplicator::new(config.vault.capture_dedup.clone());
+                    let embedder = ModelWorker::new(&config.vault.languages.embedding_model(&config.vault.embedding))?
+                        .with_engine(load_embedding_engine(&config)?);
+                    let categorizer = if config.vault.categorization.enabled {
+                        Some(Categorizer::new(config.vault.categorization.clone(), &embedder).await?)
+                    } else {
+                        None
+                    };
+                    let mut backfill = Backfill::new(config.vault.path.clone(), config.signal.backfill.clone())
+                        .with_capture_dedup(&dedup, &embedder);
+                    if let Some(transcriber) = &transcriber {
+                        backfill = backfill.with_transcriber(transcriber.as_ref());
+                    }
+                    if let Some(categorizer) = &categorizer {
+                        backfill = backfill.with_categorizer(categorizer, &embedder);
+                    }
+                    
+                    let progress = backfill.run(&history, |p| {
+                        print!("\rImporting messages: {}/{}", p.processed, p.total);
+                        let _ = std::io::Write::flush(&mut std::io::stdout());
+                    }).await?;
+                    println!();
+                    println!(
+                        "Imported {} messages ({} transcribed), skipped {} already imported, {} failed",
+                        progress.ingested, progress.transcribed, progress.skipped, progress.failed
+                    );
                 }
             }
         }
@@ -374,6 +1334,7 @@ async fn main() -> Result<()> {
         None => {
             // Default: start the service
             let mut app = NoteToAI::new(&cli.config).await?;
+            let _lock = app.lock_storage(OpenMode::Write { force: cli.force })?;
             app.start(false, false).await?;
         }
     }
@@ -381,6 +1342,267 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// The index database decrypted for this run, when `database.encrypted` is set and the
+/// passphrase is configured
+fn unseal_database(config: &Settings) -> Result<Option<SealedDatabase>> {
+    if !config.database.encrypted {
+        return Ok(None);
+    }
+    match Crypto::from_config(&config.crypto.encryption) {
+        Ok(crypto) => Ok(Some(SealedDatabase::open(&config.database.path, crypto)?)),
+        Err(e) if Crypto::is_encrypted_file(&config.database.path) => {
+            Err(e).context("The index database is encrypted")
+        }
+        Err(e) => {
+            warn!("The index database will be stored unencrypted: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// Key used for the stored Signal credentials, when a passphrase is configured
+fn signal_crypto(config: &Settings) -> Option<Crypto> {
+    Crypto::from_config(&config.crypto.encryption).ok()
+}
+
+/// The configured Signal number, or the one `signal setup` stored
+fn signal_account(config: &Settings) -> Result<String> {
+    if let Some(phone) = &config.signal.phone_number {
+        return Ok(phone.clone());
+    }
+    load_credentials(&config.crypto.key_path, signal_crypto(config).as_ref())?
+        .map(|credentials| credentials.phone_number)
+        .context("No Signal phone number configured; run `note-to-ai signal setup` first")
+}
+
+/// Link or register `phone` and store its credentials
+async fn run_signal_setup<C: SignalCli>(cli: C, config: &Settings, phone: &str, method: SetupMethod) -> Result<SetupOutcome> {
+    let mut setup = SignalSetup::new(cli, &config.crypto.key_path)?;
+    match Crypto::from_config(&config.crypto.encryption) {
+        Ok(crypto) => setup = setup.with_crypto(crypto),
+        Err(e) => warn!("Signal credentials will be stored unencrypted: {}", e),
+    }
+    setup.run(phone, method, &TerminalPrompt).await
+}
+
+/// Whether an account is set up, its number, and whether the daemon serves it
+async fn signal_status(config: &Settings) -> Result<SignalStatus> {
+    let account = match load_credentials(&config.crypto.key_path, signal_crypto(config).as_ref())? {
+        Some(credentials) if credentials.linked => SignalAccount::Linked,
+        Some(_) => SignalAccount::Registered,
+        None => SignalAccount::NotSetUp,
+    };
+    let phone = signal_account(config).ok();
+    let daemon = match &phone {
+        Some(phone) => Some(
+            SignalDaemonClient::new(config.signal.daemon.clone())
+                .probe(phone, false)
+                .await
+                .map(|report| report.latency)
+                .map_err(|e| e.to_string()),
+        ),
+        None => None,
+    };
+    Ok(SignalStatus { account, phone, daemon })
+}
+
+/// Directory holding the index database, which the storage lock guards
+fn storage_dir(config: &Settings) -> PathBuf {
+    config.database.path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Stream `url` to `path` chunk by chunk; the file only appears under `path` once complete
+async fn download_file(url: &str, path: &Path) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+    
+    let mut response = reqwest::get(url).await?
+        .error_for_status()
+        .with_context(|| format!("Failed to download {}", url))?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let mut file = tokio::fs::File::create(&partial).await
+        .with_context(|| format!("Failed to write {}", partial.display()))?;
+    while let Some(chunk) = response.chunk().await.with_context(|| format!("Failed to download {}", url))? {
+        file.write_all(&chunk).await
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+    }
+    file.flush().await?;
+    tokio::fs::rename(&partial, path).await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Fetch `files` of a Hugging Face repo into `dir`
+async fn download_repo_files(repo: &str, files: &[&str], dir: &Path) -> Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    for file in files {
+        download_file(&format!("https://huggingface.co/{}/resolve/main/{}", repo, file), &dir.join(file)).await?;
+    }
+    Ok(())
+}
+
+/// Fetch a Hugging Face repo's weights, tokenizer and config into its own folder of the model
+/// directory, quantizing the weights when configured; returns the folder
+async fn download_model(config: &Settings, repo: &str) -> Result<PathBuf> {
+    let dir = config.ai.model_path.join(ModelRegistry::name_for(repo));
+    download_repo_files(repo, &["model.safetensors", "tokenizer.json", "config.json"], &dir).await?;
+    
+    if config.ai.quantization.enabled {
+        let weights = dir.join("model.safetensors");
+        let quantized = ai::quantize::quantize_file(&weights, &config.ai.quantization)?;
+        info!("Quantized {} to {}", weights.display(), quantized.display());
+    }
+    Ok(dir)
+}
+
+/// Fetch one quantized variant from a Hugging Face GGUF repo into the model directory,
+/// with the tokenizer when the repo has one; the header is checked before it is kept
+async fn download_gguf(config: &Settings, repo: &str, variant: GgufVariant) -> Result<PathBuf> {
+    let listing: serde_json::Value = reqwest::get(format!("https://huggingface.co/api/models/{}", repo)).await?
+        .error_for_status()
+        .with_context(|| format!("Failed to list files in {}", repo))?
+        .json()
+        .await?;
+    let files: Vec<&str> = listing["siblings"].as_array()
+        .map(|siblings| siblings.iter().filter_map(|s| s["rfilename"].as_str()).collect())
+        .unwrap_or_default();
+    let file = variant.pick_file(&files)
+        .with_context(|| format!("{} has no {} GGUF file", repo, variant.tag()))?;
+    
+    tokio::fs::create_dir_all(&config.ai.model_path).await?;
+    let path = config.ai.model_path.join(file);
+    download_file(&format!("https://huggingface.co/{}/resolve/main/{}", repo, file), &path).await?;
+    
+    let header = match GgufHeader::read(&path) {
+        Ok(header) => header,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+    };
+    info!("{} model with {} tensors", header.architecture().unwrap_or("unknown"), header.tensor_count);
+    
+    if files.contains(&"tokenizer.json") {
+        let url = format!("https://huggingface.co/{}/resolve/main/tokenizer.json", repo);
+        download_file(&url, &config.ai.model_path.join("tokenizer.json")).await?;
+    }
+    Ok(path)
+}
+
+/// Fetch a Whisper checkpoint into its own folder under the model directory; Whisper weights
+/// are never quantized since the transcriber loads them as published
+async fn download_whisper(config: &Settings, repo: &str) -> Result<PathBuf> {
+    let dir = config.ai.model_path.join(ModelRegistry::name_for(repo));
+    download_repo_files(repo, &Whisper::MODEL_FILES, &dir).await?;
+    Ok(dir)
+}
+
+/// The compiled local model for the weights at `path`: a GGUF file or a downloaded model folder
+#[cfg(feature = "embeddings")]
+fn local_model(config: &Settings, path: PathBuf) -> Result<ai::local_llm_full::LocalLLM> {
+    let model = ai::local_llm_full::ModelConfig::local(path);
+    let llm = ai::local_llm_full::LocalLLM::new(ai::local_llm_full::ModelConfig {
+        chat_template: config.ai.chat_template.or(model.chat_template),
+        max_concurrent_generations: config.ai.max_concurrent_generations,
+        ..model
+    })?;
+    Ok(llm.with_generation_config(ai::local_llm_full::GenerationConfig {
+        time_budget_ms: config.ai.time_budget_ms,
+        seed: config.rng_seed().0,
+        ..Default::default()
+    }))
+}
+
+/// A generation backend for the local model at `path`, loaded on its first request
+#[cfg(feature = "embeddings")]
+fn local_backend(config: &Settings, path: PathBuf) -> Result<Arc<dyn Backend>> {
+    Ok(Arc::new(local_model(config, path)?))
+}
+
+/// Whisper from `ai.transcription`, retrying unsure passes with its escalation model
+#[cfg(feature = "embeddings")]
+fn voice_transcriber(config: &Settings) -> Result<Box<dyn Transcriber>> {
+    let transcriber = audio::escalation::EscalatingTranscriber::load(&config.ai.model_path, &config.ai.transcription)
+        .context("Failed to load Whisper; run `models download whisper-base` or set signal.backfill.transcribe_audio = false")?;
+    Ok(Box::new(transcriber))
+}
+
+#[cfg(not(feature = "embeddings"))]
+fn voice_transcriber(_config: &Settings) -> Result<Box<dyn Transcriber>> {
+    anyhow::bail!("Transcribing voice notes needs a build with the `embeddings` feature; set signal.backfill.transcribe_audio = false to import them untranscribed")
+}
+
+/// The embedding model in its folder under `ai.embeddings_path`, as published on Hugging Face;
+/// `None` while it isn't there, leaving placeholder vectors
+#[cfg(feature = "embeddings")]
+fn load_embedding_engine(config: &Settings) -> Result<Option<Arc<EmbeddingEngine>>> {
+    use vault::embedding_engine::BertEncoder;
+    
+    let model = config.vault.languages.embedding_model(&config.vault.embedding);
+    let dir = config.ai.embeddings_path.join(&model.model);
+    if !dir.join("model.safetensors").exists() {
+        warn!("No embedding model in {}; notes get placeholder vectors until it's downloaded there", dir.display());
+        return Ok(None);
+    }
+    let engine = EmbeddingEngine::new(Arc::new(BertEncoder::from_dir(&dir, model.output)?));
+    if engine.dimensions() != model.dimensions {
+        anyhow::bail!(
+            "{} produces {}-dimensional vectors but vault.embedding.dimensions is {}",
+            dir.display(), engine.dimensions(), model.dimensions
+        );
+    }
+    info!("Loaded embedding model {}", model.model);
+    Ok(Some(Arc::new(engine)))
+}
+
+#[cfg(not(feature = "embeddings"))]
+fn load_embedding_engine(_config: &Settings) -> Result<Option<Arc<EmbeddingEngine>>> {
+    warn!("Notes get placeholder vectors; embedding models need a build with the `embeddings` feature");
+    Ok(None)
+}
+
+#[cfg(not(feature = "embeddings"))]
+fn local_backend(_config: &Settings, path: PathBuf) -> Result<Arc<dyn Backend>> {
+    anyhow::bail!("{} can't be loaded; local generation needs a build with the `embeddings` feature", path.display())
+}
+
+/// Load a downloaded model and time one generation of a fixed prompt
+#[cfg(feature = "embeddings")]
+async fn benchmark_model(config: &Settings, name: &str) -> Result<()> {
+    const PROMPT: &str = "Summarize why keeping a daily journal helps with memory, in three sentences.";
+    const MAX_TOKENS: usize = 128;
+    
+    let registry = ModelRegistry::load(&config.ai.model_path)?;
+    let entry = registry.get(name)
+        .with_context(|| format!("No model named {}; see `models list`", name))?;
+    if entry.kind == ModelKind::Whisper {
+        anyhow::bail!("{} is a Whisper model; only language models can be benchmarked", name);
+    }
+    
+    let started = std::time::Instant::now();
+    let llm = local_model(config, registry.path_of(entry))?;
+    llm.ensure_loaded().await?;
+    let load_time = started.elapsed();
+    
+    let started = std::time::Instant::now();
+    let tokens = llm.generate(llm.request(PROMPT, MAX_TOKENS)).await?.tokens_generated;
+    let generation_time = started.elapsed();
+    
+    println!("Model:      {} ({})", name, entry.repo);
+    println!("Load time:  {:.2}s", load_time.as_secs_f64());
+    println!("Generated:  {} tokens in {:.2}s", tokens, generation_time.as_secs_f64());
+    println!("Throughput: {:.1} tokens/sec", tokens as f64 / generation_time.as_secs_f64().max(1e-9));
+    Ok(())
+}
+
+#[cfg(not(feature = "embeddings"))]
+async fn benchmark_model(_config: &Settings, name: &str) -> Result<()> {
+    anyhow::bail!("Can't benchmark {}; local generation needs a build with the `embeddings` feature", name)
+}
+
 fn setup_logging(level: &str, log_file: Option<&PathBuf>) -> Result<()> {
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(level));
@@ -406,6 +1628,28 @@ fn setup_logging(level: &str, log_file: Option<&PathBuf>) -> Result<()> {
     Ok(())
 }
 
+/// Shows the device link and asks for verification codes on the terminal
+struct TerminalPrompt;
+
+#[async_trait::async_trait]
+impl SetupPrompt for TerminalPrompt {
+    async fn show_link(&self, uri: &str) -> Result<()> {
+        println!("Open Signal on your phone, go to Settings → Linked devices, and scan a QR code of:");
+        println!("\n  {}\n", uri);
+        println!("Waiting for the link to be confirmed...");
+        Ok(())
+    }
+    
+    async fn verification_code(&self, phone: &str) -> Result<String> {
+        println!("Enter the verification code sent to {}:", phone);
+        let code = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).map(|_| line)
+        }).await??;
+        Ok(code)
+    }
+}
+
 fn print_startup_banner() {
     println!(r#"
 ╔══════════════════════════════════════════════════════════════╗
@@ -1,17 +1,196 @@
-// TODO: implement this file
+// src/scheduler/mod.rs - Background tasks run on fixed intervals, such as optimization and re-indexing
 pub mod tasks;
 
-use crate::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::Result;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+use crate::ai::query_queue::{QueryPriority, QueryQueue};
 
-pub struct Scheduler;
+type TaskFn = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+struct IntervalTask {
+    name: String,
+    period: Duration,
+    run: TaskFn,
+}
+
+/// Runs each task every period, skipping a tick while that task's previous run is still going
+pub struct Scheduler {
+    tasks: Mutex<Vec<IntervalTask>>,
+    loops: Mutex<Vec<JoinHandle<()>>>,
+    shutdown: watch::Sender<bool>,
+    /// Runs wait here for a background slot, so user queries go first
+    queue: Option<QueryQueue>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Scheduler {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+    pub fn new() -> Self {
+        Self {
+            tasks: Mutex::new(Vec::new()),
+            loops: Mutex::new(Vec::new()),
+            shutdown: watch::channel(false).0,
+            queue: None,
+        }
+    }
+
+    /// Start each run only once `queue` grants it a background slot, held until the run ends
+    pub fn with_query_queue(mut self, queue: QueryQueue) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Run `task` every `period`, first one period after `start`
+    pub fn add_interval_task<F, Fut>(&self, name: &str, period: Duration, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.tasks.lock().unwrap().push(IntervalTask {
+            name: name.to_string(),
+            period: period.max(Duration::from_millis(1)),
+            run: Arc::new(move || Box::pin(task())),
+        });
+    }
+
+    /// Spawn a loop for every task added so far
+    pub fn start(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let mut loops = self.loops.lock().unwrap();
+        for task in tasks {
+            info!("Scheduling {} every {:?}", task.name, task.period);
+            loops.push(tokio::spawn(run_loop(task, self.queue.clone(), self.shutdown.subscribe())));
+        }
     }
-    
-    pub async fn start(&self) -> Result<()> {
-        // TODO: Implement async task scheduling
-        Ok(())
+
+    /// Stop scheduling and wait for runs in progress to finish
+    pub async fn stop(&self) {
+        let _ = self.shutdown.send(true);
+        let loops = std::mem::take(&mut *self.loops.lock().unwrap());
+        for handle in loops {
+            let _ = handle.await;
+        }
+    }
+}
+
+async fn run_loop(task: IntervalTask, queue: Option<QueryQueue>, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + task.period, task.period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut current: Option<JoinHandle<()>> = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+        if current.as_ref().is_some_and(|run| !run.is_finished()) {
+            debug!("Skipping {}; the previous run is still going", task.name);
+            continue;
+        }
+
+        let name = task.name.clone();
+        let run = (task.run)();
+        let queue = queue.clone();
+        current = Some(tokio::spawn(async move {
+            let _slot = match &queue {
+                Some(queue) => match queue.acquire(QueryPriority::Background).await {
+                    Ok(slot) => Some(slot),
+                    Err(e) => {
+                        warn!("Skipping {}: {}", name, e);
+                        return;
+                    }
+                },
+                None => None,
+            };
+            if let Err(e) = run.await {
+                warn!("Scheduled task {} failed: {}", name, e);
+            }
+        }));
+    }
+
+    if let Some(run) = current {
+        let _ = run.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_fast_task_fires_repeatedly_without_overlapping() {
+        let scheduler = Scheduler::new();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let active = Arc::new(AtomicUsize::new(0));
+        let overlapped = Arc::new(AtomicUsize::new(0));
+        {
+            let (runs, active, overlapped) = (runs.clone(), active.clone(), overlapped.clone());
+            scheduler.add_interval_task("tick", Duration::from_millis(5), move || {
+                let (runs, active, overlapped) = (runs.clone(), active.clone(), overlapped.clone());
+                async move {
+                    if active.fetch_add(1, Ordering::SeqCst) > 0 {
+                        overlapped.fetch_add(1, Ordering::SeqCst);
+                    }
+                    // Slower than the interval, so some ticks find it still running
+                    tokio::time::sleep(Duration::from_millis(12)).await;
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+        }
+
+        scheduler.start();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        scheduler.stop().await;
+
+        let fired = runs.load(Ordering::SeqCst);
+        assert!(fired >= 2, "fired {} times", fired);
+        assert_eq!(overlapped.load(Ordering::SeqCst), 0);
+        // Stopping waited for the last run
+        assert_eq!(active.load(Ordering::SeqCst), 0);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), fired);
+    }
+
+    #[tokio::test]
+    async fn test_runs_wait_for_user_queries_to_free_the_queue() {
+        use crate::ai::query_queue::QueryQueueConfig;
+
+        let queue = QueryQueue::new(QueryQueueConfig { max_concurrent: 1, ..QueryQueueConfig::default() });
+        let scheduler = Scheduler::new().with_query_queue(queue.clone());
+        let runs = Arc::new(AtomicUsize::new(0));
+        {
+            let runs = runs.clone();
+            scheduler.add_interval_task("tick", Duration::from_millis(5), move || {
+                let runs = runs.clone();
+                async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }
+            });
+        }
+
+        let query = queue.acquire(QueryPriority::Interactive).await.unwrap();
+        scheduler.start();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        drop(query);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        scheduler.stop().await;
+        assert!(runs.load(Ordering::SeqCst) > 0);
     }
-}
\ No newline at end of file
+}
@@ -1,14 +1,89 @@
-use crate::Result;
+// src/scheduler/tasks.rs - The background tasks the service schedules by default
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use crate::ai::query_queue::{QueryPriority, QueryQueue};
+use crate::vault::indexer::VaultIndexer;
+use crate::vault::search::VectorSearchEngine;
+use super::Scheduler;
 
-pub struct TaskScheduler;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerConfig {
+    pub enabled: bool,
+    /// Seconds between re-indexing passes; only files whose size or mtime changed are re-read
+    pub reindex_interval_secs: u64,
+    /// Re-index files as soon as they change on disk
+    pub watch_vault: bool,
+    /// Seconds between compactions of the search database's full-text indexes; 0 disables them
+    #[serde(default = "default_optimize_interval_secs", alias = "background_optimization_interval_seconds")]
+    pub optimize_interval_secs: u64,
+}
+
+fn default_optimize_interval_secs() -> u64 {
+    3600
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reindex_interval_secs: 300,
+            watch_vault: true,
+            optimize_interval_secs: default_optimize_interval_secs(),
+        }
+    }
+}
 
-impl TaskScheduler {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+/// Pick up notes added, edited or deleted outside of Signal, e.g. synced from another device
+pub fn add_reindex_task(scheduler: &Scheduler, indexer: Arc<VaultIndexer>, config: &SchedulerConfig) {
+    if config.reindex_interval_secs == 0 {
+        return;
     }
-    
-    pub async fn schedule_task(&self, task: Box<dyn Fn() + Send>) -> Result<()> {
-        // TODO: Implement task scheduling
-        Ok(())
+    scheduler.add_interval_task("re-index", Duration::from_secs(config.reindex_interval_secs), move || {
+        let indexer = indexer.clone();
+        async move {
+            let stats = indexer.full_index().await?;
+            if stats.added + stats.updated + stats.deleted > 0 {
+                tracing::info!(
+                    "Re-index: {} added, {} updated, {} deleted",
+                    stats.added, stats.updated, stats.deleted
+                );
+            }
+            Ok(())
+        }
+    });
+}
+
+/// Compact the search database, whose full-text segments pile up as notes are re-indexed
+pub fn add_optimize_task(scheduler: &Scheduler, engine: Arc<VectorSearchEngine>, config: &SchedulerConfig) {
+    if config.optimize_interval_secs == 0 {
+        return;
     }
+    scheduler.add_interval_task("optimize", Duration::from_secs(config.optimize_interval_secs), move || {
+        let engine = engine.clone();
+        async move { engine.optimize().await }
+    });
+}
+
+/// Index batches of changed files as the vault watcher reports them, each once `queue`
+/// grants it a background slot
+pub fn spawn_vault_watcher(indexer: Arc<VaultIndexer>, queue: QueryQueue) -> Result<JoinHandle<()>> {
+    let mut changes = Box::pin(indexer.watch()?);
+    Ok(tokio::spawn(async move {
+        while let Some(paths) = changes.next().await {
+            let _slot = match queue.acquire(QueryPriority::Background).await {
+                Ok(slot) => slot,
+                Err(e) => {
+                    tracing::warn!("Leaving watched changes to the next re-index: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = indexer.incremental_index(paths).await {
+                tracing::warn!("Indexing watched changes failed: {}", e);
+            }
+        }
+    }))
 }
@@ -0,0 +1,530 @@
+// src/signal_integration/backfill.rs - Import existing Note to Self history into the vault
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::audio::escalation::{EscalatingTranscriber, SpeechModel};
+use crate::logger::Logger;
+use crate::vault::capture_dedup::{CaptureDeduplicator, CaptureOutcome};
+use crate::vault::categorize::Categorizer;
+use crate::vault::embedding_pool::EmbeddingWorker;
+use crate::vault::parsers::ParserRegistry;
+use super::client::SignalEnvelope;
+use super::message_note::{MessageNote, PartSource};
+
+/// Timestamps of messages already imported, so re-running skips them
+const STATE_FILE: &str = ".note-to-ai/backfill.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillConfig {
+    /// Vault folder imported messages are written to
+    pub folder: PathBuf,
+    pub transcribe_audio: bool,
+    /// Put the text of text and code attachments in the message's note so it's searchable there
+    pub attachment_text: bool,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        Self {
+            folder: PathBuf::from("Signal"),
+            transcribe_audio: true,
+            attachment_text: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoricalMessage {
+    /// Signal's sent timestamp in milliseconds, unique per message
+    pub timestamp: i64,
+    pub body: Option<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub content_type: String,
+    pub path: PathBuf,
+    pub filename: String,
+}
+
+impl HistoricalMessage {
+    /// A message received live; signal-cli has saved its attachments in `attachments_dir`
+    pub fn from_envelope(envelope: &SignalEnvelope, attachments_dir: &Path) -> Self {
+        Self {
+            timestamp: envelope.timestamp,
+            body: envelope.body.clone(),
+            attachments: envelope.attachments.iter()
+                .map(|pointer| Attachment {
+                    content_type: pointer.content_type.clone(),
+                    path: attachments_dir.join(&pointer.id),
+                    filename: pointer.filename.clone().unwrap_or_else(|| pointer.id.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Attachment {
+    pub fn is_audio(&self) -> bool {
+        self.content_type.starts_with("audio/")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackfillProgress {
+    pub total: usize,
+    pub processed: usize,
+    pub ingested: usize,
+    pub skipped: usize,
+    pub transcribed: usize,
+    pub failed: usize,
+}
+
+/// Source of past Note to Self messages
+#[async_trait]
+pub trait MessageHistory: Send + Sync {
+    async fn note_to_self_history(&self) -> Result<Vec<HistoricalMessage>>;
+}
+
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    /// Text spoken in the audio file at `audio`
+    async fn transcribe(&self, audio: &Path) -> Result<String>;
+}
+
+#[async_trait]
+impl<P: SpeechModel, E: SpeechModel> Transcriber for EscalatingTranscriber<P, E> {
+    async fn transcribe(&self, audio: &Path) -> Result<String> {
+        Ok(EscalatingTranscriber::transcribe(self, audio).await?.text)
+    }
+}
+
+/// History read from `signal-cli receive --output=json` envelopes, one per line
+pub struct SignalCliExport {
+    path: PathBuf,
+    account: String,
+    attachments_dir: PathBuf,
+}
+
+impl SignalCliExport {
+    pub fn new(path: PathBuf, account: &str, attachments_dir: PathBuf) -> Self {
+        Self {
+            path,
+            account: account.to_string(),
+            attachments_dir,
+        }
+    }
+
+    fn parse_line(&self, line: &str) -> Option<HistoricalMessage> {
+        let envelope: Value = serde_json::from_str(line).ok()?;
+        let sent = envelope.pointer("/envelope/syncMessage/sentMessage")?;
+
+        // Note to Self is a sent sync message addressed to our own number
+        if sent.get("destination").and_then(Value::as_str) != Some(self.account.as_str()) {
+            return None;
+        }
+
+        let attachments = sent.get("attachments")
+            .and_then(Value::as_array)
+            .map(|list| {
+                list.iter()
+                    .filter_map(|a| {
+                        let id = a.get("id").and_then(Value::as_str)?;
+                        Some(Attachment {
+                            content_type: a.get("contentType").and_then(Value::as_str).unwrap_or_default().to_string(),
+                            path: self.attachments_dir.join(id),
+                            filename: a.get("filename").and_then(Value::as_str).unwrap_or(id).to_string(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(HistoricalMessage {
+            timestamp: sent.get("timestamp").and_then(Value::as_i64)?,
+            body: sent.get("message").and_then(Value::as_str).map(str::to_string),
+            attachments,
+        })
+    }
+}
+
+#[async_trait]
+impl MessageHistory for SignalCliExport {
+    async fn note_to_self_history(&self) -> Result<Vec<HistoricalMessage>> {
+        let content = tokio::fs::read_to_string(&self.path).await
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+
+        let mut messages: Vec<HistoricalMessage> = content.lines()
+            .filter_map(|line| self.parse_line(line))
+            .collect();
+        messages.sort_by_key(|m| m.timestamp);
+        messages.dedup_by_key(|m| m.timestamp);
+        Ok(messages)
+    }
+}
+
+pub struct Backfill<'a> {
+    vault_path: PathBuf,
+    config: BackfillConfig,
+    /// Without one, voice messages are imported untranscribed
+    transcriber: Option<&'a dyn Transcriber>,
+    /// Folds voice captures repeating a recent one into its note
+    dedup: Option<(&'a CaptureDeduplicator, &'a dyn EmbeddingWorker)>,
+    /// Files each message's note in its category's folder instead of the backfill folder
+    categorizer: Option<(&'a Categorizer, &'a dyn EmbeddingWorker)>,
+    parsers: ParserRegistry,
+    logger: Logger,
+}
+
+impl<'a> Backfill<'a> {
+    pub fn new(vault_path: PathBuf, config: BackfillConfig) -> Self {
+        Self {
+            vault_path,
+            config,
+            transcriber: None,
+            dedup: None,
+            categorizer: None,
+            // Building the default parsers only compiles fixed patterns
+            parsers: ParserRegistry::with_defaults().unwrap_or_default(),
+            logger: Logger::new("Backfill"),
+        }
+    }
+
+    pub fn with_transcriber(mut self, transcriber: &'a dyn Transcriber) -> Self {
+        self.transcriber = Some(transcriber);
+        self
+    }
+
+    /// Compare each transcribed voice message with the ones imported shortly before it
+    pub fn with_capture_dedup(mut self, dedup: &'a CaptureDeduplicator, embedder: &'a dyn EmbeddingWorker) -> Self {
+        self.dedup = Some((dedup, embedder));
+        self
+    }
+
+    pub fn with_categorizer(mut self, categorizer: &'a Categorizer, embedder: &'a dyn EmbeddingWorker) -> Self {
+        self.categorizer = Some((categorizer, embedder));
+        self
+    }
+
+    /// Import every message in `history` not imported before, reporting progress after each one
+    pub async fn run(&self, history: &dyn MessageHistory, mut on_progress: impl FnMut(&BackfillProgress)) -> Result<BackfillProgress> {
+        let messages = history.note_to_self_history().await?;
+        let mut done = self.load_state()?;
+        let mut progress = BackfillProgress { total: messages.len(), ..Default::default() };
+
+        for message in &messages {
+            if done.contains(&message.timestamp) {
+                progress.skipped += 1;
+            } else {
+                match self.ingest(message).await {
+                    Ok((_, transcribed)) => {
+                        progress.ingested += 1;
+                        progress.transcribed += transcribed;
+                        done.insert(message.timestamp);
+                        // Saved per message so an interrupted run resumes where it stopped
+                        self.save_state(&done)?;
+                    }
+                    Err(e) => {
+                        self.logger.warn(&format!("Failed to import message {}: {}", message.timestamp, e));
+                        progress.failed += 1;
+                    }
+                }
+            }
+
+            progress.processed += 1;
+            on_progress(&progress);
+        }
+
+        self.logger.info(&format!(
+            "Backfill imported {} of {} messages ({} already imported, {} failed)",
+            progress.ingested, progress.total, progress.skipped, progress.failed
+        ));
+        Ok(progress)
+    }
+
+    /// Import a message as it arrives, marking it imported so a later backfill skips it;
+    /// returns the path of the note holding it
+    pub async fn import(&self, message: &HistoricalMessage) -> Result<PathBuf> {
+        let (path, _) = self.ingest(message).await?;
+        let mut done = self.load_state()?;
+        done.insert(message.timestamp);
+        self.save_state(&done)?;
+        Ok(path)
+    }
+
+    /// Write one message and its attachments as a single note; returns the note's path and the
+    /// number of audio attachments transcribed
+    async fn ingest(&self, message: &HistoricalMessage) -> Result<(PathBuf, usize)> {
+        let mut note = MessageNote::new(message.timestamp)?.with_text(message.body.as_deref());
+        let folder = self.vault_path.join(&self.config.folder);
+        let attachments_folder = folder.join("attachments");
+
+        let mut transcribed = 0;
+        for attachment in &message.attachments {
+            let data = tokio::fs::read(&attachment.path).await
+                .with_context(|| format!("Attachment {} is missing", attachment.path.display()))?;
+
+            // Prefix with the timestamp so same-named attachments from different messages don't collide
+            let name = format!("{}-{}", message.timestamp, attachment_name(&attachment.filename)?);
+            tokio::fs::create_dir_all(&attachments_folder).await?;
+            tokio::fs::write(attachments_folder.join(&name), &data).await?;
+
+            if attachment.is_audio() {
+                let transcript = match self.transcriber.filter(|_| self.config.transcribe_audio) {
+                    Some(transcriber) => {
+                        transcribed += 1;
+                        Some(transcriber.transcribe(&attachment.path).await?)
+                    }
+                    None => None,
+                };
+                note.add_attachment(&name, PartSource::Audio, transcript);
+            } else {
+                let text = if self.config.attachment_text {
+                    self.parsers.parse(Path::new(&attachment.filename), &data).await?
+                        .map(|parsed| parsed.plain_text)
+                } else {
+                    None
+                };
+                note.add_attachment(&name, PartSource::Attachment, text);
+            }
+        }
+
+        let name = format!("{}.md", note.sent().format("%Y-%m-%d %H%M%S%.3f"));
+        let mut path = folder.join(&name);
+        if let Some((categorizer, embedder)) = self.categorizer {
+            let document = note.to_document(&path).await?;
+            let filed = categorizer.classify(&document.plain_text, embedder).await?;
+            path = self.vault_path.join(&filed.folder).join(&name);
+            note.set_category(filed.category);
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if let (Some((dedup, embedder)), Some(transcript)) = (self.dedup, note.transcript()) {
+            let path = match dedup.save_capture_note(&transcript, &note.to_markdown(), path, note.sent(), embedder).await? {
+                CaptureOutcome::Created(path) | CaptureOutcome::Linked { created: path, .. } => path,
+                CaptureOutcome::Appended { existing, .. } => existing,
+            };
+            return Ok((path, transcribed));
+        }
+        tokio::fs::write(&path, note.to_markdown()).await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok((path, transcribed))
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.vault_path.join(STATE_FILE)
+    }
+
+    fn load_state(&self) -> Result<BTreeSet<i64>> {
+        let path = self.state_path();
+        if !path.exists() {
+            return Ok(BTreeSet::new());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        let state = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(state)
+    }
+
+    fn save_state(&self, done: &BTreeSet<i64>) -> Result<()> {
+        let path = self.state_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string(done)?)?;
+        Ok(())
+    }
+}
+
+/// The final component of a sender-supplied filename; anything that could leave the attachments folder is rejected
+fn attachment_name(filename: &str) -> Result<&str> {
+    let path = Path::new(filename);
+    if path.is_absolute() || path.components().any(|c| matches!(c, Component::ParentDir)) {
+        anyhow::bail!("Attachment filename {:?} is not a plain file name", filename);
+    }
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Attachment filename {:?} is empty", filename))
+}
+
+/// Default location of attachments downloaded by signal-cli
+pub fn default_attachments_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".local/share/signal-cli/attachments")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingTranscriber(AtomicUsize);
+
+    #[async_trait]
+    impl Transcriber for CountingTranscriber {
+        async fn transcribe(&self, _audio: &Path) -> Result<String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok("Remember to water the tomatoes".to_string())
+        }
+    }
+
+    fn envelope(destination: &str, timestamp: i64, message: Option<&str>, attachments: Value) -> String {
+        json!({
+            "envelope": {
+                "source": "+15550100",
+                "timestamp": timestamp,
+                "syncMessage": { "sentMessage": {
+                    "destination": destination,
+                    "timestamp": timestamp,
+                    "message": message,
+                    "attachments": attachments,
+                }}
+            }
+        }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_backfill_imports_history_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let attachments = dir.path().join("attachments");
+        std::fs::create_dir_all(&attachments).unwrap();
+        std::fs::write(attachments.join("voice1"), b"fake-aac").unwrap();
+        std::fs::write(attachments.join("photo1"), b"fake-jpeg").unwrap();
+
+        let export = dir.path().join("history.jsonl");
+        let lines = [
+            envelope("+15550100", 1_700_000_000_000, Some("Buy seed potatoes"), json!([])),
+            envelope("+15550100", 1_700_000_100_000, None,
+                json!([{ "id": "voice1", "contentType": "audio/aac", "filename": "voice.aac" }])),
+            envelope("+15550100", 1_700_000_200_000, Some("Garden layout"),
+                json!([{ "id": "photo1", "contentType": "image/jpeg", "filename": "garden.jpg" }])),
+            // Sent to someone else, not Note to Self
+            envelope("+15550199", 1_700_000_300_000, Some("See you at 6"), json!([])),
+            "not json".to_string(),
+        ];
+        std::fs::write(&export, lines.join("\n")).unwrap();
+
+        let history = SignalCliExport::new(export, "+15550100", attachments);
+        let transcriber = CountingTranscriber(AtomicUsize::new(0));
+        let backfill = Backfill::new(vault.clone(), BackfillConfig::default()).with_transcriber(&transcriber);
+
+        let mut reports = Vec::new();
+        let first = backfill.run(&history, |p| reports.push(p.processed)).await.unwrap();
+        assert_eq!(first, BackfillProgress { total: 3, processed: 3, ingested: 3, skipped: 0, transcribed: 1, failed: 0 });
+        assert_eq!(reports, vec![1, 2, 3]);
+
+        let notes: Vec<_> = std::fs::read_dir(vault.join("Signal")).unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+            .map(|e| std::fs::read_to_string(e.path()).unwrap())
+            .collect();
+        assert_eq!(notes.len(), 3);
+        assert!(notes.iter().any(|n| n.contains("Remember to water the tomatoes")));
+        assert!(vault.join("Signal/attachments/1700000200000-garden.jpg").exists());
+
+        let second = backfill.run(&history, |_| {}).await.unwrap();
+        assert_eq!(second.ingested, 0);
+        assert_eq!(second.skipped, 3);
+        assert_eq!(transcriber.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_voice_message_is_appended_to_the_first() {
+        use crate::test_support::BagOfWords;
+        use crate::vault::capture_dedup::CaptureDedupConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let attachments = dir.path().join("attachments");
+        std::fs::create_dir_all(&attachments).unwrap();
+        std::fs::write(attachments.join("voice1"), b"fake-aac").unwrap();
+        std::fs::write(attachments.join("voice2"), b"fake-aac").unwrap();
+
+        let export = dir.path().join("history.jsonl");
+        let lines = [
+            envelope("+15550100", 1_700_000_000_000, None,
+                json!([{ "id": "voice1", "contentType": "audio/aac", "filename": "voice.aac" }])),
+            envelope("+15550100", 1_700_000_120_000, None,
+                json!([{ "id": "voice2", "contentType": "audio/aac", "filename": "voice.aac" }])),
+        ];
+        std::fs::write(&export, lines.join("\n")).unwrap();
+
+        let history = SignalCliExport::new(export, "+15550100", attachments);
+        let transcriber = CountingTranscriber(AtomicUsize::new(0));
+        let dedup = CaptureDeduplicator::new(CaptureDedupConfig::default());
+        let embedder = BagOfWords::Vocabulary(&["water", "tomatoes", "seeds"]);
+        let backfill = Backfill::new(vault.clone(), BackfillConfig::default())
+            .with_transcriber(&transcriber)
+            .with_capture_dedup(&dedup, &embedder);
+        let progress = backfill.run(&history, |_| {}).await.unwrap();
+        assert_eq!((progress.ingested, progress.transcribed), (2, 2));
+
+        let notes: Vec<_> = std::fs::read_dir(vault.join("Signal")).unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+            .map(|e| std::fs::read_to_string(e.path()).unwrap())
+            .collect();
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].contains("signal_timestamp: 1700000000000"));
+        assert_eq!(notes[0].matches("Remember to water the tomatoes").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_filed_by_category_or_into_the_inbox() {
+        use crate::test_support::BagOfWords;
+        use crate::vault::categorize::{CategorizationConfig, Category};
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let export = dir.path().join("history.jsonl");
+        let lines = [
+            envelope("+15550100", 1_700_000_000_000, Some("New recipe: mix the flour, then bake in the oven"), json!([])),
+            envelope("+15550100", 1_700_000_100_000, Some("Call mum"), json!([])),
+        ];
+        std::fs::write(&export, lines.join("\n")).unwrap();
+
+        let embedder = BagOfWords::Vocabulary(&["recipe", "oven", "flour", "meeting", "deadline"]);
+        let config = CategorizationConfig {
+            enabled: true,
+            categories: vec![
+                Category { name: "Cooking".to_string(), folder: PathBuf::from("Cooking"), examples: vec!["A recipe with flour".to_string()] },
+                Category { name: "Work".to_string(), folder: PathBuf::from("Work"), examples: vec!["Meeting deadline".to_string()] },
+            ],
+            ..CategorizationConfig::default()
+        };
+        let categorizer = Categorizer::new(config, &embedder).await.unwrap();
+        let history = SignalCliExport::new(export, "+15550100", dir.path().join("attachments"));
+        let transcriber = CountingTranscriber(AtomicUsize::new(0));
+        Backfill::new(vault.clone(), BackfillConfig::default())
+            .with_transcriber(&transcriber)
+            .with_categorizer(&categorizer, &embedder)
+            .run(&history, |_| {})
+            .await
+            .unwrap();
+
+        let filed = std::fs::read_to_string(vault.join("Cooking/2023-11-14 221320.000.md")).unwrap();
+        assert!(filed.contains("category: \"Cooking\"\n"));
+        let inbox = std::fs::read_to_string(vault.join("Inbox/2023-11-14 221500.000.md")).unwrap();
+        assert!(!inbox.contains("category:"));
+    }
+
+    #[test]
+    fn test_attachment_names_cannot_escape_the_folder() {
+        assert_eq!(attachment_name("garden.jpg").unwrap(), "garden.jpg");
+        assert_eq!(attachment_name("photos/garden.jpg").unwrap(), "garden.jpg");
+        assert!(attachment_name("../../.bashrc").is_err());
+        assert!(attachment_name("photos/../garden.jpg").is_err());
+        assert!(attachment_name("/etc/passwd").is_err());
+        assert!(attachment_name("").is_err());
+        assert!(attachment_name("..").is_err());
+    }
+}
@@ -1,14 +1,264 @@
-use crate::Result;
+// src/signal_integration/client.rs - Long-lived signal-cli JSON-RPC connection for receiving and sending messages
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::{mpsc, oneshot};
+use super::daemon::{classify_error, DaemonError};
 
-pub struct SignalClient;
+/// Envelopes buffered before the reader waits for them to be consumed
+const ENVELOPE_BUFFER: usize = 64;
+
+/// An attachment as signal-cli reports it; the file is saved in its attachments directory as `id`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentPointer {
+    pub id: String,
+    pub content_type: String,
+    pub filename: Option<String>,
+    pub size: Option<u64>,
+}
+
+impl AttachmentPointer {
+    pub fn is_audio(&self) -> bool {
+        self.content_type.starts_with("audio/")
+    }
+}
+
+/// A message received from someone, or sent from another of the account's devices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalEnvelope {
+    /// Phone number of the sender, or their UUID when the number is hidden
+    pub sender: String,
+    /// Recipient of a message sent from another device; `None` for received and group messages
+    pub destination: Option<String>,
+    /// Set for messages sent to a group
+    pub group_id: Option<String>,
+    /// Sent time in milliseconds
+    pub timestamp: i64,
+    pub body: Option<String>,
+    pub attachments: Vec<AttachmentPointer>,
+}
+
+impl SignalEnvelope {
+    /// Parse the `envelope` of a `receive` notification; receipts, typing indicators and
+    /// other envelopes without a message give `None`
+    pub fn from_json(envelope: &Value) -> Option<Self> {
+        let sent = envelope.pointer("/syncMessage/sentMessage");
+        let message = envelope.get("dataMessage").or(sent).filter(|m| m.is_object())?;
+        let text = |value: &Value, key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+
+        let sender = ["sourceNumber", "source", "sourceUuid"].iter()
+            .find_map(|key| text(envelope, key))?;
+        let destination = sent.and_then(|sent| text(sent, "destinationNumber").or_else(|| text(sent, "destination")));
+        let group_id = message.pointer("/groupInfo/groupId").and_then(Value::as_str).map(str::to_string);
+        let timestamp = message.get("timestamp").or_else(|| envelope.get("timestamp")).and_then(Value::as_i64)?;
+        let attachments = message.get("attachments")
+            .and_then(Value::as_array)
+            .map(|list| {
+                list.iter()
+                    .filter_map(|a| {
+                        Some(AttachmentPointer {
+                            id: text(a, "id")?,
+                            content_type: text(a, "contentType").unwrap_or_default(),
+                            filename: text(a, "filename"),
+                            size: a.get("size").and_then(Value::as_u64),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            sender,
+            destination,
+            group_id,
+            timestamp,
+            body: text(message, "message"),
+            attachments,
+        })
+    }
+}
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, DaemonError>>>>>;
+
+/// One connection to `signal-cli daemon --socket`, shared by requests and the incoming message
+/// stream; responses are matched to requests by id
+pub struct SignalClient {
+    writer: tokio::sync::Mutex<OwnedWriteHalf>,
+    pending: Pending,
+    next_id: AtomicU64,
+    envelopes: tokio::sync::Mutex<mpsc::Receiver<SignalEnvelope>>,
+    account: Mutex<Option<String>>,
+    timeout: Duration,
+}
 
 impl SignalClient {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+    pub async fn connect(socket_path: &Path) -> Result<Self, DaemonError> {
+        if !socket_path.exists() {
+            return Err(DaemonError::SocketMissing(socket_path.to_path_buf()));
+        }
+        let stream = UnixStream::connect(socket_path).await.map_err(DaemonError::Connect)?;
+        let (reader, writer) = stream.into_split();
+
+        let pending = Pending::default();
+        let (sender, envelopes) = mpsc::channel(ENVELOPE_BUFFER);
+        tokio::spawn(read_messages(reader, pending.clone(), sender));
+
+        Ok(Self {
+            writer: tokio::sync::Mutex::new(writer),
+            pending,
+            next_id: AtomicU64::new(1),
+            envelopes: tokio::sync::Mutex::new(envelopes),
+            account: Mutex::new(None),
+            timeout: Duration::from_millis(5000),
+        })
+    }
+
+    /// How long a request waits for its response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send one JSON-RPC request and wait for its response
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, DaemonError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, response) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let mut line = request.to_string();
+        line.push('\n');
+        if let Err(e) = self.writer.lock().await.write_all(line.as_bytes()).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(DaemonError::Connect(e));
+        }
+
+        match tokio::time::timeout(self.timeout, response).await {
+            Ok(Ok(result)) => result.map_err(|e| match e {
+                DaemonError::Rpc(message) => classify_error(&message, &params),
+                other => other,
+            }),
+            Ok(Err(_)) => Err(DaemonError::Rpc("connection closed before a response".to_string())),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(DaemonError::Timeout(self.timeout))
+            }
+        }
     }
-    
-    pub async fn connect(&self) -> Result<()> {
-        // TODO: Implement libsignal-protocol integration
+
+    /// Start receiving `account`'s messages; they arrive through `next_envelope`
+    pub async fn subscribe(&self, account: &str) -> Result<(), DaemonError> {
+        self.call("subscribeReceive", json!({ "account": account })).await?;
+        *self.account.lock().unwrap() = Some(account.to_string());
         Ok(())
     }
+
+    /// The next incoming message, or `None` once the daemon closes the connection
+    pub async fn next_envelope(&self) -> Option<SignalEnvelope> {
+        self.envelopes.lock().await.recv().await
+    }
+
+    /// Send `body` with files attached, from the subscribed account; returns the sent timestamp
+    pub async fn send_message(&self, recipient: &str, body: &str, attachments: &[PathBuf]) -> Result<i64, DaemonError> {
+        let mut params = json!({
+            "recipient": [recipient],
+            "message": body,
+            "attachments": attachments,
+        });
+        if let Some(account) = self.account.lock().unwrap().clone() {
+            params["account"] = json!(account);
+        }
+
+        let result = self.call("send", params).await?;
+        Ok(result.get("timestamp").and_then(Value::as_i64).unwrap_or_default())
+    }
+}
+
+/// Route responses to their waiting requests and parse `receive` notifications into envelopes
+async fn read_messages(reader: OwnedReadHalf, pending: Pending, envelopes: mpsc::Sender<SignalEnvelope>) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+
+        if let Some(id) = message.get("id").and_then(Value::as_u64) {
+            let Some(waiting) = pending.lock().unwrap().remove(&id) else {
+                continue;
+            };
+            let result = match message.get("error") {
+                Some(error) => {
+                    let text = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+                    Err(DaemonError::Rpc(text.to_string()))
+                }
+                None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+            };
+            let _ = waiting.send(result);
+        } else if message.get("method").and_then(Value::as_str) == Some("receive") {
+            let envelope = message.pointer("/params/envelope").and_then(SignalEnvelope::from_json);
+            if let Some(envelope) = envelope {
+                // Nobody is listening any more; keep answering requests regardless
+                let _ = envelopes.send(envelope).await;
+            }
+        }
+    }
+
+    // Dropping the waiting senders fails their requests
+    pending.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{json_rpc_daemon, rpc_reply};
+
+    const VOICE_NOTE: &str = r#"{"jsonrpc":"2.0","method":"receive","params":{"account":"+15550100","envelope":{
+        "source":"+15550100","sourceNumber":"+15550100","sourceUuid":"a1b2","sourceDevice":2,"timestamp":1700000000123,
+        "syncMessage":{"sentMessage":{"destination":"+15550100","destinationNumber":"+15550100","timestamp":1700000000123,
+        "message":"Ideas for the garden","attachments":[{"contentType":"audio/aac","filename":"voice.m4a","id":"Xy12.m4a","size":20480}]}}}}}"#;
+
+    #[tokio::test]
+    async fn test_mock_daemon_envelope_is_parsed_and_replies_are_sent() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("socket");
+        let mut received = json_rpc_daemon(&socket, |request| {
+            let mut out = vec![rpc_reply(request, json!({ "result": { "timestamp": 1700000000999u64 } }))];
+            if request["method"] == "subscribeReceive" {
+                // A typing indicator carries no message and is skipped
+                out.push(json!({ "jsonrpc": "2.0", "method": "receive", "params": { "envelope": { "source": "+15550199", "timestamp": 1, "typingMessage": {} } } }));
+                out.push(serde_json::from_str(VOICE_NOTE).unwrap());
+            }
+            out
+        });
+
+        let client = SignalClient::connect(&socket).await.unwrap();
+        client.subscribe("+15550100").await.unwrap();
+
+        let envelope = client.next_envelope().await.unwrap();
+        assert_eq!(envelope.sender, "+15550100");
+        assert_eq!(envelope.timestamp, 1_700_000_000_123);
+        assert_eq!(envelope.body.as_deref(), Some("Ideas for the garden"));
+        assert_eq!(envelope.destination.as_deref(), Some("+15550100"));
+        assert_eq!(envelope.attachments, [AttachmentPointer {
+            id: "Xy12.m4a".to_string(),
+            content_type: "audio/aac".to_string(),
+            filename: Some("voice.m4a".to_string()),
+            size: Some(20_480),
+        }]);
+
+        let sent = client.send_message("+15550100", "Saved", &[PathBuf::from("/tmp/summary.md")]).await.unwrap();
+        assert_eq!(sent, 1_700_000_000_999);
+        received.recv().await.unwrap();
+        let send = received.recv().await.unwrap();
+        assert_eq!(send["method"], "send");
+        assert_eq!(send["params"]["account"], "+15550100");
+        assert_eq!(send["params"]["recipient"], json!(["+15550100"]));
+        assert_eq!(send["params"]["attachments"], json!(["/tmp/summary.md"]));
+    }
 }
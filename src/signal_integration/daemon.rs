@@ -0,0 +1,221 @@
+// src/signal_integration/daemon.rs - JSON-RPC client for the signal-cli daemon socket
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalDaemonConfig {
+    /// Socket opened by `signal-cli daemon --socket`
+    pub socket_path: PathBuf,
+    pub timeout_ms: u64,
+}
+
+impl Default for SignalDaemonConfig {
+    fn default() -> Self {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        Self {
+            socket_path: runtime_dir.join("signal-cli").join("socket"),
+            timeout_ms: 5000,
+        }
+    }
+}
+
+/// Why talking to the daemon failed, specific enough to tell the user what to fix
+#[derive(Debug, thiserror::Error)]
+pub enum DaemonError {
+    #[error("signal-cli socket not found at {0}; is `signal-cli daemon --socket` running?")]
+    SocketMissing(PathBuf),
+
+    #[error("Could not connect to signal-cli socket: {0}")]
+    Connect(std::io::Error),
+
+    #[error("Account {0} is not registered with signal-cli; run `note-to-ai signal setup`")]
+    NotRegistered(String),
+
+    #[error("signal-cli rejected the account credentials: {0}")]
+    Auth(String),
+
+    #[error("signal-cli did not answer within {0:?}")]
+    Timeout(Duration),
+
+    #[error("signal-cli returned an error: {0}")]
+    Rpc(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionReport {
+    pub account: String,
+    pub registered_accounts: usize,
+    /// Round trip of the account lookup request
+    pub latency: Duration,
+    pub test_message_sent: bool,
+    /// Time until the server accepted the test message, when one was sent
+    pub send_latency: Option<Duration>,
+}
+
+pub struct SignalDaemonClient {
+    config: SignalDaemonConfig,
+}
+
+impl SignalDaemonClient {
+    pub fn new(config: SignalDaemonConfig) -> Self {
+        Self { config }
+    }
+
+    /// Send one JSON-RPC request and wait for its response
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, DaemonError> {
+        self.call_within(method, params, Duration::from_millis(self.config.timeout_ms)).await
+    }
+
+    /// `call` with its own timeout, for requests that wait on the user
+    pub async fn call_within(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, DaemonError> {
+        tokio::time::timeout(timeout, self.call_inner(method, params))
+            .await
+            .map_err(|_| DaemonError::Timeout(timeout))?
+    }
+
+    async fn call_inner(&self, method: &str, params: Value) -> Result<Value, DaemonError> {
+        if !self.config.socket_path.exists() {
+            return Err(DaemonError::SocketMissing(self.config.socket_path.clone()));
+        }
+
+        let stream = UnixStream::connect(&self.config.socket_path).await.map_err(DaemonError::Connect)?;
+        let (reader, mut writer) = stream.into_split();
+
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut line = request.to_string();
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await.map_err(DaemonError::Connect)?;
+
+        // The daemon also pushes incoming messages as notifications; skip until our response
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await.map_err(DaemonError::Connect)? {
+            let response: Value = match serde_json::from_str(&line) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            if response.get("id") != Some(&json!(1)) {
+                continue;
+            }
+
+            if let Some(error) = response.get("error") {
+                let message = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+                return Err(classify_error(message, &params));
+            }
+            return Ok(response.get("result").cloned().unwrap_or(Value::Null));
+        }
+
+        Err(DaemonError::Rpc("connection closed before a response".to_string()))
+    }
+
+    /// Check the daemon is reachable and knows `account`, optionally messaging Note to Self
+    pub async fn probe(&self, account: &str, send_test: bool) -> Result<ConnectionReport, DaemonError> {
+        let start = Instant::now();
+        let accounts = self.call("listAccounts", json!({})).await?;
+        let latency = start.elapsed();
+
+        let numbers: Vec<&str> = accounts.as_array()
+            .map(|list| list.iter().filter_map(|a| a.get("number").and_then(Value::as_str)).collect())
+            .unwrap_or_default();
+        if !numbers.contains(&account) {
+            return Err(DaemonError::NotRegistered(account.to_string()));
+        }
+
+        let mut send_latency = None;
+        if send_test {
+            let start = Instant::now();
+            let sent = self.call("send", json!({
+                "account": account,
+                "noteToSelf": true,
+                "message": "note-to-ai connection test",
+            })).await?;
+            // Each recipient's result is SUCCESS once the server has accepted the message
+            let failed = sent.get("results")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|result| result.get("type").and_then(Value::as_str))
+                .find(|kind| *kind != "SUCCESS");
+            if let Some(kind) = failed {
+                return Err(DaemonError::Rpc(format!("test message was not delivered: {}", kind)));
+            }
+            send_latency = Some(start.elapsed());
+        }
+
+        Ok(ConnectionReport {
+            account: account.to_string(),
+            registered_accounts: numbers.len(),
+            latency,
+            test_message_sent: send_test,
+            send_latency,
+        })
+    }
+}
+
+pub(super) fn classify_error(message: &str, params: &Value) -> DaemonError {
+    let lower = message.to_lowercase();
+    if lower.contains("not registered") {
+        let account = params.get("account").and_then(Value::as_str).unwrap_or_default();
+        DaemonError::NotRegistered(account.to_string())
+    } else if lower.contains("auth") {
+        DaemonError::Auth(message.to_string())
+    } else {
+        DaemonError::Rpc(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{json_rpc_daemon, rpc_reply};
+
+    /// Answer each request with a canned result or error for its method, after a notification
+    fn serve(socket: &std::path::Path, respond: fn(&str) -> Value) {
+        json_rpc_daemon(socket, move |request| vec![
+            json!({ "jsonrpc": "2.0", "method": "receive", "params": {} }),
+            rpc_reply(request, respond(request["method"].as_str().unwrap())),
+        ]);
+    }
+
+    fn client(socket_path: PathBuf) -> SignalDaemonClient {
+        SignalDaemonClient::new(SignalDaemonConfig { socket_path, timeout_ms: 2000 })
+    }
+
+    #[tokio::test]
+    async fn test_probe_reports_success_against_mock_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("socket");
+        serve(&socket, |method| match method {
+            "listAccounts" => json!({ "result": [{ "number": "+15550100" }] }),
+            "send" => json!({ "result": { "timestamp": 1700000000000u64, "results": [{ "type": "SUCCESS" }] } }),
+            _ => json!({ "error": { "code": -32601, "message": "Method not implemented" } }),
+        });
+
+        let report = client(socket).probe("+15550100", true).await.unwrap();
+        assert_eq!(report.account, "+15550100");
+        assert_eq!(report.registered_accounts, 1);
+        assert!(report.test_message_sent);
+        assert!(report.send_latency.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_probe_reports_specific_failures() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let missing = client(dir.path().join("missing.sock")).probe("+15550100", false).await;
+        assert!(matches!(missing, Err(DaemonError::SocketMissing(_))));
+
+        let socket = dir.path().join("socket");
+        serve(&socket, |method| match method {
+            "listAccounts" => json!({ "result": [{ "number": "+15550199" }] }),
+            _ => json!({ "error": { "code": -1, "message": "Authorization failed" } }),
+        });
+        let unregistered = client(socket).probe("+15550100", false).await;
+        assert!(matches!(unregistered, Err(DaemonError::NotRegistered(ref a)) if a == "+15550100"));
+    }
+}
@@ -0,0 +1,209 @@
+// src/signal_integration/handler.rs - What the service does with each message in the conversation it serves
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::Result;
+use tracing::info;
+use crate::ai::hermes_integration::HermesIntegration;
+use crate::vault::focus::FocusSession;
+use crate::vault::indexer::VaultIndexer;
+use crate::vault::ingest::UrlIngestor;
+use super::backfill::{Backfill, BackfillConfig, HistoricalMessage, Transcriber};
+use super::client::SignalEnvelope;
+use super::Signal;
+
+/// Answers the `more`, `/focus` and `/save` commands; any other message is a capture, saved to
+/// the vault and passed to the assistant
+pub struct MessageHandler {
+    signal: Signal,
+    conversation: String,
+    focus: FocusSession,
+    ingestor: UrlIngestor,
+    /// Without one, captures aren't saved
+    capture: Option<MessageCapture>,
+    /// Without one, captures get no reply
+    hermes: Option<Arc<HermesIntegration>>,
+}
+
+/// Writes a message and its attachments to the vault as one note, the way backfill imports
+/// history, and indexes it right away
+pub struct MessageCapture {
+    indexer: Arc<VaultIndexer>,
+    config: BackfillConfig,
+    /// Where signal-cli saves the attachments it downloads
+    attachments_dir: PathBuf,
+    /// Without one, voice messages are saved untranscribed
+    transcriber: Option<Box<dyn Transcriber>>,
+}
+
+impl MessageCapture {
+    pub fn new(indexer: Arc<VaultIndexer>, config: BackfillConfig, attachments_dir: PathBuf) -> Self {
+        Self { indexer, config, attachments_dir, transcriber: None }
+    }
+
+    pub fn with_transcriber(mut self, transcriber: Box<dyn Transcriber>) -> Self {
+        self.transcriber = Some(transcriber);
+        self
+    }
+
+    /// Save and index the message; returns the path of its note
+    pub async fn capture(&self, envelope: &SignalEnvelope) -> Result<PathBuf> {
+        let mut backfill = Backfill::new(self.indexer.vault_path().to_path_buf(), self.config.clone());
+        if let Some(transcriber) = &self.transcriber {
+            backfill = backfill.with_transcriber(transcriber.as_ref());
+        }
+        let path = backfill.import(&HistoricalMessage::from_envelope(envelope, &self.attachments_dir)).await?;
+        self.indexer.incremental_index(vec![path.clone()]).await?;
+        Ok(path)
+    }
+}
+
+impl MessageHandler {
+    pub fn new(signal: Signal, conversation: &str, focus: FocusSession, ingestor: UrlIngestor) -> Self {
+        Self {
+            signal,
+            conversation: conversation.to_string(),
+            focus,
+            ingestor,
+            capture: None,
+            hermes: None,
+        }
+    }
+
+    pub fn with_capture(mut self, capture: MessageCapture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    pub fn with_hermes(mut self, hermes: Arc<HermesIntegration>) -> Self {
+        self.hermes = Some(hermes);
+        self
+    }
+
+    pub async fn handle(&self, envelope: &SignalEnvelope) -> Result<()> {
+        let body = envelope.body.as_deref().unwrap_or_default();
+        let handled = self.signal.send_more(&self.conversation, body).await.map_err(|e| anyhow::anyhow!("{}", e))?
+            || self.signal.send_focus(body, &self.focus).await.map_err(|e| anyhow::anyhow!("{}", e))?
+            || self.signal.send_save(body, &self.ingestor).await.map_err(|e| anyhow::anyhow!("{}", e))?;
+        if handled {
+            return Ok(());
+        }
+
+        info!("Received message {} with {} attachments", envelope.timestamp, envelope.attachments.len());
+        if let (Some(capture), false) = (&self.capture, body.trim().is_empty() && envelope.attachments.is_empty()) {
+            let path = capture.capture(envelope).await?;
+            info!("Saved message {} to {}", envelope.timestamp, path.display());
+        }
+        if let (Some(hermes), false) = (&self.hermes, body.trim().is_empty()) {
+            // A conversation restored at startup carries on where it left off
+            if hermes.get_conversation(&self.conversation).await.is_err() {
+                hermes.create_conversation(self.conversation.clone(), None).await?;
+            }
+            let response = hermes.chat(&self.conversation, body, None).await?;
+            if let Some(choice) = response.choices.first() {
+                self.signal.send_reply(&self.conversation, &choice.message.content).await
+                    .map_err(|e| anyhow::anyhow!("{}", e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use async_trait::async_trait;
+    use std::path::Path;
+    use crate::signal_integration::client::{AttachmentPointer, SignalClient};
+    use crate::test_support::{http_response, http_server, json_rpc_daemon, rpc_reply};
+    use crate::vault::ingest::IngestConfig;
+
+    const OWN: &str = "+15550100";
+
+    struct FixedTranscriber;
+
+    #[async_trait]
+    impl Transcriber for FixedTranscriber {
+        async fn transcribe(&self, _audio: &Path) -> Result<String> {
+            Ok("Plant basil next to the tomatoes".to_string())
+        }
+    }
+
+    fn note_to_self(body: Option<String>, attachments: Vec<AttachmentPointer>) -> SignalEnvelope {
+        SignalEnvelope {
+            sender: OWN.to_string(),
+            destination: Some(OWN.to_string()),
+            group_id: None,
+            timestamp: 1_700_000_000_000,
+            body,
+            attachments,
+        }
+    }
+
+    const ARTICLE: &str = "<html><head><title>Growing Tomatoes</title></head><body><article>\
+        <h1>Growing Tomatoes</h1><p>Tomatoes need six hours of sun and steady watering at the base of the plant.</p>\
+        <p>Water in the morning so the leaves dry before evening and disease stays away. Stake plants early, \
+        tie them with soft ties and prune suckers weekly.</p></article></body></html>";
+
+    #[tokio::test]
+    async fn test_save_command_saves_the_page_and_replies() {
+        let url = http_server(|_| vec![http_response("200 OK", "text/html; charset=utf-8", ARTICLE)]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let socket = dir.path().join("socket");
+        let mut received = json_rpc_daemon(&socket, |request| {
+            vec![rpc_reply(request, json!({ "result": { "timestamp": 1_700_000_000_999u64 } }))]
+        });
+        let client = Arc::new(SignalClient::connect(&socket).await.unwrap());
+        let handler = MessageHandler::new(
+            Signal::new().unwrap().with_client(client, OWN),
+            OWN,
+            FocusSession::in_memory(),
+            UrlIngestor::new(vault.clone(), IngestConfig::default()).unwrap(),
+        );
+
+        handler.handle(&note_to_self(Some(format!("/save {}/tomatoes", url)), Vec::new())).await.unwrap();
+
+        assert!(std::fs::read_to_string(vault.join("Clippings/Growing Tomatoes.md")).unwrap().contains("six hours of sun"));
+        let reply = received.recv().await.unwrap();
+        assert_eq!(reply["method"], "send");
+        assert_eq!(reply["params"]["message"], "Saved \"Growing Tomatoes\"");
+    }
+
+    #[tokio::test]
+    async fn test_text_and_voice_message_is_saved_as_one_indexed_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let attachments = dir.path().join("attachments");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::create_dir_all(&attachments).unwrap();
+        std::fs::write(attachments.join("Xy12.m4a"), b"fake-aac").unwrap();
+
+        let indexer = Arc::new(VaultIndexer::new(dir.path().join("index.db"), vault.clone()).unwrap());
+        indexer.initialize_db().await.unwrap();
+        let capture = MessageCapture::new(indexer.clone(), BackfillConfig::default(), attachments)
+            .with_transcriber(Box::new(FixedTranscriber));
+        let handler = MessageHandler::new(
+            Signal::new().unwrap(),
+            OWN,
+            FocusSession::in_memory(),
+            UrlIngestor::new(vault.clone(), IngestConfig::default()).unwrap(),
+        ).with_capture(capture);
+
+        let voice = AttachmentPointer {
+            id: "Xy12.m4a".to_string(),
+            content_type: "audio/aac".to_string(),
+            filename: Some("voice.m4a".to_string()),
+            size: Some(8),
+        };
+        handler.handle(&note_to_self(Some("Ideas for the garden".to_string()), vec![voice])).await.unwrap();
+
+        let note = vault.join("Signal/2023-11-14 221320.000.md");
+        let saved = std::fs::read_to_string(&note).unwrap();
+        assert!(saved.contains("Ideas for the garden"));
+        assert!(saved.contains("Plant basil next to the tomatoes"));
+        assert!(saved.contains("![[1700000000000-voice.m4a]]"));
+        let indexed: Vec<PathBuf> = indexer.get_all_files().await.unwrap().into_iter().map(|file| file.path).collect();
+        assert!(indexed.contains(&note), "{:?}", indexed);
+    }
+}
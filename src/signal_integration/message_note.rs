@@ -0,0 +1,160 @@
+// src/signal_integration/message_note.rs - One note per Signal message, attachments included
+use std::path::Path;
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::vault::parser::{ObsidianParser, ParsedDocument};
+
+/// Where a part of a message's note came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartSource {
+    /// The message body
+    Text,
+    /// A transcribed voice message or audio file
+    Audio,
+    /// Text extracted from a file attachment
+    Attachment,
+}
+
+impl PartSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartSource::Text => "text",
+            PartSource::Audio => "audio",
+            PartSource::Attachment => "attachment",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Part {
+    source: PartSource,
+    /// Embedded attachment, as saved in the vault
+    embed: Option<String>,
+    text: Option<String>,
+}
+
+/// A message's text body and its attachments' transcribed or extracted text, written as a
+/// single note so everything sent together is found together
+#[derive(Debug, Clone)]
+pub struct MessageNote {
+    timestamp: i64,
+    sent: DateTime<Utc>,
+    parts: Vec<Part>,
+    category: Option<String>,
+}
+
+impl MessageNote {
+    /// `timestamp` is Signal's sent time in milliseconds
+    pub fn new(timestamp: i64) -> Result<Self> {
+        let sent = DateTime::from_timestamp_millis(timestamp)
+            .context("Message has an invalid timestamp")?;
+        Ok(Self { timestamp, sent, parts: Vec::new(), category: None })
+    }
+
+    pub fn sent(&self) -> DateTime<Utc> {
+        self.sent
+    }
+
+    /// The message body; blank bodies are ignored
+    pub fn with_text(mut self, body: Option<&str>) -> Self {
+        if let Some(text) = body.map(str::trim).filter(|t| !t.is_empty()) {
+            self.parts.push(Part { source: PartSource::Text, embed: None, text: Some(text.to_string()) });
+        }
+        self
+    }
+
+    /// The category the note was filed under, recorded in its frontmatter
+    pub fn set_category(&mut self, category: Option<String>) {
+        self.category = category;
+    }
+
+    /// An attachment saved to the vault as `embed`, with the text transcribed or extracted from it
+    pub fn add_attachment(&mut self, embed: &str, source: PartSource, text: Option<String>) {
+        let text = text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+        self.parts.push(Part { source, embed: Some(embed.to_string()), text });
+    }
+
+    /// Kinds of content with text in the note, each once, in message order
+    pub fn sources(&self) -> Vec<PartSource> {
+        let mut sources = Vec::new();
+        for part in self.parts.iter().filter(|p| p.text.is_some()) {
+            if !sources.contains(&part.source) {
+                sources.push(part.source);
+            }
+        }
+        sources
+    }
+
+    /// The transcribed text of the message's audio, when it has any
+    pub fn transcript(&self) -> Option<String> {
+        let texts: Vec<&str> = self.parts.iter()
+            .filter(|p| p.source == PartSource::Audio)
+            .filter_map(|p| p.text.as_deref())
+            .collect();
+        (!texts.is_empty()).then(|| texts.join("\n\n"))
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let sources: Vec<&str> = self.sources().iter().map(PartSource::as_str).collect();
+        // A JSON string is a valid double-quoted YAML scalar
+        let category = self.category.as_deref()
+            .map(|category| format!("category: {}\n", serde_json::Value::from(category)))
+            .unwrap_or_default();
+        let mut note = format!(
+            "---\nsource: signal\nsignal_timestamp: {}\ncreated: {}\nsources: [{}]\ntags: [signal]\n{}---\n",
+            self.timestamp,
+            self.sent.to_rfc3339(),
+            sources.join(", "),
+            category
+        );
+
+        for part in &self.parts {
+            if let Some(embed) = &part.embed {
+                note.push_str(&format!("![[{}]]\n\n", embed));
+            }
+            match (part.source, &part.text) {
+                (_, None) => {}
+                (PartSource::Text, Some(text)) => note.push_str(&format!("{}\n\n", text)),
+                (PartSource::Audio, Some(text)) => {
+                    note.push_str(&format!("> [!transcript]\n> {}\n\n", text.replace('\n', "\n> ")));
+                }
+                (PartSource::Attachment, Some(text)) => {
+                    note.push_str(&format!("> [!attachment]\n> {}\n\n", text.replace('\n', "\n> ")));
+                }
+            }
+        }
+        note
+    }
+
+    /// The note as it will be indexed, stored at `path`
+    pub async fn to_document(&self, path: &Path) -> Result<ParsedDocument> {
+        ObsidianParser::new()?.parse_content(path, &self.to_markdown()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_text_and_voice_message_become_one_document() {
+        let mut note = MessageNote::new(1_700_000_000_000).unwrap()
+            .with_text(Some("Ideas for the garden"));
+        note.add_attachment("1700000000000-voice.m4a", PartSource::Audio, Some("Plant basil next to the tomatoes".to_string()));
+
+        let document = note.to_document(Path::new("Signal/note.md")).await.unwrap();
+        assert!(document.plain_text.contains("Ideas for the garden"));
+        assert!(document.plain_text.contains("Plant basil next to the tomatoes"));
+        assert!(document.content.contains("![[1700000000000-voice.m4a]]"));
+        let sources = &document.frontmatter.unwrap().custom_fields["sources"];
+        assert_eq!(sources, &serde_json::json!(["text", "audio"]));
+
+        // Without a body the attachment alone still makes a note
+        let mut voice_only = MessageNote::new(1_700_000_000_001).unwrap().with_text(Some("  "));
+        voice_only.add_attachment("voice.m4a", PartSource::Audio, Some("Buy seeds".to_string()));
+        assert_eq!(voice_only.sources(), [PartSource::Audio]);
+        assert!(voice_only.to_document(Path::new("Signal/voice.md")).await.unwrap().plain_text.contains("Buy seeds"));
+    }
+}
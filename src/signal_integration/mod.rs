@@ -1,18 +1,209 @@
+pub mod backfill;
 pub mod client;
 pub mod crypto;
+pub mod daemon;
+pub mod handler;
+pub mod message_note;
 pub mod protocol;
+pub mod registration;
+pub mod reply;
 
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 use crate::Result;
+use crate::vault::focus::{Focus, FocusSession};
+use crate::vault::ingest::UrlIngestor;
+use client::{SignalClient, SignalEnvelope};
+use reply::{ReplyConfig, ReplyPager};
 
-pub struct Signal;
+/// Command for saving a web page into the vault: `/save <url>`
+pub const SAVE_COMMAND: &str = "/save";
+
+/// Command for restricting searches: `/focus <folder|#tag>...`, `/focus` to show, `/focus clear`
+pub const FOCUS_COMMAND: &str = "/focus";
+
+/// Note to Self is a message the account sent, from one of its devices, to its own number
+pub fn is_note_to_self(envelope: &SignalEnvelope, own_number: &str) -> bool {
+    envelope.group_id.is_none()
+        && envelope.sender == own_number
+        && envelope.destination.as_deref() == Some(own_number)
+}
+
+/// Which conversation the assistant reacts to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageFilterConfig {
+    /// Process direct messages from this contact instead of Note to Self
+    pub contact: Option<String>,
+}
+
+impl MessageFilterConfig {
+    pub fn accepts(&self, envelope: &SignalEnvelope, own_number: &str) -> bool {
+        match &self.contact {
+            Some(contact) => {
+                envelope.group_id.is_none() && envelope.destination.is_none() && &envelope.sender == contact
+            }
+            None => is_note_to_self(envelope, own_number),
+        }
+    }
+
+    /// Where replies go: the allowlisted contact, or the account itself
+    pub fn reply_to<'a>(&'a self, own_number: &'a str) -> &'a str {
+        self.contact.as_deref().unwrap_or(own_number)
+    }
+}
+
+pub struct Signal {
+    pager: ReplyPager,
+    /// Connection replies go out on, and who they go to
+    client: Option<(Arc<SignalClient>, String)>,
+}
 
 impl Signal {
     pub fn new() -> Result<Self> {
-        Ok(Self)
+        Self::with_reply_config(ReplyConfig::default())
+    }
+    
+    pub fn with_reply_config(config: ReplyConfig) -> Result<Self> {
+        Ok(Self {
+            pager: ReplyPager::new(config),
+            client: None,
+        })
+    }
+    
+    /// Send replies to `recipient` through `client`; without one they are dropped
+    pub fn with_client(mut self, client: Arc<SignalClient>, recipient: &str) -> Self {
+        self.client = Some((client, recipient.to_string()));
+        self
     }
     
     pub async fn send_message(&self, message: &str) -> Result<()> {
-        // TODO: Implement Signal messaging
+        if let Some((client, recipient)) = &self.client {
+            client.send_message(recipient, message, &[]).await?;
+        }
         Ok(())
     }
+    
+    /// Send a possibly long AI reply, split to fit Signal's message size
+    pub async fn send_reply(&self, conversation_id: &str, reply: &str) -> Result<()> {
+        for message in self.pager.paginate(conversation_id, reply).await {
+            self.send_message(&message).await?;
+        }
+        Ok(())
+    }
+    
+    /// Handle an incoming "more" command; returns false if the message was something else
+    pub async fn send_more(&self, conversation_id: &str, message: &str) -> Result<bool> {
+        if !ReplyPager::is_more_command(message) {
+            return Ok(false);
+        }
+        
+        match self.pager.more(conversation_id).await {
+            Some(next) => self.send_message(&next).await?,
+            None => self.send_message("Nothing more to show.").await?,
+        }
+        Ok(true)
+    }
+    
+    /// URL argument of a `/save <url>` message
+    pub fn parse_save_command(message: &str) -> Option<&str> {
+        Self::command_argument(message, SAVE_COMMAND)
+    }
+    
+    fn command_argument<'a>(message: &'a str, command: &str) -> Option<&'a str> {
+        let rest = message.trim().strip_prefix(command)?;
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some(rest.trim())
+    }
+    
+    /// Handle an incoming "/save <url>" command; returns false if the message was something else
+    pub async fn send_save(&self, message: &str, ingestor: &UrlIngestor) -> Result<bool> {
+        let Some(url) = Self::parse_save_command(message) else {
+            return Ok(false);
+        };
+        
+        if url.is_empty() {
+            self.send_message("Usage: /save <url>").await?;
+            return Ok(true);
+        }
+        
+        match ingestor.ingest_url(url).await {
+            Ok(note) => self.send_message(&format!("Saved \"{}\"", note.document.title)).await?,
+            Err(e) => self.send_message(&format!("Couldn't save {}: {}", url, e)).await?,
+        }
+        Ok(true)
+    }
+    
+    /// Handle an incoming "/focus" command; returns false if the message was something else
+    pub async fn send_focus(&self, message: &str, session: &FocusSession) -> Result<bool> {
+        let Some(arguments) = Self::command_argument(message, FOCUS_COMMAND) else {
+            return Ok(false);
+        };
+        
+        let reply = match arguments {
+            "" => match session.current().await {
+                Some(focus) => format!("Focus: {}. Send \"/focus clear\" to search all notes.", focus.describe()),
+                None => "No focus set; searching all notes. Usage: /focus <folder|#tag>...".to_string(),
+            },
+            "clear" | "off" => {
+                session.clear().await?;
+                "Focus cleared; searching all notes.".to_string()
+            }
+            targets => {
+                let focus = Focus::parse(&targets.split_whitespace().collect::<Vec<_>>());
+                let reply = format!("Focus: {}. Send \"/focus clear\" to search all notes.", focus.describe());
+                session.set(focus).await?;
+                reply
+            }
+        };
+        self.send_message(&reply).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN: &str = "+15550100";
+
+    fn envelope(sender: &str, destination: Option<&str>, group_id: Option<&str>) -> SignalEnvelope {
+        SignalEnvelope {
+            sender: sender.to_string(),
+            destination: destination.map(str::to_string),
+            group_id: group_id.map(str::to_string),
+            timestamp: 1_700_000_000_000,
+            body: Some("Buy seeds".to_string()),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_only_note_to_self_is_processed_by_default() {
+        let filter = MessageFilterConfig::default();
+        let note_to_self = envelope(OWN, Some(OWN), None);
+        assert!(is_note_to_self(&note_to_self, OWN));
+        assert!(filter.accepts(&note_to_self, OWN));
+
+        // Sent from one of our devices to a group, to someone else, or received from a contact
+        let group = envelope(OWN, None, Some("Z3JvdXA="));
+        let sent_to_other = envelope(OWN, Some("+15550199"), None);
+        let from_contact = envelope("+15550199", None, None);
+        for other in [&group, &sent_to_other, &from_contact] {
+            assert!(!is_note_to_self(other, OWN));
+            assert!(!filter.accepts(other, OWN));
+        }
+        assert_eq!(filter.reply_to(OWN), OWN);
+    }
+
+    #[test]
+    fn test_allowlisted_contact_replaces_note_to_self() {
+        let filter = MessageFilterConfig { contact: Some("+15550199".to_string()) };
+        assert!(filter.accepts(&envelope("+15550199", None, None), OWN));
+        assert!(!filter.accepts(&envelope("+15550199", None, Some("Z3JvdXA=")), OWN));
+        assert!(!filter.accepts(&envelope("+15550123", None, None), OWN));
+        assert!(!filter.accepts(&envelope(OWN, Some(OWN), None), OWN));
+        assert_eq!(filter.reply_to(OWN), "+15550199");
+    }
 }
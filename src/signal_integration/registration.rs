@@ -0,0 +1,421 @@
+// src/signal_integration/registration.rs - Link or register a Signal account through signal-cli
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::Mutex;
+use crate::crypto::Crypto;
+use super::daemon::SignalDaemonClient;
+
+/// File the credentials are written to under the crypto key directory, encrypted when a passphrase is set
+pub const CREDENTIALS_FILE: &str = "signal_credentials.enc";
+
+/// How long linking waits for the QR code to be scanned
+const LINK_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupMethod {
+    /// Link as a secondary device by scanning a QR code in the Signal app
+    Link,
+    /// Register the number as a primary device with an SMS code
+    Sms,
+    /// Register the number as a primary device with a voice call code
+    Voice,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignalCredentials {
+    pub phone_number: String,
+    /// Primary registrations are device 1; linked devices get theirs from the server
+    pub device_id: Option<u32>,
+    pub linked: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetupOutcome {
+    Completed(SignalCredentials),
+    /// Credentials already existed and were left untouched
+    AlreadyLinked(SignalCredentials),
+}
+
+/// The signal-cli operations setup needs
+#[async_trait]
+pub trait SignalCli: Send + Sync {
+    /// Start linking and return the `sgnl://linkdevice` URI to show as a QR code
+    async fn start_link(&self, device_name: &str) -> Result<String>;
+    /// Wait for the primary device to confirm the link; returns the account's number
+    async fn finish_link(&self) -> Result<String>;
+    async fn register(&self, phone: &str, voice: bool) -> Result<()>;
+    async fn verify(&self, phone: &str, code: &str) -> Result<()>;
+}
+
+/// Interaction with the person running setup
+#[async_trait]
+pub trait SetupPrompt: Send + Sync {
+    async fn show_link(&self, uri: &str) -> Result<()>;
+    async fn verification_code(&self, phone: &str) -> Result<String>;
+}
+
+/// Runs the `signal-cli` executable
+pub struct SignalCliProcess {
+    program: PathBuf,
+    link: Mutex<Option<(Child, Lines<BufReader<ChildStdout>>)>>,
+}
+
+impl SignalCliProcess {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            link: Mutex::new(None),
+        }
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new(&self.program)
+            .args(args)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {}", self.program.display()))?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "signal-cli {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[async_trait]
+impl SignalCli for SignalCliProcess {
+    async fn start_link(&self, device_name: &str) -> Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(["link", "-n", device_name])
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program.display()))?;
+
+        let stdout = child.stdout.take().context("signal-cli produced no output")?;
+        let mut lines = BufReader::new(stdout).lines();
+        let uri = lines.next_line().await?
+            .filter(|line| line.starts_with("sgnl://"))
+            .context("signal-cli did not print a device link URI")?;
+
+        *self.link.lock().await = Some((child, lines));
+        Ok(uri)
+    }
+
+    async fn finish_link(&self) -> Result<String> {
+        let (mut child, mut lines) = self.link.lock().await.take()
+            .context("Linking was not started")?;
+
+        let mut phone = None;
+        while let Some(line) = lines.next_line().await? {
+            if let Some(number) = line.strip_prefix("Associated with: ") {
+                phone = Some(number.trim().to_string());
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("Linking was not confirmed on the primary device"));
+        }
+        phone.context("signal-cli did not report the linked number")
+    }
+
+    async fn register(&self, phone: &str, voice: bool) -> Result<()> {
+        let mut args = vec!["-a", phone, "register"];
+        if voice {
+            args.push("--voice");
+        }
+        self.run(&args).await?;
+        Ok(())
+    }
+
+    async fn verify(&self, phone: &str, code: &str) -> Result<()> {
+        self.run(&["-a", phone, "verify", code]).await?;
+        Ok(())
+    }
+}
+
+/// Links and registers through a running `signal-cli daemon`, which then serves the account
+/// without a restart
+pub struct SignalDaemonCli {
+    client: SignalDaemonClient,
+    /// Link URI and device name of the link in progress
+    link: Mutex<Option<(String, String)>>,
+}
+
+impl SignalDaemonCli {
+    pub fn new(client: SignalDaemonClient) -> Self {
+        Self { client, link: Mutex::new(None) }
+    }
+}
+
+#[async_trait]
+impl SignalCli for SignalDaemonCli {
+    async fn start_link(&self, device_name: &str) -> Result<String> {
+        let result = self.client.call("startLink", json!({})).await?;
+        let uri = result.get("deviceLinkUri")
+            .and_then(Value::as_str)
+            .filter(|uri| uri.starts_with("sgnl://"))
+            .context("signal-cli did not return a device link URI")?
+            .to_string();
+
+        *self.link.lock().await = Some((uri.clone(), device_name.to_string()));
+        Ok(uri)
+    }
+
+    async fn finish_link(&self) -> Result<String> {
+        let (uri, device_name) = self.link.lock().await.take()
+            .context("Linking was not started")?;
+        let result = self.client.call_within(
+            "finishLink",
+            json!({ "deviceLinkUri": uri, "deviceName": device_name }),
+            LINK_TIMEOUT,
+        ).await?;
+        result.get("number")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .context("signal-cli did not report the linked number")
+    }
+
+    async fn register(&self, phone: &str, voice: bool) -> Result<()> {
+        self.client.call("register", json!({ "account": phone, "voice": voice })).await?;
+        Ok(())
+    }
+
+    async fn verify(&self, phone: &str, code: &str) -> Result<()> {
+        self.client.call("verify", json!({ "account": phone, "verificationCode": code })).await?;
+        Ok(())
+    }
+}
+
+/// Credentials a previous setup stored under `key_dir`, if any
+pub fn load_credentials(key_dir: &Path, crypto: Option<&Crypto>) -> Result<Option<SignalCredentials>> {
+    let path = key_dir.join(CREDENTIALS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let stored = std::fs::read(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    // Credentials saved before encryption was set up stay readable
+    let bytes = match (crypto, Crypto::is_encrypted(&stored)) {
+        (Some(crypto), true) => crypto.decrypt(&stored)?,
+        (None, true) => return Err(anyhow::anyhow!(
+            "Stored Signal credentials are encrypted; set the passphrase to read them"
+        )),
+        (_, false) => stored,
+    };
+    let credentials = serde_json::from_slice(&bytes).context("Stored Signal credentials are corrupt")?;
+    Ok(Some(credentials))
+}
+
+pub struct SignalSetup<C: SignalCli> {
+    cli: C,
+    crypto: Option<Crypto>,
+    credentials_path: PathBuf,
+}
+
+impl<C: SignalCli> SignalSetup<C> {
+    pub fn new(cli: C, key_dir: &Path) -> Result<Self> {
+        Ok(Self {
+            cli,
+            crypto: None,
+            credentials_path: key_dir.join(CREDENTIALS_FILE),
+        })
+    }
+
+    /// Encrypt the credentials; without it they are written as plain JSON
+    pub fn with_crypto(mut self, crypto: Crypto) -> Self {
+        self.crypto = Some(crypto);
+        self
+    }
+
+    /// Stored credentials, if setup has completed before
+    pub fn credentials(&self) -> Result<Option<SignalCredentials>> {
+        let key_dir = self.credentials_path.parent().unwrap_or(Path::new(""));
+        load_credentials(key_dir, self.crypto.as_ref())
+    }
+
+    /// Link or register `phone`; existing credentials are reported, never overwritten
+    pub async fn run(&self, phone: &str, method: SetupMethod, prompt: &dyn SetupPrompt) -> Result<SetupOutcome> {
+        if let Some(existing) = self.credentials()? {
+            return Ok(SetupOutcome::AlreadyLinked(existing));
+        }
+
+        let credentials = match method {
+            SetupMethod::Link => {
+                let uri = self.cli.start_link("note-to-ai").await?;
+                prompt.show_link(&uri).await?;
+                let linked_number = self.cli.finish_link().await?;
+                if linked_number != phone {
+                    return Err(anyhow::anyhow!(
+                        "Linked to {} but setup was run for {}", linked_number, phone
+                    ));
+                }
+                SignalCredentials {
+                    phone_number: linked_number,
+                    device_id: None,
+                    linked: true,
+                    created_at: chrono::Utc::now(),
+                }
+            }
+            SetupMethod::Sms | SetupMethod::Voice => {
+                self.cli.register(phone, method == SetupMethod::Voice).await?;
+                let code = prompt.verification_code(phone).await?;
+                self.cli.verify(phone, code.trim()).await?;
+                SignalCredentials {
+                    phone_number: phone.to_string(),
+                    device_id: Some(1),
+                    linked: false,
+                    created_at: chrono::Utc::now(),
+                }
+            }
+        };
+
+        self.save(&credentials)?;
+        Ok(SetupOutcome::Completed(credentials))
+    }
+
+    fn save(&self, credentials: &SignalCredentials) -> Result<()> {
+        if let Some(parent) = self.credentials_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut bytes = serde_json::to_vec(credentials)?;
+        if let Some(crypto) = &self.crypto {
+            bytes = crypto.encrypt(&bytes)?;
+        }
+        std::fs::write(&self.credentials_path, bytes)
+            .with_context(|| format!("Failed to write {}", self.credentials_path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    #[derive(Default)]
+    struct MockSignalCli {
+        calls: StdMutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl SignalCli for MockSignalCli {
+        async fn start_link(&self, device_name: &str) -> Result<String> {
+            self.calls.lock().unwrap().push(format!("link {}", device_name));
+            Ok("sgnl://linkdevice?uuid=abc&pub_key=xyz".to_string())
+        }
+
+        async fn finish_link(&self) -> Result<String> {
+            self.calls.lock().unwrap().push("finish".to_string());
+            Ok("+15550100".to_string())
+        }
+
+        async fn register(&self, phone: &str, voice: bool) -> Result<()> {
+            self.calls.lock().unwrap().push(format!("register {} {}", phone, voice));
+            Ok(())
+        }
+
+        async fn verify(&self, phone: &str, code: &str) -> Result<()> {
+            self.calls.lock().unwrap().push(format!("verify {} {}", phone, code));
+            Ok(())
+        }
+    }
+
+    struct MockPrompt {
+        shown: StdMutex<Option<String>>,
+    }
+
+    #[async_trait]
+    impl SetupPrompt for MockPrompt {
+        async fn show_link(&self, uri: &str) -> Result<()> {
+            *self.shown.lock().unwrap() = Some(uri.to_string());
+            Ok(())
+        }
+
+        async fn verification_code(&self, _phone: &str) -> Result<String> {
+            Ok("123-456\n".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_link_persists_credentials_and_rerun_keeps_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let prompt = MockPrompt { shown: StdMutex::new(None) };
+        let setup = SignalSetup::new(MockSignalCli::default(), dir.path()).unwrap();
+
+        let outcome = setup.run("+15550100", SetupMethod::Link, &prompt).await.unwrap();
+        let SetupOutcome::Completed(credentials) = outcome else {
+            panic!("expected a completed setup, got {:?}", outcome);
+        };
+        assert!(credentials.linked);
+        assert!(prompt.shown.lock().unwrap().as_deref().unwrap().starts_with("sgnl://linkdevice"));
+        assert!(dir.path().join(CREDENTIALS_FILE).exists());
+        assert_eq!(setup.credentials().unwrap(), Some(credentials.clone()));
+
+        let again = setup.run("+15550100", SetupMethod::Sms, &prompt).await.unwrap();
+        assert_eq!(again, SetupOutcome::AlreadyLinked(credentials));
+        assert_eq!(*setup.cli.calls.lock().unwrap(), vec!["link note-to-ai", "finish"]);
+    }
+
+    #[tokio::test]
+    async fn test_sms_registration_verifies_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let prompt = MockPrompt { shown: StdMutex::new(None) };
+        let setup = SignalSetup::new(MockSignalCli::default(), dir.path()).unwrap();
+
+        setup.run("+15550100", SetupMethod::Sms, &prompt).await.unwrap();
+
+        assert_eq!(
+            *setup.cli.calls.lock().unwrap(),
+            vec!["register +15550100 false", "verify +15550100 123-456"]
+        );
+        assert_eq!(setup.credentials().unwrap().unwrap().device_id, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_link_through_mock_provisioning_socket() {
+        use crate::test_support::{json_rpc_daemon, rpc_reply};
+        use super::super::daemon::SignalDaemonConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("socket");
+        let mut received = json_rpc_daemon(&socket, |request| {
+            let result = match request["method"].as_str().unwrap() {
+                "startLink" => json!({ "deviceLinkUri": "sgnl://linkdevice?uuid=abc&pub_key=xyz" }),
+                "finishLink" => json!({ "number": "+15550100" }),
+                _ => Value::Null,
+            };
+            vec![rpc_reply(request, json!({ "result": result }))]
+        });
+
+        let client = SignalDaemonClient::new(SignalDaemonConfig { socket_path: socket, timeout_ms: 2000 });
+        let prompt = MockPrompt { shown: StdMutex::new(None) };
+        let setup = SignalSetup::new(SignalDaemonCli::new(client), dir.path()).unwrap();
+
+        let outcome = setup.run("+15550100", SetupMethod::Link, &prompt).await.unwrap();
+        assert!(matches!(outcome, SetupOutcome::Completed(ref c) if c.linked && c.phone_number == "+15550100"));
+        assert_eq!(prompt.shown.lock().unwrap().as_deref(), Some("sgnl://linkdevice?uuid=abc&pub_key=xyz"));
+        assert_eq!(received.recv().await.unwrap()["method"], "startLink");
+        let finish = received.recv().await.unwrap();
+        assert_eq!(finish["params"]["deviceLinkUri"], "sgnl://linkdevice?uuid=abc&pub_key=xyz");
+        assert_eq!(finish["params"]["deviceName"], "note-to-ai");
+
+        // A later run finds the stored account and leaves the daemon alone
+        let stored = load_credentials(dir.path(), None).unwrap().unwrap();
+        let again = setup.run("+15550100", SetupMethod::Link, &prompt).await.unwrap();
+        assert_eq!(again, SetupOutcome::AlreadyLinked(stored));
+        assert!(received.try_recv().is_err());
+    }
+}
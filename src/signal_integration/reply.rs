@@ -0,0 +1,276 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use crate::ai::segment::{segments, split_sentences, SegmentKind};
+
+/// Text a user sends to receive the next part of a truncated reply
+pub const MORE_COMMAND: &str = "more";
+const MORE_HINT: &str = "\n\n(reply 'more' to continue)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyMode {
+    /// Send every part immediately as a thread of messages
+    Thread,
+    /// Send the first part and hold the rest until the user replies "more"
+    ReadMore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplyConfig {
+    /// Maximum characters per outgoing message
+    pub max_message_chars: usize,
+    pub mode: ReplyMode,
+}
+
+impl Default for ReplyConfig {
+    fn default() -> Self {
+        Self {
+            max_message_chars: 2000,
+            mode: ReplyMode::ReadMore,
+        }
+    }
+}
+
+struct Block {
+    code: bool,
+    text: String,
+}
+
+/// Split a reply into messages of at most `max_chars` characters, breaking on
+/// paragraph and then sentence boundaries. Code blocks are never cut mid-block:
+/// one that is too large on its own is re-fenced so every part is a complete block.
+pub fn split_reply(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(16);
+    let mut pieces = Vec::new();
+
+    for block in blocks(text) {
+        if char_len(&block.text) <= max_chars {
+            pieces.push(block.text);
+        } else if block.code {
+            pieces.extend(split_code_block(&block.text, max_chars));
+        } else {
+            for sentence in split_sentences(&block.text) {
+                if char_len(&sentence) <= max_chars {
+                    pieces.push(sentence);
+                } else {
+                    pieces.extend(hard_split(&sentence, max_chars));
+                }
+            }
+        }
+    }
+
+    let mut messages = Vec::new();
+    let mut current = String::new();
+
+    for piece in pieces {
+        let separator = if current.is_empty() { "" } else { "\n\n" };
+        if char_len(&current) + separator.len() + char_len(&piece) > max_chars && !current.is_empty() {
+            messages.push(std::mem::take(&mut current));
+            current.push_str(&piece);
+        } else {
+            current.push_str(separator);
+            current.push_str(&piece);
+        }
+    }
+
+    if !current.is_empty() {
+        messages.push(current);
+    }
+
+    messages
+}
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Paragraphs and tables are split further when too long; fenced code is kept whole if it fits
+fn blocks(text: &str) -> Vec<Block> {
+    segments(text).into_iter()
+        .map(|segment| Block { code: segment.kind == SegmentKind::Code, text: segment.text })
+        .collect()
+}
+
+fn split_code_block(block: &str, max_chars: usize) -> Vec<String> {
+    let mut lines: Vec<&str> = block.lines().collect();
+    let open = lines.remove(0);
+    let marker = &open.trim_start()[..3];
+    if lines.last().is_some_and(|line| line.trim_start().starts_with(marker)) {
+        lines.pop();
+    }
+
+    // Room left for code once the opening and closing fences are added
+    let capacity = max_chars.saturating_sub(char_len(open) + marker.len() + 2).max(1);
+    let mut parts = Vec::new();
+    let mut body = String::new();
+
+    let mut push_part = |body: &mut String| {
+        parts.push(format!("{}\n{}\n{}", open, body, marker));
+        body.clear();
+    };
+
+    for line in lines {
+        for segment in hard_split(line, capacity) {
+            let extra = if body.is_empty() { 0 } else { 1 };
+            if char_len(&body) + extra + char_len(&segment) > capacity && !body.is_empty() {
+                push_part(&mut body);
+            }
+            if !body.is_empty() {
+                body.push('\n');
+            }
+            body.push_str(&segment);
+        }
+    }
+
+    if !body.is_empty() {
+        push_part(&mut body);
+    }
+
+    parts
+}
+
+/// Last resort for a single sentence or line longer than a message: break on whitespace
+fn hard_split(text: &str, max_chars: usize) -> Vec<String> {
+    if text.is_empty() {
+        return vec![String::new()];
+    }
+
+    let mut parts = Vec::new();
+    let mut rest: Vec<char> = text.chars().collect();
+
+    while rest.len() > max_chars {
+        let cut = rest[..max_chars]
+            .iter()
+            .rposition(|c| c.is_whitespace())
+            .filter(|&i| i > 0)
+            .unwrap_or(max_chars);
+        parts.push(rest[..cut].iter().collect::<String>().trim_end().to_string());
+        rest = rest[cut..].iter().copied().skip_while(|c| c.is_whitespace()).collect();
+    }
+
+    if !rest.is_empty() {
+        parts.push(rest.into_iter().collect());
+    }
+
+    parts
+}
+
+/// Holds the unsent parts of long replies per conversation for the "more" command
+#[derive(Clone)]
+pub struct ReplyPager {
+    config: ReplyConfig,
+    pending: Arc<RwLock<HashMap<String, VecDeque<String>>>>,
+}
+
+impl ReplyPager {
+    pub fn new(config: ReplyConfig) -> Self {
+        Self {
+            config,
+            pending: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Messages to send now for a reply; in read-more mode the remainder is stored
+    pub async fn paginate(&self, conversation_id: &str, text: &str) -> Vec<String> {
+        match self.config.mode {
+            ReplyMode::Thread => split_reply(text, self.config.max_message_chars),
+            ReplyMode::ReadMore => {
+                let budget = self.config.max_message_chars.saturating_sub(char_len(MORE_HINT));
+                let mut parts: VecDeque<String> = split_reply(text, budget).into();
+                let first = parts.pop_front().unwrap_or_default();
+
+                let mut pending = self.pending.write().await;
+                if parts.is_empty() {
+                    pending.remove(conversation_id);
+                    vec![first]
+                } else {
+                    pending.insert(conversation_id.to_string(), parts);
+                    vec![format!("{}{}", first, MORE_HINT)]
+                }
+            }
+        }
+    }
+
+    /// Next stored part for a conversation, if the user asked for more
+    pub async fn more(&self, conversation_id: &str) -> Option<String> {
+        let mut pending = self.pending.write().await;
+        let parts = pending.get_mut(conversation_id)?;
+        let next = parts.pop_front()?;
+
+        if parts.is_empty() {
+            pending.remove(conversation_id);
+            Some(next)
+        } else {
+            Some(format!("{}{}", next, MORE_HINT))
+        }
+    }
+
+    pub fn is_more_command(message: &str) -> bool {
+        message.trim().eq_ignore_ascii_case(MORE_COMMAND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn long_answer() -> String {
+        let mut answer = String::new();
+        for i in 0..8 {
+            answer.push_str(&format!(
+                "Step {} covers part of the setup. It explains why the vault needs indexing first. \
+                 Then it moves on to the embedding configuration.\n\n",
+                i
+            ));
+            answer.push_str("```rust\nlet vault = Vault::open(path, &config).await?;\nvault.index_document(&doc).await?;\n```\n\n");
+        }
+        answer
+    }
+
+    #[test]
+    fn test_long_answer_splits_on_boundaries() {
+        let max = 300;
+        let messages = split_reply(&long_answer(), max);
+        assert!(messages.len() > 1);
+
+        for message in &messages {
+            assert!(message.chars().count() <= max, "message too long: {}", message.len());
+            assert_eq!(message.matches("```").count() % 2, 0, "message splits a code block:\n{}", message);
+            assert!(message.ends_with('.') || message.ends_with("```"), "message ends mid-sentence:\n{}", message);
+        }
+
+        let words: usize = messages.iter().map(|m| m.split_whitespace().count()).sum();
+        assert_eq!(words, long_answer().split_whitespace().count());
+    }
+
+    #[test]
+    fn test_oversized_code_block_is_refenced() {
+        let code = format!("```python\n{}```", "print('hello world')\n".repeat(40));
+        let messages = split_reply(&code, 200);
+        assert!(messages.len() > 1);
+
+        for message in &messages {
+            assert!(message.chars().count() <= 200);
+            assert!(message.starts_with("```python\n") && message.ends_with("\n```"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_more_pages_through_reply() {
+        let pager = ReplyPager::new(ReplyConfig { max_message_chars: 300, mode: ReplyMode::ReadMore });
+
+        let first = pager.paginate("alice", &long_answer()).await;
+        assert_eq!(first.len(), 1);
+        assert!(first[0].ends_with(MORE_HINT));
+
+        let mut last = None;
+        while let Some(next) = pager.more("alice").await {
+            assert!(next.chars().count() <= 300);
+            last = Some(next);
+        }
+        assert!(!last.unwrap().ends_with(MORE_HINT));
+        assert!(pager.more("alice").await.is_none());
+    }
+}
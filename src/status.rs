@@ -0,0 +1,198 @@
+// src/status.rs - The `status` report: figures gathered from each subsystem, rendered as text
+use std::time::Duration;
+
+/// Shown in place of a figure whose subsystem could not be read
+pub const UNAVAILABLE: &str = "unavailable";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageStatus {
+    pub documents: usize,
+    pub embeddings: usize,
+    pub size_bytes: u64,
+    /// Mean search time; `None` when searches aren't timed
+    pub avg_search: Option<Duration>,
+    /// Most used tags with their document counts
+    pub top_tags: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelStatus {
+    /// Model the switcher has selected, if any
+    pub current: Option<String>,
+    /// Registered models and whether each is available
+    pub registered: Vec<(String, bool)>,
+    pub embedding_model: String,
+    /// Downloaded Whisper model, if any
+    pub whisper: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalAccount {
+    Linked,
+    Registered,
+    NotSetUp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalStatus {
+    pub account: SignalAccount,
+    pub phone: Option<String>,
+    /// Daemon round trip, or why it couldn't be reached; `None` when there was no account to check
+    pub daemon: Option<Result<Duration, String>>,
+}
+
+impl SignalStatus {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let status = match self.account {
+            SignalAccount::Linked => "linked as a secondary device",
+            SignalAccount::Registered => "registered as the primary device",
+            SignalAccount::NotSetUp => "not set up; run `note-to-ai signal setup`",
+        };
+        out.push_str(&format!("  Status: {}\n", status));
+        if let Some(phone) = &self.phone {
+            out.push_str(&format!("  Phone: {}\n", phone));
+        }
+        match &self.daemon {
+            Some(Ok(latency)) => out.push_str(&format!("  Daemon: connected ({:.1}ms)\n", latency.as_secs_f64() * 1000.0)),
+            Some(Err(e)) => out.push_str(&format!("  Daemon: {}\n", e)),
+            None => {}
+        }
+        out
+    }
+}
+
+/// Each section is `Err` with the reason when its subsystem couldn't be read
+#[derive(Debug, Clone)]
+pub struct StatusReport {
+    pub storage: Result<StorageStatus, String>,
+    pub focus: Option<String>,
+    pub models: Result<ModelStatus, String>,
+    pub signal: Result<SignalStatus, String>,
+}
+
+impl StatusReport {
+    pub fn is_healthy(&self) -> bool {
+        self.storage.is_ok()
+            && self.models.is_ok()
+            && matches!(&self.signal, Ok(signal) if matches!(signal.daemon, Some(Ok(_))))
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("🤖 note-to-ai System Status\n");
+        out.push_str("===========================\n");
+
+        out.push_str("📊 Storage:\n");
+        match &self.storage {
+            Ok(storage) => {
+                out.push_str(&format!("  Documents: {}\n", storage.documents));
+                out.push_str(&format!("  Embeddings: {}\n", storage.embeddings));
+                out.push_str(&format!("  Storage size: {}\n", format_size(storage.size_bytes)));
+                let avg_search = storage.avg_search
+                    .map(|avg| format!("{:.2}ms", avg.as_secs_f64() * 1000.0))
+                    .unwrap_or_else(|| UNAVAILABLE.to_string());
+                out.push_str(&format!("  Avg search time: {}\n", avg_search));
+                let top_tags: Vec<String> = storage.top_tags.iter()
+                    .map(|(tag, count)| format!("#{} ({})", tag, count))
+                    .collect();
+                let top_tags = if top_tags.is_empty() { "none".to_string() } else { top_tags.join(", ") };
+                out.push_str(&format!("  Top tags: {}\n", top_tags));
+            }
+            Err(e) => out.push_str(&format!("  {} ({})\n", UNAVAILABLE, e)),
+        }
+        match &self.focus {
+            Some(focus) => out.push_str(&format!("  Focus: {}\n", focus)),
+            None => out.push_str("  Focus: none (searching all notes)\n"),
+        }
+
+        out.push_str("\n🧠 AI Models:\n");
+        match &self.models {
+            Ok(models) => {
+                let available = models.registered.iter().filter(|(_, available)| *available).count();
+                let llm = match (&models.current, models.registered.len()) {
+                    (_, 0) => "none registered".to_string(),
+                    (Some(current), n) => format!("{} ({} registered, {} available)", current, n, available),
+                    (None, n) => format!("none selected ({} registered, {} available)", n, available),
+                };
+                out.push_str(&format!("  LLM: {}\n", llm));
+                out.push_str(&format!("  Embeddings: {}\n", models.embedding_model));
+                out.push_str(&format!("  Whisper: {}\n", models.whisper.as_deref().unwrap_or("none downloaded")));
+            }
+            Err(e) => out.push_str(&format!("  {} ({})\n", UNAVAILABLE, e)),
+        }
+
+        out.push_str("\n📱 Signal:\n");
+        match &self.signal {
+            Ok(signal) => out.push_str(&signal.render()),
+            Err(e) => out.push_str(&format!("  {} ({})\n", UNAVAILABLE, e)),
+        }
+
+        if self.is_healthy() {
+            out.push_str("\n✅ System is healthy and ready!\n");
+        } else {
+            out.push_str("\n⚠️  Some subsystems are unavailable; see above\n");
+        }
+        out
+    }
+}
+
+/// Bytes as MB, or GB from a gigabyte up
+pub fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    let mb = bytes as f64 / MB;
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.1} MB", mb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_renders_known_stats_and_unavailable_sections() {
+        let report = StatusReport {
+            storage: Ok(StorageStatus {
+                documents: 42,
+                embeddings: 40,
+                size_bytes: 3 * 1024 * 1024 / 2,
+                avg_search: None,
+                top_tags: vec![("rust".to_string(), 12), ("ideas".to_string(), 5)],
+            }),
+            focus: None,
+            models: Ok(ModelStatus {
+                current: Some("mistral-7b".to_string()),
+                registered: vec![("mistral-7b".to_string(), true), ("phi-2".to_string(), false)],
+                embedding_model: "all-MiniLM-L6-v2".to_string(),
+                whisper: None,
+            }),
+            signal: Ok(SignalStatus {
+                account: SignalAccount::Linked,
+                phone: Some("+15550100".to_string()),
+                daemon: Some(Ok(Duration::from_micros(1500))),
+            }),
+        };
+
+        let out = report.render();
+        assert!(out.contains("  Documents: 42\n  Embeddings: 40\n  Storage size: 1.5 MB\n"));
+        assert!(out.contains("  Avg search time: unavailable\n"));
+        assert!(out.contains("  Top tags: #rust (12), #ideas (5)\n"));
+        assert!(out.contains("  LLM: mistral-7b (2 registered, 1 available)\n"));
+        assert!(out.contains("  Whisper: none downloaded\n"));
+        assert!(out.contains("  Status: linked as a secondary device\n  Phone: +15550100\n  Daemon: connected (1.5ms)\n"));
+        assert!(out.contains("System is healthy"));
+
+        let degraded = StatusReport {
+            storage: Err("database is locked".to_string()),
+            signal: Ok(SignalStatus { account: SignalAccount::NotSetUp, phone: None, daemon: None }),
+            ..report
+        };
+        let out = degraded.render();
+        assert!(out.contains("📊 Storage:\n  unavailable (database is locked)\n"));
+        assert!(!out.contains("Documents:"));
+        assert!(out.contains("Some subsystems are unavailable"));
+    }
+}
@@ -1,14 +1,23 @@
-use crate::Result;
+// src/swarm/discovery.rs - Finding the swarm's peers on the local network
+use anyhow::Result;
+use libp2p::mdns;
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::{Multiaddr, PeerId};
 
-pub struct Discovery;
+/// mDNS announcements and queries when `enabled`; otherwise peers are only reached by dialing
+pub fn local_discovery(enabled: bool, peer_id: PeerId) -> Result<Toggle<mdns::tokio::Behaviour>> {
+    let behaviour = if enabled {
+        Some(mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)?)
+    } else {
+        None
+    };
+    Ok(Toggle::from(behaviour))
+}
 
-impl Discovery {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
-    }
-    
-    pub async fn discover_devices(&self) -> Result<Vec<String>> {
-        // TODO: Implement device discovery in private swarm
-        Ok(vec![])
+/// Newly seen peers and an address to dial each at; expiries need no action
+pub fn discovered_peers(event: mdns::Event) -> Vec<(PeerId, Multiaddr)> {
+    match event {
+        mdns::Event::Discovered(peers) => peers,
+        mdns::Event::Expired(_) => Vec::new(),
     }
 }
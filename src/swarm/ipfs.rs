@@ -1,14 +1,70 @@
-use crate::Result;
+// src/swarm/ipfs.rs - Content-addressed store of encrypted blobs shared across the private swarm
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::{anyhow, Context, Result};
+use crate::crypto::Crypto;
 
-pub struct IPFSNode;
+/// Blobs named by the hex BLAKE3 hash of their plaintext, the hash the indexer records for each
+/// file. Only the encrypted block is kept, and it is what peers are sent
+pub struct BlobStore {
+    dir: PathBuf,
+    crypto: Arc<Crypto>,
+}
+
+impl BlobStore {
+    pub fn new(dir: PathBuf, crypto: Arc<Crypto>) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create blob directory {}", dir.display()))?;
+        Ok(Self { dir, crypto })
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.path_for(hash).map(|path| path.exists()).unwrap_or(false)
+    }
+
+    /// Encrypt and store `content`, returning its hash
+    pub fn put(&self, content: &[u8]) -> Result<String> {
+        let hash = blake3::hash(content).to_hex().to_string();
+        if !self.contains(&hash) {
+            std::fs::write(self.path_for(&hash)?, self.crypto.encrypt(content)?)?;
+        }
+        Ok(hash)
+    }
+
+    /// The encrypted block for `hash`, as sent to peers
+    pub fn block(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path_for(hash)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(std::fs::read(path)?))
+    }
 
-impl IPFSNode {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+    pub fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        match self.block(hash)? {
+            Some(block) => Ok(Some(self.crypto.decrypt(&block)?)),
+            None => Ok(None),
+        }
     }
-    
-    pub async fn start_private_node(&self) -> Result<()> {
-        // TODO: Implement private IPFS node
-        Ok(())
+
+    /// Keep a block received from a peer once it decrypts to content hashing to `hash`;
+    /// returns that content
+    pub fn insert_block(&self, hash: &str, block: &[u8]) -> Result<Vec<u8>> {
+        let path = self.path_for(hash)?;
+        let content = self.crypto.decrypt(block)
+            .context("Blob was not encrypted with this swarm's key")?;
+        if blake3::hash(&content).to_hex().as_str() != hash {
+            return Err(anyhow!("Blob content does not match its hash {}", hash));
+        }
+        std::fs::write(path, block)?;
+        Ok(content)
+    }
+
+    /// Hashes come from peers, so anything but 64 hex digits is refused rather than joined to a path
+    fn path_for(&self, hash: &str) -> Result<PathBuf> {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!("Invalid blob hash {:?}", hash));
+        }
+        Ok(self.dir.join(hash.to_ascii_lowercase()))
     }
 }
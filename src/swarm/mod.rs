@@ -2,17 +2,338 @@ pub mod discovery;
 pub mod ipfs;
 pub mod sync;
 
-use crate::Result;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use libp2p::futures::StreamExt;
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{identity, mdns, Multiaddr, PeerId, SwarmBuilder};
+use rand::RngCore;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::config::settings::SwarmConfig;
+use crate::logger::Logger;
+use self::sync::{SyncBehaviour, SyncEvent, SyncMessage, VaultSync};
 
-pub struct Swarm;
+/// First line of an IPFS private network key file
+const SWARM_KEY_HEADER: &str = "/key/swarm/psk/1.0.0/";
+/// How long an idle peer connection stays open
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(NetworkBehaviour)]
+pub struct SwarmBehaviour {
+    sync: SyncBehaviour,
+    discovery: Toggle<mdns::tokio::Behaviour>,
+}
+
+enum Command {
+    Dial(Multiaddr),
+    /// Send our manifest to every connected peer
+    Announce,
+    Shutdown,
+}
+
+/// A private swarm of this user's devices: peers share the swarm key, which seals every
+/// frame, so only encrypted data leaves the device and strangers can't join
+pub struct Swarm {
+    config: SwarmConfig,
+    sync: Arc<VaultSync>,
+    logger: Logger,
+}
 
 impl Swarm {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+    pub fn new(config: SwarmConfig, sync: VaultSync) -> Self {
+        Self {
+            config,
+            sync: Arc::new(sync),
+            logger: Logger::new("Swarm"),
+        }
+    }
+
+    /// Listen, dial the bootstrap nodes and replicate with every peer that connects,
+    /// until the returned handle is shut down
+    pub async fn start(self) -> Result<SwarmHandle> {
+        let keypair = load_keypair(&self.config.private_key_path)?;
+        let peer_id = keypair.public().to_peer_id();
+        let mdns = self.config.mdns;
+        let mut swarm = SwarmBuilder::with_existing_identity(keypair)
+            .with_tokio()
+            .with_quic()
+            .with_behaviour(|key| -> Result<SwarmBehaviour, Box<dyn std::error::Error + Send + Sync>> {
+                Ok(SwarmBehaviour {
+                    sync: SyncBehaviour::default(),
+                    discovery: discovery::local_discovery(mdns, key.public().to_peer_id())?,
+                })
+            })
+            .map_err(|e| anyhow!("Failed to create swarm behaviour: {}", e))?
+            .with_swarm_config(|config| config.with_idle_connection_timeout(IDLE_TIMEOUT))
+            .build();
+
+        let listen_addr: Multiaddr = self.config.listen_addr.parse()
+            .with_context(|| format!("Invalid swarm listen address {}", self.config.listen_addr))?;
+        swarm.listen_on(listen_addr)?;
+        let mut listen_addrs = Vec::new();
+        while listen_addrs.is_empty() {
+            if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+                listen_addrs.push(address);
+            }
+        }
+        self.logger.info(&format!("Swarm peer {} listening on {:?}", peer_id, listen_addrs));
+
+        for node in &self.config.bootstrap_nodes {
+            match node.parse::<Multiaddr>() {
+                Ok(addr) => {
+                    if let Err(e) = swarm.dial(addr) {
+                        self.logger.warn(&format!("Failed to dial bootstrap node {}: {}", node, e));
+                    }
+                }
+                Err(e) => self.logger.warn(&format!("Invalid bootstrap node {}: {}", node, e)),
+            }
+        }
+
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(self.run(swarm, receiver));
+        Ok(SwarmHandle { peer_id, listen_addrs, commands, task })
+    }
+
+    async fn run(self, mut swarm: libp2p::Swarm<SwarmBehaviour>, mut commands: mpsc::UnboundedReceiver<Command>) {
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(Command::Dial(addr)) => {
+                        if let Err(e) = swarm.dial(addr.clone()) {
+                            self.logger.warn(&format!("Failed to dial {}: {}", addr, e));
+                        }
+                    }
+                    Some(Command::Announce) => {
+                        let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+                        for peer in peers {
+                            self.announce(&mut swarm, peer).await;
+                        }
+                    }
+                    Some(Command::Shutdown) | None => break,
+                },
+                event = swarm.select_next_some() => match event {
+                    SwarmEvent::Behaviour(SwarmBehaviourEvent::Discovery(event)) => {
+                        for (peer, addr) in discovery::discovered_peers(event) {
+                            if !swarm.is_connected(&peer) {
+                                let _ = swarm.dial(addr);
+                            }
+                        }
+                    }
+                    SwarmEvent::Behaviour(SwarmBehaviourEvent::Sync(event)) => self.on_sync_event(&mut swarm, event).await,
+                    SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                        self.logger.warn(&format!("Failed to connect to {:?}: {}", peer_id, error));
+                    }
+                    _ => {}
+                },
+            }
+        }
+        self.logger.info("Swarm stopped");
+    }
+
+    async fn on_sync_event(&self, swarm: &mut libp2p::Swarm<SwarmBehaviour>, event: SyncEvent) {
+        match event {
+            SyncEvent::Connected(peer) => self.announce(swarm, peer).await,
+            SyncEvent::Frame { peer, frame } => {
+                let replies = match SyncMessage::decode(&frame, self.sync.crypto()) {
+                    Ok(message) => self.sync.handle(message).await,
+                    Err(e) => {
+                        // Most likely a peer holding a different swarm key
+                        self.logger.warn(&format!("Dropping peer {}: {}", peer, e));
+                        let _ = swarm.disconnect_peer_id(peer);
+                        return;
+                    }
+                };
+                match replies {
+                    Ok(replies) => {
+                        for reply in replies {
+                            self.send(swarm, peer, &reply);
+                        }
+                    }
+                    Err(e) => self.logger.error(&format!("Sync with {} failed: {}", peer, e)),
+                }
+            }
+            SyncEvent::SendFailed { peer, error } => {
+                self.logger.warn(&format!("Failed to reach {}: {}", peer, error));
+            }
+        }
+    }
+
+    async fn announce(&self, swarm: &mut libp2p::Swarm<SwarmBehaviour>, peer: PeerId) {
+        match self.sync.manifest().await {
+            Ok(manifest) => self.send(swarm, peer, &SyncMessage::Have(manifest)),
+            Err(e) => self.logger.error(&format!("Failed to build the vault manifest: {}", e)),
+        }
+    }
+
+    fn send(&self, swarm: &mut libp2p::Swarm<SwarmBehaviour>, peer: PeerId, message: &SyncMessage) {
+        match message.encode(self.sync.crypto()) {
+            Ok(frame) => swarm.behaviour_mut().sync.send(peer, frame),
+            Err(e) => self.logger.error(&format!("Failed to encode a message for {}: {}", peer, e)),
+        }
+    }
+}
+
+/// A running swarm
+pub struct SwarmHandle {
+    pub peer_id: PeerId,
+    pub listen_addrs: Vec<Multiaddr>,
+    commands: mpsc::UnboundedSender<Command>,
+    task: JoinHandle<()>,
+}
+
+impl SwarmHandle {
+    pub fn dial(&self, addr: Multiaddr) -> Result<()> {
+        self.commands.send(Command::Dial(addr)).map_err(|_| anyhow!("Swarm has stopped"))
+    }
+
+    /// Tell connected peers about local changes, e.g. after re-indexing
+    pub fn announce(&self) -> Result<()> {
+        self.commands.send(Command::Announce).map_err(|_| anyhow!("Swarm has stopped"))
+    }
+
+    pub async fn shutdown(self) {
+        let _ = self.commands.send(Command::Shutdown);
+        let _ = self.task.await;
     }
-    
-    pub async fn start(&self) -> Result<()> {
-        // TODO: Implement IPFS private swarm
-        Ok(())
+}
+
+/// The swarm's shared secret, in the IPFS private network key format; a new key is written
+/// when the file is missing, to be copied to the user's other devices
+pub fn load_swarm_key(path: &Path) -> Result<String> {
+    if !path.exists() {
+        let mut key = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        write_secret(path, format!("{}\n/base16/\n{}\n", SWARM_KEY_HEADER, hex).as_bytes())
+            .with_context(|| format!("Failed to write swarm key {}", path.display()))?;
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read swarm key {}", path.display()))?;
+    contents.lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .filter(|key| !key.starts_with('/'))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Swarm key {} is empty", path.display()))
+}
+
+/// This device's peer id, creating its identity if the swarm hasn't started yet
+pub fn local_peer_id(config: &SwarmConfig) -> Result<PeerId> {
+    Ok(load_keypair(&config.private_key_path)?.public().to_peer_id())
+}
+
+/// This device's peer identity, created on first start
+fn load_keypair(path: &Path) -> Result<identity::Keypair> {
+    if path.exists() {
+        let bytes = std::fs::read(path)?;
+        return identity::Keypair::from_protobuf_encoding(&bytes)
+            .with_context(|| format!("Invalid peer key {}", path.display()));
+    }
+    let keypair = identity::Keypair::generate_ed25519();
+    write_secret(path, &keypair.to_protobuf_encoding()?)
+        .with_context(|| format!("Failed to write peer key {}", path.display()))?;
+    Ok(keypair)
+}
+
+/// Create `path` readable only by its owner, failing if it already exists
+fn write_secret(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::crypto::{Crypto, KdfParams};
+    use crate::swarm::ipfs::BlobStore;
+    use crate::vault::indexer::VaultIndexer;
+
+    /// A device with its own vault, index and keys, joining the swarm sealed by `swarm_key`
+    async fn node(root: &Path, swarm_key: &str) -> (SwarmHandle, PathBuf) {
+        let vault = root.join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        let indexer = VaultIndexer::new(root.join("index.db"), vault.clone()).unwrap();
+        indexer.initialize_db().await.unwrap();
+        indexer.full_index().await.unwrap();
+
+        let crypto = Arc::new(Crypto::with_params(swarm_key, KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 }));
+        let blobs = BlobStore::new(root.join("blobs"), crypto.clone()).unwrap();
+        let config = SwarmConfig {
+            bootstrap_nodes: vec![],
+            private_key_path: root.join("keys/private.key"),
+            swarm_key_path: root.join("keys/swarm.key"),
+            replica_id: None,
+            enabled: true,
+            listen_addr: "/ip4/127.0.0.1/udp/0/quic-v1".to_string(),
+            mdns: false,
+            blob_path: root.join("blobs"),
+        };
+        let handle = Swarm::new(config, VaultSync::new(Arc::new(indexer), blobs, crypto)).start().await.unwrap();
+        (handle, vault)
+    }
+
+    #[tokio::test]
+    async fn test_two_nodes_sync_a_note() {
+        let (laptop_dir, phone_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::create_dir_all(laptop_dir.path().join("vault/journal")).unwrap();
+        std::fs::write(laptop_dir.path().join("vault/journal/Today.md"), "# Today\nPlanted tomatoes.\n").unwrap();
+
+        let (laptop, _) = node(laptop_dir.path(), "shared secret").await;
+        let (phone, phone_vault) = node(phone_dir.path(), "shared secret").await;
+        phone.dial(laptop.listen_addrs[0].clone()).unwrap();
+
+        let synced = phone_vault.join("journal/Today.md");
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while !synced.exists() && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(std::fs::read_to_string(&synced).unwrap(), "# Today\nPlanted tomatoes.\n");
+
+        // Blobs at rest are the encrypted blocks, never the note itself
+        let blob = std::fs::read_dir(phone_dir.path().join("blobs")).unwrap().next().unwrap().unwrap();
+        assert!(Crypto::is_encrypted(&std::fs::read(blob.path()).unwrap()));
+
+        laptop.shutdown().await;
+        phone.shutdown().await;
+    }
+
+    #[test]
+    fn test_swarm_key_is_generated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys/swarm.key");
+        let key = load_swarm_key(&path).unwrap();
+
+        assert_eq!(key.len(), 64);
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with(SWARM_KEY_HEADER));
+        assert_eq!(load_swarm_key(&path).unwrap(), key);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keys_are_readable_only_by_their_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let swarm_key = dir.path().join("keys/swarm.key");
+        let peer_key = dir.path().join("keys/peer.key");
+        load_swarm_key(&swarm_key).unwrap();
+        load_keypair(&peer_key).unwrap();
+        for path in [swarm_key, peer_key] {
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600, "{}", path.display());
+        }
     }
-}// TODO: implement this file
+}
@@ -1,14 +1,562 @@
-use crate::Result;
+// src/swarm/sync.rs - Vault replication between swarm peers: manifests of indexed files, encrypted blobs and note histories
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll, Waker};
+use anyhow::{anyhow, Context, Result};
+use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p::core::{Endpoint, Multiaddr};
+use libp2p::futures::future::BoxFuture;
+use libp2p::futures::{AsyncReadExt, AsyncWriteExt, FutureExt};
+use libp2p::swarm::handler::OneShotHandler;
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, NotifyHandler, THandler,
+    THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{PeerId, Stream, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use crate::crypto::Crypto;
+use crate::logger::Logger;
+use crate::swarm::ipfs::BlobStore;
+use crate::vault::crdt::{CrdtStore, NoteCrdt};
+use crate::vault::indexer::VaultIndexer;
 
-pub struct Sync;
+pub const PROTOCOL: StreamProtocol = StreamProtocol::new("/note-to-ai/sync/1.0.0");
+/// Largest frame read from a peer
+const MAX_FRAME: u64 = 64 * 1024 * 1024;
+const HASH_LEN: usize = 64;
 
-impl Sync {
-    pub fn new() -> Result<Self> {
-        Ok(Self)
+/// An indexed vault file as announced to peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Relative to the vault
+    pub path: PathBuf,
+    /// BLAKE3 of the content, naming its blob
+    pub hash: String,
+    /// Unix seconds; the newer side wins when both changed a file that isn't merged
+    pub modified: u64,
+}
+
+/// One replica's CRDT history of a text note
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteHistory {
+    /// Relative to the vault
+    pub path: PathBuf,
+    pub replica: String,
+    /// As saved by `NoteCrdt::save`
+    pub state: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncMessage {
+    /// The sender's manifest
+    Have(Vec<ManifestEntry>),
+    /// Blobs the sender is missing
+    Want(Vec<String>),
+    /// An encrypted block as kept in the sender's `BlobStore`
+    Blob { hash: String, block: Vec<u8> },
+    /// Text notes whose histories the sender wants to merge with its own
+    WantHistory(Vec<PathBuf>),
+    History(NoteHistory),
+}
+
+impl SyncMessage {
+    /// A tag byte, then the message sealed with the swarm key, except for a blob: its hash
+    /// followed by its already encrypted block
+    pub fn encode(&self, crypto: &Crypto) -> Result<Vec<u8>> {
+        let mut frame = Vec::new();
+        match self {
+            SyncMessage::Have(entries) => {
+                frame.push(0);
+                frame.extend(crypto.encrypt(&serde_json::to_vec(entries)?)?);
+            }
+            SyncMessage::Want(hashes) => {
+                frame.push(1);
+                frame.extend(crypto.encrypt(&serde_json::to_vec(hashes)?)?);
+            }
+            SyncMessage::Blob { hash, block } => {
+                if hash.len() != HASH_LEN {
+                    return Err(anyhow!("Invalid blob hash {:?}", hash));
+                }
+                frame.push(2);
+                frame.extend(hash.as_bytes());
+                frame.extend(block);
+            }
+            SyncMessage::WantHistory(paths) => {
+                frame.push(3);
+                frame.extend(crypto.encrypt(&serde_json::to_vec(paths)?)?);
+            }
+            SyncMessage::History(history) => {
+                frame.push(4);
+                frame.extend(crypto.encrypt(&serde_json::to_vec(history)?)?);
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Fails for frames not sealed with the same swarm key
+    pub fn decode(frame: &[u8], crypto: &Crypto) -> Result<Self> {
+        let (tag, body) = frame.split_first().ok_or_else(|| anyhow!("Empty frame"))?;
+        let open = |body: &[u8]| crypto.decrypt(body).context("Frame was not sealed with this swarm's key");
+        match tag {
+            0 => Ok(SyncMessage::Have(serde_json::from_slice(&open(body)?)?)),
+            1 => Ok(SyncMessage::Want(serde_json::from_slice(&open(body)?)?)),
+            2 if body.len() > HASH_LEN => {
+                let (hash, block) = body.split_at(HASH_LEN);
+                let hash = std::str::from_utf8(hash).context("Invalid blob hash")?;
+                Ok(SyncMessage::Blob { hash: hash.to_string(), block: block.to_vec() })
+            }
+            3 => Ok(SyncMessage::WantHistory(serde_json::from_slice(&open(body)?)?)),
+            4 => Ok(SyncMessage::History(serde_json::from_slice(&open(body)?)?)),
+            _ => Err(anyhow!("Unknown frame")),
+        }
+    }
+}
+
+/// Reads one frame from an inbound stream
+#[derive(Debug, Clone, Default)]
+pub struct FrameProtocol;
+
+impl UpgradeInfo for FrameProtocol {
+    type Info = StreamProtocol;
+    type InfoIter = std::iter::Once<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(PROTOCOL)
+    }
+}
+
+impl InboundUpgrade<Stream> for FrameProtocol {
+    type Output = Vec<u8>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Vec<u8>>>;
+
+    fn upgrade_inbound(self, stream: Stream, _: StreamProtocol) -> Self::Future {
+        async move {
+            let mut frame = Vec::new();
+            stream.take(MAX_FRAME + 1).read_to_end(&mut frame).await?;
+            if frame.len() as u64 > MAX_FRAME {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Frame too large"));
+            }
+            Ok(frame)
+        }
+        .boxed()
+    }
+}
+
+/// One frame written to a fresh outbound stream
+#[derive(Debug)]
+pub struct Frame(pub Vec<u8>);
+
+impl UpgradeInfo for Frame {
+    type Info = StreamProtocol;
+    type InfoIter = std::iter::Once<StreamProtocol>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        std::iter::once(PROTOCOL)
+    }
+}
+
+impl OutboundUpgrade<Stream> for Frame {
+    type Output = ();
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<()>>;
+
+    fn upgrade_outbound(self, mut stream: Stream, _: StreamProtocol) -> Self::Future {
+        async move {
+            stream.write_all(&self.0).await?;
+            stream.close().await
+        }
+        .boxed()
+    }
+}
+
+#[derive(Debug)]
+pub enum HandlerEvent {
+    Received(Vec<u8>),
+    Sent,
+}
+
+impl From<Vec<u8>> for HandlerEvent {
+    fn from(frame: Vec<u8>) -> Self {
+        HandlerEvent::Received(frame)
+    }
+}
+
+impl From<()> for HandlerEvent {
+    fn from(_: ()) -> Self {
+        HandlerEvent::Sent
+    }
+}
+
+#[derive(Debug)]
+pub enum SyncEvent {
+    /// First connection to a peer, time to send it our manifest
+    Connected(PeerId),
+    Frame { peer: PeerId, frame: Vec<u8> },
+    SendFailed { peer: PeerId, error: String },
+}
+
+/// Carries frames between connected peers; `VaultSync` decides what they mean
+#[derive(Default)]
+pub struct SyncBehaviour {
+    events: VecDeque<ToSwarm<SyncEvent, Frame>>,
+    waker: Option<Waker>,
+}
+
+impl SyncBehaviour {
+    pub fn send(&mut self, peer: PeerId, frame: Vec<u8>) {
+        self.push(ToSwarm::NotifyHandler { peer_id: peer, handler: NotifyHandler::Any, event: Frame(frame) });
+    }
+
+    fn push(&mut self, event: ToSwarm<SyncEvent, Frame>) {
+        self.events.push_back(event);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl NetworkBehaviour for SyncBehaviour {
+    type ConnectionHandler = OneShotHandler<FrameProtocol, Frame, HandlerEvent>;
+    type ToSwarm = SyncEvent;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(OneShotHandler::default())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(OneShotHandler::default())
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        if let FromSwarm::ConnectionEstablished(established) = event {
+            if established.other_established == 0 {
+                self.push(ToSwarm::GenerateEvent(SyncEvent::Connected(established.peer_id)));
+            }
+        }
+    }
+
+    fn on_connection_handler_event(&mut self, peer: PeerId, _: ConnectionId, event: THandlerOutEvent<Self>) {
+        match event {
+            Ok(HandlerEvent::Received(frame)) => self.push(ToSwarm::GenerateEvent(SyncEvent::Frame { peer, frame })),
+            Ok(HandlerEvent::Sent) => {}
+            Err(e) => self.push(ToSwarm::GenerateEvent(SyncEvent::SendFailed { peer, error: e.to_string() })),
+        }
+    }
+
+    fn poll(&mut self, cx: &mut TaskContext<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// This device's side of replication: the manifest it announces and how it answers peers
+pub struct VaultSync {
+    indexer: Arc<VaultIndexer>,
+    blobs: BlobStore,
+    crypto: Arc<Crypto>,
+    /// Files asked for in a `Want`, by the hash of the blob that fills them
+    wanted: Mutex<HashMap<String, Vec<ManifestEntry>>>,
+    /// Notes asked for in a `WantHistory`
+    wanted_histories: Mutex<HashSet<PathBuf>>,
+    logger: Logger,
+}
+
+impl VaultSync {
+    /// `crypto` holds the swarm key; it seals every frame and every stored blob
+    pub fn new(indexer: Arc<VaultIndexer>, blobs: BlobStore, crypto: Arc<Crypto>) -> Self {
+        Self {
+            indexer,
+            blobs,
+            crypto,
+            wanted: Mutex::new(HashMap::new()),
+            wanted_histories: Mutex::new(HashSet::new()),
+            logger: Logger::new("VaultSync"),
+        }
+    }
+
+    pub fn crypto(&self) -> &Crypto {
+        &self.crypto
+    }
+
+    /// Indexed vault files, each stored as a blob; files changed since they were indexed are
+    /// left out until the indexer catches up
+    pub async fn manifest(&self) -> Result<Vec<ManifestEntry>> {
+        let vault_path = self.indexer.vault_path();
+        let mut entries = Vec::new();
+        for file in self.indexer.get_all_files().await? {
+            let Ok(path) = file.path.strip_prefix(vault_path) else {
+                continue;
+            };
+            if !self.blobs.contains(&file.hash) {
+                let Ok(content) = tokio::fs::read(&file.path).await else {
+                    continue;
+                };
+                if self.blobs.put(&content)? != file.hash {
+                    continue;
+                }
+            }
+            entries.push(ManifestEntry { path: path.to_path_buf(), hash: file.hash, modified: file.modified });
+        }
+        Ok(entries)
+    }
+
+    /// Replies to one message from a peer
+    pub async fn handle(&self, message: SyncMessage) -> Result<Vec<SyncMessage>> {
+        match message {
+            SyncMessage::Have(remote) => {
+                let local: HashMap<PathBuf, ManifestEntry> = self.manifest().await?
+                    .into_iter()
+                    .map(|entry| (entry.path.clone(), entry))
+                    .collect();
+
+                let mut ready = Vec::new();
+                let mut histories = Vec::new();
+                let hashes: Vec<String> = {
+                    let mut wanted = self.wanted.lock().unwrap();
+                    for entry in remote {
+                        if !is_vault_relative(&entry.path) {
+                            self.logger.warn(&format!("Ignoring peer file outside the vault: {}", entry.path.display()));
+                            continue;
+                        }
+                        // Merging keeps both sides' edits, so any difference is fetched as history
+                        if self.indexer.merges_edits(&entry.path) {
+                            if local.get(&entry.path).is_none_or(|mine| mine.hash != entry.hash) {
+                                histories.push(entry.path);
+                            }
+                            continue;
+                        }
+                        let newer = match local.get(&entry.path) {
+                            Some(mine) => mine.hash != entry.hash && entry.modified > mine.modified,
+                            None => true,
+                        };
+                        if !newer {
+                            continue;
+                        }
+                        if self.blobs.contains(&entry.hash) {
+                            ready.push(entry);
+                        } else {
+                            wanted.entry(entry.hash.clone()).or_default().push(entry);
+                        }
+                    }
+                    wanted.keys().cloned().collect()
+                };
+
+                for entry in &ready {
+                    if let Some(content) = self.blobs.get(&entry.hash)? {
+                        self.write(std::slice::from_ref(entry), &content).await?;
+                    }
+                }
+                let mut replies = Vec::new();
+                if !hashes.is_empty() {
+                    replies.push(SyncMessage::Want(hashes));
+                }
+                if !histories.is_empty() {
+                    self.wanted_histories.lock().unwrap().extend(histories.iter().cloned());
+                    replies.push(SyncMessage::WantHistory(histories));
+                }
+                Ok(replies)
+            }
+            SyncMessage::Want(hashes) => {
+                let unique: HashSet<String> = hashes.into_iter().collect();
+                let mut blobs = Vec::new();
+                for hash in unique {
+                    if let Some(block) = self.blobs.block(&hash)? {
+                        blobs.push(SyncMessage::Blob { hash, block });
+                    }
+                }
+                Ok(blobs)
+            }
+            SyncMessage::Blob { hash, block } => {
+                // Only blobs we asked for are kept
+                let Some(entries) = self.wanted.lock().unwrap().remove(&hash) else {
+                    return Ok(Vec::new());
+                };
+                let content = self.blobs.insert_block(&hash, &block)?;
+                self.write(&entries, &content).await?;
+                Ok(Vec::new())
+            }
+            SyncMessage::WantHistory(paths) => {
+                let Some(crdt) = self.indexer.crdt() else {
+                    return Ok(Vec::new());
+                };
+                let unique: HashSet<PathBuf> = paths.into_iter().filter(|path| is_vault_relative(path)).collect();
+                let mut histories = Vec::new();
+                for path in unique {
+                    if let Some(state) = crdt.state(&path)? {
+                        histories.push(SyncMessage::History(NoteHistory { path, replica: crdt.replica().to_string(), state }));
+                    }
+                }
+                Ok(histories)
+            }
+            SyncMessage::History(history) => {
+                // Only histories we asked for are merged
+                if !self.wanted_histories.lock().unwrap().remove(&history.path) {
+                    return Ok(Vec::new());
+                }
+                if let Some(crdt) = self.indexer.crdt() {
+                    self.merge(crdt, history).await?;
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Queue a peer's history of a note and reindex the note, which merges it with ours and
+    /// writes the merged text; a note new to this vault starts as the peer's text
+    async fn merge(&self, crdt: &CrdtStore, history: NoteHistory) -> Result<()> {
+        if history.replica == crdt.replica() {
+            self.logger.warn(&format!(
+                "Peer uses this device's replica id {:?}; not merging {}", history.replica, history.path.display()
+            ));
+            return Ok(());
+        }
+        let remote = NoteCrdt::load(&history.path, &history.replica, &history.state)?;
+        crdt.receive_remote(&history.path, &history.replica, &history.state)?;
+
+        let path = self.indexer.vault_path().join(&history.path);
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, remote.content()?).await
+                .with_context(|| format!("Failed to write synced note {}", path.display()))?;
+        }
+        self.indexer.incremental_index(vec![path]).await?;
+        self.logger.info(&format!("Merged {}'s edits to {}", history.replica, history.path.display()));
+        Ok(())
     }
-    
-    pub async fn sync_vault(&self) -> Result<()> {
-        // TODO: Implement cross-device synchronization
+
+    /// Write received content to each file and index it, so its manifest entry is current
+    async fn write(&self, entries: &[ManifestEntry], content: &[u8]) -> Result<()> {
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = self.indexer.vault_path().join(&entry.path);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, content).await
+                .with_context(|| format!("Failed to write synced file {}", path.display()))?;
+            self.logger.info(&format!("Synced {}", entry.path.display()));
+            paths.push(path);
+        }
+        self.indexer.incremental_index(paths).await?;
         Ok(())
     }
 }
+
+/// Peers name files, so only plain relative paths inside the vault are accepted
+fn is_vault_relative(path: &Path) -> bool {
+    path.components().next().is_some() && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KdfParams;
+
+    fn crypto(key: &str) -> Crypto {
+        Crypto::with_params(key, KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 })
+    }
+
+    #[test]
+    fn test_frames_only_open_with_the_swarm_key() {
+        let ours = crypto("shared secret");
+        let have = SyncMessage::Have(vec![ManifestEntry {
+            path: PathBuf::from("journal/Today.md"),
+            hash: "a".repeat(64),
+            modified: 1_700_000_000,
+        }]);
+        let frame = have.encode(&ours).unwrap();
+
+        assert!(!String::from_utf8_lossy(&frame).contains("journal"));
+        assert_eq!(SyncMessage::decode(&frame, &ours).unwrap(), have);
+        assert!(SyncMessage::decode(&frame, &crypto("someone else")).is_err());
+        assert!(SyncMessage::decode(&[], &ours).is_err());
+    }
+
+    #[test]
+    fn test_peer_paths_must_stay_inside_the_vault() {
+        assert!(is_vault_relative(Path::new("journal/Today.md")));
+        assert!(!is_vault_relative(Path::new("../.ssh/authorized_keys")));
+        assert!(!is_vault_relative(Path::new("/etc/passwd")));
+        assert!(!is_vault_relative(Path::new("")));
+    }
+
+    /// A device's vault, index and blobs, merging text notes as `replica`
+    async fn device(root: &Path, replica: &str) -> VaultSync {
+        let vault = root.join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        let indexer = VaultIndexer::new(root.join("index.db"), vault).unwrap().with_crdt(replica);
+        indexer.initialize_db().await.unwrap();
+        indexer.full_index().await.unwrap();
+        let crypto = Arc::new(crypto("shared secret"));
+        VaultSync::new(Arc::new(indexer), BlobStore::new(root.join("blobs"), crypto.clone()).unwrap(), crypto)
+    }
+
+    /// `from` sends its manifest to `to` and the two answer each other, through sealed frames,
+    /// until neither has anything left to send
+    async fn announce(from: &VaultSync, to: &VaultSync) {
+        let mut pending = vec![(SyncMessage::Have(from.manifest().await.unwrap()), to, from)];
+        while let Some((message, receiver, sender)) = pending.pop() {
+            let frame = message.encode(sender.crypto()).unwrap();
+            for reply in receiver.handle(SyncMessage::decode(&frame, receiver.crypto()).unwrap()).await.unwrap() {
+                pending.push((reply, sender, receiver));
+            }
+        }
+    }
+
+    async fn edit(device: &VaultSync, path: &Path, content: &str) {
+        std::fs::write(path, content).unwrap();
+        device.indexer.incremental_index(vec![path.to_path_buf()]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notes_edited_on_two_devices_keep_both_edits() {
+        let (laptop_dir, phone_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let laptop_note = laptop_dir.path().join("vault/journal/Today.md");
+        std::fs::create_dir_all(laptop_note.parent().unwrap()).unwrap();
+        std::fs::write(&laptop_note, "Woke up.\n").unwrap();
+        let laptop = device(laptop_dir.path(), "laptop").await;
+        let phone = device(phone_dir.path(), "phone").await;
+
+        // New on the phone: it takes the laptop's history, so later edits share a root
+        announce(&laptop, &phone).await;
+        let phone_note = phone_dir.path().join("vault/journal/Today.md");
+        assert_eq!(std::fs::read_to_string(&phone_note).unwrap(), "Woke up.\n");
+
+        edit(&laptop, &laptop_note, "Woke up early.\n").await;
+        edit(&phone, &phone_note, "Woke up.\nWent for a run.\n").await;
+        announce(&laptop, &phone).await;
+        assert_eq!(std::fs::read_to_string(&phone_note).unwrap(), "Woke up early.\nWent for a run.\n");
+
+        announce(&phone, &laptop).await;
+        assert_eq!(std::fs::read_to_string(&laptop_note).unwrap(), "Woke up early.\nWent for a run.\n");
+        assert!(!laptop.indexer.crdt().unwrap().has_remote(Path::new("journal/Today.md")).unwrap());
+
+        // Histories nobody asked for are ignored
+        let phone_state = phone.indexer.crdt().unwrap().state(Path::new("journal/Today.md")).unwrap().unwrap();
+        let unasked = SyncMessage::History(NoteHistory { path: PathBuf::from("journal/Other.md"), replica: "phone".to_string(), state: phone_state });
+        assert!(laptop.handle(unasked).await.unwrap().is_empty());
+        assert!(!laptop_dir.path().join("vault/journal/Other.md").exists());
+    }
+}
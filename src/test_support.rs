@@ -0,0 +1,158 @@
+// src/test_support.rs - Stand-ins shared by the unit tests of several modules
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tokio::sync::mpsc;
+use crate::vault::embedding_pool::EmbeddingWorker;
+
+/// Bag-of-words vectors, so texts sharing words get similar embeddings without a model
+pub enum BagOfWords {
+    /// How often each word occurs in the text (case-insensitive), one dimension per word
+    Vocabulary(&'static [&'static str]),
+    /// Words hashed into this many buckets
+    Hashed(usize),
+}
+
+impl BagOfWords {
+    pub fn vector(&self, text: &str) -> Vec<f32> {
+        let text = text.to_lowercase();
+        match self {
+            BagOfWords::Vocabulary(words) => words.iter().map(|w| text.matches(w).count() as f32).collect(),
+            BagOfWords::Hashed(buckets) => {
+                let mut vector = vec![0.0; *buckets];
+                for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+                    let mut hasher = DefaultHasher::new();
+                    word.hash(&mut hasher);
+                    vector[(hasher.finish() % *buckets as u64) as usize] += 1.0;
+                }
+                vector
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingWorker for BagOfWords {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(self.vector(text))
+    }
+}
+
+/// Embeds every text as the same unit vector, for tests that don't rank by similarity
+pub struct UnitWorker;
+
+#[async_trait]
+impl EmbeddingWorker for UnitWorker {
+    async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+        Ok(vec![1.0, 0.0])
+    }
+}
+
+/// One request read by `http_server`
+pub struct HttpRequest {
+    pub head: String,
+    pub body: String,
+}
+
+/// A complete HTTP/1.1 response that closes the connection
+pub fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )
+}
+
+/// Serve HTTP on a local port for the rest of the test. Each connection has one request read,
+/// gets the chunks `respond` returns written a few milliseconds apart, and is closed.
+/// Returns the base URL.
+pub async fn http_server<F>(mut respond: F) -> String
+where
+    F: FnMut(HttpRequest) -> Vec<String> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let request = read_http_request(&mut socket).await;
+            for (i, chunk) in respond(request).into_iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                if socket.write_all(chunk.as_bytes()).await.is_err() {
+                    break;
+                }
+                let _ = socket.flush().await;
+            }
+        }
+    });
+
+    url
+}
+
+/// Headers, then a body of the length they give (or up to EOF)
+async fn read_http_request(socket: &mut TcpStream) -> HttpRequest {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buf).await.unwrap_or(0);
+        request.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&request).to_string();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head.lines()
+                .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                .unwrap_or(0);
+            if body.len() >= length || n == 0 {
+                return HttpRequest { head: head.to_string(), body: body.to_string() };
+            }
+        } else if n == 0 {
+            return HttpRequest { head: text, body: String::new() };
+        }
+    }
+}
+
+/// A JSON-RPC reply to `request`: `body` (`{"result": ..}` or `{"error": ..}`) with its id
+pub fn rpc_reply(request: &Value, mut body: Value) -> Value {
+    body["jsonrpc"] = json!("2.0");
+    body["id"] = request["id"].clone();
+    body
+}
+
+/// A signal-cli style daemon on a Unix socket: every request line is answered with the messages
+/// `respond` returns, one per line, and forwarded on the returned channel
+pub fn json_rpc_daemon<F>(socket: &Path, respond: F) -> mpsc::UnboundedReceiver<Value>
+where
+    F: Fn(&Value) -> Vec<Value> + Send + Sync + 'static,
+{
+    let listener = UnixListener::bind(socket).unwrap();
+    let respond = Arc::new(respond);
+    let (requests, received) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let respond = respond.clone();
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let request: Value = serde_json::from_str(&line).unwrap();
+                    let out: String = respond(&request).iter().map(|message| format!("{}\n", message)).collect();
+                    if writer.write_all(out.as_bytes()).await.is_err() {
+                        break;
+                    }
+                    let _ = requests.send(request);
+                }
+            });
+        }
+    });
+
+    received
+}
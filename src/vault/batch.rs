@@ -0,0 +1,223 @@
+// src/vault/batch.rs - Embed and index a batch of documents, keeping whatever succeeds
+use std::sync::Arc;
+use anyhow::Result;
+use crate::vault::embedding_pool::{EmbeddingPool, EmbeddingWorker, RetryConfig};
+use crate::vault::embeddings::{self, EmbeddingVector};
+use crate::vault::hierarchical::{self, HierarchicalEmbeddingConfig};
+use crate::vault::parser::ParsedDocument;
+use crate::vault::qa::QaExtractionConfig;
+use crate::vault::search::VectorSearchEngine;
+use crate::webhooks::{WebhookEvent, WebhookNotifier};
+
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    pub documents_processed: usize,
+    /// Documents whose first embedding attempt failed and were retried with shorter input
+    pub retried: usize,
+    pub errors: Vec<String>,
+}
+
+/// Embeds documents through a pool and indexes each one as soon as it has a vector,
+/// so one failing document never costs the rest of the batch
+pub struct BatchIndexer<'a, W: EmbeddingWorker> {
+    engine: &'a VectorSearchEngine,
+    pool: &'a Arc<EmbeddingPool<W>>,
+    model_name: &'a str,
+    webhooks: Option<&'a WebhookNotifier>,
+    hierarchy: Option<&'a HierarchicalEmbeddingConfig>,
+    qa: Option<&'a QaExtractionConfig>,
+}
+
+impl<'a, W: EmbeddingWorker + 'static> BatchIndexer<'a, W> {
+    pub fn new(engine: &'a VectorSearchEngine, pool: &'a Arc<EmbeddingPool<W>>, model_name: &'a str) -> Self {
+        Self { engine, pool, model_name, webhooks: None, hierarchy: None, qa: None }
+    }
+
+    /// Report each indexed document, and each failure, to the configured webhook
+    pub fn with_webhooks(mut self, webhooks: &'a WebhookNotifier) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Embed the sections of long documents, and a summary of them as the document vector
+    pub fn with_hierarchy(mut self, config: &'a HierarchicalEmbeddingConfig) -> Self {
+        self.hierarchy = Some(config);
+        self
+    }
+
+    /// Extract question/answer pairs from each indexed document and embed their questions
+    pub fn with_qa(mut self, config: &'a QaExtractionConfig) -> Self {
+        self.qa = Some(config);
+        self
+    }
+
+    pub async fn index(&self, documents: &[ParsedDocument], retry: &RetryConfig) -> Result<BatchResult> {
+        let plans: Vec<_> = documents.iter()
+            .map(|d| self.hierarchy.and_then(|h| h.plan(d)))
+            .collect();
+        let texts = documents.iter().zip(&plans)
+            .map(|(document, sections)| match (self.hierarchy, sections) {
+                (Some(hierarchy), Some(sections)) => hierarchy.summary(document, sections),
+                _ => document.plain_text.clone(),
+            })
+            .collect();
+        let batch = self.pool.embed_batch_partial(texts, retry).await?;
+
+        // Every section of every long document, embedded in one more batch
+        let section_texts = plans.iter().flatten().flatten().map(|s| s.text.clone()).collect();
+        let section_batch = self.pool.embed_batch_partial(section_texts, retry).await?;
+        let mut section_results = section_batch.results.into_iter();
+
+        // And every code symbol, so definitions are found on their own
+        let symbols: Vec<_> = documents.iter().map(embeddings::symbol_texts).collect();
+        let symbol_batch = self.pool.embed_batch_partial(symbols.iter().flatten().cloned().collect(), retry).await?;
+        let mut symbol_results = symbol_batch.results.into_iter();
+
+        let mut result = BatchResult {
+            retried: batch.retried + section_batch.retried + symbol_batch.retried,
+            ..Default::default()
+        };
+        for (((document, embedded), sections), symbols) in documents.iter().zip(batch.results).zip(&plans).zip(&symbols) {
+            let section_vectors: Option<Result<Vec<_>>> = sections.as_ref()
+                .map(|sections| section_results.by_ref().take(sections.len()).collect());
+            let mut block_embeddings = match (sections, section_vectors) {
+                (Some(sections), Some(Ok(vectors))) => Some(hierarchical::section_embeddings(document, sections, vectors)),
+                (_, Some(Err(e))) => {
+                    // The summary vector still makes the document findable
+                    self.report_error(document, &e);
+                    result.errors.push(format!("Section embeddings {}: {}", document.path.display(), e));
+                    None
+                }
+                _ => None,
+            };
+            let symbol_vectors: Result<Vec<_>> = symbol_results.by_ref().take(symbols.len()).collect();
+            match symbol_vectors {
+                // Symbols still match by name without their vectors
+                Err(e) => {
+                    self.report_error(document, &e);
+                    result.errors.push(format!("Symbol embeddings {}: {}", document.path.display(), e));
+                }
+                Ok(vectors) if !vectors.is_empty() => {
+                    block_embeddings.get_or_insert_with(Vec::new).extend(embeddings::symbol_embeddings(document, vectors));
+                }
+                Ok(_) => {}
+            }
+            let vector = match embedded {
+                Ok(vector) => vector,
+                Err(e) => {
+                    self.report_error(document, &e);
+                    result.errors.push(format!("Embeddings {}: {}", document.path.display(), e));
+                    continue;
+                }
+            };
+
+            let embedding = EmbeddingVector {
+                text: document.plain_text.clone(),
+                vector,
+                model_name: self.model_name.to_string(),
+                created_at: chrono::Utc::now(),
+                block_embeddings,
+                title_vector: None,
+            };
+            match self.engine.index_document(document, &embedding).await {
+                Ok(()) => {
+                    result.documents_processed += 1;
+                    if let Err(e) = self.index_qa(document, retry).await {
+                        self.report_error(document, &e);
+                        result.errors.push(format!("Q&A pairs {}: {}", document.path.display(), e));
+                    }
+                    if let Some(webhooks) = self.webhooks {
+                        webhooks.notify(WebhookEvent::DocumentIndexed, serde_json::json!({
+                            "path": document.path,
+                            "title": document.title,
+                            "tags": document.tags,
+                            "word_count": document.metadata.word_count,
+                            "content": document.content,
+                        }));
+                    }
+                }
+                Err(e) => {
+                    self.report_error(document, &e);
+                    result.errors.push(format!("Document {}: {}", document.path.display(), e));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Replace the document's pairs, clearing them when an edit removed its questions
+    async fn index_qa(&self, document: &ParsedDocument, retry: &RetryConfig) -> Result<()> {
+        let Some(qa) = self.qa.filter(|qa| qa.enabled) else {
+            return Ok(());
+        };
+        let pairs = qa.extract(document);
+        let questions = pairs.iter().map(|p| p.question.clone()).collect();
+        let vectors = self.pool.embed_batch_partial(questions, retry).await?
+            .results
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        self.engine.replace_qa_pairs(&document.path, &pairs, &vectors).await
+    }
+
+    fn report_error(&self, document: &ParsedDocument, error: &anyhow::Error) {
+        if let Some(webhooks) = self.webhooks {
+            webhooks.notify(WebhookEvent::Error, serde_json::json!({
+                "stage": "index",
+                "path": document.path,
+                "error": error.to_string(),
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use async_trait::async_trait;
+    use crate::vault::parser::ObsidianParser;
+
+    /// Rejects any text mentioning "quota", however short
+    struct FlakyWorker;
+
+    #[async_trait]
+    impl EmbeddingWorker for FlakyWorker {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            if text.contains("quota") {
+                return Err(anyhow::anyhow!("rate limited"));
+            }
+            Ok(vec![1.0, text.len() as f32])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_document_is_reported_and_others_are_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let mut documents = Vec::new();
+        for (name, body) in [("a.md", "Tomatoes."), ("b.md", "quota exceeded"), ("c.md", "Basil.")] {
+            documents.push(parser.parse_content(Path::new(name), body).await.unwrap());
+        }
+
+        let pool = Arc::new(EmbeddingPool::new(vec![FlakyWorker, FlakyWorker]).unwrap());
+        let result = BatchIndexer::new(&engine, &pool, "test")
+            .index(&documents, &RetryConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(result.documents_processed, 2);
+        assert_eq!(result.retried, 1);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("b.md") && result.errors[0].contains("rate limited"));
+
+        let stored: Vec<String> = engine.stored_document_embeddings().await.unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(stored, ["a.md", "c.md"]);
+    }
+}
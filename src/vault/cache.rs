@@ -54,6 +54,18 @@ impl Cache {
             access_count: 1,
         });
     }
+    
+    pub async fn contains(&self, key: &str) -> bool {
+        self.data.read().await.contains_key(key)
+    }
+    
+    pub async fn len(&self) -> usize {
+        self.data.read().await.len()
+    }
+    
+    pub async fn is_empty(&self) -> bool {
+        self.data.read().await.is_empty()
+    }
 }
 
 // Smart caching (stub)
\ No newline at end of file
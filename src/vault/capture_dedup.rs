@@ -0,0 +1,204 @@
+// src/vault/capture_dedup.rs - Fold near-identical voice captures into the note they repeat
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use crate::logger::Logger;
+use crate::vault::embedding_pool::EmbeddingWorker;
+use crate::vault::embeddings::cosine_similarity;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateAction {
+    /// Append the new transcript to the earlier note instead of creating one
+    #[default]
+    Append,
+    /// Create the note but link it to the earlier one as a possible duplicate
+    Link,
+    /// Create a separate note as if no duplicate was found
+    Keep,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureDedupConfig {
+    pub enabled: bool,
+    /// Only captures made within this many minutes of each other are compared
+    pub window_minutes: i64,
+    /// Cosine similarity at or above which a capture counts as a duplicate
+    pub similarity: f32,
+    pub action: DuplicateAction,
+}
+
+impl Default for CaptureDedupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_minutes: 30,
+            similarity: 0.92,
+            action: DuplicateAction::Append,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureOutcome {
+    Created(PathBuf),
+    Appended { existing: PathBuf, similarity: f32 },
+    Linked { created: PathBuf, existing: PathBuf, similarity: f32 },
+}
+
+struct RecentCapture {
+    path: PathBuf,
+    embedding: Vec<f32>,
+    captured_at: DateTime<Utc>,
+}
+
+pub struct CaptureDeduplicator {
+    config: CaptureDedupConfig,
+    recent: RwLock<VecDeque<RecentCapture>>,
+    logger: Logger,
+}
+
+impl CaptureDeduplicator {
+    pub fn new(config: CaptureDedupConfig) -> Self {
+        Self {
+            config,
+            recent: RwLock::new(VecDeque::new()),
+            logger: Logger::new("CaptureDeduplicator"),
+        }
+    }
+
+    /// Write a transcribed capture to `path`, or fold it into a recent near-duplicate
+    pub async fn save_capture(
+        &self,
+        transcript: &str,
+        path: PathBuf,
+        captured_at: DateTime<Utc>,
+        embedder: &dyn EmbeddingWorker,
+    ) -> Result<CaptureOutcome> {
+        self.save_capture_note(transcript, &format!("{}\n", transcript.trim()), path, captured_at, embedder).await
+    }
+
+    /// `save_capture` for a capture written as `note`, compared and appended by its transcript alone
+    pub async fn save_capture_note(
+        &self,
+        transcript: &str,
+        note: &str,
+        path: PathBuf,
+        captured_at: DateTime<Utc>,
+        embedder: &dyn EmbeddingWorker,
+    ) -> Result<CaptureOutcome> {
+        let embedding = embedder.embed(transcript).await?;
+        let duplicate = if self.config.enabled {
+            self.find_duplicate(&embedding, captured_at).await
+        } else {
+            None
+        };
+
+        let outcome = match (duplicate, self.config.action) {
+            (Some((existing, similarity)), DuplicateAction::Append) => {
+                let mut content = tokio::fs::read_to_string(&existing).await
+                    .with_context(|| format!("Failed to read {}", existing.display()))?;
+                content.push_str(&format!("\n\n---\n*Recorded again {}*\n\n{}\n", captured_at.format("%H:%M"), transcript.trim()));
+                tokio::fs::write(&existing, content).await?;
+                self.logger.info(&format!("Appended duplicate capture to {} ({:.2})", existing.display(), similarity));
+                return Ok(CaptureOutcome::Appended { existing, similarity });
+            }
+            (Some((existing, similarity)), DuplicateAction::Link) => {
+                let name = existing.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+                let content = format!("{}\n\nPossible duplicate of [[{}]]\n", note.trim_end(), name);
+                write_note(&path, &content).await?;
+                CaptureOutcome::Linked { created: path.clone(), existing, similarity }
+            }
+            _ => {
+                write_note(&path, note).await?;
+                CaptureOutcome::Created(path.clone())
+            }
+        };
+
+        self.remember(path, embedding, captured_at).await;
+        Ok(outcome)
+    }
+
+    /// Most similar capture within the window that clears the similarity threshold
+    async fn find_duplicate(&self, embedding: &[f32], captured_at: DateTime<Utc>) -> Option<(PathBuf, f32)> {
+        let window = Duration::minutes(self.config.window_minutes);
+        let recent = self.recent.read().await;
+
+        recent.iter()
+            .filter(|c| (captured_at - c.captured_at).abs() <= window)
+            .map(|c| (c, cosine_similarity(embedding, &c.embedding)))
+            .filter(|(_, similarity)| *similarity >= self.config.similarity)
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(c, similarity)| (c.path.clone(), similarity))
+    }
+
+    async fn remember(&self, path: PathBuf, embedding: Vec<f32>, captured_at: DateTime<Utc>) {
+        let cutoff = captured_at - Duration::minutes(self.config.window_minutes);
+        let mut recent = self.recent.write().await;
+        recent.retain(|c| c.captured_at >= cutoff);
+        recent.push_back(RecentCapture { path, embedding, captured_at });
+    }
+}
+
+async fn write_note(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, content).await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::BagOfWords;
+
+    /// A tiny vocabulary, enough to tell paraphrases apart
+    const WORD_COUNTS: BagOfWords = BagOfWords::Vocabulary(&["call", "dentist", "tomorrow", "morning", "buy", "milk", "eggs"]);
+
+    #[tokio::test]
+    async fn test_near_identical_capture_is_appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let dedup = CaptureDeduplicator::new(CaptureDedupConfig::default());
+        let now = Utc::now();
+
+        let first = dedup.save_capture(
+            "Call the dentist tomorrow morning", dir.path().join("a.md"), now, &WORD_COUNTS,
+        ).await.unwrap();
+        assert_eq!(first, CaptureOutcome::Created(dir.path().join("a.md")));
+
+        let second = dedup.save_capture(
+            "Remember to call the dentist tomorrow morning", dir.path().join("b.md"), now + Duration::minutes(2), &WORD_COUNTS,
+        ).await.unwrap();
+        assert!(matches!(second, CaptureOutcome::Appended { ref existing, .. } if existing == &dir.path().join("a.md")));
+        assert!(!dir.path().join("b.md").exists());
+        assert!(std::fs::read_to_string(dir.path().join("a.md")).unwrap().contains("Remember to call"));
+
+        let unrelated = dedup.save_capture(
+            "Buy milk and eggs", dir.path().join("c.md"), now + Duration::minutes(3), &WORD_COUNTS,
+        ).await.unwrap();
+        assert_eq!(unrelated, CaptureOutcome::Created(dir.path().join("c.md")));
+
+        // Outside the window the same thought is a new note again
+        let later = dedup.save_capture(
+            "Call the dentist tomorrow morning", dir.path().join("d.md"), now + Duration::hours(2), &WORD_COUNTS,
+        ).await.unwrap();
+        assert_eq!(later, CaptureOutcome::Created(dir.path().join("d.md")));
+    }
+
+    #[tokio::test]
+    async fn test_link_action_creates_linked_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let dedup = CaptureDeduplicator::new(CaptureDedupConfig { action: DuplicateAction::Link, ..Default::default() });
+        let now = Utc::now();
+
+        dedup.save_capture("Buy milk and eggs", dir.path().join("a.md"), now, &WORD_COUNTS).await.unwrap();
+        let outcome = dedup.save_capture("Buy eggs and milk", dir.path().join("b.md"), now, &WORD_COUNTS).await.unwrap();
+
+        assert!(matches!(outcome, CaptureOutcome::Linked { .. }));
+        assert!(std::fs::read_to_string(dir.path().join("b.md")).unwrap().contains("[[a]]"));
+    }
+}
@@ -0,0 +1,195 @@
+// src/vault/categorize.rs - File new notes into configured categories by embedding similarity
+use std::path::PathBuf;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::vault::embedding_pool::EmbeddingWorker;
+use crate::vault::parser::ParsedDocument;
+use crate::vault::embeddings::cosine_similarity;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Category {
+    pub name: String,
+    pub folder: PathBuf,
+    /// Short descriptions or sample notes; their mean embedding is the category centroid
+    pub examples: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategorizationConfig {
+    pub enabled: bool,
+    pub categories: Vec<Category>,
+    /// Notes less similar than this to every category go to the inbox
+    pub min_confidence: f32,
+    /// Notes whose two best categories are closer than this go to the inbox
+    pub min_margin: f32,
+    pub inbox: PathBuf,
+}
+
+impl Default for CategorizationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            categories: Vec::new(),
+            min_confidence: 0.5,
+            min_margin: 0.05,
+            inbox: PathBuf::from("Inbox"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Categorization {
+    /// `None` when the note was sent to the inbox
+    pub category: Option<String>,
+    pub folder: PathBuf,
+    pub confidence: f32,
+}
+
+pub struct Categorizer {
+    config: CategorizationConfig,
+    centroids: Vec<Vec<f32>>,
+    logger: Logger,
+}
+
+impl Categorizer {
+    /// Embed each category's examples once and keep their centroids
+    pub async fn new(config: CategorizationConfig, embedder: &dyn EmbeddingWorker) -> Result<Self> {
+        let mut centroids = Vec::with_capacity(config.categories.len());
+        for category in &config.categories {
+            if category.examples.is_empty() {
+                return Err(anyhow::anyhow!("Category '{}' has no examples", category.name));
+            }
+
+            let mut centroid: Vec<f32> = Vec::new();
+            for example in &category.examples {
+                let vector = embedder.embed(example).await?;
+                if centroid.is_empty() {
+                    centroid = vec![0.0; vector.len()];
+                }
+                for (c, v) in centroid.iter_mut().zip(&vector) {
+                    *c += v / category.examples.len() as f32;
+                }
+            }
+            centroids.push(centroid);
+        }
+
+        Ok(Self {
+            config,
+            centroids,
+            logger: Logger::new("Categorizer"),
+        })
+    }
+
+    /// Pick the nearest category, or the inbox when the match is weak or ambiguous
+    pub async fn classify(&self, text: &str, embedder: &dyn EmbeddingWorker) -> Result<Categorization> {
+        let embedding = embedder.embed(text).await?;
+
+        let mut scores: Vec<(usize, f32)> = self.centroids.iter()
+            .map(|centroid| cosine_similarity(&embedding, centroid))
+            .enumerate()
+            .collect();
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let inbox = Categorization {
+            category: None,
+            folder: self.config.inbox.clone(),
+            confidence: scores.first().map(|s| s.1).unwrap_or(0.0),
+        };
+
+        let Some(&(best, confidence)) = scores.first() else {
+            return Ok(inbox);
+        };
+        let runner_up = scores.get(1).map(|s| s.1).unwrap_or(0.0);
+        if confidence < self.config.min_confidence || confidence - runner_up < self.config.min_margin {
+            return Ok(inbox);
+        }
+
+        let category = &self.config.categories[best];
+        Ok(Categorization {
+            category: Some(category.name.clone()),
+            folder: category.folder.clone(),
+            confidence,
+        })
+    }
+
+    /// Classify a document and record the result in its frontmatter custom fields
+    pub async fn categorize(&self, document: &mut ParsedDocument, embedder: &dyn EmbeddingWorker) -> Result<Categorization> {
+        let result = self.classify(&document.plain_text, embedder).await?;
+
+        let frontmatter = document.frontmatter.get_or_insert_with(Default::default);
+        frontmatter.custom_fields.insert(
+            "folder".to_string(),
+            serde_json::Value::String(result.folder.to_string_lossy().into_owned()),
+        );
+        match &result.category {
+            Some(name) => {
+                frontmatter.custom_fields.insert("category".to_string(), serde_json::Value::String(name.clone()));
+            }
+            None => {
+                frontmatter.custom_fields.remove("category");
+            }
+        }
+
+        self.logger.info(&format!(
+            "Filed {} under {} ({:.2})",
+            document.path.display(),
+            result.folder.display(),
+            result.confidence
+        ));
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::test_support::BagOfWords;
+    use crate::vault::parser::ObsidianParser;
+
+    const WORD_COUNTS: BagOfWords = BagOfWords::Vocabulary(&["recipe", "oven", "flour", "meeting", "deadline", "client", "today"]);
+
+    fn config() -> CategorizationConfig {
+        CategorizationConfig {
+            enabled: true,
+            categories: vec![
+                Category {
+                    name: "Cooking".to_string(),
+                    folder: PathBuf::from("Cooking"),
+                    examples: vec!["A bread recipe with flour".to_string(), "Preheat the oven".to_string()],
+                },
+                Category {
+                    name: "Work".to_string(),
+                    folder: PathBuf::from("Work"),
+                    examples: vec!["Client meeting notes".to_string(), "Project deadline".to_string()],
+                },
+            ],
+            ..CategorizationConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_note_is_filed_and_ambiguous_goes_to_inbox() {
+        let categorizer = Categorizer::new(config(), &WORD_COUNTS).await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+
+        let mut clear = parser.parse_content(
+            Path::new("capture.md"), "New recipe: mix flour, then bake in the oven.",
+        ).await.unwrap();
+        let result = categorizer.categorize(&mut clear, &WORD_COUNTS).await.unwrap();
+        assert_eq!(result.category.as_deref(), Some("Cooking"));
+        let fields = &clear.frontmatter.as_ref().unwrap().custom_fields;
+        assert_eq!(fields["folder"], "Cooking");
+        assert_eq!(fields["category"], "Cooking");
+
+        // Equally close to both categories
+        let ambiguous = categorizer.classify("Bring the recipe and flour to the client meeting", &WORD_COUNTS).await.unwrap();
+        assert_eq!(ambiguous.category, None);
+        assert_eq!(ambiguous.folder, PathBuf::from("Inbox"));
+
+        // Close to neither
+        let unrelated = categorizer.classify("Call mum today", &WORD_COUNTS).await.unwrap();
+        assert_eq!(unrelated.folder, PathBuf::from("Inbox"));
+    }
+}
@@ -0,0 +1,261 @@
+// src/vault/circuit_breaker.rs - Stop calling an embedding backend that keeps failing, then probe it
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use crate::vault::embedding_pool::EmbeddingWorker;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    pub enabled: bool,
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker fails fast before letting one probe request through
+    pub cooldown_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: 5,
+            cooldown_ms: 30_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Requests go through
+    Closed,
+    /// Requests fail immediately until the cooldown ends
+    Open,
+    /// A probe request is in flight; its outcome closes or reopens the breaker
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakerMetrics {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// Times the breaker has opened, including reopening after a failed probe
+    pub times_opened: u64,
+    /// Requests failed fast without reaching the backend
+    pub rejected: u64,
+}
+
+/// Returned instead of calling the backend while the breaker is open
+#[derive(Debug, thiserror::Error)]
+#[error("Embedding backend is unavailable after repeated failures; retrying in {}s", retry_in.as_secs())]
+pub struct BreakerOpen {
+    pub retry_in: Duration,
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    times_opened: u64,
+    rejected: u64,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    config: CircuitBreakerConfig,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    /// `name` identifies the backend in logs
+    pub fn new(name: &str, config: CircuitBreakerConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                times_opened: 0,
+                rejected: 0,
+            }),
+        }
+    }
+
+    /// Whether `error` came from an open breaker rather than the backend itself
+    pub fn is_open_error(error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| cause.is::<BreakerOpen>())
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    pub fn metrics(&self) -> BreakerMetrics {
+        let inner = self.inner.lock().unwrap();
+        BreakerMetrics {
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+            times_opened: inner.times_opened,
+            rejected: inner.rejected,
+        }
+    }
+
+    /// Run `request` unless the breaker is open, recording its outcome
+    pub async fn call<T, F>(&self, request: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        if !self.config.enabled {
+            return request.await;
+        }
+        self.admit()?;
+
+        let result = request.await;
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(_) => self.record_failure(),
+        }
+        result
+    }
+
+    fn admit(&self) -> Result<(), BreakerOpen> {
+        let mut inner = self.inner.lock().unwrap();
+        let cooldown = Duration::from_millis(self.config.cooldown_ms);
+        match inner.state {
+            BreakerState::Closed => Ok(()),
+            BreakerState::Open => {
+                let elapsed = inner.opened_at.map_or(cooldown, |at| at.elapsed());
+                if elapsed >= cooldown {
+                    info!("Probing {} after {}s open", self.name, elapsed.as_secs());
+                    inner.state = BreakerState::HalfOpen;
+                    inner.opened_at = Some(Instant::now());
+                    Ok(())
+                } else {
+                    inner.rejected += 1;
+                    Err(BreakerOpen { retry_in: cooldown - elapsed })
+                }
+            }
+            // Only the probe goes through until it settles the state; a probe that was
+            // cancelled never does, so another is let through after a further cooldown
+            BreakerState::HalfOpen => {
+                let elapsed = inner.opened_at.map_or(cooldown, |at| at.elapsed());
+                if elapsed >= cooldown {
+                    inner.opened_at = Some(Instant::now());
+                    Ok(())
+                } else {
+                    inner.rejected += 1;
+                    Err(BreakerOpen { retry_in: cooldown - elapsed })
+                }
+            }
+        }
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state != BreakerState::Closed {
+            info!("{} recovered; closing circuit breaker", self.name);
+        }
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+    }
+
+    fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let reopen = inner.state == BreakerState::HalfOpen;
+        if reopen || (inner.state == BreakerState::Closed && inner.consecutive_failures >= self.config.failure_threshold.max(1)) {
+            warn!(
+                "{} failed {} times in a row; failing fast for {}ms",
+                self.name, inner.consecutive_failures, self.config.cooldown_ms
+            );
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+            inner.times_opened += 1;
+        }
+    }
+}
+
+/// A worker whose requests go through a breaker, e.g. the query embedder of a search engine
+pub struct GuardedWorker<W: EmbeddingWorker> {
+    worker: W,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<W: EmbeddingWorker> GuardedWorker<W> {
+    pub fn new(worker: W, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { worker, breaker }
+    }
+}
+
+#[async_trait]
+impl<W: EmbeddingWorker> EmbeddingWorker for GuardedWorker<W> {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.breaker.call(self.worker.embed(text)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Fails while `down` is set, counting the calls that reach it
+    struct Backend {
+        down: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl EmbeddingWorker for Backend {
+        async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow::anyhow!("connection refused"));
+            }
+            Ok(vec![1.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_on_repeated_failures_and_closes_after_recovery() {
+        let down = Arc::new(AtomicBool::new(true));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let config = CircuitBreakerConfig { enabled: true, failure_threshold: 3, cooldown_ms: 50 };
+        let breaker = Arc::new(CircuitBreaker::new("embeddings", config));
+        let worker = GuardedWorker::new(Backend { down: down.clone(), calls: calls.clone() }, breaker.clone());
+
+        for _ in 0..3 {
+            assert!(!CircuitBreaker::is_open_error(&worker.embed("a").await.unwrap_err()));
+        }
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // Open: fails fast without touching the backend
+        let rejected = worker.embed("a").await.unwrap_err();
+        assert!(CircuitBreaker::is_open_error(&rejected));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // A failed probe after the cooldown reopens it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(worker.embed("a").await.is_err());
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        // Once the backend is back, the next probe closes it
+        down.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(worker.embed("a").await.unwrap(), [1.0]);
+        assert_eq!(breaker.metrics(), BreakerMetrics {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            times_opened: 2,
+            rejected: 1,
+        });
+    }
+}
@@ -1,27 +1,282 @@
-// src/vault/crdt.rs - Automerge CRDT integration
-use crate::Result;
-use automerge::Automerge;
+// src/vault/crdt.rs - Conflict-free merging of note edits made on different replicas
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Context, Result};
+use automerge::transaction::Transactable;
+use automerge::{ActorId, AutoCommit, ObjId, ObjType, ReadDoc, ROOT};
+use rusqlite::{params, Connection, OptionalExtension};
+use similar::{DiffTag, TextDiff};
 
-pub struct CRDT {
-    doc: Automerge,
+/// Key of the text object holding the note in each Automerge document
+const CONTENT: &str = "content";
+
+/// A note's content as an Automerge text object, edited as one replica. Replicas only merge
+/// cleanly when their histories share a root, i.e. one was `fork`ed or `load`ed from the other
+pub struct NoteCrdt {
+    path: PathBuf,
+    replica: String,
+    doc: AutoCommit,
+    text: ObjId,
+}
+
+/// Two replicas' histories of a note combined
+pub struct Merged {
+    pub content: String,
+    pub crdt: NoteCrdt,
 }
 
-impl CRDT {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            doc: Automerge::new(),
-        })
+impl NoteCrdt {
+    /// A fresh history whose first change writes `content`; `replica` must be unique per device
+    pub fn new(path: &Path, replica: &str, content: &str) -> Result<Self> {
+        let mut doc = AutoCommit::new().with_actor(ActorId::from(replica.as_bytes()));
+        let text = doc.put_object(ROOT, CONTENT, ObjType::Text)?;
+        doc.splice_text(&text, 0, 0, content)?;
+        Ok(Self { path: path.to_path_buf(), replica: replica.to_string(), doc, text })
+    }
+
+    /// A history saved by `save`, continued as `replica`
+    pub fn load(path: &Path, replica: &str, bytes: &[u8]) -> Result<Self> {
+        let mut doc = AutoCommit::load(bytes)
+            .with_context(|| format!("Corrupt CRDT state for {}", path.display()))?;
+        doc.set_actor(ActorId::from(replica.as_bytes()));
+        let text = match doc.get(ROOT, CONTENT)? {
+            Some((_, text)) => text,
+            None => return Err(anyhow!("CRDT state for {} has no content", path.display())),
+        };
+        Ok(Self { path: path.to_path_buf(), replica: replica.to_string(), doc, text })
+    }
+
+    pub fn save(&mut self) -> Vec<u8> {
+        self.doc.save()
     }
-    
-    pub async fn merge(&mut self, _other: &CRDT) -> Result<()> {
-        // TODO: Implement Automerge integration for conflict-free replication
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn replica(&self) -> &str {
+        &self.replica
+    }
+
+    pub fn content(&self) -> Result<String> {
+        Ok(self.doc.text(&self.text)?)
+    }
+
+    /// Record the edits turning the current content into `content`, as character-level
+    /// inserts and deletes so concurrent edits elsewhere in the note survive a merge
+    pub fn update(&mut self, content: &str) -> Result<()> {
+        let current = self.content()?;
+        if current == content {
+            return Ok(());
+        }
+        let old: Vec<char> = current.chars().collect();
+        let new: Vec<char> = content.chars().collect();
+        let byte_len = |chars: &[char]| chars.iter().map(|c| c.len_utf8()).sum::<usize>();
+
+        // Splice positions are UTF-8 byte offsets into the text as edited so far
+        let mut cursor = 0;
+        for op in TextDiff::from_chars(current.as_str(), content).ops() {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+            let deleted = byte_len(&old[old_range]);
+            match tag {
+                DiffTag::Equal => cursor += deleted,
+                DiffTag::Delete => self.doc.splice_text(&self.text, cursor, deleted, "")?,
+                DiffTag::Insert | DiffTag::Replace => {
+                    let inserted: String = new[new_range].iter().collect();
+                    self.doc.splice_text(&self.text, cursor, deleted, &inserted)?;
+                    cursor += inserted.len();
+                }
+            }
+        }
         Ok(())
     }
-    
-    pub async fn sync(&self) -> Result<Vec<u8>> {
-        // TODO: Generate sync data for IPFS distribution
-        Ok(Vec::new())
+
+    /// Another replica's copy of this history, sharing everything recorded so far
+    pub fn fork(&mut self, replica: &str) -> Self {
+        let mut doc = self.doc.fork();
+        doc.set_actor(ActorId::from(replica.as_bytes()));
+        Self { path: self.path.clone(), replica: replica.to_string(), doc, text: self.text.clone() }
     }
 }
 
-// Automerge CRDT (stub)
\ No newline at end of file
+/// `local`'s history with `remote`'s changes applied, continuing as `local`'s replica.
+/// Concurrent inserts at one spot are ordered by replica id, so merging either way round
+/// yields the same text
+pub fn merge(local: &mut NoteCrdt, remote: &mut NoteCrdt) -> Result<Merged> {
+    if local.path != remote.path {
+        return Err(anyhow!("Cannot merge {} with {}", local.path.display(), remote.path.display()));
+    }
+    let replica = local.replica.clone();
+    let mut crdt = local.fork(&replica);
+    crdt.doc.merge(&mut remote.doc)?;
+    Ok(Merged { content: crdt.content()?, crdt })
+}
+
+/// This replica's saved note histories, keyed by vault-relative path, plus histories
+/// received from other replicas that are waiting to be merged in
+pub struct CrdtStore {
+    db_path: PathBuf,
+    replica: String,
+}
+
+impl CrdtStore {
+    pub fn new(db_path: PathBuf, replica: &str) -> Self {
+        Self { db_path, replica: replica.to_string() }
+    }
+
+    pub fn replica(&self) -> &str {
+        &self.replica
+    }
+
+    pub fn initialize(&self) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS crdt_notes (
+                path TEXT PRIMARY KEY,
+                state BLOB NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS crdt_remote (
+                path TEXT NOT NULL,
+                replica TEXT NOT NULL,
+                state BLOB NOT NULL,
+                PRIMARY KEY (path, replica)
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// This replica's history of `path`, to send to other replicas
+    pub fn state(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let conn = Connection::open(&self.db_path)?;
+        Ok(conn.query_row(
+            "SELECT state FROM crdt_notes WHERE path = ?1",
+            params![path.to_string_lossy()],
+            |row| row.get(0),
+        ).optional()?)
+    }
+
+    /// Queue another replica's history of `path`, replacing any earlier one from that replica
+    pub fn receive_remote(&self, path: &Path, replica: &str, state: &[u8]) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO crdt_remote (path, replica, state) VALUES (?1, ?2, ?3)",
+            params![path.to_string_lossy(), replica, state],
+        )?;
+        Ok(())
+    }
+
+    pub fn has_remote(&self, path: &Path) -> Result<bool> {
+        let conn = Connection::open(&self.db_path)?;
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM crdt_remote WHERE path = ?1",
+            params![path.to_string_lossy()],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Record `content` as this replica's latest edit of `path`, merge in the queued remote
+    /// histories and return the resulting text, which differs from `content` when a remote
+    /// replica changed the note too
+    pub fn reconcile(&self, path: &Path, content: &str) -> Result<String> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let key = path.to_string_lossy();
+        let stored: Option<Vec<u8>> = conn.query_row(
+            "SELECT state FROM crdt_notes WHERE path = ?1",
+            params![key],
+            |row| row.get(0),
+        ).optional()?;
+        let remotes = {
+            let mut stmt = conn.prepare("SELECT replica, state FROM crdt_remote WHERE path = ?1 ORDER BY replica")?;
+            let rows = stmt.query_map(params![key], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        // Without a history of our own, continue the first remote one so the two share a root
+        let mut local = match stored.as_ref().or(remotes.first().map(|(_, state)| state)) {
+            Some(state) => NoteCrdt::load(path, &self.replica, state)?,
+            None => NoteCrdt::new(path, &self.replica, content)?,
+        };
+        local.update(content)?;
+        for (replica, state) in &remotes {
+            let mut remote = NoteCrdt::load(path, replica, state)?;
+            local = merge(&mut local, &mut remote)?.crdt;
+        }
+
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO crdt_notes (path, state) VALUES (?1, ?2)",
+            params![key, local.save()],
+        )?;
+        tx.execute("DELETE FROM crdt_remote WHERE path = ?1", params![key])?;
+        tx.commit()?;
+        local.content()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Base history shared by a laptop and a phone replica
+    fn replicas(content: &str) -> (NoteCrdt, NoteCrdt) {
+        let mut laptop = NoteCrdt::new(Path::new("Groceries.md"), "laptop", content).unwrap();
+        let phone = laptop.fork("phone");
+        (laptop, phone)
+    }
+
+    #[test]
+    fn test_concurrent_inserts_merge_the_same_either_way() {
+        let (mut laptop, mut phone) = replicas("- eggs\n- milk\n");
+        laptop.update("- eggs\n- bread\n- milk\n").unwrap();
+        phone.update("- eggs\n- milk\n- butter\n").unwrap();
+
+        let merged = merge(&mut laptop, &mut phone).unwrap();
+        assert_eq!(merged.content, "- eggs\n- bread\n- milk\n- butter\n");
+        assert_eq!(merged.crdt.replica(), "laptop");
+
+        // Both insert at the same spot: replica ids, not merge direction, decide the order
+        let (mut laptop, mut phone) = replicas("Shop: ");
+        laptop.update("Shop: apples").unwrap();
+        phone.update("Shop: pears").unwrap();
+        let one_way = merge(&mut laptop, &mut phone).unwrap().content;
+        let other_way = merge(&mut phone, &mut laptop).unwrap().content;
+        assert_eq!(one_way, other_way);
+        assert!(one_way == "Shop: applespears" || one_way == "Shop: pearsapples", "{}", one_way);
+    }
+
+    #[test]
+    fn test_concurrent_insert_and_delete_keep_both_edits() {
+        let (mut laptop, mut phone) = replicas("- eggs\n- milk\n- flour\n");
+        laptop.update("- eggs\n- flour\n").unwrap();
+        phone.update("- eggs\n- milk\n- flour\n- sugar\n").unwrap();
+
+        let merged = merge(&mut laptop, &mut phone).unwrap();
+        assert_eq!(merged.content, "- eggs\n- flour\n- sugar\n");
+        assert_eq!(merge(&mut phone, &mut laptop).unwrap().content, merged.content);
+
+        let mut other = NoteCrdt::new(Path::new("Other.md"), "phone", "").unwrap();
+        assert!(merge(&mut laptop, &mut other).is_err());
+    }
+
+    #[test]
+    fn test_store_merges_queued_remote_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CrdtStore::new(dir.path().join("index.db"), "laptop");
+        store.initialize().unwrap();
+        let path = Path::new("journal/Today.md");
+
+        assert_eq!(store.reconcile(path, "Woke up.\n").unwrap(), "Woke up.\n");
+        let mut phone = NoteCrdt::load(path, "phone", &store.state(path).unwrap().unwrap()).unwrap();
+        phone.update("Woke up.\nWent for a run.\n").unwrap();
+        store.receive_remote(path, "phone", &phone.save()).unwrap();
+        assert!(store.has_remote(path).unwrap());
+
+        let merged = store.reconcile(path, "Woke up early.\n").unwrap();
+        assert_eq!(merged, "Woke up early.\nWent for a run.\n");
+        assert!(!store.has_remote(path).unwrap());
+    }
+}
@@ -0,0 +1,353 @@
+// src/vault/embedding_engine.rs - Sentence embeddings from a pluggable encoder, in batches
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use crate::vault::embeddings::{BlockEmbedding, EmbeddingConfig, EmbeddingVector, Pooling};
+use crate::vault::parser::ParsedDocument;
+
+/// Texts per forward pass unless configured otherwise
+const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// A model turning texts into fixed-size vectors
+pub trait SentenceEncoder: Send + Sync {
+    fn name(&self) -> &str;
+    fn dimensions(&self) -> usize;
+    /// One vector per text, in order
+    fn encode(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embeds texts, documents and their blocks, feeding the encoder at most `batch_size` texts at a time
+pub struct EmbeddingEngine {
+    encoder: Arc<dyn SentenceEncoder>,
+    batch_size: usize,
+}
+
+impl EmbeddingEngine {
+    pub fn new(encoder: Arc<dyn SentenceEncoder>) -> Self {
+        Self { encoder, batch_size: DEFAULT_BATCH_SIZE }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn model_name(&self) -> &str {
+        self.encoder.name()
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.encoder.dimensions()
+    }
+
+    pub fn embed(&self, text: &str) -> Result<EmbeddingVector> {
+        let mut embedded = self.embed_batch(&[text.to_string()])?;
+        embedded.pop().ok_or_else(|| anyhow!("Encoder returned no vector"))
+    }
+
+    pub fn embed_batch(&self, texts: &[String]) -> Result<Vec<EmbeddingVector>> {
+        let vectors = self.vectors(texts)?;
+        Ok(texts.iter().zip(vectors).map(|(text, vector)| EmbeddingVector {
+            text: text.clone(),
+            vector,
+            model_name: self.model_name().to_string(),
+            created_at: chrono::Utc::now(),
+            block_embeddings: None,
+            title_vector: None,
+        }).collect())
+    }
+
+    /// Bare vectors for `texts`, checked against the encoder's dimensions
+    pub fn vectors(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            let encoded = self.encoder.encode(batch)?;
+            if encoded.len() != batch.len() {
+                return Err(anyhow!("Encoder returned {} vectors for {} texts", encoded.len(), batch.len()));
+            }
+            if let Some(vector) = encoded.iter().find(|v| v.len() != self.dimensions()) {
+                return Err(anyhow!("Encoder returned a {}-dimensional vector, expected {}", vector.len(), self.dimensions()));
+            }
+            vectors.extend(encoded);
+        }
+        Ok(vectors)
+    }
+
+    /// One embedding per non-empty parsed block, with ids `path#index` in block order
+    pub fn embed_blocks(&self, document: &ParsedDocument) -> Result<Vec<BlockEmbedding>> {
+        let blocks: Vec<_> = document.blocks.iter()
+            .enumerate()
+            .filter(|(_, block)| !block.content.trim().is_empty())
+            .collect();
+        let texts: Vec<String> = blocks.iter().map(|(_, block)| block.content.clone()).collect();
+        let vectors = self.vectors(&texts)?;
+        Ok(blocks.into_iter().zip(vectors).map(|((i, block), vector)| BlockEmbedding {
+            block_id: format!("{}#{}", document.path.display(), i),
+            block_type: block.block_type.clone(),
+            content: block.content.clone(),
+            vector,
+            start_pos: block.position.start,
+            end_pos: block.position.end,
+        }).collect())
+    }
+
+    /// The document's plain text and title, and each of its blocks
+    pub fn embed_document(&self, document: &ParsedDocument) -> Result<EmbeddingVector> {
+        let mut vectors = self.vectors(&[document.plain_text.clone(), document.title.clone()])?.into_iter();
+        Ok(EmbeddingVector {
+            text: document.plain_text.clone(),
+            vector: vectors.next().unwrap_or_default(),
+            model_name: self.model_name().to_string(),
+            created_at: chrono::Utc::now(),
+            block_embeddings: Some(self.embed_blocks(document)?),
+            title_vector: vectors.next(),
+        })
+    }
+}
+
+/// One text's vector from its token vectors; `mask` is 0 for padding
+pub fn pool(tokens: &[Vec<f32>], mask: &[u32], config: &EmbeddingConfig) -> Vec<f32> {
+    let dimensions = tokens.first().map_or(0, Vec::len);
+    let mut pooled = match config.pooling {
+        Pooling::Cls => tokens.first().cloned().unwrap_or_default(),
+        Pooling::Mean => {
+            let mut sum = vec![0.0; dimensions];
+            let mut count = 0;
+            for (token, _) in tokens.iter().zip(mask).filter(|(_, &m)| m != 0) {
+                for (total, value) in sum.iter_mut().zip(token) {
+                    *total += value;
+                }
+                count += 1;
+            }
+            sum.iter().map(|total| total / count.max(1) as f32).collect()
+        }
+    };
+    if config.normalize {
+        l2_normalize(&mut pooled);
+    }
+    pooled
+}
+
+/// Scale to unit length; a zero vector stays zero
+pub fn l2_normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > f32::EPSILON {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// A BERT model such as all-MiniLM-L6-v2, pooled as `output` says
+#[cfg(feature = "embeddings")]
+pub struct BertEncoder {
+    name: String,
+    model: candle_transformers::models::bert::BertModel,
+    tokenizer: tokenizers::Tokenizer,
+    dimensions: usize,
+    output: EmbeddingConfig,
+}
+
+#[cfg(feature = "embeddings")]
+impl BertEncoder {
+    /// Batches are padded to their longest text and texts cut to the model's maximum length
+    pub fn new(
+        name: &str,
+        model: candle_transformers::models::bert::BertModel,
+        config: &candle_transformers::models::bert::Config,
+        mut tokenizer: tokenizers::Tokenizer,
+        output: EmbeddingConfig,
+    ) -> Result<Self> {
+        tokenizer.with_padding(Some(tokenizers::PaddingParams {
+            strategy: tokenizers::PaddingStrategy::BatchLongest,
+            pad_id: config.pad_token_id as u32,
+            ..Default::default()
+        }));
+        tokenizer.with_truncation(Some(tokenizers::TruncationParams {
+            max_length: config.max_position_embeddings,
+            ..Default::default()
+        })).map_err(|e| anyhow!("Failed to configure truncation: {}", e))?;
+        Ok(Self { name: name.to_string(), model, tokenizer, dimensions: config.hidden_size, output })
+    }
+
+    /// A model directory as published on Hugging Face: `config.json`, `tokenizer.json` and
+    /// `model.safetensors`. Fails when the directory's `1_Pooling/config.json` names a
+    /// different pooling than `output`
+    pub fn from_dir(dir: &std::path::Path, output: EmbeddingConfig) -> Result<Self> {
+        use anyhow::Context;
+        use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+
+        let config: Config = serde_json::from_str(&std::fs::read_to_string(dir.join("config.json"))?)
+            .with_context(|| format!("Invalid model config in {}", dir.display()))?;
+        let tokenizer = tokenizers::Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| anyhow!("Failed to load tokenizer from {}: {}", dir.display(), e))?;
+        // Safety: the weights file is only read, and must not change while the model is loaded
+        let weights = unsafe {
+            candle_nn::VarBuilder::from_mmaped_safetensors(&[dir.join("model.safetensors")], DTYPE, &candle_core::Device::Cpu)?
+        };
+        let model = BertModel::load(weights, &config)?;
+        let name = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(trained) = trained_pooling(dir)? {
+            if trained != output.pooling {
+                return Err(anyhow!(
+                    "{} was trained with {:?} pooling but {:?} is configured",
+                    name, trained, output.pooling
+                ));
+            }
+        }
+        Self::new(&name, model, &config, tokenizer, output)
+    }
+}
+
+/// Pooling declared by a sentence-transformers model directory, if it declares one we support
+#[cfg(feature = "embeddings")]
+fn trained_pooling(dir: &std::path::Path) -> Result<Option<Pooling>> {
+    let path = dir.join("1_Pooling").join("config.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let config: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    let enabled = |key: &str| config.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    Ok(match (enabled("pooling_mode_mean_tokens"), enabled("pooling_mode_cls_token")) {
+        (true, false) => Some(Pooling::Mean),
+        (false, true) => Some(Pooling::Cls),
+        _ => None,
+    })
+}
+
+#[cfg(feature = "embeddings")]
+impl SentenceEncoder for BertEncoder {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn encode(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        use candle_core::Tensor;
+
+        let encodings = self.tokenizer.encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow!("Tokenization failed: {}", e))?;
+        let device = &self.model.device;
+        let ids = encodings.iter()
+            .map(|e| Tensor::new(e.get_ids(), device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let masks = encodings.iter()
+            .map(|e| Tensor::new(e.get_attention_mask(), device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let input_ids = Tensor::stack(&ids, 0)?;
+        let attention_mask = Tensor::stack(&masks, 0)?;
+        let hidden = self.model.forward(&input_ids, &input_ids.zeros_like()?, Some(&attention_mask))?;
+
+        Ok(hidden.to_vec3::<f32>()?.iter()
+            .zip(&encodings)
+            .map(|(tokens, encoding)| pool(tokens, encoding.get_attention_mask(), &self.output))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use crate::vault::parser::ObsidianParser;
+
+    /// Vectors of text lengths; records the size of each batch it is given
+    struct LengthEncoder {
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl SentenceEncoder for LengthEncoder {
+        fn name(&self) -> &str {
+            "length"
+        }
+
+        fn dimensions(&self) -> usize {
+            2
+        }
+
+        fn encode(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.batches.lock().unwrap().push(texts.len());
+            Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_and_block_embeddings() {
+        let encoder = Arc::new(LengthEncoder { batches: Mutex::new(Vec::new()) });
+        let engine = EmbeddingEngine::new(encoder.clone()).with_batch_size(2);
+
+        let texts: Vec<String> = ["a", "bb", "ccc", "dddd", "eeeee"].iter().map(|t| t.to_string()).collect();
+        let embedded = engine.embed_batch(&texts).unwrap();
+        assert_eq!(*encoder.batches.lock().unwrap(), [2, 2, 1]);
+        assert_eq!(embedded.iter().map(|e| e.vector[0]).collect::<Vec<_>>(), [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(embedded[2].text, "ccc");
+
+        let document = ObsidianParser::new().unwrap()
+            .parse_content(Path::new("Garden.md"), "# Garden\n\nWater the tomatoes.\n")
+            .await
+            .unwrap();
+        let blocks = engine.embed_blocks(&document).unwrap();
+        assert_eq!(blocks.len(), document.blocks.len());
+        assert_eq!(blocks[0].block_id, "Garden.md#0");
+        assert_eq!(blocks[1].vector[0], document.blocks[1].content.len() as f32);
+    }
+
+    #[test]
+    fn test_mean_pooling_ignores_padding_and_normalizes() {
+        let tokens = vec![vec![1.0, 2.0], vec![3.0, 6.0], vec![100.0, -100.0]];
+        let mask = [1, 1, 0];
+        let raw = EmbeddingConfig { pooling: Pooling::Mean, normalize: false };
+        assert_eq!(pool(&tokens, &mask, &raw), [2.0, 4.0]);
+        assert_eq!(pool(&tokens[..2], &mask[..2], &raw), [2.0, 4.0]);
+        assert_eq!(pool(&tokens, &mask, &EmbeddingConfig { pooling: Pooling::Cls, normalize: false }), [1.0, 2.0]);
+
+        for pooling in [Pooling::Mean, Pooling::Cls] {
+            let vector = pool(&tokens, &mask, &EmbeddingConfig { pooling, normalize: true });
+            let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-6, "{:?}: {}", pooling, norm);
+        }
+        let mut zero = vec![0.0; 3];
+        l2_normalize(&mut zero);
+        assert_eq!(zero, [0.0; 3]);
+    }
+
+    #[cfg(feature = "embeddings")]
+    #[test]
+    fn test_minilm_shaped_model_gives_384_deterministic_dimensions() {
+        use std::str::FromStr;
+        use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+
+        // all-MiniLM-L6-v2's shape with one layer and a tiny vocabulary, randomly initialized
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "vocab_size": 8, "hidden_size": 384, "num_hidden_layers": 1, "num_attention_heads": 12,
+            "intermediate_size": 1536, "hidden_act": "gelu", "hidden_dropout_prob": 0.1,
+            "max_position_embeddings": 512, "type_vocab_size": 2, "initializer_range": 0.02,
+            "layer_norm_eps": 1e-12, "pad_token_id": 0, "classifier_dropout": null, "model_type": "bert"
+        })).unwrap();
+        let weights = candle_nn::VarMap::new();
+        let model = BertModel::load(candle_nn::VarBuilder::from_varmap(&weights, DTYPE, &candle_core::Device::Cpu), &config).unwrap();
+        let tokenizer = tokenizers::Tokenizer::from_str(r#"{
+            "version": "1.0", "truncation": null, "padding": null, "added_tokens": [],
+            "normalizer": null, "pre_tokenizer": { "type": "BertPreTokenizer" },
+            "post_processor": null, "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": { "[PAD]": 0, "[UNK]": 1, "water": 2, "the": 3, "tomatoes": 4, "prune": 5, "roses": 6 },
+                "unk_token": "[UNK]"
+            }
+        }"#).unwrap();
+        let engine = EmbeddingEngine::new(Arc::new(BertEncoder::new("mini", model, &config, tokenizer, EmbeddingConfig::default()).unwrap()));
+
+        let texts = vec!["water the tomatoes".to_string(), "prune roses".to_string(), "water the tomatoes".to_string()];
+        let vectors = engine.vectors(&texts).unwrap();
+        assert!(vectors.iter().all(|v| v.len() == 384));
+        assert_eq!(engine.embed("water the tomatoes").unwrap().vector.len(), 384);
+        // Padding the shorter text in the batch must not change the longer ones
+        assert_eq!(vectors[0], vectors[2]);
+        let alone = engine.embed("prune roses").unwrap().vector;
+        assert!(alone.iter().zip(&vectors[1]).all(|(a, b)| (a - b).abs() < 1e-5));
+        let norm: f32 = vectors[0].iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,343 @@
+// src/vault/embedding_pool.rs - Serve concurrent embedding requests from a pool of model instances
+use std::sync::{Arc, Mutex};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use crate::vault::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig};
+use crate::vault::embedding_engine::EmbeddingEngine;
+use crate::vault::embeddings::{EmbeddingModelConfig, Embeddings};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingPoolConfig {
+    /// Model instances embedding in parallel; further requests wait for a free one
+    pub workers: usize,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Fail fast for a while once the embedding backend keeps failing
+    #[serde(default)]
+    pub breaker: CircuitBreakerConfig,
+}
+
+impl Default for EmbeddingPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            retry: RetryConfig::default(),
+            breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+/// Second pass over texts whose embedding failed in a batch (rate limits, over-long input)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    pub enabled: bool,
+    /// Retried texts are cut to at most this many characters, and to half their length if shorter
+    pub max_chars: usize,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_chars: 2000,
+        }
+    }
+}
+
+/// Per-text outcome of a batch that keeps going past individual failures
+pub struct PartialBatch {
+    pub results: Vec<Result<Vec<f32>>>,
+    /// Texts that failed the first pass and were retried shortened, whatever the outcome
+    pub retried: usize,
+}
+
+/// One embedding model instance; the pool gives each request exclusive use of a worker
+#[async_trait]
+pub trait EmbeddingWorker: Send + Sync {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+}
+
+#[async_trait]
+impl<W: EmbeddingWorker + ?Sized> EmbeddingWorker for Arc<W> {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        (**self).embed(text).await
+    }
+}
+
+/// Worker backed by an `Embeddings` instance and a fixed model; embeds document text with the
+/// model's passage prefix, or search queries with its query prefix
+pub struct ModelWorker {
+    embeddings: Embeddings,
+    model: EmbeddingModelConfig,
+    queries: bool,
+}
+
+impl ModelWorker {
+    pub fn new(model: &EmbeddingModelConfig) -> Result<Self> {
+        Ok(Self {
+            embeddings: Embeddings::new()?,
+            model: model.clone(),
+            queries: false,
+        })
+    }
+
+    pub fn for_queries(model: &EmbeddingModelConfig) -> Result<Self> {
+        Ok(Self { queries: true, ..Self::new(model)? })
+    }
+
+    /// Embed with the model loaded in `engine`; without one, vectors are placeholders
+    pub fn with_engine(mut self, engine: Option<Arc<EmbeddingEngine>>) -> Self {
+        if let Some(engine) = engine {
+            self.embeddings = self.embeddings.with_engine(engine);
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl EmbeddingWorker for ModelWorker {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        if self.queries {
+            self.embeddings.embed_query(text, &self.model).await
+        } else {
+            self.embeddings.embed_passage(text, &self.model).await
+        }
+    }
+}
+
+pub struct EmbeddingPool<W: EmbeddingWorker> {
+    idle: Mutex<Vec<W>>,
+    permits: Semaphore,
+    size: usize,
+    breaker: Option<Arc<CircuitBreaker>>,
+}
+
+/// Returns its worker to the pool when dropped, even if the request was cancelled
+struct Checkout<'a, W: EmbeddingWorker> {
+    pool: &'a EmbeddingPool<W>,
+    worker: Option<W>,
+}
+
+impl<W: EmbeddingWorker> Drop for Checkout<'_, W> {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.pool.idle.lock().unwrap().push(worker);
+        }
+    }
+}
+
+impl<W: EmbeddingWorker> EmbeddingPool<W> {
+    pub fn new(workers: Vec<W>) -> Result<Self> {
+        if workers.is_empty() {
+            return Err(anyhow::anyhow!("Embedding pool needs at least one worker"));
+        }
+
+        Ok(Self {
+            size: workers.len(),
+            permits: Semaphore::new(workers.len()),
+            idle: Mutex::new(workers),
+            breaker: None,
+        })
+    }
+
+    /// Route every request through `breaker`, so a failing backend is not hammered
+    pub fn with_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn breaker(&self) -> Option<&Arc<CircuitBreaker>> {
+        self.breaker.as_ref()
+    }
+
+    /// Embed on the next free worker, queueing until one is available
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        match &self.breaker {
+            Some(breaker) => breaker.call(self.embed_on_worker(text)).await,
+            None => self.embed_on_worker(text).await,
+        }
+    }
+
+    async fn embed_on_worker(&self, text: &str) -> Result<Vec<f32>> {
+        let _permit = self.permits.acquire().await?;
+        // A permit guarantees an idle worker
+        let worker = self.idle.lock().unwrap().pop()
+            .ok_or_else(|| anyhow::anyhow!("Embedding pool has no idle worker"))?;
+        let checkout = Checkout { pool: self, worker: Some(worker) };
+
+        checkout.worker.as_ref().unwrap().embed(text).await
+    }
+}
+
+impl<W: EmbeddingWorker + 'static> EmbeddingPool<W> {
+    /// Embed many texts concurrently, up to the pool size at a time; results keep input order
+    pub async fn embed_batch(self: &Arc<Self>, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_each(texts).await?.into_iter().collect()
+    }
+
+    /// Like `embed_batch`, but a failed text doesn't fail the others; failures are retried
+    /// once with a shortened text when `retry` is enabled
+    pub async fn embed_batch_partial(self: &Arc<Self>, texts: Vec<String>, retry: &RetryConfig) -> Result<PartialBatch> {
+        let mut results = self.embed_each(texts.clone()).await?;
+
+        let failed: Vec<usize> = results.iter()
+            .enumerate()
+            .filter(|(_, result)| result.is_err())
+            .map(|(i, _)| i)
+            .collect();
+        if !retry.enabled || failed.is_empty() {
+            return Ok(PartialBatch { results, retried: 0 });
+        }
+
+        let shortened = failed.iter().map(|&i| shorten(&texts[i], retry.max_chars)).collect();
+        for (i, result) in failed.iter().zip(self.embed_each(shortened).await?) {
+            results[*i] = result;
+        }
+        Ok(PartialBatch { results, retried: failed.len() })
+    }
+
+    async fn embed_each(self: &Arc<Self>, texts: Vec<String>) -> Result<Vec<Result<Vec<f32>>>> {
+        let mut tasks = JoinSet::new();
+        let count = texts.len();
+
+        for (i, text) in texts.into_iter().enumerate() {
+            let pool = Arc::clone(self);
+            tasks.spawn(async move { (i, pool.embed(&text).await) });
+        }
+
+        let mut results: Vec<Result<Vec<f32>>> = (0..count).map(|_| Ok(Vec::new())).collect();
+        while let Some(joined) = tasks.join_next().await {
+            let (i, embedding) = joined?;
+            results[i] = embedding;
+        }
+
+        Ok(results)
+    }
+}
+
+/// At most `max_chars` characters, and never more than half of the original
+fn shorten(text: &str, max_chars: usize) -> String {
+    let limit = max_chars.min(text.chars().count() / 2).max(1);
+    text.chars().take(limit).collect()
+}
+
+impl EmbeddingPool<ModelWorker> {
+    pub fn for_model(model: &EmbeddingModelConfig, config: &EmbeddingPoolConfig, engine: Option<&Arc<EmbeddingEngine>>) -> Result<Self> {
+        Self::with_model_workers(model, config, engine, |worker| worker)
+    }
+}
+
+/// A pool whose holder isn't tied to one worker type
+pub type SharedEmbeddingPool = EmbeddingPool<Arc<dyn EmbeddingWorker>>;
+
+impl SharedEmbeddingPool {
+    pub fn shared_for_model(model: &EmbeddingModelConfig, config: &EmbeddingPoolConfig, engine: Option<&Arc<EmbeddingEngine>>) -> Result<Self> {
+        Self::with_model_workers(model, config, engine, |worker| Arc::new(worker) as Arc<dyn EmbeddingWorker>)
+    }
+}
+
+impl<W: EmbeddingWorker> EmbeddingPool<W> {
+    fn with_model_workers(
+        model: &EmbeddingModelConfig,
+        config: &EmbeddingPoolConfig,
+        engine: Option<&Arc<EmbeddingEngine>>,
+        wrap: impl Fn(ModelWorker) -> W,
+    ) -> Result<Self> {
+        let workers = (0..config.workers.max(1))
+            .map(|_| ModelWorker::new(model).map(|worker| wrap(worker.with_engine(engine.cloned()))))
+            .collect::<Result<Vec<_>>>()?;
+        let breaker = CircuitBreaker::new(&format!("Embedding model {}", model.model), config.breaker.clone());
+        Ok(Self::new(workers)?.with_breaker(Arc::new(breaker)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::embedding_engine::SentenceEncoder;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Takes a fixed time per call and records how many calls overlap
+    struct SlowWorker {
+        active: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl EmbeddingWorker for SlowWorker {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            let now = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(vec![text.len() as f32])
+        }
+    }
+
+    async fn run(workers: usize, texts: &[String]) -> (Duration, usize, Vec<Vec<f32>>) {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let pool = Arc::new(EmbeddingPool::new(
+            (0..workers)
+                .map(|_| SlowWorker { active: Arc::clone(&active), peak: Arc::clone(&peak) })
+                .collect(),
+        ).unwrap());
+
+        let start = Instant::now();
+        let results = pool.embed_batch(texts.to_vec()).await.unwrap();
+        (start.elapsed(), peak.load(Ordering::SeqCst), results)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_embeds_scale_with_pool_size() {
+        let texts: Vec<String> = (0..16).map(|i| "x".repeat(i + 1)).collect();
+        let expected: Vec<Vec<f32>> = texts.iter().map(|t| vec![t.len() as f32]).collect();
+
+        let (single, single_peak, single_results) = run(1, &texts).await;
+        let (pooled, pooled_peak, pooled_results) = run(4, &texts).await;
+
+        assert_eq!(single_results, expected);
+        assert_eq!(pooled_results, expected);
+        assert_eq!(single_peak, 1);
+        assert_eq!(pooled_peak, 4);
+        assert!(pooled < single / 2, "pool of 4 took {:?}, single worker {:?}", pooled, single);
+    }
+
+    /// Vectors of text lengths
+    struct LengthEncoder;
+
+    impl SentenceEncoder for LengthEncoder {
+        fn name(&self) -> &str {
+            "length"
+        }
+
+        fn dimensions(&self) -> usize {
+            2
+        }
+
+        fn encode(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|t| vec![t.len() as f32, 1.0]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pooled_workers_embed_with_the_loaded_model() {
+        let model = EmbeddingModelConfig::default();
+        let engine = Arc::new(EmbeddingEngine::new(Arc::new(LengthEncoder)));
+        let pool = EmbeddingPool::for_model(&model, &EmbeddingPoolConfig::default(), Some(&engine)).unwrap();
+
+        let passage = model.prefixes.passage_text("sourdough");
+        assert_eq!(pool.embed("sourdough").await.unwrap(), engine.vectors(&[passage]).unwrap()[0]);
+
+        let placeholder = EmbeddingPool::for_model(&model, &EmbeddingPoolConfig::default(), None).unwrap();
+        assert_eq!(placeholder.embed("sourdough").await.unwrap().len(), 384);
+    }
+}
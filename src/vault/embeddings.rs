@@ -5,6 +5,8 @@ use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use crate::logger::Logger;
+use crate::vault::embedding_engine::EmbeddingEngine;
+use crate::vault::parser::{BlockType, ParsedDocument};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingModel {
@@ -30,20 +32,209 @@ pub struct EmbeddingVector {
     pub model_name: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub block_embeddings: Option<Vec<BlockEmbedding>>, // Added missing field
+    #[serde(default)]
+    pub title_vector: Option<Vec<f32>>, // Embedding of ParsedDocument::title_text
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockEmbedding {
     pub block_id: String,
+    /// Type of the block the text came from; a section takes its heading's type
+    #[serde(default)]
+    pub block_type: BlockType,
     pub content: String,
     pub vector: Vec<f32>,
     pub start_pos: usize,
     pub end_pos: usize,
 }
 
+/// Embedding model a vault is configured to use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingModelConfig {
+    pub model: String,
+    pub dimensions: usize,
+    #[serde(default)]
+    pub prefixes: EmbeddingPrefixes,
+    /// Revision or hash of the model's weights; changing it refreshes stored vectors gradually
+    #[serde(default)]
+    pub version: String,
+    #[serde(flatten)]
+    pub output: EmbeddingConfig,
+}
+
+impl Default for EmbeddingModelConfig {
+    fn default() -> Self {
+        Self {
+            model: "all-MiniLM-L6-v2".to_string(),
+            dimensions: 384,
+            prefixes: EmbeddingPrefixes::default(),
+            version: String::new(),
+            output: EmbeddingConfig::default(),
+        }
+    }
+}
+
+/// How token vectors become one text vector; must match what the model was trained with
+/// (its `1_Pooling/config.json`), or similarities quietly lose meaning
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    /// Average of the tokens, leaving out padding (sentence-transformers models)
+    #[default]
+    Mean,
+    /// The first, `[CLS]`, token (bge and most retrieval-tuned BERTs)
+    Cls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    #[serde(default)]
+    pub pooling: Pooling,
+    /// Scale vectors to unit length, so a dot product is their cosine similarity
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+}
+
+fn default_normalize() -> bool {
+    true
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            pooling: Pooling::Mean,
+            normalize: true,
+        }
+    }
+}
+
+/// Instructions asymmetric models (e5, bge) expect before queries and documents,
+/// e.g. `"query: "` and `"passage: "`; empty prefixes embed text as-is
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingPrefixes {
+    pub query: String,
+    pub passage: String,
+}
+
+impl EmbeddingPrefixes {
+    pub fn query_text(&self, text: &str) -> String {
+        format!("{}{}", self.query, text)
+    }
+
+    pub fn passage_text(&self, text: &str) -> String {
+        format!("{}{}", self.passage, text)
+    }
+}
+
+/// Embedding model recorded in a vault's metadata when the vault is created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultEmbeddingRecord {
+    pub model: String,
+    pub dimensions: usize,
+    /// Prefixes the stored vectors were embedded with; queries must use the matching ones
+    #[serde(default)]
+    pub prefixes: EmbeddingPrefixes,
+    /// Latest model version; vectors from older versions are refreshed, not rejected
+    #[serde(default)]
+    pub version: String,
+    /// Pooling and normalization the stored vectors were made with
+    #[serde(flatten)]
+    pub output: EmbeddingConfig,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl VaultEmbeddingRecord {
+    const METADATA_DIR: &'static str = ".note-to-ai";
+    const FILE_NAME: &'static str = "embedding_model.json";
+
+    pub fn path(vault_path: &Path) -> std::path::PathBuf {
+        vault_path.join(Self::METADATA_DIR).join(Self::FILE_NAME)
+    }
+
+    pub fn load(vault_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path(vault_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let record = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(record))
+    }
+
+    pub fn save(&self, vault_path: &Path) -> Result<()> {
+        let path = Self::path(vault_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Record the configured model on first open, and refuse to open a vault whose
+    /// existing vectors were produced by a different model or dimension. A new version of
+    /// the same model is recorded; its vectors stay comparable while they are refreshed
+    pub fn check_or_record(vault_path: &Path, config: &EmbeddingModelConfig) -> Result<Self> {
+        match Self::load(vault_path)? {
+            Some(record) => {
+                if record.model != config.model || record.dimensions != config.dimensions {
+                    return Err(anyhow::anyhow!(
+                        "Vault {} was embedded with {} ({} dims) but {} ({} dims) is configured; \
+                         re-embed the vault or switch the configured model back",
+                        vault_path.display(),
+                        record.model, record.dimensions,
+                        config.model, config.dimensions
+                    ));
+                }
+                if record.prefixes != config.prefixes {
+                    return Err(anyhow::anyhow!(
+                        "Vault {} was embedded with query prefix {:?} and passage prefix {:?} but {:?} and {:?} \
+                         are configured; re-embed the vault or restore the recorded prefixes",
+                        vault_path.display(),
+                        record.prefixes.query, record.prefixes.passage,
+                        config.prefixes.query, config.prefixes.passage
+                    ));
+                }
+                if record.output != config.output {
+                    return Err(anyhow::anyhow!(
+                        "Vault {} was embedded with {:?} pooling (normalized: {}) but {:?} (normalized: {}) \
+                         is configured; re-embed the vault or restore the recorded pooling",
+                        vault_path.display(),
+                        record.output.pooling, record.output.normalize,
+                        config.output.pooling, config.output.normalize
+                    ));
+                }
+                if record.version != config.version {
+                    let record = Self { version: config.version.clone(), ..record };
+                    record.save(vault_path)?;
+                    return Ok(record);
+                }
+                Ok(record)
+            }
+            None => {
+                let record = Self {
+                    model: config.model.clone(),
+                    dimensions: config.dimensions,
+                    prefixes: config.prefixes.clone(),
+                    version: config.version.clone(),
+                    output: config.output,
+                    created_at: chrono::Utc::now(),
+                };
+                record.save(vault_path)?;
+                Ok(record)
+            }
+        }
+    }
+}
+
 pub struct Embeddings {
     models: Arc<RwLock<HashMap<String, EmbeddingModel>>>,
     cache: Arc<RwLock<HashMap<String, Vec<f32>>>>,
+    engine: Option<Arc<EmbeddingEngine>>,
     logger: Logger,
 }
 
@@ -52,10 +243,17 @@ impl Embeddings {
         Ok(Self {
             models: Arc::new(RwLock::new(HashMap::new())),
             cache: Arc::new(RwLock::new(HashMap::new())),
+            engine: None,
             logger: Logger::new("Embeddings"),
         })
     }
 
+    /// Produce vectors with `engine` instead of placeholder ones
+    pub fn with_engine(mut self, engine: Arc<EmbeddingEngine>) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
     pub async fn add_model(&self, model: EmbeddingModel) -> Result<()> {
         let mut models = self.models.write().await;
         let model_name = model.name.clone();
@@ -74,9 +272,10 @@ impl Embeddings {
             }
         }
 
-        // TODO: Implement actual embedding generation
-        // For now, return a dummy embedding
-        let embedding = self.generate_dummy_embedding(text, model_name).await?;
+        let embedding = match &self.engine {
+            Some(engine) => self.encode(engine, vec![text.to_string()]).await?.pop().unwrap_or_default(),
+            None => self.generate_dummy_embedding(text, model_name).await?,
+        };
         
         // Cache the result
         {
@@ -87,6 +286,16 @@ impl Embeddings {
         Ok(embedding)
     }
 
+    /// Embed a search query with the configured query prefix
+    pub async fn embed_query(&self, text: &str, config: &EmbeddingModelConfig) -> Result<Vec<f32>> {
+        self.embed_text(&config.prefixes.query_text(text), &config.model).await
+    }
+
+    /// Embed document text with the configured passage prefix
+    pub async fn embed_passage(&self, text: &str, config: &EmbeddingModelConfig) -> Result<Vec<f32>> {
+        self.embed_text(&config.prefixes.passage_text(text), &config.model).await
+    }
+
     async fn generate_dummy_embedding(&self, text: &str, model_name: &str) -> Result<Vec<f32>> {
         // Generate a simple hash-based embedding for testing
         let mut embedding = Vec::new();
@@ -103,7 +312,16 @@ impl Embeddings {
         Ok(embedding)
     }
 
+    /// Model inference is CPU-bound, so it runs off the async workers
+    async fn encode(&self, engine: &Arc<EmbeddingEngine>, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let engine = engine.clone();
+        tokio::task::spawn_blocking(move || engine.vectors(&texts)).await?
+    }
+
     pub async fn batch_embed(&self, texts: Vec<String>, model_name: &str) -> Result<Vec<Vec<f32>>> {
+        if let Some(engine) = &self.engine {
+            return self.encode(engine, texts).await;
+        }
         let mut embeddings = Vec::new();
         
         for text in texts {
@@ -114,29 +332,20 @@ impl Embeddings {
         Ok(embeddings)
     }
 
+    /// One embedding per symbol block of a parsed code file, so definitions are searchable on their own
+    pub async fn embed_symbols(&self, document: &ParsedDocument, model_name: &str) -> Result<Vec<BlockEmbedding>> {
+        let mut vectors = Vec::new();
+        for text in symbol_texts(document) {
+            vectors.push(self.embed_text(&text, model_name).await?);
+        }
+        Ok(symbol_embeddings(document, vectors))
+    }
+
     pub async fn similarity(&self, embedding1: &[f32], embedding2: &[f32]) -> Result<f32> {
         if embedding1.len() != embedding2.len() {
             return Err(anyhow::anyhow!("Embedding dimensions don't match"));
         }
-        
-        // Calculate cosine similarity
-        let mut dot_product = 0.0;
-        let mut norm1 = 0.0;
-        let mut norm2 = 0.0;
-        
-        for (a, b) in embedding1.iter().zip(embedding2.iter()) {
-            dot_product += a * b;
-            norm1 += a * a;
-            norm2 += b * b;
-        }
-        
-        let similarity = if norm1 > 0.0 && norm2 > 0.0 {
-            dot_product / (norm1.sqrt() * norm2.sqrt())
-        } else {
-            0.0
-        };
-        
-        Ok(similarity)
+        Ok(cosine_similarity(embedding1, embedding2))
     }
 
     pub async fn find_similar(&self, query_embedding: &[f32], embeddings: &[Vec<f32>], top_k: usize) -> Result<Vec<(usize, f32)>> {
@@ -184,4 +393,128 @@ impl Embeddings {
         stats.insert("cache_size_bytes".to_string(), cache.values().map(|v| v.len() * 4).sum());
         Ok(stats)
     }
-}
\ No newline at end of file
+}
+
+/// Text embedded for each symbol block of a parsed code file: its kind, name and definition
+pub fn symbol_texts(document: &ParsedDocument) -> Vec<String> {
+    document.blocks.iter()
+        .filter_map(|block| match &block.block_type {
+            BlockType::Symbol { kind, name } => Some(format!("{} {}\n{}", kind, name, block.content)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The symbol blocks of `document` with their vectors, given in `symbol_texts` order
+pub fn symbol_embeddings(document: &ParsedDocument, vectors: Vec<Vec<f32>>) -> Vec<BlockEmbedding> {
+    document.blocks.iter()
+        .filter_map(|block| match &block.block_type {
+            BlockType::Symbol { name, .. } => Some((block, name)),
+            _ => None,
+        })
+        .zip(vectors)
+        .map(|((block, name), vector)| BlockEmbedding {
+            block_id: format!("{}#{}", document.path.display(), name),
+            block_type: block.block_type.clone(),
+            content: block.content.clone(),
+            vector,
+            start_pos: block.position.start,
+            end_pos: block.position.end,
+        })
+        .collect()
+}
+
+/// Cosine of the angle between two vectors; 0.0 when their lengths differ or either is zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot_product / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatched_embedding_model_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = EmbeddingModelConfig::default();
+
+        let record = VaultEmbeddingRecord::check_or_record(dir.path(), &config).unwrap();
+        assert_eq!(record.model, config.model);
+        assert!(VaultEmbeddingRecord::check_or_record(dir.path(), &config).is_ok());
+
+        let mismatched = EmbeddingModelConfig {
+            model: "bge-large-en".to_string(),
+            dimensions: 1024,
+            ..Default::default()
+        };
+        assert!(VaultEmbeddingRecord::check_or_record(dir.path(), &mismatched).is_err());
+        let cls = EmbeddingModelConfig {
+            output: EmbeddingConfig { pooling: Pooling::Cls, normalize: true },
+            ..config.clone()
+        };
+        assert!(VaultEmbeddingRecord::check_or_record(dir.path(), &cls).is_err());
+
+        // A newer version of the same model is accepted and recorded
+        let upgraded = EmbeddingModelConfig { version: "2".to_string(), ..config };
+        assert_eq!(VaultEmbeddingRecord::check_or_record(dir.path(), &upgraded).unwrap().version, "2");
+        assert_eq!(VaultEmbeddingRecord::load(dir.path()).unwrap().unwrap().version, "2");
+    }
+
+    /// Stand-in for an asymmetric model: only prefixed text is mapped onto topic axes,
+    /// bare text falls back to a vector driven by length
+    fn asymmetric_fixture(text: &str) -> Vec<f32> {
+        let Some(body) = text.strip_prefix("query: ").or_else(|| text.strip_prefix("passage: ")) else {
+            return vec![1.0, 1.0, text.len() as f32 / 10.0];
+        };
+        let body = body.to_lowercase();
+        vec![
+            body.matches("tomato").count() as f32,
+            body.matches("tax").count() as f32,
+            0.0,
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_prefixes_are_applied_and_recorded() {
+        let config = EmbeddingModelConfig {
+            model: "e5-small".to_string(),
+            prefixes: EmbeddingPrefixes { query: "query: ".to_string(), passage: "passage: ".to_string() },
+            ..Default::default()
+        };
+        assert_eq!(config.prefixes.query_text("tomato care"), "query: tomato care");
+        assert_eq!(config.prefixes.passage_text("Water daily."), "passage: Water daily.");
+
+        let embeddings = Embeddings::new().unwrap();
+        assert_eq!(
+            embeddings.embed_query("tomato care", &config).await.unwrap(),
+            embeddings.embed_text("query: tomato care", "e5-small").await.unwrap()
+        );
+
+        // With prefixes the tomato note wins; bare text only matches on length, which favours the tax note
+        let documents = ["Tomato care: water tomatoes daily.", "Tax notes: file the tax return before the April deadline."];
+        for (prefixes, expected_top) in [(config.prefixes.clone(), 0), (EmbeddingPrefixes::default(), 1)] {
+            let query = asymmetric_fixture(&prefixes.query_text("how do I look after a tomato plant so it keeps fruiting all summer"));
+            let vectors: Vec<Vec<f32>> = documents.iter().map(|d| asymmetric_fixture(&prefixes.passage_text(d))).collect();
+            let ranked = embeddings.find_similar(&query, &vectors, 1).await.unwrap();
+            assert_eq!(ranked[0].0, expected_top, "prefixes {:?}", prefixes);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let record = VaultEmbeddingRecord::check_or_record(dir.path(), &config).unwrap();
+        assert_eq!(record.prefixes, config.prefixes);
+        let unprefixed = EmbeddingModelConfig { prefixes: EmbeddingPrefixes::default(), ..config };
+        assert!(VaultEmbeddingRecord::check_or_record(dir.path(), &unprefixed).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+// src/vault/export.rs - Export stored notes and embeddings for use in external tools
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::{Result, Context};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::vault::search::{DocumentRecord, VectorSearchEngine};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Describes the rows of the exported `.npy` matrices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingManifest {
+    pub model: String,
+    pub dimensions: usize,
+    /// Document path for each row of `embeddings.npy`
+    pub documents: Vec<String>,
+    /// (document path, block id) for each row of `block_embeddings.npy`, when exported
+    #[serde(default)]
+    pub blocks: Vec<(String, String)>,
+}
+
+pub struct EmbeddingExporter<'a> {
+    engine: &'a VectorSearchEngine,
+    model: String,
+    dimensions: usize,
+    logger: Logger,
+}
+
+impl<'a> EmbeddingExporter<'a> {
+    pub fn new(engine: &'a VectorSearchEngine, model: &str, dimensions: usize) -> Self {
+        Self {
+            engine,
+            model: model.to_string(),
+            dimensions,
+            logger: Logger::new("EmbeddingExporter"),
+        }
+    }
+
+    /// Write `embeddings.npy`, optionally `block_embeddings.npy`, and `embeddings.json`
+    pub async fn export(&self, output: &Path, include_blocks: bool) -> Result<EmbeddingManifest> {
+        tokio::fs::create_dir_all(output).await?;
+
+        let documents = self.engine.stored_document_embeddings().await?;
+        let vectors: Vec<&[f32]> = documents.iter().map(|(_, v)| v.as_slice()).collect();
+        write_npy(&output.join("embeddings.npy"), &vectors, self.dimensions)?;
+
+        let mut manifest = EmbeddingManifest {
+            model: self.model.clone(),
+            dimensions: self.dimensions,
+            documents: documents.iter().map(|(path, _)| path.clone()).collect(),
+            blocks: Vec::new(),
+        };
+
+        if include_blocks {
+            let blocks = self.engine.stored_block_embeddings().await?;
+            let vectors: Vec<&[f32]> = blocks.iter().map(|(_, _, v)| v.as_slice()).collect();
+            write_npy(&output.join("block_embeddings.npy"), &vectors, self.dimensions)?;
+            manifest.blocks = blocks.into_iter().map(|(path, block_id, _)| (path, block_id)).collect();
+        }
+
+        std::fs::write(output.join("embeddings.json"), serde_json::to_string_pretty(&manifest)?)?;
+
+        self.logger.info(&format!(
+            "Exported {} document and {} block embeddings to {}",
+            manifest.documents.len(), manifest.blocks.len(), output.display()
+        ));
+        Ok(manifest)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One `.md` per note with YAML frontmatter
+    Obsidian,
+    /// One `.md` per note without frontmatter
+    Markdown,
+    /// A single `documents.json` array
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "obsidian" => Ok(Self::Obsidian),
+            "markdown" | "md" => Ok(Self::Markdown),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow::anyhow!("Unknown export format '{}'; expected obsidian, markdown or json", other)),
+        }
+    }
+}
+
+/// Inclusive range of days, parsed from `YYYY-MM-DD to YYYY-MM-DD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    pub fn parse(range: &str) -> Result<Self> {
+        let (start, end) = range.split_once(" to ")
+            .with_context(|| format!("Date range '{}' should look like YYYY-MM-DD to YYYY-MM-DD", range))?;
+        let parse = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .with_context(|| format!("Invalid date '{}' in range", date.trim()));
+        let (start, end) = (parse(start)?, parse(end)?);
+        if end < start {
+            return Err(anyhow::anyhow!("Date range ends ({}) before it starts ({})", end, start));
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+            .is_some_and(|time| (self.start..=self.end).contains(&time.date_naive()))
+    }
+}
+
+/// Writes indexed notes back out as markdown or JSON
+pub struct NoteExporter<'a> {
+    engine: &'a VectorSearchEngine,
+    vault_path: PathBuf,
+    logger: Logger,
+}
+
+impl<'a> NoteExporter<'a> {
+    pub fn new(engine: &'a VectorSearchEngine, vault_path: &Path) -> Self {
+        Self {
+            engine,
+            vault_path: vault_path.to_path_buf(),
+            logger: Logger::new("NoteExporter"),
+        }
+    }
+
+    /// Export every indexed note modified within `range`; returns how many were written
+    pub async fn export(&self, output: &Path, format: ExportFormat, range: Option<DateRange>) -> Result<usize> {
+        tokio::fs::create_dir_all(output).await?;
+
+        let documents: Vec<DocumentRecord> = self.engine.stored_documents().await?
+            .into_iter()
+            .filter(|doc| range.is_none_or(|range| range.contains(doc.modified)))
+            .collect();
+
+        if format == ExportFormat::Json {
+            let path = output.join("documents.json");
+            std::fs::write(&path, serde_json::to_string_pretty(&documents)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        } else {
+            for doc in &documents {
+                let path = output.join(self.relative_path(&doc.path));
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let body = self.body(doc).await;
+                let note = match format {
+                    ExportFormat::Obsidian => format!("{}{}", self.frontmatter(doc).await, body),
+                    _ => body,
+                };
+                tokio::fs::write(&path, note).await
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+
+        self.logger.info(&format!("Exported {} notes to {}", documents.len(), output.display()));
+        Ok(documents.len())
+    }
+
+    /// Path under the export directory, mirroring the vault layout
+    fn relative_path(&self, path: &Path) -> PathBuf {
+        let relative = path.strip_prefix(&self.vault_path).unwrap_or(path);
+        let relative = if relative.is_absolute() {
+            PathBuf::from(relative.file_name().unwrap_or_default())
+        } else {
+            relative.to_path_buf()
+        };
+        relative.with_extension("md")
+    }
+
+    /// The note's markdown from disk when it still exists, otherwise the indexed plain text
+    async fn body(&self, doc: &DocumentRecord) -> String {
+        match tokio::fs::read_to_string(self.source_path(&doc.path)).await {
+            Ok(raw) => strip_frontmatter(&raw).to_string(),
+            Err(_) => format!("# {}\n\n{}\n", doc.title, doc.content),
+        }
+    }
+
+    async fn frontmatter(&self, doc: &DocumentRecord) -> String {
+        let modified = DateTime::<Utc>::from_timestamp(doc.modified as i64, 0).unwrap_or_default();
+        let created = tokio::fs::metadata(self.source_path(&doc.path)).await
+            .and_then(|meta| meta.created())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or(modified);
+
+        let mut yaml = format!("---\ntitle: {}\n", serde_json::to_string(&doc.title).unwrap_or_default());
+        if !doc.tags.is_empty() {
+            yaml.push_str(&format!("tags: [{}]\n", doc.tags.join(", ")));
+        }
+        yaml.push_str(&format!("created: {}\nmodified: {}\n---\n\n", created.to_rfc3339(), modified.to_rfc3339()));
+        yaml
+    }
+
+    fn source_path(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() { path.to_path_buf() } else { self.vault_path.join(path) }
+    }
+}
+
+fn strip_frontmatter(raw: &str) -> &str {
+    raw.strip_prefix("---\n")
+        .and_then(|rest| rest.split_once("\n---\n"))
+        .map(|(_, body)| body.trim_start_matches('\n'))
+        .unwrap_or(raw)
+}
+
+/// Write vectors as a little-endian float32 `.npy` matrix of shape (rows, dimensions)
+pub fn write_npy(path: &Path, vectors: &[&[f32]], dimensions: usize) -> Result<()> {
+    if let Some(bad) = vectors.iter().find(|v| v.len() != dimensions) {
+        return Err(anyhow::anyhow!(
+            "Embedding has {} dimensions but the vault records {}", bad.len(), dimensions
+        ));
+    }
+
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        vectors.len(), dimensions
+    );
+    // Magic (6) + version (2) + header length (2) + header must be a multiple of 64
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(NPY_MAGIC.len() + 4 + header.len() + vectors.len() * dimensions * 4);
+    bytes.extend_from_slice(NPY_MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for vector in vectors {
+        for value in *vector {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    std::fs::write(path, bytes).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Read a float32 `.npy` matrix written by `write_npy`
+pub fn read_npy(path: &Path) -> Result<Vec<Vec<f32>>> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        return Err(anyhow::anyhow!("{} is not an npy file", path.display()));
+    }
+
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let header = std::str::from_utf8(&bytes[10..10 + header_len])?;
+    if !header.contains("'<f4'") {
+        return Err(anyhow::anyhow!("Only little-endian float32 npy files are supported"));
+    }
+
+    let shape = header
+        .split("'shape': (")
+        .nth(1)
+        .and_then(|rest| rest.split(')').next())
+        .context("npy header has no shape")?;
+    let dims: Vec<usize> = shape
+        .split(',')
+        .filter(|d| !d.trim().is_empty())
+        .map(|d| d.trim().parse())
+        .collect::<std::result::Result<_, _>>()?;
+    let (rows, cols) = match dims[..] {
+        [rows, cols] => (rows, cols),
+        _ => return Err(anyhow::anyhow!("Expected a 2-D npy matrix, got shape ({})", shape)),
+    };
+
+    let data = &bytes[10 + header_len..];
+    if data.len() != rows * cols * 4 {
+        return Err(anyhow::anyhow!("npy data length does not match shape ({}, {})", rows, cols));
+    }
+
+    if cols == 0 {
+        return Ok(vec![Vec::new(); rows]);
+    }
+
+    Ok(data
+        .chunks_exact(cols * 4)
+        .map(|row| {
+            row.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::embeddings::EmbeddingVector;
+    use crate::vault::parser::ObsidianParser;
+
+    #[tokio::test]
+    async fn test_exported_embeddings_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, vector) in [("a.md", vec![0.25, -1.5, 3.0]), ("b.md", vec![1.0, 0.0, -0.125])] {
+            let doc = parser.parse_content(Path::new(name), "# Note\n\nBody.").await.unwrap();
+            let embedding = EmbeddingVector {
+                text: String::new(),
+                vector,
+                model_name: "test-model".to_string(),
+                created_at: chrono::Utc::now(),
+                block_embeddings: None,
+                title_vector: None,
+            };
+            engine.index_document(&doc, &embedding).await.unwrap();
+        }
+
+        let output = dir.path().join("export");
+        let manifest = EmbeddingExporter::new(&engine, "test-model", 3)
+            .export(&output, false)
+            .await
+            .unwrap();
+
+        assert_eq!(manifest.model, "test-model");
+        assert_eq!(manifest.dimensions, 3);
+
+        let exported = read_npy(&output.join("embeddings.npy")).unwrap();
+        let stored = engine.stored_document_embeddings().await.unwrap();
+        assert_eq!(exported.len(), stored.len());
+        for ((path, vector), (row, manifest_path)) in stored.iter().zip(exported.iter().zip(&manifest.documents)) {
+            assert_eq!(path, manifest_path);
+            assert_eq!(vector, row);
+        }
+
+        let written: EmbeddingManifest =
+            serde_json::from_str(&std::fs::read_to_string(output.join("embeddings.json")).unwrap()).unwrap();
+        assert_eq!(written.documents, manifest.documents);
+    }
+
+    #[tokio::test]
+    async fn test_notes_export_in_each_format_within_date_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(vault.join("garden")).unwrap();
+        let raw = "---\naliases: [tom]\n---\n# Tomatoes\n\nWater **daily**. #garden\n";
+        std::fs::write(vault.join("garden/Tomatoes.md"), raw).unwrap();
+        // Last edited 2001-06-01, long before it is indexed
+        std::fs::File::options().write(true).open(vault.join("garden/Tomatoes.md")).unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(991_353_600)).unwrap();
+
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let dated = "---\nmodified: 2001-03-01 09:00:00\n---\n# Dated\n\nNo longer on disk.";
+        let documents = [
+            parser.parse_file(&vault.join("garden/Tomatoes.md")).await.unwrap(),
+            parser.parse_content(&vault.join("Gone.md"), "# Gone\n\nDeleted since.").await.unwrap(),
+            parser.parse_content(&vault.join("Dated.md"), dated).await.unwrap(),
+        ];
+        for doc in documents {
+            let embedding = EmbeddingVector {
+                text: String::new(),
+                vector: vec![1.0],
+                model_name: "test-model".to_string(),
+                created_at: chrono::Utc::now(),
+                block_embeddings: None,
+                title_vector: None,
+            };
+            engine.index_document(&doc, &embedding).await.unwrap();
+        }
+        let exporter = NoteExporter::new(&engine, &vault);
+
+        let obsidian = dir.path().join("obsidian");
+        assert_eq!(exporter.export(&obsidian, "obsidian".parse().unwrap(), None).await.unwrap(), 3);
+        let note = std::fs::read_to_string(obsidian.join("garden/Tomatoes.md")).unwrap();
+        assert!(note.starts_with("---\ntitle: \"Tomatoes\"\ntags: [garden]\ncreated: "));
+        assert!(note.contains("\nmodified: 2001-06-01T00:00:00+00:00\n"));
+        assert!(note.ends_with("---\n\n# Tomatoes\n\nWater **daily**. #garden\n"));
+        assert!(std::fs::read_to_string(obsidian.join("Gone.md")).unwrap().contains("Deleted since."));
+
+        let markdown = dir.path().join("markdown");
+        exporter.export(&markdown, ExportFormat::Markdown, None).await.unwrap();
+        assert_eq!(std::fs::read_to_string(markdown.join("garden/Tomatoes.md")).unwrap(), "# Tomatoes\n\nWater **daily**. #garden\n");
+
+        let json = dir.path().join("json");
+        exporter.export(&json, ExportFormat::Json, None).await.unwrap();
+        let records: Vec<DocumentRecord> = serde_json::from_str(&std::fs::read_to_string(json.join("documents.json")).unwrap()).unwrap();
+        assert_eq!(records.len(), 3);
+
+        // Dated by the file's mtime or the frontmatter, not by when they were indexed
+        let past = DateRange::parse("2001-01-01 to 2001-12-31").unwrap();
+        assert_eq!(exporter.export(&dir.path().join("old"), ExportFormat::Json, Some(past)).await.unwrap(), 2);
+        let today = Utc::now().date_naive();
+        let current = DateRange { start: today.pred_opt().unwrap(), end: today.succ_opt().unwrap() };
+        assert_eq!(exporter.export(&dir.path().join("new"), ExportFormat::Json, Some(current)).await.unwrap(), 1);
+
+        assert!("pdf".parse::<ExportFormat>().is_err());
+        assert!(DateRange::parse("2024-02-01").is_err());
+        assert!(DateRange::parse("2024-02-01 to 2024-01-01").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// src/vault/focus.rs - Session-wide restriction of search and retrieval to some folders and tags
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+const STATE_FILE: &str = ".note-to-ai/focus.json";
+
+/// Folders and tags a session is focused on; a note is in focus if it matches any of them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Focus {
+    pub paths: Vec<PathBuf>,
+    pub tags: Vec<String>,
+}
+
+impl Focus {
+    /// Targets like `projects/alpha` or `#rust`; those starting with `#` are tags
+    pub fn parse<S: AsRef<str>>(targets: &[S]) -> Self {
+        let mut focus = Self::default();
+        for target in targets.iter().map(|t| t.as_ref().trim()).filter(|t| !t.is_empty()) {
+            match target.strip_prefix('#') {
+                Some(tag) => focus.tags.push(tag.trim_matches('/').to_string()),
+                None => focus.paths.push(PathBuf::from(target.trim_end_matches('/'))),
+            }
+        }
+        focus
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.tags.is_empty()
+    }
+
+    /// Whether a note lies under one of the folders or carries one of the tags or a nested tag below it
+    pub fn matches(&self, path: &Path, tags: &[String]) -> bool {
+        // Indexed paths may be absolute, so a folder matches wherever it appears in the path
+        let in_folder = self.paths.iter().any(|folder| {
+            path.ancestors().skip(1).any(|ancestor| ancestor.ends_with(folder))
+        });
+        let tagged = tags.iter().map(|t| t.trim_start_matches('#')).any(|tag| {
+            self.tags.iter().any(|focus| {
+                tag == focus || tag.strip_prefix(focus.as_str()).is_some_and(|rest| rest.starts_with('/'))
+            })
+        });
+        in_folder || tagged
+    }
+
+    /// `projects/alpha, #rust`
+    pub fn describe(&self) -> String {
+        self.paths.iter()
+            .map(|p| p.display().to_string())
+            .chain(self.tags.iter().map(|t| format!("#{}", t)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The focus applied to every search in a session. Kept in the vault when loaded from one,
+/// so it lasts across commands until it is cleared.
+#[derive(Clone, Default)]
+pub struct FocusSession {
+    state_path: Option<PathBuf>,
+    current: Arc<RwLock<Option<Focus>>>,
+}
+
+impl FocusSession {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    pub fn load(vault: &Path) -> Result<Self> {
+        let state_path = vault.join(STATE_FILE);
+        let current = if state_path.exists() {
+            let json = std::fs::read_to_string(&state_path)
+                .with_context(|| format!("Failed to read {}", state_path.display()))?;
+            Some(serde_json::from_str(&json).context("Invalid focus state")?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            state_path: Some(state_path),
+            current: Arc::new(RwLock::new(current)),
+        })
+    }
+
+    pub async fn current(&self) -> Option<Focus> {
+        self.current.read().await.clone()
+    }
+
+    /// Replace the focus; an empty one clears it
+    pub async fn set(&self, focus: Focus) -> Result<()> {
+        if focus.is_empty() {
+            return self.clear().await;
+        }
+
+        if let Some(path) = &self.state_path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, serde_json::to_string_pretty(&focus)?)?;
+        }
+        *self.current.write().await = Some(focus);
+        Ok(())
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        if let Some(path) = self.state_path.as_ref().filter(|p| p.exists()) {
+            std::fs::remove_file(path)?;
+        }
+        *self.current.write().await = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+// src/vault/hierarchical.rs - Section embeddings plus a summary-of-sections vector for long notes
+use serde::{Deserialize, Serialize};
+use crate::vault::embeddings::BlockEmbedding;
+use crate::vault::parser::{BlockType, ParsedDocument};
+
+/// One vector over a very long note averages its topics away; above `min_words` each section
+/// is embedded on its own and the document vector comes from a summary of the sections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HierarchicalEmbeddingConfig {
+    pub enabled: bool,
+    /// Notes shorter than this keep a single embedding of their whole text
+    pub min_words: usize,
+    /// Characters of each section that go into the summary
+    pub summary_chars: usize,
+}
+
+impl Default for HierarchicalEmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_words: 1500,
+            summary_chars: 300,
+        }
+    }
+}
+
+/// A heading and the blocks up to the next heading; text before the first heading is a section too
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub heading: Option<String>,
+    /// Type of the section's first block, the heading unless the section precedes it
+    pub block_type: BlockType,
+    pub text: String,
+    pub start_pos: usize,
+    pub end_pos: usize,
+}
+
+impl HierarchicalEmbeddingConfig {
+    /// The note's sections when it should be embedded hierarchically, `None` for a single vector
+    pub fn plan(&self, document: &ParsedDocument) -> Option<Vec<Section>> {
+        if !self.enabled || document.metadata.word_count < self.min_words {
+            return None;
+        }
+        let sections = sections(document);
+        (sections.len() > 1).then_some(sections)
+    }
+
+    /// Text embedded as the document vector: the title, then the start of every section
+    pub fn summary(&self, document: &ParsedDocument, sections: &[Section]) -> String {
+        let mut summary = document.title.clone();
+        for section in sections {
+            let end = section.text.char_indices()
+                .nth(self.summary_chars)
+                .map_or(section.text.len(), |(i, _)| i);
+            summary.push('\n');
+            summary.push_str(&section.text[..end]);
+        }
+        summary
+    }
+}
+
+pub fn sections(document: &ParsedDocument) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    for block in &document.blocks {
+        let content = block.content.trim();
+        match (&block.block_type, sections.last_mut()) {
+            (BlockType::Heading(_), _) | (_, None) => sections.push(Section {
+                heading: matches!(block.block_type, BlockType::Heading(_)).then(|| content.to_string()),
+                block_type: block.block_type.clone(),
+                text: content.to_string(),
+                start_pos: block.position.start,
+                end_pos: block.position.end,
+            }),
+            (_, Some(section)) => {
+                if !content.is_empty() {
+                    section.text.push('\n');
+                    section.text.push_str(content);
+                }
+                section.end_pos = block.position.end;
+            }
+        }
+    }
+    sections.retain(|s| !s.text.is_empty());
+    sections
+}
+
+/// Block embeddings for `sections`, in order, from their vectors
+pub fn section_embeddings(document: &ParsedDocument, sections: &[Section], vectors: Vec<Vec<f32>>) -> Vec<BlockEmbedding> {
+    sections.iter().zip(vectors).enumerate()
+        .map(|(i, (section, vector))| BlockEmbedding {
+            block_id: format!("{}#section-{}", document.path.display(), i),
+            block_type: section.block_type.clone(),
+            content: section.text.clone(),
+            vector,
+            start_pos: section.start_pos,
+            end_pos: section.end_pos,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use crate::test_support::BagOfWords;
+    use crate::vault::embedding_pool::{EmbeddingPool, EmbeddingWorker};
+    use crate::vault::indexer::VaultIndexer;
+    use crate::vault::search::{SearchOptions, VectorSearchEngine};
+
+    async fn finds_sourdough(hierarchy: &HierarchicalEmbeddingConfig) -> bool {
+        // Eight long sections on other topics and a short one on the query's
+        let mut note = String::from("# Household\n\n");
+        for topic in 0..8 {
+            let filler: Vec<String> = (0..200).map(|k| format!("topic{}word{}", topic, k % 20)).collect();
+            note.push_str(&format!("## Topic {}\n\n{}\n\n", topic, filler.join(" ")));
+        }
+        note.push_str("## Sourdough starter\n\nSourdough starter feeding schedule: feed the starter every morning.\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("household.md"), &note).unwrap();
+        let db = dir.path().join("index.db");
+        let engine = Arc::new(VectorSearchEngine::new(db.clone()).unwrap());
+        engine.initialize().await.unwrap();
+        let pool = Arc::new(EmbeddingPool::new(vec![Arc::new(BagOfWords::Hashed(512)) as Arc<dyn EmbeddingWorker>]).unwrap());
+        let indexer = VaultIndexer::new(db, vault).unwrap()
+            .with_search_engine(engine.clone())
+            .with_embeddings(pool, "test")
+            .with_hierarchical_embeddings(hierarchy.clone());
+        indexer.initialize_db().await.unwrap();
+        let indexed = indexer.full_index().await.unwrap();
+        assert_eq!((indexed.added, indexed.errors), (1, 0));
+
+        let query = "sourdough starter feeding schedule";
+        let options = SearchOptions { similarity_threshold: 0.6, include_context: false, ..SearchOptions::default() };
+        let results = engine.semantic_search_by_vector(query, &BagOfWords::Hashed(512).embed(query).await.unwrap(), &options).await.unwrap();
+        results.iter().any(|r| r.document.path == Path::new("household.md"))
+    }
+
+    #[tokio::test]
+    async fn test_long_note_is_found_through_its_matching_section() {
+        let hierarchy = HierarchicalEmbeddingConfig { min_words: 1000, ..HierarchicalEmbeddingConfig::default() };
+        assert!(finds_sourdough(&hierarchy).await);
+
+        // The whole-note vector alone is too diluted to pass the threshold
+        let flat = HierarchicalEmbeddingConfig { enabled: false, ..hierarchy };
+        assert!(!finds_sourdough(&flat).await);
+    }
+}
@@ -0,0 +1,473 @@
+// src/vault/hnsw.rs - Approximate nearest-neighbour graph over note embeddings (HNSW)
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+/// Which index `semantic_search` ranks candidates with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorIndexMode {
+    /// The HNSW graph once the vault holds `HNSW_MIN_VECTORS` embeddings, brute force below that
+    #[default]
+    Auto,
+    /// Score every stored embedding; exact but O(N) per query
+    BruteForce,
+    Hnsw,
+}
+
+/// How two embeddings are compared; every metric is turned into a similarity, higher is closer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    /// Cosine of the angle, from -1 to 1
+    #[default]
+    Cosine,
+    /// Euclidean distance `d`, scored as `1 / (1 + d)` so identical vectors score 1
+    L2,
+    /// Raw dot product, for models trained to rank by it; unbounded
+    Dot,
+}
+
+impl DistanceMetric {
+    pub fn similarity(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            DistanceMetric::Cosine => dot(&normalized(a), &normalized(b)),
+            DistanceMetric::L2 => 1.0 / (1.0 + l2(a, b)),
+            DistanceMetric::Dot => dot(a, b),
+        }
+    }
+}
+
+/// Below this many vectors scanning them all is about as fast and always exact
+pub const HNSW_MIN_VECTORS: usize = 2000;
+
+#[derive(Debug, Clone)]
+pub struct HnswConfig {
+    /// Neighbours kept per node on the upper layers; layer 0 keeps twice as many
+    pub m: usize,
+    /// Candidates considered while linking a new node
+    pub ef_construction: usize,
+    pub metric: DistanceMetric,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self { m: 16, ef_construction: 100, metric: DistanceMetric::default() }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    label: String,
+    /// Normalized under the cosine metric, so similarity is a dot product
+    vector: Vec<f32>,
+    /// Neighbour ids per layer, from layer 0 up to the node's level
+    neighbors: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+/// Candidate ordered by similarity, highest first in a `BinaryHeap`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored(f32, usize);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+/// Hierarchical navigable small-world graph. Several vectors may share a label (a note and its
+/// sections); removal tombstones a label's nodes, and the graph is rebuilt once most are tombstones.
+#[derive(Debug)]
+pub struct HnswIndex {
+    config: HnswConfig,
+    nodes: Vec<Node>,
+    by_label: HashMap<String, Vec<usize>>,
+    entry: Option<usize>,
+    deleted: usize,
+    rng: u64,
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswConfig::default())
+    }
+}
+
+impl HnswIndex {
+    pub fn new(config: HnswConfig) -> Self {
+        Self {
+            config,
+            nodes: Vec::new(),
+            by_label: HashMap::new(),
+            entry: None,
+            deleted: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Live vectors in the graph
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.deleted
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replace the vectors stored under `label`
+    pub fn insert(&mut self, label: &str, vectors: &[&[f32]]) {
+        self.remove(label);
+        for vector in vectors {
+            let id = self.add_node(label, vector);
+            self.by_label.entry(label.to_string()).or_default().push(id);
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) {
+        let Some(ids) = self.by_label.remove(label) else {
+            return;
+        };
+        for id in ids {
+            self.nodes[id].deleted = true;
+            self.deleted += 1;
+        }
+        if self.deleted > self.nodes.len() / 2 {
+            self.rebuild();
+        }
+    }
+
+    /// Up to `k` labels nearest to `query` with their best similarity under the configured metric;
+    /// `ef` (at least `k`) trades speed for recall
+    pub fn search(&self, query: &[f32], k: usize, ef: usize) -> Vec<(String, f32)> {
+        let Some(mut entry) = self.entry else {
+            return Vec::new();
+        };
+        let query = self.prepare(query);
+        if query.len() != self.nodes[entry].vector.len() {
+            return Vec::new();
+        }
+
+        let top = self.nodes[entry].neighbors.len() - 1;
+        for layer in (1..=top).rev() {
+            entry = self.greedy_closest(&query, entry, layer);
+        }
+        // Labels repeat across a note's sections, so search wider than `k` nodes
+        let found = self.search_layer(&query, &[entry], ef.max(k), 0);
+
+        let mut best: HashMap<&str, f32> = HashMap::new();
+        for Scored(similarity, id) in found {
+            let node = &self.nodes[id];
+            if node.deleted {
+                continue;
+            }
+            let score = best.entry(node.label.as_str()).or_insert(f32::MIN);
+            *score = score.max(similarity);
+        }
+        let mut results: Vec<(String, f32)> = best.into_iter().map(|(label, s)| (label.to_string(), s)).collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(k);
+        results
+    }
+
+    fn add_node(&mut self, label: &str, vector: &[f32]) -> usize {
+        let level = self.random_level();
+        let id = self.nodes.len();
+        let vector = self.prepare(vector);
+        self.nodes.push(Node {
+            label: label.to_string(),
+            vector,
+            neighbors: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+
+        let Some(mut entry) = self.entry else {
+            self.entry = Some(id);
+            return id;
+        };
+        let query = self.nodes[id].vector.clone();
+        let top = self.nodes[entry].neighbors.len() - 1;
+
+        for layer in (level + 1..=top).rev() {
+            entry = self.greedy_closest(&query, entry, layer);
+        }
+        let mut entries = vec![entry];
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, &entries, self.config.ef_construction, layer);
+            let neighbors = self.select_neighbors(&candidates, self.max_neighbors(layer));
+            for &neighbor in &neighbors {
+                self.nodes[neighbor].neighbors[layer].push(id);
+                self.prune(neighbor, layer);
+            }
+            self.nodes[id].neighbors[layer] = neighbors;
+            entries = candidates.iter().map(|c| c.1).collect();
+        }
+
+        if level > top {
+            self.entry = Some(id);
+        }
+        id
+    }
+
+    /// Follow the single best neighbour on `layer` until none is closer
+    fn greedy_closest(&self, query: &[f32], mut current: usize, layer: usize) -> usize {
+        let mut best = self.score(query, &self.nodes[current].vector);
+        loop {
+            let mut moved = false;
+            for &neighbor in &self.nodes[current].neighbors[layer] {
+                let similarity = self.score(query, &self.nodes[neighbor].vector);
+                if similarity > best {
+                    best = similarity;
+                    current = neighbor;
+                    moved = true;
+                }
+            }
+            if !moved {
+                return current;
+            }
+        }
+    }
+
+    /// Best-first search on one layer, returning up to `ef` nodes, most similar first.
+    /// Tombstoned nodes are still traversed so the graph stays connected.
+    fn search_layer(&self, query: &[f32], entries: &[usize], ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        // Min-heap of the best `ef` so far, via reversed ordering
+        let mut found: BinaryHeap<std::cmp::Reverse<Scored>> = BinaryHeap::new();
+
+        for &entry in entries {
+            let scored = Scored(self.score(query, &self.nodes[entry].vector), entry);
+            candidates.push(scored);
+            found.push(std::cmp::Reverse(scored));
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Scored(similarity, current)) = candidates.pop() {
+            let worst = found.peek().map_or(f32::MIN, |w| w.0 .0);
+            if similarity < worst && found.len() >= ef {
+                break;
+            }
+            for &neighbor in &self.nodes[current].neighbors[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let similarity = self.score(query, &self.nodes[neighbor].vector);
+                let worst = found.peek().map_or(f32::MIN, |w| w.0 .0);
+                if found.len() < ef || similarity > worst {
+                    candidates.push(Scored(similarity, neighbor));
+                    found.push(std::cmp::Reverse(Scored(similarity, neighbor)));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        let mut found: Vec<Scored> = found.into_iter().map(|r| r.0).collect();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+
+    /// Keep candidates that are closer to the new node than to any neighbour already kept,
+    /// so links spread across directions instead of bunching in one cluster
+    fn select_neighbors(&self, candidates: &[Scored], max: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(max);
+        for &Scored(similarity, id) in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            let dominated = selected.iter()
+                .any(|&kept| self.score(&self.nodes[id].vector, &self.nodes[kept].vector) > similarity);
+            if !dominated {
+                selected.push(id);
+            }
+        }
+        // Fill up with the nearest skipped candidates so sparse regions stay reachable
+        for &Scored(_, id) in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            if !selected.contains(&id) {
+                selected.push(id);
+            }
+        }
+        selected
+    }
+
+    /// Drop the farthest links of an overflowing neighbour list; the diversity heuristic only
+    /// runs for new nodes, since re-running it on every neighbour makes builds quadratic in `m`
+    fn prune(&mut self, id: usize, layer: usize) {
+        let max = self.max_neighbors(layer);
+        if self.nodes[id].neighbors[layer].len() <= max {
+            return;
+        }
+        let vector = &self.nodes[id].vector;
+        let mut candidates: Vec<Scored> = self.nodes[id].neighbors[layer].iter()
+            .map(|&n| Scored(self.score(vector, &self.nodes[n].vector), n))
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        self.nodes[id].neighbors[layer] = candidates.into_iter().take(max).map(|c| c.1).collect();
+    }
+
+    /// A vector as stored and searched: unit length under the cosine metric
+    fn prepare(&self, vector: &[f32]) -> Vec<f32> {
+        match self.config.metric {
+            DistanceMetric::Cosine => normalized(vector),
+            DistanceMetric::L2 | DistanceMetric::Dot => vector.to_vec(),
+        }
+    }
+
+    /// Similarity of two prepared vectors
+    fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self.config.metric {
+            DistanceMetric::Cosine | DistanceMetric::Dot => dot(a, b),
+            DistanceMetric::L2 => 1.0 / (1.0 + l2(a, b)),
+        }
+    }
+
+    fn max_neighbors(&self, layer: usize) -> usize {
+        if layer == 0 { self.config.m * 2 } else { self.config.m }
+    }
+
+    /// Exponentially distributed level with normalization 1/ln(m); xorshift keeps builds reproducible
+    fn random_level(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = ((self.rng >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let level = -uniform.ln() / (self.config.m.max(2) as f64).ln();
+        (level as usize).min(16)
+    }
+
+    /// Rebuild from the live nodes only
+    fn rebuild(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.by_label.clear();
+        self.entry = None;
+        self.deleted = 0;
+        for node in nodes.into_iter().filter(|n| !n.deleted) {
+            let id = self.add_node(&node.label, &node.vector);
+            self.by_label.entry(node.label).or_default().push(id);
+        }
+    }
+}
+
+fn normalized(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn l2(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vectors(count: usize, dimensions: usize, mut seed: u64) -> Vec<Vec<f32>> {
+        (0..count).map(|_| {
+            (0..dimensions).map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                ((seed >> 33) as f32 / (1u64 << 31) as f32) - 0.5
+            }).collect()
+        }).collect()
+    }
+
+    fn exact(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<String> {
+        let query = normalized(query);
+        let mut scored: Vec<(usize, f32)> = vectors.iter().enumerate()
+            .map(|(i, v)| (i, dot(&query, &normalized(v))))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.iter().take(k).map(|(i, _)| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_search_recalls_exact_neighbors_and_forgets_removed_labels() {
+        let vectors = random_vectors(1000, 16, 7);
+        let mut index = HnswIndex::default();
+        for (i, vector) in vectors.iter().enumerate() {
+            index.insert(&i.to_string(), &[vector]);
+        }
+        assert_eq!(index.len(), 1000);
+
+        let queries = random_vectors(20, 16, 99);
+        let mut hits = 0;
+        for query in &queries {
+            let expected = exact(&vectors, query, 10);
+            let found: Vec<String> = index.search(query, 10, 64).into_iter().map(|(label, _)| label).collect();
+            hits += found.iter().filter(|label| expected.contains(label)).count();
+        }
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall >= 0.9, "recall {}", recall);
+
+        let nearest = &index.search(&queries[0], 1, 64)[0].0;
+        index.remove(nearest);
+        assert!(index.search(&queries[0], 10, 64).iter().all(|(label, _)| label != nearest));
+
+        // Re-inserting a label replaces its vectors instead of adding to them
+        index.insert("0", &[&vectors[1], &vectors[2]]);
+        assert_eq!(index.len(), 1000);
+        let (label, similarity) = &index.search(&vectors[2], 1, 64)[0];
+        assert!(label == "0" || label == "2");
+        assert!((similarity - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_graph_is_rebuilt_after_most_labels_are_removed() {
+        let vectors = random_vectors(200, 8, 3);
+        let mut index = HnswIndex::default();
+        for (i, vector) in vectors.iter().enumerate() {
+            index.insert(&i.to_string(), &[vector]);
+        }
+        for i in 0..150 {
+            index.remove(&i.to_string());
+        }
+        assert_eq!(index.len(), 50);
+        assert!(index.nodes.len() < 100);
+        let found = index.search(&vectors[180], 1, 32);
+        assert_eq!(found[0].0, "180");
+    }
+
+    #[test]
+    fn test_each_metric_scores_identical_vectors_and_orders_the_graph() {
+        let a = [3.0, 4.0];
+        assert!((DistanceMetric::Cosine.similarity(&a, &a) - 1.0).abs() < 1e-6);
+        assert!((DistanceMetric::L2.similarity(&a, &a) - 1.0).abs() < 1e-6);
+        assert!((DistanceMetric::Dot.similarity(&a, &a) - 25.0).abs() < 1e-6);
+        // Same direction, different length: only cosine ignores the length
+        let b = [6.0, 8.0];
+        assert!((DistanceMetric::Cosine.similarity(&a, &b) - 1.0).abs() < 1e-6);
+        assert!((DistanceMetric::L2.similarity(&a, &b) - 1.0 / 6.0).abs() < 1e-6);
+
+        let vectors = [vec![1.0, 0.0], vec![10.0, 0.0], vec![0.0, 1.0]];
+        let nearest = |metric: DistanceMetric| {
+            let mut index = HnswIndex::new(HnswConfig { metric, ..HnswConfig::default() });
+            for (i, vector) in vectors.iter().enumerate() {
+                index.insert(&i.to_string(), &[vector]);
+            }
+            index.search(&[1.0, 0.0], 1, 8)[0].clone()
+        };
+        assert_eq!(nearest(DistanceMetric::L2), ("0".to_string(), 1.0));
+        assert_eq!(nearest(DistanceMetric::Dot), ("1".to_string(), 10.0));
+    }
+}
@@ -1,14 +1,29 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use blake3::{Hash, Hasher};
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
 use tokio::fs as async_fs;
 use walkdir::WalkDir;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use rusqlite::{Connection, params};
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
 use crate::logger::Logger;
+use crate::vault::batch::BatchIndexer;
+use crate::vault::crdt::CrdtStore;
+use crate::vault::embedding_pool::{RetryConfig, SharedEmbeddingPool};
+use crate::vault::hierarchical::HierarchicalEmbeddingConfig;
+use crate::vault::moc::MocGenerator;
+use crate::vault::parser::{LinkResolutionConfig, LinkResolver, ParsedDocument};
+use crate::vault::parsers::ParserRegistry;
+use crate::vault::search::VectorSearchEngine;
+use crate::webhooks::{WebhookEvent, WebhookNotifier};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileIndex {
@@ -24,6 +39,7 @@ pub struct FileIndex {
 pub enum FileType {
     Markdown,
     Text,
+    Code,
     Image,
     Audio,
     Video,
@@ -36,6 +52,8 @@ impl FileType {
         match ext.to_lowercase().as_str() {
             "md" | "markdown" => FileType::Markdown,
             "txt" | "text" => FileType::Text,
+            "rs" | "py" | "js" | "mjs" | "cjs" | "ts" | "tsx" | "go" | "java" | "c" | "h" | "cpp" | "cc" | "hpp"
+            | "rb" | "sh" | "bash" | "swift" | "kt" => FileType::Code,
             "png" | "jpg" | "jpeg" | "gif" | "webp" | "svg" => FileType::Image,
             "mp3" | "wav" | "flac" | "ogg" | "m4a" => FileType::Audio,
             "mp4" | "avi" | "mkv" | "webm" | "mov" => FileType::Video,
@@ -49,6 +67,22 @@ pub struct VaultIndexer {
     db_path: PathBuf,
     vault_path: PathBuf,
     ignore_patterns: HashSet<String>,
+    /// The vault's `.gitignore` files, deepest first, reloaded on each full index
+    gitignores: Arc<RwLock<Vec<Gitignore>>>,
+    parsers: ParserRegistry,
+    /// Quiet period that ends a batch of watched changes
+    debounce: Duration,
+    /// Note histories used to merge edits from other replicas instead of overwriting them
+    crdt: Option<CrdtStore>,
+    /// Search index that deleted files are removed from, keyed by vault-relative path
+    search: Option<Arc<VectorSearchEngine>>,
+    /// Embeds parsed files into `search`, with the model name recorded on their vectors
+    embeddings: Option<(Arc<SharedEmbeddingPool>, String)>,
+    hierarchy: Option<HierarchicalEmbeddingConfig>,
+    links: LinkResolutionConfig,
+    /// Brought up to date after every pass that changed a file
+    moc: Option<Arc<MocGenerator>>,
+    webhooks: WebhookNotifier,
     logger: Logger,
 }
 
@@ -57,18 +91,149 @@ impl VaultIndexer {
         let mut ignore_patterns = HashSet::new();
         ignore_patterns.insert(".git".to_string());
         ignore_patterns.insert(".obsidian".to_string());
+        ignore_patterns.insert(".note-to-ai".to_string());
         ignore_patterns.insert("node_modules".to_string());
         ignore_patterns.insert(".DS_Store".to_string());
         ignore_patterns.insert("Thumbs.db".to_string());
 
+        let gitignores = load_gitignores(&vault_path, &ignore_patterns);
+
         Ok(Self {
             db_path,
             vault_path,
             ignore_patterns,
+            gitignores: Arc::new(RwLock::new(gitignores)),
+            parsers: ParserRegistry::with_defaults()?,
+            debounce: Duration::from_millis(500),
+            crdt: None,
+            search: None,
+            embeddings: None,
+            hierarchy: None,
+            links: LinkResolutionConfig::default(),
+            moc: None,
+            webhooks: WebhookNotifier::disabled(),
             logger: Logger::new("VaultIndexer"),
         })
     }
 
+    /// Replace the parsers files are dispatched to by type or extension
+    pub fn with_parsers(mut self, parsers: ParserRegistry) -> Self {
+        self.parsers = parsers;
+        self
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Keep a CRDT history of each text note as `replica`, so a note edited here while
+    /// another replica's edits are queued in `crdt()` is merged rather than overwritten
+    pub fn with_crdt(mut self, replica: &str) -> Self {
+        self.crdt = Some(CrdtStore::new(self.db_path.clone(), replica));
+        self
+    }
+
+    /// Remove deleted files' documents and vectors from `engine` as well as the file index
+    pub fn with_search_engine(mut self, engine: Arc<VectorSearchEngine>) -> Self {
+        self.search = Some(engine);
+        self
+    }
+
+    /// Embed each added or updated file that parses into a document into the search index
+    pub fn with_embeddings(mut self, pool: Arc<SharedEmbeddingPool>, model_name: &str) -> Self {
+        self.embeddings = Some((pool, model_name.to_string()));
+        self
+    }
+
+    /// Embed long notes section by section, as `config` plans them
+    pub fn with_hierarchical_embeddings(mut self, config: HierarchicalEmbeddingConfig) -> Self {
+        self.hierarchy = Some(config);
+        self
+    }
+
+    /// Update the map-of-content notes whenever indexing changed a file
+    pub fn with_moc(mut self, generator: Arc<MocGenerator>) -> Self {
+        self.moc = Some(generator);
+        self
+    }
+
+    /// How link targets are matched to files before documents are indexed
+    pub fn with_link_resolution(mut self, config: LinkResolutionConfig) -> Self {
+        self.links = config;
+        self
+    }
+
+    /// Report each added or updated file, and each failure, to the configured webhook
+    pub fn with_webhooks(mut self, webhooks: WebhookNotifier) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    pub fn crdt(&self) -> Option<&CrdtStore> {
+        self.crdt.as_ref()
+    }
+
+    /// Whether edits to `path` from other replicas are merged through its CRDT history
+    pub fn merges_edits(&self, path: &Path) -> bool {
+        self.crdt.is_some() && path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(FileType::from_extension(ext), FileType::Markdown | FileType::Text))
+    }
+
+    pub fn vault_path(&self) -> &Path {
+        &self.vault_path
+    }
+
+    /// Batches of vault files created, changed, deleted or renamed, each emitted once the vault
+    /// has been quiet for the debounce period; hand them to `incremental_index`. Editors that
+    /// save through a temporary file and a rename produce just the saved path
+    pub fn watch(&self) -> Result<impl Stream<Item = Vec<PathBuf>>> {
+        let (events, mut raw) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let _ = events.send(event.paths);
+            }
+        })?;
+        watcher.watch(&self.vault_path, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", self.vault_path.display()))?;
+
+        let (batches, stream) = mpsc::channel(16);
+        let vault_path = self.vault_path.clone();
+        let ignore_patterns = self.ignore_patterns.clone();
+        let gitignores = self.gitignores.clone();
+        let debounce = self.debounce;
+        tokio::spawn(async move {
+            // Owned here so watching stops once the stream is dropped
+            let _watcher = watcher;
+            while let Some(paths) = raw.recv().await {
+                // A save seen as delete then create lands in the same set as one path
+                let mut pending: BTreeSet<PathBuf> = paths.into_iter().collect();
+                loop {
+                    match tokio::time::timeout(debounce, raw.recv()).await {
+                        Ok(Some(paths)) => pending.extend(paths),
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+
+                let changed: Vec<PathBuf> = pending.into_iter()
+                    .filter(|path| !path.is_dir())
+                    .filter(|path| {
+                        let relative = path.strip_prefix(&vault_path).unwrap_or(path);
+                        !is_ignored(&ignore_patterns, relative)
+                            && !is_gitignored(&gitignores.read().unwrap(), path)
+                    })
+                    .collect();
+                if !changed.is_empty() && batches.send(changed).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(ReceiverStream::new(stream))
+    }
+
     pub fn add_ignore_pattern(&mut self, pattern: String) {
         self.ignore_patterns.insert(pattern);
     }
@@ -107,6 +272,24 @@ impl VaultIndexer {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS access_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                path TEXT NOT NULL,
+                accessed_at INTEGER NOT NULL
+            )",
+            [],
+        ).context("Failed to create access_log table")?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_access_path ON access_log(path)",
+            [],
+        )?;
+
+        if let Some(crdt) = &self.crdt {
+            crdt.initialize()?;
+        }
+
         self.logger.info("Database initialized successfully");
         Ok(())
     }
@@ -116,27 +299,33 @@ impl VaultIndexer {
         let start_time = std::time::Instant::now();
 
         let mut stats = IndexStats::default();
+        *self.gitignores.write().unwrap() = load_gitignores(&self.vault_path, &self.ignore_patterns);
         let entries = self.scan_vault_files()?;
+        let mut documents = Vec::new();
 
-        for entry in entries {
-            match self.index_file(&entry).await {
-                Ok(action) => {
+        for entry in &entries {
+            match self.index_file(entry).await {
+                Ok((action, document)) => {
                     match action {
                         IndexAction::Added => stats.added += 1,
                         IndexAction::Updated => stats.updated += 1,
                         IndexAction::Skipped => stats.skipped += 1,
                     }
+                    documents.extend(document);
                 }
                 Err(e) => {
                     self.logger.error(&format!("Failed to index {}: {}", entry.display(), e));
+                    self.report_error(entry, &e);
                     stats.errors += 1;
                 }
             }
         }
+        self.index_documents(documents, &entries, &mut stats).await?;
 
         // Clean up deleted files
         let deleted = self.clean_deleted_files().await?;
         stats.deleted = deleted;
+        self.update_mocs(&stats).await;
 
         let duration = start_time.elapsed();
         self.logger.info(&format!(
@@ -150,6 +339,7 @@ impl VaultIndexer {
     pub async fn incremental_index(&self, paths: Vec<PathBuf>) -> Result<IndexStats> {
         self.logger.info(&format!("Starting incremental indexing of {} files", paths.len()));
         let mut stats = IndexStats::default();
+        let mut documents = Vec::new();
 
         for path in paths {
             if !path.exists() {
@@ -161,19 +351,26 @@ impl VaultIndexer {
             }
 
             match self.index_file(&path).await {
-                Ok(action) => {
+                Ok((action, document)) => {
                     match action {
                         IndexAction::Added => stats.added += 1,
                         IndexAction::Updated => stats.updated += 1,
                         IndexAction::Skipped => stats.skipped += 1,
                     }
+                    documents.extend(document);
                 }
                 Err(e) => {
                     self.logger.error(&format!("Failed to index {}: {}", path.display(), e));
+                    self.report_error(&path, &e);
                     stats.errors += 1;
                 }
             }
         }
+        if !documents.is_empty() {
+            let files: Vec<PathBuf> = self.get_all_files().await?.into_iter().map(|file| file.path).collect();
+            self.index_documents(documents, &files, &mut stats).await?;
+        }
+        self.update_mocs(&stats).await;
 
         self.logger.info(&format!(
             "Incremental indexing completed: {} added, {} updated, {} deleted, {} skipped, {} errors",
@@ -183,16 +380,78 @@ impl VaultIndexer {
         Ok(stats)
     }
 
-    async fn index_file(&self, path: &Path) -> Result<IndexAction> {
+    /// Regenerate the MOC notes from every note in the vault; a failure leaves them for the next pass
+    async fn update_mocs(&self, stats: &IndexStats) {
+        let Some(moc) = &self.moc else {
+            return;
+        };
+        if stats.added + stats.updated + stats.deleted == 0 {
+            return;
+        }
+        let files = match self.scan_vault_files() {
+            Ok(files) => files,
+            Err(e) => {
+                self.logger.error(&format!("Failed to update the MOC notes: {}", e));
+                return;
+            }
+        };
+        let mut documents = Vec::new();
+        for path in files {
+            let parsed = match async_fs::read(&path).await {
+                Ok(content) => self.parsers.parse(&path, &content).await,
+                Err(e) => Err(e.into()),
+            };
+            match parsed {
+                Ok(document) => documents.extend(document),
+                Err(e) => self.logger.warn(&format!("Leaving {} out of the MOC notes: {}", path.display(), e)),
+            }
+        }
+        if let Err(e) = moc.update(&self.vault_path, &documents).await {
+            self.logger.error(&format!("Failed to update the MOC notes: {}", e));
+        }
+    }
+
+    /// Resolve the parsed files' links against every file in the vault, then embed them into
+    /// the search index under their vault-relative paths
+    async fn index_documents(&self, mut documents: Vec<ParsedDocument>, vault_files: &[PathBuf], stats: &mut IndexStats) -> Result<()> {
+        let (Some(search), Some((pool, model_name))) = (&self.search, &self.embeddings) else {
+            return Ok(());
+        };
+        if documents.is_empty() {
+            return Ok(());
+        }
+
+        for document in &mut documents {
+            document.path = self.relative_path(&document.path).to_path_buf();
+        }
+        let mut resolver = LinkResolver::new(&documents, self.links.clone());
+        for path in vault_files {
+            resolver.add_path(self.relative_path(path));
+        }
+        resolver.resolve_all(&mut documents);
+
+        let mut batch = BatchIndexer::new(search, pool, model_name);
+        if let Some(hierarchy) = &self.hierarchy {
+            batch = batch.with_hierarchy(hierarchy);
+        }
+        let indexed = batch.index(&documents, &RetryConfig::default()).await?;
+        for error in &indexed.errors {
+            self.logger.error(&format!("Failed to add to the search index: {}", error));
+        }
+        stats.errors += indexed.errors.len();
+        Ok(())
+    }
+
+    async fn index_file(&self, path: &Path) -> Result<(IndexAction, Option<ParsedDocument>)> {
         if self.should_ignore_file(path) {
-            return Ok(IndexAction::Skipped);
+            return Ok((IndexAction::Skipped, None));
         }
 
-        let metadata = fs::metadata(path)
+        let mut metadata = fs::metadata(path)
             .context("Failed to read file metadata")?;
 
         if metadata.is_dir() {
-            return Ok(IndexAction::Skipped);
+            return Ok((IndexAction::Skipped, None));
         }
 
         let modified = metadata.modified()?
@@ -201,22 +460,36 @@ impl VaultIndexer {
 
         // Check if file needs indexing
         if let Some(existing) = self.get_file_index(path).await? {
-            if existing.modified >= modified && existing.size == metadata.len() {
-                return Ok(IndexAction::Skipped);
+            if existing.modified >= modified && existing.size == metadata.len() && !self.has_remote_edits(path)? {
+                return Ok((IndexAction::Skipped, None));
             }
         }
 
-        // Calculate BLAKE3 hash
-        let content = async_fs::read(path).await
+        let mut content = async_fs::read(path).await
             .context("Failed to read file content")?;
-        
-        let hash = self.calculate_blake3_hash(&content);
-        
+
         let file_type = path.extension()
             .and_then(|ext| ext.to_str())
             .map(FileType::from_extension)
             .unwrap_or(FileType::Unknown);
 
+        if let (Some(crdt), FileType::Markdown | FileType::Text, Ok(text)) = (&self.crdt, &file_type, std::str::from_utf8(&content)) {
+            let merged = crdt.reconcile(self.relative_path(path), text)?;
+            if merged != text {
+                self.logger.info(&format!("Merged remote edits into {}", path.display()));
+                async_fs::write(path, &merged).await
+                    .context("Failed to write merged note")?;
+                metadata = fs::metadata(path)?;
+                content = merged.into_bytes();
+            }
+        }
+        let modified = metadata.modified()?
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        // Calculate BLAKE3 hash
+        let hash = self.calculate_blake3_hash(&content);
+
         let file_index = FileIndex {
             path: path.to_path_buf(),
             hash: hash.to_string(),
@@ -228,15 +501,58 @@ impl VaultIndexer {
             file_type,
         };
 
+        // A parse failure still indexes the file, just without document metadata
+        let mut document = match self.parsers.parse(path, &content).await {
+            Ok(document) => document,
+            Err(e) => {
+                self.logger.warn(&format!("Failed to parse {}: {}", path.display(), e));
+                None
+            }
+        };
+        if let Some(document) = &mut document {
+            document.metadata.modified = Some(metadata.modified()?.into());
+        }
+        let metadata = document.as_ref()
+            .map(|document| serde_json::to_value(&document.metadata))
+            .transpose()?;
+
+        let stored = metadata.as_ref().map(|metadata| metadata.to_string());
         let action = if self.get_file_index(path).await?.is_some() {
-            self.update_file_index(&file_index).await?;
+            self.update_file_index(&file_index, stored.as_deref()).await?;
             IndexAction::Updated
         } else {
-            self.insert_file_index(&file_index).await?;
+            self.insert_file_index(&file_index, stored.as_deref()).await?;
             IndexAction::Added
         };
 
-        Ok(action)
+        self.webhooks.notify(WebhookEvent::DocumentIndexed, serde_json::json!({
+            "path": self.relative_path(path),
+            "action": if matches!(action, IndexAction::Added) { "added" } else { "updated" },
+            "file_type": file_index.file_type,
+            "size": file_index.size,
+            "metadata": metadata,
+        }));
+        Ok((action, document))
+    }
+
+    fn report_error(&self, path: &Path, error: &anyhow::Error) {
+        self.webhooks.notify(WebhookEvent::Error, serde_json::json!({
+            "stage": "index",
+            "path": self.relative_path(path),
+            "error": error.to_string(),
+        }));
+    }
+
+    /// Vault-relative, so replicas with the vault in different places agree on the key
+    fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.vault_path).unwrap_or(path)
+    }
+
+    fn has_remote_edits(&self, path: &Path) -> Result<bool> {
+        match &self.crdt {
+            Some(crdt) => crdt.has_remote(self.relative_path(path)),
+            None => Ok(false),
+        }
     }
 
     fn calculate_blake3_hash(&self, content: &[u8]) -> Hash {
@@ -264,31 +580,7 @@ impl VaultIndexer {
     }
 
     fn should_ignore_file(&self, path: &Path) -> bool {
-        // Check ignore patterns
-        for component in path.components() {
-            if let Some(name) = component.as_os_str().to_str() {
-                if self.ignore_patterns.contains(name) {
-                    return true;
-                }
-            }
-        }
-
-        // Check file extension
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            match ext.to_lowercase().as_str() {
-                "tmp" | "temp" | "lock" | "swp" | "bak" => return true,
-                _ => {}
-            }
-        }
-
-        // Check filename patterns
-        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if name.starts_with('.') && name != ".md" {
-                return true;
-            }
-        }
-
-        false
+        is_ignored(&self.ignore_patterns, path) || is_gitignored(&self.gitignores.read().unwrap(), path)
     }
 
     async fn get_file_index(&self, path: &Path) -> Result<Option<FileIndex>> {
@@ -318,31 +610,32 @@ impl VaultIndexer {
         }
     }
 
-    async fn insert_file_index(&self, index: &FileIndex) -> Result<()> {
+    async fn insert_file_index(&self, index: &FileIndex, metadata: Option<&str>) -> Result<()> {
         let conn = Connection::open(&self.db_path)?;
         
         conn.execute(
-            "INSERT INTO file_index (path, hash, size, modified, indexed_at, file_type)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO file_index (path, hash, size, modified, indexed_at, file_type, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 index.path.to_string_lossy(),
                 index.hash,
                 index.size,
                 index.modified,
                 index.indexed_at,
-                serde_json::to_string(&index.file_type)?
+                serde_json::to_string(&index.file_type)?,
+                metadata
             ],
         )?;
 
         Ok(())
     }
 
-    async fn update_file_index(&self, index: &FileIndex) -> Result<()> {
+    async fn update_file_index(&self, index: &FileIndex, metadata: Option<&str>) -> Result<()> {
         let conn = Connection::open(&self.db_path)?;
         
         conn.execute(
             "UPDATE file_index 
-             SET hash = ?2, size = ?3, modified = ?4, indexed_at = ?5, file_type = ?6
+             SET hash = ?2, size = ?3, modified = ?4, indexed_at = ?5, file_type = ?6, metadata = ?7
              WHERE path = ?1",
             params![
                 index.path.to_string_lossy(),
@@ -350,7 +643,8 @@ impl VaultIndexer {
                 index.size,
                 index.modified,
                 index.indexed_at,
-                serde_json::to_string(&index.file_type)?
+                serde_json::to_string(&index.file_type)?,
+                metadata
             ],
         )?;
 
@@ -358,38 +652,75 @@ impl VaultIndexer {
     }
 
     async fn remove_file_from_index(&self, path: &Path) -> Result<bool> {
-        let conn = Connection::open(&self.db_path)?;
-        
-        let changed = conn.execute(
-            "DELETE FROM file_index WHERE path = ?1",
-            params![path.to_string_lossy()],
-        )?;
+        let changed = {
+            let conn = Connection::open(&self.db_path)?;
+            conn.execute(
+                "DELETE FROM file_index WHERE path = ?1",
+                params![path.to_string_lossy()],
+            )?
+        };
+        self.remove_from_search(path).await?;
 
         Ok(changed > 0)
     }
 
     async fn clean_deleted_files(&self) -> Result<usize> {
-        let conn = Connection::open(&self.db_path)?;
-        
-        let mut stmt = conn.prepare("SELECT path FROM file_index")?;
-        let paths: Vec<String> = stmt.query_map([], |row| {
-            Ok(row.get::<_, String>(0)?)
-        })?.collect::<Result<Vec<_>, _>>()?;
-
-        let mut deleted_count = 0;
-        for path_str in paths {
-            let path = PathBuf::from(&path_str);
-            if !path.exists() {
-                conn.execute(
-                    "DELETE FROM file_index WHERE path = ?1",
-                    params![path_str],
-                )?;
-                deleted_count += 1;
-                self.logger.debug(&format!("Removed deleted file from index: {}", path_str));
+        let mut deleted = Vec::new();
+        {
+            let conn = Connection::open(&self.db_path)?;
+
+            let mut stmt = conn.prepare("SELECT path FROM file_index")?;
+            let paths: Vec<String> = stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for path_str in paths {
+                let path = PathBuf::from(&path_str);
+                if !path.exists() {
+                    conn.execute(
+                        "DELETE FROM file_index WHERE path = ?1",
+                        params![path_str],
+                    )?;
+                    self.logger.debug(&format!("Removed deleted file from index: {}", path_str));
+                    deleted.push(path);
+                }
             }
         }
 
-        Ok(deleted_count)
+        for path in &deleted {
+            self.remove_from_search(path).await?;
+        }
+        Ok(deleted.len())
+    }
+
+    async fn remove_from_search(&self, path: &Path) -> Result<()> {
+        if let Some(search) = &self.search {
+            search.remove_document(&self.relative_path(path).to_path_buf()).await?;
+        }
+        Ok(())
+    }
+
+    /// Every indexed file, by path
+    pub async fn get_all_files(&self) -> Result<Vec<FileIndex>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT path, hash, size, modified, indexed_at, file_type FROM file_index ORDER BY path"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(FileIndex {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                hash: row.get(1)?,
+                size: row.get(2)?,
+                modified: row.get(3)?,
+                indexed_at: row.get(4)?,
+                file_type: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or(FileType::Unknown),
+            })
+        })?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+        Ok(files)
     }
 
     pub async fn get_files_by_type(&self, file_type: FileType) -> Result<Vec<FileIndex>> {
@@ -447,6 +778,52 @@ impl VaultIndexer {
         Ok(files)
     }
 
+    /// Record that a document was read, for warmup and ranking; `path` may be vault-relative
+    pub async fn record_access(&self, path: &Path) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        
+        conn.execute(
+            "INSERT INTO access_log (path, accessed_at) VALUES (?1, ?2)",
+            params![
+                self.vault_path.join(path).to_string_lossy(),
+                SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Indexed files ordered by access count, most recently accessed first on ties
+    pub async fn get_most_accessed_files(&self, limit: usize) -> Result<Vec<FileIndex>> {
+        let conn = Connection::open(&self.db_path)?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT f.path, f.hash, f.size, f.modified, f.indexed_at, f.file_type
+             FROM access_log a JOIN file_index f ON f.path = a.path
+             GROUP BY f.path
+             ORDER BY COUNT(*) DESC, MAX(a.accessed_at) DESC, MAX(a.id) DESC
+             LIMIT ?1"
+        )?;
+
+        let rows = stmt.query_map(params![limit], |row| {
+            Ok(FileIndex {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                hash: row.get(1)?,
+                size: row.get(2)?,
+                modified: row.get(3)?,
+                indexed_at: row.get(4)?,
+                file_type: serde_json::from_str(&row.get::<_, String>(5)?).unwrap_or(FileType::Unknown),
+            })
+        })?;
+
+        let mut files = Vec::new();
+        for row in rows {
+            files.push(row?);
+        }
+
+        Ok(files)
+    }
+
     pub async fn get_stats(&self) -> Result<VaultStats> {
         let conn = Connection::open(&self.db_path)?;
         
@@ -488,6 +865,96 @@ impl VaultIndexer {
     }
 }
 
+fn is_ignored(ignore_patterns: &HashSet<String>, path: &Path) -> bool {
+    // Check ignore patterns
+    for component in path.components() {
+        if let Some(name) = component.as_os_str().to_str() {
+            if ignore_patterns.contains(name) {
+                return true;
+            }
+        }
+    }
+
+    // Check file extension
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        match ext.to_lowercase().as_str() {
+            "tmp" | "temp" | "lock" | "swp" | "bak" => return true,
+            _ => {}
+        }
+    }
+
+    // Check filename patterns; `note.md~` is an editor backup
+    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+        if (name.starts_with('.') && name != ".md") || name.ends_with('~') {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Name of the vault-wide ignore file, read after the top-level `.gitignore` so it can override it
+const NTAI_IGNORE: &str = ".ntaiignore";
+
+/// One matcher per directory with a `.gitignore`, deepest first; the top-level one also holds
+/// `.ntaiignore`. Directories the built-in patterns exclude, such as `.git`, aren't searched
+fn load_gitignores(vault_path: &Path, ignore_patterns: &HashSet<String>) -> Vec<Gitignore> {
+    let logger = Logger::new("VaultIndexer");
+    let mut matchers = Vec::new();
+    let directories = WalkDir::new(vault_path)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !is_ignored(ignore_patterns, Path::new(e.file_name())))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir());
+
+    for directory in directories {
+        let dir = directory.path();
+        let mut files = vec![dir.join(".gitignore")];
+        if directory.depth() == 0 {
+            files.push(dir.join(NTAI_IGNORE));
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for file in files.iter().filter(|f| f.is_file()) {
+            found = true;
+            if let Some(e) = builder.add(file) {
+                logger.warn(&format!("Skipping invalid rules in {}: {}", file.display(), e));
+            }
+        }
+        if !found {
+            continue;
+        }
+        match builder.build() {
+            Ok(matcher) => matchers.push((directory.depth(), matcher)),
+            Err(e) => logger.warn(&format!("Failed to load ignore rules in {}: {}", dir.display(), e)),
+        }
+    }
+
+    matchers.sort_by_key(|(depth, _)| std::cmp::Reverse(*depth));
+    matchers.into_iter().map(|(_, matcher)| matcher).collect()
+}
+
+/// Whether the closest rule to `path`, in the deepest ignore file that has one, excludes it;
+/// a `!pattern` negation re-includes a file even when an outer file excludes it
+fn is_gitignored(gitignores: &[Gitignore], path: &Path) -> bool {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    for matcher in gitignores {
+        let root = matcher.path();
+        let root = root.strip_prefix("./").unwrap_or(root);
+        if !path.starts_with(root) || path == root {
+            continue;
+        }
+        match matcher.matched_path_or_any_parents(path, false) {
+            ignore::Match::Ignore(_) => return true,
+            ignore::Match::Whitelist(_) => return false,
+            ignore::Match::None => {}
+        }
+    }
+    false
+}
+
 #[derive(Debug, Default)]
 pub struct IndexStats {
     pub added: usize,
@@ -511,3 +978,163 @@ enum IndexAction {
     Skipped,
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_watch_emits_saved_paths_once_and_skips_temp_files() {
+        let vault = tempfile::tempdir().unwrap();
+        let indexer = VaultIndexer::new(vault.path().join("index.db"), vault.path().to_path_buf())
+            .unwrap()
+            .with_debounce(Duration::from_millis(100));
+        let mut changes = Box::pin(indexer.watch().unwrap());
+
+        std::fs::write(vault.path().join("Garden.md"), "# Garden").unwrap();
+        let batch = tokio::time::timeout(Duration::from_secs(5), changes.next()).await.unwrap().unwrap();
+        assert_eq!(batch, [vault.path().join("Garden.md")]);
+
+        // An atomic save: write a temporary file, then rename it over the note
+        std::fs::write(vault.path().join(".Garden.md.swp"), "# Garden\n\nTomatoes").unwrap();
+        std::fs::rename(vault.path().join(".Garden.md.swp"), vault.path().join("Garden.md")).unwrap();
+        let batch = tokio::time::timeout(Duration::from_secs(5), changes.next()).await.unwrap().unwrap();
+        assert_eq!(batch, [vault.path().join("Garden.md")]);
+    }
+
+    #[tokio::test]
+    async fn test_gitignore_and_ntaiignore_rules_with_negation() {
+        let vault = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = vault.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("Garden.md", "# Garden");
+        write("projects/.gitignore", "build/\n");
+        write("projects/Plan.md", "# Plan");
+        write("projects/build/Output.md", "# Output");
+        // Scoped to `projects/`, so a top-level `build/` is still indexed
+        write("build/Notes.md", "# Notes");
+        write(".ntaiignore", "drafts/*\n!drafts/Publish.md\n");
+        write("drafts/Idea.md", "# Idea");
+        write("drafts/Publish.md", "# Publish");
+
+        let indexer = VaultIndexer::new(vault.path().join("index.db"), vault.path().to_path_buf()).unwrap();
+        let mut files: Vec<PathBuf> = indexer.scan_vault_files().unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(vault.path()).unwrap().to_path_buf())
+            .collect();
+        files.sort();
+        assert_eq!(files, [
+            PathBuf::from("Garden.md"),
+            PathBuf::from("build/Notes.md"),
+            PathBuf::from("drafts/Publish.md"),
+            PathBuf::from("projects/Plan.md"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_local_edit_merges_with_queued_remote_edit() {
+        use crate::vault::crdt::NoteCrdt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        let note = vault.join("Plan.md");
+        std::fs::write(&note, "# Plan\n\n- draft\n").unwrap();
+
+        let indexer = VaultIndexer::new(dir.path().join("index.db"), vault.clone()).unwrap().with_crdt("laptop");
+        indexer.initialize_db().await.unwrap();
+        indexer.full_index().await.unwrap();
+
+        // The phone edits its copy of the history and sends it over
+        let crdt = indexer.crdt().unwrap();
+        let key = Path::new("Plan.md");
+        let mut phone = NoteCrdt::load(key, "phone", &crdt.state(key).unwrap().unwrap()).unwrap();
+        phone.update("# Plan\n\n- draft\n- review\n").unwrap();
+        crdt.receive_remote(key, "phone", &phone.save()).unwrap();
+
+        std::fs::write(&note, "# Plan for May\n\n- draft\n").unwrap();
+        let stats = indexer.incremental_index(vec![note.clone()]).await.unwrap();
+
+        assert_eq!(stats.updated, 1);
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "# Plan for May\n\n- draft\n- review\n");
+        assert!(!crdt.has_remote(key).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_deleted_note_leaves_the_search_index() {
+        use crate::vault::embeddings::EmbeddingVector;
+        use crate::vault::parser::ObsidianParser;
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        let db = dir.path().join("index.db");
+        let engine = Arc::new(VectorSearchEngine::new(db.clone()).unwrap());
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for name in ["Garden.md", "Compost.md"] {
+            std::fs::write(vault.join(name), "# Note").unwrap();
+            let document = parser.parse_content(Path::new(name), "# Note").await.unwrap();
+            let embedding = EmbeddingVector {
+                text: String::new(),
+                vector: vec![1.0, 0.0],
+                model_name: "test".to_string(),
+                created_at: chrono::Utc::now(),
+                block_embeddings: None,
+                title_vector: None,
+            };
+            engine.index_document(&document, &embedding).await.unwrap();
+        }
+
+        let indexer = VaultIndexer::new(db, vault.clone()).unwrap().with_search_engine(engine.clone());
+        indexer.initialize_db().await.unwrap();
+        indexer.full_index().await.unwrap();
+
+        std::fs::remove_file(vault.join("Garden.md")).unwrap();
+        let stats = indexer.incremental_index(vec![vault.join("Garden.md")]).await.unwrap();
+        assert_eq!(stats.deleted, 1);
+        assert_eq!(engine.get_stats().await.unwrap().total_embeddings, 1);
+
+        std::fs::remove_file(vault.join("Compost.md")).unwrap();
+        let stats = indexer.full_index().await.unwrap();
+        assert_eq!(stats.deleted, 1);
+        assert_eq!(engine.get_stats().await.unwrap().total_embeddings, 0);
+        assert!(engine.stored_document_embeddings().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_indexed_notes_link_to_files_they_were_not_parsed_with() {
+        use crate::test_support::UnitWorker;
+        use crate::vault::embedding_pool::{EmbeddingPool, EmbeddingWorker};
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(vault.join("notes")).unwrap();
+        std::fs::write(vault.join("Garden.md"), "Feed with [[Compost]].").unwrap();
+        std::fs::write(vault.join("notes/Compost.md"), "# Compost").unwrap();
+        std::fs::write(vault.join("notes/Seeds.md"), "# Seeds").unwrap();
+
+        let db = dir.path().join("index.db");
+        let engine = Arc::new(VectorSearchEngine::new(db.clone()).unwrap());
+        engine.initialize().await.unwrap();
+        let pool = Arc::new(EmbeddingPool::new(vec![Arc::new(UnitWorker) as Arc<dyn EmbeddingWorker>]).unwrap());
+        let indexer = VaultIndexer::new(db, vault.clone()).unwrap()
+            .with_search_engine(engine.clone())
+            .with_embeddings(pool, "unit");
+        indexer.initialize_db().await.unwrap();
+
+        indexer.full_index().await.unwrap();
+        assert_eq!(engine.backlinks(Path::new("notes/Compost.md"), None).await, [PathBuf::from("Garden.md")]);
+
+        // Only the edited note is parsed again; the unchanged one it now links to is still found
+        std::fs::write(vault.join("Garden.md"), "Sow [[Seeds]] in spring.").unwrap();
+        indexer.incremental_index(vec![vault.join("Garden.md")]).await.unwrap();
+        assert_eq!(engine.backlinks(Path::new("notes/Seeds.md"), None).await, [PathBuf::from("Garden.md")]);
+        assert!(engine.backlinks(Path::new("notes/Compost.md"), None).await.is_empty());
+    }
+}
@@ -0,0 +1,320 @@
+// src/vault/ingest.rs - Save web articles into the vault as markdown notes
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{Result, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::vault::parser::{ObsidianParser, ParsedDocument};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestConfig {
+    /// Vault folder new clippings are written to
+    pub folder: PathBuf,
+    pub timeout_secs: u64,
+    /// Pages with less readable text than this are rejected rather than saved blank
+    pub min_content_chars: usize,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            folder: PathBuf::from("Clippings"),
+            timeout_secs: 20,
+            min_content_chars: 200,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IngestedNote {
+    pub path: PathBuf,
+    pub document: ParsedDocument,
+}
+
+/// Readable content pulled out of an HTML page
+#[derive(Debug, Clone)]
+pub struct Article {
+    pub title: String,
+    pub markdown: String,
+}
+
+pub struct UrlIngestor {
+    client: reqwest::Client,
+    vault_path: PathBuf,
+    config: IngestConfig,
+    parser: ObsidianParser,
+    logger: Logger,
+}
+
+impl UrlIngestor {
+    pub fn new(vault_path: PathBuf, config: IngestConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent("note-to-ai")
+            .build()
+            .context("Failed to build HTTP client")?;
+
+        Ok(Self {
+            client,
+            vault_path,
+            config,
+            parser: ObsidianParser::new()?,
+            logger: Logger::new("UrlIngestor"),
+        })
+    }
+
+    /// Fetch a page, extract its readable content, and save it as a note with source metadata
+    pub async fn ingest_url(&self, url: &str) -> Result<IngestedNote> {
+        let parsed_url = reqwest::Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+        if !matches!(parsed_url.scheme(), "http" | "https") {
+            return Err(anyhow::anyhow!("Only http and https URLs can be saved, got {}", url));
+        }
+
+        let response = self.client.get(parsed_url.clone()).send().await
+            .with_context(|| format!("Failed to fetch {}", url))?;
+
+        let status = response.status();
+        if matches!(status.as_u16(), 401..=403) {
+            return Err(anyhow::anyhow!(
+                "{} returned {}; the page is paywalled or requires a login", url, status
+            ));
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("{} returned {}", url, status));
+        }
+
+        let is_html = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("html"))
+            .unwrap_or(true);
+        if !is_html {
+            return Err(anyhow::anyhow!("{} is not an HTML page", url));
+        }
+
+        let html = response.text().await?;
+        let article = extract_article(&html);
+
+        if article.markdown.chars().count() < self.config.min_content_chars {
+            return Err(anyhow::anyhow!(
+                "No readable content found at {}; the page may be paywalled or rendered by JavaScript", url
+            ));
+        }
+
+        let title = if article.title.is_empty() {
+            parsed_url.host_str().unwrap_or("Untitled").to_string()
+        } else {
+            article.title.clone()
+        };
+
+        // Most articles repeat the page title as their first heading
+        let heading = format!("# {}", title);
+        let body = article.markdown.strip_prefix(&heading).unwrap_or(&article.markdown).trim_start();
+
+        let markdown = format!(
+            "---\ntitle: \"{}\"\nsource: {}\nfetched: {}\ntags: [clipping]\n---\n{}\n\n{}\n",
+            title.replace('"', "'"),
+            url,
+            chrono::Utc::now().to_rfc3339(),
+            heading,
+            body
+        );
+
+        let path = self.note_path(&title);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, &markdown).await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        let document = self.parser.parse_content(&path, &markdown).await?;
+        self.logger.info(&format!("Saved {} to {}", url, path.display()));
+
+        Ok(IngestedNote { path, document })
+    }
+
+    /// A file name derived from the title that does not overwrite an existing note
+    fn note_path(&self, title: &str) -> PathBuf {
+        let folder = self.vault_path.join(&self.config.folder);
+        let stem: String = title
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' { c } else { ' ' })
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let stem = if stem.is_empty() { "Clipping".to_string() } else { stem };
+
+        let mut path = folder.join(format!("{}.md", stem));
+        let mut n = 1;
+        while path.exists() {
+            path = folder.join(format!("{} {}.md", stem, n));
+            n += 1;
+        }
+        path
+    }
+}
+
+/// Pull the main content out of a page and convert it to markdown
+pub fn extract_article(html: &str) -> Article {
+    let title = capture(r"(?is)<title\b[^>]*>(.*?)</title>", html)
+        .or_else(|| capture(r"(?is)<h1\b[^>]*>(.*?)</h1>", html))
+        .map(|t| clean_inline(&t))
+        .unwrap_or_default();
+
+    let mut body = html.to_string();
+    for tag in ["script", "style", "noscript", "nav", "header", "footer", "aside", "form", "svg"] {
+        let re = Regex::new(&format!(r"(?is)<{0}\b[^>]*>.*?</{0}>", tag)).unwrap();
+        body = re.replace_all(&body, "").into_owned();
+    }
+    body = Regex::new(r"(?s)<!--.*?-->").unwrap().replace_all(&body, "").into_owned();
+
+    // Prefer the article, then main, then body
+    let content = ["article", "main", "body"]
+        .iter()
+        .find_map(|tag| capture(&format!(r"(?is)<{0}\b[^>]*>(.*)</{0}>", tag), &body))
+        .unwrap_or(body);
+
+    Article {
+        title,
+        markdown: html_to_markdown(&content),
+    }
+}
+
+fn capture(pattern: &str, text: &str) -> Option<String> {
+    Regex::new(pattern).ok()?
+        .captures(text)
+        .map(|c| c[1].to_string())
+}
+
+fn html_to_markdown(html: &str) -> String {
+    // Code blocks are set aside so whitespace collapsing leaves them intact
+    let mut code_blocks = Vec::new();
+    let pre = Regex::new(r"(?is)<pre\b[^>]*>(.*?)</pre>").unwrap();
+    let text = pre.replace_all(html, |caps: &regex::Captures| {
+        let code = decode_entities(&strip_tags(&caps[1]));
+        code_blocks.push(format!("```\n{}\n```", code.trim_matches('\n')));
+        format!("\n\n\u{0}{}\u{0}\n\n", code_blocks.len() - 1)
+    }).into_owned();
+
+    let heading = Regex::new(r"(?is)<h([1-6])\b[^>]*>(.*?)</h[1-6]>").unwrap();
+    let text = heading.replace_all(&text, |caps: &regex::Captures| {
+        let level: usize = caps[1].parse().unwrap_or(2);
+        format!("\n\n{} {}\n\n", "#".repeat(level), clean_inline(&caps[2]))
+    }).into_owned();
+
+    let link = Regex::new(r#"(?is)<a\b[^>]*href\s*=\s*["']([^"']*)["'][^>]*>(.*?)</a>"#).unwrap();
+    let text = link.replace_all(&text, |caps: &regex::Captures| {
+        let label = clean_inline(&caps[2]);
+        if label.is_empty() { String::new() } else { format!("[{}]({})", label, &caps[1]) }
+    }).into_owned();
+
+    let replacements = [
+        (r"(?is)<(strong|b)\b[^>]*>(.*?)</(strong|b)>", "**$2**"),
+        (r"(?is)<(em|i)\b[^>]*>(.*?)</(em|i)>", "*$2*"),
+        (r"(?is)<code\b[^>]*>(.*?)</code>", "`$1`"),
+        (r"(?is)<li\b[^>]*>", "\n- "),
+        (r"(?is)<br\s*/?>", "\n"),
+        (r"(?is)</?(p|div|section|blockquote|ul|ol|table|tr)\b[^>]*>", "\n\n"),
+    ];
+    let mut text = text;
+    for (pattern, replacement) in replacements {
+        text = Regex::new(pattern).unwrap().replace_all(&text, replacement).into_owned();
+    }
+
+    let text = decode_entities(&strip_tags(&text));
+
+    // Collapse whitespace within lines and runs of blank lines
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+
+    let mut markdown = lines.join("\n").trim().to_string();
+    for (i, block) in code_blocks.iter().enumerate() {
+        markdown = markdown.replace(&format!("\u{0}{}\u{0}", i), block);
+    }
+    markdown
+}
+
+fn clean_inline(html: &str) -> String {
+    decode_entities(&strip_tags(html)).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn strip_tags(html: &str) -> String {
+    Regex::new(r"(?s)<[^>]*>").unwrap().replace_all(html, "").into_owned()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{http_response, http_server};
+
+    const ARTICLE: &str = r#"<html><head><title>Growing Tomatoes</title>
+<script>trackVisitor();</script><style>body { color: red; }</style></head>
+<body><nav><a href="/">Home</a> | <a href="/about">About</a></nav>
+<article>
+  <h1>Growing Tomatoes</h1>
+  <p>Tomatoes need <strong>six hours</strong> of sun and steady watering. Water at the base of the plant
+     in the morning so the leaves dry before evening &amp; disease stays away.</p>
+  <h2>Staking</h2>
+  <p>Stake plants early. See the <a href="https://example.com/stakes">stake guide</a> for options.</p>
+  <ul><li>Use soft ties</li><li>Prune suckers weekly</li></ul>
+  <pre><code>water(plant, litres=2)</code></pre>
+</article>
+<footer>Copyright 2024</footer></body></html>"#;
+
+    /// Serve a fixed HTTP response to every connection
+    async fn serve(status: &'static str, body: &'static str) -> String {
+        let url = http_server(move |_| vec![http_response(status, "text/html; charset=utf-8", body)]).await;
+        format!("{}/tomatoes", url)
+    }
+
+    #[tokio::test]
+    async fn test_ingest_url_saves_readable_article() {
+        let url = serve("200 OK", ARTICLE).await;
+        let vault = tempfile::tempdir().unwrap();
+        let ingestor = UrlIngestor::new(vault.path().to_path_buf(), IngestConfig::default()).unwrap();
+
+        let note = ingestor.ingest_url(&url).await.unwrap();
+        let saved = std::fs::read_to_string(&note.path).unwrap();
+
+        assert_eq!(note.path, vault.path().join("Clippings/Growing Tomatoes.md"));
+        assert!(saved.contains(&format!("source: {}", url)));
+        assert!(saved.contains("## Staking"));
+        assert!(saved.contains("**six hours**"));
+        assert!(saved.contains("[stake guide](https://example.com/stakes)"));
+        assert!(saved.contains("- Prune suckers weekly"));
+        assert!(saved.contains("```\nwater(plant, litres=2)\n```"));
+        assert!(saved.contains("evening & disease"));
+        assert!(!saved.contains("trackVisitor") && !saved.contains("Copyright") && !saved.contains("About"));
+        assert_eq!(saved.matches("# Growing Tomatoes").count(), 1);
+        assert_eq!(note.document.title, "Growing Tomatoes");
+    }
+
+    #[tokio::test]
+    async fn test_paywalled_page_is_an_error_not_a_blank_note() {
+        let url = serve("402 Payment Required", "<html><body>Subscribe</body></html>").await;
+        let vault = tempfile::tempdir().unwrap();
+        let ingestor = UrlIngestor::new(vault.path().to_path_buf(), IngestConfig::default()).unwrap();
+
+        let error = ingestor.ingest_url(&url).await.unwrap_err();
+        assert!(error.to_string().contains("paywalled"));
+        assert!(!vault.path().join("Clippings").exists());
+    }
+}
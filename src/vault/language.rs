@@ -0,0 +1,156 @@
+// src/vault/language.rs - Detect a note's language and pick the matching full-text analyzer
+use serde::{Deserialize, Serialize};
+use crate::vault::embeddings::EmbeddingModelConfig;
+use crate::vault::parser::ParsedDocument;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageConfig {
+    /// Route each note and query to a full-text analyzer for its language
+    pub enabled: bool,
+    /// Guess the language of notes and queries that don't declare one
+    pub detect: bool,
+    /// ISO 639-1 code assumed when nothing is declared or detected
+    pub default_language: String,
+    /// Embedding model used for the whole vault instead of `vault.embedding.model`,
+    /// e.g. `paraphrase-multilingual-MiniLM-L12-v2`
+    pub multilingual_model: Option<String>,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detect: true,
+            default_language: "en".to_string(),
+            multilingual_model: None,
+        }
+    }
+}
+
+impl LanguageConfig {
+    /// Language of a note: frontmatter `lang`/`language`, else detected, else the default
+    pub fn document_language(&self, document: &ParsedDocument) -> String {
+        let declared = document.frontmatter.as_ref().and_then(|fm| {
+            ["lang", "language"].iter()
+                .find_map(|key| fm.custom_fields.get(*key).and_then(|v| v.as_str()))
+        });
+        declared.map(|lang| lang.trim().to_lowercase())
+            .or_else(|| self.detect.then(|| detect_language(&document.plain_text)).flatten())
+            .unwrap_or_else(|| self.default_language.clone())
+    }
+
+    /// Language of a query: the one given, else detected, else the default
+    pub fn query_language(&self, query: &str, language: Option<&str>) -> String {
+        language.map(str::to_lowercase)
+            .or_else(|| self.detect.then(|| detect_language(query)).flatten())
+            .unwrap_or_else(|| self.default_language.clone())
+    }
+
+    /// The configured embedding model, swapped for the multilingual one when set
+    pub fn embedding_model(&self, embedding: &EmbeddingModelConfig) -> EmbeddingModelConfig {
+        match (&self.multilingual_model, self.enabled) {
+            (Some(model), true) => EmbeddingModelConfig { model: model.clone(), ..embedding.clone() },
+            _ => embedding.clone(),
+        }
+    }
+}
+
+/// SQLite FTS5 tokenizer setups, one full-text table each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FtsAnalyzer {
+    /// Porter stemming, for English
+    English,
+    /// Word tokens with accents folded, for other space-separated languages
+    Unicode,
+    /// Character trigrams, for Chinese, Japanese and Korean, which don't separate words
+    Cjk,
+}
+
+impl FtsAnalyzer {
+    pub const ALL: [FtsAnalyzer; 3] = [Self::English, Self::Unicode, Self::Cjk];
+
+    pub fn for_language(language: &str) -> Self {
+        match language {
+            "en" => Self::English,
+            "zh" | "ja" | "ko" => Self::Cjk,
+            _ => Self::Unicode,
+        }
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            Self::English => "search_fts_en",
+            Self::Unicode => "search_fts_unicode",
+            Self::Cjk => "search_fts_cjk",
+        }
+    }
+
+    pub fn tokenizer(&self) -> &'static str {
+        match self {
+            Self::English => "porter unicode61 remove_diacritics 2",
+            Self::Unicode => "unicode61 remove_diacritics 2",
+            Self::Cjk => "trigram",
+        }
+    }
+}
+
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "of", "to", "with", "that", "for", "this", "are"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "mit", "ein", "eine", "ich"]),
+    ("fr", &["le", "la", "les", "et", "est", "des", "une", "pour", "dans", "avec"]),
+    ("es", &["el", "los", "las", "y", "es", "una", "para", "con", "por", "que"]),
+    ("it", &["il", "gli", "e", "che", "di", "una", "per", "non", "sono", "con"]),
+    ("pt", &["o", "os", "e", "um", "uma", "para", "com", "não", "que", "do"]),
+    ("nl", &["de", "het", "een", "en", "is", "van", "niet", "met", "voor", "dat"]),
+];
+
+/// Best guess at the ISO 639-1 code of `text`, by script and then by common function words
+pub fn detect_language(text: &str) -> Option<String> {
+    let mut kana = 0;
+    let mut hangul = 0;
+    let mut han = 0;
+    let mut letters = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        match c as u32 {
+            0x3040..=0x30ff => kana += 1,
+            0xac00..=0xd7af | 0x1100..=0x11ff => hangul += 1,
+            0x4e00..=0x9fff | 0x3400..=0x4dbf => han += 1,
+            _ => {}
+        }
+    }
+    if letters == 0 {
+        return None;
+    }
+    if (kana + hangul + han) * 3 >= letters {
+        let language = if kana > 0 { "ja" } else if hangul > han { "ko" } else { "zh" };
+        return Some(language.to_string());
+    }
+
+    let words: Vec<String> = text.split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    STOPWORDS.iter()
+        .map(|(language, stopwords)| (*language, words.iter().filter(|w| stopwords.contains(&w.as_str())).count()))
+        .filter(|(_, hits)| *hits > 0)
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(language, _)| language.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_script_and_stopword_languages() {
+        assert_eq!(detect_language("東京タワーに行きました").as_deref(), Some("ja"));
+        assert_eq!(detect_language("我们明天去北京").as_deref(), Some("zh"));
+        assert_eq!(detect_language("Der Hund ist nicht mit der Katze im Garten").as_deref(), Some("de"));
+        assert_eq!(detect_language("The dog is in the garden with the cat").as_deref(), Some("en"));
+        assert_eq!(detect_language("1234 ..."), None);
+
+        assert_eq!(FtsAnalyzer::for_language("ja"), FtsAnalyzer::Cjk);
+        assert_eq!(FtsAnalyzer::for_language("de"), FtsAnalyzer::Unicode);
+    }
+}
@@ -0,0 +1,360 @@
+// src/vault/lock.rs - Advisory lockfile so only one process writes to a storage directory
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Whether a storage open needs the write lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    ReadOnly,
+    /// Take the lock; `force` replaces a lock held by another instance
+    Write { force: bool },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockConfig {
+    /// Take over locks whose holder is no longer running, e.g. after a crash; when off they
+    /// block until removed by hand or `--force`
+    pub recover_stale: bool,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self { recover_stale: true }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockError {
+    #[error("Another instance (pid {pid}) is using this storage at {}; stop it or pass --force", dir.display())]
+    Held { dir: PathBuf, pid: u32 },
+
+    #[error("Storage at {} is locked by pid {pid}, which is no longer running; remove {} or pass --force", dir.display(), dir.join(StorageLock::FILE_NAME).display())]
+    Stale { dir: PathBuf, pid: u32 },
+
+    #[error("Storage at {} has a lockfile that doesn't name its holder; remove {} or pass --force", dir.display(), dir.join(StorageLock::FILE_NAME).display())]
+    Unrecognized { dir: PathBuf },
+
+    #[error("Failed to lock storage at {}: {source}", dir.display())]
+    Io {
+        dir: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// The process recorded in a lockfile. The start time tells a holder apart from a later
+/// process that was given the same PID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Holder {
+    pid: u32,
+    /// Clock ticks after boot, from `/proc/<pid>/stat`
+    started: Option<u64>,
+}
+
+impl Holder {
+    fn current() -> Self {
+        let pid = std::process::id();
+        Self { pid, started: process_start(pid) }
+    }
+
+    /// `pid=<pid>` and `started=<ticks>` lines; older lockfiles hold only the PID
+    fn parse(contents: &str) -> Option<Self> {
+        let contents = contents.trim();
+        if let Ok(pid) = contents.parse() {
+            return Some(Self { pid, started: None });
+        }
+
+        let mut pid = None;
+        let mut started = None;
+        for line in contents.lines() {
+            match line.split_once('=') {
+                Some(("pid", value)) => pid = value.trim().parse().ok(),
+                Some(("started", value)) => started = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Self { pid: pid?, started })
+    }
+
+    fn to_file_contents(self) -> String {
+        match self.started {
+            Some(started) => format!("pid={}\nstarted={}\n", self.pid, started),
+            None => format!("pid={}\n", self.pid),
+        }
+    }
+
+    /// Without `/proc` there is no cheap portable check, so the holder is assumed alive
+    fn is_alive(&self) -> bool {
+        let proc = Path::new("/proc");
+        if !proc.is_dir() {
+            return true;
+        }
+        if !proc.join(self.pid.to_string()).exists() {
+            return false;
+        }
+        match (self.started, process_start(self.pid)) {
+            (Some(recorded), Some(current)) => recorded == current,
+            _ => true,
+        }
+    }
+}
+
+/// Held for as long as a process may write to the directory. The lockfile is locked with the
+/// OS file lock, which the kernel releases when its holder exits, and names the holder for
+/// error messages; it is removed on drop
+#[derive(Debug)]
+pub struct StorageLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl StorageLock {
+    pub const FILE_NAME: &'static str = ".note-to-ai.lock";
+
+    /// How long to wait for a holder that has the file lock to record itself
+    const HOLDER_WAIT: Duration = Duration::from_millis(500);
+
+    /// Lock for write opens, nothing for read-only ones
+    pub fn for_mode(dir: &Path, mode: OpenMode) -> Result<Option<Self>, LockError> {
+        Self::for_mode_with(dir, mode, &LockConfig::default())
+    }
+
+    pub fn for_mode_with(dir: &Path, mode: OpenMode, config: &LockConfig) -> Result<Option<Self>, LockError> {
+        match mode {
+            OpenMode::ReadOnly => Ok(None),
+            OpenMode::Write { force } => Self::acquire_with(dir, force, config).map(Some),
+        }
+    }
+
+    /// Lock the directory, failing if a live process holds it; locks left by a process
+    /// that no longer exists are taken over
+    pub fn acquire(dir: &Path, force: bool) -> Result<Self, LockError> {
+        Self::acquire_with(dir, force, &LockConfig::default())
+    }
+
+    pub fn acquire_with(dir: &Path, force: bool, config: &LockConfig) -> Result<Self, LockError> {
+        let io_error = |source| LockError::Io { dir: dir.to_path_buf(), source };
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+        let path = dir.join(Self::FILE_NAME);
+        let mut waited = Duration::ZERO;
+
+        loop {
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)
+                .map_err(io_error)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) if force => {
+                    // The holder keeps its lock on the unlinked file; we lock a new one
+                    warn!("Replacing the storage lock at {} held by another instance", dir.display());
+                    match std::fs::remove_file(&path) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(io_error(e)),
+                        _ => continue,
+                    }
+                }
+                Err(TryLockError::WouldBlock) => {
+                    let holder = std::fs::read_to_string(&path).ok().and_then(|contents| Holder::parse(&contents));
+                    match holder {
+                        Some(holder) => return Err(LockError::Held { dir: dir.to_path_buf(), pid: holder.pid }),
+                        // Locked but not yet written
+                        None if waited < Self::HOLDER_WAIT => {
+                            std::thread::sleep(Duration::from_millis(10));
+                            waited += Duration::from_millis(10);
+                            continue;
+                        }
+                        None => return Err(LockError::Unrecognized { dir: dir.to_path_buf() }),
+                    }
+                }
+                Err(TryLockError::Error(e)) => return Err(io_error(e)),
+            }
+            // Its last holder may have removed the file between our open and lock
+            if !is_same_file(&file, &path) {
+                continue;
+            }
+
+            // Nobody holds the file lock, but lockfiles written without one name their holder only
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).map_err(io_error)?;
+            if !force && !contents.trim().is_empty() {
+                match Holder::parse(&contents) {
+                    Some(holder) if holder.is_alive() => {
+                        return Err(LockError::Held { dir: dir.to_path_buf(), pid: holder.pid });
+                    }
+                    Some(holder) if !config.recover_stale => {
+                        return Err(LockError::Stale { dir: dir.to_path_buf(), pid: holder.pid });
+                    }
+                    Some(holder) => {
+                        warn!("Recovering storage lock at {} left by pid {}, which is no longer running", dir.display(), holder.pid);
+                    }
+                    None => return Err(LockError::Unrecognized { dir: dir.to_path_buf() }),
+                }
+            }
+
+            file.set_len(0)
+                .and_then(|()| file.rewind())
+                .and_then(|()| file.write_all(Holder::current().to_file_contents().as_bytes()))
+                .and_then(|()| file.sync_all())
+                .map_err(io_error)?;
+            return Ok(Self { path, file });
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for StorageLock {
+    fn drop(&mut self) {
+        // Removed while still locked, unless --force has put another instance's lockfile there
+        if is_same_file(&self.file, &self.path) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Whether `path` still names the open `file`
+#[cfg(unix)]
+pub(crate) fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(named)) => open.dev() == named.dev() && open.ino() == named.ino(),
+        _ => false,
+    }
+}
+
+/// Open files can't be removed on Windows, so the path can't have been re-created
+#[cfg(not(unix))]
+pub(crate) fn is_same_file(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+/// Start time of a running process, the 22nd field of `/proc/<pid>/stat`
+fn process_start(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in field 2 may itself contain spaces and parentheses
+    let after_name = &stat[stat.rfind(')')? + 1..];
+    after_name.split_whitespace().nth(19)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_writer_fails_to_acquire_lock() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let first = StorageLock::acquire(dir.path(), false).unwrap();
+        let second = StorageLock::acquire(dir.path(), false);
+        assert!(matches!(second, Err(LockError::Held { pid, .. }) if pid == std::process::id()));
+        assert!(second.unwrap_err().to_string().contains("Another instance"));
+
+        // Readers never need the lock
+        assert!(StorageLock::for_mode(dir.path(), OpenMode::ReadOnly).unwrap().is_none());
+
+        drop(first);
+        assert!(!dir.path().join(StorageLock::FILE_NAME).exists());
+        let _again = StorageLock::acquire(dir.path(), false).unwrap();
+    }
+
+    #[test]
+    fn test_force_and_stale_locks_are_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let _held = StorageLock::acquire(dir.path(), false).unwrap();
+        let forced = StorageLock::for_mode(dir.path(), OpenMode::Write { force: true }).unwrap();
+        assert!(forced.is_some());
+
+        if Path::new("/proc").is_dir() {
+            let stale = tempfile::tempdir().unwrap();
+            std::fs::write(stale.path().join(StorageLock::FILE_NAME), u32::MAX.to_string()).unwrap();
+            assert!(StorageLock::acquire(stale.path(), false).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_dead_holder_is_recovered_and_live_holder_blocks() {
+        if !Path::new("/proc").is_dir() {
+            return;
+        }
+        let lockfile = |dir: &Path, holder: Holder| {
+            std::fs::write(dir.join(StorageLock::FILE_NAME), holder.to_file_contents()).unwrap();
+        };
+
+        // A crashed daemon's PID that nothing runs under any more
+        let dir = tempfile::tempdir().unwrap();
+        lockfile(dir.path(), Holder { pid: u32::MAX, started: Some(12) });
+        assert!(matches!(
+            StorageLock::acquire_with(dir.path(), false, &LockConfig { recover_stale: false }),
+            Err(LockError::Stale { pid: u32::MAX, .. })
+        ));
+        let recovered = StorageLock::acquire(dir.path(), false).unwrap();
+        let contents = std::fs::read_to_string(recovered.path()).unwrap();
+        assert_eq!(Holder::parse(&contents), Some(Holder::current()));
+        drop(recovered);
+
+        // The PID was reused by an unrelated process that started later
+        let current = Holder::current();
+        lockfile(dir.path(), Holder { started: current.started.map(|s| s + 1), ..current });
+        assert!(StorageLock::acquire(dir.path(), false).is_ok());
+
+        // A running holder still blocks
+        lockfile(dir.path(), current);
+        assert!(matches!(
+            StorageLock::acquire(dir.path(), false),
+            Err(LockError::Held { pid, .. }) if pid == current.pid
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_takeover_of_a_stale_lock_has_one_winner() {
+        if !Path::new("/proc").is_dir() {
+            return;
+        }
+        for _ in 0..20 {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join(StorageLock::FILE_NAME), Holder { pid: u32::MAX, started: Some(12) }.to_file_contents()).unwrap();
+
+            let barrier = std::sync::Barrier::new(8);
+            let results: Vec<_> = std::thread::scope(|scope| {
+                let takers: Vec<_> = (0..8)
+                    .map(|_| scope.spawn(|| {
+                        barrier.wait();
+                        StorageLock::acquire(dir.path(), false)
+                    }))
+                    .collect();
+                takers.into_iter().map(|taker| taker.join().unwrap()).collect()
+            });
+
+            assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+            assert!(results.iter().all(|result| matches!(result, Ok(_) | Err(LockError::Held { .. }))));
+        }
+    }
+
+    #[test]
+    fn test_unrecognized_lockfile_is_kept_unless_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let lockfile = dir.path().join(StorageLock::FILE_NAME);
+        std::fs::write(&lockfile, "held by a backup tool").unwrap();
+
+        assert!(matches!(StorageLock::acquire(dir.path(), false), Err(LockError::Unrecognized { .. })));
+        assert_eq!(std::fs::read_to_string(&lockfile).unwrap(), "held by a backup tool");
+        assert!(StorageLock::acquire(dir.path(), true).is_ok());
+    }
+
+    #[test]
+    fn test_lockfile_appears_with_its_contents_and_no_temp_is_left() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = StorageLock::acquire(dir.path(), false).unwrap();
+        let contents = std::fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(Holder::parse(&contents), Some(Holder::current()));
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, vec![std::ffi::OsString::from(StorageLock::FILE_NAME)]);
+    }
+}
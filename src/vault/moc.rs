@@ -0,0 +1,271 @@
+// src/vault/moc.rs - Maintain a "map of content" note per configured tag
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use crate::ai::backend::Backend;
+use crate::logger::Logger;
+use crate::vault::parser::ParsedDocument;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MocConfig {
+    pub enabled: bool,
+    /// Tags (with or without `#`) that get a map-of-content note
+    pub tags: Vec<String>,
+    /// Vault folder the MOC notes are written to
+    pub folder: PathBuf,
+    /// Token budget for each note's one-line description
+    pub description_tokens: usize,
+}
+
+impl Default for MocConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tags: Vec::new(),
+            folder: PathBuf::from("MOCs"),
+            description_tokens: 60,
+        }
+    }
+}
+
+/// Descriptions already generated for a tag, keyed by note path, so unchanged notes aren't re-described
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MocState {
+    entries: BTreeMap<PathBuf, MocEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MocEntry {
+    title: String,
+    checksum: String,
+    description: String,
+}
+
+#[derive(Debug, Default)]
+pub struct MocUpdate {
+    /// MOC notes whose content changed
+    pub written: Vec<PathBuf>,
+    /// Notes that needed a new description
+    pub described: usize,
+}
+
+pub struct MocGenerator {
+    config: MocConfig,
+    backend: Arc<dyn Backend>,
+    logger: Logger,
+}
+
+impl MocGenerator {
+    pub fn new(config: MocConfig, backend: Arc<dyn Backend>) -> Self {
+        Self {
+            config,
+            backend,
+            logger: Logger::new("MocGenerator"),
+        }
+    }
+
+    /// Path of the MOC note for `tag` inside the vault
+    pub fn note_path(&self, vault: &Path, tag: &str) -> PathBuf {
+        vault.join(&self.config.folder).join(format!("{} MOC.md", normalize_tag(tag)))
+    }
+
+    /// Bring every configured tag's MOC in line with `documents`; only notes that are new or
+    /// changed since the last run are sent to the LLM
+    pub async fn update(&self, vault: &Path, documents: &[ParsedDocument]) -> Result<MocUpdate> {
+        let mut update = MocUpdate::default();
+        if !self.config.enabled {
+            return Ok(update);
+        }
+
+        let moc_folder = vault.join(&self.config.folder);
+        for tag in &self.config.tags {
+            let tag = normalize_tag(tag);
+            let state_path = vault.join(".note-to-ai").join("moc").join(format!("{}.json", tag));
+            let previous = load_state(&state_path).await?;
+
+            let mut state = MocState::default();
+            for doc in documents.iter().filter(|d| !d.path.starts_with(&moc_folder)) {
+                if !doc.tags.iter().any(|t| normalize_tag(t) == tag) {
+                    continue;
+                }
+
+                let entry = match previous.entries.get(&doc.path) {
+                    Some(entry) if entry.checksum == doc.metadata.checksum => entry.clone(),
+                    _ => {
+                        update.described += 1;
+                        MocEntry {
+                            title: doc.title.clone(),
+                            checksum: doc.metadata.checksum.clone(),
+                            description: self.describe(doc).await?,
+                        }
+                    }
+                };
+                state.entries.insert(doc.path.clone(), entry);
+            }
+
+            let note_path = self.note_path(vault, tag);
+            let content = render_note(tag, &state);
+            let current = tokio::fs::read_to_string(&note_path).await.unwrap_or_default();
+            if current != content {
+                if let Some(parent) = note_path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&note_path, content).await
+                    .with_context(|| format!("Failed to write {}", note_path.display()))?;
+                update.written.push(note_path);
+            }
+            save_state(&state_path, &state).await?;
+        }
+
+        self.logger.info(&format!(
+            "Updated {} MOC notes, described {} notes",
+            update.written.len(),
+            update.described
+        ));
+        Ok(update)
+    }
+
+    async fn describe(&self, doc: &ParsedDocument) -> Result<String> {
+        let snippet: String = doc.plain_text.chars().take(800).collect();
+        let prompt = format!(
+            "Describe what this note is about in one short sentence.\n\nTitle: {}\n\n{}",
+            doc.title, snippet
+        );
+        let description = self.backend.generate(&prompt, self.config.description_tokens).await?;
+        Ok(description.lines().next().unwrap_or_default().trim().to_string())
+    }
+}
+
+fn normalize_tag(tag: &str) -> &str {
+    tag.trim().trim_start_matches('#')
+}
+
+/// Wikilinks sorted by title, each with its description
+fn render_note(tag: &str, state: &MocState) -> String {
+    let mut entries: Vec<(&PathBuf, &MocEntry)> = state.entries.iter().collect();
+    entries.sort_by(|a, b| a.1.title.to_lowercase().cmp(&b.1.title.to_lowercase()).then_with(|| a.0.cmp(b.0)));
+
+    let mut note = format!("---\nmoc: {}\n---\n\n# {} map of content\n\n", tag, tag);
+    if entries.is_empty() {
+        note.push_str("No notes are tagged yet.\n");
+    }
+    for (path, entry) in entries {
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or(&entry.title);
+        if entry.description.is_empty() {
+            note.push_str(&format!("- [[{}]]\n", name));
+        } else {
+            note.push_str(&format!("- [[{}]]: {}\n", name, entry.description));
+        }
+    }
+    note
+}
+
+async fn load_state(path: &Path) -> Result<MocState> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(MocState::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn save_state(path: &Path, state: &MocState) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, serde_json::to_string_pretty(state)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use async_trait::async_trait;
+    use crate::ai::backend::BackendKind;
+    use crate::vault::parser::ObsidianParser;
+
+    struct TitleBackend(AtomicUsize);
+
+    #[async_trait]
+    impl Backend for TitleBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Local
+        }
+
+        async fn generate(&self, prompt: &str, _max_tokens: usize) -> Result<String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let title = prompt.lines().find_map(|l| l.strip_prefix("Title: ")).unwrap_or_default();
+            Ok(format!("Notes on {}.", title.to_lowercase()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_moc_lists_current_tagged_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path();
+        let parser = ObsidianParser::new().unwrap();
+        let parse = |name: &'static str, body: &'static str| {
+            let parser = &parser;
+            async move { parser.parse_content(&vault.join(name), body).await.unwrap() }
+        };
+
+        let backend = Arc::new(TitleBackend(AtomicUsize::new(0)));
+        let config = MocConfig { enabled: true, tags: vec!["#garden".to_string()], ..Default::default() };
+        let generator = MocGenerator::new(config, backend.clone());
+
+        let documents = vec![
+            parse("Tomatoes.md", "# Tomatoes\n\nWater daily. #garden").await,
+            parse("Basil.md", "# Basil\n\nPinch the tops. #garden").await,
+            parse("Taxes.md", "# Taxes\n\nDue in April. #finance").await,
+        ];
+        let update = generator.update(vault, &documents).await.unwrap();
+        assert_eq!(update.described, 2);
+
+        let note = std::fs::read_to_string(generator.note_path(vault, "garden")).unwrap();
+        assert!(note.contains("- [[Basil]]: Notes on basil.\n- [[Tomatoes]]: Notes on tomatoes.\n"));
+        assert!(!note.contains("Taxes"));
+
+        // Basil loses the tag and a new note gains it; Tomatoes is unchanged and not re-described
+        let documents = vec![
+            documents[0].clone(),
+            parse("Basil.md", "# Basil\n\nPinch the tops.").await,
+            parse("Mint.md", "# Mint\n\nKeep it in a pot. #garden").await,
+        ];
+        let update = generator.update(vault, &documents).await.unwrap();
+        assert_eq!(update.described, 1);
+        assert_eq!(backend.0.load(Ordering::SeqCst), 3);
+
+        let note = std::fs::read_to_string(generator.note_path(vault, "garden")).unwrap();
+        assert!(note.contains("[[Mint]]") && note.contains("[[Tomatoes]]"));
+        assert!(!note.contains("[[Basil]]"));
+    }
+
+    #[tokio::test]
+    async fn test_indexing_a_tag_change_updates_the_moc() {
+        use crate::vault::indexer::VaultIndexer;
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("Tomatoes.md"), "# Tomatoes\n\nWater daily. #garden").unwrap();
+        std::fs::write(vault.join("Taxes.md"), "# Taxes\n\nDue in April. #finance").unwrap();
+
+        let config = MocConfig { enabled: true, tags: vec!["garden".to_string()], ..Default::default() };
+        let generator = Arc::new(MocGenerator::new(config, Arc::new(TitleBackend(AtomicUsize::new(0)))));
+        let indexer = VaultIndexer::new(dir.path().join("index.db"), vault.clone()).unwrap()
+            .with_moc(generator.clone());
+        indexer.initialize_db().await.unwrap();
+
+        indexer.full_index().await.unwrap();
+        let note = std::fs::read_to_string(generator.note_path(&vault, "garden")).unwrap();
+        assert!(note.contains("- [[Tomatoes]]: Notes on tomatoes.\n"));
+        assert!(!note.contains("Taxes"));
+
+        std::fs::write(vault.join("Basil.md"), "# Basil\n\nPinch the tops. #garden").unwrap();
+        indexer.incremental_index(vec![vault.join("Basil.md")]).await.unwrap();
+        let note = std::fs::read_to_string(generator.note_path(&vault, "garden")).unwrap();
+        assert!(note.contains("[[Basil]]") && note.contains("[[Tomatoes]]"));
+    }
+}
@@ -1,14 +1,40 @@
 // src/vault/mod.rs - Core vault functionality (hybrid storage temporarily disabled)
+pub mod batch;
 pub mod cache;
+pub mod capture_dedup;
+pub mod categorize;
+pub mod circuit_breaker;
 pub mod crdt;
+pub mod embedding_engine;
+pub mod embedding_pool;
 pub mod embeddings;
+pub mod export;
+pub mod focus;
+pub mod hierarchical;
+pub mod hnsw;
 pub mod indexer;
+pub mod language;
+pub mod ingest;
+pub mod lock;
+pub mod moc;
 pub mod parser;
+pub mod parsers;
+pub mod pii;
+pub mod qa;
+pub mod refresh;
+pub mod related;
+pub mod sealed;
 pub mod search;
+pub mod search_note;
+pub mod snippet;
+pub mod tag_graph;
+pub mod vacuum;
+pub mod warmup;
 // pub mod storage; // Temporarily disabled while fixing Arrow ecosystem
 
 use crate::Result;
 use std::path::PathBuf;
+use embeddings::{EmbeddingModelConfig, VaultEmbeddingRecord};
 
 pub struct Vault {
     // pub storage_engine: HybridStorageEngine, // Temporarily disabled
@@ -20,6 +46,12 @@ impl Vault {
         Ok(Self {})
     }
     
+    /// Open a vault, refusing it if its recorded embedding model differs from the configured one
+    pub async fn open(path: PathBuf, embedding: &EmbeddingModelConfig) -> Result<Self> {
+        VaultEmbeddingRecord::check_or_record(&path, embedding)?;
+        Self::new(path).await
+    }
+    
     /// Index a document (simplified implementation)
     pub async fn index_document(&self, _document_path: &PathBuf) -> Result<()> {
         // TODO: Re-implement with hybrid storage once Arrow conflicts resolved
@@ -3,10 +3,11 @@ use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
 use regex::Regex;
-use pulldown_cmark::{Parser, Event, Tag, TagEnd, CowStr};
+use pulldown_cmark::{Parser, Options, Event, Tag, TagEnd};
 use yaml_rust::{YamlLoader, Yaml};
 use chrono::{DateTime, Utc, NaiveDateTime};
 use crate::logger::Logger;
+use crate::vault::parsers::ParserConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedDocument {
@@ -22,7 +23,7 @@ pub struct ParsedDocument {
     pub metadata: DocumentMetadata,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Frontmatter {
     pub title: Option<String>,
     pub tags: Vec<String>,
@@ -36,9 +37,63 @@ pub struct Frontmatter {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Link {
     pub link_type: LinkType,
+    /// The linked page, without any heading or block anchor
     pub target: String,
     pub alias: Option<String>,
     pub position: TextPosition,
+    #[serde(default)]
+    pub resolution: LinkResolution,
+    /// `Section` in `[[Note#Section]]`
+    #[serde(default)]
+    pub heading: Option<String>,
+    /// `abc123` in `[[Note^abc123]]` or `[[Note#^abc123]]`
+    #[serde(default)]
+    pub block_id: Option<String>,
+}
+
+impl Link {
+    /// The anchor as written after the page: `Section` for a heading, `^abc123` for a block
+    pub fn anchor(&self) -> Option<String> {
+        match (&self.block_id, &self.heading) {
+            (Some(block_id), _) => Some(format!("^{}", block_id)),
+            (None, Some(heading)) => Some(heading.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Split a wikilink target into its page, heading and block id
+fn split_wikilink_target(raw: &str) -> (String, Option<String>, Option<String>) {
+    let non_empty = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+    if let Some((page, anchor)) = raw.split_once('#') {
+        return match anchor.strip_prefix('^') {
+            Some(block_id) => (page.trim().to_string(), None, non_empty(block_id)),
+            None => (page.trim().to_string(), non_empty(anchor), None),
+        };
+    }
+    match raw.split_once('^') {
+        Some((page, block_id)) => (page.trim().to_string(), None, non_empty(block_id)),
+        None => (raw.trim().to_string(), None, None),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkResolution {
+    Resolved(PathBuf),
+    #[default]
+    Unresolved,
+    External,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkResolutionConfig {
+    pub case_insensitive: bool,
+}
+
+impl Default for LinkResolutionConfig {
+    fn default() -> Self {
+        Self { case_insensitive: true }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,8 +121,9 @@ pub struct Block {
     pub metadata: Option<HashMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub enum BlockType {
+    #[default]
     Paragraph,
     Heading(u8),
     CodeBlock(Option<String>), // language
@@ -77,6 +133,8 @@ pub enum BlockType {
     Callout(String), // callout type
     Math,
     Embed,
+    Symbol { kind: String, name: String }, // function, struct, class... in a code file
+    Task { checked: bool }, // `- [ ]` / `- [x]` list item
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,7 +151,41 @@ pub struct DocumentMetadata {
     pub char_count: usize,
     pub reading_time_minutes: usize,
     pub last_parsed: DateTime<Utc>,
+    /// The file's modification time, when it was parsed from disk
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
     pub checksum: String,
+    /// Programming language of code files
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl ParsedDocument {
+    /// When the note last changed: its frontmatter `modified` date, else the file's
+    /// modification time, else when it was parsed
+    pub fn modified_at(&self) -> DateTime<Utc> {
+        self.frontmatter.as_ref()
+            .and_then(|frontmatter| frontmatter.modified)
+            .or(self.metadata.modified)
+            .unwrap_or(self.metadata.last_parsed)
+    }
+
+    /// Title, aliases, and tags as one short text, embedded separately from the body
+    pub fn title_text(&self) -> String {
+        let mut parts = vec![self.title.clone()];
+
+        if let Some(frontmatter) = &self.frontmatter {
+            if let Some(title) = &frontmatter.title {
+                if title != &self.title {
+                    parts.push(title.clone());
+                }
+            }
+            parts.extend(frontmatter.aliases.iter().cloned());
+        }
+        parts.extend(self.tags.iter().cloned());
+
+        parts.join("\n")
+    }
 }
 
 pub struct ObsidianParser {
@@ -103,10 +195,15 @@ pub struct ObsidianParser {
     callout_regex: Regex,
     math_regex: Regex,
     embed_regex: Regex,
+    reading_wpm: usize,
 }
 
 impl ObsidianParser {
     pub fn new() -> Result<Self> {
+        Self::with_config(&ParserConfig::default())
+    }
+
+    pub fn with_config(config: &ParserConfig) -> Result<Self> {
         let wikilink_regex = Regex::new(r"\[\[([^\]|]+)(\|([^\]]+))?\]\]")?;
         let tag_regex = Regex::new(r"(?:^|\s)#([a-zA-Z0-9_/-]+)")?;
         let callout_regex = Regex::new(r"^>\s*\[!(\w+)\]([+-]?)\s*(.*)")?;
@@ -120,6 +217,7 @@ impl ObsidianParser {
             callout_regex,
             math_regex,
             embed_regex,
+            reading_wpm: config.reading_wpm.max(1),
         })
     }
 
@@ -127,7 +225,9 @@ impl ObsidianParser {
         let content = tokio::fs::read_to_string(path).await
             .context("Failed to read file")?;
 
-        self.parse_content(path, &content).await
+        let mut document = self.parse_content(path, &content).await?;
+        document.metadata.modified = tokio::fs::metadata(path).await?.modified().ok().map(DateTime::<Utc>::from);
+        Ok(document)
     }
 
     pub async fn parse_content(&self, path: &Path, content: &str) -> Result<ParsedDocument> {
@@ -147,7 +247,9 @@ impl ObsidianParser {
             char_count: plain_text.len(),
             reading_time_minutes: self.estimate_reading_time(&plain_text),
             last_parsed: Utc::now(),
+            modified: None,
             checksum: self.calculate_checksum(content),
+            language: None,
         };
 
         Ok(ParsedDocument {
@@ -379,7 +481,7 @@ impl ObsidianParser {
         // Extract wikilinks
         for cap in self.wikilink_regex.captures_iter(content) {
             let full_match = cap.get(0).unwrap();
-            let target = cap.get(1).unwrap().as_str().to_string();
+            let (target, heading, block_id) = split_wikilink_target(cap.get(1).unwrap().as_str());
             let alias = cap.get(3).map(|m| m.as_str().to_string());
             
             let link_type = if alias.is_some() {
@@ -388,54 +490,65 @@ impl ObsidianParser {
                 LinkType::WikiLink
             };
 
-            let text_position = self.calculate_position(content, full_match.start());
+            let text_position = self.calculate_position(content, full_match.start(), full_match.end());
             
             links.push(Link {
                 link_type,
                 target,
                 alias,
                 position: text_position,
+                resolution: LinkResolution::Unresolved,
+                heading,
+                block_id,
             });
         }
 
         // Extract embed links
         for cap in self.embed_regex.captures_iter(content) {
             let full_match = cap.get(0).unwrap();
-            let target = cap.get(1).unwrap().as_str().to_string();
+            let (target, heading, block_id) = split_wikilink_target(cap.get(1).unwrap().as_str());
             
-            let text_position = self.calculate_position(content, full_match.start());
+            let text_position = self.calculate_position(content, full_match.start(), full_match.end());
             
             links.push(Link {
                 link_type: LinkType::EmbedLink,
                 target,
                 alias: None,
                 position: text_position,
+                resolution: LinkResolution::Unresolved,
+                heading,
+                block_id,
             });
         }
 
         // Extract markdown links using pulldown-cmark
-        let parser = Parser::new(content);
-        let mut current_pos = 0;
+        let parser = Parser::new(content).into_offset_iter();
         
-        for event in parser {
+        for (event, range) in parser {
             match event {
                 Event::Start(Tag::Link { dest_url, .. }) => {
                     let url = dest_url.to_string();
                     if url.starts_with("http") {
-                        let text_position = self.calculate_position(content, current_pos);
+                        let text_position = self.calculate_position(content, range.start, range.end);
                         links.push(Link {
                             link_type: LinkType::ExternalLink,
                             target: url,
                             alias: None,
                             position: text_position,
+                            resolution: LinkResolution::External,
+                            heading: None,
+                            block_id: None,
                         });
                     } else {
-                        let text_position = self.calculate_position(content, current_pos);
+                        let text_position = self.calculate_position(content, range.start, range.end);
                         links.push(Link {
                             link_type: LinkType::MarkdownLink,
                             target: url,
                             alias: None,
                             position: text_position,
+                            resolution: LinkResolution::Unresolved,
+                            heading: None,
+                            block_id: None,
                         });
                     }
                 }
@@ -468,12 +581,14 @@ impl ObsidianParser {
 
     fn extract_headings(&self, content: &str) -> Vec<Heading> {
         let mut headings = Vec::new();
-        let parser = Parser::new(content);
+        let parser = Parser::new(content).into_offset_iter();
         let mut current_heading_level = 0;
         let mut current_heading_text = String::new();
-        let mut current_pos = 0;
+        // Ids handed out so far, and how many times each slug has been seen
+        let mut used_ids = HashSet::new();
+        let mut slug_counts: HashMap<String, usize> = HashMap::new();
 
-        for event in parser {
+        for (event, range) in parser {
             match event {
                 Event::Start(Tag::Heading { level, .. }) => {
                     current_heading_level = level as u8;
@@ -481,8 +596,16 @@ impl ObsidianParser {
                 }
                 Event::End(TagEnd::Heading(_)) => {
                     if !current_heading_text.is_empty() {
-                        let id = self.generate_heading_id(&current_heading_text);
-                        let text_position = self.calculate_position(content, current_pos);
+                        let slug = self.generate_heading_id(&current_heading_text);
+                        // Repeats get `-1`, `-2`, ... in document order, skipping ids already taken
+                        let count = slug_counts.entry(slug.clone()).or_insert(0);
+                        let mut id = slug.clone();
+                        while used_ids.contains(&id) {
+                            *count += 1;
+                            id = format!("{}-{}", slug, count);
+                        }
+                        used_ids.insert(id.clone());
+                        let text_position = self.calculate_position(content, range.start, range.end);
                         
                         headings.push(Heading {
                             level: current_heading_level,
@@ -507,13 +630,17 @@ impl ObsidianParser {
 
     fn extract_blocks(&self, content: &str) -> Result<Vec<Block>> {
         let mut blocks = Vec::new();
-        let parser = Parser::new(content);
-        let mut current_pos = 0;
+        // End events carry the byte range of the whole element
+        let parser = Parser::new_ext(content, Options::ENABLE_TASKLISTS).into_offset_iter();
         let mut in_code_block = false;
         let mut code_lang: Option<String> = None;
         let mut current_content = String::new();
+        // Open list items as (start, task state, own text), innermost last
+        let mut items: Vec<(usize, Option<bool>, String)> = Vec::new();
+        // Per open list, whether it has an item that isn't a task
+        let mut lists: Vec<bool> = Vec::new();
 
-        for event in parser {
+        for (event, range) in parser {
             match event {
                 Event::Start(tag) => {
                     let block_type = match tag {
@@ -530,8 +657,15 @@ impl ObsidianParser {
                             Some(BlockType::CodeBlock(code_lang.clone()))
                         }
                         Tag::BlockQuote => Some(BlockType::Quote),
-                        Tag::List(_) => Some(BlockType::List),
+                        Tag::List(_) => {
+                            lists.push(false);
+                            Some(BlockType::List)
+                        }
                         Tag::Table(_) => Some(BlockType::Table),
+                        Tag::Item => {
+                            items.push((range.start, None, String::new()));
+                            None
+                        }
                         _ => None,
                     };
 
@@ -540,10 +674,41 @@ impl ObsidianParser {
                         // We'll add the block when we see the end tag
                     }
                 }
+                Event::End(TagEnd::Item) => {
+                    let Some((start, task, text)) = items.pop() else { continue };
+                    match task {
+                        Some(checked) => {
+                            // The item's text belongs to the task, not the surrounding list
+                            if current_content.ends_with(&text) {
+                                current_content.truncate(current_content.len() - text.len());
+                            }
+                            blocks.push(Block {
+                                block_type: BlockType::Task { checked },
+                                content: text.trim().to_string(),
+                                position: self.calculate_position(content, start, range.end),
+                                metadata: None,
+                            });
+                        }
+                        None => {
+                            if let Some(has_plain_item) = lists.last_mut() {
+                                *has_plain_item = true;
+                            }
+                        }
+                    }
+                }
+                Event::TaskListMarker(checked) => {
+                    if let Some(item) = items.last_mut() {
+                        item.1 = Some(checked);
+                    }
+                }
                 Event::End(tag_end) => {
                     let should_add_block = match tag_end {
-                        TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock 
-                        | TagEnd::BlockQuote | TagEnd::List(_) | TagEnd::Table => true,
+                        // A task's paragraphs are part of its Task block
+                        TagEnd::Paragraph => !matches!(items.last(), Some((_, Some(_), _))),
+                        // A list of nothing but tasks is fully covered by its Task blocks
+                        TagEnd::List(_) => lists.pop().unwrap_or(true),
+                        TagEnd::Heading(_) | TagEnd::CodeBlock
+                        | TagEnd::BlockQuote | TagEnd::Table => true,
                         _ => false,
                     };
 
@@ -561,7 +726,7 @@ impl ObsidianParser {
                             _ => BlockType::Paragraph,
                         };
 
-                        let text_position = self.calculate_position(content, current_pos);
+                        let text_position = self.calculate_position(content, range.start, range.end);
                         
                         blocks.push(Block {
                             block_type,
@@ -573,8 +738,16 @@ impl ObsidianParser {
                 }
                 Event::Text(text) => {
                     current_content.push_str(&text);
+                    if let Some(item) = items.last_mut() {
+                        item.2.push_str(&text);
+                    }
                 }
                 Event::Code(text) => {
+                    if let Some(item) = items.last_mut() {
+                        item.2.push('`');
+                        item.2.push_str(&text);
+                        item.2.push('`');
+                    }
                     if !in_code_block {
                         current_content.push('`');
                         current_content.push_str(&text);
@@ -594,17 +767,16 @@ impl ObsidianParser {
     }
 
     fn extract_callout_blocks(&self, content: &str, blocks: &mut Vec<Block>) {
-        for (line_idx, line) in content.lines().enumerate() {
+        let mut line_start = 0;
+        for line in content.split_inclusive('\n') {
+            let start = line_start;
+            line_start += line.len();
+            let line = line.trim_end_matches(['\r', '\n']);
             if let Some(cap) = self.callout_regex.captures(line) {
                 let callout_type = cap.get(1).unwrap().as_str().to_string();
                 let content_text = cap.get(3).map(|m| m.as_str()).unwrap_or("").to_string();
                 
-                let text_position = TextPosition {
-                    start: 0, // Approximate
-                    end: line.len(),
-                    line: line_idx + 1,
-                    column: 0,
-                };
+                let text_position = self.calculate_position(content, start, start + line.len());
 
                 blocks.push(Block {
                     block_type: BlockType::Callout(callout_type),
@@ -637,13 +809,14 @@ impl ObsidianParser {
         text.split_whitespace().collect::<Vec<_>>().join(" ")
     }
 
-    fn calculate_position(&self, content: &str, byte_pos: usize) -> TextPosition {
+    /// Byte range `start..end` of `content`, with the 1-based line and column of `start`
+    fn calculate_position(&self, content: &str, start: usize, end: usize) -> TextPosition {
         let mut line = 1;
         let mut column = 1;
         let mut current_pos = 0;
 
         for ch in content.chars() {
-            if current_pos >= byte_pos {
+            if current_pos >= start {
                 break;
             }
             
@@ -658,8 +831,8 @@ impl ObsidianParser {
         }
 
         TextPosition {
-            start: byte_pos,
-            end: byte_pos,
+            start,
+            end,
             line,
             column,
         }
@@ -679,9 +852,8 @@ impl ObsidianParser {
     }
 
     fn estimate_reading_time(&self, text: &str) -> usize {
-        const AVERAGE_WPM: usize = 200;
         let word_count = self.count_words(text);
-        (word_count + AVERAGE_WPM - 1) / AVERAGE_WPM // Ceiling division
+        word_count.div_ceil(self.reading_wpm)
     }
 
     fn calculate_checksum(&self, content: &str) -> String {
@@ -697,3 +869,281 @@ impl Default for ObsidianParser {
         Self::new().expect("Failed to create ObsidianParser")
     }
 }
+
+/// Resolves parsed links against the set of documents in a vault
+pub struct LinkResolver {
+    names: HashMap<String, PathBuf>,
+    paths: Vec<(String, PathBuf)>,
+    config: LinkResolutionConfig,
+}
+
+impl LinkResolver {
+    pub fn new(documents: &[ParsedDocument], config: LinkResolutionConfig) -> Self {
+        let mut resolver = Self {
+            names: HashMap::new(),
+            paths: Vec::new(),
+            config,
+        };
+
+        for doc in documents {
+            resolver.add_path(&doc.path);
+            if let Some(fm) = &doc.frontmatter {
+                for alias in &fm.aliases {
+                    resolver.insert_name(alias, &doc.path);
+                }
+            }
+        }
+
+        resolver
+    }
+
+    /// Make a file that wasn't parsed, such as an unchanged note or an attachment, a link target
+    /// by its name and path; documents passed to `new` take precedence
+    pub fn add_path(&mut self, path: &Path) {
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            self.insert_name(name, path);
+        }
+        if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+            self.insert_name(stem, path);
+        }
+
+        let without_ext = path.with_extension("");
+        let key = self.normalize(&without_ext.to_string_lossy());
+        self.paths.push((key, path.to_path_buf()));
+    }
+
+    fn insert_name(&mut self, name: &str, path: &Path) {
+        let key = self.normalize(name);
+        // First document wins so resolution is stable across runs
+        self.names.entry(key).or_insert_with(|| path.to_path_buf());
+    }
+
+    fn normalize(&self, name: &str) -> String {
+        let name = name.trim().trim_start_matches("./").trim_start_matches('/');
+        if self.config.case_insensitive {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Resolve a single link to a vault document
+    pub fn resolve(&self, link: &Link) -> LinkResolution {
+        if matches!(link.link_type, LinkType::ExternalLink) || link.target.contains("://") {
+            return LinkResolution::External;
+        }
+
+        // Markdown links keep their fragment: [text](Note.md#heading) resolves to Note
+        let target = link.target.split('#').next().unwrap_or("");
+        if target.trim().is_empty() {
+            return LinkResolution::Unresolved;
+        }
+
+        let key = self.normalize(target);
+        if let Some(path) = self.names.get(&key) {
+            return LinkResolution::Resolved(path.clone());
+        }
+
+        let key = key.strip_suffix(".md").unwrap_or(&key);
+        if let Some(path) = self.names.get(key) {
+            return LinkResolution::Resolved(path.clone());
+        }
+
+        // Path-style targets like [[folder/Note]] match on the trailing components
+        if key.contains('/') {
+            let suffix = format!("/{}", key);
+            for (doc_key, path) in &self.paths {
+                if doc_key == key || doc_key.ends_with(&suffix) {
+                    return LinkResolution::Resolved(path.clone());
+                }
+            }
+        }
+
+        LinkResolution::Unresolved
+    }
+
+    /// Run the resolution pass over every link in the given documents
+    pub fn resolve_all(&self, documents: &mut [ParsedDocument]) {
+        for doc in documents.iter_mut() {
+            for link in &mut doc.links {
+                // [[#Heading]] and [[^block]] point into the linking note itself
+                link.resolution = if link.target.is_empty() && link.anchor().is_some() {
+                    LinkResolution::Resolved(doc.path.clone())
+                } else {
+                    self.resolve(link)
+                };
+            }
+        }
+    }
+
+    /// Links that point at no document in the vault
+    pub fn broken_links(documents: &[ParsedDocument]) -> Vec<(PathBuf, Link)> {
+        documents.iter()
+            .flat_map(|doc| {
+                doc.links.iter()
+                    .filter(|link| link.resolution == LinkResolution::Unresolved)
+                    .map(move |link| (doc.path.clone(), link.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(parser: &ObsidianParser, path: &str, content: &str) -> ParsedDocument {
+        parser.parse_content(Path::new(path), content).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_link_resolution() {
+        let parser = ObsidianParser::new().unwrap();
+
+        let mut docs = vec![
+            parse(&parser, "notes/Project.md", "# Project\n\nSee [[meeting notes]] and [[Missing Page]].").await,
+            parse(&parser, "notes/Meeting Notes.md", "---\naliases: [standup]\n---\nBack to [[Project#Goals]].").await,
+            parse(&parser, "daily/Today.md", "Had a [[standup]], read [docs](https://example.com).").await,
+        ];
+
+        let resolver = LinkResolver::new(&docs, LinkResolutionConfig::default());
+        resolver.resolve_all(&mut docs);
+
+        let resolution = |doc: usize, target: &str| {
+            docs[doc].links.iter()
+                .find(|l| l.target == target)
+                .map(|l| l.resolution.clone())
+                .unwrap()
+        };
+
+        // Case-insensitive filename match
+        assert_eq!(resolution(0, "meeting notes"), LinkResolution::Resolved(PathBuf::from("notes/Meeting Notes.md")));
+        // Heading anchors are split off the page for resolution
+        assert_eq!(resolution(1, "Project"), LinkResolution::Resolved(PathBuf::from("notes/Project.md")));
+        assert_eq!(docs[1].links[0].heading.as_deref(), Some("Goals"));
+        // Frontmatter alias
+        assert_eq!(resolution(2, "standup"), LinkResolution::Resolved(PathBuf::from("notes/Meeting Notes.md")));
+        assert_eq!(resolution(2, "https://example.com"), LinkResolution::External);
+        assert_eq!(resolution(0, "Missing Page"), LinkResolution::Unresolved);
+
+        let broken = LinkResolver::broken_links(&docs);
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].1.target, "Missing Page");
+    }
+
+    #[tokio::test]
+    async fn test_block_positions_cover_their_source() {
+        let parser = ObsidianParser::new().unwrap();
+        let content = "# Garden\n\nWater the tomatoes.\n\n```rust\nfn main() {}\n```\n\n## Basil\n\nPinch the tops — often.\n";
+        let doc = parse(&parser, "Garden.md", content).await;
+
+        assert_eq!(doc.blocks.len(), 5);
+        let mut previous_end = 0;
+        for block in &doc.blocks {
+            let position = &block.position;
+            assert!(position.start >= previous_end && position.end > position.start, "{:?}", block);
+            assert!(content[position.start..position.end].contains(block.content.lines().next().unwrap()));
+            previous_end = position.end;
+        }
+
+        let lines: Vec<(usize, usize)> = doc.blocks.iter().map(|b| (b.position.line, b.position.column)).collect();
+        assert_eq!(lines, [(1, 1), (3, 1), (5, 1), (9, 1), (11, 1)]);
+        assert_eq!(&content[doc.headings[1].position.start..doc.headings[1].position.end], "## Basil\n");
+    }
+
+    #[tokio::test]
+    async fn test_case_sensitive_resolution() {
+        let parser = ObsidianParser::new().unwrap();
+
+        let mut docs = vec![
+            parse(&parser, "Project.md", "# Project").await,
+            parse(&parser, "Index.md", "[[project]]").await,
+        ];
+
+        let resolver = LinkResolver::new(&docs, LinkResolutionConfig { case_insensitive: false });
+        resolver.resolve_all(&mut docs);
+
+        assert_eq!(docs[1].links[0].resolution, LinkResolution::Unresolved);
+    }
+
+    #[tokio::test]
+    async fn test_heading_and_block_anchors_are_split_from_the_target() {
+        let parser = ObsidianParser::new().unwrap();
+        let doc = parse(&parser, "Index.md", "See [[Note#Section]], [[Note#Section|the plan]], [[Note^abc123]] and [[#Intro]].").await;
+
+        let anchors: Vec<(&str, Option<&str>, Option<&str>, Option<&str>)> = doc.links.iter()
+            .map(|l| (l.target.as_str(), l.heading.as_deref(), l.block_id.as_deref(), l.alias.as_deref()))
+            .collect();
+        assert_eq!(anchors, [
+            ("Note", Some("Section"), None, None),
+            ("Note", Some("Section"), None, Some("the plan")),
+            ("Note", None, Some("abc123"), None),
+            ("", Some("Intro"), None, None),
+        ]);
+        assert_eq!(doc.links[2].anchor().as_deref(), Some("^abc123"));
+
+        let mut docs = vec![parse(&parser, "Note.md", "# Section").await, doc];
+        LinkResolver::new(&docs, LinkResolutionConfig::default()).resolve_all(&mut docs);
+        let resolved: Vec<&LinkResolution> = docs[1].links.iter().map(|l| &l.resolution).collect();
+        assert_eq!(resolved[..3], [&LinkResolution::Resolved(PathBuf::from("Note.md")); 3]);
+        assert_eq!(resolved[3], &LinkResolution::Resolved(PathBuf::from("Index.md")));
+    }
+
+    #[tokio::test]
+    async fn test_task_items_become_task_blocks() {
+        let parser = ObsidianParser::new().unwrap();
+        let tasks = |doc: &ParsedDocument| -> Vec<(String, Option<bool>)> {
+            doc.blocks.iter().map(|b| match b.block_type {
+                BlockType::Task { checked } => (b.content.clone(), Some(checked)),
+                _ => (b.content.clone(), None),
+            }).collect()
+        };
+
+        let unchecked = parse(&parser, "Todo.md", "- [ ] Water the `tomatoes`\n- [ ] Buy seeds\n").await;
+        assert_eq!(tasks(&unchecked), [
+            ("Water the `tomatoes`".to_string(), Some(false)),
+            ("Buy seeds".to_string(), Some(false)),
+        ]);
+        let position = &unchecked.blocks[1].position;
+        assert_eq!((position.line, position.column), (2, 1));
+
+        let checked = parse(&parser, "Done.md", "- [x] Water the tomatoes\n\n- [X] Buy seeds\n").await;
+        assert_eq!(tasks(&checked), [
+            ("Water the tomatoes".to_string(), Some(true)),
+            ("Buy seeds".to_string(), Some(true)),
+        ]);
+
+        let mixed = parse(&parser, "Mixed.md", "- [x] Water the tomatoes\n- Compost\n- [ ] Buy seeds\n").await;
+        assert_eq!(tasks(&mixed), [
+            ("Water the tomatoes".to_string(), Some(true)),
+            ("Buy seeds".to_string(), Some(false)),
+            ("Compost".to_string(), None),
+        ]);
+        assert_eq!(mixed.blocks[2].block_type, BlockType::List);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_headings_get_unique_ids() {
+        let parser = ObsidianParser::new().unwrap();
+        let content = "# Notes\n\n## Notes\n\n## Notes 1\n\n### Notes\n";
+        let ids = |doc: &ParsedDocument| doc.headings.iter().map(|h| h.id.clone()).collect::<Vec<_>>();
+
+        let doc = parse(&parser, "Journal.md", content).await;
+        assert_eq!(ids(&doc), ["notes", "notes-1", "notes-1-1", "notes-2"]);
+        assert_eq!(ids(&parse(&parser, "Journal.md", content).await), ids(&doc));
+    }
+
+    #[tokio::test]
+    async fn test_reading_time_follows_configured_wpm() {
+        let content = "word ".repeat(450);
+        let reading_time = |wpm: usize| {
+            let parser = ObsidianParser::with_config(&ParserConfig { reading_wpm: wpm, ..ParserConfig::default() }).unwrap();
+            let content = content.clone();
+            async move { parse(&parser, "Dense.md", &content).await.metadata.reading_time_minutes }
+        };
+
+        assert_eq!(reading_time(200).await, 3);
+        assert_eq!(reading_time(100).await, 5);
+        assert_eq!(reading_time(450).await, 1);
+    }
+}
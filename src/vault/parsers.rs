@@ -0,0 +1,384 @@
+// src/vault/parsers.rs - Per-file-type document parsers the indexer dispatches through
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::vault::indexer::FileType;
+use crate::vault::pii::PiiScanner;
+use crate::vault::parser::{Block, BlockType, DocumentMetadata, ObsidianParser, ParsedDocument, TextPosition};
+
+/// Turns a file's bytes into a `ParsedDocument`
+#[async_trait]
+pub trait DocumentParser: Send + Sync {
+    async fn parse(&self, path: &Path, bytes: &[u8]) -> Result<ParsedDocument>;
+}
+
+#[async_trait]
+impl DocumentParser for ObsidianParser {
+    async fn parse(&self, path: &Path, bytes: &[u8]) -> Result<ParsedDocument> {
+        self.parse_content(path, &String::from_utf8_lossy(bytes)).await
+    }
+}
+
+/// Built-in parsers an extension can be mapped to in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParserKind {
+    Markdown,
+    PlainText,
+    Code,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParserConfig {
+    /// Extra extensions (without the dot) and the parser that handles them, e.g. `org = "plain_text"`
+    #[serde(default)]
+    pub extensions: HashMap<String, ParserKind>,
+    /// Words per minute behind a note's reading time; lower it for dense technical notes
+    #[serde(default = "default_reading_wpm")]
+    pub reading_wpm: usize,
+}
+
+fn default_reading_wpm() -> usize {
+    200
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            extensions: HashMap::new(),
+            reading_wpm: default_reading_wpm(),
+        }
+    }
+}
+
+/// Paragraphs of a text file, split on blank lines
+pub struct PlainTextParser;
+
+#[async_trait]
+impl DocumentParser for PlainTextParser {
+    async fn parse(&self, path: &Path, bytes: &[u8]) -> Result<ParsedDocument> {
+        let text = String::from_utf8_lossy(bytes).into_owned();
+
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        let mut line = 1;
+        for paragraph in text.split("\n\n") {
+            if !paragraph.trim().is_empty() {
+                blocks.push(Block {
+                    block_type: BlockType::Paragraph,
+                    content: paragraph.trim().to_string(),
+                    position: TextPosition { start: offset, end: offset + paragraph.len(), line, column: 0 },
+                    metadata: None,
+                });
+            }
+            offset += paragraph.len() + 2;
+            line += paragraph.matches('\n').count() + 2;
+        }
+
+        Ok(document(path, text, blocks, Vec::new(), None))
+    }
+}
+
+/// Source files split into one block per symbol, tagged with the detected language
+pub struct CodeParser;
+
+impl CodeParser {
+    /// Language from the extension, falling back to a `#!` interpreter line
+    pub fn detect_language(path: &Path, text: &str) -> Option<String> {
+        let by_extension = path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|ext| match ext.to_lowercase().as_str() {
+                "rs" => Some("rust"),
+                "py" => Some("python"),
+                "js" | "mjs" | "cjs" => Some("javascript"),
+                "ts" | "tsx" => Some("typescript"),
+                "go" => Some("go"),
+                "java" => Some("java"),
+                "c" | "h" => Some("c"),
+                "cpp" | "cc" | "hpp" => Some("cpp"),
+                "rb" => Some("ruby"),
+                "sh" | "bash" => Some("shell"),
+                "swift" => Some("swift"),
+                "kt" => Some("kotlin"),
+                _ => None,
+            });
+        if let Some(language) = by_extension {
+            return Some(language.to_string());
+        }
+
+        let interpreter = text.lines().next()?.strip_prefix("#!")?;
+        let program = interpreter.split_whitespace()
+            .flat_map(|part| part.rsplit('/').next())
+            .find(|name| *name != "env")?;
+        match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+            "python" => Some("python".to_string()),
+            "node" => Some("javascript".to_string()),
+            "ruby" => Some("ruby".to_string()),
+            "sh" | "bash" | "zsh" => Some("shell".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Functions, types and other definitions found with per-language line patterns,
+    /// as (kind, name, byte offset of the defining line), in source order
+    pub fn extract_symbols(language: &str, text: &str) -> Vec<(String, String, usize)> {
+        let mut symbols = Vec::new();
+        for (pattern, fixed_kind) in symbol_patterns(language) {
+            let regex = match Regex::new(pattern) {
+                Ok(regex) => regex,
+                Err(_) => continue,
+            };
+            for captures in regex.captures_iter(text) {
+                let (Some(whole), Some(name)) = (captures.get(0), captures.name("name")) else {
+                    continue;
+                };
+                let kind = fixed_kind
+                    .or_else(|| captures.name("kind").map(|k| k.as_str()))
+                    .unwrap_or("symbol");
+                let line_start = text[..whole.start()].rfind('\n').map_or(0, |i| i + 1);
+                symbols.push((normalize_kind(kind).to_string(), name.as_str().to_string(), line_start));
+            }
+        }
+        symbols.sort_by_key(|(_, _, offset)| *offset);
+        symbols.dedup_by_key(|(_, _, offset)| *offset);
+        symbols
+    }
+}
+
+/// Line patterns with `name` (and optionally `kind`) groups, plus a kind for patterns without one
+fn symbol_patterns(language: &str) -> Vec<(&'static str, Option<&'static str>)> {
+    match language {
+        "rust" => vec![(
+            r"(?m)^[ \t]*(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe)\s+)*(?P<kind>fn|struct|enum|trait|mod|type|impl)\b(?:<[^>]*>)?\s+(?:[\w:]+(?:<[^>]*>)?\s+for\s+)?(?P<name>[A-Za-z_]\w*)",
+            None,
+        )],
+        "python" => vec![(r"(?m)^[ \t]*(?:async\s+)?(?P<kind>def|class)\s+(?P<name>[A-Za-z_]\w*)", None)],
+        "javascript" | "typescript" => vec![
+            (
+                r"(?m)^[ \t]*(?:export\s+)?(?:default\s+)?(?:async\s+)?(?P<kind>function|class|interface|type)\*?\s+(?P<name>[A-Za-z_$][\w$]*)",
+                None,
+            ),
+            (
+                r"(?m)^[ \t]*(?:export\s+)?(?:const|let)\s+(?P<name>[A-Za-z_$][\w$]*)\s*=\s*(?:async\s+)?(?:\([^)]*\)|[A-Za-z_$][\w$]*)\s*=>",
+                Some("function"),
+            ),
+        ],
+        "go" => vec![
+            (r"(?m)^func\s+(?:\([^)]*\)\s*)?(?P<name>[A-Za-z_]\w*)", Some("function")),
+            (r"(?m)^type\s+(?P<name>[A-Za-z_]\w*)", Some("type")),
+        ],
+        "ruby" => vec![(r"(?m)^[ \t]*(?P<kind>def|class|module)\s+(?:self\.)?(?P<name>[A-Za-z_]\w*[?!]?)", None)],
+        "shell" => vec![(r"(?m)^[ \t]*(?:function\s+)?(?P<name>[A-Za-z_][\w-]*)\s*\(\)\s*\{", Some("function"))],
+        _ => Vec::new(),
+    }
+}
+
+fn normalize_kind(kind: &str) -> &str {
+    match kind {
+        "fn" | "def" | "func" => "function",
+        other => other,
+    }
+}
+
+#[async_trait]
+impl DocumentParser for CodeParser {
+    async fn parse(&self, path: &Path, bytes: &[u8]) -> Result<ParsedDocument> {
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        let language = Self::detect_language(path, &text);
+        let symbols = language.as_deref()
+            .map(|language| Self::extract_symbols(language, &text))
+            .unwrap_or_default();
+
+        let line_of = |offset: usize| text[..offset].matches('\n').count() + 1;
+        let mut blocks = Vec::new();
+
+        // Imports and other code before the first symbol, or the whole file when nothing was found
+        let preamble_end = symbols.first().map_or(text.len(), |(_, _, offset)| *offset);
+        if !text[..preamble_end].trim().is_empty() {
+            blocks.push(Block {
+                block_type: BlockType::CodeBlock(language.clone()),
+                content: text[..preamble_end].to_string(),
+                position: TextPosition { start: 0, end: preamble_end, line: 1, column: 0 },
+                metadata: None,
+            });
+        }
+
+        // Each symbol runs until the next one starts
+        for (i, (kind, name, start)) in symbols.iter().enumerate() {
+            let end = symbols.get(i + 1).map_or(text.len(), |(_, _, next)| *next);
+            blocks.push(Block {
+                block_type: BlockType::Symbol { kind: kind.clone(), name: name.clone() },
+                content: text[*start..end].to_string(),
+                position: TextPosition { start: *start, end, line: line_of(*start), column: 0 },
+                metadata: None,
+            });
+        }
+
+        let tags = language.iter().cloned().collect();
+        Ok(document(path, text, blocks, tags, language))
+    }
+}
+
+fn document(path: &Path, text: String, blocks: Vec<Block>, tags: Vec<String>, language: Option<String>) -> ParsedDocument {
+    let word_count = text.split_whitespace().count();
+    ParsedDocument {
+        path: path.to_path_buf(),
+        title: path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string(),
+        plain_text: text.clone(),
+        frontmatter: None,
+        links: Vec::new(),
+        tags,
+        headings: Vec::new(),
+        blocks,
+        metadata: DocumentMetadata {
+            word_count,
+            char_count: text.len(),
+            reading_time_minutes: word_count.div_ceil(200),
+            last_parsed: Utc::now(),
+            modified: None,
+            checksum: blake3::hash(text.as_bytes()).to_string(),
+            language,
+        },
+        content: text,
+    }
+}
+
+/// Parsers by file type, with per-extension overrides taking precedence
+#[derive(Clone, Default)]
+pub struct ParserRegistry {
+    by_type: HashMap<FileType, Arc<dyn DocumentParser>>,
+    by_extension: HashMap<String, Arc<dyn DocumentParser>>,
+    pii: Option<Arc<PiiScanner>>,
+}
+
+impl ParserRegistry {
+    /// Obsidian for markdown, plain text for text files, and the code parser for source files
+    pub fn with_defaults() -> Result<Self> {
+        let mut registry = Self::default();
+        registry.register(FileType::Markdown, Arc::new(ObsidianParser::new()?));
+        registry.register(FileType::Text, Arc::new(PlainTextParser));
+        registry.register(FileType::Code, Arc::new(CodeParser));
+        Ok(registry)
+    }
+
+    pub fn from_config(config: &ParserConfig) -> Result<Self> {
+        let mut registry = Self::with_defaults()?;
+        registry.register(FileType::Markdown, Arc::new(ObsidianParser::with_config(config)?));
+        for (extension, kind) in &config.extensions {
+            let parser: Arc<dyn DocumentParser> = match kind {
+                ParserKind::Markdown => Arc::new(ObsidianParser::with_config(config)?),
+                ParserKind::PlainText => Arc::new(PlainTextParser),
+                ParserKind::Code => Arc::new(CodeParser),
+            };
+            registry.register_extension(extension, parser);
+        }
+        Ok(registry)
+    }
+
+    /// Mark parsed documents containing PII; `None` turns scanning off
+    pub fn with_pii_scanner(mut self, scanner: Option<PiiScanner>) -> Self {
+        self.pii = scanner.map(Arc::new);
+        self
+    }
+
+    pub fn register(&mut self, file_type: FileType, parser: Arc<dyn DocumentParser>) {
+        self.by_type.insert(file_type, parser);
+    }
+
+    pub fn register_extension(&mut self, extension: &str, parser: Arc<dyn DocumentParser>) {
+        self.by_extension.insert(extension.trim_start_matches('.').to_lowercase(), parser);
+    }
+
+    pub fn parser_for(&self, path: &Path) -> Option<Arc<dyn DocumentParser>> {
+        let extension = path.extension().and_then(|e| e.to_str())?.to_lowercase();
+        self.by_extension.get(&extension)
+            .or_else(|| self.by_type.get(&FileType::from_extension(&extension)))
+            .cloned()
+    }
+
+    /// Parse with the parser registered for the path, or `None` when no parser handles it
+    pub async fn parse(&self, path: &Path, bytes: &[u8]) -> Result<Option<ParsedDocument>> {
+        let Some(parser) = self.parser_for(path) else {
+            return Ok(None);
+        };
+
+        let mut parsed = parser.parse(path, bytes).await?;
+        if let Some(scanner) = &self.pii {
+            scanner.mark(&mut parsed);
+        }
+        Ok(Some(parsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::vault::indexer::VaultIndexer;
+
+    struct RecipeParser(AtomicUsize);
+
+    #[async_trait]
+    impl DocumentParser for RecipeParser {
+        async fn parse(&self, path: &Path, bytes: &[u8]) -> Result<ParsedDocument> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let mut parsed = PlainTextParser.parse(path, bytes).await?;
+            parsed.tags.push("recipe".to_string());
+            Ok(parsed)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_parser_handles_new_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("pancakes.recipe"), "Flour, eggs, milk.\n\nWhisk and fry.").unwrap();
+        std::fs::write(vault.join("notes.md"), "# Notes\n\nSee [[pancakes]].").unwrap();
+
+        let recipes = Arc::new(RecipeParser(AtomicUsize::new(0)));
+        let mut registry = ParserRegistry::with_defaults().unwrap();
+        registry.register_extension(".recipe", recipes.clone());
+
+        let parsed = registry.parse(&vault.join("pancakes.recipe"), b"Flour").await.unwrap().unwrap();
+        assert!(parsed.tags.contains(&"recipe".to_string()));
+        assert!(registry.parse(&vault.join("photo.png"), b"").await.unwrap().is_none());
+
+        let indexer = VaultIndexer::new(dir.path().join("index.db"), vault)
+            .unwrap()
+            .with_parsers(registry);
+        indexer.initialize_db().await.unwrap();
+        let stats = indexer.full_index().await.unwrap();
+
+        assert_eq!(stats.added, 2);
+        assert_eq!(recipes.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_code_language_detection() {
+        assert_eq!(CodeParser::detect_language(Path::new("main.rs"), "").as_deref(), Some("rust"));
+        assert_eq!(
+            CodeParser::detect_language(Path::new("deploy"), "#!/usr/bin/env python3\nprint()").as_deref(),
+            Some("python")
+        );
+        assert_eq!(CodeParser::detect_language(Path::new("README"), "hello"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rust_definitions_become_symbol_blocks() {
+        let source = "use std::fmt;\n\npub struct Greenhouse {\n    temperature: f32,\n}\n\nimpl Greenhouse {\n    pub fn water_tomatoes(&self) {}\n\n    async fn open_vents(&mut self) {}\n}\n";
+        let parsed = CodeParser.parse(Path::new("greenhouse.rs"), source.as_bytes()).await.unwrap();
+        assert_eq!(parsed.metadata.language.as_deref(), Some("rust"));
+
+        let names: Vec<_> = parsed.blocks.iter().filter_map(|block| match &block.block_type {
+            BlockType::Symbol { name, .. } => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(names, ["Greenhouse", "Greenhouse", "water_tomatoes", "open_vents"]);
+    }
+}
@@ -0,0 +1,159 @@
+// src/vault/pii.rs - Cheap regex scan that marks notes containing personal information
+use anyhow::{Result, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::vault::parser::{Frontmatter, ParsedDocument};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PiiPattern {
+    /// Recorded as the kind of PII found, e.g. `passport`
+    pub name: String,
+    pub regex: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PiiConfig {
+    pub enabled: bool,
+    /// Tag added to matching notes; the kinds found go in the frontmatter field of the same name
+    pub tag: String,
+    pub detect_emails: bool,
+    pub detect_phone_numbers: bool,
+    pub detect_card_numbers: bool,
+    /// Extra patterns checked alongside the built-in ones
+    pub patterns: Vec<PiiPattern>,
+}
+
+impl Default for PiiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tag: "pii".to_string(),
+            detect_emails: true,
+            detect_phone_numbers: true,
+            detect_card_numbers: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+pub struct PiiScanner {
+    tag: String,
+    patterns: Vec<(String, Regex)>,
+    cards: Option<Regex>,
+}
+
+impl PiiScanner {
+    /// `None` when scanning is disabled
+    pub fn from_config(config: &PiiConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let mut patterns = Vec::new();
+        if config.detect_emails {
+            patterns.push(("email".to_string(), Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}")?));
+        }
+        if config.detect_phone_numbers {
+            patterns.push((
+                "phone".to_string(),
+                Regex::new(r"(?:\+\d{1,3}[\s.-]?)?\(?\b\d{3}\)?[\s.-]?\d{3}[\s.-]?\d{4}\b")?,
+            ));
+        }
+        for pattern in &config.patterns {
+            let regex = Regex::new(&pattern.regex)
+                .with_context(|| format!("Invalid PII pattern '{}'", pattern.name))?;
+            patterns.push((pattern.name.clone(), regex));
+        }
+
+        let cards = if config.detect_card_numbers {
+            Some(Regex::new(r"\b(?:\d[ -]?){12,18}\d\b")?)
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            tag: config.tag.trim_start_matches('#').to_string(),
+            patterns,
+            cards,
+        }))
+    }
+
+    /// Kinds of PII found in `text`, in pattern order
+    pub fn scan(&self, text: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        // Card numbers are also digit runs a phone pattern could match, so they're checked on their own
+        if let Some(cards) = &self.cards {
+            if cards.find_iter(text).any(|m| luhn_valid(m.as_str())) {
+                found.push("card".to_string());
+            }
+        }
+        for (name, regex) in &self.patterns {
+            if regex.is_match(text) && !found.contains(name) {
+                found.push(name.clone());
+            }
+        }
+        found
+    }
+
+    /// Tag the document and record the kinds found in its frontmatter; returns whether any PII was found
+    pub fn mark(&self, document: &mut ParsedDocument) -> bool {
+        let found = self.scan(&document.content);
+        if found.is_empty() {
+            return false;
+        }
+
+        if !document.tags.contains(&self.tag) {
+            document.tags.push(self.tag.clone());
+        }
+        document.frontmatter
+            .get_or_insert_with(Frontmatter::default)
+            .custom_fields
+            .insert(self.tag.clone(), serde_json::json!(found));
+        true
+    }
+}
+
+/// Luhn checksum, so order numbers and other long digit runs aren't taken for cards
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+
+    let sum: u32 = digits.iter().rev().enumerate().map(|(i, &d)| {
+        if !i.is_multiple_of(2) {
+            let doubled = d * 2;
+            if doubled > 9 { doubled - 9 } else { doubled }
+        } else {
+            d
+        }
+    }).sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::vault::parser::ObsidianParser;
+
+    #[tokio::test]
+    async fn test_note_with_email_is_marked_and_clean_note_is_not() {
+        let scanner = PiiScanner::from_config(&PiiConfig { enabled: true, ..Default::default() }).unwrap().unwrap();
+        let parser = ObsidianParser::new().unwrap();
+
+        let mut contact = parser.parse_content(Path::new("Contact.md"), "# Contact\n\nReach Sam at sam@example.com.").await.unwrap();
+        let mut clean = parser.parse_content(Path::new("Garden.md"), "# Garden\n\nWater the tomatoes.").await.unwrap();
+
+        assert!(scanner.mark(&mut contact));
+        assert!(contact.tags.contains(&"pii".to_string()));
+        assert_eq!(contact.frontmatter.unwrap().custom_fields["pii"], serde_json::json!(["email"]));
+
+        assert!(!scanner.mark(&mut clean));
+        assert!(!clean.tags.contains(&"pii".to_string()));
+
+        assert_eq!(scanner.scan("Card 4111 1111 1111 1111"), ["card"]);
+        assert!(scanner.scan("Order 1234 5678 9012 3456").is_empty());
+        assert!(PiiScanner::from_config(&PiiConfig::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,209 @@
+// src/vault/qa.rs - Question/answer pairs found in notes, answered directly before full retrieval
+use std::collections::HashSet;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::vault::parser::{BlockType, ParsedDocument};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QaExtractionConfig {
+    pub enabled: bool,
+    /// Longer answers are cut here; the source note holds the rest
+    pub max_answer_chars: usize,
+    /// Similarity between the query and a stored question needed to answer directly
+    pub min_similarity: f32,
+}
+
+impl Default for QaExtractionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_answer_chars: 600,
+            min_similarity: 0.85,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QaPair {
+    pub question: String,
+    pub answer: String,
+    pub source: PathBuf,
+    /// Byte offset of the question in the source note
+    pub start_pos: usize,
+}
+
+/// A stored pair answering a query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QaMatch {
+    pub pair: QaPair,
+    pub similarity: f32,
+}
+
+impl QaExtractionConfig {
+    /// Pairs written as `Q:`/`A:` lines, and headings phrased as questions with the section
+    /// below as the answer
+    pub fn extract(&self, document: &ParsedDocument) -> Vec<QaPair> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let mut pairs = marked_pairs(document);
+        pairs.extend(heading_pairs(document));
+
+        let mut seen = HashSet::new();
+        pairs.retain(|pair| seen.insert(pair.question.to_lowercase()));
+        for pair in &mut pairs {
+            if let Some((end, _)) = pair.answer.char_indices().nth(self.max_answer_chars) {
+                pair.answer.truncate(end);
+                pair.answer.push('…');
+            }
+        }
+        pairs.sort_by_key(|pair| pair.start_pos);
+        pairs
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Marker {
+    Question,
+    Answer,
+}
+
+/// `Q: ...` / `Question: ...` and `A: ...` / `Answer: ...`, optionally bold or in a list
+fn marker(line: &str) -> Option<(Marker, &str)> {
+    let line = line.trim_start_matches(['-', '*', '>', ' ']);
+    let (label, rest) = line.split_once(':')?;
+    let marker = match label.trim_end_matches('*').to_lowercase().as_str() {
+        "q" | "question" => Marker::Question,
+        "a" | "answer" => Marker::Answer,
+        _ => return None,
+    };
+    Some((marker, rest.trim_start_matches('*').trim()))
+}
+
+fn marked_pairs(document: &ParsedDocument) -> Vec<QaPair> {
+    let mut pairs = Vec::new();
+    let mut question: Option<(String, usize)> = None;
+    let mut answer: Option<String> = None;
+    let mut flush = |question: &mut Option<(String, usize)>, answer: &mut Option<String>| {
+        if let (Some((text, start_pos)), Some(body)) = (question.take(), answer.take()) {
+            if !text.is_empty() && !body.trim().is_empty() {
+                pairs.push(QaPair { question: text, answer: body.trim().to_string(), source: document.path.clone(), start_pos });
+            }
+        }
+    };
+
+    let mut offset = 0;
+    for line in document.content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let line = line.trim();
+
+        match marker(line) {
+            Some((Marker::Question, text)) => {
+                flush(&mut question, &mut answer);
+                question = Some((text.to_string(), start));
+            }
+            Some((Marker::Answer, text)) if question.is_some() => answer = Some(text.to_string()),
+            // A blank line or a heading ends the answer; other lines continue it
+            _ if line.is_empty() || line.starts_with('#') => flush(&mut question, &mut answer),
+            _ => match (&mut answer, &mut question) {
+                (Some(body), _) => {
+                    body.push('\n');
+                    body.push_str(line);
+                }
+                // A question can wrap onto several lines before its answer
+                (None, Some((text, _))) => {
+                    text.push(' ');
+                    text.push_str(line);
+                }
+                (None, None) => {}
+            },
+        }
+    }
+    flush(&mut question, &mut answer);
+    pairs
+}
+
+fn heading_pairs(document: &ParsedDocument) -> Vec<QaPair> {
+    let mut pairs = Vec::new();
+    for (i, block) in document.blocks.iter().enumerate() {
+        let BlockType::Heading(level) = block.block_type else {
+            continue;
+        };
+        let question = block.content.trim();
+        if !question.ends_with('?') {
+            continue;
+        }
+
+        let answer: Vec<&str> = document.blocks[i + 1..].iter()
+            .take_while(|b| !matches!(b.block_type, BlockType::Heading(l) if l <= level))
+            .filter(|b| !matches!(b.block_type, BlockType::Heading(_) | BlockType::Embed))
+            .map(|b| b.content.trim())
+            .filter(|text| !text.is_empty())
+            .collect();
+        if !answer.is_empty() {
+            pairs.push(QaPair {
+                question: question.to_string(),
+                answer: answer.join("\n"),
+                source: document.path.clone(),
+                start_pos: block.position.start,
+            });
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use crate::test_support::BagOfWords;
+    use crate::vault::batch::BatchIndexer;
+    use crate::vault::embedding_pool::{EmbeddingPool, RetryConfig};
+    use crate::vault::parser::ObsidianParser;
+    use crate::vault::search::VectorSearchEngine;
+
+    const NOTE: &str = "# Home\n\n**Q:** What is the wifi password?\n**A:** It is on the router, hunter2.\n\n## How often do I water the ficus?\n\nOnce a week, less in winter.\n";
+
+    #[tokio::test]
+    async fn test_note_with_qa_is_answered_by_question_similarity() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Hashed(256)));
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let document = parser.parse_content(Path::new("home.md"), NOTE).await.unwrap();
+
+        let config = QaExtractionConfig::default();
+        let pairs = config.extract(&document);
+        assert_eq!(pairs.iter().map(|p| p.question.as_str()).collect::<Vec<_>>(), [
+            "What is the wifi password?",
+            "How often do I water the ficus?",
+        ]);
+        assert_eq!(pairs[0].answer, "It is on the router, hunter2.");
+
+        let pool = Arc::new(EmbeddingPool::new(vec![BagOfWords::Hashed(256)]).unwrap());
+        let indexed = BatchIndexer::new(&engine, &pool, "test")
+            .with_qa(&config)
+            .index(&[document], &RetryConfig::default())
+            .await
+            .unwrap();
+        assert!(indexed.errors.is_empty());
+
+        let hit = engine.direct_answer("what is the wifi password", config.min_similarity).await.unwrap().unwrap();
+        assert_eq!(hit.pair.answer, "It is on the router, hunter2.");
+        assert_eq!(hit.pair.source, Path::new("home.md"));
+        assert!(engine.direct_answer("best pizza in town", config.min_similarity).await.unwrap().is_none());
+
+        // Re-indexing the edited note replaces its pairs
+        let edited = parser.parse_content(Path::new("home.md"), "# Home\n\nNo questions here.\n").await.unwrap();
+        BatchIndexer::new(&engine, &pool, "test")
+            .with_qa(&config)
+            .index(&[edited], &RetryConfig::default())
+            .await
+            .unwrap();
+        assert!(engine.direct_answer("what is the wifi password", config.min_similarity).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,192 @@
+// src/vault/refresh.rs - Re-embed notes whose vectors came from an older version of the model
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::scheduler::Scheduler;
+use crate::vault::batch::BatchIndexer;
+use crate::vault::embedding_pool::{EmbeddingPool, EmbeddingWorker, RetryConfig};
+use crate::vault::parser::ObsidianParser;
+use crate::vault::search::VectorSearchEngine;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshMode {
+    /// Re-embed a stale note when a search returns it
+    #[default]
+    Lazy,
+    /// Also work through every stale note in the background
+    Eager,
+    Off,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingRefreshConfig {
+    pub mode: RefreshMode,
+    /// Notes re-embedded per background pass
+    pub batch_size: usize,
+    pub interval_secs: u64,
+}
+
+impl Default for EmbeddingRefreshConfig {
+    fn default() -> Self {
+        Self {
+            mode: RefreshMode::Lazy,
+            batch_size: 16,
+            interval_secs: 60,
+        }
+    }
+}
+
+/// Re-indexes stale notes from their files through an engine set to the current model version
+pub struct EmbeddingRefresher<W: EmbeddingWorker> {
+    engine: Arc<VectorSearchEngine>,
+    pool: Arc<EmbeddingPool<W>>,
+    model_name: String,
+    vault_path: PathBuf,
+    config: EmbeddingRefreshConfig,
+    parser: ObsidianParser,
+    logger: Logger,
+}
+
+impl<W: EmbeddingWorker + 'static> EmbeddingRefresher<W> {
+    pub fn new(
+        engine: Arc<VectorSearchEngine>,
+        pool: Arc<EmbeddingPool<W>>,
+        model_name: &str,
+        vault_path: &Path,
+        config: EmbeddingRefreshConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            engine,
+            pool,
+            model_name: model_name.to_string(),
+            vault_path: vault_path.to_path_buf(),
+            config,
+            parser: ObsidianParser::new()?,
+            logger: Logger::new("EmbeddingRefresher"),
+        })
+    }
+
+    /// Re-embed whichever of `paths`, e.g. the results of a search, are stale
+    pub async fn on_access(&self, paths: &[PathBuf]) -> Result<usize> {
+        if self.config.mode == RefreshMode::Off {
+            return Ok(0);
+        }
+        let mut stale = Vec::new();
+        for path in paths {
+            if self.engine.is_stale(path).await? {
+                stale.push(path.clone());
+            }
+        }
+        self.refresh(&stale).await
+    }
+
+    /// Re-embed the next batch of stale notes; returns how many were refreshed
+    pub async fn refresh_stale(&self) -> Result<usize> {
+        let stale = self.engine.stale_documents(self.config.batch_size.max(1)).await?;
+        self.refresh(&stale).await
+    }
+
+    /// Refresh a batch every `interval_secs` in eager mode
+    pub fn schedule(self: &Arc<Self>, scheduler: &Scheduler) {
+        if self.config.mode != RefreshMode::Eager {
+            return;
+        }
+        let refresher = Arc::clone(self);
+        scheduler.add_interval_task("embedding refresh", Duration::from_secs(self.config.interval_secs.max(1)), move || {
+            let refresher = refresher.clone();
+            async move {
+                refresher.refresh_stale().await?;
+                Ok(())
+            }
+        });
+    }
+
+    async fn refresh(&self, paths: &[PathBuf]) -> Result<usize> {
+        if paths.is_empty() {
+            return Ok(0);
+        }
+
+        let mut documents = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = self.vault_path.join(path);
+            match tokio::fs::read_to_string(&file).await {
+                // Parsed under the stored path so the new vector replaces the old one
+                Ok(content) => documents.push(self.parser.parse_content(path, &content).await?),
+                // A deleted note would stay stale forever
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    self.engine.remove_document(path).await?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let indexed = BatchIndexer::new(&self.engine, &self.pool, &self.model_name)
+            .index(&documents, &RetryConfig::default())
+            .await?;
+        for error in &indexed.errors {
+            self.logger.warn(&format!("Embedding refresh failed: {}", error));
+        }
+        if indexed.documents_processed > 0 {
+            self.logger.info(&format!("Refreshed {} embeddings", indexed.documents_processed));
+        }
+        Ok(indexed.documents_processed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    /// Every text gets the same vector, so the stored one shows which model version wrote it
+    struct Version(f32);
+
+    #[async_trait]
+    impl EmbeddingWorker for Version {
+        async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+            Ok(vec![self.0, 1.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accessed_documents_are_reembedded_after_a_version_bump() {
+        let vault = tempfile::tempdir().unwrap();
+        let db = vault.path().join("search.db");
+        let parser = ObsidianParser::new().unwrap();
+        let mut documents = Vec::new();
+        for name in ["Garden.md", "Taxes.md"] {
+            let content = format!("# {}\n\nSome notes.", name);
+            std::fs::write(vault.path().join(name), &content).unwrap();
+            documents.push(parser.parse_content(Path::new(name), &content).await.unwrap());
+        }
+
+        let old = VectorSearchEngine::new(db.clone()).unwrap().with_model_version("1");
+        old.initialize().await.unwrap();
+        let old_pool = Arc::new(EmbeddingPool::new(vec![Version(1.0)]).unwrap());
+        BatchIndexer::new(&old, &old_pool, "test").index(&documents, &RetryConfig::default()).await.unwrap();
+
+        let engine = Arc::new(VectorSearchEngine::new(db).unwrap().with_model_version("2"));
+        engine.initialize().await.unwrap();
+        assert_eq!(engine.stale_documents(10).await.unwrap().len(), 2);
+
+        let pool = Arc::new(EmbeddingPool::new(vec![Version(2.0)]).unwrap());
+        let refresher = EmbeddingRefresher::new(engine.clone(), pool, "test", vault.path(), EmbeddingRefreshConfig::default()).unwrap();
+        assert_eq!(refresher.on_access(&[PathBuf::from("Garden.md")]).await.unwrap(), 1);
+        // Already current, so a second access does nothing
+        assert_eq!(refresher.on_access(&[PathBuf::from("Garden.md")]).await.unwrap(), 0);
+
+        let vectors = engine.stored_document_embeddings().await.unwrap();
+        assert_eq!(vectors, [
+            ("Garden.md".to_string(), vec![2.0, 1.0]),
+            ("Taxes.md".to_string(), vec![1.0, 1.0]),
+        ]);
+        assert_eq!(engine.stale_documents(10).await.unwrap(), [PathBuf::from("Taxes.md")]);
+
+        assert_eq!(refresher.refresh_stale().await.unwrap(), 1);
+        assert!(engine.stale_documents(10).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+// src/vault/related.rs - Suggest wikilinks between semantically similar notes
+use std::collections::HashSet;
+use std::path::PathBuf;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::vault::parser::{LinkResolution, LinkResolutionConfig, LinkResolver, ParsedDocument};
+use crate::vault::embeddings::cosine_similarity;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelatedLinksConfig {
+    /// Minimum cosine similarity for a note to be suggested
+    pub threshold: f32,
+    /// Suggestions kept per note, most similar first
+    pub max_suggestions: usize,
+    /// Write suggestions into the note instead of only reporting them
+    pub auto_insert: bool,
+    /// Heading of the section suggestions are inserted under
+    pub heading: String,
+}
+
+impl Default for RelatedLinksConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.8,
+            max_suggestions: 5,
+            auto_insert: false,
+            heading: "Related".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkSuggestion {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub similarity: f32,
+}
+
+impl LinkSuggestion {
+    /// Wikilink text for the target, e.g. `[[Meeting Notes]]`
+    pub fn wikilink(&self) -> String {
+        let name = self.target.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        format!("[[{}]]", name)
+    }
+}
+
+pub struct LinkSuggester {
+    config: RelatedLinksConfig,
+    links: LinkResolutionConfig,
+    logger: Logger,
+}
+
+impl LinkSuggester {
+    pub fn new(config: RelatedLinksConfig, links: LinkResolutionConfig) -> Self {
+        Self {
+            config,
+            links,
+            logger: Logger::new("LinkSuggester"),
+        }
+    }
+
+    /// Suggest links for every note; `embeddings[i]` is the vector for `documents[i]`.
+    /// Notes already linked in either direction and the note itself are never suggested.
+    pub fn suggest(&self, documents: &[ParsedDocument], embeddings: &[Vec<f32>]) -> Vec<LinkSuggestion> {
+        let resolver = LinkResolver::new(documents, self.links.clone());
+
+        let mut linked: HashSet<(PathBuf, PathBuf)> = HashSet::new();
+        for doc in documents {
+            for link in &doc.links {
+                if let LinkResolution::Resolved(target) = resolver.resolve(link) {
+                    linked.insert((target.clone(), doc.path.clone()));
+                    linked.insert((doc.path.clone(), target));
+                }
+            }
+        }
+
+        let mut suggestions = Vec::new();
+        for (i, doc) in documents.iter().enumerate() {
+            let mut candidates: Vec<LinkSuggestion> = documents.iter()
+                .enumerate()
+                .filter(|(j, other)| *j != i && other.path != doc.path)
+                .filter(|(_, other)| !linked.contains(&(doc.path.clone(), other.path.clone())))
+                .filter_map(|(j, other)| {
+                    let similarity = cosine_similarity(&embeddings[i], &embeddings[j]);
+                    (similarity >= self.config.threshold).then(|| LinkSuggestion {
+                        source: doc.path.clone(),
+                        target: other.path.clone(),
+                        similarity,
+                    })
+                })
+                .collect();
+
+            candidates.sort_by(|a, b| {
+                b.similarity.partial_cmp(&a.similarity)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a.target.cmp(&b.target))
+            });
+            candidates.truncate(self.config.max_suggestions);
+            suggestions.extend(candidates);
+        }
+
+        self.logger.info(&format!("Suggested {} links across {} notes", suggestions.len(), documents.len()));
+        suggestions
+    }
+
+    /// Add suggested wikilinks for one note under the configured "Related" heading
+    pub fn insert_related(&self, content: &str, suggestions: &[LinkSuggestion]) -> String {
+        let missing: Vec<String> = suggestions.iter()
+            .map(|s| s.wikilink())
+            .filter(|link| !content.contains(link.as_str()))
+            .collect();
+        if missing.is_empty() {
+            return content.to_string();
+        }
+
+        let items: String = missing.iter().map(|link| format!("- {}\n", link)).collect();
+        let heading = format!("## {}", self.config.heading);
+        let mut lines: Vec<&str> = content.lines().collect();
+
+        match lines.iter().position(|line| line.trim() == heading) {
+            Some(start) => {
+                // Append to the end of the existing section
+                let end = lines[start + 1..]
+                    .iter()
+                    .position(|line| line.starts_with('#'))
+                    .map(|offset| start + 1 + offset)
+                    .unwrap_or(lines.len());
+                let mut insert_at = end;
+                while insert_at > start + 1 && lines[insert_at - 1].trim().is_empty() {
+                    insert_at -= 1;
+                }
+                let items = items.trim_end();
+                lines.insert(insert_at, items);
+                let mut updated = lines.join("\n");
+                updated.push('\n');
+                updated
+            }
+            None => format!("{}\n\n{}\n{}", content.trim_end(), heading, items),
+        }
+    }
+
+    /// Write suggestions into their source notes; returns the number of notes changed
+    pub async fn apply(&self, suggestions: &[LinkSuggestion]) -> Result<usize> {
+        // `suggest` emits each note's suggestions together
+        let mut sources: Vec<&PathBuf> = suggestions.iter().map(|s| &s.source).collect();
+        sources.dedup();
+
+        let mut changed = 0;
+        for source in sources {
+            let for_source: Vec<LinkSuggestion> = suggestions.iter()
+                .filter(|s| &s.source == source)
+                .cloned()
+                .collect();
+
+            let content = tokio::fs::read_to_string(source).await
+                .with_context(|| format!("Failed to read {}", source.display()))?;
+            let updated = self.insert_related(&content, &for_source);
+            if updated != content {
+                tokio::fs::write(source, updated).await?;
+                changed += 1;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::vault::parser::ObsidianParser;
+
+    #[tokio::test]
+    async fn test_suggests_similar_unlinked_notes_only() {
+        let parser = ObsidianParser::new().unwrap();
+        let documents = vec![
+            parser.parse_content(Path::new("Sourdough.md"), "# Sourdough\n\nFeeding the starter.").await.unwrap(),
+            parser.parse_content(Path::new("Bread Baking.md"), "# Bread Baking\n\nOven spring.").await.unwrap(),
+            parser.parse_content(Path::new("Starter.md"), "# Starter\n\nSee [[Sourdough]].").await.unwrap(),
+            parser.parse_content(Path::new("Taxes.md"), "# Taxes\n\nDeadlines.").await.unwrap(),
+        ];
+        let embeddings = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.95, 0.05, 0.0],
+            vec![0.97, 0.02, 0.0],
+            vec![0.0, 0.0, 1.0],
+        ];
+
+        let suggester = LinkSuggester::new(RelatedLinksConfig::default(), LinkResolutionConfig::default());
+        let suggestions = suggester.suggest(&documents, &embeddings);
+        let suggested = |from: &str, to: &str| {
+            suggestions.iter().any(|s| s.source == Path::new(from) && s.target == Path::new(to))
+        };
+
+        assert!(suggested("Sourdough.md", "Bread Baking.md"));
+        assert!(suggested("Bread Baking.md", "Sourdough.md"));
+        // Already linked in one direction, so neither side gets a suggestion
+        assert!(!suggested("Starter.md", "Sourdough.md"));
+        assert!(!suggested("Sourdough.md", "Starter.md"));
+        assert!(!suggestions.iter().any(|s| s.source == s.target));
+        assert!(!suggestions.iter().any(|s| s.target == Path::new("Taxes.md")));
+
+        let content = "# Sourdough\n\nFeeding the starter.\n";
+        let for_note: Vec<_> = suggestions.iter().filter(|s| s.source == Path::new("Sourdough.md")).cloned().collect();
+        let updated = suggester.insert_related(content, &for_note);
+        assert!(updated.ends_with("## Related\n- [[Bread Baking]]\n"));
+        assert_eq!(suggester.insert_related(&updated, &for_note), updated);
+    }
+}
@@ -0,0 +1,154 @@
+// src/vault/sealed.rs - The index database encrypted at rest, decrypted to a working copy while open
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use tracing::warn;
+use crate::crypto::Crypto;
+use super::lock::is_same_file;
+
+/// SQLite needs a plain file, so the sealed database at `path` is decrypted beside it for as
+/// long as the first process to open it runs, then encrypted back over `path` and removed.
+/// Processes opening it meanwhile share that working copy
+pub struct SealedDatabase {
+    sealed: PathBuf,
+    working: PathBuf,
+    crypto: Crypto,
+    /// Locked while this process owns the working copy; `None` when sharing another's
+    owner: Option<File>,
+}
+
+impl SealedDatabase {
+    /// Decrypt `path` for use; a plaintext database there is encrypted when sealed
+    pub fn open(path: &Path, crypto: Crypto) -> Result<Self> {
+        let mut working = path.as_os_str().to_owned();
+        working.push(".open");
+        let working = PathBuf::from(working);
+
+        loop {
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&working)
+                .with_context(|| format!("Failed to open {}", working.display()))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    return Ok(Self { sealed: path.to_path_buf(), working, crypto, owner: None });
+                }
+                Err(TryLockError::Error(e)) => return Err(e).with_context(|| format!("Failed to lock {}", working.display())),
+            }
+            // Its last owner may have sealed and removed it between our open and lock
+            if !is_same_file(&file, &working) {
+                continue;
+            }
+
+            if file.metadata()?.len() > 0 {
+                warn!("Keeping {}, left open by a run that didn't finish; it is newer than {}", working.display(), path.display());
+            } else if path.exists() {
+                let unsealed = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+                    .and_then(|data| match Crypto::is_encrypted(&data) {
+                        true => crypto.decrypt(&data).with_context(|| format!("Failed to decrypt {}", path.display())),
+                        false => Ok(data),
+                    })
+                    .and_then(|plain| {
+                        file.write_all(&plain).and_then(|()| file.sync_all())
+                            .with_context(|| format!("Failed to write {}", working.display()))
+                    });
+                if let Err(e) = unsealed {
+                    let _ = std::fs::remove_file(&working);
+                    return Err(e);
+                }
+            }
+            return Ok(Self { sealed: path.to_path_buf(), working, crypto, owner: Some(file) });
+        }
+    }
+
+    /// Where the database can be opened while this is held
+    pub fn working_path(&self) -> &Path {
+        &self.working
+    }
+
+    /// Encrypt the working copy over the sealed file and remove it; only the owner seals, and
+    /// dropping seals too
+    pub fn seal(mut self) -> Result<()> {
+        self.seal_owned()
+    }
+
+    fn seal_owned(&mut self) -> Result<()> {
+        let Some(file) = self.owner.take() else {
+            return Ok(());
+        };
+        if std::fs::metadata(&self.working).is_ok_and(|working| working.len() > 0) {
+            self.crypto.encrypt_file(&self.working, &self.sealed)?;
+        }
+        std::fs::remove_file(&self.working).with_context(|| format!("Failed to remove {}", self.working.display()))?;
+        drop(file);
+        Ok(())
+    }
+}
+
+impl Drop for SealedDatabase {
+    fn drop(&mut self) {
+        if let Err(e) = self.seal_owned() {
+            warn!("Failed to encrypt {}; it stays decrypted at {}: {:#}", self.sealed.display(), self.working.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::crypto::KdfParams;
+    use crate::test_support::UnitWorker;
+    use crate::vault::embeddings::EmbeddingVector;
+    use crate::vault::parser::ObsidianParser;
+    use crate::vault::search::{SearchOptions, VectorSearchEngine};
+
+    fn crypto(passphrase: &str) -> Crypto {
+        Crypto::with_params(passphrase, KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 })
+    }
+
+    async fn engine(database: &SealedDatabase) -> VectorSearchEngine {
+        let engine = VectorSearchEngine::new(database.working_path().to_path_buf()).unwrap()
+            .with_query_embedder(Arc::new(UnitWorker));
+        engine.initialize().await.unwrap();
+        engine
+    }
+
+    #[tokio::test]
+    async fn test_search_database_is_encrypted_between_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("notetoai.db");
+
+        let database = SealedDatabase::open(&db, crypto("correct horse")).unwrap();
+        let document = ObsidianParser::new().unwrap()
+            .parse_content(Path::new("Garden.md"), "# Garden\n\nWater the tomato seedlings.").await.unwrap();
+        let embedding = EmbeddingVector {
+            text: String::new(),
+            vector: vec![1.0, 0.0],
+            model_name: "test".to_string(),
+            created_at: chrono::Utc::now(),
+            block_embeddings: None,
+            title_vector: None,
+        };
+        engine(&database).await.index_document(&document, &embedding).await.unwrap();
+        // A second process shares the open copy and leaves sealing to the first
+        let shared = SealedDatabase::open(&db, crypto("correct horse")).unwrap();
+        assert_eq!(shared.working_path(), database.working_path());
+        drop(shared);
+        assert!(database.working_path().exists());
+        database.seal().unwrap();
+
+        let sealed = std::fs::read(&db).unwrap();
+        assert!(Crypto::is_encrypted(&sealed));
+        assert!(!sealed.windows(8).any(|w| w == b"seedling"));
+        assert!(!dir.path().join("notetoai.db.open").exists());
+        assert!(SealedDatabase::open(&db, crypto("battery staple")).is_err());
+        assert!(!dir.path().join("notetoai.db.open").exists());
+
+        let reopened = SealedDatabase::open(&db, crypto("correct horse")).unwrap();
+        let results = engine(&reopened).await.text_search("seedlings", &SearchOptions::default()).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document.path, Path::new("Garden.md"));
+    }
+}
@@ -1,12 +1,25 @@
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
 use tokio::sync::RwLock;
-use std::sync::Arc;
-use crate::vault::parser::{ParsedDocument, BlockType};
-use crate::vault::embeddings::EmbeddingVector;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use crate::ai::context::{ContextQuery, Document, RetrievalResult, Retriever, SourceType};
+use crate::ai::trace::{QueryStage, QueryTrace};
+use crate::vault::parser::{ParsedDocument, BlockType, LinkResolution};
+use crate::vault::circuit_breaker::CircuitBreaker;
+use crate::vault::hnsw::{DistanceMetric, HnswConfig, HnswIndex, VectorIndexMode, HNSW_MIN_VECTORS};
+use crate::vault::embedding_pool::EmbeddingWorker;
+use crate::vault::embeddings::{cosine_similarity, EmbeddingVector};
+use crate::vault::focus::FocusSession;
+use crate::vault::indexer::VaultIndexer;
+use crate::vault::language::{FtsAnalyzer, LanguageConfig};
+use crate::vault::qa::{QaMatch, QaPair};
+use crate::vault::snippet;
 use crate::logger::Logger;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +31,21 @@ pub struct SearchResult {
     pub context: SearchContext,
 }
 
+/// A document as stored in the text index; `content` is the parsed plain text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRecord {
+    pub path: PathBuf,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    /// Unix seconds when the note last changed, per `ParsedDocument::modified_at`
+    pub modified: u64,
+    pub word_count: usize,
+    /// Link targets, with `#heading` or `#^block` when the link points into a note
+    #[serde(default)]
+    pub links: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchDocument {
     pub path: PathBuf,
@@ -26,6 +54,8 @@ pub struct SearchDocument {
     pub tags: Vec<String>,
     pub modified: u64,
     pub word_count: usize,
+    #[serde(default)]
+    pub links: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,30 +95,66 @@ pub struct SearchQuery {
 
 #[derive(Debug, Clone, Default)]
 pub struct SearchFilters {
+    /// Documents with any of these tags or a tag nested under one
     pub tags: Vec<String>,
     pub paths: Vec<PathBuf>,
+    /// File extensions, with or without the leading dot
     pub file_types: Vec<String>,
     pub date_range: Option<(u64, u64)>,
     pub min_words: Option<usize>,
     pub max_words: Option<usize>,
+    /// ISO 639-1 code of the query text; detected when unset and language routing is on
+    pub language: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SearchOptions {
     pub limit: usize,
+    /// Ranked results skipped before `limit` are taken, for paging
+    pub offset: usize,
     pub similarity_threshold: f32,
     pub include_context: bool,
     pub context_window: usize,
+    /// Raise notes indexed recently, by up to `RECENT_BOOST`
     pub boost_recent: bool,
+    /// Raise notes whose tag, or a segment of a nested tag, is a word of the query by `TAG_BOOST`
     pub boost_tags: bool,
     pub boost_titles: bool,
     pub hybrid_search: bool,
+    /// Share of the semantic score taken from the title/frontmatter embedding
+    pub title_weight: f32,
+    /// Score multipliers per source type, applied after merging
+    pub source_weights: SourceWeights,
+    /// Exact scan or HNSW graph for picking semantic candidates
+    pub vector_index: VectorIndexMode,
+    /// Re-rank with Maximal Marginal Relevance; 1.0 ranks by score alone, lower values favor diversity
+    pub mmr_lambda: Option<f32>,
+    /// Bytes of note text in a result snippet, not counting the `...` marking cut ends
+    pub snippet_length: usize,
+}
+
+/// Largest share added to the score of a note indexed just now; it halves every `RECENT_HALF_LIFE_DAYS`
+const RECENT_BOOST: f32 = 0.1;
+const RECENT_HALF_LIFE_DAYS: f32 = 30.0;
+/// Share added to the score of a note tagged with one of the query's words
+const TAG_BOOST: f32 = 0.1;
+
+/// Multiplier per source type name (`note`, `conversation`, `web_save`, `task`); unlisted types keep 1.0
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SourceWeights(pub HashMap<String, f32>);
+
+impl SourceWeights {
+    pub fn weight(&self, source_type: SourceType) -> f32 {
+        self.0.get(source_type.as_str()).copied().unwrap_or(1.0)
+    }
 }
 
 impl Default for SearchOptions {
     fn default() -> Self {
         Self {
             limit: 10,
+            offset: 0,
             similarity_threshold: 0.7,
             include_context: true,
             context_window: 100,
@@ -96,13 +162,78 @@ impl Default for SearchOptions {
             boost_tags: true,
             boost_titles: true,
             hybrid_search: true,
+            title_weight: 0.3,
+            source_weights: SourceWeights::default(),
+            vector_index: VectorIndexMode::Auto,
+            mmr_lambda: None,
+            snippet_length: 200,
         }
     }
 }
 
+/// How a wikilink that matches several documents (e.g. two `Project.md` files) is counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateLinkTargets {
+    /// Count the link toward every matching document
+    CountAll,
+    /// Count it toward the match with the shortest path, as Obsidian resolves it
+    #[default]
+    ShortestPath,
+    /// Leave ambiguous links out of backlinks and link counts
+    Ignore,
+}
+
+/// How re-indexing a document decides which block vectors to write
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockChangeDetection {
+    /// Keep stored blocks whose position and content hash are unchanged and drop removed ones
+    #[default]
+    ContentHash,
+    /// Rewrite every block vector on each index
+    Off,
+}
+
+/// Links into and out of one document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkCounts {
+    /// Other documents linking to it
+    pub inbound: usize,
+    /// Distinct link targets it names, resolved or not
+    pub outbound: usize,
+}
+
+/// How many document and section vectors the HNSW graph holds; the rest are scanned exactly
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IndexStatus {
+    pub indexed_vectors: usize,
+    pub unindexed_vectors: usize,
+}
+
+/// Vectors appended since the last graph update that trigger a background update
+const DEFAULT_INDEX_UPDATE_THRESHOLD: usize = 1000;
+
 pub struct VectorSearchEngine {
     db_path: PathBuf,
     index: Arc<RwLock<VectorIndex>>,
+    /// Embeds query text for `search`; without one, semantic results are skipped
+    query_embedder: Option<Arc<dyn EmbeddingWorker>>,
+    languages: LanguageConfig,
+    /// Implicit filter on every search until the session's focus is cleared
+    focus: FocusSession,
+    /// Embedding model version recorded with each document vector written
+    model_version: String,
+    duplicate_link_targets: DuplicateLinkTargets,
+    block_change_detection: BlockChangeDetection,
+    /// How query and document vectors are compared when ranking documents
+    distance_metric: DistanceMetric,
+    index_update_threshold: usize,
+    index_update: Mutex<Option<JoinHandle<()>>>,
+    /// Bumped whenever a document is indexed or removed
+    corpus_version: AtomicU64,
+    /// Where search hits and document reads are counted toward the startup warmup
+    access_log: Option<Arc<VaultIndexer>>,
     logger: Logger,
 }
 
@@ -110,12 +241,227 @@ pub struct VectorSearchEngine {
 struct VectorIndex {
     documents: HashMap<String, IndexedDocument>,
     embeddings: HashMap<String, Vec<f32>>,
+    title_embeddings: HashMap<String, Vec<f32>>,
     block_embeddings: HashMap<String, Vec<BlockEmbedding>>,
+    /// Document and section vectors, labelled by document
+    hnsw: HnswIndex,
+    /// Documents whose vectors are not in `hnsw` yet
+    pending: HashSet<String>,
     tag_index: HashMap<String, HashSet<String>>,
     title_index: HashMap<String, String>,
     link_graph: HashMap<String, HashSet<String>>,
 }
 
+impl VectorIndex {
+    /// Vectors of pending documents, which searches scan exactly until the graph catches up
+    fn unindexed_vectors(&self) -> usize {
+        self.pending.iter()
+            .filter(|doc_id| self.embeddings.contains_key(*doc_id))
+            .map(|doc_id| 1 + self.block_embeddings.get(doc_id).map_or(0, |blocks| {
+                blocks.iter().filter(|b| !matches!(b.block_type, BlockType::Symbol { .. })).count()
+            }))
+            .sum()
+    }
+
+    /// Insert every pending document's vectors into the graph
+    fn index_pending(&mut self) {
+        let VectorIndex { embeddings, block_embeddings, hnsw, pending, .. } = self;
+        let mut doc_ids: Vec<String> = pending.drain().collect();
+        doc_ids.sort();
+        for doc_id in doc_ids {
+            let Some(vector) = embeddings.get(&doc_id) else { continue };
+            let mut vectors = vec![vector.as_slice()];
+            if let Some(blocks) = block_embeddings.get(&doc_id) {
+                vectors.extend(blocks.iter()
+                    .filter(|b| !matches!(b.block_type, BlockType::Symbol { .. }))
+                    .map(|b| b.embedding.as_slice()));
+            }
+            hnsw.insert(&doc_id, &vectors);
+        }
+    }
+
+    /// Register `doc_id` under each tag and every ancestor of a nested tag
+    fn add_tags(&mut self, doc_id: &str, tags: &[String]) {
+        for tag in expand_tags(tags) {
+            self.tag_index.entry(tag).or_default().insert(doc_id.to_string());
+        }
+    }
+
+    /// Record that `doc_id` links to `target`, keyed by the heading or block linked to,
+    /// e.g. `notes/a.md#Section` or `notes/a.md#^abc123`
+    fn add_link(&mut self, doc_id: &str, target: &str, heading: Option<&str>, block_id: Option<&str>) {
+        let key = link_key(target, heading, block_id);
+        if let Some(doc) = self.documents.get_mut(doc_id) {
+            doc.links.push(key.clone());
+        }
+        self.link_graph.entry(key).or_default().insert(doc_id.to_string());
+    }
+
+    /// Documents a link's page names: the document with that id when there is one, otherwise
+    /// those whose path (with or without `.md`), trailing path segments or title match it
+    /// case-insensitively, narrowed to one by `policy`
+    fn link_targets(&self, page: &str, policy: DuplicateLinkTargets) -> Vec<&str> {
+        if let Some((id, _)) = self.documents.get_key_value(page) {
+            return vec![id.as_str()];
+        }
+        let mut candidates: Vec<&str> = self.documents.iter()
+            .filter(|(id, doc)| names_document(page, id, doc))
+            .map(|(id, _)| id.as_str())
+            .collect();
+        candidates.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        match policy {
+            DuplicateLinkTargets::CountAll => candidates,
+            DuplicateLinkTargets::ShortestPath => candidates.into_iter().take(1).collect(),
+            DuplicateLinkTargets::Ignore if candidates.len() == 1 => candidates,
+            DuplicateLinkTargets::Ignore => Vec::new(),
+        }
+    }
+
+    /// Other documents with a link to `doc_id` or one of its headings or blocks, by path
+    fn linking_documents(&self, doc_id: &str, policy: DuplicateLinkTargets) -> Vec<&IndexedDocument> {
+        let doc = self.documents.get(doc_id);
+        let mut linking: Vec<&IndexedDocument> = self.link_graph.iter()
+            .filter(|(key, _)| {
+                let page = link_page(key);
+                (page == doc_id || doc.is_some_and(|doc| names_document(page, doc_id, doc)))
+                    && self.link_targets(page, policy).contains(&doc_id)
+            })
+            .flat_map(|(_, sources)| sources.iter())
+            .filter(|source| *source != doc_id)
+            .filter_map(|source| self.documents.get(source))
+            .collect();
+        linking.sort_by(|a, b| a.path.cmp(&b.path));
+        linking.dedup_by(|a, b| a.path == b.path);
+        linking
+    }
+
+    /// Forget the links `doc_id` makes, before re-indexing or removing it
+    fn remove_links(&mut self, doc_id: &str) {
+        if let Some(doc) = self.documents.get_mut(doc_id) {
+            doc.links.clear();
+        }
+        self.link_graph.retain(|_, sources| {
+            sources.remove(doc_id);
+            !sources.is_empty()
+        });
+    }
+}
+
+/// Raise scores per `boost_recent` and `boost_tags`; `now` is in Unix seconds
+fn apply_boosts(results: &mut [SearchResult], query: &str, options: &SearchOptions, now: u64) {
+    let words: HashSet<String> = query.split(|c: char| !c.is_alphanumeric() && c != '/')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    for result in results {
+        let mut boost = 1.0;
+        if options.boost_recent {
+            let age_days = now.saturating_sub(result.document.modified) as f32 / 86_400.0;
+            boost += RECENT_BOOST * 0.5f32.powf(age_days / RECENT_HALF_LIFE_DAYS);
+        }
+        let tagged = result.document.tags.iter().map(|tag| normalize_tag(tag).to_lowercase()).any(|tag| {
+            words.contains(&tag) || tag.split('/').any(|segment| words.contains(segment))
+        });
+        if options.boost_tags && tagged {
+            boost += TAG_BOOST;
+        }
+        result.score *= boost;
+    }
+}
+
+/// A `search_index` row selected as path, title, content, tags, modified, word count; links are
+/// filled in separately
+fn document_record(row: &rusqlite::Row) -> rusqlite::Result<DocumentRecord> {
+    let tags_json: String = row.get(3)?;
+    Ok(DocumentRecord {
+        path: PathBuf::from(row.get::<_, String>(0)?),
+        title: row.get(1)?,
+        content: row.get(2)?,
+        tags: serde_json::from_str(&tags_json).unwrap_or_default(),
+        modified: row.get::<_, i64>(4)? as u64,
+        word_count: row.get::<_, i64>(5)? as usize,
+        links: Vec::new(),
+    })
+}
+
+/// Link graph keys of each document in `paths`, or of every document when `None`, in one query
+fn stored_links(conn: &Connection, paths: Option<&[String]>) -> Result<HashMap<String, Vec<String>>> {
+    let mut links: HashMap<String, Vec<String>> = HashMap::new();
+    if paths.is_some_and(|paths| paths.is_empty()) {
+        return Ok(links);
+    }
+    let filter = match paths {
+        Some(paths) => format!("WHERE source_path IN ({})", vec!["?"; paths.len()].join(", ")),
+        None => String::new(),
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT source_path, target, heading, block_id FROM links {} ORDER BY rowid",
+        filter
+    ))?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(paths.unwrap_or_default()), |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))
+    })?;
+    for row in rows {
+        let (source, target, heading, block_id) = row?;
+        links.entry(source).or_default().push(link_key(&target, heading.as_deref(), block_id.as_deref()));
+    }
+    Ok(links)
+}
+
+fn link_key(target: &str, heading: Option<&str>, block_id: Option<&str>) -> String {
+    match (block_id, heading) {
+        (Some(block_id), _) => format!("{}#^{}", target, block_id),
+        (None, Some(heading)) => format!("{}#{}", target, heading),
+        (None, None) => target.to_string(),
+    }
+}
+
+/// The page part of a link graph key, without its `#heading` or `#^block`
+fn link_page(key: &str) -> &str {
+    key.split_once('#').map_or(key, |(page, _)| page)
+}
+
+/// Whether an unresolved link to `page` could mean the document `doc_id`
+fn names_document(page: &str, doc_id: &str, doc: &IndexedDocument) -> bool {
+    let page = page.to_lowercase();
+    let page = page.strip_suffix(".md").unwrap_or(&page);
+    let path = doc_id.to_lowercase();
+    let path = path.strip_suffix(".md").unwrap_or(&path);
+    path == page || path.ends_with(&format!("/{}", page)) || doc.title.to_lowercase() == page
+}
+
+fn indexed_blocks(doc_id: &str, document: &ParsedDocument) -> Vec<IndexedBlock> {
+    document.blocks.iter().enumerate().map(|(i, block)| {
+        IndexedBlock {
+            block_type: block.block_type.clone(),
+            content: block.content.clone(),
+            start_pos: block.position.start,
+            end_pos: block.position.end,
+            embedding_id: format!("{}_{}", doc_id, i),
+        }
+    }).collect()
+}
+
+fn normalize_tag(tag: &str) -> &str {
+    tag.trim().trim_start_matches('#').trim_matches('/')
+}
+
+/// Tags plus their ancestors, once each: `project/rust/async` also yields `project` and `project/rust`
+fn expand_tags(tags: &[String]) -> HashSet<String> {
+    let mut expanded = HashSet::new();
+    for tag in tags {
+        let tag = normalize_tag(tag);
+        for (i, _) in tag.match_indices('/') {
+            expanded.insert(tag[..i].to_string());
+        }
+        if !tag.is_empty() {
+            expanded.insert(tag.to_string());
+        }
+    }
+    expanded
+}
+
 #[derive(Debug, Clone)]
 struct IndexedDocument {
     pub path: PathBuf,
@@ -125,9 +471,11 @@ struct IndexedDocument {
     pub modified: u64,
     pub word_count: usize,
     pub blocks: Vec<IndexedBlock>,
+    /// Outbound link graph keys, in the order the note links them
+    pub links: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct IndexedBlock {
     pub block_type: BlockType,
     pub content: String,
@@ -149,7 +497,10 @@ impl VectorSearchEngine {
         let index = VectorIndex {
             documents: HashMap::new(),
             embeddings: HashMap::new(),
+            title_embeddings: HashMap::new(),
             block_embeddings: HashMap::new(),
+            hnsw: HnswIndex::default(),
+            pending: HashSet::new(),
             tag_index: HashMap::new(),
             title_index: HashMap::new(),
             link_graph: HashMap::new(),
@@ -158,10 +509,82 @@ impl VectorSearchEngine {
         Ok(Self {
             db_path,
             index: Arc::new(RwLock::new(index)),
+            query_embedder: None,
+            languages: LanguageConfig::default(),
+            focus: FocusSession::in_memory(),
+            model_version: String::new(),
+            duplicate_link_targets: DuplicateLinkTargets::default(),
+            block_change_detection: BlockChangeDetection::default(),
+            distance_metric: DistanceMetric::default(),
+            index_update_threshold: DEFAULT_INDEX_UPDATE_THRESHOLD,
+            index_update: Mutex::new(None),
+            corpus_version: AtomicU64::new(0),
+            access_log: None,
             logger: Logger::new("VectorSearchEngine"),
         })
     }
 
+    /// Use `embedder` to embed query text; it must produce vectors in the indexed model's space
+    pub fn with_query_embedder(mut self, embedder: Arc<dyn EmbeddingWorker>) -> Self {
+        self.query_embedder = Some(embedder);
+        self
+    }
+
+    /// Index each note with the full-text analyzer for its language and route queries the same way
+    pub fn with_languages(mut self, languages: LanguageConfig) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    /// Restrict every search to the session's focus while one is set
+    pub fn with_focus(mut self, focus: FocusSession) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    /// Record `version` with the vectors this engine writes; others are reported as stale
+    pub fn with_model_version(mut self, version: &str) -> Self {
+        self.model_version = version.to_string();
+        self
+    }
+
+    pub fn model_version(&self) -> &str {
+        &self.model_version
+    }
+
+    /// Choose how links whose target matches several documents count toward backlinks
+    pub fn with_duplicate_link_targets(mut self, policy: DuplicateLinkTargets) -> Self {
+        self.duplicate_link_targets = policy;
+        self
+    }
+
+    /// Rank documents and build the HNSW graph with `metric`; `similarity_threshold` is read
+    /// on that metric's scale
+    pub fn with_distance_metric(mut self, metric: DistanceMetric) -> Self {
+        self.distance_metric = metric;
+        if let Some(index) = Arc::get_mut(&mut self.index) {
+            index.get_mut().hnsw = HnswIndex::new(HnswConfig { metric, ..HnswConfig::default() });
+        }
+        self
+    }
+
+    /// Update the HNSW graph in the background once `threshold` vectors are waiting for it
+    pub fn with_index_update_threshold(mut self, threshold: usize) -> Self {
+        self.index_update_threshold = threshold.max(1);
+        self
+    }
+
+    pub fn with_block_change_detection(mut self, detection: BlockChangeDetection) -> Self {
+        self.block_change_detection = detection;
+        self
+    }
+
+    /// Record each search hit and document read with `indexer`, so the warmup preloads them
+    pub fn with_access_log(mut self, indexer: Arc<VaultIndexer>) -> Self {
+        self.access_log = Some(indexer);
+        self
+    }
+
     pub async fn initialize(&self) -> Result<()> {
         self.create_search_tables().await?;
         self.load_index_from_db().await?;
@@ -175,6 +598,27 @@ impl VectorSearchEngine {
         // Document embeddings table
         conn.execute(
             "CREATE TABLE IF NOT EXISTS document_embeddings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                document_path TEXT UNIQUE NOT NULL,
+                embedding BLOB NOT NULL,
+                updated_at INTEGER NOT NULL,
+                model_version TEXT NOT NULL DEFAULT ''
+            )",
+            [],
+        )?;
+        // Databases created before model versions were recorded
+        let has_version: bool = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('document_embeddings') WHERE name = 'model_version'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if !has_version {
+            conn.execute("ALTER TABLE document_embeddings ADD COLUMN model_version TEXT NOT NULL DEFAULT ''", [])?;
+        }
+
+        // Title/frontmatter embeddings, scored separately from the body
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS title_embeddings (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 document_path TEXT UNIQUE NOT NULL,
                 embedding BLOB NOT NULL,
@@ -194,10 +638,21 @@ impl VectorSearchEngine {
                 embedding BLOB NOT NULL,
                 start_pos INTEGER NOT NULL,
                 end_pos INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
+                updated_at INTEGER NOT NULL,
+                content_hash TEXT
             )",
             [],
         )?;
+        // Databases created before block content hashes were recorded; their blocks are
+        // rewritten the next time each document is indexed
+        let has_hash: bool = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('block_embeddings') WHERE name = 'content_hash'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if !has_hash {
+            conn.execute("ALTER TABLE block_embeddings ADD COLUMN content_hash TEXT", [])?;
+        }
 
         // Search index for fast text queries
         conn.execute(
@@ -207,10 +662,20 @@ impl VectorSearchEngine {
                 content TEXT NOT NULL,
                 tags TEXT NOT NULL,
                 modified INTEGER NOT NULL,
-                word_count INTEGER NOT NULL
+                word_count INTEGER NOT NULL,
+                blocks TEXT NOT NULL DEFAULT '[]'
             )",
             [],
         )?;
+        // Databases created before parsed blocks were kept; their notes get blocks when re-indexed
+        let has_blocks: bool = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('search_index') WHERE name = 'blocks'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if !has_blocks {
+            conn.execute("ALTER TABLE search_index ADD COLUMN blocks TEXT NOT NULL DEFAULT '[]'", [])?;
+        }
 
         // Create FTS5 table for full-text search
         conn.execute(
@@ -220,9 +685,53 @@ impl VectorSearchEngine {
             [],
         )?;
 
+        // One FTS5 table per analyzer, keyed by the search_index rowid, plus each note's language
+        for analyzer in FtsAnalyzer::ALL {
+            conn.execute(
+                &format!(
+                    "CREATE VIRTUAL TABLE IF NOT EXISTS {} USING fts5(title, content, tags, tokenize = '{}')",
+                    analyzer.table(), analyzer.tokenizer()
+                ),
+                [],
+            )?;
+        }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS document_languages (
+                document_path TEXT PRIMARY KEY,
+                language TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Question/answer pairs extracted from notes, matched on the question's embedding
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS qa_pairs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                document_path TEXT NOT NULL,
+                question TEXT NOT NULL,
+                answer TEXT NOT NULL,
+                start_pos INTEGER NOT NULL,
+                embedding BLOB NOT NULL
+            )",
+            [],
+        )?;
+
+        // Links as parsed; `target` is the linked document's path when it was resolved
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS links (
+                source_path TEXT NOT NULL,
+                target TEXT NOT NULL,
+                heading TEXT,
+                block_id TEXT
+            )",
+            [],
+        )?;
+
         // Indexes
         conn.execute("CREATE INDEX IF NOT EXISTS idx_doc_embeddings_path ON document_embeddings(document_path)", [])?;
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_links_source ON links(source_path)", [])?;
         conn.execute("CREATE INDEX IF NOT EXISTS idx_block_embeddings_doc ON block_embeddings(document_path)", [])?;
+        conn.execute("CREATE INDEX IF NOT EXISTS idx_qa_pairs_doc ON qa_pairs(document_path)", [])?;
         conn.execute("CREATE INDEX IF NOT EXISTS idx_search_tags ON search_index(tags)", [])?;
         conn.execute("CREATE INDEX IF NOT EXISTS idx_search_modified ON search_index(modified)", [])?;
 
@@ -235,11 +744,26 @@ impl VectorSearchEngine {
         // Store document embedding
         self.store_document_embedding(&doc_id, &embedding.vector).await?;
         
+        if let Some(title_vector) = &embedding.title_vector {
+            self.store_title_embedding(&doc_id, title_vector).await?;
+        }
+        
         // Store block embeddings (if available)
         if let Some(block_embeddings) = &embedding.block_embeddings {
             self.store_block_embeddings(&doc_id, block_embeddings).await?;
         }
 
+        let links: Vec<(String, Option<String>, Option<String>)> = document.links.iter()
+            .map(|link| {
+                let target = match &link.resolution {
+                    LinkResolution::Resolved(path) => path.to_string_lossy().to_string(),
+                    _ => link.target.clone(),
+                };
+                (target, link.heading.clone(), link.block_id.clone())
+            })
+            .collect();
+        self.store_links(&doc_id, &links).await?;
+
         // Update in-memory index
         let mut index = self.index.write().await;
         
@@ -248,91 +772,194 @@ impl VectorSearchEngine {
             title: document.title.clone(),
             content: document.content.clone(),
             tags: document.tags.clone(),
-            modified: document.metadata.last_parsed.timestamp() as u64,
+            modified: document.modified_at().timestamp() as u64,
             word_count: document.metadata.word_count,
-            blocks: document.blocks.iter().enumerate().map(|(i, block)| {
-                IndexedBlock {
-                    block_type: block.block_type.clone(),
-                    content: block.content.clone(),
-                    start_pos: block.position.start,
-                    end_pos: block.position.end,
-                    embedding_id: format!("{}_{}", doc_id, i),
-                }
-            }).collect(),
+            blocks: indexed_blocks(&doc_id, document),
+            links: Vec::new(),
         };
 
         index.documents.insert(doc_id.clone(), indexed_doc);
+        match &embedding.block_embeddings {
+            Some(block_embeddings) => {
+                let blocks = block_embeddings.iter().map(|block_emb| BlockEmbedding {
+                    block_id: block_emb.block_id.clone(),
+                    embedding: block_emb.vector.clone(),
+                    content: block_emb.content.clone(),
+                    block_type: block_emb.block_type.clone(),
+                }).collect();
+                index.block_embeddings.insert(doc_id.clone(), blocks)
+            }
+            None => index.block_embeddings.remove(&doc_id),
+        };
+        // Old vectors leave the graph now; the new ones are scanned exactly until the next update
+        index.hnsw.remove(&doc_id);
+        index.pending.insert(doc_id.clone());
         index.embeddings.insert(doc_id.clone(), embedding.vector.clone());
+        match &embedding.title_vector {
+            Some(title_vector) => index.title_embeddings.insert(doc_id.clone(), title_vector.clone()),
+            None => index.title_embeddings.remove(&doc_id),
+        };
 
         // Update auxiliary indexes
-        for tag in &document.tags {
-            index.tag_index.entry(tag.clone())
-                .or_insert_with(HashSet::new)
-                .insert(doc_id.clone());
-        }
+        index.add_tags(&doc_id, &document.tags);
 
         index.title_index.insert(document.title.clone(), doc_id.clone());
 
-        // Update link graph
-        for link in &document.links {
-            index.link_graph.entry(link.target.clone())
-                .or_insert_with(HashSet::new)
-                .insert(doc_id.clone());
+        // Update link graph, keyed by the resolved document when known
+        index.remove_links(&doc_id);
+        for (target, heading, block_id) in &links {
+            index.add_link(&doc_id, target, heading.as_deref(), block_id.as_deref());
         }
 
         // Update search index
         self.update_search_index(document).await?;
 
+        let update_due = index.unindexed_vectors() >= self.index_update_threshold;
+        drop(index);
+        self.corpus_version.fetch_add(1, Ordering::SeqCst);
+        if update_due {
+            self.schedule_index_update();
+        }
+
         self.logger.debug(&format!("Indexed document: {}", document.path.display()));
         Ok(())
     }
 
+    /// Move pending vectors into the graph on a background task, unless one is still running
+    fn schedule_index_update(&self) {
+        let mut update = self.index_update.lock().unwrap();
+        if update.as_ref().is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+        let index = Arc::clone(&self.index);
+        *update = Some(tokio::spawn(async move {
+            index.write().await.index_pending();
+        }));
+    }
+
+    /// Changes whenever a document is indexed or removed, so derived answers can be invalidated
+    pub fn corpus_version(&self) -> u64 {
+        self.corpus_version.load(Ordering::SeqCst)
+    }
+
+    /// Wait for a running background graph update to finish
+    pub async fn wait_for_index_updates(&self) {
+        let task = self.index_update.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+
+    /// Indexed and unindexed vector counts of the HNSW graph
+    pub async fn index_status(&self) -> IndexStatus {
+        let index = self.index.read().await;
+        IndexStatus {
+            indexed_vectors: index.hnsw.len(),
+            unindexed_vectors: index.unindexed_vectors(),
+        }
+    }
+
     async fn store_document_embedding(&self, doc_id: &str, embedding: &[f32]) -> Result<()> {
         let conn = Connection::open(&self.db_path)?;
         let embedding_bytes = self.serialize_embedding(embedding)?;
         let now = chrono::Utc::now().timestamp();
 
         conn.execute(
-            "INSERT OR REPLACE INTO document_embeddings (document_path, embedding, updated_at)
-             VALUES (?1, ?2, ?3)",
-            params![doc_id, embedding_bytes, now],
+            "INSERT OR REPLACE INTO document_embeddings (document_path, embedding, updated_at, model_version)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![doc_id, embedding_bytes, now, self.model_version],
         )?;
 
         Ok(())
     }
 
-    async fn store_block_embeddings(&self, doc_id: &str, block_embeddings: &[crate::vault::embeddings::BlockEmbedding]) -> Result<()> {
+    async fn store_links(&self, doc_id: &str, links: &[(String, Option<String>, Option<String>)]) -> Result<()> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM links WHERE source_path = ?1", params![doc_id])?;
+        for (target, heading, block_id) in links {
+            tx.execute(
+                "INSERT INTO links (source_path, target, heading, block_id) VALUES (?1, ?2, ?3, ?4)",
+                params![doc_id, target, heading, block_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn store_title_embedding(&self, doc_id: &str, embedding: &[f32]) -> Result<()> {
         let conn = Connection::open(&self.db_path)?;
+        let embedding_bytes = self.serialize_embedding(embedding)?;
         let now = chrono::Utc::now().timestamp();
 
-        // Clear existing block embeddings for this document
         conn.execute(
-            "DELETE FROM block_embeddings WHERE document_path = ?1",
-            params![doc_id],
+            "INSERT OR REPLACE INTO title_embeddings (document_path, embedding, updated_at)
+             VALUES (?1, ?2, ?3)",
+            params![doc_id, embedding_bytes, now],
         )?;
 
-        // Insert new block embeddings
+        Ok(())
+    }
+
+    async fn store_block_embeddings(
+        &self,
+        doc_id: &str,
+        block_embeddings: &[crate::vault::embeddings::BlockEmbedding],
+    ) -> Result<()> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let now = chrono::Utc::now().timestamp();
+        let tx = conn.transaction()?;
+
+        // Stored hashes by block id, which is the document plus the block's index in it
+        let mut stored: HashMap<String, Option<String>> = HashMap::new();
+        if self.block_change_detection == BlockChangeDetection::ContentHash {
+            let mut stmt = tx.prepare("SELECT block_id, content_hash FROM block_embeddings WHERE document_path = ?1")?;
+            let rows = stmt.query_map(params![doc_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))?;
+            for row in rows {
+                let (block_id, hash) = row?;
+                stored.insert(block_id, hash);
+            }
+        } else {
+            tx.execute("DELETE FROM block_embeddings WHERE document_path = ?1", params![doc_id])?;
+        }
+
+        let mut kept = HashSet::new();
         for (i, block_emb) in block_embeddings.iter().enumerate() {
             let block_id = format!("{}_{}", doc_id, i);
-            let embedding_bytes = self.serialize_embedding(&block_emb.vector)?;
+            let hash = blake3::hash(block_emb.content.as_bytes()).to_string();
+
+            if stored.get(&block_id).is_some_and(|stored| stored.as_deref() == Some(hash.as_str())) {
+                // Same text at the same index: only where it sits in the note may have moved
+                tx.execute(
+                    "UPDATE block_embeddings SET block_type = ?2, start_pos = ?3, end_pos = ?4 WHERE block_id = ?1",
+                    params![block_id, serde_json::to_string(&block_emb.block_type)?, block_emb.start_pos, block_emb.end_pos],
+                )?;
+            } else {
+                tx.execute(
+                    "INSERT OR REPLACE INTO block_embeddings
+                     (document_path, block_id, block_type, content, embedding, start_pos, end_pos, updated_at, content_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        doc_id,
+                        block_id,
+                        serde_json::to_string(&block_emb.block_type)?,
+                        block_emb.content.clone(),
+                        self.serialize_embedding(&block_emb.vector)?,
+                        block_emb.start_pos,
+                        block_emb.end_pos,
+                        now,
+                        hash
+                    ],
+                )?;
+            }
+            kept.insert(block_id);
+        }
 
-            conn.execute(
-                "INSERT INTO block_embeddings 
-                 (document_path, block_id, block_type, content, embedding, start_pos, end_pos, updated_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-                params![
-                    doc_id,
-                    block_id,
-                    serde_json::to_string(&BlockType::Paragraph)?, // Default, should be passed properly
-                    block_emb.content.clone(),
-                    embedding_bytes,
-                    0, // start_pos - should be passed from block
-                    0, // end_pos - should be passed from block
-                    now
-                ],
-            )?;
+        for block_id in stored.keys().filter(|block_id| !kept.contains(*block_id)) {
+            tx.execute("DELETE FROM block_embeddings WHERE block_id = ?1", params![block_id])?;
         }
 
+        tx.commit()?;
         Ok(())
     }
 
@@ -340,80 +967,255 @@ impl VectorSearchEngine {
         let conn = Connection::open(&self.db_path)?;
         
         let tags_json = serde_json::to_string(&document.tags)?;
+
+        // Replacing the row gives it a new rowid, so drop the old analyzer rows first
+        if self.languages.enabled {
+            self.remove_language_rows(&conn, &document.path.to_string_lossy())?;
+        }
         
         conn.execute(
             "INSERT OR REPLACE INTO search_index 
-             (document_path, title, content, tags, modified, word_count)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+             (document_path, title, content, tags, modified, word_count, blocks)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
             params![
                 document.path.to_string_lossy(),
                 document.title,
                 document.plain_text,
                 tags_json,
-                document.metadata.last_parsed.timestamp(),
-                document.metadata.word_count
+                document.modified_at().timestamp(),
+                document.metadata.word_count,
+                serde_json::to_string(&indexed_blocks(&document.path.to_string_lossy(), document))?
             ],
         )?;
 
-        // Update FTS index
+        // Update FTS index under the same rowid, so matches join back to their document
         conn.execute(
-            "INSERT OR REPLACE INTO search_fts (title, content, tags)
-             VALUES (?1, ?2, ?3)",
-            params![
-                document.title,
-                document.plain_text,
-                tags_json
-            ],
+            "INSERT OR REPLACE INTO search_fts (rowid, title, content, tags)
+             SELECT rowid, title, content, tags FROM search_index WHERE document_path = ?1",
+            params![document.path.to_string_lossy()],
         )?;
 
+        if self.languages.enabled {
+            let language = self.languages.document_language(document);
+            conn.execute(
+                &format!(
+                    "INSERT INTO {} (rowid, title, content, tags)
+                     SELECT rowid, title, content, tags FROM search_index WHERE document_path = ?1",
+                    FtsAnalyzer::for_language(&language).table()
+                ),
+                params![document.path.to_string_lossy()],
+            )?;
+            conn.execute(
+                "INSERT OR REPLACE INTO document_languages (document_path, language) VALUES (?1, ?2)",
+                params![document.path.to_string_lossy(), language],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_language_rows(&self, conn: &Connection, doc_id: &str) -> Result<()> {
+        for analyzer in FtsAnalyzer::ALL {
+            conn.execute(
+                &format!(
+                    "DELETE FROM {} WHERE rowid IN (SELECT rowid FROM search_index WHERE document_path = ?1)",
+                    analyzer.table()
+                ),
+                params![doc_id],
+            )?;
+        }
+        conn.execute("DELETE FROM document_languages WHERE document_path = ?1", params![doc_id])?;
         Ok(())
     }
 
+    /// Language recorded for an indexed note, when language routing was on while it was indexed
+    pub fn document_language(&self, path: &Path) -> Result<Option<String>> {
+        let conn = Connection::open(&self.db_path)?;
+        Ok(conn.query_row(
+            "SELECT language FROM document_languages WHERE document_path = ?1",
+            params![path.to_string_lossy()],
+            |row| row.get(0),
+        ).optional()?)
+    }
+
     pub async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
-        let mut results = Vec::new();
+        self.search_traced(query, &mut QueryTrace::new(&query.text)).await
+    }
 
-        if query.options.hybrid_search {
-            // Combine multiple search strategies
-            let semantic_results = self.semantic_search(&query.text, &query.options).await?;
-            let text_results = self.text_search(&query.text, &query.options).await?;
-            let tag_results = self.tag_search(&query.filters.tags, &query.options).await?;
+    /// `search`, recording the embed, vector search, text search, merge and rerank stages in `trace`;
+    /// enrich and generate belong to answering, which records them around this call
+    pub async fn search_traced(&self, query: &SearchQuery, trace: &mut QueryTrace) -> Result<Vec<SearchResult>> {
+        // Each strategy fetches enough to fill the requested page after the skipped ones
+        let retrieval = SearchOptions { limit: query.options.limit + query.options.offset, ..query.options.clone() };
 
-            results = self.merge_search_results(semantic_results, text_results, tag_results, &query.options)?;
+        let mut results = if query.options.hybrid_search {
+            // Combine multiple search strategies
+            // Text and tag matches still answer while the embedding backend is failing
+            let semantic_results = match self.semantic_search_traced(&query.text, &retrieval, trace).await {
+                Err(e) if CircuitBreaker::is_open_error(&e) => {
+                    self.logger.warn(&format!("Semantic search skipped: {}", e));
+                    Vec::new()
+                }
+                results => results?,
+            };
+            let (text_results, tag_results) = trace.time_async(QueryStage::TextSearch, async {
+                let text_results = self.text_search_in(&query.text, query.filters.language.as_deref(), &retrieval).await?;
+                anyhow::Ok((text_results, self.tag_search(&query.filters.tags, &retrieval).await?))
+            }).await?;
+
+            trace.time(QueryStage::Merge, || {
+                self.merge_search_results(semantic_results, text_results, tag_results, &query.options)
+            })?
         } else {
             // Use primary search method
-            results = self.semantic_search(&query.text, &query.options).await?;
+            self.semantic_search_traced(&query.text, &retrieval, trace).await?
+        };
+
+        let focus = self.focus.current().await;
+        results = trace.time(QueryStage::Merge, || {
+            // Apply filters
+            let mut results = self.apply_filters(results, &query.filters)?;
+            if let Some(focus) = focus {
+                results.retain(|result| focus.matches(&result.document.path, &result.document.tags));
+            }
+            apply_boosts(&mut results, &query.text, &query.options, chrono::Utc::now().timestamp() as u64);
+
+            // Sort; ties break by path so equal scores order the same every run
+            results.sort_by(|a, b| {
+                b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| a.document.path.cmp(&b.document.path))
+            });
+            anyhow::Ok(results)
+        })?;
+        results = match query.options.mmr_lambda {
+            Some(lambda) => trace.time_async(QueryStage::Rerank, self.select_diverse(results, lambda, retrieval.limit)).await,
+            None => {
+                results.truncate(retrieval.limit);
+                results
+            }
+        };
+
+        let results: Vec<SearchResult> = results.into_iter().skip(query.options.offset).collect();
+        self.record_access(results.iter().map(|result| result.document.path.as_path())).await;
+        Ok(results)
+    }
+
+    /// Count reads of `paths` in the access log; a failure to record doesn't fail the read
+    async fn record_access(&self, paths: impl Iterator<Item = &Path>) {
+        let Some(access_log) = &self.access_log else {
+            return;
+        };
+        for path in paths {
+            if let Err(e) = access_log.record_access(path).await {
+                self.logger.warn(&format!("Failed to record access to {}: {}", path.display(), e));
+            }
         }
+    }
 
-        // Apply filters
-        results = self.apply_filters(results, &query.filters)?;
+    /// Maximal Marginal Relevance: repeatedly take the result with the best balance of score and
+    /// cosine distance from those already taken, so near-duplicate notes don't fill the page.
+    /// `candidates` must be sorted by score; results without an embedding count as dissimilar
+    async fn select_diverse(&self, mut candidates: Vec<SearchResult>, lambda: f32, limit: usize) -> Vec<SearchResult> {
+        let index = self.index.read().await;
+        let embedding = |result: &SearchResult| index.embeddings.get(result.document.path.to_string_lossy().as_ref());
+        let mut selected: Vec<SearchResult> = Vec::new();
+
+        while selected.len() < limit && !candidates.is_empty() {
+            let mmr = |candidate: &SearchResult| {
+                let redundancy = match embedding(candidate) {
+                    Some(candidate_emb) => selected
+                        .iter()
+                        .filter_map(embedding)
+                        .map(|chosen_emb| cosine_similarity(candidate_emb, chosen_emb))
+                        .fold(0.0, f32::max),
+                    None => 0.0,
+                };
+                lambda * candidate.score - (1.0 - lambda) * redundancy
+            };
 
-        // Sort and limit results
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
-        results.truncate(query.options.limit);
+            // Ties keep the earlier, higher-scoring candidate
+            let mut best = 0;
+            let mut best_score = mmr(&candidates[0]);
+            for (i, candidate) in candidates.iter().enumerate().skip(1) {
+                let score = mmr(candidate);
+                if score > best_score {
+                    best = i;
+                    best_score = score;
+                }
+            }
+            selected.push(candidates.remove(best));
+        }
 
-        Ok(results)
+        selected
     }
 
-    async fn semantic_search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
-        // This would use the embeddings engine to get query embedding
-        // For now, we'll simulate with a placeholder
-        let query_embedding = vec![0.0; 384]; // Placeholder - should come from embeddings engine
+    async fn semantic_search_traced(&self, query: &str, options: &SearchOptions, trace: &mut QueryTrace) -> Result<Vec<SearchResult>> {
+        let Some(embedder) = &self.query_embedder else {
+            self.logger.debug("No query embedder configured; skipping semantic search");
+            return Ok(Vec::new());
+        };
+
+        let query_embedding = trace.time_async(QueryStage::Embed, embedder.embed(query)).await
+            .context("Failed to embed search query")?;
+        trace.time_async(QueryStage::VectorSearch, self.semantic_search_by_vector(query, &query_embedding, options)).await
+    }
 
+    /// Rank documents against a query embedding, blending in title similarity when enabled
+    pub async fn semantic_search_by_vector(
+        &self,
+        query: &str,
+        query_embedding: &[f32],
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
         let index = self.index.read().await;
         let mut results = Vec::new();
 
-        for (doc_id, doc_embedding) in &index.embeddings {
-            let similarity = self.cosine_similarity(&query_embedding, doc_embedding);
+        if let Some(stored) = index.embeddings.values().next() {
+            if stored.len() != query_embedding.len() {
+                return Err(anyhow::anyhow!(
+                    "Query embedding has {} dimensions but the index holds {}-dimensional embeddings; \
+                     embed queries with the model the vault was indexed with",
+                    query_embedding.len(), stored.len()
+                ));
+            }
+        }
+
+        let use_hnsw = match options.vector_index {
+            VectorIndexMode::BruteForce => false,
+            VectorIndexMode::Hnsw => true,
+            VectorIndexMode::Auto => index.hnsw.len() + index.unindexed_vectors() >= HNSW_MIN_VECTORS,
+        };
+        // The graph only shortlists; every candidate is re-scored exactly below, with title
+        // blending, so fetch enough that the re-scoring can still reorder the top results.
+        // Documents not in the graph yet are always candidates.
+        let shortlist: Option<Vec<String>> = use_hnsw.then(|| {
+            let k = (options.limit * 4).max(50);
+            let mut doc_ids: Vec<String> = index.hnsw.search(query_embedding, k, k * 2)
+                .into_iter().map(|(doc_id, _)| doc_id).collect();
+            doc_ids.extend(index.pending.iter().cloned());
+            doc_ids
+        });
+        let doc_ids: Vec<&str> = match &shortlist {
+            Some(doc_ids) => doc_ids.iter().map(String::as_str).collect(),
+            None => index.embeddings.keys().map(String::as_str).collect(),
+        };
+
+        for doc_id in doc_ids {
+            let Some(similarity) = self.blended_similarity(&index, doc_id, query_embedding, options) else {
+                continue;
+            };
             
             if similarity >= options.similarity_threshold {
                 if let Some(doc) = index.documents.get(doc_id) {
                     let search_doc = SearchDocument {
                         path: doc.path.clone(),
                         title: doc.title.clone(),
-                        snippet: self.generate_snippet(&doc.content, query, 200),
+                        snippet: self.generate_snippet(&doc.content, query, options.snippet_length),
                         tags: doc.tags.clone(),
                         modified: doc.modified,
                         word_count: doc.word_count,
+
+                        links: doc.links.clone(),
                     };
 
                     let context = if options.include_context {
@@ -441,26 +1243,146 @@ impl VectorSearchEngine {
         Ok(results)
     }
 
-    async fn text_search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
-        let conn = Connection::open(&self.db_path)?;
-        
-        let mut stmt = conn.prepare(
-            "SELECT document_path, title, content, tags, modified, word_count, 
-                    bm25(search_fts) as score
-             FROM search_fts 
-             WHERE search_fts MATCH ?1
-             ORDER BY score
-             LIMIT ?2"
-        )?;
+    /// Body (or best section) similarity to the query, blended with title similarity when enabled;
+    /// `None` if not embedded
+    fn blended_similarity(&self, index: &VectorIndex, doc_id: &str, query_embedding: &[f32], options: &SearchOptions) -> Option<f32> {
+        let metric = self.distance_metric;
+        let mut similarity = metric.similarity(query_embedding, index.embeddings.get(doc_id)?);
 
-        let rows = stmt.query_map(params![query, options.limit], |row| {
-            let path: String = row.get(0)?;
+        // A long note matches as well as its best section
+        if let Some(blocks) = index.block_embeddings.get(doc_id) {
+            for block in blocks.iter().filter(|b| !matches!(b.block_type, BlockType::Symbol { .. })) {
+                similarity = similarity.max(metric.similarity(query_embedding, &block.embedding));
+            }
+        }
+
+        if options.boost_titles {
+            if let Some(title_embedding) = index.title_embeddings.get(doc_id) {
+                let title_similarity = metric.similarity(query_embedding, title_embedding);
+                let weight = options.title_weight.clamp(0.0, 1.0);
+                similarity = similarity * (1.0 - weight) + title_similarity * weight;
+            }
+        }
+
+        Some(similarity)
+    }
+
+    /// Why `path` would or wouldn't come back for `query`: its similarity against the threshold,
+    /// how much of the query its text matches, and the first filter that excludes it
+    pub async fn explain_retrieval(&self, query: &SearchQuery, query_embedding: &[f32], path: &Path) -> Result<RetrievalExplanation> {
+        let index = self.index.read().await;
+        let doc_id = path.to_string_lossy().to_string();
+        let threshold = query.options.similarity_threshold;
+
+        let Some(doc) = index.documents.get(&doc_id) else {
+            return Ok(RetrievalExplanation {
+                path: path.to_path_buf(),
+                semantic_similarity: None,
+                threshold,
+                passed_threshold: false,
+                text_score: 0.0,
+                excluded_by: None,
+                verdict: RetrievalVerdict::NotIndexed,
+            });
+        };
+
+        let semantic_similarity = self.blended_similarity(&index, &doc_id, query_embedding, &query.options);
+        let passed_threshold = semantic_similarity.is_some_and(|similarity| similarity >= threshold);
+
+        // Share of query terms in the document; full-text search needs all of them
+        let content = doc.content.to_lowercase();
+        let terms: Vec<String> = query.text.split_whitespace().map(|t| t.to_lowercase()).collect();
+        let text_score = if terms.is_empty() {
+            0.0
+        } else {
+            terms.iter().filter(|term| content.contains(term.as_str())).count() as f32 / terms.len() as f32
+        };
+        let text_match = query.options.hybrid_search && text_score >= 1.0;
+
+        let search_doc = SearchDocument {
+            path: doc.path.clone(),
+            title: doc.title.clone(),
+            snippet: String::new(),
+            tags: doc.tags.clone(),
+            modified: doc.modified,
+            word_count: doc.word_count,
+
+            links: doc.links.clone(),
+        };
+        let excluded_by = Self::excluding_filter(&search_doc, &query.filters).map(str::to_string);
+
+        let verdict = if let Some(filter) = &excluded_by {
+            RetrievalVerdict::FilteredOut(filter.clone())
+        } else if passed_threshold || text_match {
+            RetrievalVerdict::Retrieved
+        } else if semantic_similarity.is_none() {
+            RetrievalVerdict::NotEmbedded
+        } else {
+            RetrievalVerdict::BelowThreshold
+        };
+
+        Ok(RetrievalExplanation {
+            path: path.to_path_buf(),
+            semantic_similarity,
+            threshold,
+            passed_threshold,
+            text_score,
+            excluded_by,
+            verdict,
+        })
+    }
+
+    /// Keyword search over titles, content and tags; higher scores are better matches
+    pub async fn text_search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        let results = self.text_search_in(query, None, options).await?;
+        self.record_access(results.iter().map(|result| result.document.path.as_path())).await;
+        Ok(results)
+    }
+
+    /// Keyword search with the analyzer for `language`, detected from the query when `None`.
+    /// Without language routing every note shares the default analyzer.
+    pub async fn text_search_in(&self, query: &str, language: Option<&str>, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        let table = if self.languages.enabled {
+            FtsAnalyzer::for_language(&self.languages.query_language(query, language)).table()
+        } else {
+            "search_fts"
+        };
+
+        // Quote each term so punctuation in user input isn't read as FTS syntax
+        let terms: Vec<String> = query.split_whitespace()
+            .map(|term| term.replace('"', ""))
+            .filter(|term| !term.is_empty())
+            .map(|term| format!("\"{}\"", term))
+            .collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // With a focus, every match is fetched and the limit applied after filtering
+        let focus = self.focus.current().await;
+        let limit = if focus.is_some() { -1 } else { options.limit as i64 };
+
+        let conn = Connection::open(&self.db_path)?;
+        
+        let mut stmt = conn.prepare(&format!(
+            "SELECT si.document_path, si.title, si.content, si.tags, si.modified, si.word_count,
+                    bm25({table}) as score
+             FROM {table}
+             JOIN search_index si ON si.rowid = {table}.rowid
+             WHERE {table} MATCH ?1
+             ORDER BY score ASC
+             LIMIT ?2",
+            table = table
+        ))?;
+
+        let rows = stmt.query_map(params![terms.join(" OR "), limit], |row| {
+            let path: String = row.get(0)?;
             let title: String = row.get(1)?;
             let content: String = row.get(2)?;
             let tags_json: String = row.get(3)?;
             let modified: i64 = row.get(4)?;
             let word_count: i64 = row.get(5)?;
-            let score: f64 = row.get(6)?;
+            let bm25: f64 = row.get(6)?;
 
             let tags: Vec<String> = serde_json::from_str(&tags_json)
                 .unwrap_or_default();
@@ -469,12 +1391,13 @@ impl VectorSearchEngine {
                 document: SearchDocument {
                     path: PathBuf::from(path),
                     title: title.clone(),
-                    snippet: self.generate_snippet(&content, query, 200),
+                    snippet: self.generate_snippet(&content, query, options.snippet_length),
                     tags,
                     modified: modified as u64,
                     word_count: word_count as usize,
+                    links: Vec::new(),
                 },
-                score: score as f32,
+                score: bm25_relevance(bm25),
                 match_type: MatchType::Exact,
                 matched_content: query.to_string(),
                 context: SearchContext {
@@ -490,6 +1413,15 @@ impl VectorSearchEngine {
         for row in rows {
             results.push(row?);
         }
+        let paths: Vec<String> = results.iter().map(|r| r.document.path.to_string_lossy().to_string()).collect();
+        let mut links = stored_links(&conn, Some(&paths))?;
+        for result in &mut results {
+            result.document.links = links.remove(result.document.path.to_string_lossy().as_ref()).unwrap_or_default();
+        }
+        if let Some(focus) = focus {
+            results.retain(|result| focus.matches(&result.document.path, &result.document.tags));
+            results.truncate(options.limit);
+        }
 
         Ok(results)
     }
@@ -501,25 +1433,32 @@ impl VectorSearchEngine {
 
         let index = self.index.read().await;
         let mut results = Vec::new();
+        let mut seen = HashSet::new();
 
-        for (tag, doc_ids) in &index.tag_index {
-            if tags.iter().any(|t| tag.contains(t) || t.contains(tag)) {
+        // Ancestors are indexed too, so a parent tag's entry already holds every descendant
+        for tag in tags.iter().map(|t| normalize_tag(t)) {
+            if let Some(doc_ids) = index.tag_index.get(tag) {
                 for doc_id in doc_ids {
+                    if !seen.insert(doc_id) {
+                        continue;
+                    }
                     if let Some(doc) = index.documents.get(doc_id) {
                         let search_doc = SearchDocument {
                             path: doc.path.clone(),
                             title: doc.title.clone(),
-                            snippet: self.generate_snippet(&doc.content, &tags.join(" "), 200),
+                            snippet: self.generate_snippet(&doc.content, &tags.join(" "), options.snippet_length),
                             tags: doc.tags.clone(),
                             modified: doc.modified,
                             word_count: doc.word_count,
+
+                            links: doc.links.clone(),
                         };
 
                         results.push(SearchResult {
                             document: search_doc,
                             score: 0.8, // High score for tag matches
                             match_type: MatchType::Tag,
-                            matched_content: tag.clone(),
+                            matched_content: tag.to_string(),
                             context: SearchContext {
                                 matched_blocks: Vec::new(),
                                 surrounding_context: String::new(),
@@ -535,6 +1474,18 @@ impl VectorSearchEngine {
         Ok(results)
     }
 
+    /// Paths of documents tagged `prefix` or any tag nested under it, e.g. `project` for `project/rust/async`
+    pub async fn get_documents_by_tag_prefix(&self, prefix: &str) -> Vec<PathBuf> {
+        let index = self.index.read().await;
+        let mut paths: Vec<PathBuf> = index.tag_index.get(normalize_tag(prefix))
+            .into_iter()
+            .flatten()
+            .filter_map(|doc_id| index.documents.get(doc_id).map(|doc| doc.path.clone()))
+            .collect();
+        paths.sort();
+        paths
+    }
+
     fn merge_search_results(
         &self,
         semantic: Vec<SearchResult>,
@@ -542,87 +1493,111 @@ impl VectorSearchEngine {
         tag: Vec<SearchResult>,
         options: &SearchOptions,
     ) -> Result<Vec<SearchResult>> {
-        let mut merged = HashMap::new();
-
-        // Add semantic results
-        for result in semantic {
-            let key = result.document.path.clone();
-            merged.insert(key, result);
-        }
+        // Text and tag hits count for less than semantic ones
+        const SEMANTIC_WEIGHT: f32 = 1.0;
+        const TEXT_WEIGHT: f32 = 0.7;
+        const TAG_WEIGHT: f32 = 0.5;
+
+        let capacity = semantic.len() + text.len() + tag.len();
+        let mut candidates = Vec::with_capacity(capacity);
+        candidates.extend(semantic.into_iter().map(|r| (r, SEMANTIC_WEIGHT)));
+        candidates.extend(text.into_iter().map(|r| (r, TEXT_WEIGHT)));
+        candidates.extend(tag.into_iter().map(|r| (r, TAG_WEIGHT)));
+
+        // Intern each path to the index of its first candidate, borrowing rather than cloning keys
+        let first_seen: Vec<usize> = {
+            let mut ids: HashMap<&Path, usize> = HashMap::with_capacity(capacity);
+            candidates
+                .iter()
+                .enumerate()
+                .map(|(i, (result, _))| *ids.entry(result.document.path.as_path()).or_insert(i))
+                .collect()
+        };
 
-        // Boost text results
-        for mut result in text {
-            let key = result.document.path.clone();
-            if let Some(existing) = merged.get_mut(&key) {
-                existing.score = (existing.score + result.score * 0.7).max(existing.score);
-                existing.match_type = MatchType::Hybrid;
-            } else {
-                result.score *= 0.7;
-                merged.insert(key, result);
+        // Position in `merged` for each interned id, once materialized
+        let mut slots: Vec<Option<usize>> = vec![None; capacity];
+        let mut merged: Vec<SearchResult> = Vec::with_capacity(capacity);
+
+        for (i, (mut result, weight)) in candidates.into_iter().enumerate() {
+            let id = first_seen[i];
+            match slots[id] {
+                Some(slot) if weight == SEMANTIC_WEIGHT => merged[slot] = result,
+                Some(slot) => {
+                    let existing = &mut merged[slot];
+                    existing.score = (existing.score + result.score * weight).max(existing.score);
+                    existing.match_type = MatchType::Hybrid;
+                }
+                None => {
+                    result.score *= weight;
+                    slots[id] = Some(merged.len());
+                    merged.push(result);
+                }
             }
         }
 
-        // Boost tag results
-        for mut result in tag {
-            let key = result.document.path.clone();
-            if let Some(existing) = merged.get_mut(&key) {
-                existing.score = (existing.score + result.score * 0.5).max(existing.score);
-                existing.match_type = MatchType::Hybrid;
-            } else {
-                result.score *= 0.5;
-                merged.insert(key, result);
-            }
+        for result in &mut merged {
+            result.score *= options.source_weights.weight(SourceType::from_tags(&result.document.tags));
         }
-
-        Ok(merged.into_values().collect())
+        Ok(merged)
     }
 
     fn apply_filters(&self, mut results: Vec<SearchResult>, filters: &SearchFilters) -> Result<Vec<SearchResult>> {
-        results.retain(|result| {
-            // Filter by tags
-            if !filters.tags.is_empty() {
-                let has_tag = filters.tags.iter().any(|filter_tag| {
-                    result.document.tags.iter().any(|doc_tag| doc_tag.contains(filter_tag))
-                });
-                if !has_tag {
-                    return false;
-                }
+        results.retain(|result| Self::excluding_filter(&result.document, filters).is_none());
+        Ok(results)
+    }
+
+    /// Name of the first filter the document fails, if any
+    fn excluding_filter(document: &SearchDocument, filters: &SearchFilters) -> Option<&'static str> {
+        // Filter by tags; a nested tag such as `project/rust` counts as having `project`
+        if !filters.tags.is_empty() {
+            let tags = expand_tags(&document.tags);
+            let has_tag = filters.tags.iter().any(|filter_tag| tags.contains(normalize_tag(filter_tag)));
+            if !has_tag {
+                return Some("tags");
             }
+        }
 
-            // Filter by paths
-            if !filters.paths.is_empty() {
-                let matches_path = filters.paths.iter().any(|filter_path| {
-                    result.document.path.starts_with(filter_path)
-                });
-                if !matches_path {
-                    return false;
-                }
+        if !filters.file_types.is_empty() {
+            let extension = document.path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+            let matches_type = filters.file_types.iter().any(|file_type| {
+                extension.as_deref() == Some(file_type.trim_start_matches('.').to_lowercase().as_str())
+            });
+            if !matches_type {
+                return Some("file_types");
             }
+        }
 
-            // Filter by date range
-            if let Some((start, end)) = filters.date_range {
-                if result.document.modified < start || result.document.modified > end {
-                    return false;
-                }
+        // Filter by paths
+        if !filters.paths.is_empty() {
+            let matches_path = filters.paths.iter().any(|filter_path| {
+                document.path.starts_with(filter_path)
+            });
+            if !matches_path {
+                return Some("paths");
             }
+        }
 
-            // Filter by word count
-            if let Some(min_words) = filters.min_words {
-                if result.document.word_count < min_words {
-                    return false;
-                }
+        // Filter by date range
+        if let Some((start, end)) = filters.date_range {
+            if document.modified < start || document.modified > end {
+                return Some("date_range");
             }
+        }
 
-            if let Some(max_words) = filters.max_words {
-                if result.document.word_count > max_words {
-                    return false;
-                }
+        // Filter by word count
+        if let Some(min_words) = filters.min_words {
+            if document.word_count < min_words {
+                return Some("min_words");
             }
+        }
 
-            true
-        });
+        if let Some(max_words) = filters.max_words {
+            if document.word_count > max_words {
+                return Some("max_words");
+            }
+        }
 
-        Ok(results)
+        None
     }
 
     async fn build_search_context(&self, doc: &IndexedDocument, query: &str, index: &VectorIndex) -> Result<SearchContext> {
@@ -643,16 +1618,9 @@ impl VectorSearchEngine {
 
         // Find backlinks
         let doc_path = doc.path.to_string_lossy().to_string();
-        let mut backlinks = Vec::new();
-        for (link_target, linking_docs) in &index.link_graph {
-            if link_target.contains(&doc.title) || link_target.contains(&doc_path) {
-                for linking_doc in linking_docs {
-                    if let Some(linking_document) = index.documents.get(linking_doc) {
-                        backlinks.push(linking_document.title.clone());
-                    }
-                }
-            }
-        }
+        let backlinks = index.linking_documents(&doc_path, self.duplicate_link_targets).into_iter()
+            .map(|linking| linking.title.clone())
+            .collect();
 
         // Find related tags
         let mut related_tags = HashSet::new();
@@ -678,43 +1646,8 @@ impl VectorSearchEngine {
         })
     }
 
-    fn cosine_similarity(&self, a: &[f32], b: &[f32]) -> f32 {
-        if a.len() != b.len() {
-            return 0.0;
-        }
-
-        let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-
-        if norm_a == 0.0 || norm_b == 0.0 {
-            0.0
-        } else {
-            dot_product / (norm_a * norm_b)
-        }
-    }
-
     fn generate_snippet(&self, content: &str, query: &str, max_length: usize) -> String {
-        let query_lower = query.to_lowercase();
-        let content_lower = content.to_lowercase();
-
-        if let Some(pos) = content_lower.find(&query_lower) {
-            let start = pos.saturating_sub(max_length / 2);
-            let end = (pos + query.len() + max_length / 2).min(content.len());
-            
-            let mut snippet = content[start..end].to_string();
-            
-            if start > 0 {
-                snippet = format!("...{}", snippet);
-            }
-            if end < content.len() {
-                snippet = format!("{}...", snippet);
-            }
-            
-            snippet
-        } else {
-            content.chars().take(max_length).collect::<String>()
-        }
+        snippet::generate(content, query, max_length).text
     }
 
     fn serialize_embedding(&self, embedding: &[f32]) -> Result<Vec<u8>> {
@@ -742,9 +1675,9 @@ impl VectorSearchEngine {
         let conn = Connection::open(&self.db_path)?;
         let mut index = self.index.write().await;
 
-        // Load documents from search index
+        // Load documents from search index, with the blocks results are matched and tasks listed from
         let mut stmt = conn.prepare(
-            "SELECT document_path, title, content, tags, modified, word_count FROM search_index"
+            "SELECT document_path, title, content, tags, modified, word_count, blocks FROM search_index"
         )?;
 
         let rows = stmt.query_map([], |row| {
@@ -754,14 +1687,16 @@ impl VectorSearchEngine {
             let tags_json: String = row.get(3)?;
             let modified: i64 = row.get(4)?;
             let word_count: i64 = row.get(5)?;
+            let blocks_json: String = row.get(6)?;
 
             let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+            let blocks: Vec<IndexedBlock> = serde_json::from_str(&blocks_json).unwrap_or_default();
 
-            Ok((path, title, content, tags, modified as u64, word_count as usize))
+            Ok((path, title, content, tags, modified as u64, word_count as usize, blocks))
         })?;
 
         for row in rows {
-            let (path_str, title, content, tags, modified, word_count) = row?;
+            let (path_str, title, content, tags, modified, word_count, blocks) = row?;
             let path = PathBuf::from(&path_str);
 
             let indexed_doc = IndexedDocument {
@@ -771,19 +1706,62 @@ impl VectorSearchEngine {
                 tags: tags.clone(),
                 modified,
                 word_count,
-                blocks: Vec::new(), // Will be populated separately if needed
+                blocks,
+                links: Vec::new(),
             };
 
             index.documents.insert(path_str.clone(), indexed_doc);
             index.title_index.insert(title, path_str.clone());
 
-            for tag in tags {
-                index.tag_index.entry(tag)
-                    .or_insert_with(HashSet::new)
-                    .insert(path_str.clone());
+            index.add_tags(&path_str, &tags);
+        }
+
+        let mut stmt = conn.prepare("SELECT source_path, target, heading, block_id FROM links ORDER BY rowid")?;
+        let links = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))
+        })?;
+        for link in links {
+            let (source, target, heading, block_id) = link?;
+            index.add_link(&source, &target, heading.as_deref(), block_id.as_deref());
+        }
+
+        // Body and title vectors, so semantic search and title blending work after a restart
+        for (table, titles) in [("document_embeddings", false), ("title_embeddings", true)] {
+            let mut stmt = conn.prepare(&format!("SELECT document_path, embedding FROM {}", table))?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))?;
+            for row in rows {
+                let (doc_id, bytes) = row?;
+                let vector = self.deserialize_embedding(&bytes)?;
+                if titles {
+                    index.title_embeddings.insert(doc_id, vector);
+                } else {
+                    index.embeddings.insert(doc_id, vector);
+                }
             }
         }
 
+        let mut stmt = conn.prepare(
+            "SELECT document_path, block_id, block_type, content, embedding FROM block_embeddings ORDER BY document_path, id"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?, row.get::<_, Vec<u8>>(4)?))
+        })?;
+        for row in rows {
+            let (doc_id, block_id, block_type, content, bytes) = row?;
+            let block = BlockEmbedding {
+                block_id,
+                embedding: self.deserialize_embedding(&bytes)?,
+                content,
+                block_type: serde_json::from_str(&block_type)?,
+            };
+            index.block_embeddings.entry(doc_id).or_default().push(block);
+        }
+
+        // Rebuild the graph from the stored vectors, the same ones `index_document` queues
+        let doc_ids: Vec<String> = index.embeddings.keys().cloned().collect();
+        index.pending.extend(doc_ids);
+        index.index_pending();
+
         self.logger.info(&format!("Loaded {} documents into search index", index.documents.len()));
         Ok(())
     }
@@ -791,48 +1769,1601 @@ impl VectorSearchEngine {
     pub async fn remove_document(&self, path: &PathBuf) -> Result<()> {
         let doc_id = path.to_string_lossy().to_string();
         
-        // Remove from database
-        let conn = Connection::open(&self.db_path)?;
-        conn.execute("DELETE FROM document_embeddings WHERE document_path = ?1", params![doc_id])?;
-        conn.execute("DELETE FROM block_embeddings WHERE document_path = ?1", params![doc_id])?;
-        conn.execute("DELETE FROM search_index WHERE document_path = ?1", params![doc_id])?;
-        conn.execute("DELETE FROM search_fts WHERE rowid IN (SELECT rowid FROM search_index WHERE document_path = ?1)", params![doc_id])?;
+        // Remove from database; full-text rows are found through search_index, so go first
+        {
+            let mut conn = Connection::open(&self.db_path)?;
+            let tx = conn.transaction()?;
+            self.remove_language_rows(&tx, &doc_id)?;
+            tx.execute("DELETE FROM search_fts WHERE rowid IN (SELECT rowid FROM search_index WHERE document_path = ?1)", params![doc_id])?;
+            tx.execute("DELETE FROM search_index WHERE document_path = ?1", params![doc_id])?;
+            tx.execute("DELETE FROM document_embeddings WHERE document_path = ?1", params![doc_id])?;
+            tx.execute("DELETE FROM title_embeddings WHERE document_path = ?1", params![doc_id])?;
+            tx.execute("DELETE FROM block_embeddings WHERE document_path = ?1", params![doc_id])?;
+            tx.execute("DELETE FROM qa_pairs WHERE document_path = ?1", params![doc_id])?;
+            tx.execute("DELETE FROM links WHERE source_path = ?1", params![doc_id])?;
+            tx.commit()?;
+        }
 
         // Remove from in-memory index
         let mut index = self.index.write().await;
+        index.remove_links(&doc_id);
         if let Some(doc) = index.documents.remove(&doc_id) {
             index.embeddings.remove(&doc_id);
+            index.hnsw.remove(&doc_id);
+            index.pending.remove(&doc_id);
+            index.title_embeddings.remove(&doc_id);
+            index.block_embeddings.remove(&doc_id);
             index.title_index.remove(&doc.title);
             
-            for tag in &doc.tags {
-                if let Some(tag_docs) = index.tag_index.get_mut(tag) {
+            for tag in expand_tags(&doc.tags) {
+                if let Some(tag_docs) = index.tag_index.get_mut(&tag) {
                     tag_docs.remove(&doc_id);
                     if tag_docs.is_empty() {
-                        index.tag_index.remove(tag);
+                        index.tag_index.remove(&tag);
                     }
                 }
             }
         }
+        self.corpus_version.fetch_add(1, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Merge each full-text index's segments and refresh SQLite's query planner statistics
+    pub async fn optimize(&self) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        for table in std::iter::once("search_fts").chain(FtsAnalyzer::ALL.iter().map(|analyzer| analyzer.table())) {
+            conn.execute(&format!("INSERT INTO {table}({table}) VALUES ('optimize')"), [])?;
+        }
+        conn.execute_batch("PRAGMA optimize")?;
+        Ok(())
+    }
+
+    /// Documents whose vector came from another model version, least recently embedded first
+    pub async fn stale_documents(&self, limit: usize) -> Result<Vec<PathBuf>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT document_path FROM document_embeddings WHERE model_version != ?1 ORDER BY updated_at, id LIMIT ?2"
+        )?;
+        let paths = stmt.query_map(params![self.model_version, limit as i64], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(paths)
+    }
+
+    pub async fn is_stale(&self, path: &Path) -> Result<bool> {
+        let conn = Connection::open(&self.db_path)?;
+        let version: Option<String> = conn.query_row(
+            "SELECT model_version FROM document_embeddings WHERE document_path = ?1",
+            params![path.to_string_lossy()],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(version.is_some_and(|v| v != self.model_version))
+    }
+
+    /// Stored document vectors as (document path, vector), ordered by path
+    pub async fn stored_document_embeddings(&self) -> Result<Vec<(String, Vec<f32>)>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT document_path, embedding FROM document_embeddings ORDER BY document_path"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+
+        let mut embeddings = Vec::new();
+        for row in rows {
+            let (path, bytes) = row?;
+            embeddings.push((path, self.deserialize_embedding(&bytes)?));
+        }
+        Ok(embeddings)
+    }
+
+    /// Every document in the text index, ordered by path
+    pub async fn stored_documents(&self) -> Result<Vec<DocumentRecord>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT document_path, title, content, tags, modified, word_count
+             FROM search_index ORDER BY document_path"
+        )?;
+
+        let rows = stmt.query_map([], document_record)?;
+
+        let mut documents = Vec::new();
+        for row in rows {
+            documents.push(row?);
+        }
+        let mut links = stored_links(&conn, None)?;
+        for doc in &mut documents {
+            doc.links = links.remove(doc.path.to_string_lossy().as_ref()).unwrap_or_default();
+        }
+        Ok(documents)
+    }
+
+    /// One stored document with its tags and links, if it's indexed
+    pub async fn get_document(&self, path: &Path) -> Result<Option<DocumentRecord>> {
+        let conn = Connection::open(&self.db_path)?;
+        let doc_id = path.to_string_lossy().to_string();
+        let document = conn.query_row(
+            "SELECT document_path, title, content, tags, modified, word_count
+             FROM search_index WHERE document_path = ?1",
+            params![doc_id],
+            document_record,
+        ).optional()?;
+
+        let document = match document {
+            Some(mut document) => {
+                document.links = stored_links(&conn, Some(std::slice::from_ref(&doc_id)))?.remove(&doc_id).unwrap_or_default();
+                Some(document)
+            }
+            None => None,
+        };
+        drop(conn);
+        if document.is_some() {
+            self.record_access(std::iter::once(path)).await;
+        }
+        Ok(document)
+    }
 
+    /// Replace the question/answer pairs of `path`, each with its question's vector
+    pub async fn replace_qa_pairs(&self, path: &Path, pairs: &[QaPair], vectors: &[Vec<f32>]) -> Result<()> {
+        let doc_id = path.to_string_lossy().to_string();
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM qa_pairs WHERE document_path = ?1", params![doc_id])?;
+        for (pair, vector) in pairs.iter().zip(vectors) {
+            tx.execute(
+                "INSERT INTO qa_pairs (document_path, question, answer, start_pos, embedding) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![doc_id, pair.question, pair.answer, pair.start_pos as i64, self.serialize_embedding(vector)?],
+            )?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// The stored pair whose question is most similar to `query`, if any reaches `min_similarity`
+    pub async fn direct_answer(&self, query: &str, min_similarity: f32) -> Result<Option<QaMatch>> {
+        let Some(embedder) = &self.query_embedder else {
+            return Ok(None);
+        };
+        let query_embedding = embedder.embed(query).await
+            .context("Failed to embed search query")?;
+
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare("SELECT document_path, question, answer, start_pos, embedding FROM qa_pairs")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                QaPair {
+                    source: PathBuf::from(row.get::<_, String>(0)?),
+                    question: row.get(1)?,
+                    answer: row.get(2)?,
+                    start_pos: row.get::<_, i64>(3)? as usize,
+                },
+                row.get::<_, Vec<u8>>(4)?,
+            ))
+        })?;
+
+        let mut best: Option<QaMatch> = None;
+        for row in rows {
+            let (pair, bytes) = row?;
+            let similarity = cosine_similarity(&query_embedding, &self.deserialize_embedding(&bytes)?);
+            if similarity >= min_similarity && best.as_ref().is_none_or(|b| similarity > b.similarity) {
+                best = Some(QaMatch { pair, similarity });
+            }
+        }
+        Ok(best)
+    }
+
+    /// Stored block vectors as (document path, block id, vector), ordered by path then block
+    pub async fn stored_block_embeddings(&self) -> Result<Vec<(String, String, Vec<f32>)>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT document_path, block_id, embedding FROM block_embeddings ORDER BY document_path, id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Vec<u8>>(2)?))
+        })?;
+
+        let mut embeddings = Vec::new();
+        for row in rows {
+            let (path, block_id, bytes) = row?;
+            embeddings.push((path, block_id, self.deserialize_embedding(&bytes)?));
+        }
+        Ok(embeddings)
+    }
+
+    /// Code symbols ranked by how well their name matches the query, and by embedding
+    /// similarity to `query_embedding` when the document's symbols were embedded
+    pub async fn search_symbols(&self, query: &str, query_embedding: Option<&[f32]>, limit: usize) -> Vec<SymbolMatch> {
+        let index = self.index.read().await;
+        let mut matches = Vec::new();
+
+        for (doc_id, doc) in &index.documents {
+            let embedded = index.block_embeddings.get(doc_id);
+            for block in &doc.blocks {
+                let BlockType::Symbol { kind, name } = &block.block_type else {
+                    continue;
+                };
+
+                let mut score = symbol_name_score(query, name);
+                if let (Some(query_embedding), Some(embedded)) = (query_embedding, embedded) {
+                    let vector = embedded.iter()
+                        .find(|b| matches!(&b.block_type, BlockType::Symbol { name: n, .. } if n == name))
+                        .map(|b| &b.embedding);
+                    if let Some(vector) = vector {
+                        score = score.max(cosine_similarity(query_embedding, vector));
+                    }
+                }
+
+                if score > 0.0 {
+                    matches.push(SymbolMatch {
+                        path: doc.path.clone(),
+                        name: name.clone(),
+                        kind: kind.clone(),
+                        content: block.content.clone(),
+                        start_pos: block.start_pos,
+                        end_pos: block.end_pos,
+                        score,
+                    });
+                }
+            }
+        }
+
+        matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        matches.truncate(limit);
+        matches
+    }
+
+    /// Unchecked `- [ ]` items across the vault, by path and then position in the note
+    pub async fn incomplete_tasks(&self) -> Vec<TaskItem> {
+        let index = self.index.read().await;
+        let mut tasks: Vec<TaskItem> = index.documents.values()
+            .flat_map(|doc| doc.blocks.iter()
+                .filter(|block| block.block_type == BlockType::Task { checked: false })
+                .map(|block| TaskItem {
+                    path: doc.path.clone(),
+                    content: block.content.clone(),
+                    start_pos: block.start_pos,
+                    end_pos: block.end_pos,
+                }))
+            .collect();
+        tasks.sort_by(|a, b| a.path.cmp(&b.path).then(a.start_pos.cmp(&b.start_pos)));
+        tasks
+    }
+
+    /// Documents linking to `path`, or only to its heading or `^block` when `anchor` is given
+    pub async fn backlinks(&self, path: &Path, anchor: Option<&str>) -> Vec<PathBuf> {
+        let index = self.index.read().await;
+        let path = path.to_string_lossy();
+        let exact = anchor.map(|anchor| format!("{}#{}", path, anchor));
+        let anchored = format!("{}#", path);
+        
+        let mut linking: Vec<PathBuf> = index.link_graph.iter()
+            .filter(|(target, _)| match &exact {
+                Some(exact) => *target == exact,
+                None => *target == path.as_ref() || target.starts_with(&anchored),
+            })
+            .flat_map(|(_, docs)| docs.iter())
+            .filter_map(|doc_id| index.documents.get(doc_id).map(|doc| doc.path.clone()))
+            .collect();
+        linking.sort();
+        linking.dedup();
+        linking
+    }
+
+    /// Documents linking to `path` or any of its headings or blocks; links the parser left
+    /// unresolved match by path, trailing path segments or title, per the duplicate target policy
+    pub async fn get_backlinks(&self, path: &Path) -> Vec<DocumentRecord> {
+        let index = self.index.read().await;
+        let linking = index.linking_documents(&path.to_string_lossy(), self.duplicate_link_targets);
+
+        linking.into_iter()
+            .map(|doc| DocumentRecord {
+                path: doc.path.clone(),
+                title: doc.title.clone(),
+                content: doc.content.clone(),
+                tags: doc.tags.clone(),
+                modified: doc.modified,
+                word_count: doc.word_count,
+
+                links: doc.links.clone(),
+            })
+            .collect()
+    }
+
+    /// How many other documents link to `path` and how many targets it links to
+    pub async fn link_counts(&self, path: &Path) -> LinkCounts {
+        let index = self.index.read().await;
+        let doc_id = path.to_string_lossy();
+        LinkCounts {
+            inbound: index.linking_documents(&doc_id, self.duplicate_link_targets).len(),
+            outbound: index.link_graph.values().filter(|sources| sources.contains(doc_id.as_ref())).count(),
+        }
+    }
+
     pub async fn get_stats(&self) -> Result<SearchStats> {
+        const TOP_TAGS: usize = 5;
         let index = self.index.read().await;
         
+        let mut top_tags: Vec<(String, usize)> = index.tag_index.iter()
+            .map(|(tag, docs)| (tag.clone(), docs.len()))
+            .collect();
+        top_tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_tags.truncate(TOP_TAGS);
+        
         Ok(SearchStats {
             total_documents: index.documents.len(),
             total_embeddings: index.embeddings.len(),
             total_tags: index.tag_index.len(),
             total_links: index.link_graph.len(),
+            top_tags,
         })
     }
 }
 
+/// Outcome of `explain_retrieval` for one document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RetrievalVerdict {
+    Retrieved,
+    /// The path isn't in the index at all
+    NotIndexed,
+    /// Indexed but without a document embedding, so only text search can find it
+    NotEmbedded,
+    BelowThreshold,
+    /// Excluded by the named filter (`tags`, `paths`, `date_range`, `min_words`, `max_words`)
+    FilteredOut(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrievalExplanation {
+    pub path: PathBuf,
+    /// Title-blended similarity as used for ranking; `None` when the document isn't embedded
+    pub semantic_similarity: Option<f32>,
+    pub threshold: f32,
+    pub passed_threshold: bool,
+    /// Share of query terms found in the document
+    pub text_score: f32,
+    pub excluded_by: Option<String>,
+    pub verdict: RetrievalVerdict,
+}
+
+/// A function, type or other definition found by `search_symbols`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolMatch {
+    pub path: PathBuf,
+    pub name: String,
+    pub kind: String,
+    pub content: String,
+    pub start_pos: usize,
+    pub end_pos: usize,
+    pub score: f32,
+}
+
+/// A task list item found by `incomplete_tasks`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskItem {
+    pub path: PathBuf,
+    pub content: String,
+    pub start_pos: usize,
+    pub end_pos: usize,
+}
+
+/// FTS5's `bm25()` is negative and lower for better matches; map it onto 0..1, higher
+/// being better, so text scores sort and blend like semantic and tag scores
+fn bm25_relevance(bm25: f64) -> f32 {
+    let relevance = (-bm25).max(0.0);
+    (relevance / (1.0 + relevance)) as f32
+}
+
+/// 1.0 for the exact name, less for substrings, and the share of query words found
+/// among the name's `snake_case`/`camelCase` parts otherwise
+fn symbol_name_score(query: &str, name: &str) -> f32 {
+    let query = query.trim().to_lowercase();
+    let lower = name.to_lowercase();
+    if query.is_empty() {
+        return 0.0;
+    }
+    if lower == query {
+        return 1.0;
+    }
+    if lower.contains(&query) {
+        return 0.8;
+    }
+
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for c in name.chars() {
+        if (c == '_' || c == '-' || c.is_uppercase()) && !current.is_empty() {
+            parts.push(std::mem::take(&mut current).to_lowercase());
+        }
+        if c != '_' && c != '-' {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current.to_lowercase());
+    }
+
+    let words: Vec<&str> = query.split_whitespace().collect();
+    let found = words.iter().filter(|word| parts.iter().any(|part| part == *word)).count();
+    0.6 * found as f32 / words.len() as f32
+}
+
 #[derive(Debug, Serialize)]
 pub struct SearchStats {
     pub total_documents: usize,
     pub total_embeddings: usize,
     pub total_tags: usize,
     pub total_links: usize,
+    /// Most used tags with their document counts
+    pub top_tags: Vec<(String, usize)>,
+}
+
+/// Serves the AI's passages from the search index: each hit becomes a passage of its matched
+/// blocks, or of the whole note when no block matched
+pub struct SearchRetriever {
+    engine: Arc<VectorSearchEngine>,
+    /// Used for every retrieval, except that the limit and MMR balance come from the query
+    options: SearchOptions,
+}
+
+impl SearchRetriever {
+    pub fn new(engine: Arc<VectorSearchEngine>, options: SearchOptions) -> Self {
+        Self { engine, options }
+    }
+}
+
+#[async_trait]
+impl Retriever for SearchRetriever {
+    async fn retrieve(&self, query: &ContextQuery, trace: &mut QueryTrace) -> Result<Vec<RetrievalResult>> {
+        let search = SearchQuery {
+            text: query.query.clone(),
+            filters: SearchFilters::default(),
+            options: SearchOptions {
+                limit: query.max_results,
+                mmr_lambda: query.mmr_lambda,
+                ..self.options.clone()
+            },
+        };
+        let results = self.engine.search_traced(&search, trace).await?;
+
+        let index = self.engine.index.read().await;
+        Ok(results.into_iter().enumerate().map(|(position, result)| {
+            let doc_id = result.document.path.to_string_lossy().to_string();
+            let content = if result.context.matched_blocks.is_empty() {
+                index.documents.get(&doc_id).map(|doc| doc.content.clone()).unwrap_or(result.document.snippet)
+            } else {
+                result.context.matched_blocks.iter().map(|block| block.content.as_str()).collect::<Vec<_>>().join("\n\n")
+            };
+            let metadata = HashMap::from([
+                ("title".to_string(), result.document.title),
+                ("tags".to_string(), result.document.tags.join(",")),
+                (SourceType::METADATA_KEY.to_string(), SourceType::from_tags(&result.document.tags).as_str().to_string()),
+            ]);
+            RetrievalResult {
+                document: Document {
+                    id: doc_id.clone(),
+                    content,
+                    metadata,
+                    embedding: None,
+                    chunk_index: 0,
+                    source: doc_id,
+                    timestamp: chrono::DateTime::from_timestamp(result.document.modified as i64, 0).unwrap_or_default(),
+                },
+                similarity_score: result.score,
+                relevance_score: result.score,
+                context_position: position,
+            }
+        }).collect())
+    }
+
+    fn corpus_version(&self) -> u64 {
+        self.engine.corpus_version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::vault::parser::ObsidianParser;
+    use crate::vault::focus::Focus;
+    use crate::test_support::BagOfWords;
+
+    fn embedding(vector: Vec<f32>, title_vector: Option<Vec<f32>>) -> EmbeddingVector {
+        EmbeddingVector {
+            text: String::new(),
+            vector,
+            model_name: "test".to_string(),
+            created_at: chrono::Utc::now(),
+            block_embeddings: None,
+            title_vector,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_title_match_ranks_above_body_only_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let titled = parser.parse_content(Path::new("Rust Ownership.md"), "# Rust Ownership\n\nNotes.").await.unwrap();
+        let body_only = parser.parse_content(Path::new("Misc.md"), "# Misc\n\nNotes.").await.unwrap();
+
+        // Both bodies are equally similar to the query; only one title matches it
+        let query = vec![1.0, 0.0, 0.0];
+        let body = vec![0.8, 0.6, 0.0];
+        engine.index_document(&titled, &embedding(body.clone(), Some(vec![1.0, 0.0, 0.0]))).await.unwrap();
+        engine.index_document(&body_only, &embedding(body, None)).await.unwrap();
+
+        let options = SearchOptions {
+            similarity_threshold: 0.5,
+            include_context: false,
+            ..SearchOptions::default()
+        };
+        let mut results = engine.semantic_search_by_vector("rust ownership", &query, &options).await.unwrap();
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].document.path, PathBuf::from("Rust Ownership.md"));
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[tokio::test]
+    async fn test_title_vectors_are_reloaded_on_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let title_match = parser.parse_content(Path::new("Rust.md"), "# Rust\n\nNotes").await.unwrap();
+        let body_match = parser.parse_content(Path::new("Other.md"), "# Other\n\nNotes").await.unwrap();
+        engine.index_document(&title_match, &embedding(vec![0.6, 0.8], Some(vec![1.0, 0.0]))).await.unwrap();
+        engine.index_document(&body_match, &embedding(vec![0.8, 0.6], Some(vec![0.0, 1.0]))).await.unwrap();
+
+        let restarted = VectorSearchEngine::new(db).unwrap();
+        restarted.initialize().await.unwrap();
+        let options = SearchOptions { boost_titles: true, title_weight: 0.5, similarity_threshold: 0.0, ..SearchOptions::default() };
+        let results = restarted.semantic_search_by_vector("rust", &[1.0, 0.0], &options).await.unwrap();
+        let mut ranked: Vec<_> = results.iter().map(|r| (r.document.path.clone(), r.score)).collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        assert_eq!(ranked[0].0, PathBuf::from("Rust.md"));
+    }
+
+    #[tokio::test]
+    async fn test_hnsw_graph_is_rebuilt_on_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        for (i, name) in ["North.md", "East.md", "South.md"].iter().enumerate() {
+            let angle = i as f32;
+            let document = parser.parse_content(Path::new(name), "Body").await.unwrap();
+            engine.index_document(&document, &embedding(vec![angle.cos(), angle.sin()], None)).await.unwrap();
+        }
+
+        let restarted = VectorSearchEngine::new(db).unwrap();
+        restarted.initialize().await.unwrap();
+        assert_eq!(restarted.index.read().await.hnsw.len(), 3);
+        let options = SearchOptions {
+            vector_index: VectorIndexMode::Hnsw,
+            similarity_threshold: 0.9,
+            include_context: false,
+            ..SearchOptions::default()
+        };
+        let results = restarted.semantic_search_by_vector("east", &[1f32.cos(), 1f32.sin()], &options).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document.path, PathBuf::from("East.md"));
+    }
+
+    #[tokio::test]
+    async fn test_explain_retrieval_identifies_below_threshold_exclusion() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let note = parser.parse_content(Path::new("Garden.md"), "# Garden\n\nTomatoes and basil.").await.unwrap();
+        engine.index_document(&note, &embedding(vec![0.6, 0.8, 0.0], None)).await.unwrap();
+
+        let mut query = SearchQuery {
+            text: "irrigation schedule".to_string(),
+            filters: SearchFilters::default(),
+            options: SearchOptions::default(),
+        };
+        let explanation = engine.explain_retrieval(&query, &[1.0, 0.0, 0.0], Path::new("Garden.md")).await.unwrap();
+
+        assert_eq!(explanation.verdict, RetrievalVerdict::BelowThreshold);
+        assert!((explanation.semantic_similarity.unwrap() - 0.6).abs() < 1e-5);
+        assert!(!explanation.passed_threshold);
+        assert_eq!(explanation.text_score, 0.0);
+        assert_eq!(explanation.excluded_by, None);
+
+        query.filters.min_words = Some(100);
+        let filtered = engine.explain_retrieval(&query, &[1.0, 0.0, 0.0], Path::new("Garden.md")).await.unwrap();
+        assert_eq!(filtered.verdict, RetrievalVerdict::FilteredOut("min_words".to_string()));
+
+        let missing = engine.explain_retrieval(&query, &[1.0, 0.0, 0.0], Path::new("Other.md")).await.unwrap();
+        assert_eq!(missing.verdict, RetrievalVerdict::NotIndexed);
+    }
+
+    fn result(path: &str, score: f32, match_type: MatchType) -> SearchResult {
+        SearchResult {
+            document: SearchDocument {
+                path: PathBuf::from(path),
+                title: String::new(),
+                snippet: String::new(),
+                tags: Vec::new(),
+                modified: 0,
+                word_count: 0,
+                links: Vec::new(),
+            },
+            score,
+            match_type,
+            matched_content: String::new(),
+            context: SearchContext {
+                matched_blocks: Vec::new(),
+                surrounding_context: String::new(),
+                backlinks: Vec::new(),
+                related_tags: Vec::new(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parent_tags_find_nested_tag_descendants() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [
+            ("Tokio.md", "# Tokio\n\n#project/rust/async #project/rust/web"),
+            ("Borrowck.md", "# Borrowck\n\n#project/rust"),
+            ("Garden.md", "# Garden\n\n#project/garden"),
+            ("Trust.md", "# Trust\n\n#trust"),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(engine.get_documents_by_tag_prefix("#project").await, paths(&["Borrowck.md", "Garden.md", "Tokio.md"]));
+        assert_eq!(engine.get_documents_by_tag_prefix("project/rust").await, paths(&["Borrowck.md", "Tokio.md"]));
+        assert_eq!(engine.get_documents_by_tag_prefix("project/rust/async").await, paths(&["Tokio.md"]));
+        assert!(engine.get_documents_by_tag_prefix("project/ru").await.is_empty());
+
+        // Tokio reaches `project` through two tags but is returned once
+        let results = engine.tag_search(&["project".to_string(), "project/rust".to_string()], &SearchOptions::default()).await.unwrap();
+        let mut found: Vec<_> = results.iter().map(|r| r.document.path.clone()).collect();
+        found.sort();
+        assert_eq!(found, paths(&["Borrowck.md", "Garden.md", "Tokio.md"]));
+
+        engine.remove_document(&PathBuf::from("Tokio.md")).await.unwrap();
+        assert!(engine.get_documents_by_tag_prefix("project/rust/async").await.is_empty());
+        assert_eq!(engine.get_documents_by_tag_prefix("project/rust").await, paths(&["Borrowck.md"]));
+        assert_eq!(engine.get_stats().await.unwrap().total_tags, 4);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search_embeds_the_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["tomato", "tax"])));
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let garden = parser.parse_content(Path::new("Garden.md"), "# Garden\n\nNotes.").await.unwrap();
+        let taxes = parser.parse_content(Path::new("Taxes.md"), "# Taxes\n\nNotes.").await.unwrap();
+        engine.index_document(&garden, &embedding(vec![0.9, 0.1], None)).await.unwrap();
+        engine.index_document(&taxes, &embedding(vec![0.1, 0.9], None)).await.unwrap();
+
+        let query = SearchQuery {
+            text: "when to water the tomato seedlings".to_string(),
+            filters: SearchFilters::default(),
+            options: SearchOptions { similarity_threshold: 0.0, hybrid_search: false, include_context: false, ..SearchOptions::default() },
+        };
+        let results = engine.search(&query).await.unwrap();
+        assert_eq!(results[0].document.path, PathBuf::from("Garden.md"));
+        assert!(results[0].score > results[1].score);
+
+        let query = SearchQuery { text: "invoice and budget for the tax year".to_string(), ..query };
+        assert_eq!(engine.search(&query).await.unwrap()[0].document.path, PathBuf::from("Taxes.md"));
+
+        let query = SearchQuery {
+            options: SearchOptions { vector_index: VectorIndexMode::Hnsw, ..query.options.clone() },
+            ..query
+        };
+        assert_eq!(engine.search(&query).await.unwrap()[0].document.path, PathBuf::from("Taxes.md"));
+
+        let mismatched = VectorSearchEngine::new(dir.path().join("other.db")).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["tomato", "tax", "soil"])));
+        mismatched.initialize().await.unwrap();
+        mismatched.index_document(&garden, &embedding(vec![0.9, 0.1], None)).await.unwrap();
+        let error = mismatched.search(&query).await.unwrap_err().to_string();
+        assert!(error.contains("3 dimensions") && error.contains("2-dimensional"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_text_search_finds_indexed_notes_by_keyword() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [
+            ("Tomatoes.md", "# Tomatoes\n\nWater tomatoes daily, tomatoes love sun."),
+            ("Basil.md", "# Basil\n\nPinch the tops; keep near the tomatoes."),
+            ("Taxes.md", "# Taxes\n\nDue in April."),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+        // Re-indexing a note must not leave a stale match behind
+        let document = parser.parse_content(Path::new("Taxes.md"), "# Taxes\n\nDue in April.").await.unwrap();
+        engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+
+        let results = engine.text_search("tomatoes \"sun", &SearchOptions::default()).await.unwrap();
+        let paths: Vec<_> = results.iter().map(|r| r.document.path.to_string_lossy().to_string()).collect();
+        assert_eq!(paths, ["Tomatoes.md", "Basil.md"]);
+        assert!(results[0].score > results[1].score);
+        assert_eq!(results[0].document.title, "Tomatoes");
+
+        assert_eq!(engine.text_search("april", &SearchOptions::default()).await.unwrap().len(), 1);
+        assert!(engine.text_search("  ", &SearchOptions::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_text_search_ranks_higher_term_frequency_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        // Same length, so only how often "compost" appears differs
+        for (name, body) in [
+            ("Once.md", "compost leaves grass straw mulch soil bark"),
+            ("Thrice.md", "compost leaves compost straw compost soil bark"),
+            ("Twice.md", "compost leaves grass compost mulch soil bark"),
+            ("Unrelated.md", "invoice receipts budget ledger taxes april bank"),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+
+        let results = engine.text_search("compost", &SearchOptions::default()).await.unwrap();
+        let paths: Vec<_> = results.iter().map(|r| r.document.path.to_string_lossy().to_string()).collect();
+        assert_eq!(paths, ["Thrice.md", "Twice.md", "Once.md"]);
+        assert!(results.windows(2).all(|pair| pair[0].score > pair[1].score));
+        assert!(results.iter().all(|r| r.score > 0.0 && r.score < 1.0));
+
+        // The merged ranking keeps the same order
+        let merged = engine.merge_search_results(Vec::new(), results, Vec::new(), &SearchOptions::default()).unwrap();
+        let query = SearchQuery {
+            text: "compost".to_string(),
+            filters: SearchFilters::default(),
+            options: SearchOptions { include_context: false, ..SearchOptions::default() },
+        };
+        let searched = engine.search(&query).await.unwrap();
+        assert_eq!(
+            searched.iter().map(|r| &r.document.path).collect::<Vec<_>>(),
+            merged.iter().map(|r| &r.document.path).collect::<Vec<_>>(),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_embeddings_keep_their_block_type_and_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let document = parser.parse_content(
+            Path::new("Setup.md"),
+            "# Setup\n\n```bash\ncargo build\n```\n",
+        ).await.unwrap();
+        let block_embeddings = document.blocks.iter().enumerate().map(|(i, block)| crate::vault::embeddings::BlockEmbedding {
+            block_id: format!("Setup.md#{}", i),
+            block_type: block.block_type.clone(),
+            content: block.content.clone(),
+            vector: vec![1.0, 0.0],
+            start_pos: block.position.start,
+            end_pos: block.position.end,
+        }).collect();
+        let embedding = EmbeddingVector { block_embeddings: Some(block_embeddings), ..embedding(vec![1.0, 0.0], None) };
+        engine.index_document(&document, &embedding).await.unwrap();
+
+        let conn = Connection::open(dir.path().join("search.db")).unwrap();
+        let mut stmt = conn.prepare("SELECT block_type, start_pos, end_pos FROM block_embeddings ORDER BY id").unwrap();
+        let stored: Vec<(BlockType, usize, usize)> = stmt.query_map([], |row| {
+            Ok((serde_json::from_str(&row.get::<_, String>(0)?).unwrap(), row.get(1)?, row.get(2)?))
+        }).unwrap().map(|row| row.unwrap()).collect();
+        let expected: Vec<(BlockType, usize, usize)> = document.blocks.iter()
+            .map(|block| (block.block_type.clone(), block.position.start, block.position.end))
+            .collect();
+        assert_eq!(stored, expected);
+        assert_eq!(stored[0].0, BlockType::Heading(1));
+        assert_eq!(stored[1].0, BlockType::CodeBlock(Some("bash".to_string())));
+        assert!(stored[1].1 > stored[0].2);
+
+        let index = engine.index.read().await;
+        let types: Vec<_> = index.block_embeddings["Setup.md"].iter().map(|b| b.block_type.clone()).collect();
+        assert_eq!(types, [BlockType::Heading(1), BlockType::CodeBlock(Some("bash".to_string()))]);
+    }
+
+    #[tokio::test]
+    async fn test_focus_restricts_searches_until_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+        let focus = FocusSession::load(dir.path()).unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_focus(focus.clone());
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [
+            ("projects/alpha/Plan.md", "# Plan\n\nWe ship the release in May."),
+            ("journal/Monday.md", "# Monday\n\nPlanning the release party. #project/alpha"),
+            ("journal/Tuesday.md", "# Tuesday\n\nWrote release notes for the other team."),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+        let paths = |results: Vec<SearchResult>| {
+            let mut paths: Vec<_> = results.iter().map(|r| r.document.path.to_string_lossy().to_string()).collect();
+            paths.sort();
+            paths
+        };
+
+        focus.set(Focus::parse(&["projects/alpha", "#project"])).await.unwrap();
+        let focused = engine.text_search("release", &SearchOptions::default()).await.unwrap();
+        assert_eq!(paths(focused), ["journal/Monday.md", "projects/alpha/Plan.md"]);
+
+        // The focus is kept with the vault, so a new session starts focused too
+        assert_eq!(FocusSession::load(dir.path()).unwrap().current().await.unwrap().describe(), "projects/alpha, #project");
+
+        focus.clear().await.unwrap();
+        let all = engine.text_search("release", &SearchOptions::default()).await.unwrap();
+        assert_eq!(paths(all).len(), 3);
+        assert!(FocusSession::load(dir.path()).unwrap().current().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_language_analyzers_match_non_english_queries() {
+        let notes = [
+            ("Tokyo.md", "# 東京\n\n昨日は東京タワーに行きました。夜景がきれいでした。"),
+            ("Kyoto.md", "# 京都\n\n金閣寺を見に行きました。"),
+            ("Garden.md", "# Garden\n\nThe tomatoes are growing in the gardens."),
+        ];
+        let parser = ObsidianParser::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let default_engine = VectorSearchEngine::new(dir.path().join("default.db")).unwrap();
+        let routed_engine = VectorSearchEngine::new(dir.path().join("routed.db")).unwrap()
+            .with_languages(LanguageConfig { enabled: true, ..Default::default() });
+        for engine in [&default_engine, &routed_engine] {
+            engine.initialize().await.unwrap();
+            for (name, body) in notes {
+                let document = parser.parse_content(Path::new(name), body).await.unwrap();
+                engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+            }
+        }
+
+        // The default tokenizer keeps an unspaced Japanese sentence as one token, so a word inside it never matches
+        assert!(default_engine.text_search("東京タワー", &SearchOptions::default()).await.unwrap().is_empty());
+
+        let results = routed_engine.text_search("東京タワー", &SearchOptions::default()).await.unwrap();
+        let paths: Vec<_> = results.iter().map(|r| r.document.path.to_string_lossy().to_string()).collect();
+        assert_eq!(paths, ["Tokyo.md"]);
+        assert_eq!(routed_engine.document_language(Path::new("Tokyo.md")).unwrap().as_deref(), Some("ja"));
+
+        // English notes get stemming, and an explicit language overrides detection
+        assert_eq!(routed_engine.text_search_in("garden", Some("en"), &SearchOptions::default()).await.unwrap().len(), 1);
+        assert!(routed_engine.text_search_in("garden", Some("ja"), &SearchOptions::default()).await.unwrap().is_empty());
+
+        routed_engine.remove_document(&PathBuf::from("Tokyo.md")).await.unwrap();
+        assert!(routed_engine.text_search("東京タワー", &SearchOptions::default()).await.unwrap().is_empty());
+        assert_eq!(routed_engine.document_language(Path::new("Tokyo.md")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_merge_large_candidate_sets() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        let n = 20_000;
+
+        // Semantic hits on 0..n, text hits on n/2..3n/2, tag hits on every tenth note
+        let semantic = (0..n).map(|i| result(&format!("notes/{}.md", i), 0.5, MatchType::Semantic)).collect();
+        let text = (n / 2..n + n / 2).map(|i| result(&format!("notes/{}.md", i), 1.0, MatchType::Exact)).collect();
+        let tag = (0..n).step_by(10).map(|i| result(&format!("notes/{}.md", i), 0.8, MatchType::Tag)).collect();
+
+        let merged = engine.merge_search_results(semantic, text, tag, &SearchOptions::default()).unwrap();
+        assert_eq!(merged.len(), n + n / 2);
+
+        let by_path: HashMap<_, _> = merged.iter().map(|r| (r.document.path.clone(), r)).collect();
+        let score = |i: usize| by_path[&PathBuf::from(format!("notes/{}.md", i))].score;
+
+        assert!((score(1) - 0.5).abs() < 1e-6);                 // semantic only
+        assert!((score(10) - (0.5 + 0.4)).abs() < 1e-6);        // semantic + tag
+        assert!((score(n / 2 + 1) - (0.5 + 0.7)).abs() < 1e-6); // semantic + text
+        assert!((score(n + 1) - 0.7).abs() < 1e-6);             // text only
+        assert!(matches!(by_path[&PathBuf::from("notes/10.md")].match_type, MatchType::Hybrid));
+    }
+
+    #[test]
+    fn test_source_weight_ranks_note_above_equally_scored_web_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        let mut clipping = result("clippings/Tomatoes.md", 0.8, MatchType::Semantic);
+        clipping.document.tags = vec!["clipping".to_string()];
+        let note = result("garden/Tomatoes.md", 0.8, MatchType::Semantic);
+
+        let options = SearchOptions {
+            source_weights: SourceWeights(HashMap::from([("note".to_string(), 1.5)])),
+            ..SearchOptions::default()
+        };
+        let mut merged = engine.merge_search_results(vec![clipping, note], Vec::new(), Vec::new(), &options).unwrap();
+        merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        assert_eq!(merged[0].document.path, Path::new("garden/Tomatoes.md"));
+        assert!((merged[0].score - 1.2).abs() < 1e-6);
+        assert!((merged[1].score - 0.8).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_mmr_promotes_distinct_result_over_near_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let notes = [
+            ("Tomatoes 1.md", vec![1.0, 0.0], 0.95),
+            ("Tomatoes 2.md", vec![0.99, 0.05], 0.94),
+            ("Tomatoes 3.md", vec![0.98, 0.1], 0.93),
+            ("Compost.md", vec![0.0, 1.0], 0.8),
+        ];
+        let mut candidates = Vec::new();
+        for (name, vector, score) in notes {
+            let document = parser.parse_content(Path::new(name), "# Notes\n\nWatering.").await.unwrap();
+            engine.index_document(&document, &embedding(vector, None)).await.unwrap();
+            candidates.push(result(name, score, MatchType::Semantic));
+        }
+        let paths = |results: &[SearchResult]| -> Vec<PathBuf> {
+            results.iter().map(|r| r.document.path.clone()).collect()
+        };
+
+        let ranked = engine.select_diverse(candidates.clone(), 1.0, 3).await;
+        assert_eq!(paths(&ranked), paths(&candidates[..3]));
+
+        let diverse = engine.select_diverse(candidates, 0.5, 3).await;
+        assert_eq!(paths(&diverse), [
+            PathBuf::from("Tomatoes 1.md"),
+            PathBuf::from("Compost.md"),
+            PathBuf::from("Tomatoes 2.md"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_backlinks_distinguish_heading_and_block_anchors() {
+        use crate::vault::parser::{LinkResolutionConfig, LinkResolver};
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let mut docs = Vec::new();
+        for (name, body) in [
+            ("Note.md", "# Section\n\nA paragraph. ^abc123"),
+            ("Heading.md", "See [[Note#Section|the section]]."),
+            ("Block.md", "Quoting [[Note^abc123]]."),
+            ("Page.md", "All of [[Note]]."),
+        ] {
+            docs.push(parser.parse_content(Path::new(name), body).await.unwrap());
+        }
+        LinkResolver::new(&docs, LinkResolutionConfig::default()).resolve_all(&mut docs);
+        for document in &docs {
+            engine.index_document(document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+
+        let note = Path::new("Note.md");
+        assert_eq!(engine.backlinks(note, Some("Section")).await, [PathBuf::from("Heading.md")]);
+        assert_eq!(engine.backlinks(note, Some("^abc123")).await, [PathBuf::from("Block.md")]);
+        assert_eq!(
+            engine.backlinks(note, None).await,
+            [PathBuf::from("Block.md"), PathBuf::from("Heading.md"), PathBuf::from("Page.md")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_indexed_code_symbols_match_by_name_and_by_embedding() {
+        use crate::vault::batch::BatchIndexer;
+        use crate::vault::embedding_pool::{EmbeddingPool, RetryConfig};
+        use crate::vault::parsers::{CodeParser, DocumentParser};
+
+        let source = "pub struct Greenhouse {\n    hose: Hose,\n}\n\nimpl Greenhouse {\n    pub fn water_tomatoes(&self) {\n        self.hose.run();\n    }\n}\n\nasync fn open_vents() {}\n";
+        let parsed = CodeParser.parse(Path::new("greenhouse.rs"), source.as_bytes()).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+        let words = BagOfWords::Vocabulary(&["hose", "vents"]);
+        let pool = Arc::new(EmbeddingPool::new(vec![BagOfWords::Vocabulary(&["hose", "vents"])]).unwrap());
+        let result = BatchIndexer::new(&engine, &pool, "test").index(&[parsed], &RetryConfig::default()).await.unwrap();
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+
+        let found = engine.search_symbols("water_tomatoes", None, 5).await;
+        assert_eq!(found[0].name, "water_tomatoes");
+        assert_eq!(found[0].kind, "function");
+        assert!(found[0].content.contains("fn water_tomatoes"));
+        assert_eq!(engine.search_symbols("open vents", None, 5).await[0].name, "open_vents");
+
+        // Only the definitions' embedded bodies mention the hose
+        assert!(engine.search_symbols("hose", None, 5).await.is_empty());
+        let found = engine.search_symbols("hose", Some(&words.vector("hose")), 5).await;
+        assert!(found.iter().any(|symbol| symbol.name == "water_tomatoes"));
+        assert!(!found.iter().any(|symbol| symbol.name == "open_vents"));
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_tasks_span_the_vault_in_note_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [
+            ("Garden.md", "- [ ] Water the tomatoes\n- [x] Buy seeds\n- [ ] Pinch the basil\n"),
+            ("Done.md", "- [x] Everything\n"),
+            ("Errands.md", "- [ ] Post the letter\n"),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+
+        let tasks: Vec<(PathBuf, String)> = engine.incomplete_tasks().await.into_iter()
+            .map(|t| (t.path, t.content))
+            .collect();
+        assert_eq!(tasks, [
+            (PathBuf::from("Errands.md"), "Post the letter".to_string()),
+            (PathBuf::from("Garden.md"), "Water the tomatoes".to_string()),
+            (PathBuf::from("Garden.md"), "Pinch the basil".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_snippets_respect_length_and_mark_cut_ends() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        let content = format!("{}needle{}", "a".repeat(100), "é".repeat(100));
+
+        let snippet = engine.generate_snippet(&content, "needle", 20);
+        assert_eq!(snippet, "...aaaaaaaneedleééé...");
+
+        let wide = engine.generate_snippet(&content, "needle", 400);
+        assert_eq!(wide, content);
+
+        let short = "A short note";
+        assert_eq!(engine.generate_snippet(short, "missing", 200), short);
+        assert_eq!(engine.generate_snippet(&content, "missing", 10), "aaaaaaaaaa...");
+    }
+
+    #[tokio::test]
+    async fn test_backlinks_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [
+            ("Target.md", "# Target\n\nThe note everyone cites."),
+            ("First.md", "Builds on [[Target]]."),
+            ("Second.md", "Expands [[target#Target|the target]]."),
+            ("Unrelated.md", "Mentions [[Elsewhere]]."),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+
+        let restarted = VectorSearchEngine::new(db).unwrap();
+        restarted.initialize().await.unwrap();
+        let backlinks: Vec<PathBuf> = restarted.get_backlinks(Path::new("Target.md")).await
+            .into_iter()
+            .map(|doc| doc.path)
+            .collect();
+        assert_eq!(backlinks, [PathBuf::from("First.md"), PathBuf::from("Second.md")]);
+
+        restarted.remove_document(&PathBuf::from("First.md")).await.unwrap();
+        let backlinks = restarted.get_backlinks(Path::new("Target.md")).await;
+        assert_eq!(backlinks.len(), 1);
+        assert_eq!(backlinks[0].title, "Second");
+        assert!(backlinks[0].content.contains("Expands"));
+    }
+
+    #[tokio::test]
+    async fn test_link_counts_resolve_wikilink_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+
+        let parser = ObsidianParser::new().unwrap();
+        let mut docs = Vec::new();
+        for (name, body) in [
+            ("notes/Project.md", "# Project"),
+            ("archive/Project.md", "# Old project"),
+            ("daily/2024-01-01.md", "Worked on [[Project]]."),
+            ("daily/2024-01-02.md", "Reviewed [[notes/Project#Goals]] and [[Elsewhere]]."),
+        ] {
+            docs.push(parser.parse_content(Path::new(name), body).await.unwrap());
+        }
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+        for document in &docs {
+            engine.index_document(document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+
+        let project = Path::new("notes/Project.md");
+        assert_eq!(engine.link_counts(project).await, LinkCounts { inbound: 2, outbound: 0 });
+        assert_eq!(engine.link_counts(Path::new("archive/Project.md")).await.inbound, 0);
+        assert_eq!(engine.link_counts(Path::new("daily/2024-01-02.md")).await, LinkCounts { inbound: 0, outbound: 2 });
+
+        let count_all = VectorSearchEngine::new(db.clone()).unwrap()
+            .with_duplicate_link_targets(DuplicateLinkTargets::CountAll);
+        count_all.initialize().await.unwrap();
+        assert_eq!(count_all.link_counts(Path::new("archive/Project.md")).await.inbound, 1);
+
+        let ignore = VectorSearchEngine::new(db).unwrap()
+            .with_duplicate_link_targets(DuplicateLinkTargets::Ignore);
+        ignore.initialize().await.unwrap();
+        let backlinks: Vec<PathBuf> = ignore.get_backlinks(project).await.into_iter().map(|doc| doc.path).collect();
+        assert_eq!(backlinks, [PathBuf::from("daily/2024-01-02.md")]);
+    }
+
+    #[tokio::test]
+    async fn test_reindexing_an_unchanged_note_keeps_its_block_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        // A table from before content hashes, which initialize migrates
+        Connection::open(&db).unwrap().execute(
+            "CREATE TABLE block_embeddings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                document_path TEXT NOT NULL,
+                block_id TEXT UNIQUE NOT NULL,
+                block_type TEXT NOT NULL,
+                content TEXT NOT NULL,
+                embedding BLOB NOT NULL,
+                start_pos INTEGER NOT NULL,
+                end_pos INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            )",
+            [],
+        ).unwrap();
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let index = |body: &'static str| {
+            let parser = &parser;
+            let engine = &engine;
+            async move {
+                let document = parser.parse_content(Path::new("Repeat.md"), body).await.unwrap();
+                let blocks = document.blocks.iter().enumerate().map(|(i, block)| crate::vault::embeddings::BlockEmbedding {
+                    block_id: format!("Repeat.md#{}", i),
+                    block_type: block.block_type.clone(),
+                    content: block.content.clone(),
+                    vector: vec![1.0, 0.0],
+                    start_pos: block.position.start,
+                    end_pos: block.position.end,
+                }).collect();
+                let embedding = EmbeddingVector { block_embeddings: Some(blocks), ..embedding(vec![1.0, 0.0], None) };
+                engine.index_document(&document, &embedding).await.unwrap();
+            }
+        };
+        let rows = || {
+            let conn = Connection::open(&db).unwrap();
+            let mut stmt = conn.prepare("SELECT id, content FROM block_embeddings ORDER BY block_id").unwrap();
+            stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+                .unwrap()
+                .map(|row| row.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Identical paragraphs are separate blocks and both keep a vector
+        index("Same line.\n\nSame line.\n\nLast line.").await;
+        let first = rows();
+        assert_eq!(first.len(), 3);
+
+        index("Same line.\n\nSame line.\n\nLast line.").await;
+        assert_eq!(rows(), first);
+
+        index("Same line.\n\nChanged line.").await;
+        let edited = rows();
+        assert_eq!(edited.len(), 2);
+        assert_eq!(edited[0], first[0]);
+        assert_eq!(edited[1].1, "Changed line.");
+        assert_ne!(edited[1].0, first[1].0);
+    }
+
+    #[tokio::test]
+    async fn test_removed_document_leaves_semantic_and_text_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = VectorSearchEngine::new(db.clone()).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["tomato", "tax"])));
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let garden = parser.parse_content(Path::new("Garden.md"), "# Garden\n\nYoung tomato seedlings.").await.unwrap();
+        let greenhouse = parser.parse_content(Path::new("Greenhouse.md"), "# Greenhouse\n\nTall tomato vines.").await.unwrap();
+        engine.index_document(&garden, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        engine.index_document(&greenhouse, &embedding(vec![0.9, 0.1], None)).await.unwrap();
+
+        let options = SearchOptions { similarity_threshold: 0.0, include_context: false, ..SearchOptions::default() };
+        let found = |results: Vec<SearchResult>| {
+            let mut paths: Vec<PathBuf> = results.into_iter().map(|r| r.document.path).collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(found(engine.semantic_search_traced("tomato", &options, &mut QueryTrace::new("tomato")).await.unwrap()).len(), 2);
+
+        engine.remove_document(&PathBuf::from("Garden.md")).await.unwrap();
+        let remaining = [PathBuf::from("Greenhouse.md")];
+        assert_eq!(found(engine.semantic_search_traced("tomato", &options, &mut QueryTrace::new("tomato")).await.unwrap()), remaining);
+        assert_eq!(found(engine.text_search("tomato", &options).await.unwrap()), remaining);
+
+        let fts_rows: i64 = Connection::open(&db).unwrap()
+            .query_row("SELECT COUNT(*) FROM search_fts WHERE search_fts MATCH 'seedlings'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(fts_rows, 0);
+
+        let restarted = VectorSearchEngine::new(db).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["tomato", "tax"])));
+        restarted.initialize().await.unwrap();
+        let options = SearchOptions { vector_index: VectorIndexMode::Hnsw, ..options };
+        assert_eq!(found(restarted.semantic_search_traced("tomato", &options, &mut QueryTrace::new("tomato")).await.unwrap()), remaining);
+    }
+
+    #[tokio::test]
+    async fn test_optimize_keeps_full_text_search_working() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [("Garden.md", "Young tomato seedlings."), ("Greenhouse.md", "Tall tomato vines."), ("Taxes.md", "File the tax return.")] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+        engine.remove_document(&PathBuf::from("Garden.md")).await.unwrap();
+        engine.optimize().await.unwrap();
+
+        let options = SearchOptions { include_context: false, ..SearchOptions::default() };
+        let found: Vec<PathBuf> = engine.text_search("tomato", &options).await.unwrap()
+            .into_iter().map(|r| r.document.path).collect();
+        assert_eq!(found, [PathBuf::from("Greenhouse.md")]);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_results_keep_blocks_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        let document = parser.parse_content(
+            Path::new("Garden.md"),
+            "# Garden\n\n- [ ] Water the tomatoes\n\nTomatoes like full sun. #outdoors\n",
+        ).await.unwrap();
+        engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+
+        let restarted = VectorSearchEngine::new(db).unwrap();
+        restarted.initialize().await.unwrap();
+        let options = SearchOptions { similarity_threshold: 0.0, ..SearchOptions::default() };
+        let results = restarted.semantic_search_by_vector("tomatoes", &[1.0, 0.0], &options).await.unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document.title, "Garden");
+        assert_eq!(results[0].document.tags, ["outdoors"]);
+        let matched: Vec<&str> = results[0].context.matched_blocks.iter().map(|b| b.content.as_str()).collect();
+        assert_eq!(matched, ["Water the tomatoes", "Tomatoes like full sun. #outdoors"]);
+        let tasks: Vec<String> = restarted.incomplete_tasks().await.into_iter().map(|t| t.content).collect();
+        assert_eq!(tasks, ["Water the tomatoes"]);
+    }
+
+    #[tokio::test]
+    async fn test_lookups_return_tags_and_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("search.db");
+        let engine = VectorSearchEngine::new(db.clone()).unwrap();
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body) in [
+            ("Garden.md", "# Garden\n\nPlant tomatoes near [[Compost#Ratios]]. #outdoors #food/veg"),
+            ("Compost.md", "# Compost\n\n## Ratios\n\nBrowns and greens."),
+        ] {
+            let document = parser.parse_content(Path::new(name), body).await.unwrap();
+            engine.index_document(&document, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+        }
+        // The parser collects tags into a set
+        let sorted = |mut tags: Vec<String>| {
+            tags.sort();
+            tags
+        };
+        let expected_tags = ["food/veg", "outdoors"];
+        let expected_links = ["Compost#Ratios"];
+
+        let document = engine.get_document(Path::new("Garden.md")).await.unwrap().unwrap();
+        assert_eq!(sorted(document.tags.clone()), expected_tags);
+        assert_eq!(document.links, expected_links);
+        assert!(engine.get_document(Path::new("Missing.md")).await.unwrap().is_none());
+
+        let matches = engine.text_search("tomatoes", &SearchOptions::default()).await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(sorted(matches[0].document.tags.clone()), expected_tags);
+        assert_eq!(matches[0].document.links, expected_links);
+
+        let stored = engine.stored_documents().await.unwrap();
+        let garden = stored.iter().find(|doc| doc.path == Path::new("Garden.md")).unwrap();
+        assert_eq!(garden.links, expected_links);
+
+        let restarted = VectorSearchEngine::new(db).unwrap();
+        restarted.initialize().await.unwrap();
+        let options = SearchOptions { similarity_threshold: 0.0, include_context: false, ..SearchOptions::default() };
+        let results = restarted.semantic_search_by_vector("tomatoes", &[1.0, 0.0], &options).await.unwrap();
+        let garden = results.iter().find(|r| r.document.path == Path::new("Garden.md")).unwrap();
+        assert_eq!(sorted(garden.document.tags.clone()), expected_tags);
+        assert_eq!(garden.document.links, expected_links);
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_paging_and_boosts_shape_the_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["garden"])));
+        engine.initialize().await.unwrap();
+
+        let parser = ObsidianParser::new().unwrap();
+        for (name, body, age_days) in [
+            ("Archive.md", "# Archive\n\nOld notes. #project", 90),
+            ("Beds.md", "# Beds\n\nRaised beds. #project/garden", 0),
+            ("Compost.txt", "Compost heap.", 0),
+            ("Drafts.md", "# Drafts\n\nLoose ideas.", 0),
+        ] {
+            let mut document = parser.parse_content(Path::new(name), body).await.unwrap();
+            document.metadata.last_parsed = chrono::Utc::now() - chrono::Duration::days(age_days);
+            engine.index_document(&document, &embedding(vec![1.0], None)).await.unwrap();
+        }
+
+        // Every note is equally similar, so with no boosts they rank by path
+        let plain = SearchOptions {
+            similarity_threshold: 0.0,
+            include_context: false,
+            hybrid_search: false,
+            boost_recent: false,
+            boost_tags: false,
+            ..SearchOptions::default()
+        };
+        let search = |filters: SearchFilters, options: SearchOptions| {
+            let engine = &engine;
+            async move {
+                let query = SearchQuery { text: "garden".to_string(), filters, options };
+                engine.search(&query).await.unwrap().into_iter()
+                    .map(|r| r.document.path.to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(search(SearchFilters::default(), plain.clone()).await, ["Archive.md", "Beds.md", "Compost.txt", "Drafts.md"]);
+
+        let tags = SearchFilters { tags: vec!["#project".to_string()], ..SearchFilters::default() };
+        assert_eq!(search(tags, plain.clone()).await, ["Archive.md", "Beds.md"]);
+
+        let file_types = SearchFilters { file_types: vec![".TXT".to_string()], ..SearchFilters::default() };
+        assert_eq!(search(file_types, plain.clone()).await, ["Compost.txt"]);
+
+        let month_ago = (chrono::Utc::now() - chrono::Duration::days(30)).timestamp() as u64;
+        let date_range = SearchFilters { date_range: Some((month_ago, u64::MAX)), ..SearchFilters::default() };
+        assert_eq!(search(date_range, plain.clone()).await, ["Beds.md", "Compost.txt", "Drafts.md"]);
+
+        let page = SearchOptions { limit: 2, offset: 1, ..plain.clone() };
+        assert_eq!(search(SearchFilters::default(), page).await, ["Beds.md", "Compost.txt"]);
+
+        let recent = SearchOptions { boost_recent: true, ..plain.clone() };
+        assert_eq!(search(SearchFilters::default(), recent).await.last().unwrap(), "Archive.md");
+
+        let tagged = SearchOptions { boost_tags: true, ..plain };
+        assert_eq!(search(SearchFilters::default(), tagged).await[0], "Beds.md");
+    }
+
+    #[tokio::test]
+    async fn test_distance_metric_ranks_documents_and_the_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let near = parser.parse_content(Path::new("Near.md"), "# Near").await.unwrap();
+        let long = parser.parse_content(Path::new("Long.md"), "# Long").await.unwrap();
+
+        let ranked = |metric: DistanceMetric, vector_index: VectorIndexMode| {
+            let (dir, near, long) = (&dir, &near, &long);
+            async move {
+                let engine = VectorSearchEngine::new(dir.path().join(format!("{:?}-{:?}.db", metric, vector_index))).unwrap()
+                    .with_distance_metric(metric)
+                    .with_index_update_threshold(1);
+                engine.initialize().await.unwrap();
+                engine.index_document(near, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+                engine.wait_for_index_updates().await;
+                engine.index_document(long, &embedding(vec![4.0, 0.0], None)).await.unwrap();
+                engine.wait_for_index_updates().await;
+                assert_eq!(engine.index_status().await.unindexed_vectors, 0);
+                let options = SearchOptions {
+                    similarity_threshold: 0.0,
+                    include_context: false,
+                    boost_titles: false,
+                    vector_index,
+                    ..SearchOptions::default()
+                };
+                let mut results = engine.semantic_search_by_vector("q", &[1.0, 0.0], &options).await.unwrap();
+                results.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.document.path.cmp(&b.document.path)));
+                results.into_iter().map(|r| (r.document.title, r.score)).collect::<Vec<_>>()
+            }
+        };
+
+        for vector_index in [VectorIndexMode::BruteForce, VectorIndexMode::Hnsw] {
+            let cosine = ranked(DistanceMetric::Cosine, vector_index).await;
+            assert!(cosine.iter().all(|(_, score)| (score - 1.0).abs() < 1e-6), "{:?}", cosine);
+
+            let l2 = ranked(DistanceMetric::L2, vector_index).await;
+            assert_eq!(l2, [("Near".to_string(), 1.0), ("Long".to_string(), 0.25)]);
+
+            let dot = ranked(DistanceMetric::Dot, vector_index).await;
+            assert_eq!(dot, [("Long".to_string(), 4.0), ("Near".to_string(), 1.0)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_graph_catches_up_in_the_background_past_the_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_index_update_threshold(3);
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let hnsw = SearchOptions {
+            similarity_threshold: 0.5,
+            include_context: false,
+            vector_index: VectorIndexMode::Hnsw,
+            ..SearchOptions::default()
+        };
+
+        for (i, name) in ["A", "B"].iter().enumerate() {
+            let doc = parser.parse_content(Path::new(&format!("{}.md", name)), "Plain note").await.unwrap();
+            let mut vector = vec![0.0; 4];
+            vector[i] = 1.0;
+            engine.index_document(&doc, &embedding(vector, None)).await.unwrap();
+        }
+        engine.wait_for_index_updates().await;
+        assert_eq!(engine.index_status().await, IndexStatus { indexed_vectors: 0, unindexed_vectors: 2 });
+
+        // Below the threshold, the graph is empty but the appended notes are still found
+        let found = engine.semantic_search_by_vector("q", &[0.0, 1.0, 0.0, 0.0], &hnsw).await.unwrap();
+        assert_eq!(found.iter().map(|r| r.document.title.as_str()).collect::<Vec<_>>(), ["B"]);
+
+        for (i, name) in ["C", "D"].iter().enumerate() {
+            let doc = parser.parse_content(Path::new(&format!("{}.md", name)), "Plain note").await.unwrap();
+            let mut vector = vec![0.0; 4];
+            vector[i + 2] = 1.0;
+            engine.index_document(&doc, &embedding(vector, None)).await.unwrap();
+        }
+        engine.wait_for_index_updates().await;
+        assert_eq!(engine.index_status().await, IndexStatus { indexed_vectors: 4, unindexed_vectors: 0 });
+
+        let found = engine.semantic_search_by_vector("q", &[0.0, 0.0, 0.0, 1.0], &hnsw).await.unwrap();
+        assert_eq!(found.iter().map(|r| r.document.title.as_str()).collect::<Vec<_>>(), ["D"]);
+
+        // Reindexing a note takes its old vectors out of the graph until the next update
+        let b = parser.parse_content(Path::new("B.md"), "Plain note").await.unwrap();
+        engine.index_document(&b, &embedding(vec![0.0, 0.0, 0.0, 1.0], None)).await.unwrap();
+        assert_eq!(engine.index_status().await, IndexStatus { indexed_vectors: 3, unindexed_vectors: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_ai_context_only_answers_come_from_the_index() {
+        use crate::ai::{QueryMode, AI};
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Arc::new(VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["tomato", "tax"]))));
+        engine.initialize().await.unwrap();
+        let ai = AI::new().unwrap().with_retriever(Arc::new(SearchRetriever::new(
+            engine.clone(),
+            SearchOptions { similarity_threshold: 0.0, ..SearchOptions::default() },
+        )));
+        let ask = || ai.process_query_with_mode("when to water the tomato seedlings", QueryMode::ContextOnly, 1);
+        assert_eq!(ask().await.unwrap(), "No relevant passages found.");
+
+        let parser = ObsidianParser::new().unwrap();
+        let garden = parser.parse_content(Path::new("Garden.md"), "# Garden\n\nWater the seedlings every morning.").await.unwrap();
+        let taxes = parser.parse_content(Path::new("Taxes.md"), "# Taxes\n\nFile the return in April.").await.unwrap();
+        let version = engine.corpus_version();
+        engine.index_document(&garden, &embedding(vec![0.9, 0.1], None)).await.unwrap();
+        engine.index_document(&taxes, &embedding(vec![0.1, 0.9], None)).await.unwrap();
+        assert!(engine.corpus_version() > version);
+
+        let passages = ask().await.unwrap();
+        assert!(passages.starts_with("[1] Garden.md"), "{}", passages);
+        assert!(passages.contains("every morning"), "{}", passages);
+        assert!(!passages.contains("Taxes.md"), "{}", passages);
+    }
+
+    #[tokio::test]
+    async fn test_traced_search_records_each_stage() {
+        use crate::ai::trace::QueryStage;
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap()
+            .with_query_embedder(Arc::new(BagOfWords::Vocabulary(&["tomato", "tax"])));
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let garden = parser.parse_content(Path::new("Garden.md"), "Water the tomato seedlings").await.unwrap();
+        engine.index_document(&garden, &embedding(vec![0.9, 0.1], None)).await.unwrap();
+
+        let query = SearchQuery {
+            text: "tomato".to_string(),
+            filters: SearchFilters::default(),
+            options: SearchOptions { similarity_threshold: 0.0, mmr_lambda: Some(0.5), ..SearchOptions::default() },
+        };
+        let mut trace = QueryTrace::new(&query.text);
+        let results = engine.search_traced(&query, &mut trace).await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        let stages: Vec<_> = trace.stages.iter().map(|timing| timing.stage).collect();
+        assert_eq!(stages, vec![
+            QueryStage::Embed,
+            QueryStage::VectorSearch,
+            QueryStage::TextSearch,
+            QueryStage::Merge,
+            QueryStage::Merge,
+            QueryStage::Rerank,
+        ]);
+        assert!(trace.summary().contains("embed"));
+    }
+
+    #[tokio::test]
+    async fn test_generated_answers_are_grounded_in_the_indexed_notes() {
+        use crate::ai::backend::{Backend, BackendKind};
+        use crate::ai::grounding::GroundingConfig;
+        use crate::ai::{QueryMode, AI};
+
+        struct Scripted;
+
+        #[async_trait]
+        impl Backend for Scripted {
+            fn kind(&self) -> BackendKind {
+                BackendKind::Local
+            }
+
+            async fn generate(&self, _prompt: &str, _max_tokens: usize) -> Result<String> {
+                Ok("Water the seedlings every morning. Tomatoes were first grown on Mars.".to_string())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = Arc::new(VectorSearchEngine::new(dir.path().join("search.db")).unwrap());
+        engine.initialize().await.unwrap();
+        let parser = ObsidianParser::new().unwrap();
+        let garden = parser.parse_content(Path::new("Garden.md"), "# Garden\n\nWater the seedlings every morning.").await.unwrap();
+        engine.index_document(&garden, &embedding(vec![1.0, 0.0], None)).await.unwrap();
+
+        let ai = AI::new().unwrap()
+            .with_retriever(Arc::new(SearchRetriever::new(engine, SearchOptions::default())))
+            .with_backend(Arc::new(Scripted))
+            .with_grounding(GroundingConfig { enabled: true, ..GroundingConfig::default() });
+        let (answer, _) = ai.process_query_traced("seedlings", QueryMode::Generate, 3).await.unwrap();
+
+        assert!(answer.contains("grown on Mars. [unsupported]"), "{}", answer);
+        assert!(!answer.contains("every morning. [unsupported]"), "{}", answer);
+    }
 }
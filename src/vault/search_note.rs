@@ -0,0 +1,177 @@
+// src/vault/search_note.rs - Capture a query's results as a note in the vault
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{Result, Context};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use crate::vault::batch::BatchIndexer;
+use crate::vault::embedding_pool::{EmbeddingPool, EmbeddingWorker, RetryConfig};
+use crate::vault::parser::ObsidianParser;
+use crate::vault::search::{SearchFilters, SearchOptions, SearchQuery, SearchResult, VectorSearchEngine};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchNoteConfig {
+    /// Vault folder the search notes are written to
+    pub folder: PathBuf,
+    /// Results listed in each note
+    pub limit: usize,
+}
+
+impl Default for SearchNoteConfig {
+    fn default() -> Self {
+        Self {
+            folder: PathBuf::from("Searches"),
+            limit: 10,
+        }
+    }
+}
+
+/// Writes search results as a note with links to each match, then indexes it
+pub struct SearchNoteWriter<'a, W: EmbeddingWorker> {
+    engine: &'a VectorSearchEngine,
+    pool: &'a Arc<EmbeddingPool<W>>,
+    model_name: &'a str,
+    vault: &'a Path,
+    config: SearchNoteConfig,
+}
+
+impl<'a, W: EmbeddingWorker + 'static> SearchNoteWriter<'a, W> {
+    pub fn new(
+        engine: &'a VectorSearchEngine,
+        pool: &'a Arc<EmbeddingPool<W>>,
+        model_name: &'a str,
+        vault: &'a Path,
+        config: SearchNoteConfig,
+    ) -> Self {
+        Self { engine, pool, model_name, vault, config }
+    }
+
+    /// Note for `title`; the same title always maps to the same file
+    pub fn note_path(&self, title: &str) -> PathBuf {
+        let name: String = title.trim()
+            .chars()
+            .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') { '-' } else { c })
+            .collect();
+        self.vault.join(&self.config.folder).join(format!("{}.md", name))
+    }
+
+    /// Run `query` and write its ranked results to the note `title`, replacing an earlier
+    /// capture with the same title. Returns the note's path.
+    pub async fn search_to_note(&self, query: &str, title: &str) -> Result<PathBuf> {
+        let path = self.note_path(title);
+        let search = SearchQuery {
+            text: query.to_string(),
+            filters: SearchFilters::default(),
+            options: SearchOptions {
+                // One extra, in case an earlier capture of this search is among the matches
+                limit: self.config.limit + 1,
+                include_context: false,
+                ..SearchOptions::default()
+            },
+        };
+        let mut results = self.engine.search(&search).await?;
+        results.retain(|result| result.document.path != path);
+        results.truncate(self.config.limit);
+
+        // An update keeps the original capture time
+        let existing = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+        let created = existing.lines()
+            .find_map(|line| line.strip_prefix("created: "))
+            .map(str::to_string);
+        let content = self.render(query, title, created, &results);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, &content).await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        let document = ObsidianParser::new()?.parse_content(&path, &content).await?;
+        let indexed = BatchIndexer::new(self.engine, self.pool, self.model_name)
+            .index(&[document], &RetryConfig::default())
+            .await?;
+        if let Some(error) = indexed.errors.first() {
+            return Err(anyhow::anyhow!("Saved {} but couldn't index it: {}", path.display(), error));
+        }
+        Ok(path)
+    }
+
+    fn render(&self, query: &str, title: &str, created: Option<String>, results: &[SearchResult]) -> String {
+        let now = Utc::now();
+        let mut note = format!(
+            "---\ntitle: {}\nquery: {:?}\ncreated: {}\nupdated: {}\ntags: [search]\n---\n\n# {}\n\nResults for \"{}\", captured {}.\n\n",
+            title,
+            query,
+            created.unwrap_or_else(|| now.to_rfc3339()),
+            now.to_rfc3339(),
+            title,
+            query,
+            now.format("%Y-%m-%d %H:%M UTC"),
+        );
+
+        if results.is_empty() {
+            note.push_str("No notes matched.\n");
+        }
+        for (i, result) in results.iter().enumerate() {
+            note.push_str(&format!(
+                "{}. [[{}|{}]] (score {:.2})\n",
+                i + 1,
+                self.link_target(&result.document.path),
+                result.document.title,
+                result.score
+            ));
+            let snippet = result.document.snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !snippet.is_empty() {
+                note.push_str(&format!("   > {}\n", snippet));
+            }
+        }
+        note
+    }
+
+    /// Vault-relative path without the extension, as Obsidian links expect
+    fn link_target(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(self.vault).unwrap_or(path);
+        relative.with_extension("").to_string_lossy().replace('\\', "/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::UnitWorker;
+
+    #[tokio::test]
+    async fn test_search_note_links_results_and_updates_in_place() {
+        let vault = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(vault.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+        let pool = Arc::new(EmbeddingPool::new(vec![UnitWorker]).unwrap());
+
+        let parser = ObsidianParser::new().unwrap();
+        let documents = vec![
+            parser.parse_content(&vault.path().join("garden/Tomatoes.md"), "# Tomatoes\n\nWater the tomatoes daily.").await.unwrap(),
+            parser.parse_content(&vault.path().join("Taxes.md"), "# Taxes\n\nFile them in April.").await.unwrap(),
+        ];
+        BatchIndexer::new(&engine, &pool, "test").index(&documents, &RetryConfig::default()).await.unwrap();
+
+        let writer = SearchNoteWriter::new(&engine, &pool, "test", vault.path(), SearchNoteConfig::default());
+        let path = writer.search_to_note("tomatoes", "Tomato research").await.unwrap();
+        assert_eq!(path, vault.path().join("Searches/Tomato research.md"));
+
+        let note = std::fs::read_to_string(&path).unwrap();
+        assert!(note.contains("query: \"tomatoes\""));
+        assert!(note.contains("1. [[garden/Tomatoes|Tomatoes]]"));
+        assert!(note.contains("the tomatoes daily."));
+        assert!(!note.contains("[[Taxes"));
+        let created = note.lines().find(|l| l.starts_with("created: ")).unwrap().to_string();
+
+        // Re-running rewrites the same note, which doesn't list itself, and keeps its creation time
+        let again = writer.search_to_note("tomatoes", "Tomato research").await.unwrap();
+        assert_eq!(again, path);
+        assert_eq!(std::fs::read_dir(vault.path().join("Searches")).unwrap().count(), 1);
+        let note = std::fs::read_to_string(&path).unwrap();
+        assert!(note.contains(&created));
+        assert!(!note.contains("[[Searches/"));
+        assert_eq!(engine.text_search("research", &SearchOptions::default()).await.unwrap().len(), 1);
+    }
+}
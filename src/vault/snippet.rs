@@ -0,0 +1,155 @@
+// src/vault/snippet.rs - Result previews: the passage densest in query terms, plain and with matches marked
+use regex::{Regex, RegexBuilder};
+
+/// A preview of a document for a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    /// The passage, with `...` where it was cut from the surrounding text
+    pub text: String,
+    /// `text` HTML-escaped, with each matched term wrapped in `<mark>...</mark>`
+    pub highlighted: String,
+}
+
+/// Lowercased, de-duplicated words of `query`
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for term in query.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()) {
+        let term = term.to_lowercase();
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// A window of at most `max_length` bytes of `content`, placed over the passage containing
+/// the most distinct query terms; the start of `content` when no term occurs
+pub fn generate(content: &str, query: &str, max_length: usize) -> Snippet {
+    let terms = query_terms(query);
+    let matches = term_regex(&terms)
+        .map(|regex| regex.find_iter(content)
+            .map(|m| (m.start(), m.end(), term_index(&terms, m.as_str())))
+            .collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let (start, end) = match densest_window(&matches, max_length) {
+        Some((first, last)) => {
+            let (span_start, span_end) = (matches[first].0, matches[last].1);
+            let padding = max_length.saturating_sub(span_end - span_start) / 2;
+            let start = floor_char_boundary(content, span_start.saturating_sub(padding));
+            (start, floor_char_boundary(content, start + max_length))
+        }
+        None => (0, floor_char_boundary(content, max_length)),
+    };
+
+    let mut text = content[start..end].to_string();
+    let mut highlighted = String::new();
+    let mut cursor = start;
+    for &(m_start, m_end, _) in matches.iter().filter(|m| m.0 >= start && m.1 <= end) {
+        highlighted.push_str(&escape_html(&content[cursor..m_start]));
+        highlighted.push_str("<mark>");
+        highlighted.push_str(&escape_html(&content[m_start..m_end]));
+        highlighted.push_str("</mark>");
+        cursor = m_end;
+    }
+    highlighted.push_str(&escape_html(&content[cursor..end]));
+
+    if start > 0 {
+        text = format!("...{}", text);
+        highlighted = format!("...{}", highlighted);
+    }
+    if end < content.len() {
+        text.push_str("...");
+        highlighted.push_str("...");
+    }
+    Snippet { text, highlighted }
+}
+
+/// Case-insensitive alternation of the terms, longest first so a term isn't cut short by its prefix
+fn term_regex(terms: &[String]) -> Option<Regex> {
+    if terms.is_empty() {
+        return None;
+    }
+    let mut sorted: Vec<&String> = terms.iter().collect();
+    sorted.sort_by_key(|t| std::cmp::Reverse(t.len()));
+    let pattern = sorted.iter().map(|t| regex::escape(t)).collect::<Vec<_>>().join("|");
+    RegexBuilder::new(&pattern).case_insensitive(true).build().ok()
+}
+
+fn term_index(terms: &[String], matched: &str) -> usize {
+    let matched = matched.to_lowercase();
+    terms.iter().position(|t| *t == matched).unwrap_or(0)
+}
+
+/// First and last match of the window starting at a match that holds the most distinct
+/// terms, ties going to more matches and then to the earlier window
+fn densest_window(matches: &[(usize, usize, usize)], max_length: usize) -> Option<(usize, usize)> {
+    let mut best: Option<((usize, usize), (usize, usize))> = None;
+    for first in 0..matches.len() {
+        let window_end = matches[first].0 + max_length;
+        let last = (first..matches.len())
+            .take_while(|&i| matches[i].1 <= window_end)
+            .last()
+            .unwrap_or(first);
+
+        let mut distinct: Vec<usize> = matches[first..=last].iter().map(|m| m.2).collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        let score = (distinct.len(), last - first + 1);
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, (first, last)));
+        }
+    }
+    best.map(|(_, window)| window)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Largest char boundary of `s` at or before `index`
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_term_is_centered_and_marked() {
+        let content = format!("{}Rust borrow checker{}", "x ".repeat(50), " y".repeat(50));
+        let snippet = generate(&content, "rust", 20);
+
+        assert_eq!(snippet.text, "...x x x x Rust borrow ...");
+        assert_eq!(snippet.highlighted, "...x x x x <mark>Rust</mark> borrow ...");
+    }
+
+    #[test]
+    fn test_multi_term_window_prefers_the_passage_with_most_terms() {
+        let content = format!(
+            "Tokio appears early. {} The tokio runtime drives async tasks. {}",
+            "filler ".repeat(20),
+            "filler ".repeat(20)
+        );
+        let snippet = generate(&content, "tokio async runtime", 50);
+
+        assert!(snippet.text.contains("The tokio runtime drives async tasks"), "{}", snippet.text);
+        assert!(snippet.highlighted.contains("The <mark>tokio</mark> <mark>runtime</mark> drives <mark>async</mark> tasks"));
+        assert!(snippet.text.starts_with("...") && snippet.text.ends_with("..."));
+    }
+
+    #[test]
+    fn test_no_match_takes_the_start_and_escapes_markup() {
+        let snippet = generate("Use <b> & </b> sparingly in notes", "python", 14);
+        assert_eq!(snippet.text, "Use <b> & </b>...");
+        assert_eq!(snippet.highlighted, "Use &lt;b&gt; &amp; &lt;/b&gt;...");
+
+        let whole = generate("A short note", "", 200);
+        assert_eq!(whole, Snippet { text: "A short note".to_string(), highlighted: "A short note".to_string() });
+    }
+}
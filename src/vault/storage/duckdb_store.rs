@@ -5,6 +5,8 @@ use serde_json;
 use tracing::{info, debug, error, instrument};
 use chrono::{DateTime, Utc};
 use duckdb::{Connection, params, Result as DuckResult};
+use crate::vault::snippet;
+use crate::vault::tag_graph::{TagGraph, TagGraphConfig};
 
 use super::{
     StorageEngine, DocumentMetadata, DocumentEmbeddings, BlockEmbedding,
@@ -432,10 +434,12 @@ impl StorageEngine for DuckDBStore {
             
             let score = (title_score.unwrap_or(0.0) * 2.0 + content_score.unwrap_or(0.0)) as f32;
             
-            let snippet = if let Some(text) = plain_text {
-                self.generate_snippet(&text, query, 200)
-            } else {
-                title.clone()
+            let (snippet, highlight) = match plain_text {
+                Some(text) => {
+                    let snippet = snippet::generate(&text, query, 200);
+                    (snippet.text, Some(snippet.highlighted))
+                }
+                None => (title.clone(), None),
             };
             
             Ok(SearchResult {
@@ -456,7 +460,7 @@ impl StorageEngine for DuckDBStore {
                         custom_fields,
                     },
                     snippet: Some(snippet),
-                    highlight: None,
+                    highlight,
                 },
                 score,
                 match_type: MatchType::FullText,
@@ -741,14 +745,13 @@ impl StorageEngine for DuckDBStore {
         // Analyze tables for better query planning
         self.connection.execute("ANALYZE", [])?;
         
-        // Checkpoint WAL if enabled
-        if self.config.wal_mode {
+        // Checkpointing also returns the blocks of deleted rows to DuckDB's free list
+        if self.config.full_vacuum {
+            self.connection.execute("VACUUM", [])?;
+        } else {
             self.connection.execute("CHECKPOINT", [])?;
         }
         
-        // Vacuum to reclaim space
-        self.connection.execute("VACUUM", [])?;
-        
         info!("DuckDB optimization completed");
         Ok(())
     }
@@ -823,6 +826,29 @@ impl DuckDBStore {
         Ok(tags)
     }
     
+    /// Tag hierarchy from `tag_popularity` and co-occurrence edges from a self-join of `document_tags`
+    pub async fn tag_graph(&self, config: &TagGraphConfig) -> Result<TagGraph> {
+        let mut stmt = self.connection.prepare("SELECT tag, document_count FROM tag_popularity")?;
+        let popularity = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+        })?.collect::<DuckResult<Vec<_>>>()?;
+
+        let mut stmt = self.connection.prepare(
+            "SELECT ta.tag, tb.tag, COUNT(*) AS shared
+             FROM document_tags a
+             JOIN document_tags b ON a.document_id = b.document_id AND a.tag_id < b.tag_id
+             JOIN tags ta ON a.tag_id = ta.id
+             JOIN tags tb ON b.tag_id = tb.id
+             GROUP BY ta.tag, tb.tag
+             HAVING COUNT(*) >= ?"
+        )?;
+        let pairs = stmt.query_map(params![config.min_co_occurrence as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)? as usize))
+        })?.collect::<DuckResult<Vec<_>>>()?;
+
+        Ok(TagGraph::from_counts(&popularity, &pairs, config))
+    }
+    
     /// Get recent document activity
     pub async fn get_recent_activity(&self, limit: usize) -> Result<Vec<ActivityRecord>> {
         let mut stmt = self.connection.prepare(
@@ -861,30 +887,6 @@ impl DuckDBStore {
         Ok(activities)
     }
     
-    /// Generate a snippet around the query match
-    fn generate_snippet(&self, content: &str, query: &str, max_length: usize) -> String {
-        let query_lower = query.to_lowercase();
-        let content_lower = content.to_lowercase();
-        
-        if let Some(pos) = content_lower.find(&query_lower) {
-            let start = pos.saturating_sub(max_length / 2);
-            let end = (pos + query.len() + max_length / 2).min(content.len());
-            
-            let mut snippet = content[start..end].to_string();
-            
-            if start > 0 {
-                snippet = format!("...{}", snippet);
-            }
-            if end < content.len() {
-                snippet = format!("{}...", snippet);
-            }
-            
-            snippet
-        } else {
-            content.chars().take(max_length).collect::<String>()
-        }
-    }
-    
     /// Estimate storage size
     async fn estimate_storage_size(&self) -> Result<u64> {
         // Get file size of the database
@@ -986,4 +988,50 @@ pub struct MLFeature {
     pub created_hour: f64,
     pub created_day_of_week: f64,
     pub days_since_modified: f64,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(path: &str, links: &[&str]) -> DocumentMetadata {
+        let now = Utc::now();
+        DocumentMetadata {
+            path: PathBuf::from(path),
+            title: path.to_string(),
+            content_hash: path.to_string(),
+            size: 0,
+            word_count: 0,
+            created_at: now,
+            modified_at: now,
+            indexed_at: now,
+            tags: Vec::new(),
+            links: links.iter().map(|l| l.to_string()).collect(),
+            file_type: FileType::Markdown,
+            language: None,
+            custom_fields: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tag_graph_joins_document_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = DuckDBStore::new(DuckDBConfig {
+            database_path: dir.path().join("metadata.duckdb"),
+            ..DuckDBConfig::default()
+        }).await.unwrap();
+        store.initialize().await.unwrap();
+
+        for (path, tags) in [("a.md", ["garden/herbs", "summer"]), ("b.md", ["garden/herbs", "summer"]), ("c.md", ["garden", "winter"])] {
+            let mut metadata = note(path, &[]);
+            metadata.tags = tags.iter().map(|t| t.to_string()).collect();
+            store.store_document_metadata(&metadata).await.unwrap();
+        }
+
+        let graph = store.tag_graph(&TagGraphConfig::default()).await.unwrap();
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!((graph.edges[0].source.as_str(), graph.edges[0].target.as_str()), ("garden/herbs", "summer"));
+        let garden = graph.hierarchy.iter().find(|n| n.tag == "garden").unwrap();
+        assert_eq!((garden.count, garden.children[0].tag.as_str(), garden.children[0].count), (1, "garden/herbs", 2));
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use anyhow::{Result, Context};
 use tokio::sync::RwLock;
@@ -10,6 +10,9 @@ use super::{
     SearchResult, DocumentRecord, StorageStats, MatchType,
     QueryBuilder, BatchOperations
 };
+use crate::crypto::Crypto;
+use crate::scheduler::Scheduler;
+use crate::vault::lock::{OpenMode, StorageLock};
 
 /// Hybrid storage engine that coordinates DuckDB (metadata/text) and Lance (vectors)
 pub struct HybridStorageEngine {
@@ -17,6 +20,72 @@ pub struct HybridStorageEngine {
     lance: Arc<LanceStore>,
     config: StorageConfig,
     stats: Arc<RwLock<RuntimeStats>>,
+    /// Held by write opens; the Lance dataset isn't safe with several writers
+    _lock: Option<StorageLock>,
+    /// Set when `StorageConfig.encryption` is enabled
+    at_rest: Option<AtRest>,
+}
+
+/// Encrypted DuckDB file and Lance dataset, and the plaintext copies the stores work on
+struct AtRest {
+    crypto: Crypto,
+    /// Pairs of encrypted path and working path
+    paths: Vec<(PathBuf, PathBuf)>,
+    working_dir: PathBuf,
+}
+
+impl AtRest {
+    /// Decrypt the stores into a private working directory and point `config` at it
+    fn unseal(config: &mut StorageConfig) -> Result<Self> {
+        let crypto = Crypto::from_config(&config.encryption)?;
+        let working_dir = std::env::temp_dir().join(format!("note-to-ai-storage-{}", std::process::id()));
+        std::fs::create_dir_all(&working_dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&working_dir, std::fs::Permissions::from_mode(0o700))?;
+        }
+
+        let duckdb = working_dir.join("metadata.duckdb");
+        let lance = working_dir.join("vectors.lance");
+        let paths = vec![
+            (std::mem::replace(&mut config.duckdb_config.database_path, duckdb.clone()), duckdb),
+            (std::mem::replace(&mut config.lance_config.dataset_path, lance.clone()), lance),
+        ];
+        for (sealed, working) in &paths {
+            if sealed.exists() {
+                crypto.decrypt_path(sealed, working)
+                    .with_context(|| format!("Failed to decrypt {}", sealed.display()))?;
+            }
+        }
+        Ok(Self { crypto, paths, working_dir })
+    }
+
+    /// Encrypt the working copies back over the stored ones
+    fn seal(&self) -> Result<()> {
+        for (sealed, working) in &self.paths {
+            if !working.exists() {
+                continue;
+            }
+            // Replaced whole, so files the store has since deleted don't linger encrypted
+            let staging = sealed.with_extension("sealing");
+            remove_path(&staging)?;
+            self.crypto.encrypt_path(working, &staging)?;
+            remove_path(sealed)?;
+            std::fs::rename(&staging, sealed)?;
+        }
+        Ok(())
+    }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else if path.exists() {
+        std::fs::remove_file(path)
+    } else {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Default)]
@@ -30,12 +99,23 @@ struct RuntimeStats {
 }
 
 impl HybridStorageEngine {
-    /// Create a new hybrid storage engine
+    /// Create a new hybrid storage engine, open for writing
     pub async fn new(config: StorageConfig) -> Result<Self> {
+        Self::open(config, OpenMode::Write { force: false }).await
+    }
+    
+    /// Open the storage; write opens take the advisory lock on `base_path` first
+    pub async fn open(mut config: StorageConfig, mode: OpenMode) -> Result<Self> {
         info!("Initializing hybrid storage engine");
         
         // Create storage directories
         tokio::fs::create_dir_all(&config.base_path).await?;
+        let lock = StorageLock::for_mode(&config.base_path, mode)?;
+        let at_rest = if config.encryption.enabled {
+            Some(AtRest::unseal(&mut config)?)
+        } else {
+            None
+        };
         tokio::fs::create_dir_all(&config.duckdb_config.database_path.parent().unwrap_or(&config.base_path)).await?;
         tokio::fs::create_dir_all(&config.lance_config.dataset_path.parent().unwrap_or(&config.base_path)).await?;
         
@@ -50,6 +130,8 @@ impl HybridStorageEngine {
             lance,
             config,
             stats: Arc::new(RwLock::new(RuntimeStats::default())),
+            _lock: lock,
+            at_rest,
         };
         
         info!("Hybrid storage engine initialized successfully");
@@ -577,6 +659,44 @@ pub struct BackupReport {
         Ok(report)
     }
     
+    /// Write the working copies of an encrypted store back to disk encrypted
+    pub fn seal(&self) -> Result<()> {
+        match &self.at_rest {
+            Some(at_rest) => at_rest.seal(),
+            None => Ok(()),
+        }
+    }
+
+    /// Seal an encrypted store and remove its plaintext working copies
+    pub async fn close(self) -> Result<()> {
+        self.seal()?;
+        let Self { duckdb, lance, at_rest, .. } = self;
+        drop((duckdb, lance));
+        if let Some(at_rest) = at_rest {
+            tokio::fs::remove_dir_all(&at_rest.working_dir).await?;
+        }
+        Ok(())
+    }
+
+    /// Optimize both stores every `background_optimization_interval_seconds`
+    pub fn schedule_optimization(self: &Arc<Self>, scheduler: &Scheduler) {
+        let interval = self.config.performance_config.background_optimization_interval_seconds;
+        if interval == 0 {
+            return;
+        }
+        let engine = Arc::clone(self);
+        scheduler.add_interval_task("storage optimization", std::time::Duration::from_secs(interval), move || {
+            let engine = engine.clone();
+            async move {
+                let report = engine.optimize_all().await?;
+                for error in &report.errors {
+                    error!("{}", error);
+                }
+                Ok(())
+            }
+        });
+    }
+    
     /// Create a query builder for complex searches
     pub fn query(&self) -> HybridQueryBuilder {
         HybridQueryBuilder::new(self)
@@ -599,6 +719,11 @@ pub struct BackupReport {
             self.lance.backup(&lance_backup_path)
         );
         
+        // Backups of an encrypted store are encrypted too
+        if let Some(at_rest) = &self.at_rest {
+            at_rest.crypto.encrypt_path(backup_path, backup_path)?;
+        }
+        
         let backup_time = start_time.elapsed();
         
         let report = BackupReport {
@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::crypto::EncryptionConfig;
 
 // Re-exports for convenience
 pub use hybrid_engine::HybridStorageEngine;
@@ -193,6 +194,10 @@ pub struct StorageConfig {
     pub lance_config: LanceConfig,
     pub cache_config: CacheConfig,
     pub performance_config: PerformanceConfig,
+    /// Keep the DuckDB file and Lance dataset encrypted on disk; they are decrypted into a
+    /// private working directory while open
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -203,6 +208,9 @@ pub struct DuckDBConfig {
     pub enable_parquet_cache: bool,
     pub max_cache_size_mb: usize,
     pub wal_mode: bool,
+    /// `optimize` runs a full `VACUUM`, which rewrites the file; otherwise it only checkpoints
+    #[serde(default)]
+    pub full_vacuum: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -249,6 +257,7 @@ impl Default for StorageConfig {
             lance_config: LanceConfig::default(),
             cache_config: CacheConfig::default(),
             performance_config: PerformanceConfig::default(),
+            encryption: EncryptionConfig::default(),
         }
     }
 }
@@ -262,6 +271,7 @@ impl Default for DuckDBConfig {
             enable_parquet_cache: true,
             max_cache_size_mb: 512,
             wal_mode: true,
+            full_vacuum: false,
         }
     }
 }
@@ -280,6 +290,15 @@ impl Default for LanceConfig {
     }
 }
 
+impl From<crate::config::settings::LanceSettings> for LanceConfig {
+    fn from(settings: crate::config::settings::LanceSettings) -> Self {
+        Self {
+            vector_dimension: settings.vector_dimension,
+            ..Self::default()
+        }
+    }
+}
+
 impl Default for CacheConfig {
     fn default() -> Self {
         Self {
@@ -0,0 +1,224 @@
+// src/vault/tag_graph.rs - Tag hierarchy and co-occurrence graph, exported as JSON or Graphviz DOT
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagGraphConfig {
+    /// Notes two tags must share before they get an edge
+    pub min_co_occurrence: usize,
+    /// Strongest edges kept, so large vaults still give a readable graph
+    pub max_edges: usize,
+}
+
+impl Default for TagGraphConfig {
+    fn default() -> Self {
+        Self {
+            min_co_occurrence: 2,
+            max_edges: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagGraphFormat {
+    Json,
+    /// Graphviz; hierarchy as arrows, co-occurrence as dashed undirected edges
+    Dot,
+}
+
+impl FromStr for TagGraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "dot" | "graphviz" => Ok(Self::Dot),
+            other => Err(anyhow::anyhow!("Unknown tag graph format '{}'; expected json or dot", other)),
+        }
+    }
+}
+
+impl TagGraphFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Dot => "dot",
+        }
+    }
+}
+
+/// A tag and the tags nested under it; `project/rust` is a child of `project`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagNode {
+    /// Full tag path, e.g. `project/rust`
+    pub tag: String,
+    /// Notes tagged with exactly this tag; 0 for a parent only used through its children
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagEdge {
+    pub source: String,
+    pub target: String,
+    /// Notes carrying both tags
+    pub weight: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagGraph {
+    pub hierarchy: Vec<TagNode>,
+    pub edges: Vec<TagEdge>,
+}
+
+impl TagGraph {
+    /// From per-tag note counts and per-pair shared note counts, e.g. the `tag_popularity`
+    /// view and a self-join of `document_tags`
+    pub fn from_counts(popularity: &[(String, usize)], pairs: &[(String, String, usize)], config: &TagGraphConfig) -> Self {
+        let mut edges: Vec<TagEdge> = pairs.iter()
+            .filter(|(a, b, weight)| a != b && *weight >= config.min_co_occurrence.max(1))
+            .map(|(a, b, weight)| {
+                let (source, target) = if a <= b { (a, b) } else { (b, a) };
+                TagEdge { source: source.clone(), target: target.clone(), weight: *weight }
+            })
+            .collect();
+        edges.sort_by(|a, b| {
+            b.weight.cmp(&a.weight)
+                .then_with(|| a.source.cmp(&b.source))
+                .then_with(|| a.target.cmp(&b.target))
+        });
+        edges.dedup_by(|a, b| a.source == b.source && a.target == b.target);
+        edges.truncate(config.max_edges);
+
+        Self { hierarchy: hierarchy(popularity), edges }
+    }
+
+    /// Count tags and co-occurrences over each note's tag list
+    pub fn from_documents(documents: &[Vec<String>], config: &TagGraphConfig) -> Self {
+        let mut popularity: HashMap<String, usize> = HashMap::new();
+        let mut pairs: HashMap<(String, String), usize> = HashMap::new();
+        for tags in documents {
+            let mut tags: Vec<&str> = tags.iter().map(|t| normalize(t)).filter(|t| !t.is_empty()).collect();
+            tags.sort_unstable();
+            tags.dedup();
+            for (i, tag) in tags.iter().enumerate() {
+                *popularity.entry(tag.to_string()).or_default() += 1;
+                for other in &tags[i + 1..] {
+                    *pairs.entry((tag.to_string(), other.to_string())).or_default() += 1;
+                }
+            }
+        }
+
+        let popularity: Vec<(String, usize)> = popularity.into_iter().collect();
+        let pairs: Vec<(String, String, usize)> = pairs.into_iter().map(|((a, b), n)| (a, b, n)).collect();
+        Self::from_counts(&popularity, &pairs, config)
+    }
+
+    pub fn render(&self, format: TagGraphFormat) -> Result<String> {
+        match format {
+            TagGraphFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            TagGraphFormat::Dot => Ok(self.to_dot()),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        fn nodes(node: &TagNode, dot: &mut String) {
+            dot.push_str(&format!("  \"{}\" [label=\"#{} ({})\"];\n", escape(&node.tag), escape(&node.tag), node.count));
+            for child in &node.children {
+                dot.push_str(&format!("  \"{}\" -> \"{}\";\n", escape(&node.tag), escape(&child.tag)));
+                nodes(child, dot);
+            }
+        }
+
+        let mut dot = String::from("digraph tags {\n  rankdir=LR;\n  node [shape=box];\n");
+        for root in &self.hierarchy {
+            nodes(root, &mut dot);
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!(
+                "  \"{}\" -> \"{}\" [dir=none, style=dashed, label=\"{}\", penwidth={}];\n",
+                escape(&edge.source), escape(&edge.target), edge.weight, edge.weight.min(8)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn normalize(tag: &str) -> &str {
+    tag.trim().trim_start_matches('#').trim_matches('/')
+}
+
+fn escape(tag: &str) -> String {
+    tag.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Nest every tag under its ancestors, creating the ancestors nobody uses directly
+fn hierarchy(popularity: &[(String, usize)]) -> Vec<TagNode> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for (tag, count) in popularity {
+        let tag = normalize(tag);
+        if tag.is_empty() {
+            continue;
+        }
+        for (i, _) in tag.match_indices('/') {
+            counts.entry(tag[..i].to_string()).or_default();
+        }
+        *counts.entry(tag.to_string()).or_default() += count;
+    }
+
+    fn children_of(parent: Option<&str>, counts: &BTreeMap<String, usize>) -> Vec<TagNode> {
+        counts.iter()
+            .filter(|(tag, _)| match parent {
+                Some(parent) => tag.rsplit_once('/').is_some_and(|(head, _)| head == parent),
+                None => !tag.contains('/'),
+            })
+            .map(|(tag, count)| TagNode {
+                tag: tag.clone(),
+                count: *count,
+                children: children_of(Some(tag), counts),
+            })
+            .collect()
+    }
+    children_of(None, &counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_co_occurring_tags_share_an_edge_and_nested_tags_nest() {
+        let documents = [
+            tags(&["#project/rust", "learning"]),
+            tags(&["project/rust", "learning", "project/rust/async"]),
+            tags(&["project/python", "learning"]),
+            tags(&["cooking"]),
+        ];
+        let graph = TagGraph::from_documents(&documents, &TagGraphConfig::default());
+
+        // Only pairs sharing at least two notes are connected
+        assert_eq!(graph.edges, [TagEdge { source: "learning".into(), target: "project/rust".into(), weight: 2 }]);
+
+        let roots: Vec<&str> = graph.hierarchy.iter().map(|n| n.tag.as_str()).collect();
+        assert_eq!(roots, ["cooking", "learning", "project"]);
+        let project = &graph.hierarchy[2];
+        assert_eq!(project.count, 0);
+        assert_eq!(project.children.iter().map(|n| n.tag.as_str()).collect::<Vec<_>>(), ["project/python", "project/rust"]);
+        let rust = &project.children[1];
+        assert_eq!(rust.count, 2);
+        assert_eq!(rust.children, [TagNode { tag: "project/rust/async".into(), count: 1, children: Vec::new() }]);
+
+        let dot = graph.render(TagGraphFormat::Dot).unwrap();
+        assert!(dot.contains("\"project\" -> \"project/rust\";"));
+        assert!(dot.contains("\"learning\" -> \"project/rust\" [dir=none"));
+        let json: serde_json::Value = serde_json::from_str(&graph.render(TagGraphFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["edges"][0]["weight"], 2);
+    }
+}
@@ -0,0 +1,209 @@
+// src/vault/warmup.rs - Preload frequently accessed documents into the cache on startup
+use std::path::Path;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::logger::Logger;
+use crate::vault::cache::Cache;
+use crate::vault::embeddings::{EmbeddingModelConfig, Embeddings};
+use crate::vault::indexer::{FileIndex, FileType, VaultIndexer};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmupConfig {
+    pub enabled: bool,
+    /// Number of most-accessed documents to preload
+    pub documents: usize,
+    /// Upper bound on time spent warming before startup continues
+    pub timeout_ms: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            documents: 50,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WarmupStats {
+    pub loaded: usize,
+    pub failed: usize,
+    pub timed_out: bool,
+    pub elapsed: Duration,
+}
+
+pub fn metadata_key(path: &Path) -> String {
+    format!("meta:{}", path.display())
+}
+
+pub fn embedding_key(path: &Path) -> String {
+    format!("embedding:{}", path.display())
+}
+
+pub struct IndexWarmup<'a> {
+    indexer: &'a VaultIndexer,
+    embeddings: &'a Embeddings,
+    cache: &'a Cache,
+    model: EmbeddingModelConfig,
+    logger: Logger,
+}
+
+impl<'a> IndexWarmup<'a> {
+    pub fn new(indexer: &'a VaultIndexer, embeddings: &'a Embeddings, cache: &'a Cache, model: &EmbeddingModelConfig) -> Self {
+        Self {
+            indexer,
+            embeddings,
+            cache,
+            model: model.clone(),
+            logger: Logger::new("IndexWarmup"),
+        }
+    }
+
+    /// Load metadata and embeddings for the top accessed documents, stopping at the deadline
+    pub async fn run(&self, config: &WarmupConfig) -> Result<WarmupStats> {
+        let mut stats = WarmupStats::default();
+        if !config.enabled || config.documents == 0 {
+            return Ok(stats);
+        }
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(config.timeout_ms);
+        let documents = self.indexer.get_most_accessed_files(config.documents).await?;
+
+        for document in &documents {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                stats.timed_out = true;
+                break;
+            }
+
+            match tokio::time::timeout(remaining, self.warm_document(document)).await {
+                Ok(Ok(())) => stats.loaded += 1,
+                Ok(Err(e)) => {
+                    self.logger.warn(&format!("Failed to warm {}: {}", document.path.display(), e));
+                    stats.failed += 1;
+                }
+                Err(_) => {
+                    stats.timed_out = true;
+                    break;
+                }
+            }
+        }
+
+        stats.elapsed = start.elapsed();
+        self.logger.info(&format!(
+            "Warmup loaded {} of {} documents in {:?}{}",
+            stats.loaded,
+            documents.len(),
+            stats.elapsed,
+            if stats.timed_out { " (time limit reached)" } else { "" }
+        ));
+
+        Ok(stats)
+    }
+
+    async fn warm_document(&self, document: &FileIndex) -> Result<()> {
+        self.cache.set(metadata_key(&document.path), serde_json::to_vec(document)?).await;
+
+        if !matches!(document.file_type, FileType::Markdown | FileType::Text) {
+            return Ok(());
+        }
+
+        let content = tokio::fs::read_to_string(&document.path).await?;
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        let vector = self.embeddings.embed_passage(&content, &self.model).await?;
+        let bytes = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.cache.set(embedding_key(&document.path), bytes).await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_warmup_loads_top_accessed_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+
+        let paths: Vec<_> = ["hot.md", "warm.md", "cold.md"]
+            .iter()
+            .map(|name| {
+                let path = vault.join(name);
+                std::fs::write(&path, format!("# {}\n\nSome notes.", name)).unwrap();
+                path
+            })
+            .collect();
+
+        let indexer = VaultIndexer::new(dir.path().join("index.db"), vault).unwrap();
+        indexer.initialize_db().await.unwrap();
+        indexer.full_index().await.unwrap();
+
+        for (path, count) in paths.iter().zip([3, 2, 1]) {
+            for _ in 0..count {
+                indexer.record_access(path).await.unwrap();
+            }
+        }
+
+        let embeddings = Embeddings::new().unwrap();
+        let cache = Cache::new(100);
+        let config = WarmupConfig { documents: 2, ..WarmupConfig::default() };
+
+        let stats = IndexWarmup::new(&indexer, &embeddings, &cache, &EmbeddingModelConfig::default())
+            .run(&config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.loaded, 2);
+        assert!(cache.contains(&metadata_key(&paths[0])).await);
+        assert!(cache.contains(&embedding_key(&paths[0])).await);
+        assert!(cache.contains(&metadata_key(&paths[1])).await);
+        assert!(!cache.contains(&metadata_key(&paths[2])).await);
+    }
+
+    #[tokio::test]
+    async fn test_search_hits_and_document_reads_are_warmed_first() {
+        use std::sync::Arc;
+        use crate::test_support::UnitWorker;
+        use crate::vault::embedding_pool::{EmbeddingPool, EmbeddingWorker};
+        use crate::vault::search::{SearchOptions, VectorSearchEngine};
+
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        std::fs::create_dir_all(&vault).unwrap();
+        std::fs::write(vault.join("Garden.md"), "# Garden\n\nWater the tomatoes.").unwrap();
+        std::fs::write(vault.join("Taxes.md"), "# Taxes\n\nFile by April.").unwrap();
+        std::fs::write(vault.join("Recipes.md"), "# Recipes\n\nTomato soup.").unwrap();
+
+        let db = dir.path().join("index.db");
+        let access_log = Arc::new(VaultIndexer::new(db.clone(), vault.clone()).unwrap());
+        access_log.initialize_db().await.unwrap();
+        let engine = Arc::new(VectorSearchEngine::new(db.clone()).unwrap().with_access_log(access_log.clone()));
+        engine.initialize().await.unwrap();
+        let pool = Arc::new(EmbeddingPool::new(vec![Arc::new(UnitWorker) as Arc<dyn EmbeddingWorker>]).unwrap());
+        let indexer = VaultIndexer::new(db, vault.clone()).unwrap()
+            .with_search_engine(engine.clone())
+            .with_embeddings(pool, "unit");
+        indexer.full_index().await.unwrap();
+
+        let hits = engine.text_search("tomatoes", &SearchOptions::default()).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        engine.get_document(Path::new("Taxes.md")).await.unwrap().unwrap();
+        engine.get_document(Path::new("Taxes.md")).await.unwrap().unwrap();
+
+        let warmed: Vec<_> = access_log.get_most_accessed_files(10).await.unwrap()
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(warmed, [vault.join("Taxes.md"), vault.join("Garden.md")]);
+    }
+}
@@ -0,0 +1,220 @@
+// src/webhooks.rs - POST pipeline events to a user-configured URL for integrations
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use crate::logger::Logger;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    DocumentIndexed,
+    QueryAnswered,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub url: Option<String>,
+    /// Events to send; empty sends all of them
+    pub events: Vec<WebhookEvent>,
+    /// Payload fields left out before sending, e.g. `content` or `answer`
+    pub omit_fields: Vec<String>,
+    /// Extra attempts after the first failed delivery
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further one
+    pub retry_delay_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: None,
+            events: Vec::new(),
+            omit_fields: Vec::new(),
+            max_retries: 3,
+            retry_delay_ms: 500,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub data: Value,
+}
+
+/// Delivers events in the background; delivery failures are logged and never reach the caller
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: WebhookConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self { config, client })
+    }
+
+    /// Notifier that never sends anything
+    pub fn disabled() -> Self {
+        Self {
+            config: WebhookConfig::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn is_subscribed(&self, event: WebhookEvent) -> bool {
+        self.config.enabled
+            && self.config.url.is_some()
+            && (self.config.events.is_empty() || self.config.events.contains(&event))
+    }
+
+    /// Queue `data` for delivery; `None` when the event isn't subscribed
+    pub fn notify(&self, event: WebhookEvent, data: Value) -> Option<JoinHandle<()>> {
+        if !self.is_subscribed(event) {
+            return None;
+        }
+
+        let payload = WebhookPayload {
+            event,
+            timestamp: chrono::Utc::now(),
+            data: self.redact(data),
+        };
+        let notifier = self.clone();
+        Some(tokio::spawn(async move {
+            if let Err(e) = notifier.deliver(&payload).await {
+                Logger::new("Webhooks").warn(&format!("Dropped {:?} webhook: {}", payload.event, e));
+            }
+        }))
+    }
+
+    async fn deliver(&self, payload: &WebhookPayload) -> Result<()> {
+        let url = self.config.url.as_deref().unwrap_or_default();
+        let mut delay = Duration::from_millis(self.config.retry_delay_ms);
+        let mut attempt = 0;
+
+        loop {
+            let sent = self.client.post(url).json(payload).send().await
+                .and_then(|response| response.error_for_status());
+            match sent {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt >= self.config.max_retries => {
+                    return Err(anyhow::anyhow!("{} after {} attempts", e, attempt + 1));
+                }
+                Err(_) => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn redact(&self, mut data: Value) -> Value {
+        if let Some(fields) = data.as_object_mut() {
+            for field in &self.config.omit_fields {
+                fields.remove(field);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+    use crate::test_support::{http_response, http_server, UnitWorker};
+    use crate::vault::batch::BatchIndexer;
+    use crate::vault::embedding_pool::{EmbeddingPool, RetryConfig};
+    use crate::vault::parser::ObsidianParser;
+    use crate::vault::indexer::VaultIndexer;
+    use crate::vault::search::VectorSearchEngine;
+
+    /// Accepts webhook POSTs, failing the first `failures` with a 503, and forwards each accepted body
+    async fn receiver(failures: usize) -> (String, mpsc::UnboundedReceiver<WebhookPayload>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut seen = 0;
+        let url = http_server(move |request| {
+            seen += 1;
+            if seen <= failures {
+                return vec![http_response("503 Service Unavailable", "text/plain", "")];
+            }
+            let _ = tx.send(serde_json::from_str(&request.body).unwrap());
+            vec![http_response("200 OK", "text/plain", "")]
+        }).await;
+
+        (format!("{}/hooks", url), rx)
+    }
+
+    #[tokio::test]
+    async fn test_indexed_document_is_posted_after_retry() {
+        let (url, mut received) = receiver(1).await;
+        let notifier = WebhookNotifier::new(WebhookConfig {
+            enabled: true,
+            url: Some(url),
+            omit_fields: vec!["content".to_string()],
+            retry_delay_ms: 10,
+            ..Default::default()
+        }).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let engine = VectorSearchEngine::new(dir.path().join("search.db")).unwrap();
+        engine.initialize().await.unwrap();
+        let pool = Arc::new(EmbeddingPool::new(vec![UnitWorker]).unwrap());
+        let document = ObsidianParser::new().unwrap()
+            .parse_content(Path::new("Tomatoes.md"), "# Tomatoes\n\nWater daily. #garden").await.unwrap();
+
+        let result = BatchIndexer::new(&engine, &pool, "test")
+            .with_webhooks(&notifier)
+            .index(&[document], &RetryConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(result.documents_processed, 1);
+
+        let payload = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+        assert_eq!(payload.event, WebhookEvent::DocumentIndexed);
+        assert_eq!(payload.data["path"], "Tomatoes.md");
+        assert_eq!(payload.data["title"], "Tomatoes");
+        assert_eq!(payload.data["tags"], serde_json::json!(["garden"]));
+        assert!(payload.data.get("content").is_none());
+
+        assert!(WebhookNotifier::disabled().notify(WebhookEvent::Error, serde_json::json!({})).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_vault_indexer_posts_each_indexed_file() {
+        let (url, mut received) = receiver(0).await;
+        let notifier = WebhookNotifier::new(WebhookConfig {
+            enabled: true,
+            url: Some(url),
+            events: vec![WebhookEvent::DocumentIndexed],
+            ..Default::default()
+        }).unwrap();
+
+        let (dir, vault) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::write(vault.path().join("Tomatoes.md"), "# Tomatoes\n\nWater daily. #garden").unwrap();
+        let indexer = VaultIndexer::new(dir.path().join("index.db"), vault.path().to_path_buf())
+            .unwrap()
+            .with_webhooks(notifier);
+        indexer.initialize_db().await.unwrap();
+        assert_eq!(indexer.full_index().await.unwrap().added, 1);
+
+        let payload = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+        assert_eq!(payload.event, WebhookEvent::DocumentIndexed);
+        assert_eq!(payload.data["path"], "Tomatoes.md");
+        assert_eq!(payload.data["action"], "added");
+    }
+}